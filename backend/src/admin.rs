@@ -0,0 +1,526 @@
+//! Operator endpoints under `/api/admin`.
+//!
+//! They are disabled unless `ADMIN_TOKEN` is set, and every request must carry it as
+//! `Authorization: Bearer <token>`. `GET`/`PUT /api/admin/config` read and change the
+//! settings that can be adjusted without a restart, and `/api/admin/log-level` does the
+//! same for the log filter. Changes last until the process exits or
+//! `POST /api/admin/config/reload` re-applies `RUNTIME_CONFIG_FILE`. `GET /api/admin/jobs`
+//! tells whether the instance that answers is the background job leader, and
+//! `/api/admin/queue` shows the task queue and retries or drops its dead tasks.
+
+use crate::errors::ErrorCode;
+use crate::jobs::{JobRunner, JobsStatus};
+use crate::logging::{self, SetFilterError};
+use crate::queue::{QueueError, QueueStatus, Task, TaskQueue};
+use crate::{keys_match, settings, sqltrace, ApiResponse, TodoError};
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+    /// Let every request through; only for `--local`, which listens on loopback
+    pub skip_auth: bool,
+}
+
+impl AdminConfig {
+    /// Reads `ADMIN_TOKEN`; the admin endpoints stay disabled without it
+    pub fn from_env() -> Self {
+        Self {
+            token: std::env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            skip_auth: false,
+        }
+    }
+
+    /// 404 when admin endpoints are disabled, 401 without the right bearer token
+    pub fn authorize(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        if self.skip_auth {
+            return Ok(());
+        }
+        let Some(token) = self.token.as_deref() else {
+            tracing::warn!("Admin request received but ADMIN_TOKEN is not configured");
+            return Err(StatusCode::NOT_FOUND);
+        };
+        let provided = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !keys_match(token, provided) {
+            tracing::warn!("Rejected admin request with invalid token");
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Ok(())
+    }
+}
+
+/// The error for a refused `authorize`, for handlers that answer with `TodoError`s
+pub fn refusal(status: StatusCode) -> TodoError {
+    match status {
+        StatusCode::UNAUTHORIZED => TodoError::Unauthorized("Invalid admin token".to_string()),
+        _ => TodoError::NotFound("Admin endpoints".to_string()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeConfig {
+    /// Log SQL statements with redacted bind values at debug level
+    #[schema(example = false)]
+    pub sql_trace: bool,
+    /// Log filter in effect; `null` when it cannot be changed in this process
+    #[schema(example = "md_todo_backend=debug,warn")]
+    pub log_filter: Option<String>,
+}
+
+impl RuntimeConfig {
+    pub fn current() -> Self {
+        Self {
+            sql_trace: sqltrace::is_enabled(),
+            log_filter: logging::current_filter(),
+        }
+    }
+}
+
+/// Settings to change; omitted ones keep their value
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeConfigUpdate {
+    #[schema(example = true)]
+    pub sql_trace: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeConfigResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<RuntimeConfig>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<RuntimeConfig>> for RuntimeConfigResponse {
+    fn from(response: ApiResponse<RuntimeConfig>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/config",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Current runtime settings", body = RuntimeConfigResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_config(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<RuntimeConfigResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    Ok(Json(ApiResponse::success(RuntimeConfig::current()).into()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/config",
+    request_body = RuntimeConfigUpdate,
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Settings after the update", body = RuntimeConfigResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn put_config(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Json(update): Json<RuntimeConfigUpdate>,
+) -> Result<Json<RuntimeConfigResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    if let Some(enabled) = update.sql_trace {
+        sqltrace::set_enabled(enabled);
+        tracing::info!(
+            "SQL tracing {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+    Ok(Json(ApiResponse::success(RuntimeConfig::current()).into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/config/reload",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Settings after applying RUNTIME_CONFIG_FILE", body = RuntimeConfigResponse),
+        (status = 400, description = "The file cannot be read or is invalid; nothing was applied", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints or RUNTIME_CONFIG_FILE are not configured", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn reload_config(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<RuntimeConfigResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    let Some(path) = settings::config_file() else {
+        tracing::warn!("Config reload requested but RUNTIME_CONFIG_FILE is not configured");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    match settings::reload(&path) {
+        Ok(_) => Ok(Json(ApiResponse::success(RuntimeConfig::current()).into())),
+        Err(e) => {
+            tracing::error!("Failed to reload runtime config: {}", e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LogLevel {
+    /// `RUST_LOG`-style filter directives
+    #[schema(example = "md_todo_backend=debug,tower_http=info")]
+    pub filter: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LogLevelResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<LogLevel>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<LogLevel>> for LogLevelResponse {
+    fn from(response: ApiResponse<LogLevel>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+fn current_log_level() -> Result<Json<LogLevelResponse>, StatusCode> {
+    match logging::current_filter() {
+        Some(filter) => Ok(Json(ApiResponse::success(LogLevel { filter }).into())),
+        None => {
+            tracing::warn!("Log filter is not reloadable in this process");
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/log-level",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Log filter in effect", body = LogLevelResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse),
+        (status = 503, description = "The log filter cannot be changed in this process", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_log_level(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<LogLevelResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    current_log_level()
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/log-level",
+    request_body = LogLevel,
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Log filter after the change", body = LogLevelResponse),
+        (status = 400, description = "Invalid filter directives", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse),
+        (status = 503, description = "The log filter cannot be changed in this process", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn put_log_level(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Json(request): Json<LogLevel>,
+) -> Result<Json<LogLevelResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    match logging::set_filter(&request.filter) {
+        Ok(()) => {
+            tracing::info!("Log filter changed to {}", request.filter);
+            current_log_level()
+        }
+        Err(SetFilterError::Invalid(e)) => {
+            tracing::warn!("Rejected log filter {:?}: {}", request.filter, e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+        Err(SetFilterError::NotReloadable) => {
+            tracing::warn!("Log filter is not reloadable in this process");
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JobsResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<JobsStatus>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<JobsStatus>> for JobsResponse {
+    fn from(response: ApiResponse<JobsStatus>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+/// Each replica answers for itself; ask several to find the leader
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Leadership of this instance and its background jobs", body = JobsResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_jobs(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    Extension(jobs): Extension<Arc<JobRunner>>,
+    headers: HeaderMap,
+) -> Result<Json<JobsResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    Ok(Json(ApiResponse::success(jobs.status()).into()))
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QueueResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<QueueStatus>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<QueueStatus>> for QueueResponse {
+    fn from(response: ApiResponse<QueueStatus>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Task>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Task>> for TaskResponse {
+    fn from(response: ApiResponse<Task>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+/// 404 when the server runs without a database and so without a task queue
+fn task_queue(queue: Option<TaskQueue>) -> Result<TaskQueue, StatusCode> {
+    queue.ok_or_else(|| {
+        tracing::warn!("Task queue requested but the server has none");
+        StatusCode::NOT_FOUND
+    })
+}
+
+fn queue_error(e: QueueError) -> StatusCode {
+    tracing::error!("Task queue request failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/queue",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Task counts and the latest dead tasks", body = QueueResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured, or there is no database", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn get_queue(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    Extension(queue): Extension<Option<TaskQueue>>,
+    headers: HeaderMap,
+) -> Result<Json<QueueResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    let status = task_queue(queue)?.status().await.map_err(queue_error)?;
+    Ok(Json(ApiResponse::success(status).into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/queue/{id}/retry",
+    params(
+        ("id" = i64, Path, description = "Task ID"),
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Dead task queued again with fresh attempts", body = TaskResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No dead task with this id", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn retry_task(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    Extension(queue): Extension<Option<TaskQueue>>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    config.authorize(&headers)?;
+    match task_queue(queue)?.retry(id).await.map_err(queue_error)? {
+        Some(task) => {
+            tracing::info!("Retrying dead task {} ({})", task.id, task.kind);
+            Ok(Json(ApiResponse::success(task).into()))
+        }
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/queue/{id}",
+    params(
+        ("id" = i64, Path, description = "Task ID"),
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 204, description = "Dead task dropped"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No dead task with this id", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn discard_task(
+    Extension(config): Extension<Arc<AdminConfig>>,
+    Extension(queue): Extension<Option<TaskQueue>>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    config.authorize(&headers)?;
+    if task_queue(queue)?.discard(id).await.map_err(queue_error)? {
+        tracing::info!("Discarded dead task {}", id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_authorize() {
+        let config = AdminConfig {
+            token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.authorize(&headers("Bearer secret")), Ok(()));
+        assert_eq!(
+            config.authorize(&headers("Bearer wrong")),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            config.authorize(&headers("secret")),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            AdminConfig::default().authorize(&headers("Bearer secret")),
+            Err(StatusCode::NOT_FOUND)
+        );
+        let local = AdminConfig {
+            skip_auth: true,
+            ..Default::default()
+        };
+        assert_eq!(local.authorize(&HeaderMap::new()), Ok(()));
+    }
+}
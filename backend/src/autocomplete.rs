@@ -0,0 +1,269 @@
+//! Title suggestions at `GET /api/todos/autocomplete`, for `[[...]]` links in the editor.
+//!
+//! Titles starting with `q` come first, newest first, followed by titles whose words
+//! resemble it by `pg_trgm` word similarity, so a typo or a word from the middle of a
+//! title still finds it. Archived todos are left out. Both matches are served by the
+//! trigram index on `title`.
+//!
+//! The editor asks again on every keystroke, so answers are kept in `TitleCache` for
+//! `CACHE_TTL` and marked cacheable by the browser for as long. A todo created or renamed
+//! meanwhile shows up once the entry expires.
+
+use crate::domain::{TodoId, TodoTitle};
+use crate::errors::ErrorCode;
+use crate::search::{self, MIN_SIMILARITY};
+use crate::{ApiResponse, Todo, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+pub const DEFAULT_LIMIT: i64 = 8;
+pub const MAX_LIMIT: i64 = 20;
+/// How long an answer is reused, by the server and the browser
+pub const CACHE_TTL: Duration = Duration::from_secs(30);
+/// Answers kept at most; the cache starts over once full
+const CACHE_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AutocompleteQuery {
+    /// What has been typed so far, trimmed
+    pub text: String,
+    pub limit: i64,
+}
+
+impl AutocompleteQuery {
+    /// Reads `q` and `limit`
+    pub fn from_query(params: &HashMap<String, String>) -> Result<Self, String> {
+        let text = params
+            .get("q")
+            .map(|q| q.trim())
+            .filter(|q| !q.is_empty())
+            .ok_or("q must not be empty")?
+            .to_string();
+        let limit = match params.get("limit") {
+            None => DEFAULT_LIMIT,
+            Some(limit) => match limit.parse::<i64>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+                _ => {
+                    return Err(format!(
+                        "limit must be between 1 and {}, got {:?}",
+                        MAX_LIMIT, limit
+                    ))
+                }
+            },
+        };
+        Ok(Self { text, limit })
+    }
+
+    /// `LIKE` pattern of titles starting with the text
+    pub fn like_prefix(&self) -> String {
+        format!("{}%", escape_like(&self.text))
+    }
+
+    /// Key of the answer in `TitleCache`; matching ignores case
+    fn cache_key(&self) -> (String, i64) {
+        (self.text.to_lowercase(), self.limit)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct TitleSuggestion {
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: TodoId,
+    #[schema(value_type = String, example = "Buy groceries")]
+    pub title: TodoTitle,
+    #[schema(example = false)]
+    pub completed: bool,
+}
+
+impl From<&Todo> for TitleSuggestion {
+    fn from(todo: &Todo) -> Self {
+        Self {
+            id: todo.id,
+            title: todo.title.clone(),
+            completed: todo.completed,
+        }
+    }
+}
+
+/// `text` with the `LIKE` wildcards and escape character escaped
+pub fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Suggestions among `todos`, the way `DatabaseTodoRepository` orders them
+pub fn suggest_in<'a>(
+    query: &AutocompleteQuery,
+    todos: impl IntoIterator<Item = &'a Todo>,
+) -> Vec<TitleSuggestion> {
+    let prefix = query.text.to_lowercase();
+    let mut hits: Vec<(bool, f32, &Todo)> = todos
+        .into_iter()
+        .filter(|todo| !todo.archived)
+        .filter_map(|todo| {
+            let is_prefix = todo.title.to_lowercase().starts_with(&prefix);
+            let score = search::word_similarity(&query.text, &todo.title);
+            (is_prefix || score >= MIN_SIMILARITY).then_some((is_prefix, score, todo))
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(b.1.total_cmp(&a.1))
+            .then(b.2.id.cmp(&a.2.id))
+    });
+    hits.truncate(query.limit as usize);
+    hits.into_iter()
+        .map(|(_, _, todo)| TitleSuggestion::from(todo))
+        .collect()
+}
+
+/// An answer and when it was stored
+type CacheEntry = (Instant, Vec<TitleSuggestion>);
+
+/// Recent answers by query, shared by every request
+#[derive(Default)]
+pub struct TitleCache {
+    entries: Mutex<HashMap<(String, i64), CacheEntry>>,
+}
+
+impl TitleCache {
+    fn get(&self, query: &AutocompleteQuery) -> Option<Vec<TitleSuggestion>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&query.cache_key())
+            .filter(|(stored, _)| stored.elapsed() < CACHE_TTL)
+            .map(|(_, suggestions)| suggestions.clone())
+    }
+
+    fn insert(&self, query: &AutocompleteQuery, suggestions: Vec<TitleSuggestion>) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= CACHE_ENTRIES {
+            entries.retain(|_, (stored, _)| stored.elapsed() < CACHE_TTL);
+            if entries.len() >= CACHE_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(query.cache_key(), (Instant::now(), suggestions));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AutocompleteResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    /// Titles starting with `q` first, then similar ones
+    pub data: Option<Vec<TitleSuggestion>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<TitleSuggestion>>> for AutocompleteResponse {
+    fn from(response: ApiResponse<Vec<TitleSuggestion>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/autocomplete",
+    params(
+        ("q" = String, Query, description = "Start of the title, or words resembling it"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of titles (1-20, default 8)")
+    ),
+    responses(
+        (status = 200, description = "Matching titles, cacheable for 30 seconds", body = AutocompleteResponse,
+            headers(("Cache-Control" = String, description = "private, max-age=30"))),
+        (status = 400, description = "Missing q or invalid limit", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn autocomplete_titles<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(cache): Extension<Arc<TitleCache>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, Json<AutocompleteResponse>), TodoError> {
+    let query = AutocompleteQuery::from_query(&params).map_err(|e| {
+        tracing::warn!("Invalid autocomplete: {}", e);
+        TodoError::Validation(e.to_string())
+    })?;
+    let suggestions = match cache.get(&query) {
+        Some(suggestions) => suggestions,
+        None => {
+            let suggestions = repository.autocomplete_titles(&query).await.map_err(|e| {
+                tracing::error!("Failed to autocomplete titles: {}", e);
+                e
+            })?;
+            cache.insert(&query, suggestions.clone());
+            suggestions
+        }
+    };
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&format!("private, max-age={}", CACHE_TTL.as_secs())) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    Ok((headers, Json(ApiResponse::success(suggestions).into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_matches_come_before_similar_titles() {
+        let todo = |title: &str| Todo::new_with_validation(title, "").unwrap();
+        let mut archived = todo("Groceries for the party");
+        archived.archived = true;
+        let todos = [
+            todo("Buy groceries"),
+            todo("Grocery budget"),
+            todo("Call the plumber"),
+            todo("groceries list"),
+            archived,
+        ];
+        let query = AutocompleteQuery {
+            text: "Groc".to_string(),
+            limit: 5,
+        };
+
+        let titles: Vec<String> = suggest_in(&query, &todos)
+            .into_iter()
+            .map(|suggestion| suggestion.title.into_inner())
+            .collect();
+        assert_eq!(
+            titles,
+            ["groceries list", "Grocery budget", "Buy groceries"]
+        );
+    }
+
+    #[test]
+    fn test_like_prefix_escapes_wildcards() {
+        let query = AutocompleteQuery {
+            text: r"50%_off\".to_string(),
+            limit: 1,
+        };
+        assert_eq!(query.like_prefix(), r"50\%\_off\\%");
+    }
+}
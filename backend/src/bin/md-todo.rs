@@ -0,0 +1,406 @@
+//! Command line client for a remote MD-Todo server: `md-todo list`, `add`, `done`, `rm`,
+//! and `md-todo tui` for a terminal dashboard when built with the `tui` feature.
+//! `md-todo healthcheck` doubles as a container health probe that needs no curl.
+
+use md_todo_backend::client::{ClientError, FailureKind, RequestError, TodoClient};
+use md_todo_backend::domain::TodoId;
+use md_todo_backend::{CreateTodoRequest, Todo, UpdateTodoRequest};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: md-todo [--server URL] [--output FORMAT] <command>
+
+Commands:
+  list [--open | --done]   List todos, newest first
+  add <title> [content]    Create a todo
+  done <id>...             Mark todos completed
+  rm <id>...               Delete todos
+  tui                      Browse, search and complete todos in a terminal dashboard
+                           (needs a build with --features tui)
+  healthcheck              Exit 0 when the server and its database are ready, else 1;
+                           for Docker HEALTHCHECK and Kubernetes exec probes
+
+<id> is a full todo id or a unique end of one, such as the eight characters list shows.
+The server is --server, else $MD_TODO_SERVER, else http://localhost:8000.
+
+Output formats (--output, -o):
+  table   Aligned columns for reading (default)
+  json    The todos as JSON; --json is short for --output json
+  tsv     id, completed, updated_at and title per line, without a header; tabs, newlines
+          and backslashes in titles are escaped as \\t, \\n and \\\\
+  ids     One full id per line: md-todo -o ids list --done | xargs md-todo rm
+
+Exit status:
+  0  Success
+  1  Any other error, such as an unexpected response
+  2  Invalid arguments
+  3  No such todo, or no todo id ends with the given characters
+  4  More than one todo id ends with the given characters
+  5  The server could not be reached
+  6  The server rejected the request
+healthcheck exits 1 on any of these failures.";
+
+const EXIT_USAGE: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Table,
+    Json,
+    Tsv,
+    Ids,
+}
+
+impl Output {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "tsv" => Ok(Self::Tsv),
+            "ids" => Ok(Self::Ids),
+            other => Err(format!(
+                "Unknown output format: {other} (expected table, json, tsv or ids)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    List { completed: Option<bool> },
+    Add { title: String, content: String },
+    Done { ids: Vec<String> },
+    Remove { ids: Vec<String> },
+    Tui,
+    Healthcheck,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    server: Option<String>,
+    output: Output,
+    command: Command,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut server = None;
+        let mut output = Output::Table;
+        let mut completed = None;
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--server" => server = Some(args.next().ok_or("--server needs a URL")?),
+                "--output" | "-o" => {
+                    output = Output::parse(&args.next().ok_or("--output needs a format")?)?
+                }
+                "--json" => output = Output::Json,
+                "--open" => completed = Some(false),
+                "--done" => completed = Some(true),
+                flag if flag.starts_with("--output=") => {
+                    output = Output::parse(&flag["--output=".len()..])?
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option: {flag}")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let name = positional.next().ok_or("No command given")?;
+        let rest: Vec<String> = positional.collect();
+        if completed.is_some() && name != "list" {
+            return Err("--open and --done only apply to list".to_string());
+        }
+        let command = match name.as_str() {
+            "list" if rest.is_empty() => Command::List { completed },
+            "add" if (1..=2).contains(&rest.len()) => {
+                let mut rest = rest.into_iter();
+                Command::Add {
+                    title: rest.next().unwrap_or_default(),
+                    content: rest.next().unwrap_or_default(),
+                }
+            }
+            "done" if !rest.is_empty() => Command::Done { ids: rest },
+            "rm" if !rest.is_empty() => Command::Remove { ids: rest },
+            "tui" if rest.is_empty() => Command::Tui,
+            "healthcheck" if rest.is_empty() => Command::Healthcheck,
+            "list" | "add" | "done" | "rm" | "tui" | "healthcheck" => {
+                return Err(format!("Wrong number of arguments for {name}"))
+            }
+            other => return Err(format!("Unknown command: {other}")),
+        };
+        Ok(Self {
+            server,
+            output,
+            command,
+        })
+    }
+}
+
+/// Last characters of an id; the leading ones of a UUIDv7 are a timestamp
+fn short_id(id: TodoId) -> String {
+    let id = id.to_string();
+    id[id.len() - 8..].to_string()
+}
+
+fn render_table(todos: &[Todo]) -> String {
+    let mut table = String::from("ID        DONE  UPDATED           TITLE\n");
+    for todo in todos {
+        table.push_str(&format!(
+            "{}  {}   {}  {}\n",
+            short_id(todo.id),
+            if todo.completed { "[x]" } else { "[ ]" },
+            todo.updated_at.format("%Y-%m-%d %H:%M"),
+            todo.title
+        ));
+    }
+    table
+}
+
+/// Keeps every todo on one line with exactly four fields
+fn escape_tsv(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn render_tsv(todos: &[Todo]) -> String {
+    todos
+        .iter()
+        .map(|todo| {
+            format!(
+                "{}\t{}\t{}\t{}\n",
+                todo.id,
+                todo.completed,
+                todo.updated_at.to_rfc3339(),
+                escape_tsv(&todo.title)
+            )
+        })
+        .collect()
+}
+
+fn render_ids(ids: impl IntoIterator<Item = TodoId>) -> String {
+    ids.into_iter().map(|id| format!("{id}\n")).collect()
+}
+
+fn print_json(value: &impl serde::Serialize) -> Result<(), ClientError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_todos(output: Output, todos: &[Todo]) -> Result<(), ClientError> {
+    match output {
+        Output::Table => print!("{}", render_table(todos)),
+        Output::Json => print_json(&todos)?,
+        Output::Tsv => print!("{}", render_tsv(todos)),
+        Output::Ids => print!("{}", render_ids(todos.iter().map(|todo| todo.id))),
+    }
+    Ok(())
+}
+
+async fn run(options: Options) -> Result<(), ClientError> {
+    let client = match &options.server {
+        Some(server) => TodoClient::new(server)?,
+        None => TodoClient::from_env()?,
+    };
+    let output = options.output;
+    match options.command {
+        Command::List { completed } => {
+            let mut todos = client.list_todos().await?;
+            if let Some(completed) = completed {
+                todos.retain(|todo| todo.completed == completed);
+            }
+            print_todos(output, &todos)?;
+        }
+        Command::Add { title, content } => {
+            let todo = client
+                .create_todo(&CreateTodoRequest {
+                    id: None,
+                    title,
+                    content,
+                    metadata: None,
+                    labels: None,
+                    estimate_minutes: None,
+                    tags: None,
+                    priority: None,
+                    list_id: None,
+                })
+                .await?;
+            match output {
+                Output::Table => println!("Added {}  {}", short_id(todo.id), todo.title),
+                Output::Json => print_json(&todo)?,
+                Output::Tsv | Output::Ids => print_todos(output, &[todo])?,
+            }
+        }
+        Command::Done { ids } => {
+            let mut completed = Vec::new();
+            for id in ids {
+                let id = client.resolve_id(&id).await?;
+                let update = UpdateTodoRequest {
+                    completed: Some(true),
+                    ..Default::default()
+                };
+                let todo = client.update_todo(id, &update).await?;
+                if output == Output::Table {
+                    println!("Completed {}  {}", short_id(todo.id), todo.title);
+                }
+                completed.push(todo);
+            }
+            if output != Output::Table {
+                print_todos(output, &completed)?;
+            }
+        }
+        Command::Remove { ids } => {
+            let mut deleted = Vec::new();
+            for id in ids {
+                let id = client.resolve_id(&id).await?;
+                client.delete_todo(id).await?;
+                if output == Output::Table {
+                    println!("Deleted {}", short_id(id));
+                }
+                deleted.push(id);
+            }
+            match output {
+                Output::Table => {}
+                Output::Json => print_json(&deleted)?,
+                Output::Tsv | Output::Ids => print!("{}", render_ids(deleted)),
+            }
+        }
+        Command::Tui => {
+            #[cfg(feature = "tui")]
+            md_todo_backend::tui::run(&client).await?;
+            #[cfg(not(feature = "tui"))]
+            return Err("This build lacks the `tui` feature; rebuild with --features tui".into());
+        }
+        Command::Healthcheck => {
+            client.check_ready().await?;
+            if output == Output::Table {
+                println!("OK");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Exit status of a failed command, as listed in USAGE
+fn exit_code(error: &ClientError) -> u8 {
+    match RequestError::kind_of(error) {
+        Some(FailureKind::NotFound) => 3,
+        Some(FailureKind::Ambiguous) => 4,
+        Some(FailureKind::Unreachable) => 5,
+        Some(FailureKind::Rejected) => 6,
+        None => 1,
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    // Probes treat every status but 0 and 1 as an error of their own
+    let healthcheck = options.command == Command::Healthcheck;
+    match run(options).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("md-todo: {e}");
+            if healthcheck {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::from(exit_code(&e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse(&["--json", "list", "--open"]),
+            Ok(Options {
+                server: None,
+                output: Output::Json,
+                command: Command::List {
+                    completed: Some(false)
+                },
+            })
+        );
+        assert_eq!(
+            parse(&["add", "Buy milk", "--server", "http://todo.lan"])
+                .map(|options| (options.server, options.command)),
+            Ok((
+                Some("http://todo.lan".to_string()),
+                Command::Add {
+                    title: "Buy milk".to_string(),
+                    content: String::new(),
+                }
+            ))
+        );
+        assert_eq!(
+            parse(&["rm", "3e4f5a6b7c8d", "6b7c8d"]).map(|options| options.command),
+            Ok(Command::Remove {
+                ids: vec!["3e4f5a6b7c8d".to_string(), "6b7c8d".to_string()]
+            })
+        );
+        assert_eq!(
+            parse(&["-o", "ids", "list"]).map(|options| options.output),
+            Ok(Output::Ids)
+        );
+        assert_eq!(
+            parse(&["list", "--output=tsv"]).map(|options| options.output),
+            Ok(Output::Tsv)
+        );
+        assert_eq!(
+            parse(&["healthcheck"]).map(|options| options.command),
+            Ok(Command::Healthcheck)
+        );
+        for args in [
+            &[][..],
+            &["done"],
+            &["add"],
+            &["list", "extra"],
+            &["rm", "1", "--done"],
+            &["sync"],
+            &["list", "--server"],
+            &["list", "--output", "csv"],
+            &["list", "--output"],
+            &["healthcheck", "now"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_render_tsv_keeps_one_todo_per_line() {
+        let mut todo = Todo::new_with_validation("Call\tBob\rback \\o/", "").unwrap();
+        todo.updated_at = Utc.with_ymd_and_hms(2026, 10, 16, 9, 30, 0).unwrap();
+        assert_eq!(
+            render_tsv(std::slice::from_ref(&todo)),
+            format!(
+                "{}\tfalse\t2026-10-16T09:30:00+00:00\tCall\\tBob\\rback \\\\o/\n",
+                todo.id
+            )
+        );
+        assert_eq!(escape_tsv("a\nb"), "a\\nb");
+        assert_eq!(render_ids([todo.id]), format!("{}\n", todo.id));
+    }
+}
@@ -0,0 +1,561 @@
+//! Creating, updating and deleting many todos in one request under `/api/todos/bulk`.
+//!
+//! `POST` validates every item like a single `POST /api/todos`. The valid ones are
+//! inserted together in one transaction, and the response has a result per item in
+//! request order, so a client can fix and resend just the rejected ones. Imports too
+//! large to wait for go through `POST /api/import` instead.
+//!
+//! `PATCH` applies one update to a list of todos and `DELETE` deletes a list of todos,
+//! each in one transaction. Their response tells the ids that succeeded from those with
+//! no todo; when the transaction fails nothing changes and every id is reported failed.
+//!
+//! `POST /api/todos/complete-all` and `DELETE /api/todos/completed` act on every todo in
+//! view, leaving archived ones alone, and answer with how many they changed.
+
+use crate::domain::TodoId;
+use crate::emoji::EmojiConfig;
+use crate::errors::ErrorCode;
+use crate::moderation::{self, Moderator};
+use crate::subtasks::{self, SubtaskConfig};
+use crate::{
+    lists, metadata, policy, prepare_update, ApiResponse, CreateTodoRequest, Todo, TodoError,
+    TodoRepositoryTrait, UpdateTodoRequest,
+};
+use axum::{extract::State, http::StatusCode, response::Json, Extension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Most items one request may carry
+pub const MAX_BULK_TODOS: usize = 1000;
+
+/// Outcome of one item of the request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BulkItemResult {
+    /// Position of the item in the request
+    #[schema(example = 0)]
+    pub index: usize,
+    #[schema(example = true)]
+    pub success: bool,
+    /// The created todo
+    pub data: Option<Todo>,
+    /// Why the item was rejected
+    #[schema(example = "Title cannot be empty")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// What content policies flagged in the created todo
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkCreateResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    /// One result per item, in request order
+    pub data: Option<Vec<BulkItemResult>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<BulkItemResult>>> for BulkCreateResponse {
+    fn from(response: ApiResponse<Vec<BulkItemResult>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BulkUpdateRequest {
+    /// Todos to update, at most 1000
+    #[schema(value_type = Vec<Uuid>)]
+    pub ids: Vec<TodoId>,
+    /// Applied to every todo, as by `PATCH /api/todos/:id`
+    pub updates: UpdateTodoRequest,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BulkDeleteRequest {
+    /// Todos to delete, at most 1000
+    #[schema(value_type = Vec<Uuid>)]
+    pub ids: Vec<TodoId>,
+}
+
+/// An id whose change did not go through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BulkFailure {
+    #[schema(value_type = Uuid)]
+    pub id: TodoId,
+    #[schema(example = "Internal Server Error")]
+    pub error: String,
+}
+
+/// What became of each id of a bulk update or delete; duplicates are reported once
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BulkChangeResult {
+    /// Todos updated or deleted
+    #[schema(value_type = Vec<Uuid>)]
+    pub succeeded: Vec<TodoId>,
+    /// Ids with no todo
+    #[schema(value_type = Vec<Uuid>)]
+    pub missing: Vec<TodoId>,
+    pub failed: Vec<BulkFailure>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkChangeResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<BulkChangeResult>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<BulkChangeResult>> for BulkChangeResponse {
+    fn from(response: ApiResponse<BulkChangeResult>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+/// How many todos an operation changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AffectedTodos {
+    #[schema(example = 12)]
+    pub affected: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AffectedTodosResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<AffectedTodos>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<AffectedTodos>> for AffectedTodosResponse {
+    fn from(response: ApiResponse<AffectedTodos>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+type BulkChangeError = (StatusCode, Json<BulkChangeResponse>);
+
+fn change_error(e: TodoError) -> BulkChangeError {
+    (e.status(), Json(e.body::<BulkChangeResult>().into()))
+}
+
+/// `ids` without duplicates, in request order; 400 unless there are 1 to 1000
+fn unique_ids(ids: Vec<TodoId>) -> Result<Vec<TodoId>, BulkChangeError> {
+    let mut seen = HashSet::new();
+    let ids: Vec<TodoId> = ids.into_iter().filter(|id| seen.insert(*id)).collect();
+    if ids.is_empty() || ids.len() > MAX_BULK_TODOS {
+        tracing::warn!(
+            "Bulk changes need 1 to {} ids, got {}",
+            MAX_BULK_TODOS,
+            ids.len()
+        );
+        return Err(change_error(TodoError::Validation(format!(
+            "A bulk change needs 1 to {MAX_BULK_TODOS} ids"
+        ))));
+    }
+    Ok(ids)
+}
+
+/// Sorts `ids` into those that `changed` and those that are missing
+fn change_result(ids: Vec<TodoId>, changed: &[TodoId]) -> BulkChangeResult {
+    let changed: HashSet<&TodoId> = changed.iter().collect();
+    let (succeeded, missing) = ids.into_iter().partition(|id| changed.contains(id));
+    BulkChangeResult {
+        succeeded,
+        missing,
+        failed: Vec::new(),
+    }
+}
+
+/// 500 reporting every id as failed, since the transaction changed nothing
+fn change_failed(ids: Vec<TodoId>) -> BulkChangeError {
+    let result = BulkChangeResult {
+        failed: ids
+            .into_iter()
+            .map(|id| BulkFailure {
+                id,
+                error: "Internal Server Error".to_string(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(BulkChangeResponse {
+            success: false,
+            data: Some(result),
+            error: Some("No todo was changed".to_string()),
+            code: Some(ErrorCode::InternalError),
+        }),
+    )
+}
+
+/// Each request as the todo to store, or why it was rejected. Metadata fields, lists and
+/// the validation policy are looked up once for all of them. A client-chosen id that is
+/// taken, or used by an earlier item, rejects the item, and so does a content policy;
+/// what the content policies flag is left to the caller.
+pub(crate) async fn prepare_todos<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    emoji: &EmojiConfig,
+    moderator: &Moderator,
+    requests: Vec<CreateTodoRequest>,
+) -> Result<Vec<Result<Todo, String>>, TodoError> {
+    let fields = repository.list_metadata_fields().await.map_err(|e| {
+        tracing::error!("Failed to load metadata fields: {}", e);
+        e
+    })?;
+    let mut missing_lists: HashMap<Uuid, Option<String>> = HashMap::new();
+    for list_id in requests.iter().filter_map(|request| request.list_id) {
+        if missing_lists.contains_key(&list_id) {
+            continue;
+        }
+        let missing = match lists::check_list(repository, Some(list_id)).await {
+            Ok(()) => None,
+            Err(TodoError::Validation(e)) => Some(e),
+            Err(e) => return Err(e),
+        };
+        missing_lists.insert(list_id, missing);
+    }
+    let policy = policy::load(repository).await?;
+    let mut taken = HashSet::new();
+    for id in requests.iter().filter_map(|request| request.id) {
+        if repository.get_todo_by_id(id).await?.is_some() {
+            taken.insert(id);
+        }
+    }
+
+    Ok(requests
+        .into_iter()
+        .map(|request| {
+            let todo = request.into_todo(emoji)?;
+            if !taken.insert(todo.id) {
+                return Err(format!("Todo {} already exists", todo.id));
+            }
+            metadata::validate_metadata(&todo.metadata, &fields)?;
+            if let Some(Some(e)) = todo.list_id.map(|list_id| &missing_lists[&list_id]) {
+                return Err(e.clone());
+            }
+            policy
+                .check(&todo)
+                .into_result()
+                .map_err(|e| e.to_string())?;
+            moderator
+                .review(Some(todo.title.as_str()), Some(todo.content.as_str()))
+                .map_err(|e| e.to_string())?;
+            Ok(todo)
+        })
+        .collect())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos/bulk",
+    request_body = Vec<CreateTodoRequest>,
+    responses(
+        (status = 200, description = "Valid items created in one transaction; each item reports its own result, including rejections by a content policy", body = BulkCreateResponse),
+        (status = 400, description = "Empty request or more than 1000 items", body = ErrorResponse),
+        (status = 409, description = "A client-chosen id was taken meanwhile; no item was created", body = ErrorResponse),
+        (status = 500, description = "Internal server error; no item was created", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn create_todos_bulk<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Json(requests): Json<Vec<CreateTodoRequest>>,
+) -> Result<Json<BulkCreateResponse>, TodoError> {
+    tracing::info!("Creating {} todos in bulk", requests.len());
+    if requests.is_empty() || requests.len() > MAX_BULK_TODOS {
+        tracing::warn!(
+            "Bulk create needs 1 to {} items, got {}",
+            MAX_BULK_TODOS,
+            requests.len()
+        );
+        return Err(TodoError::Validation(format!(
+            "Bulk create needs 1 to {} items",
+            MAX_BULK_TODOS
+        )));
+    }
+
+    let mut results = prepare_todos(repository.as_ref(), &emoji, &moderator, requests).await?;
+
+    let valid: Vec<Todo> = results
+        .iter()
+        .filter_map(|result| result.as_ref().ok().cloned())
+        .collect();
+    let mut created = if valid.is_empty() {
+        Vec::new()
+    } else {
+        repository.create_todos_bulk(&valid).await.map_err(|e| {
+            tracing::error!("Failed to create todos in bulk: {}", e);
+            e
+        })?
+    }
+    .into_iter();
+    // Stored todos replace the validated ones in request order
+    for result in results.iter_mut().filter(|result| result.is_ok()) {
+        if let Some(todo) = created.next() {
+            *result = Ok(todo);
+        }
+    }
+
+    let mut items = Vec::with_capacity(results.len());
+    for (index, result) in results.into_iter().enumerate() {
+        items.push(match result {
+            Ok(todo) => {
+                // Items a policy rejects never got here, so this only finds flags
+                let findings = moderator
+                    .review(Some(todo.title.as_str()), Some(todo.content.as_str()))
+                    .unwrap_or_default();
+                moderation::record(repository.as_ref(), todo.id, &findings).await;
+                BulkItemResult {
+                    index,
+                    success: true,
+                    data: Some(todo),
+                    error: None,
+                    code: None,
+                    warnings: moderation::warnings(&findings),
+                }
+            }
+            Err(e) => BulkItemResult {
+                index,
+                success: false,
+                data: None,
+                error: Some(e),
+                code: Some(ErrorCode::ValidationFailed),
+                warnings: Vec::new(),
+            },
+        });
+    }
+    let results = items;
+    let rejected = results.iter().filter(|result| !result.success).count();
+    tracing::info!(
+        "Created {} todos in bulk, rejected {}",
+        results.len() - rejected,
+        rejected
+    );
+    Ok(Json(ApiResponse::success(results).into()))
+}
+
+/// 400 reporting every todo the update would make break the validation policy; the
+/// todos are only read when the policy covers the update
+async fn check_policy<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    ids: &[TodoId],
+    updates: &UpdateTodoRequest,
+) -> Result<(), BulkChangeError> {
+    let policy = policy::load(repository).await.map_err(change_error)?;
+    if !policy.covers(updates) {
+        return Ok(());
+    }
+    let mut failed = Vec::new();
+    for &id in ids {
+        let Some(todo) = repository.get_todo_by_id(id).await.map_err(change_error)? else {
+            continue;
+        };
+        if let Err(errors) = policy.check_update(&todo, updates).into_result() {
+            failed.push(BulkFailure {
+                id,
+                error: errors.to_string(),
+            });
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!(
+        "Bulk update would make {} todos break the validation policy",
+        failed.len()
+    );
+    Err((
+        StatusCode::BAD_REQUEST,
+        Json(BulkChangeResponse {
+            success: false,
+            data: Some(BulkChangeResult {
+                failed,
+                ..Default::default()
+            }),
+            error: Some("No todo was changed".to_string()),
+            code: Some(ErrorCode::ValidationFailed),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/todos/bulk",
+    request_body = BulkUpdateRequest,
+    responses(
+        (status = 200, description = "Existing todos updated in one transaction; ids with no todo are reported missing; with MERGE_COMPLETED_SUBTASKS, completing the last open subtask of a todo also archives it", body = BulkChangeResponse),
+        (status = 400, description = "Invalid update, a content policy rejects it, not 1 to 1000 ids, or todos reported failed would break the validation policy", body = BulkChangeResponse),
+        (status = 500, description = "No todo was changed; every id is reported failed", body = BulkChangeResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn update_todos_bulk<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Extension(subtasks): Extension<Arc<SubtaskConfig>>,
+    Json(mut request): Json<BulkUpdateRequest>,
+) -> Result<Json<BulkChangeResponse>, BulkChangeError> {
+    let ids = unique_ids(request.ids)?;
+    tracing::info!("Updating {} todos in bulk", ids.len());
+    prepare_update(repository.as_ref(), &emoji, &mut request.updates)
+        .await
+        .map_err(change_error)?;
+    check_policy(repository.as_ref(), &ids, &request.updates).await?;
+    let findings = moderator
+        .review(
+            request.updates.title.as_deref(),
+            request.updates.content.as_deref(),
+        )
+        .map_err(change_error)?;
+
+    match repository.update_todos_bulk(&ids, &request.updates).await {
+        Ok(mut updated) => {
+            if request.updates.completed == Some(true) {
+                subtasks::merge_completed(repository.as_ref(), &moderator, &subtasks, &mut updated)
+                    .await;
+            }
+            let updated: Vec<TodoId> = updated.iter().map(|todo| todo.id).collect();
+            for &id in &updated {
+                moderation::record(repository.as_ref(), id, &findings).await;
+            }
+            let result = change_result(ids, &updated);
+            tracing::info!(
+                "Updated {} todos in bulk, {} missing",
+                result.succeeded.len(),
+                result.missing.len()
+            );
+            Ok(Json(ApiResponse::success(result).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to update todos in bulk: {}", e);
+            Err(change_failed(ids))
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/todos/bulk",
+    request_body = BulkDeleteRequest,
+    responses(
+        (status = 200, description = "Existing todos deleted in one transaction; ids with no todo are reported missing", body = BulkChangeResponse),
+        (status = 400, description = "Not 1 to 1000 ids", body = BulkChangeResponse),
+        (status = 500, description = "No todo was deleted; every id is reported failed", body = BulkChangeResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn delete_todos_bulk<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Json(request): Json<BulkDeleteRequest>,
+) -> Result<Json<BulkChangeResponse>, BulkChangeError> {
+    let ids = unique_ids(request.ids)?;
+    tracing::info!("Deleting {} todos in bulk", ids.len());
+
+    match repository.delete_todos_bulk(&ids).await {
+        Ok(deleted) => {
+            let result = change_result(ids, &deleted);
+            tracing::info!(
+                "Deleted {} todos in bulk, {} missing",
+                result.succeeded.len(),
+                result.missing.len()
+            );
+            Ok(Json(ApiResponse::success(result).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete todos in bulk: {}", e);
+            Err(change_failed(ids))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos/complete-all",
+    responses(
+        (status = 200, description = "Every open todo that is not archived completed; with MERGE_COMPLETED_SUBTASKS, subtasks that were the last open ones of a todo are also archived", body = AffectedTodosResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn complete_all_todos<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Extension(subtasks): Extension<Arc<SubtaskConfig>>,
+) -> Result<Json<AffectedTodosResponse>, TodoError> {
+    match repository.complete_all_todos().await {
+        Ok(mut completed) => {
+            tracing::info!("Completed {} todos", completed.len());
+            subtasks::merge_completed(repository.as_ref(), &moderator, &subtasks, &mut completed)
+                .await;
+            let affected = AffectedTodos {
+                affected: completed.len(),
+            };
+            Ok(Json(ApiResponse::success(affected).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to complete all todos: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/todos/completed",
+    responses(
+        (status = 200, description = "Every completed todo that is not archived deleted", body = AffectedTodosResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn delete_completed_todos<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<AffectedTodosResponse>, TodoError> {
+    match repository.delete_completed_todos().await {
+        Ok(deleted) => {
+            tracing::info!("Deleted {} completed todos", deleted.len());
+            let affected = AffectedTodos {
+                affected: deleted.len(),
+            };
+            Ok(Json(ApiResponse::success(affected).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete completed todos: {}", e);
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Typed HTTP client for the todo API of a remote server, as used by the `md-todo`
+//! command line tool.
+//!
+//! Requests and responses are the same types the server uses, so the two cannot drift
+//! apart. The server is `MD_TODO_SERVER`, `http://localhost:8000` when unset. Failures the
+//! caller may want to tell apart are `RequestError`s inside the boxed `ClientError`.
+
+use crate::domain::TodoId;
+use crate::search::{SearchHit, SearchResponse};
+use crate::{CreateTodoRequest, Todo, TodoListResponse, TodoResponse, UpdateTodoRequest};
+use reqwest::{Method, StatusCode, Url};
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+pub type ClientError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const DEFAULT_SERVER: &str = "http://localhost:8000";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// No connection to the server, or no answer in time
+    Unreachable,
+    /// No todo has the id, or none ends with it
+    NotFound,
+    /// More than one todo id ends with the given characters
+    Ambiguous,
+    /// The server answered with any other error status
+    Rejected,
+}
+
+#[derive(Debug)]
+pub struct RequestError {
+    pub kind: FailureKind,
+    message: String,
+}
+
+impl RequestError {
+    fn boxed(kind: FailureKind, message: String) -> ClientError {
+        Box::new(Self { kind, message })
+    }
+
+    /// Kind of `error` when it is a `RequestError`
+    pub fn kind_of(error: &ClientError) -> Option<FailureKind> {
+        error.downcast_ref::<Self>().map(|e| e.kind)
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+#[derive(Debug, Clone)]
+pub struct TodoClient {
+    base: Url,
+    http: reqwest::Client,
+}
+
+impl TodoClient {
+    /// `server` is the origin the API is served from, optionally under a path prefix
+    pub fn new(server: &str) -> Result<Self, ClientError> {
+        let mut base =
+            Url::parse(server).map_err(|e| format!("Invalid server URL {:?}: {}", server, e))?;
+        if !matches!(base.scheme(), "http" | "https") {
+            return Err(format!("Server URL must be http or https, got {:?}", server).into());
+        }
+        // Relative joins keep the last path segment only when it ends with a slash
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("md-todo-cli/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self { base, http })
+    }
+
+    /// Reads `MD_TODO_SERVER`
+    pub fn from_env() -> Result<Self, ClientError> {
+        let server = std::env::var("MD_TODO_SERVER")
+            .ok()
+            .filter(|server| !server.is_empty());
+        Self::new(server.as_deref().unwrap_or(DEFAULT_SERVER))
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base
+    }
+
+    /// Succeeds when `/health/ready` answers 200, meaning the server and its database are up
+    pub async fn check_ready(&self) -> Result<(), ClientError> {
+        self.request(Method::GET, self.url("health/ready")?, None)
+            .await?;
+        Ok(())
+    }
+
+    /// Every todo, newest first
+    pub async fn list_todos(&self) -> Result<Vec<Todo>, ClientError> {
+        let response: TodoListResponse =
+            self.send(Method::GET, self.url("api/todos")?, None).await?;
+        envelope(response.data, response.error)
+    }
+
+    /// Best matches first; `fuzzy` compares titles by trigram similarity
+    pub async fn search_todos(
+        &self,
+        text: &str,
+        fuzzy: bool,
+    ) -> Result<Vec<SearchHit>, ClientError> {
+        let mut url = self.url("api/search")?;
+        url.query_pairs_mut()
+            .append_pair("q", text)
+            .append_pair("fuzzy", if fuzzy { "true" } else { "false" });
+        let response: SearchResponse = self.send(Method::GET, url, None).await?;
+        envelope(response.data, response.error)
+    }
+
+    pub async fn get_todo(&self, id: TodoId) -> Result<Todo, ClientError> {
+        let response: TodoResponse = self
+            .send(Method::GET, self.url(&format!("api/todos/{id}"))?, None)
+            .await?;
+        envelope(response.data, response.error)
+    }
+
+    pub async fn create_todo(&self, request: &CreateTodoRequest) -> Result<Todo, ClientError> {
+        let response: TodoResponse = self
+            .send(Method::POST, self.url("api/todos")?, Some(json(request)?))
+            .await?;
+        envelope(response.data, response.error)
+    }
+
+    pub async fn update_todo(
+        &self,
+        id: TodoId,
+        request: &UpdateTodoRequest,
+    ) -> Result<Todo, ClientError> {
+        let response: TodoResponse = self
+            .send(
+                Method::PATCH,
+                self.url(&format!("api/todos/{id}"))?,
+                Some(json(request)?),
+            )
+            .await?;
+        envelope(response.data, response.error)
+    }
+
+    pub async fn delete_todo(&self, id: TodoId) -> Result<(), ClientError> {
+        self.request(Method::DELETE, self.url(&format!("api/todos/{id}"))?, None)
+            .await?;
+        Ok(())
+    }
+
+    /// The todo whose id is `id`, or ends with it. Ids are UUIDv7, so todos created around
+    /// the same time share their leading characters while the trailing ones are random.
+    pub async fn resolve_id(&self, id: &str) -> Result<TodoId, ClientError> {
+        if let Ok(id) = id.parse::<TodoId>() {
+            return Ok(id);
+        }
+        let suffix = id.to_ascii_lowercase();
+        if suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Err(RequestError::boxed(
+                FailureKind::NotFound,
+                format!("Not a todo id: {:?}", id),
+            ));
+        }
+        let todos = self.list_todos().await?;
+        let mut matches = todos
+            .iter()
+            .filter(|todo| todo.id.to_string().ends_with(&suffix));
+        match (matches.next(), matches.next()) {
+            (Some(todo), None) => Ok(todo.id),
+            (None, _) => Err(RequestError::boxed(
+                FailureKind::NotFound,
+                format!("No todo id ends with {:?}", id),
+            )),
+            (Some(_), Some(_)) => Err(RequestError::boxed(
+                FailureKind::Ambiguous,
+                format!("More than one todo id ends with {:?}", id),
+            )),
+        }
+    }
+
+    fn url(&self, path: &str) -> Result<Url, ClientError> {
+        Ok(self.base.join(path)?)
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, ClientError> {
+        let mut request = self.http.request(method.clone(), url.clone());
+        // The CLI writes what it was told to, whatever happened to the todo meanwhile;
+        // this keeps it working against a server with REQUIRE_IF_MATCH
+        if method == Method::PATCH || method == Method::DELETE {
+            request = request.header(reqwest::header::IF_MATCH, "*");
+        }
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+        let unreachable = |e: reqwest::Error| {
+            RequestError::boxed(
+                FailureKind::Unreachable,
+                format!("Could not reach {}: {}", self.base, e),
+            )
+        };
+        let response = request.send().await.map_err(unreachable)?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(unreachable)?.to_vec();
+        if !status.is_success() {
+            return Err(status_error(status, &url, &body));
+        }
+        Ok(body)
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+    ) -> Result<T, ClientError> {
+        let body = self.request(method, url, body).await?;
+        serde_json::from_slice(&body)
+            .map_err(|e| format!("Unexpected response from {}: {}", self.base, e).into())
+    }
+}
+
+fn json(value: &impl Serialize) -> Result<Vec<u8>, ClientError> {
+    Ok(serde_json::to_vec(value)?)
+}
+
+/// The `data` of an `ApiResponse`, or its `error`
+fn envelope<T>(data: Option<T>, error: Option<String>) -> Result<T, ClientError> {
+    data.ok_or_else(|| {
+        error
+            .unwrap_or_else(|| "Response carried no data".to_string())
+            .into()
+    })
+}
+
+/// Most handlers answer errors with a bare status; some add an `ApiResponse` with a message
+fn status_error(status: StatusCode, url: &Url, body: &[u8]) -> ClientError {
+    let message = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string));
+    let kind = if status == StatusCode::NOT_FOUND {
+        FailureKind::NotFound
+    } else {
+        FailureKind::Rejected
+    };
+    let message = match (status, message) {
+        (_, Some(message)) => format!("{} {}: {}", status, url.path(), message),
+        (StatusCode::NOT_FOUND, None) => format!("{} {}: no such todo", status, url.path()),
+        (_, None) => format!("{} {}", status, url.path()),
+    };
+    RequestError::boxed(kind, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_url_keeps_path_prefix() {
+        let client = TodoClient::new("https://todo.example.com/md").unwrap();
+        assert_eq!(
+            client.base_url().join("api/todos").unwrap().as_str(),
+            "https://todo.example.com/md/api/todos"
+        );
+        let client = TodoClient::new(DEFAULT_SERVER).unwrap();
+        assert_eq!(
+            client.base_url().join("api/todos").unwrap().as_str(),
+            "http://localhost:8000/api/todos"
+        );
+        assert!(TodoClient::new("localhost:8000").is_err());
+        assert!(TodoClient::new("not a url").is_err());
+    }
+}
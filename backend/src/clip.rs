@@ -0,0 +1,399 @@
+//! Web clipping: `POST /api/clip` turns a page URL, and optionally the text selected on
+//! it, into a todo so a browser bookmarklet can capture the current page in one click.
+//!
+//! The page title is fetched server-side. If the page cannot be fetched, the URL itself
+//! becomes the title. Requests must carry `CLIP_API_KEY` in the `X-API-Key` header.
+//!
+//! So that a clip cannot make the server reach into its own network, the host is resolved
+//! first and only fetched when every address it resolves to is public: loopback,
+//! link-local and private addresses are refused unless `CLIP_ALLOW_PRIVATE_HOSTS` is set.
+//! The connection goes to the addresses that were checked, and redirects are followed by
+//! hand so each target is checked the same way.
+//!
+//! Clipped todos go through the content policies like todos created over the API.
+
+use crate::domain::{TodoContent, TodoTitle};
+use crate::moderation::{self, Moderator};
+use crate::{
+    keys_match, truncate_to_chars, unicode, ApiResponse, Todo, TodoError, TodoRepositoryTrait,
+    TodoResponse, MAX_CONTENT_CHARS,
+};
+use axum::{
+    extract::State,
+    http::{header::LOCATION, HeaderMap},
+    response::Json,
+    Extension,
+};
+use reqwest::{redirect, Url};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+const API_KEY_HEADER: &str = "x-api-key";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Titles live in `<head>`, so there is no need to download whole pages
+const MAX_PAGE_BYTES: usize = 512 * 1024;
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, Default)]
+pub struct ClipConfig {
+    pub api_key: Option<String>,
+    /// Accept clips without a key; only for `--local`, which listens on loopback
+    pub skip_auth: bool,
+    /// Fetch titles of pages on loopback, link-local and private addresses too
+    pub allow_private_hosts: bool,
+}
+
+impl ClipConfig {
+    /// Reads `CLIP_API_KEY` and `CLIP_ALLOW_PRIVATE_HOSTS`; the endpoint stays disabled
+    /// without the key
+    pub fn from_env() -> Self {
+        Self {
+            api_key: std::env::var("CLIP_API_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            skip_auth: false,
+            allow_private_hosts: std::env::var("CLIP_ALLOW_PRIVATE_HOSTS")
+                .map(|value| matches!(value.as_str(), "1" | "true"))
+                .unwrap_or(false),
+        }
+    }
+
+    /// 404 when clipping is disabled, 401 without the right API key
+    pub fn authorize(&self, headers: &HeaderMap) -> Result<(), TodoError> {
+        if self.skip_auth {
+            return Ok(());
+        }
+        let Some(api_key) = self.api_key.as_deref() else {
+            tracing::warn!("Clip received but CLIP_API_KEY is not configured");
+            return Err(TodoError::NotFound("Clipping".to_string()));
+        };
+        let provided = headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !keys_match(api_key, provided) {
+            tracing::warn!("Rejected clip with invalid API key");
+            return Err(TodoError::Unauthorized("Invalid API key".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClipRequest {
+    /// Page to clip; only http and https URLs are accepted
+    pub url: String,
+    /// Text the user had selected, quoted below the link
+    pub selection: Option<String>,
+}
+
+/// Whether `ip` is on the public internet rather than loopback, link-local, a private
+/// network or another range that is never routed there
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// The host of `url` when it is an IP address rather than a domain
+fn host_ip(url: &Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// The addresses of the host of `url`; `None` when it does not resolve or, unless
+/// `allow_private` is set, when any of them is not public
+async fn resolve_public(url: &Url, allow_private: bool) -> Option<Vec<SocketAddr>> {
+    let port = url.port_or_known_default()?;
+    let addrs: Vec<SocketAddr> = match host_ip(url) {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => tokio::net::lookup_host((url.host_str()?, port))
+            .await
+            .ok()?
+            .collect(),
+    };
+    if addrs.is_empty() {
+        return None;
+    }
+    if !allow_private && !addrs.iter().all(|addr| is_public(addr.ip())) {
+        tracing::warn!(
+            "Refusing to fetch {}: it resolves to a private address",
+            url
+        );
+        return None;
+    }
+    Some(addrs)
+}
+
+pub async fn fetch_page_title(url: &Url, allow_private: bool) -> Option<String> {
+    let mut url = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        let addrs = resolve_public(&url, allow_private).await?;
+        let mut client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .user_agent(concat!("md-todo-clipper/", env!("CARGO_PKG_VERSION")))
+            .redirect(redirect::Policy::none())
+            // A proxy would connect wherever it resolves the host to
+            .no_proxy();
+        if let (None, Some(domain)) = (host_ip(&url), url.host_str()) {
+            // Connect to the addresses just checked, not to what a second lookup returns
+            client = client.resolve_to_addrs(domain, &addrs);
+        }
+        let response = client.build().ok()?.get(url.clone()).send().await.ok()?;
+
+        if response.status().is_redirection() {
+            let location = response.headers().get(LOCATION)?.to_str().ok()?;
+            url = url.join(location).ok()?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return None;
+            }
+            continue;
+        }
+
+        let mut response = response.error_for_status().ok()?;
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.ok()? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_PAGE_BYTES {
+                break;
+            }
+        }
+        return extract_title(&String::from_utf8_lossy(&body));
+    }
+    tracing::debug!("Gave up on {} after {} redirects", url, MAX_REDIRECTS);
+    None
+}
+
+pub fn extract_title(html: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets identical to `html`
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = Todo::sanitize_title(&decode_entities(&html[start..end]));
+    (!title.is_empty()).then_some(title)
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest
+            .get(1..rest.len().min(12))
+            .and_then(|candidate| candidate.find(';').map(|end| &candidate[..end]));
+        let Some(ch) = entity.and_then(decode_entity) else {
+            decoded.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        decoded.push(ch);
+        rest = &rest[entity.map_or(0, str::len) + 2..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let number = entity.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Markdown link to the page followed by the selection as a blockquote
+pub fn clip_to_todo(url: &Url, title: Option<String>, selection: Option<&str>) -> Todo {
+    let title = title.unwrap_or_else(|| Todo::sanitize_title(url.as_str()));
+    let link_text = title.replace('[', "\\[").replace(']', "\\]");
+    let link_target = url.as_str().replace('(', "%28").replace(')', "%29");
+    let mut content = format!("[{link_text}]({link_target})");
+
+    if let Some(selection) = selection.map(str::trim).filter(|s| !s.is_empty()) {
+        let quoted = selection
+            .lines()
+            .map(|line| format!("> {line}").trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        content.push_str("\n\n");
+        content.push_str(&quoted);
+    }
+
+    let content = unicode::nfc(&content);
+    Todo::new(
+        TodoTitle::new(title).expect("page titles and URLs are sanitized"),
+        TodoContent::new(truncate_to_chars(&content, MAX_CONTENT_CHARS))
+            .expect("content is truncated to the limit"),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/clip",
+    request_body = ClipRequest,
+    params(
+        ("X-API-Key" = String, Header, description = "Value of CLIP_API_KEY")
+    ),
+    responses(
+        (status = 200, description = "Todo created from the page", body = TodoResponse),
+        (status = 400, description = "URL is not an http(s) URL, or a content policy rejected the todo", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Clipping is not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Inbound"
+)]
+pub async fn clip_page<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<ClipConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    headers: HeaderMap,
+    Json(request): Json<ClipRequest>,
+) -> Result<Json<TodoResponse>, TodoError> {
+    config.authorize(&headers)?;
+
+    let url = match Url::parse(request.url.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => {
+            tracing::warn!("Rejected clip of invalid URL: {}", request.url);
+            return Err(TodoError::Validation(
+                "Only http and https URLs can be clipped".to_string(),
+            ));
+        }
+    };
+
+    tracing::info!("Clipping page: {}", url);
+    let title = fetch_page_title(&url, config.allow_private_hosts).await;
+    if title.is_none() {
+        tracing::debug!("Could not fetch a title for {}; using the URL", url);
+    }
+    let todo = clip_to_todo(&url, title, request.selection.as_deref());
+    let findings = moderator.review(Some(todo.title.as_str()), Some(todo.content.as_str()))?;
+
+    match repository.create_todo(&todo).await {
+        Ok(created_todo) => {
+            tracing::info!(
+                "Successfully created todo with id: {} from clip",
+                created_todo.id
+            );
+            moderation::record(repository.as_ref(), created_todo.id, &findings).await;
+            let response = TodoResponse::from(ApiResponse::success(created_todo));
+            Ok(Json(response.with_warnings(&findings)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create todo from clip: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_title() {
+        let html =
+            "<html><HEAD><Title lang=\"en\">\n  Rust &amp; Axum &#8212; Guide\n</TITLE></head>";
+        assert_eq!(extract_title(html).unwrap(), "Rust & Axum \u{2014} Guide");
+        assert_eq!(extract_title("<title> </title>"), None);
+        assert_eq!(extract_title("<p>No title</p>"), None);
+    }
+
+    #[test]
+    fn test_decode_entities_keeps_unknown() {
+        assert_eq!(
+            decode_entities("a & b &bogus; &lt;c&gt;"),
+            "a & b &bogus; <c>"
+        );
+    }
+
+    #[test]
+    fn test_clip_to_todo() {
+        let url = Url::parse("https://example.com/a_(b)").unwrap();
+        let todo = clip_to_todo(
+            &url,
+            Some("[Draft] Notes".to_string()),
+            Some("first line\n\nsecond line\n"),
+        );
+        assert_eq!(todo.title, "[Draft] Notes");
+        assert_eq!(
+            todo.content,
+            "[\\[Draft\\] Notes](https://example.com/a_%28b%29)\n\n> first line\n>\n> second line"
+        );
+
+        let todo = clip_to_todo(&url, None, None);
+        assert_eq!(todo.title, "https://example.com/a_(b)");
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1::1"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_hosts_are_not_fetched() {
+        let url = Url::parse("http://127.0.0.1:9/").unwrap();
+        assert_eq!(resolve_public(&url, false).await, None);
+        assert!(resolve_public(&url, true).await.is_some());
+        let url = Url::parse("http://[::1]:9/").unwrap();
+        assert_eq!(resolve_public(&url, false).await, None);
+    }
+}
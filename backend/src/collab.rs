@@ -0,0 +1,894 @@
+//! Real-time collaborative editing of todo content.
+//!
+//! Every todo being edited gets an in-memory room holding an Automerge document whose
+//! `content` text object mirrors the todo's markdown. Peers connect over a WebSocket:
+//! they first receive the full document as a binary frame, then exchange binary frames
+//! of Automerge changes which the room applies and relays to the other peers. The room
+//! periodically writes the merged text back to the `content` column and does a final
+//! write when the last peer leaves. Those writes are prepared and checked as
+//! `PATCH /api/todos/:id` would: NFC and emoji shortcodes, the validation policy and the
+//! content policies. Text they reject is not written back, and what a policy flags is
+//! queued for review.
+//!
+//! The room remembers the version of the todo it last matched. Its writes expect that
+//! version, and when the todo has moved on they reload it and merge the stored content
+//! into the document before trying again, so an edit made elsewhere is never overwritten.
+//! Writes through the REST API reach open rooms directly: `CollabHub` subscribes to the
+//! event bus and merges each updated todo the same way, sending the change to the peers.
+//! Writes on other replicas are merged when the room next saves.
+//!
+//! Presence travels on the same socket as JSON text frames: a `welcome` listing who is
+//! already in the room, then `join`/`leave`/`cursor` messages as peers come and go.
+
+use crate::domain::TodoId;
+use crate::emoji::EmojiConfig;
+use crate::errors::ErrorCode;
+use crate::events::{EventPublisher, PublishError, TodoEvent};
+use crate::moderation::{self, Moderator};
+use crate::{
+    policy, prepare_update, ApiResponse, Todo, TodoError, TodoRepositoryTrait, UpdateTodoRequest,
+};
+use automerge::{
+    transaction::Transactable, AutoCommit, AutomergeError, ChangeHash, ObjId, ObjType, ReadDoc,
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    response::{Json, Response},
+    Extension,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+#[allow(unused_imports)] // json! macro is used in schema examples
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+use utoipa::{IntoParams, ToSchema};
+
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+
+const ROOM_CHANNEL_CAPACITY: usize = 256;
+const MAX_PEER_NAME_LENGTH: usize = 64;
+/// Writes of a room tried before it waits for the next snapshot, each after merging what
+/// was stored meanwhile
+const MAX_SAVE_ATTEMPTS: usize = 3;
+/// Sender of the changes the server makes to a room; peer ids start at 1
+const SERVER_PEER_ID: u64 = 0;
+
+/// Selection of a peer as character offsets into the content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Cursor {
+    #[schema(example = 5)]
+    pub anchor: usize,
+    #[schema(example = 12)]
+    pub head: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Peer {
+    #[schema(example = 3)]
+    pub peer_id: u64,
+    #[schema(example = "Alice")]
+    pub name: String,
+    pub cursor: Option<Cursor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Presence {
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub todo_id: TodoId,
+    #[schema(example = 1)]
+    pub count: usize,
+    pub peers: Vec<Peer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "success": true,
+    "data": {
+        "todo_id": "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d",
+        "count": 1,
+        "peers": [
+            { "peer_id": 3, "name": "Alice", "cursor": { "anchor": 5, "head": 12 } }
+        ]
+    },
+    "error": null
+}))]
+pub struct PresenceResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Presence>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Presence>> for PresenceResponse {
+    fn from(response: ApiResponse<Presence>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+/// Presence messages sent by the server as text frames
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceMessage {
+    /// First text frame a peer receives: its own id and everyone in the room
+    Welcome {
+        peer_id: u64,
+        peers: Vec<Peer>,
+    },
+    Join {
+        peer: Peer,
+    },
+    Leave {
+        peer_id: u64,
+    },
+    Cursor {
+        peer_id: u64,
+        cursor: Option<Cursor>,
+    },
+}
+
+/// Text frames accepted from peers
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Cursor { cursor: Option<Cursor> },
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CollabParams {
+    /// Display name shown to other editors
+    pub name: Option<String>,
+}
+
+#[derive(Clone)]
+enum RoomEvent {
+    Change { peer_id: u64, change: Arc<Vec<u8>> },
+    Presence { peer_id: u64, message: Arc<String> },
+}
+
+impl RoomEvent {
+    fn peer_id(&self) -> u64 {
+        match self {
+            RoomEvent::Change { peer_id, .. } | RoomEvent::Presence { peer_id, .. } => *peer_id,
+        }
+    }
+}
+
+/// Where a room stands against the stored todo
+struct Base {
+    /// Version of the todo the room last matched
+    version: i32,
+    /// Heads of the document when it held that version's content
+    heads: Vec<ChangeHash>,
+    /// Content being written and the heads it was read at, while a write is under way
+    saving: Option<(String, Vec<ChangeHash>)>,
+}
+
+/// A shared Automerge document for a single todo
+pub struct Room {
+    doc: std::sync::Mutex<AutoCommit>,
+    content: ObjId,
+    base: std::sync::Mutex<Base>,
+    events: broadcast::Sender<RoomEvent>,
+    dirty: AtomicBool,
+    peers: std::sync::Mutex<BTreeMap<u64, Peer>>,
+}
+
+impl Room {
+    /// A room holding `content` at `version` of the todo
+    pub fn new(content: &str, version: i32) -> Result<Self, AutomergeError> {
+        let mut doc = AutoCommit::new();
+        let text = doc.put_object(automerge::ROOT, "content", ObjType::Text)?;
+        doc.splice_text(&text, 0, 0, content)?;
+        let base = Base {
+            version,
+            heads: doc.get_heads(),
+            saving: None,
+        };
+        let (events, _) = broadcast::channel(ROOM_CHANNEL_CAPACITY);
+        Ok(Self {
+            doc: std::sync::Mutex::new(doc),
+            content: text,
+            base: std::sync::Mutex::new(base),
+            events,
+            dirty: AtomicBool::new(false),
+            peers: std::sync::Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Version of the todo the room last matched
+    pub fn version(&self) -> i32 {
+        self.base.lock().unwrap().version
+    }
+
+    /// Merges the content of `todo` into the document if it is newer than the room's
+    /// version, as an edit made concurrently with the peers' since then; returns the
+    /// change to send them, if any
+    pub fn catch_up(&self, todo: &Todo) -> Result<Option<Vec<u8>>, AutomergeError> {
+        let mut base = self.base.lock().unwrap();
+        if todo.version <= base.version {
+            return Ok(None);
+        }
+        // The room's own write comes back as what it saved, preparation included
+        let from = match &base.saving {
+            Some((content, heads)) if todo.content == *content => heads.clone(),
+            _ => base.heads.clone(),
+        };
+        let mut doc = self.doc.lock().unwrap();
+        let before = doc.get_heads();
+        let mut fork = doc.fork_at(&from)?;
+        fork.update_text(&self.content, todo.content.as_str())?;
+        base.version = todo.version;
+        base.heads = fork.get_heads();
+        doc.merge(&mut fork)?;
+        let change = doc.save_after(&before);
+        Ok((!change.is_empty()).then_some(change))
+    }
+
+    /// `catch_up`, sending the change to the peers
+    fn refresh(&self, todo: &Todo) {
+        match self.catch_up(todo) {
+            Ok(Some(change)) => {
+                tracing::debug!(
+                    "CollabHub: Merged version {} of todo {} into its room",
+                    todo.version,
+                    todo.id
+                );
+                let _ = self.events.send(RoomEvent::Change {
+                    peer_id: SERVER_PEER_ID,
+                    change: Arc::new(change),
+                });
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!(
+                "CollabHub: Failed to merge version {} of todo {}: {}",
+                todo.version,
+                todo.id,
+                e
+            ),
+        }
+    }
+
+    /// Marks `content`, prepared from the text at `heads`, as being written; returns the
+    /// version the write must expect
+    fn start_save(&self, content: &str, heads: Vec<ChangeHash>) -> i32 {
+        let mut base = self.base.lock().unwrap();
+        base.saving = Some((content.to_string(), heads));
+        base.version
+    }
+
+    fn end_save(&self) {
+        self.base.lock().unwrap().saving = None;
+    }
+
+    /// The merged text and the heads it was read at
+    fn draft(&self) -> Result<(String, Vec<ChangeHash>), AutomergeError> {
+        let mut doc = self.doc.lock().unwrap();
+        Ok((doc.text(&self.content)?, doc.get_heads()))
+    }
+
+    /// Full document, sent to peers when they join or fall behind
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.doc.lock().unwrap().save()
+    }
+
+    /// Apply changes produced by a peer's `save_incremental`/`save`, returning whether
+    /// the document changed (unparseable or already-known chunks are ignored)
+    pub fn apply(&self, change: &[u8]) -> Result<bool, AutomergeError> {
+        let mut doc = self.doc.lock().unwrap();
+        let before = doc.get_heads();
+        doc.load_incremental(change)?;
+        let changed = doc.get_heads() != before;
+        if changed {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+        Ok(changed)
+    }
+
+    pub fn text(&self) -> Result<String, AutomergeError> {
+        self.doc.lock().unwrap().text(&self.content)
+    }
+
+    pub fn peers(&self) -> Vec<Peer> {
+        self.peers.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.peers.lock().unwrap().len()
+    }
+
+    fn set_cursor(&self, peer_id: u64, cursor: Option<Cursor>) {
+        if let Some(peer) = self.peers.lock().unwrap().get_mut(&peer_id) {
+            peer.cursor = cursor;
+        }
+        self.announce(peer_id, &PresenceMessage::Cursor { peer_id, cursor });
+    }
+
+    fn announce(&self, peer_id: u64, message: &PresenceMessage) {
+        match serde_json::to_string(message) {
+            Ok(message) => {
+                let _ = self.events.send(RoomEvent::Presence {
+                    peer_id,
+                    message: Arc::new(message),
+                });
+            }
+            Err(e) => tracing::error!("CollabHub: Failed to encode presence message: {}", e),
+        }
+    }
+}
+
+/// A peer's seat in a room, with its event subscription
+struct Membership {
+    room: Arc<Room>,
+    peer_id: u64,
+    events: broadcast::Receiver<RoomEvent>,
+}
+
+/// Registry of open collaboration rooms, keyed by todo id
+pub struct CollabHub {
+    rooms: Mutex<HashMap<TodoId, Arc<Room>>>,
+    next_peer_id: AtomicU64,
+    snapshot_interval: Duration,
+}
+
+impl std::fmt::Debug for CollabHub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CollabHub")
+            .field("snapshot_interval", &self.snapshot_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for CollabHub {
+    fn default() -> Self {
+        Self::new(DEFAULT_SNAPSHOT_INTERVAL)
+    }
+}
+
+impl CollabHub {
+    pub fn new(snapshot_interval: Duration) -> Self {
+        Self {
+            rooms: Mutex::new(HashMap::new()),
+            next_peer_id: AtomicU64::new(1),
+            snapshot_interval,
+        }
+    }
+
+    pub async fn room(&self, id: TodoId) -> Option<Arc<Room>> {
+        self.rooms.lock().await.get(&id).cloned()
+    }
+
+    /// Who is currently editing a todo; empty when no room is open
+    pub async fn presence(&self, id: TodoId) -> Presence {
+        let peers = match self.room(id).await {
+            Some(room) => room.peers(),
+            None => Vec::new(),
+        };
+        Presence {
+            todo_id: id,
+            count: peers.len(),
+            peers,
+        }
+    }
+
+    async fn join<R: TodoRepositoryTrait + 'static>(
+        self: &Arc<Self>,
+        todo: &Todo,
+        name: String,
+        writer: Writer<R>,
+    ) -> Result<Membership, AutomergeError> {
+        let mut rooms = self.rooms.lock().await;
+        let room = match rooms.get(&todo.id) {
+            Some(room) => room.clone(),
+            None => {
+                tracing::debug!("CollabHub: Opening room for todo with id: {}", todo.id);
+                let room = Arc::new(Room::new(&todo.content, todo.version)?);
+                rooms.insert(todo.id, room.clone());
+                self.spawn_snapshot_task(todo.id, room.clone(), writer);
+                room
+            }
+        };
+
+        let peer = Peer {
+            peer_id: self.next_peer_id.fetch_add(1, Ordering::SeqCst),
+            name,
+            cursor: None,
+        };
+        let events = room.events.subscribe();
+        room.peers
+            .lock()
+            .unwrap()
+            .insert(peer.peer_id, peer.clone());
+        room.announce(peer.peer_id, &PresenceMessage::Join { peer: peer.clone() });
+
+        Ok(Membership {
+            room,
+            peer_id: peer.peer_id,
+            events,
+        })
+    }
+
+    async fn leave<R: TodoRepositoryTrait>(
+        &self,
+        id: TodoId,
+        room: &Arc<Room>,
+        peer_id: u64,
+        writer: &Writer<R>,
+    ) {
+        {
+            let mut rooms = self.rooms.lock().await;
+            let remaining = {
+                let mut peers = room.peers.lock().unwrap();
+                peers.remove(&peer_id);
+                peers.len()
+            };
+            if remaining > 0 {
+                room.announce(peer_id, &PresenceMessage::Leave { peer_id });
+                return;
+            }
+            rooms.remove(&id);
+        }
+        tracing::debug!("CollabHub: Closing room for todo with id: {}", id);
+        persist_room(id, room, writer).await;
+    }
+
+    fn spawn_snapshot_task<R: TodoRepositoryTrait + 'static>(
+        self: &Arc<Self>,
+        id: TodoId,
+        room: Arc<Room>,
+        writer: Writer<R>,
+    ) {
+        let hub = Arc::downgrade(self);
+        let interval = self.snapshot_interval;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(hub) = hub.upgrade() else { break };
+                // Stop once the room has been closed (or replaced by a newer one)
+                match hub.room(id).await {
+                    Some(current) if Arc::ptr_eq(&current, &room) => {}
+                    _ => break,
+                }
+                persist_room(id, &room, &writer).await;
+            }
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl EventPublisher for CollabHub {
+    /// Merges todos updated elsewhere into their open rooms
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        if let TodoEvent::Updated(todo) = event {
+            if let Some(room) = self.room(todo.id).await {
+                room.refresh(todo);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a room writes its text back with
+struct Writer<R> {
+    repository: Arc<R>,
+    emoji: Arc<EmojiConfig>,
+    moderator: Arc<Moderator>,
+}
+
+impl<R> Clone for Writer<R> {
+    fn clone(&self) -> Self {
+        Self {
+            repository: self.repository.clone(),
+            emoji: self.emoji.clone(),
+            moderator: self.moderator.clone(),
+        }
+    }
+}
+
+/// How one write of a room went
+enum Save {
+    /// Written, or nothing that can be written
+    Done,
+    /// The todo had moved on; the room merged it and can try again
+    Stale,
+}
+
+/// Write the room's merged text back to the todo if peers changed it
+async fn persist_room<R: TodoRepositoryTrait>(id: TodoId, room: &Room, writer: &Writer<R>) {
+    if !room.dirty.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    for _ in 0..MAX_SAVE_ATTEMPTS {
+        let save = save_room(id, room, writer).await;
+        room.end_save();
+        if let Save::Done = save {
+            return;
+        }
+    }
+    tracing::warn!(
+        "CollabHub: Todo {} kept changing while its room was saved; retrying later",
+        id
+    );
+    room.dirty.store(true, Ordering::SeqCst);
+}
+
+async fn save_room<R: TodoRepositoryTrait>(id: TodoId, room: &Room, writer: &Writer<R>) -> Save {
+    let repository = writer.repository.as_ref();
+    let (content, heads) = match room.draft() {
+        Ok(draft) => draft,
+        Err(e) => {
+            tracing::error!("CollabHub: Failed to read room text for todo {}: {}", id, e);
+            return Save::Done;
+        }
+    };
+    let mut updates = UpdateTodoRequest {
+        content: Some(content),
+        ..Default::default()
+    };
+    let checked = async {
+        prepare_update(repository, &writer.emoji, &mut updates).await?;
+        let policy = policy::load(repository).await?;
+        policy::check_update(repository, &policy, id, &updates).await?;
+        writer.moderator.review(None, updates.content.as_deref())
+    };
+    let findings = match checked.await {
+        Ok(findings) => findings,
+        Err(e) => {
+            tracing::warn!("CollabHub: Not persisting snapshot of todo {}: {}", id, e);
+            return Save::Done;
+        }
+    };
+
+    let prepared = updates.content.as_deref().unwrap_or_default();
+    let version = room.start_save(prepared, heads);
+    match repository.update_todo(id, &updates, Some(version)).await {
+        Ok(Some(todo)) => {
+            tracing::debug!(
+                "CollabHub: Persisted snapshot of todo {} as version {}",
+                id,
+                todo.version
+            );
+            moderation::record(repository, id, &findings).await;
+            // Hands the peers what preparing the text changed
+            room.refresh(&todo);
+            Save::Done
+        }
+        Ok(None) => {
+            tracing::warn!("CollabHub: Todo {} was deleted while being edited", id);
+            Save::Done
+        }
+        Err(TodoError::PreconditionFailed(_)) => match repository.get_todo_by_id(id).await {
+            Ok(Some(todo)) => {
+                tracing::info!(
+                    "CollabHub: Todo {} changed from version {} to {} while being edited",
+                    id,
+                    version,
+                    todo.version
+                );
+                room.end_save();
+                room.refresh(&todo);
+                Save::Stale
+            }
+            Ok(None) => {
+                tracing::warn!("CollabHub: Todo {} was deleted while being edited", id);
+                Save::Done
+            }
+            Err(e) => {
+                tracing::error!("CollabHub: Failed to reload todo {}: {}", id, e);
+                room.dirty.store(true, Ordering::SeqCst);
+                Save::Done
+            }
+        },
+        Err(e) => {
+            tracing::error!(
+                "CollabHub: Failed to persist snapshot of todo {}: {}",
+                id,
+                e
+            );
+            room.dirty.store(true, Ordering::SeqCst);
+            Save::Done
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}/collab",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID"),
+        CollabParams,
+        ("ticket" = Option<String>, Query, description = "Ticket from `POST /api/auth/ticket`, for browsers that cannot send a bearer token")
+    ),
+    responses(
+        (status = 101, description = "WebSocket upgrade; binary frames carry Automerge changes, text frames carry presence messages"),
+        (status = 401, description = "Missing, invalid or expired access token or ticket", body = ErrorResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn collab_socket<R: TodoRepositoryTrait + 'static>(
+    State(repository): State<Arc<R>>,
+    Extension(hub): Extension<Arc<CollabHub>>,
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Path(id): Path<TodoId>,
+    Query(params): Query<CollabParams>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, TodoError> {
+    let todo = match repository.get_todo_by_id(id).await {
+        Ok(Some(todo)) => todo,
+        Ok(None) => {
+            tracing::warn!("Todo not found for collaboration with id: {}", id);
+            return Err(TodoError::NotFound(format!("Todo {}", id)));
+        }
+        Err(e) => {
+            tracing::error!("Failed to get todo with id {} for collaboration: {}", id, e);
+            return Err(e);
+        }
+    };
+
+    let name = params
+        .name
+        .map(|name| {
+            name.trim()
+                .chars()
+                .take(MAX_PEER_NAME_LENGTH)
+                .collect::<String>()
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "Anonymous".to_string());
+
+    let writer = Writer {
+        repository,
+        emoji,
+        moderator,
+    };
+    Ok(ws.on_upgrade(move |socket| run_peer(socket, hub, todo, name, writer)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}/presence",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    responses(
+        (status = 200, description = "Editors currently connected to the todo", body = PresenceResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_presence<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(hub): Extension<Arc<CollabHub>>,
+    Path(id): Path<TodoId>,
+) -> Result<Json<PresenceResponse>, TodoError> {
+    match repository.get_todo_by_id(id).await {
+        Ok(Some(_)) => Ok(Json(ApiResponse::success(hub.presence(id).await).into())),
+        Ok(None) => {
+            tracing::warn!("Todo not found for presence with id: {}", id);
+            Err(TodoError::NotFound(format!("Todo {}", id)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get todo with id {} for presence: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+async fn run_peer<R: TodoRepositoryTrait + 'static>(
+    socket: WebSocket,
+    hub: Arc<CollabHub>,
+    todo: Todo,
+    name: String,
+    writer: Writer<R>,
+) {
+    let id = todo.id;
+    let Membership {
+        room,
+        peer_id,
+        mut events,
+    } = match hub.join(&todo, name, writer.clone()).await {
+        Ok(membership) => membership,
+        Err(e) => {
+            tracing::error!("CollabHub: Failed to open room for todo {}: {}", id, e);
+            return;
+        }
+    };
+    tracing::info!("Peer {} joined collaboration on todo {}", peer_id, id);
+
+    relay(socket, &room, peer_id, &mut events, id).await;
+
+    tracing::info!("Peer {} left collaboration on todo {}", peer_id, id);
+    hub.leave(id, &room, peer_id, &writer).await;
+}
+
+/// Greet a peer with the document and presence, then pump frames until it disconnects
+async fn relay(
+    socket: WebSocket,
+    room: &Room,
+    peer_id: u64,
+    events: &mut broadcast::Receiver<RoomEvent>,
+    id: TodoId,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let welcome = PresenceMessage::Welcome {
+        peer_id,
+        peers: room.peers(),
+    };
+    let Ok(welcome) = serde_json::to_string(&welcome) else {
+        return;
+    };
+    if sender.send(Message::Binary(room.snapshot())).await.is_err()
+        || sender.send(Message::Text(welcome)).await.is_err()
+    {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Binary(change))) => match room.apply(&change) {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        let _ = room.events.send(RoomEvent::Change {
+                            peer_id,
+                            change: Arc::new(change),
+                        });
+                    }
+                    Err(e) => tracing::warn!(
+                        "Peer {} sent an invalid change for todo {}: {}",
+                        peer_id,
+                        id,
+                        e
+                    ),
+                },
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientMessage::Cursor { cursor }) => room.set_cursor(peer_id, cursor),
+                    Err(e) => tracing::warn!(
+                        "Peer {} sent an invalid presence message for todo {}: {}",
+                        peer_id,
+                        id,
+                        e
+                    ),
+                },
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => {
+                let frame = match event {
+                    Ok(event) if event.peer_id() == peer_id => continue,
+                    Ok(RoomEvent::Change { change, .. }) => Message::Binary(change.as_ref().clone()),
+                    Ok(RoomEvent::Presence { message, .. }) => Message::Text(message.as_ref().clone()),
+                    // Too far behind to relay individual changes; resync with the full document
+                    Err(broadcast::error::RecvError::Lagged(_)) => Message::Binary(room.snapshot()),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if sender.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_starts_with_todo_content() {
+        let room = Room::new("# Notes", 1).unwrap();
+        assert_eq!(room.text().unwrap(), "# Notes");
+        assert_eq!(room.peer_count(), 0);
+    }
+
+    #[test]
+    fn test_room_applies_concurrent_peer_changes() {
+        let room = Room::new("hello", 1).unwrap();
+        let snapshot = room.snapshot();
+
+        let mut alice = AutoCommit::load(&snapshot).unwrap();
+        let mut bob = AutoCommit::load(&snapshot).unwrap();
+        let (_, text) = alice.get(automerge::ROOT, "content").unwrap().unwrap();
+        alice.splice_text(&text, 0, 0, "Alice: ").unwrap();
+        bob.splice_text(&text, 5, 0, " world").unwrap();
+
+        assert!(room.apply(&alice.save_incremental()).unwrap());
+        assert!(room.apply(&bob.save_incremental()).unwrap());
+
+        assert_eq!(room.text().unwrap(), "Alice: hello world");
+    }
+
+    fn todo(content: &str, version: i32) -> Todo {
+        let mut todo = Todo::new_with_validation("Notes", content).unwrap();
+        todo.version = version;
+        todo
+    }
+
+    #[test]
+    fn test_room_merges_newer_versions_with_unsaved_edits() {
+        let room = Room::new("hello", 1).unwrap();
+        let mut peer = AutoCommit::load(&room.snapshot()).unwrap();
+        let (_, text) = peer.get(automerge::ROOT, "content").unwrap().unwrap();
+        peer.splice_text(&text, 5, 0, " world").unwrap();
+        room.apply(&peer.save_incremental()).unwrap();
+
+        let change = room.catch_up(&todo("Note: hello", 2)).unwrap().unwrap();
+        assert_eq!(room.text().unwrap(), "Note: hello world");
+        assert_eq!(room.version(), 2);
+        peer.load_incremental(&change).unwrap();
+        assert_eq!(peer.text(&text).unwrap(), "Note: hello world");
+
+        // Versions the room has already seen change nothing
+        assert!(room.catch_up(&todo("hello", 2)).unwrap().is_none());
+        assert_eq!(room.text().unwrap(), "Note: hello world");
+    }
+
+    #[test]
+    fn test_room_takes_back_its_own_write_as_stored() {
+        let room = Room::new("cafe", 1).unwrap();
+        let mut peer = AutoCommit::load(&room.snapshot()).unwrap();
+        let (_, text) = peer.get(automerge::ROOT, "content").unwrap().unwrap();
+        peer.splice_text(&text, 4, 0, "\u{301}").unwrap();
+        room.apply(&peer.save_incremental()).unwrap();
+
+        let (draft, heads) = room.draft().unwrap();
+        assert_eq!(draft, "cafe\u{301}");
+        assert_eq!(room.start_save("caf\u{e9}", heads), 1);
+        room.catch_up(&todo("caf\u{e9}", 2)).unwrap();
+        room.end_save();
+        assert_eq!(room.text().unwrap(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_presence_message_format() {
+        let message = PresenceMessage::Cursor {
+            peer_id: 2,
+            cursor: Some(Cursor { anchor: 1, head: 4 }),
+        };
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            json!({ "type": "cursor", "peer_id": 2, "cursor": { "anchor": 1, "head": 4 } })
+        );
+
+        let parsed: ClientMessage =
+            serde_json::from_str(r#"{ "type": "cursor", "cursor": null }"#).unwrap();
+        assert!(matches!(parsed, ClientMessage::Cursor { cursor: None }));
+    }
+
+    #[tokio::test]
+    async fn test_presence_is_empty_without_room() {
+        let hub = CollabHub::default();
+        let id = TodoId::new();
+        let presence = hub.presence(id).await;
+        assert_eq!(presence.todo_id, id);
+        assert_eq!(presence.count, 0);
+        assert!(presence.peers.is_empty());
+    }
+
+    #[test]
+    fn test_room_ignores_garbage() {
+        let room = Room::new("hello", 1).unwrap();
+        assert!(!room.apply(b"not an automerge change").unwrap());
+        assert_eq!(room.text().unwrap(), "hello");
+    }
+}
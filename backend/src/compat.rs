@@ -0,0 +1,129 @@
+//! Compatibility with request bodies written for older versions of the API.
+//!
+//! Old clients, mostly mobile apps that update slowly, still send field names that have
+//! since been renamed. Middleware on the affected routes rewrites those names to the
+//! current ones before the handler sees the body, and marks the response with
+//! deprecation headers so client owners can spot the requests that need updating.
+//! When a body carries both names, the current one wins.
+
+use crate::deprecation::Deprecation;
+use crate::resources::MaxBodyBytes;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Extension, Request},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+/// `(deprecated, current)` field names accepted by `PATCH /api/todos/:id`
+pub const UPDATE_TODO_RENAMES: &[(&str, &str)] = &[("done", "completed")];
+
+pub const UPDATE_TODO_DEPRECATION: Deprecation = Deprecation {
+    name: "PATCH /api/todos/:id renamed fields",
+    // 2026-10-16
+    since: 1_792_108_800,
+    sunset: None,
+    successor: None,
+};
+
+/// Renames deprecated top-level fields of a JSON object; returns the deprecated names found
+pub fn rename_fields<'a>(body: &mut Value, renames: &[(&'a str, &str)]) -> Vec<&'a str> {
+    let Value::Object(fields) = body else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for (deprecated, current) in renames {
+        let Some(value) = fields.remove(*deprecated) else {
+            continue;
+        };
+        fields.entry(*current).or_insert(value);
+        found.push(*deprecated);
+    }
+    found
+}
+
+async fn translate(
+    request: Request,
+    next: Next,
+    max_body_bytes: usize,
+    renames: &[(&str, &str)],
+    deprecation: &Deprecation,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    // The rewritten body still has to pass the router's limit, so one above it is no use
+    let bytes = match to_bytes(body, max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let mut renamed = Vec::new();
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(mut json) => {
+            renamed = rename_fields(&mut json, renames);
+            if renamed.is_empty() {
+                Body::from(bytes)
+            } else {
+                Body::from(json.to_string())
+            }
+        }
+        // Let the handler's extractor report the malformed body
+        Err(_) => Body::from(bytes),
+    };
+
+    let path = parts.uri.path().to_string();
+    let mut response = next.run(Request::from_parts(parts, body)).await;
+    if !renamed.is_empty() {
+        tracing::warn!(
+            "Accepted deprecated request fields for {}: {}",
+            path,
+            renamed.join(", ")
+        );
+        deprecation.record(response.headers_mut());
+    }
+    response
+}
+
+/// Middleware for `PATCH /api/todos/:id`
+pub async fn translate_update_todo(
+    Extension(MaxBodyBytes(max_body_bytes)): Extension<MaxBodyBytes>,
+    request: Request,
+    next: Next,
+) -> Response {
+    translate(
+        request,
+        next,
+        max_body_bytes,
+        UPDATE_TODO_RENAMES,
+        &UPDATE_TODO_DEPRECATION,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rename_fields() {
+        let mut body = json!({ "title": "T", "done": true });
+        assert_eq!(rename_fields(&mut body, UPDATE_TODO_RENAMES), vec!["done"]);
+        assert_eq!(body, json!({ "title": "T", "completed": true }));
+    }
+
+    #[test]
+    fn test_current_field_wins() {
+        let mut body = json!({ "done": true, "completed": false });
+        rename_fields(&mut body, UPDATE_TODO_RENAMES);
+        assert_eq!(body, json!({ "completed": false }));
+    }
+
+    #[test]
+    fn test_untouched_without_deprecated_fields() {
+        let mut body = json!({ "completed": true });
+        assert!(rename_fields(&mut body, UPDATE_TODO_RENAMES).is_empty());
+        let mut body = json!(["done"]);
+        assert!(rename_fields(&mut body, UPDATE_TODO_RENAMES).is_empty());
+    }
+}
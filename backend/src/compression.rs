@@ -0,0 +1,122 @@
+//! Optional zstd compression of todo content at rest.
+//!
+//! With `CONTENT_COMPRESSION_MIN_BYTES` set, `DatabaseTodoRepository` stores bodies at least
+//! that long compressed in `content_zstd` and leaves `content` empty. Reads decompress
+//! transparently, so the API never sees the difference and both forms can coexist in one
+//! table. `cargo run --bin compress_content` rewrites existing rows to match the current
+//! setting, which also decompresses everything again once the variable is unset.
+//!
+//! Postgres already compresses large TEXT values when it TOASTs them; zstd pays off for
+//! long markdown bodies where its ratio is noticeably better than pglz.
+
+use crate::TodoError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentCompression {
+    /// Bodies of at least this many bytes are compressed; `None` disables compression
+    pub min_bytes: Option<usize>,
+    pub level: i32,
+}
+
+impl Default for ContentCompression {
+    fn default() -> Self {
+        Self {
+            min_bytes: None,
+            level: Self::DEFAULT_LEVEL,
+        }
+    }
+}
+
+impl ContentCompression {
+    pub const DEFAULT_LEVEL: i32 = 3;
+
+    /// Reads `CONTENT_COMPRESSION_MIN_BYTES` and `CONTENT_COMPRESSION_LEVEL`
+    pub fn from_env() -> Self {
+        Self {
+            min_bytes: std::env::var("CONTENT_COMPRESSION_MIN_BYTES")
+                .ok()
+                .and_then(|value| value.parse().ok()),
+            level: std::env::var("CONTENT_COMPRESSION_LEVEL")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(Self::DEFAULT_LEVEL),
+        }
+    }
+
+    /// Values for the `(content, content_zstd)` columns. Content stays inline when it is
+    /// short or does not get any smaller.
+    pub fn encode<'a>(&self, content: &'a str) -> (&'a str, Option<Vec<u8>>) {
+        if self.min_bytes.is_none_or(|min| content.len() < min) {
+            return (content, None);
+        }
+        match zstd::bulk::compress(content.as_bytes(), self.level) {
+            Ok(compressed) if compressed.len() < content.len() => ("", Some(compressed)),
+            Ok(_) => (content, None),
+            Err(e) => {
+                tracing::warn!("Failed to compress content, storing it inline: {}", e);
+                (content, None)
+            }
+        }
+    }
+
+    /// Content as the API sees it, from the two stored columns
+    pub fn decode(content: String, compressed: Option<&[u8]>) -> Result<String, TodoError> {
+        let Some(compressed) = compressed else {
+            return Ok(content);
+        };
+        let bytes = zstd::stream::decode_all(compressed)
+            .map_err(|e| TodoError::Internal(format!("cannot decompress content: {e}")))?;
+        String::from_utf8(bytes)
+            .map_err(|e| TodoError::Internal(format!("decompressed content is not UTF-8: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(min_bytes: usize) -> ContentCompression {
+        ContentCompression {
+            min_bytes: Some(min_bytes),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let content = "- [ ] repeat me\n".repeat(200);
+        let (inline, compressed) = enabled(1024).encode(&content);
+        assert_eq!(inline, "");
+        let compressed = compressed.unwrap();
+        assert!(compressed.len() < content.len() / 10);
+        assert_eq!(
+            ContentCompression::decode(inline.to_string(), Some(&compressed)).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn test_short_or_disabled_stays_inline() {
+        let content = "a".repeat(100);
+        assert_eq!(enabled(1024).encode(&content), (content.as_str(), None));
+        let content = "a".repeat(5000);
+        assert_eq!(
+            ContentCompression::default().encode(&content),
+            (content.as_str(), None)
+        );
+        assert_eq!(
+            ContentCompression::decode(content.clone(), None).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn test_incompressible_stays_inline() {
+        assert_eq!(enabled(1).encode("ab"), ("ab", None));
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        assert!(ContentCompression::decode(String::new(), Some(b"not zstd")).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Announcing deprecated parts of the API to clients.
+//!
+//! A `Deprecation` describes something clients should stop using: when it was deprecated,
+//! when it goes away and what replaces it. Responses that touch it carry the matching
+//! headers (`Deprecation` per RFC 9745, `Sunset` per RFC 8594 and a `successor-version`
+//! link), and every use is counted in `md_todo_deprecated_requests_total` at `/metrics`, so
+//! a removal can wait until traffic has actually moved.
+//!
+//! Deprecating a whole route takes two steps: add it to `DEPRECATED_ROUTES`, which flags
+//! the operation in the OpenAPI document, and layer `track` onto the route in the router:
+//!
+//! ```ignore
+//! .route("/api/v1/todos", get(list).layer(middleware::from_fn_with_state(&ROUTE, deprecation::track)))
+//! ```
+
+use crate::metrics::RepositoryMetrics;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::DateTime;
+use utoipa::openapi::{path::PathItemType, Deprecated, OpenApi};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    /// Label of the usage counter, e.g. `GET /api/v1/todos`
+    pub name: &'static str,
+    /// Unix time the deprecation took effect
+    pub since: i64,
+    /// Unix time after which the feature may be removed
+    pub sunset: Option<i64>,
+    /// Path or URL of the replacement
+    pub successor: Option<&'static str>,
+}
+
+impl Deprecation {
+    /// Adds the deprecation headers to a response
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let mut set = |name: &'static str, value: String| {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        };
+        set("deprecation", format!("@{}", self.since));
+        if let Some(sunset) = self.sunset.and_then(|at| DateTime::from_timestamp(at, 0)) {
+            set(
+                "sunset",
+                sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            );
+        }
+        if let Some(successor) = self.successor {
+            set("link", format!("<{successor}>; rel=\"successor-version\""));
+        }
+    }
+
+    /// Counts one use and adds the headers
+    pub fn record(&self, headers: &mut HeaderMap) {
+        RepositoryMetrics::global().record_deprecated_use(self.name);
+        self.apply(headers);
+    }
+}
+
+/// A deprecated operation as it appears in the OpenAPI document
+#[derive(Clone)]
+pub struct DeprecatedRoute {
+    pub method: PathItemType,
+    /// OpenAPI path, e.g. `/api/todos/{id}`
+    pub path: &'static str,
+    pub deprecation: Deprecation,
+}
+
+/// Operations to flag as deprecated in the OpenAPI document
+pub const DEPRECATED_ROUTES: &[DeprecatedRoute] = &[];
+
+/// Middleware for a deprecated route: `middleware::from_fn_with_state(&ROUTE, track)`
+pub async fn track(
+    State(route): State<&'static DeprecatedRoute>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    route.deprecation.record(response.headers_mut());
+    response
+}
+
+/// Sets `deprecated: true` on the given operations
+pub fn annotate(openapi: &mut OpenApi, routes: &[DeprecatedRoute]) {
+    for route in routes {
+        let operation = openapi
+            .paths
+            .paths
+            .get_mut(route.path)
+            .and_then(|item| item.operations.get_mut(&route.method));
+        match operation {
+            Some(operation) => operation.deprecated = Some(Deprecated::True),
+            None => tracing::warn!(
+                "Deprecated route {} is not in the OpenAPI document",
+                route.path
+            ),
+        }
+    }
+}
+
+/// Flags `DEPRECATED_ROUTES` in `ApiDoc`
+pub struct DeprecatedRoutes;
+
+impl utoipa::Modify for DeprecatedRoutes {
+    fn modify(&self, openapi: &mut OpenApi) {
+        annotate(openapi, DEPRECATED_ROUTES);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApiDoc;
+    use axum::{body::Body, http::Request, middleware, routing::get, Router};
+    use tower::ServiceExt;
+    use utoipa::OpenApi as _;
+
+    static HEALTH: DeprecatedRoute = DeprecatedRoute {
+        method: PathItemType::Get,
+        path: "/health",
+        deprecation: Deprecation {
+            name: "GET /health",
+            since: 1_792_108_800,
+            sunset: Some(1_807_833_600),
+            successor: Some("/api/v2/health"),
+        },
+    };
+
+    #[test]
+    fn test_apply_headers() {
+        let mut headers = HeaderMap::new();
+        HEALTH.deprecation.apply(&mut headers);
+        assert_eq!(headers["deprecation"], "@1792108800");
+        assert_eq!(headers["sunset"], "Fri, 16 Apr 2027 00:00:00 GMT");
+        assert_eq!(
+            headers["link"],
+            "</api/v2/health>; rel=\"successor-version\""
+        );
+    }
+
+    #[test]
+    fn test_annotate_openapi() {
+        let mut openapi = ApiDoc::openapi();
+        annotate(&mut openapi, std::slice::from_ref(&HEALTH));
+        let operation = &openapi.paths.paths["/health"].operations[&PathItemType::Get];
+        assert!(matches!(operation.deprecated, Some(Deprecated::True)));
+    }
+
+    #[tokio::test]
+    async fn test_track_counts_uses() {
+        let app = Router::new().route(
+            "/health",
+            get(|| async { "OK" }).layer(middleware::from_fn_with_state(&HEALTH, track)),
+        );
+        let response = app
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["deprecation"], "@1792108800");
+        assert!(RepositoryMetrics::global()
+            .render()
+            .contains("md_todo_deprecated_requests_total{deprecation=\"GET /health\"}"));
+    }
+}
@@ -0,0 +1,245 @@
+//! Newtypes for the id, title and content of a todo.
+//!
+//! `TodoTitle` and `TodoContent` can only be built through constructors that validate,
+//! so a `Todo` never holds a title or content the API would reject. Deserializing
+//! validates too. Rows read from Postgres are taken as they are, since every write went
+//! through a constructor. Request bodies keep plain strings, so that a create or update
+//! reports every invalid field at once instead of failing on the first one; handlers turn
+//! them into these types.
+//!
+//! The three types dereference to `Uuid` and `str`, and serialize as before.
+
+use crate::errors::FieldError;
+use crate::Todo;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Id of a todo, as opposed to the id of a list, tag or anything else
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(transparent)]
+#[sqlx(transparent)]
+pub struct TodoId(Uuid);
+
+impl TodoId {
+    /// A new time-ordered id
+    pub fn new() -> Self {
+        Self(Uuid::now_v7())
+    }
+}
+
+impl Default for TodoId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Uuid> for TodoId {
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+}
+
+impl From<TodoId> for Uuid {
+    fn from(id: TodoId) -> Self {
+        id.0
+    }
+}
+
+impl Deref for TodoId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl FromStr for TodoId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::from_str(s).map(Self)
+    }
+}
+
+impl fmt::Display for TodoId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Formats as the bare `Uuid`, so logs read the same as before the newtype
+impl fmt::Debug for TodoId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+/// A title that passes `Todo::check_title`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(try_from = "String", into = "String")]
+#[sqlx(transparent)]
+pub struct TodoTitle(String);
+
+impl TodoTitle {
+    /// `title` as it is, if valid
+    pub fn new(title: impl Into<String>) -> Result<Self, FieldError> {
+        let title = title.into();
+        Todo::check_title(&title)?;
+        Ok(Self(title))
+    }
+
+    /// A client-supplied title, normalized the way titles are stored; errors quote `raw`
+    pub fn parse(raw: &str) -> Result<Self, FieldError> {
+        Todo::check_raw_title(raw)?;
+        Ok(Self(Todo::normalize_title(raw)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+/// Content that passes `Todo::check_content`; empty by default
+#[derive(
+    Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(try_from = "String", into = "String")]
+#[sqlx(transparent)]
+pub struct TodoContent(String);
+
+impl TodoContent {
+    /// `content` as it is, if valid
+    pub fn new(content: impl Into<String>) -> Result<Self, FieldError> {
+        let content = content.into();
+        Todo::check_content(&content)?;
+        Ok(Self(content))
+    }
+
+    /// Content read back from storage, which was validated when it was written
+    pub(crate) fn stored(content: String) -> Self {
+        Self(content)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+macro_rules! text_newtype {
+    ($name:ident) => {
+        impl TryFrom<String> for $name {
+            type Error = FieldError;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = FieldError;
+
+            fn try_from(value: &str) -> Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl PartialEq<String> for $name {
+            fn eq(&self, other: &String) -> bool {
+                &self.0 == other
+            }
+        }
+    };
+}
+
+text_newtype!(TodoTitle);
+text_newtype!(TodoContent);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::FieldErrorCode;
+
+    #[test]
+    fn test_constructors_validate() {
+        assert_eq!(TodoTitle::new("Buy milk").unwrap(), "Buy milk");
+        assert_eq!(
+            TodoTitle::new("  ").unwrap_err().code,
+            FieldErrorCode::Required
+        );
+        assert!(TodoTitle::new("Buy\nmilk").is_err());
+        assert_eq!(TodoTitle::parse(" Buy\n milk ").unwrap(), "Buy milk");
+        assert_eq!(
+            TodoContent::new("a".repeat(10001)).unwrap_err().code,
+            FieldErrorCode::TooLong
+        );
+        assert_eq!(TodoContent::default(), "");
+    }
+
+    #[test]
+    fn test_serde_keeps_the_wire_format() {
+        let id = TodoId::new();
+        assert_eq!(
+            serde_json::to_value(id).unwrap(),
+            serde_json::json!(id.to_string())
+        );
+        let title: TodoTitle = serde_json::from_str("\"Buy milk\"").unwrap();
+        assert_eq!(serde_json::to_string(&title).unwrap(), "\"Buy milk\"");
+        assert!(serde_json::from_str::<TodoTitle>("\"\"").is_err());
+        assert!(serde_json::from_str::<TodoContent>(&format!("{:?}", "a".repeat(10001))).is_err());
+    }
+}
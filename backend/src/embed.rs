@@ -0,0 +1,421 @@
+//! Read-only widgets of shared todos for wikis and dashboards: `GET /embed/:token`.
+//!
+//! An embed is opened with a share link token. Only links without a passcode can be
+//! embedded, since an iframe cannot send `X-Share-Passcode`; expiry and view limits apply
+//! as on `/s/:token`, and every load lands in the link's access log. The page is a small
+//! self-contained document without scripts, locked down by `Content-Security-Policy`, and
+//! nothing on this path reads or sets cookies. Links without a view limit may be cached for
+//! `CACHE_MAX_AGE_SECS` and revalidated by ETag; view-limited ones are `no-store` so every
+//! view is counted.
+//!
+//! `GET /oembed?url=<share or embed URL>` describes the widget as oEmbed JSON, for tools
+//! that turn a pasted link into an embed.
+
+use crate::html::{escape_html, render_markdown, LinkTargets};
+use crate::preconditions::etag;
+use crate::share::{ShareAccess, ShareLink, ShareOutcome};
+use crate::{emoji, Todo, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// How long browsers and proxies may reuse a widget of a link without a view limit
+pub const CACHE_MAX_AGE_SECS: u32 = 60;
+pub const DEFAULT_WIDTH: u32 = 480;
+pub const DEFAULT_HEIGHT: u32 = 360;
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:0;padding:.75rem 1rem;line-height:1.5;font-size:.95rem}\
+h1{font-size:1.2rem;margin:0 0 .5rem}.done{text-decoration:line-through;color:#666}\
+pre{background:#f5f5f5;padding:.5rem;overflow-x:auto}ul{padding-left:1.25rem}";
+
+pub fn embed_path(token: &str) -> String {
+    format!("/embed/{token}")
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbedConfig {
+    /// `frame-ancestors` sources allowed to show widgets
+    pub frame_ancestors: String,
+}
+
+impl Default for EmbedConfig {
+    fn default() -> Self {
+        Self {
+            frame_ancestors: "*".to_string(),
+        }
+    }
+}
+
+impl EmbedConfig {
+    /// Reads `EMBED_FRAME_ANCESTORS`, e.g. `https://wiki.example.com`; any site by default
+    pub fn from_env() -> Self {
+        std::env::var("EMBED_FRAME_ANCESTORS")
+            .ok()
+            .map(|sources| sources.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|sources| !sources.is_empty() && !sources.contains([';', ',']))
+            .map(|frame_ancestors| Self { frame_ancestors })
+            .unwrap_or_default()
+    }
+
+    /// Scripts, frames, forms and plugins stay off; only inline styles and images load
+    pub fn content_security_policy(&self) -> String {
+        format!(
+            "default-src 'none'; style-src 'unsafe-inline'; img-src https: data:; \
+             base-uri 'none'; form-action 'none'; frame-ancestors {}",
+            self.frame_ancestors
+        )
+    }
+}
+
+/// Complete document shown inside the iframe
+pub fn render_widget(todo: &Todo) -> String {
+    let title = escape_html(&emoji::expand(&todo.title));
+    let links = LinkTargets::new(std::slice::from_ref(todo), HashMap::new());
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"referrer\" content=\"no-referrer\">\n<base target=\"_blank\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1{}>{title}</h1>\n\
+         {}</body>\n</html>\n",
+        if todo.completed {
+            " class=\"done\""
+        } else {
+            ""
+        },
+        render_markdown(&todo.content, &links)
+    )
+}
+
+fn render_message(message: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>{STYLE}</style>\n\
+         </head>\n<body>\n<p>{}</p>\n</body>\n</html>\n",
+        escape_html(message)
+    )
+}
+
+fn cache_control(link: &ShareLink) -> String {
+    if link.max_views.is_some() {
+        "no-store".to_string()
+    } else {
+        format!("public, max-age={CACHE_MAX_AGE_SECS}")
+    }
+}
+
+fn page(config: &EmbedConfig, status: StatusCode, cache_control: &str, body: String) -> Response {
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    let mut set = |name: header::HeaderName, value: &str| {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            headers.insert(name, value);
+        }
+    };
+    set(header::CONTENT_TYPE, "text/html; charset=utf-8");
+    set(
+        header::CONTENT_SECURITY_POLICY,
+        &config.content_security_policy(),
+    );
+    set(header::CACHE_CONTROL, cache_control);
+    set(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    set(header::REFERRER_POLICY, "no-referrer");
+    response
+}
+
+fn outcome_message(outcome: ShareOutcome) -> (StatusCode, &'static str) {
+    match outcome {
+        ShareOutcome::PasscodeRequired | ShareOutcome::WrongPasscode => (
+            StatusCode::FORBIDDEN,
+            "Links with a passcode cannot be embedded",
+        ),
+        _ => (StatusCode::GONE, "This todo is no longer shared"),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/embed/{token}",
+    params(
+        ("token" = String, Path, description = "Share link token"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached widget")
+    ),
+    responses(
+        (status = 200, description = "Read-only HTML widget of the shared todo", content_type = "text/html", body = String),
+        (status = 304, description = "The cached widget is still current"),
+        (status = 403, description = "The link has a passcode and cannot be embedded"),
+        (status = 404, description = "Unknown or revoked link"),
+        (status = 410, description = "Expired, out of views or locked"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "Todos"
+)]
+pub async fn get_embed<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<EmbedConfig>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let error = |status: StatusCode, message: &str| {
+        page(&config, status, "no-store", render_message(message))
+    };
+    let internal_error = |e| {
+        tracing::error!("Failed to render embed: {}", e);
+        error(StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong")
+    };
+
+    let link = match repository.get_share_link(&token).await {
+        Ok(Some(link)) => link,
+        Ok(None) => {
+            tracing::warn!("Unknown share link embedded");
+            return error(StatusCode::NOT_FOUND, "This todo is not shared");
+        }
+        Err(e) => return internal_error(e),
+    };
+    let checked = link.check(None, Utc::now());
+    let outcome = match repository
+        .record_share_access(&token, &ShareAccess::from_headers(&headers, checked))
+        .await
+    {
+        Ok(outcome) => outcome,
+        Err(e) => return internal_error(e),
+    };
+    if outcome != ShareOutcome::Viewed {
+        tracing::warn!(
+            "Denied embed of todo {}: {}",
+            link.todo_id,
+            outcome.as_str()
+        );
+        let (status, message) = outcome_message(outcome);
+        return error(status, message);
+    }
+
+    let todo = match repository.get_todo_by_id(link.todo_id).await {
+        Ok(Some(todo)) => todo,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "This todo is not shared"),
+        Err(e) => return internal_error(e),
+    };
+    let etag = etag(&todo);
+    let cache_control = cache_control(&link);
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+    let mut response = if cached {
+        page(
+            &config,
+            StatusCode::NOT_MODIFIED,
+            &cache_control,
+            String::new(),
+        )
+    } else {
+        page(
+            &config,
+            StatusCode::OK,
+            &cache_control,
+            render_widget(&todo),
+        )
+    };
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    response
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OEmbedQuery {
+    /// Share link (`/s/<token>`) or widget (`/embed/<token>`) URL
+    pub url: String,
+    /// Only `json` is supported
+    pub format: Option<String>,
+    pub maxwidth: Option<u32>,
+    pub maxheight: Option<u32>,
+}
+
+/// oEmbed 1.0 `rich` response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct OEmbed {
+    #[serde(rename = "type")]
+    #[schema(example = "rich")]
+    pub kind: String,
+    #[schema(example = "1.0")]
+    pub version: String,
+    #[schema(example = "Release checklist")]
+    pub title: String,
+    #[schema(example = "MD-Todo")]
+    pub provider_name: String,
+    /// Iframe showing the widget
+    pub html: String,
+    #[schema(example = 480)]
+    pub width: u32,
+    #[schema(example = 360)]
+    pub height: u32,
+    /// Seconds the response may be cached; absent for view-limited links
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 60)]
+    pub cache_age: Option<u32>,
+}
+
+/// The widget URL on the same origin as `url`, and its token
+pub fn widget_url(url: &str) -> Option<(Url, String)> {
+    let mut url = Url::parse(url).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    let token = match (segments.next(), segments.next(), segments.next()) {
+        (Some("s" | "embed"), Some(token), None | Some("")) if !token.is_empty() => {
+            token.to_string()
+        }
+        _ => return None,
+    };
+    url.set_path(&embed_path(&token));
+    url.set_query(None);
+    url.set_fragment(None);
+    Some((url, token))
+}
+
+#[utoipa::path(
+    get,
+    path = "/oembed",
+    params(OEmbedQuery),
+    responses(
+        (status = 200, description = "oEmbed description of the widget", body = OEmbed),
+        (status = 401, description = "The link has a passcode and cannot be embedded", body = ErrorResponse),
+        (status = 404, description = "Not a share link, or the link is unknown, expired or used up", body = ErrorResponse),
+        (status = 501, description = "Unsupported format", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_oembed<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Query(query): Query<OEmbedQuery>,
+) -> Result<Json<OEmbed>, TodoError> {
+    if query
+        .format
+        .as_deref()
+        .is_some_and(|format| format != "json")
+    {
+        return Err(TodoError::NotImplemented(
+            "Only the json oEmbed format is supported".to_string(),
+        ));
+    }
+    let Some((src, token)) = widget_url(&query.url) else {
+        tracing::warn!("oEmbed requested for unsupported URL");
+        return Err(TodoError::NotFound("Embeddable todo".to_string()));
+    };
+    let internal_error = |e| {
+        tracing::error!("Failed to describe embed: {}", e);
+        e
+    };
+    let link = repository
+        .get_share_link(&token)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| TodoError::NotFound("Share link".to_string()))?;
+    // Describing the widget is not a view, so nothing is recorded
+    match link.check(None, Utc::now()) {
+        ShareOutcome::Viewed => {}
+        ShareOutcome::PasscodeRequired | ShareOutcome::WrongPasscode => {
+            return Err(TodoError::Unauthorized(
+                "The share link needs a passcode".to_string(),
+            ))
+        }
+        _ => return Err(TodoError::NotFound("Share link".to_string())),
+    }
+    let todo = repository
+        .get_todo_by_id(link.todo_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| TodoError::NotFound(format!("Todo {}", link.todo_id)))?;
+
+    let width = query
+        .maxwidth
+        .map_or(DEFAULT_WIDTH, |max| max.min(DEFAULT_WIDTH));
+    let height = query
+        .maxheight
+        .map_or(DEFAULT_HEIGHT, |max| max.min(DEFAULT_HEIGHT));
+    let title = emoji::expand(&todo.title).into_owned();
+    Ok(Json(OEmbed {
+        kind: "rich".to_string(),
+        version: "1.0".to_string(),
+        html: format!(
+            "<iframe src=\"{}\" width=\"{width}\" height=\"{height}\" title=\"{}\" \
+             sandbox=\"allow-popups allow-popups-to-escape-sandbox\" frameborder=\"0\"></iframe>",
+            escape_html(src.as_str()),
+            escape_html(&title)
+        ),
+        title,
+        provider_name: "MD-Todo".to_string(),
+        width,
+        height,
+        cache_age: link.max_views.is_none().then_some(CACHE_MAX_AGE_SECS),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{TodoContent, TodoId, TodoTitle};
+    use crate::Priority;
+
+    #[test]
+    fn test_widget_url() {
+        let (src, token) = widget_url("https://todo.example.com/s/abc123?x=1#top").unwrap();
+        assert_eq!(src.as_str(), "https://todo.example.com/embed/abc123");
+        assert_eq!(token, "abc123");
+        let (src, _) = widget_url("http://localhost:8000/embed/abc123").unwrap();
+        assert_eq!(src.as_str(), "http://localhost:8000/embed/abc123");
+        assert!(widget_url("https://todo.example.com/t/1C").is_none());
+        assert!(widget_url("https://todo.example.com/s/").is_none());
+        assert!(widget_url("https://todo.example.com/s/abc/extra").is_none());
+        assert!(widget_url("javascript:alert(1)").is_none());
+    }
+
+    #[test]
+    fn test_render_widget_is_inert() {
+        let now = Utc::now();
+        let todo = Todo {
+            id: TodoId::new(),
+            title: TodoTitle::new("Launch <b>plan</b> :rocket:").unwrap(),
+            content: TodoContent::new("- [x] Write docs\n- [ ] Ship\n\n<script>alert(1)</script>")
+                .unwrap(),
+            completed: false,
+            version: 3,
+            metadata: serde_json::json!({}),
+            labels: vec![],
+            tags: vec![],
+            estimate_minutes: None,
+            priority: Priority::default(),
+            list_id: None,
+            archived: false,
+            owner_id: uuid::Uuid::nil(),
+            stale: false,
+            relations: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let html = render_widget(&todo);
+        assert!(html.contains("<h1>Launch &lt;b&gt;plan&lt;/b&gt; 🚀</h1>"));
+        assert!(html.contains("type=\"checkbox\""));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert_eq!(etag(&todo), format!("\"{}-3\"", todo.id));
+    }
+
+    #[test]
+    fn test_content_security_policy() {
+        let csp = EmbedConfig::default().content_security_policy();
+        assert!(csp.starts_with("default-src 'none';"));
+        assert!(csp.ends_with("frame-ancestors *"));
+        assert!(!csp.contains("script-src"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Slack/GitHub-style emoji shortcodes such as `:rocket:`.
+//!
+//! Content keeps the shortcodes as written and the HTML export turns them into emoji when
+//! it renders. With `EXPAND_EMOJI_SHORTCODES=true` they are also replaced on write, for
+//! clients that show the raw markdown. Either way only text is touched: code spans and
+//! code blocks keep their colons, and names missing from `SHORTCODES` stay as they are.
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Longest shortcode name in the catalog, bounding the search for the closing colon
+const MAX_NAME_LEN: usize = 32;
+
+/// `(name, emoji)` pairs, sorted by name for binary search
+pub const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("bell", "🔔"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("bookmark", "🔖"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("chart_with_upwards_trend", "📈"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("coffee", "☕"),
+    ("construction", "🚧"),
+    ("cry", "😢"),
+    ("dart", "🎯"),
+    ("email", "📧"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("gear", "⚙️"),
+    ("gift", "🎁"),
+    ("grin", "😁"),
+    ("hammer", "🔨"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("moneybag", "💰"),
+    ("muscle", "💪"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("paperclip", "📎"),
+    ("partying_face", "🥳"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("phone", "☎️"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("rocket", "🚀"),
+    ("rotating_light", "🚨"),
+    ("scissors", "✂️"),
+    ("see_no_evil", "🙈"),
+    ("seedling", "🌱"),
+    ("shipit", "🐿️"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("sunglasses", "😎"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thinking_face", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("trophy", "🏆"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmojiConfig {
+    /// Replace shortcodes in content before it is stored
+    pub expand_on_write: bool,
+}
+
+impl EmojiConfig {
+    /// Reads `EXPAND_EMOJI_SHORTCODES`; shortcodes are stored as written by default
+    pub fn from_env() -> Self {
+        Self {
+            expand_on_write: std::env::var("EXPAND_EMOJI_SHORTCODES")
+                .map(|value| matches!(value.as_str(), "1" | "true"))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Content as it should be stored
+    pub fn prepare_content(&self, content: &str) -> String {
+        if self.expand_on_write {
+            expand_markdown(content)
+        } else {
+            content.to_string()
+        }
+    }
+}
+
+pub fn lookup(name: &str) -> Option<&'static str> {
+    SHORTCODES
+        .binary_search_by(|(candidate, _)| (*candidate).cmp(name))
+        .ok()
+        .map(|index| SHORTCODES[index].1)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '+' | '-')
+}
+
+/// Replaces every known `:name:` in plain text
+pub fn expand(text: &str) -> Cow<'_, str> {
+    if !text.contains(':') {
+        return Cow::Borrowed(text);
+    }
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let emoji = after
+            .char_indices()
+            .take(MAX_NAME_LEN + 1)
+            .find(|&(_, c)| !is_name_char(c))
+            .filter(|&(end, c)| c == ':' && end > 0)
+            .and_then(|(end, _)| Some((end, lookup(&after[..end])?)));
+        match emoji {
+            Some((end, emoji)) => {
+                expanded.push_str(emoji);
+                rest = &after[end + 1..];
+            }
+            None => {
+                expanded.push(':');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Cow::Owned(expanded)
+}
+
+/// Byte ranges of markdown text outside code spans and code blocks
+fn text_ranges(markdown: &str) -> Vec<Range<usize>> {
+    let options =
+        Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut in_code_block = false;
+    let mut ranges = Vec::new();
+    for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            // Offsets of escaped or entity-decoded text do not map back one to one
+            Event::Text(text) if !in_code_block && markdown[range.clone()] == *text => {
+                ranges.push(range)
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Replaces shortcodes in the text of a markdown document, leaving code untouched
+pub fn expand_markdown(markdown: &str) -> String {
+    let mut expanded = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    for range in text_ranges(markdown) {
+        expanded.push_str(&markdown[copied..range.start]);
+        expanded.push_str(&expand(&markdown[range.clone()]));
+        copied = range.end;
+    }
+    expanded.push_str(&markdown[copied..]);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_is_sorted() {
+        assert!(SHORTCODES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(SHORTCODES
+            .iter()
+            .all(|(name, _)| name.len() <= MAX_NAME_LEN && name.chars().all(is_name_char)));
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("Ship it :rocket::tada:"), "Ship it 🚀🎉");
+        assert_eq!(expand(":+1: and :-1:"), "👍 and 👎");
+        assert_eq!(expand("at 10:30:45"), "at 10:30:45");
+        assert_eq!(expand(":not_an_emoji: ::rocket:"), ":not_an_emoji: :🚀");
+        assert_eq!(expand("Rocket :Rocket:"), "Rocket :Rocket:");
+    }
+
+    #[test]
+    fn test_expand_markdown_skips_code() {
+        let markdown = "Done :tada:\n\nRun `cargo :fire:`\n\n```\n:bug:\n```\n";
+        assert_eq!(
+            expand_markdown(markdown),
+            "Done 🎉\n\nRun `cargo :fire:`\n\n```\n:bug:\n```\n"
+        );
+    }
+}
@@ -0,0 +1,623 @@
+//! End-to-end encrypted todos, for deployments that should never hold plaintext.
+//!
+//! Clients encrypt a todo with a key of their own and upload the result under
+//! `/api/encrypted-todos`: the `ciphertext`, the `nonce` it was sealed with and an
+//! `envelope`, the todo's metadata (title, tags, due date...) encrypted the same way. The
+//! key is only ever named by its `key_fingerprint`, the hex SHA-256 of a public
+//! identifier the client chooses, so one server can hold the todos of several keys and
+//! clients can fetch just theirs. All byte fields travel as base64.
+//!
+//! The server cannot read what it stores, so none of the todo features apply: search,
+//! filters, moderation and exports only see plaintext todos, and searching encrypted
+//! ones happens on the client after decrypting them. Replacing a todo takes the
+//! `version` it was based on and answers 409 with the stored blob when another client got
+//! there first; merging is up to the client.
+//!
+//! `E2EE_MODE` turns this on: `off` (the default) hides the endpoints, `optional` serves
+//! them next to plaintext todos and `required` also refuses every plaintext write with
+//! 403, including capture by email, clipping and imports, and IMAP capture does not start.
+
+use crate::errors::{ErrorCode, FieldErrorCode, ValidationErrors};
+use crate::{ApiResponse, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub const MIN_NONCE_BYTES: usize = 8;
+pub const MAX_NONCE_BYTES: usize = 32;
+pub const MAX_CIPHERTEXT_BYTES: usize = 64 * 1024;
+pub const MAX_ENVELOPE_BYTES: usize = 16 * 1024;
+pub const MAX_ALGORITHM_LENGTH: usize = 32;
+
+/// Whether the server stores encrypted todos, and whether it stores anything else
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncryptionMode {
+    #[default]
+    Off,
+    Optional,
+    Required,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EncryptionConfig {
+    pub mode: EncryptionMode,
+}
+
+impl EncryptionConfig {
+    /// Reads `E2EE_MODE`: `off`, `optional` or `required`; anything else is `off`
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("E2EE_MODE").as_deref() {
+            Ok("optional") => EncryptionMode::Optional,
+            Ok("required") => EncryptionMode::Required,
+            Ok("off") | Err(_) => EncryptionMode::Off,
+            Ok(other) => {
+                tracing::warn!("Unknown E2EE_MODE {:?}; encrypted todos are off", other);
+                EncryptionMode::Off
+            }
+        };
+        Self { mode }
+    }
+
+    /// 404 while encrypted todos are off
+    fn check_enabled(&self) -> Result<(), TodoError> {
+        match self.mode {
+            EncryptionMode::Off => Err(TodoError::NotFound("Encrypted todos".to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn plaintext_allowed(&self) -> bool {
+        self.mode != EncryptionMode::Required
+    }
+}
+
+/// Middleware for routes that write plaintext todos: 403 when `E2EE_MODE` is `required`
+pub async fn refuse_plaintext(
+    Extension(config): Extension<Arc<EncryptionConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if config.plaintext_allowed() {
+        return next.run(request).await;
+    }
+    tracing::warn!(
+        "Refused plaintext write to {} {}: E2EE_MODE is required",
+        request.method(),
+        request.uri().path()
+    );
+    (
+        StatusCode::FORBIDDEN,
+        Json(ApiResponse::<()>::error(
+            ErrorCode::Forbidden,
+            "This server only stores encrypted todos; use /api/encrypted-todos".to_string(),
+        )),
+    )
+        .into_response()
+}
+
+/// Byte fields as standard base64 strings
+mod base64_bytes {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A todo as stored in end-to-end encrypted mode; only the client can read it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct EncryptedTodo {
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Uuid,
+    /// Hex SHA-256 naming the key the todo is encrypted with
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub key_fingerprint: String,
+    /// Cipher the client used, for its own bookkeeping
+    #[schema(example = "xchacha20poly1305")]
+    pub algorithm: String,
+    #[serde(with = "base64_bytes")]
+    #[schema(value_type = String, format = Byte, example = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYX")]
+    pub nonce: Vec<u8>,
+    /// The encrypted todo
+    #[serde(with = "base64_bytes")]
+    #[schema(value_type = String, format = Byte, example = "3q2+7w==")]
+    pub ciphertext: Vec<u8>,
+    /// The encrypted metadata of the todo
+    #[serde(with = "base64_bytes")]
+    #[schema(value_type = String, format = Byte, example = "yv66vg==")]
+    pub envelope: Vec<u8>,
+    #[schema(example = 1)]
+    pub version: i32,
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub created_at: DateTime<Utc>,
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Outcome of replacing an encrypted todo
+#[derive(Debug)]
+pub enum EncryptedUpdate {
+    Updated(EncryptedTodo),
+    /// The todo is past the base version; holds what is stored
+    Conflict(EncryptedTodo),
+    NotFound,
+}
+
+/// Body of `POST /api/encrypted-todos` and `PUT /api/encrypted-todos/:id`
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct EncryptedTodoRequest {
+    /// Client-generated UUIDv7 on create; one is generated when absent. Ignored on
+    /// replace.
+    #[serde(default)]
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Option<Uuid>,
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub key_fingerprint: String,
+    #[schema(example = "xchacha20poly1305", max_length = 32)]
+    pub algorithm: String,
+    /// Base64, 8 to 32 bytes
+    #[schema(format = Byte, example = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYX")]
+    pub nonce: String,
+    /// Base64, up to 64 KiB
+    #[schema(format = Byte, example = "3q2+7w==")]
+    pub ciphertext: String,
+    /// Base64, up to 16 KiB
+    #[serde(default)]
+    #[schema(format = Byte, example = "yv66vg==")]
+    pub envelope: String,
+    /// Version the replacement is based on; required on replace
+    #[serde(default)]
+    #[schema(example = 1)]
+    pub version: Option<i32>,
+}
+
+fn check_fingerprint(fingerprint: &str) -> bool {
+    fingerprint.len() == 64
+        && fingerprint
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn check_algorithm(algorithm: &str) -> bool {
+    (1..=MAX_ALGORITHM_LENGTH).contains(&algorithm.len())
+        && algorithm
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// The decoded bytes of a base64 field, or `None` after recording why they are invalid
+fn decode(
+    errors: &mut ValidationErrors,
+    field: &str,
+    text: &str,
+    min: usize,
+    max: usize,
+) -> Option<Vec<u8>> {
+    let Ok(bytes) = STANDARD.decode(text) else {
+        errors.add(
+            field,
+            FieldErrorCode::Invalid,
+            format!("{field} must be standard base64"),
+        );
+        return None;
+    };
+    if bytes.len() < min {
+        let code = match bytes.len() {
+            0 => FieldErrorCode::Required,
+            _ => FieldErrorCode::OutOfRange,
+        };
+        errors.add(field, code, format!("{field} must be at least {min} bytes"));
+        return None;
+    }
+    if bytes.len() > max {
+        errors.add(
+            field,
+            FieldErrorCode::TooLong,
+            format!("{field} cannot exceed {max} bytes"),
+        );
+        return None;
+    }
+    Some(bytes)
+}
+
+impl EncryptedTodoRequest {
+    /// The todo to store under `id`, at version 1; the repository keeps the version and
+    /// creation time of a todo it replaces
+    pub fn into_todo(self, id: Uuid) -> Result<EncryptedTodo, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if !check_fingerprint(&self.key_fingerprint) {
+            errors.add(
+                "key_fingerprint",
+                FieldErrorCode::Invalid,
+                "key_fingerprint must be 64 lowercase hex digits".to_string(),
+            );
+        }
+        if !check_algorithm(&self.algorithm) {
+            errors.add(
+                "algorithm",
+                FieldErrorCode::Invalid,
+                format!(
+                    "algorithm must be 1 to {} lowercase letters, digits or dashes",
+                    MAX_ALGORITHM_LENGTH
+                ),
+            );
+        }
+        let nonce = decode(
+            &mut errors,
+            "nonce",
+            &self.nonce,
+            MIN_NONCE_BYTES,
+            MAX_NONCE_BYTES,
+        );
+        let ciphertext = decode(
+            &mut errors,
+            "ciphertext",
+            &self.ciphertext,
+            1,
+            MAX_CIPHERTEXT_BYTES,
+        );
+        let envelope = decode(
+            &mut errors,
+            "envelope",
+            &self.envelope,
+            0,
+            MAX_ENVELOPE_BYTES,
+        );
+        match (nonce, ciphertext, envelope) {
+            (Some(nonce), Some(ciphertext), Some(envelope)) if errors.is_empty() => {
+                let now = Utc::now();
+                Ok(EncryptedTodo {
+                    id,
+                    key_fingerprint: self.key_fingerprint,
+                    algorithm: self.algorithm,
+                    nonce,
+                    ciphertext,
+                    envelope,
+                    version: 1,
+                    created_at: now,
+                    updated_at: now,
+                })
+            }
+            _ => Err(errors),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct EncryptedTodoQuery {
+    /// Only todos encrypted with this key
+    pub key_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EncryptedTodoResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<EncryptedTodo>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<EncryptedTodo>> for EncryptedTodoResponse {
+    fn from(response: ApiResponse<EncryptedTodo>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EncryptedTodoListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<EncryptedTodo>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<EncryptedTodo>>> for EncryptedTodoListResponse {
+    fn from(response: ApiResponse<Vec<EncryptedTodo>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/encrypted-todos",
+    params(EncryptedTodoQuery),
+    responses(
+        (status = 200, description = "Encrypted todos, oldest first", body = EncryptedTodoListResponse),
+        (status = 404, description = "E2EE_MODE is off", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Encrypted todos"
+)]
+pub async fn list_encrypted_todos<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<EncryptionConfig>>,
+    Query(query): Query<EncryptedTodoQuery>,
+) -> Result<Json<EncryptedTodoListResponse>, TodoError> {
+    config.check_enabled()?;
+    match repository
+        .list_encrypted_todos(query.key_fingerprint.as_deref())
+        .await
+    {
+        Ok(todos) => Ok(Json(ApiResponse::success(todos).into())),
+        Err(e) => {
+            tracing::error!("Failed to list encrypted todos: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/encrypted-todos",
+    request_body = EncryptedTodoRequest,
+    responses(
+        (status = 200, description = "Encrypted todo stored", body = EncryptedTodoResponse),
+        (status = 400, description = "Invalid fingerprint, algorithm, id or encoding", body = ErrorResponse),
+        (status = 404, description = "E2EE_MODE is off", body = ErrorResponse),
+        (status = 409, description = "A todo with this id exists", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Encrypted todos"
+)]
+pub async fn create_encrypted_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<EncryptionConfig>>,
+    Json(request): Json<EncryptedTodoRequest>,
+) -> Result<Json<EncryptedTodoResponse>, TodoError> {
+    config.check_enabled()?;
+    let id = match request.id {
+        Some(id) if id.get_version_num() != 7 => {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "id",
+                FieldErrorCode::Invalid,
+                "id must be a UUIDv7".to_string(),
+            );
+            return Err(errors.into());
+        }
+        Some(id) => id,
+        None => Uuid::now_v7(),
+    };
+    let todo = request.into_todo(id).map_err(|e| {
+        tracing::warn!("Validation failed for encrypted todo {}: {}", id, e);
+        TodoError::from(e)
+    })?;
+    match repository.create_encrypted_todo(&todo).await {
+        Ok(todo) => {
+            tracing::info!("Stored encrypted todo {}", todo.id);
+            Ok(Json(ApiResponse::success(todo).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to store encrypted todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/encrypted-todos/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Encrypted todo ID")
+    ),
+    responses(
+        (status = 200, description = "The encrypted todo", body = EncryptedTodoResponse),
+        (status = 404, description = "No such todo, or E2EE_MODE is off", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Encrypted todos"
+)]
+pub async fn get_encrypted_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<EncryptionConfig>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<EncryptedTodoResponse>, TodoError> {
+    config.check_enabled()?;
+    match repository.get_encrypted_todo(id).await {
+        Ok(Some(todo)) => Ok(Json(ApiResponse::success(todo).into())),
+        Ok(None) => Err(TodoError::NotFound(format!("Encrypted todo {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to get encrypted todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/encrypted-todos/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Encrypted todo ID")
+    ),
+    request_body = EncryptedTodoRequest,
+    responses(
+        (status = 200, description = "Encrypted todo replaced", body = EncryptedTodoResponse),
+        (status = 400, description = "Invalid fingerprint, algorithm or encoding, or no version", body = ErrorResponse),
+        (status = 404, description = "No such todo, or E2EE_MODE is off", body = ErrorResponse),
+        (status = 409, description = "Replaced by another client since the given version; stored todo returned in data", body = EncryptedTodoResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Encrypted todos"
+)]
+pub async fn replace_encrypted_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<EncryptionConfig>>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<EncryptedTodoRequest>,
+) -> Result<Json<EncryptedTodoResponse>, (StatusCode, Json<EncryptedTodoResponse>)> {
+    let error = |e: TodoError| (e.status(), Json(e.body::<EncryptedTodo>().into()));
+
+    config.check_enabled().map_err(error)?;
+    let Some(base_version) = request.version else {
+        let mut errors = ValidationErrors::default();
+        errors.add(
+            "version",
+            FieldErrorCode::Required,
+            "version is required to replace an encrypted todo".to_string(),
+        );
+        return Err(error(errors.into()));
+    };
+    let todo = request.into_todo(id).map_err(|e| {
+        tracing::warn!("Validation failed for encrypted todo {}: {}", id, e);
+        error(e.into())
+    })?;
+
+    match repository.update_encrypted_todo(&todo, base_version).await {
+        Ok(EncryptedUpdate::Updated(todo)) => {
+            tracing::info!("Replaced encrypted todo {} at version {}", id, todo.version);
+            Ok(Json(ApiResponse::success(todo).into()))
+        }
+        Ok(EncryptedUpdate::Conflict(current)) => {
+            tracing::warn!(
+                "Encrypted todo {} conflict: base version {}, current version {}",
+                id,
+                base_version,
+                current.version
+            );
+            Err((
+                StatusCode::CONFLICT,
+                Json(EncryptedTodoResponse {
+                    success: false,
+                    data: Some(current),
+                    error: Some("Todo was modified by another client".to_string()),
+                    code: Some(ErrorCode::Conflict),
+                }),
+            ))
+        }
+        Ok(EncryptedUpdate::NotFound) => {
+            tracing::warn!("Encrypted todo not found for replace with id: {}", id);
+            Err(error(TodoError::NotFound(format!("Encrypted todo {}", id))))
+        }
+        Err(e) => {
+            tracing::error!("Failed to replace encrypted todo {}: {}", id, e);
+            Err(error(e))
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/encrypted-todos/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Encrypted todo ID")
+    ),
+    responses(
+        (status = 204, description = "Encrypted todo deleted"),
+        (status = 404, description = "No such todo, or E2EE_MODE is off", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Encrypted todos"
+)]
+pub async fn delete_encrypted_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<EncryptionConfig>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, TodoError> {
+    config.check_enabled()?;
+    match repository.delete_encrypted_todo(id).await {
+        Ok(true) => {
+            tracing::info!("Deleted encrypted todo {}", id);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(TodoError::NotFound(format!("Encrypted todo {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to delete encrypted todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> EncryptedTodoRequest {
+        EncryptedTodoRequest {
+            id: None,
+            key_fingerprint: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                .to_string(),
+            algorithm: "xchacha20poly1305".to_string(),
+            nonce: STANDARD.encode([7; 24]),
+            ciphertext: STANDARD.encode(b"sealed"),
+            envelope: String::new(),
+            version: None,
+        }
+    }
+
+    #[test]
+    fn test_into_todo_decodes_base64() {
+        let id = Uuid::now_v7();
+        let todo = request().into_todo(id).unwrap();
+        assert_eq!(todo.id, id);
+        assert_eq!(todo.nonce, [7; 24]);
+        assert_eq!(todo.ciphertext, b"sealed");
+        assert!(todo.envelope.is_empty());
+
+        let json = serde_json::to_value(&todo).unwrap();
+        assert_eq!(json["ciphertext"], "c2VhbGVk");
+        let back: EncryptedTodo = serde_json::from_value(json).unwrap();
+        assert_eq!(back, todo);
+    }
+
+    #[test]
+    fn test_into_todo_reports_every_field() {
+        let errors = EncryptedTodoRequest {
+            key_fingerprint: "9F86D081".to_string(),
+            algorithm: "AES GCM".to_string(),
+            nonce: STANDARD.encode([1; 4]),
+            ciphertext: "not base64!".to_string(),
+            envelope: STANDARD.encode(vec![0; MAX_ENVELOPE_BYTES + 1]),
+            ..request()
+        }
+        .into_todo(Uuid::now_v7())
+        .unwrap_err();
+        let fields: Vec<(&str, FieldErrorCode)> = errors
+            .fields()
+            .iter()
+            .map(|error| (error.field.as_str(), error.code))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("key_fingerprint", FieldErrorCode::Invalid),
+                ("algorithm", FieldErrorCode::Invalid),
+                ("nonce", FieldErrorCode::OutOfRange),
+                ("ciphertext", FieldErrorCode::Invalid),
+                ("envelope", FieldErrorCode::TooLong),
+            ]
+        );
+    }
+}
@@ -0,0 +1,666 @@
+//! Machine-readable error codes and JSON error bodies.
+//!
+//! Every failed request answers with the `ApiResponse` envelope: `success: false`, the
+//! message in `error` and one of the `ErrorCode`s in `code`, so clients can branch on
+//! the code and show the message. Handlers build that body through `TodoError`; the
+//! `json_errors` middleware wraps whatever else fails without a JSON body, such as a
+//! request body axum could not parse or a method the route does not have.
+//!
+//! A request that breaks rules on several fields lists each of them in `fields` as a
+//! `FieldError`, so clients can mark every invalid field after one round trip.
+//!
+//! The same middleware can answer with RFC 7807 problem details
+//! (`application/problem+json`) instead: for every request when `ERROR_FORMAT=problem`,
+//! or for requests whose `Accept` header asks for `application/problem+json`.
+
+use crate::ApiResponse;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::openapi::{Content, OpenApi, Ref, RefOr};
+use utoipa::ToSchema;
+
+/// Longest plain-text error body carried over into `error`
+const MAX_MESSAGE_BYTES: usize = 4096;
+
+/// Media type of RFC 7807 problem details
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request could not be read, e.g. malformed JSON
+    BadRequest,
+    /// The request was read but breaks a rule, e.g. an empty title
+    ValidationFailed,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    /// It existed but is used up, e.g. an expired share link
+    Gone,
+    /// The request clashes with stored state, e.g. a newer version or a taken name
+    Conflict,
+    /// The resource is no longer at the version `If-Match` named
+    PreconditionFailed,
+    /// The request has to name the version it expects with `If-Match`
+    PreconditionRequired,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    TooManyRequests,
+    InternalError,
+    NotImplemented,
+    /// A dependency such as the database is down
+    Unavailable,
+}
+
+impl ErrorCode {
+    /// Code for an error response that only has a status
+    pub fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::GONE => ErrorCode::Gone,
+            StatusCode::PRECONDITION_FAILED => ErrorCode::PreconditionFailed,
+            StatusCode::PRECONDITION_REQUIRED => ErrorCode::PreconditionRequired,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::ValidationFailed,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::TooManyRequests,
+            StatusCode::NOT_IMPLEMENTED => ErrorCode::NotImplemented,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+            status if status.is_server_error() => ErrorCode::InternalError,
+            _ => ErrorCode::BadRequest,
+        }
+    }
+
+    /// The snake_case name clients see in `code`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::Gone => "gone",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::PreconditionFailed => "precondition_failed",
+            ErrorCode::PreconditionRequired => "precondition_required",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            ErrorCode::TooManyRequests => "too_many_requests",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::NotImplemented => "not_implemented",
+            ErrorCode::Unavailable => "unavailable",
+        }
+    }
+}
+
+/// Rule a field breaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldErrorCode {
+    /// Empty, or nothing left once normalized
+    Required,
+    TooLong,
+    /// A list with more entries than allowed
+    TooMany,
+    OutOfRange,
+    /// Any other rule, such as a newline in a title
+    Invalid,
+}
+
+/// What a length limit counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthUnit {
+    /// Unicode scalar values, as Postgres and JSON Schema's `maxLength` count; `あ` and
+    /// `é` are one each
+    Characters,
+}
+
+/// The length rule a too long field broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct LengthLimit {
+    #[schema(example = 255)]
+    pub max: usize,
+    /// Length of the value as given, in `unit`
+    #[schema(example = 300)]
+    pub actual: usize,
+    pub unit: LengthUnit,
+}
+
+impl LengthLimit {
+    /// `None` when `text` is within `max` characters
+    pub fn characters(text: &str, max: usize) -> Option<Self> {
+        let actual = text.chars().count();
+        (actual > max).then_some(Self {
+            max,
+            actual,
+            unit: LengthUnit::Characters,
+        })
+    }
+}
+
+/// One invalid field of a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Name of the field in the request body
+    #[schema(example = "title")]
+    pub field: String,
+    pub code: FieldErrorCode,
+    #[schema(example = "Title cannot be empty")]
+    pub message: String,
+    /// How the length of a `too_long` field was measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<LengthLimit>,
+}
+
+impl FieldError {
+    /// An error not yet tied to a field; `ValidationErrors::check` names it
+    pub fn new(code: FieldErrorCode, message: String) -> Self {
+        Self {
+            field: String::new(),
+            code,
+            message,
+            limit: None,
+        }
+    }
+
+    pub fn too_long(limit: LengthLimit, message: String) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::new(FieldErrorCode::TooLong, message)
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Every invalid field of a request, in the order they were checked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, code: FieldErrorCode, message: String) {
+        self.check(field, Err(FieldError::new(code, message)));
+    }
+
+    /// The value `result` holds, or `None` after recording its error against `field`
+    pub fn take<T>(&mut self, field: &str, result: Result<T, FieldError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.check(field, Err(error));
+                None
+            }
+        }
+    }
+
+    /// Records the rule `result` reports as broken, if any, against `field`
+    pub fn check(&mut self, field: &str, result: Result<(), FieldError>) {
+        if let Err(error) = result {
+            self.0.push(FieldError {
+                field: field.to_string(),
+                ..error
+            });
+        }
+    }
+
+    pub fn fields(&self) -> &[FieldError] {
+        &self.0
+    }
+
+    pub fn into_fields(self) -> Vec<FieldError> {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// The messages of all fields, for places that report a single string
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, field) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            f.write_str(&field.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Body of every failed request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "success": false,
+    "data": null,
+    "error": "Title cannot be empty; Estimate cannot be negative",
+    "code": "validation_failed",
+    "fields": [
+        {"field": "title", "code": "required", "message": "Title cannot be empty"},
+        {"field": "estimate_minutes", "code": "out_of_range", "message": "Estimate cannot be negative"}
+    ]
+}))]
+pub struct ErrorResponse {
+    #[schema(example = false)]
+    pub success: bool,
+    /// Usually null; a 409 on content carries the current todo
+    pub data: Option<serde_json::Value>,
+    /// Message to show; with several invalid fields, their messages joined by `; `
+    #[schema(example = "Title cannot be empty")]
+    pub error: Option<String>,
+    pub code: Option<ErrorCode>,
+    /// Each invalid field of a create or update; left out otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+/// How failed requests describe themselves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// The `ApiResponse` envelope
+    #[default]
+    Envelope,
+    /// RFC 7807 problem details
+    Problem,
+}
+
+impl ErrorFormat {
+    /// Reads `ERROR_FORMAT`: `envelope` (default) or `problem`
+    pub fn from_env() -> Self {
+        match std::env::var("ERROR_FORMAT") {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                tracing::warn!(
+                    "Ignoring ERROR_FORMAT={:?}, expected envelope or problem",
+                    value
+                );
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Format for one request: problem details when configured, or when its `Accept`
+    /// header lists `application/problem+json`
+    pub fn negotiate(self, headers: &HeaderMap) -> Self {
+        let wants_problem = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media| {
+                media
+                    .split(';')
+                    .next()
+                    .is_some_and(|media| media.trim().eq_ignore_ascii_case(PROBLEM_JSON))
+            });
+        if wants_problem {
+            Self::Problem
+        } else {
+            self
+        }
+    }
+}
+
+impl std::str::FromStr for ErrorFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "envelope" => Ok(Self::Envelope),
+            "problem" => Ok(Self::Problem),
+            _ => Err(()),
+        }
+    }
+}
+
+/// RFC 7807 body of a failed request, sent as `application/problem+json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "type": "urn:md-todo:problem:validation_failed",
+    "title": "Bad Request",
+    "status": 400,
+    "detail": "Title cannot be empty",
+    "instance": "/api/todos",
+    "code": "validation_failed"
+}))]
+pub struct ProblemDetails {
+    /// URN naming the kind of problem, one per `ErrorCode`
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// Reason phrase of the status
+    pub title: String,
+    pub status: u16,
+    /// The same message the envelope has in `error`
+    pub detail: Option<String>,
+    /// Path of the failed request
+    pub instance: Option<String>,
+    pub code: ErrorCode,
+    /// What the envelope had in `data`, e.g. the current todo of a 409 on content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    /// What the envelope had in `fields`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode, code: ErrorCode, detail: Option<String>) -> Self {
+        Self {
+            problem_type: format!("urn:md-todo:problem:{}", code.as_str()),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            code,
+            data: None,
+            fields: Vec::new(),
+        }
+    }
+
+    fn from_envelope(status: StatusCode, envelope: ApiResponse<serde_json::Value>) -> Self {
+        let code = envelope
+            .code
+            .unwrap_or_else(|| ErrorCode::for_status(status));
+        Self {
+            data: envelope.data,
+            fields: envelope.fields,
+            ..Self::new(status, code, envelope.error)
+        }
+    }
+}
+
+/// Gives error responses without a JSON body the `ApiResponse` envelope. Plain-text
+/// bodies, such as axum's extractor rejections, become the message; HTML pages and
+/// other typed bodies are left alone. When the request gets problem details, envelope
+/// bodies are rewritten into them too.
+pub async fn json_errors(
+    State(format): State<ErrorFormat>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let format = format.negotiate(request.headers());
+    let instance = request.uri().path().to_string();
+    let response = next.run(request).await;
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+    let content_type = response.headers().get(header::CONTENT_TYPE);
+    let is_json = content_type.is_some_and(is_json);
+    if content_type.is_some_and(|value| !is_plain_text(value)) && !is_json {
+        return response;
+    }
+    if is_json && format == ErrorFormat::Envelope {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let problem = if is_json {
+        // Envelope bodies can carry a whole todo, so they are read in full
+        let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+            return Response::from_parts(parts, Body::empty());
+        };
+        match serde_json::from_slice::<ApiResponse<serde_json::Value>>(&bytes) {
+            Ok(envelope) if !envelope.success => ProblemDetails::from_envelope(status, envelope),
+            // Some other JSON document, e.g. the readiness report
+            _ => return Response::from_parts(parts, Body::from(bytes)),
+        }
+    } else {
+        let text = match to_bytes(body, MAX_MESSAGE_BYTES).await {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_string(),
+            Err(_) => String::new(),
+        };
+        // Server errors are not described, as for TodoError
+        let message = if text.is_empty() || status.is_server_error() {
+            status.canonical_reason().unwrap_or_default().to_string()
+        } else {
+            text
+        };
+        ProblemDetails::new(status, ErrorCode::for_status(status), Some(message))
+    };
+
+    let mut response = match format {
+        ErrorFormat::Envelope => {
+            let body = ApiResponse::<()>::error(problem.code, problem.detail.unwrap_or_default());
+            (status, Json(body)).into_response()
+        }
+        ErrorFormat::Problem => {
+            let problem = ProblemDetails {
+                instance: Some(instance),
+                ..problem
+            };
+            let mut response = (status, Json(problem)).into_response();
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+            response
+        }
+    };
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().append(name, value.clone());
+        }
+    }
+    response
+}
+
+/// Lists `ProblemDetails` as `application/problem+json` next to every `ErrorResponse`
+/// in `ApiDoc`
+pub struct ProblemResponses;
+
+impl utoipa::Modify for ProblemResponses {
+    fn modify(&self, openapi: &mut OpenApi) {
+        let envelope = Ref::from_schema_name("ErrorResponse").ref_location;
+        let operations = openapi
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|item| item.operations.values_mut());
+        for operation in operations {
+            for response in operation.responses.responses.values_mut() {
+                let RefOr::T(response) = response else {
+                    continue;
+                };
+                let has_envelope = response.content.values().any(|content| {
+                    matches!(&content.schema, RefOr::Ref(schema) if schema.ref_location == envelope)
+                });
+                if has_envelope {
+                    response.content.insert(
+                        PROBLEM_JSON.to_string(),
+                        Content::new(Ref::from_schema_name("ProblemDetails")),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn is_plain_text(value: &HeaderValue) -> bool {
+    value
+        .to_str()
+        .is_ok_and(|value| value.starts_with("text/plain"))
+}
+
+fn is_json(value: &HeaderValue) -> bool {
+    value
+        .to_str()
+        .is_ok_and(|value| value.starts_with("application/json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn fetch(app: &Router, method: &str, uri: &str) -> (StatusCode, serde_json::Value) {
+        let (status, _, body) = fetch_with(app, method, uri, None).await;
+        (status, body)
+    }
+
+    async fn fetch_with(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        accept: Option<&str>,
+    ) -> (StatusCode, Option<HeaderValue>, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            content_type,
+            serde_json::from_slice(&bytes).unwrap_or_default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_bodiless_errors_get_the_envelope() {
+        let app = Router::new()
+            .route("/gone", get(|| async { StatusCode::GONE }))
+            .route(
+                "/broken",
+                get(|| async { (StatusCode::BAD_GATEWAY, "upstream said no") }),
+            )
+            .route(
+                "/bad",
+                get(|| async { (StatusCode::BAD_REQUEST, "Missing field `title`") }),
+            )
+            .layer(middleware::from_fn_with_state(
+                ErrorFormat::Envelope,
+                json_errors,
+            ));
+
+        let (status, body) = fetch(&app, "GET", "/bad").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "success": false,
+                "data": null,
+                "error": "Missing field `title`",
+                "code": "bad_request"
+            })
+        );
+
+        let (_, body) = fetch(&app, "GET", "/gone").await;
+        assert_eq!(body["error"], "Gone");
+        assert_eq!(body["code"], "gone");
+
+        let (_, body) = fetch(&app, "GET", "/broken").await;
+        assert_eq!(body["error"], "Bad Gateway");
+        assert_eq!(body["code"], "internal_error");
+
+        let (status, body) = fetch(&app, "POST", "/bad").await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(body["code"], "method_not_allowed");
+    }
+
+    fn problem_app(format: ErrorFormat) -> Router {
+        Router::new()
+            .route(
+                "/todos/:id",
+                get(|| async {
+                    let body = ApiResponse::<()>::error(ErrorCode::NotFound, "Todo 42".to_string());
+                    (StatusCode::NOT_FOUND, Json(body))
+                }),
+            )
+            .route(
+                "/bad",
+                get(|| async { (StatusCode::BAD_REQUEST, "Missing field `title`") }),
+            )
+            .route(
+                "/ready",
+                get(|| async {
+                    let body = serde_json::json!({"status": "not ready"});
+                    (StatusCode::SERVICE_UNAVAILABLE, Json(body))
+                }),
+            )
+            .layer(middleware::from_fn_with_state(format, json_errors))
+    }
+
+    #[tokio::test]
+    async fn test_problem_details_by_accept_or_config() {
+        let app = problem_app(ErrorFormat::Envelope);
+        let (_, content_type, body) = fetch_with(&app, "GET", "/todos/42", None).await;
+        assert_eq!(content_type.unwrap(), "application/json");
+        assert_eq!(body["code"], "not_found");
+
+        let accept = Some("application/problem+json, application/json;q=0.9");
+        let (status, content_type, body) = fetch_with(&app, "GET", "/todos/42", accept).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type.unwrap(), PROBLEM_JSON);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "urn:md-todo:problem:not_found",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Todo 42",
+                "instance": "/todos/42",
+                "code": "not_found"
+            })
+        );
+
+        let app = problem_app(ErrorFormat::Problem);
+        let (_, content_type, body) = fetch_with(&app, "GET", "/bad", None).await;
+        assert_eq!(content_type.unwrap(), PROBLEM_JSON);
+        assert_eq!(body["detail"], "Missing field `title`");
+        assert_eq!(body["type"], "urn:md-todo:problem:bad_request");
+
+        // JSON that is not an envelope is left as it is
+        let (status, content_type, body) = fetch_with(&app, "GET", "/ready", None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(content_type.unwrap(), "application/json");
+        assert_eq!(body, serde_json::json!({"status": "not ready"}));
+    }
+
+    #[test]
+    fn test_error_format_parsing() {
+        assert_eq!("problem".parse(), Ok(ErrorFormat::Problem));
+        assert_eq!(" Envelope ".parse(), Ok(ErrorFormat::Envelope));
+        assert!("rfc7807".parse::<ErrorFormat>().is_err());
+    }
+
+    #[test]
+    fn test_code_names_match_serde() {
+        for code in [ErrorCode::ValidationFailed, ErrorCode::TooManyRequests] {
+            assert_eq!(serde_json::to_value(code).unwrap(), code.as_str());
+        }
+    }
+}
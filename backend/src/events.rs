@@ -0,0 +1,768 @@
+//! Domain events for todo changes, the in-process bus that carries them and their delivery
+//! to external brokers.
+//!
+//! `PublishingTodoRepository` wraps any repository and hands a `TodoEvent` to an
+//! `EventPublisher` after every successful write, so features can react to changes
+//! without hooking into handlers and other services without polling the REST API. The
+//! server gives it an `EventBus`, to which each consumer subscribes as an `EventPublisher`
+//! of its own: the broker, the change feed of `/api/todos/events`, the other replicas
+//! (`notify`, or `fanout` with Redis) and webhooks. Publishing is best effort: a failing
+//! subscriber is logged but never fails the request that caused the event, nor keeps the
+//! event from the other subscribers. With a database the events go to the broker through the task queue first
+//! (`QueuedEventPublisher`), which retries delivery while the broker is down.
+
+use crate::auth::User;
+use crate::autocomplete::{AutocompleteQuery, TitleSuggestion};
+use crate::domain::{TodoContent, TodoId};
+use crate::encryption::{EncryptedTodo, EncryptedUpdate};
+use crate::imports::{ImportItem, ImportJob};
+use crate::lists::{ListUpdate, TodoList, UpdateTodoListRequest};
+use crate::metadata::MetadataField;
+use crate::moderation::{Finding, ModerationFlag};
+use crate::policy::ValidationPolicy;
+use crate::queue::{QueueError, TaskHandler, TaskQueue};
+use crate::relations::{RelationSummary, TodoRelation};
+use crate::search::{SearchHit, SearchQuery};
+use crate::share::{ShareAccess, ShareLink, ShareOutcome};
+use crate::split::TodoSplit;
+use crate::stats::{AgingReport, WorkloadTotals};
+use crate::streaks::CompletionDay;
+use crate::subtasks::SubtaskMerge;
+use crate::tags::{Tag, TagRename};
+use crate::webhooks::{Webhook, WebhookDelivery};
+use crate::{
+    ContentUpdate, SortOrder, Todo, TodoError, TodoFilter, TodoRepositoryTrait, TodoSort,
+    TodoUpsert, UpdateTodoRequest,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum TodoEvent {
+    Created(Todo),
+    Updated(Todo),
+    /// Follows the `Updated` of a write that completed an open todo
+    Completed(Todo),
+    Deleted {
+        id: TodoId,
+    },
+}
+
+impl TodoEvent {
+    /// Every `kind`
+    pub const KINDS: [&'static str; 4] = ["created", "updated", "completed", "deleted"];
+
+    /// Short, stable name used in broker subjects/topics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TodoEvent::Created(_) => "created",
+            TodoEvent::Updated(_) => "updated",
+            TodoEvent::Completed(_) => "completed",
+            TodoEvent::Deleted { .. } => "deleted",
+        }
+    }
+
+    pub fn todo_id(&self) -> TodoId {
+        match self {
+            TodoEvent::Created(todo) | TodoEvent::Updated(todo) | TodoEvent::Completed(todo) => {
+                todo.id
+            }
+            TodoEvent::Deleted { id } => *id,
+        }
+    }
+}
+
+pub type PublishError = Box<dyn std::error::Error + Send + Sync>;
+
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError>;
+
+    /// Fails when events cannot currently reach the broker
+    async fn check(&self) -> Result<(), PublishError> {
+        Ok(())
+    }
+}
+
+/// Publisher used when no broker is configured
+pub struct NoopEventPublisher;
+
+#[async_trait]
+impl EventPublisher for NoopEventPublisher {
+    async fn publish(&self, _event: &TodoEvent) -> Result<(), PublishError> {
+        Ok(())
+    }
+}
+
+/// Hands every event to each subscriber in the order they subscribed
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: RwLock<Vec<(String, Arc<dyn EventPublisher>)>>,
+}
+
+impl EventBus {
+    /// `name` identifies the subscriber in logs
+    pub fn subscribe(&self, name: &str, subscriber: Arc<dyn EventPublisher>) {
+        self.subscribers
+            .write()
+            .unwrap()
+            .push((name.to_string(), subscriber));
+    }
+}
+
+#[async_trait]
+impl EventPublisher for EventBus {
+    /// Every subscriber gets the event even when an earlier one fails; failures are only
+    /// logged
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        let subscribers = self.subscribers.read().unwrap().clone();
+        for (name, subscriber) in subscribers {
+            if let Err(e) = subscriber.publish(event).await {
+                tracing::error!(
+                    "Subscriber {} failed on {} event for todo {}: {}",
+                    name,
+                    event.kind(),
+                    event.todo_id(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Publishes JSON-encoded events to `<prefix>.<kind>` subjects on a NATS server
+#[cfg(feature = "nats")]
+pub struct NatsEventPublisher {
+    client: async_nats::Client,
+    subject_prefix: String,
+}
+
+#[cfg(feature = "nats")]
+impl NatsEventPublisher {
+    pub const DEFAULT_SUBJECT_PREFIX: &'static str = "md_todo.todos";
+
+    pub async fn connect(url: &str, subject_prefix: &str) -> Result<Self, PublishError> {
+        let client = async_nats::connect(url).await?;
+        Ok(Self {
+            client,
+            subject_prefix: subject_prefix.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventPublisher for NatsEventPublisher {
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        let subject = format!("{}.{}", self.subject_prefix, event.kind());
+        let payload = serde_json::to_vec(event)?;
+        self.client.publish(subject, payload.into()).await?;
+        Ok(())
+    }
+
+    /// A round trip to the server
+    async fn check(&self) -> Result<(), PublishError> {
+        let state = self.client.connection_state();
+        if state != async_nats::connection::State::Connected {
+            return Err(format!("NATS connection is {}", state).into());
+        }
+        self.client.flush().await?;
+        Ok(())
+    }
+}
+
+/// Task kind of an event waiting in the task queue for the broker
+pub const PUBLISH_EVENT_TASK: &str = "publish_event";
+
+/// Queues events in the task queue, so a broker outage delays them instead of dropping
+/// them. Retries can reorder events of a todo; consumers should compare its `version`.
+pub struct QueuedEventPublisher {
+    queue: TaskQueue,
+}
+
+impl QueuedEventPublisher {
+    pub fn new(queue: TaskQueue) -> Self {
+        Self { queue }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for QueuedEventPublisher {
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        self.queue
+            .enqueue(PUBLISH_EVENT_TASK, &serde_json::to_value(event)?)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Hands queued events to the broker
+pub struct EventDelivery {
+    broker: Arc<dyn EventPublisher>,
+}
+
+impl EventDelivery {
+    pub fn new(broker: Arc<dyn EventPublisher>) -> Self {
+        Self { broker }
+    }
+}
+
+#[async_trait]
+impl TaskHandler for EventDelivery {
+    async fn run(&self, payload: &serde_json::Value) -> Result<(), QueueError> {
+        let event: TodoEvent = serde_json::from_value(payload.clone())?;
+        self.broker.publish(&event).await
+    }
+}
+
+/// Repository decorator that publishes an event after each successful write
+pub struct PublishingTodoRepository<R> {
+    inner: R,
+    publisher: Arc<dyn EventPublisher>,
+}
+
+impl<R: TodoRepositoryTrait> PublishingTodoRepository<R> {
+    pub fn new(inner: R, publisher: Arc<dyn EventPublisher>) -> Self {
+        Self { inner, publisher }
+    }
+
+    async fn publish(&self, event: TodoEvent) {
+        if let Err(e) = self.publisher.publish(&event).await {
+            tracing::error!(
+                "Failed to publish {} event for todo {}: {}",
+                event.kind(),
+                event.todo_id(),
+                e
+            );
+        }
+    }
+
+    /// The open todos among `ids`, read before a write that may complete them
+    async fn open_ids(&self, ids: &[TodoId]) -> HashSet<TodoId> {
+        let todos = futures::future::join_all(ids.iter().map(|id| self.inner.get_todo_by_id(*id)));
+        todos
+            .await
+            .into_iter()
+            .filter_map(|todo| todo.ok().flatten())
+            .filter(|todo| !todo.completed)
+            .map(|todo| todo.id)
+            .collect()
+    }
+
+    /// `Updated`, then `Completed` when the todo was among `open` and is completed now
+    async fn publish_update(&self, todo: &Todo, open: &HashSet<TodoId>) {
+        self.publish(TodoEvent::Updated(todo.clone())).await;
+        if todo.completed && open.contains(&todo.id) {
+            self.publish(TodoEvent::Completed(todo.clone())).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepositoryTrait> TodoRepositoryTrait for PublishingTodoRepository<R> {
+    async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError> {
+        let created = self.inner.create_todo(todo).await?;
+        self.publish(TodoEvent::Created(created.clone())).await;
+        Ok(created)
+    }
+
+    async fn create_todos_bulk(&self, todos: &[Todo]) -> Result<Vec<Todo>, TodoError> {
+        let created = self.inner.create_todos_bulk(todos).await?;
+        for todo in &created {
+            self.publish(TodoEvent::Created(todo.clone())).await;
+        }
+        Ok(created)
+    }
+
+    async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        self.inner.get_all_todos().await
+    }
+
+    async fn find_todos(&self, filter: &TodoFilter) -> Result<Vec<Todo>, TodoError> {
+        self.inner.find_todos(filter).await
+    }
+
+    async fn search_todos(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, TodoError> {
+        self.inner.search_todos(query).await
+    }
+
+    async fn autocomplete_titles(
+        &self,
+        query: &AutocompleteQuery,
+    ) -> Result<Vec<TitleSuggestion>, TodoError> {
+        self.inner.autocomplete_titles(query).await
+    }
+
+    async fn get_todos_after(
+        &self,
+        cursor: Option<TodoId>,
+        limit: i64,
+        sort: TodoSort,
+        order: SortOrder,
+        include_archived: bool,
+    ) -> Result<Vec<Todo>, TodoError> {
+        self.inner
+            .get_todos_after(cursor, limit, sort, order, include_archived)
+            .await
+    }
+
+    async fn get_todo_by_id(&self, id: TodoId) -> Result<Option<Todo>, TodoError> {
+        self.inner.get_todo_by_id(id).await
+    }
+
+    async fn update_todo(
+        &self,
+        id: TodoId,
+        updates: &UpdateTodoRequest,
+        expected_version: Option<i32>,
+    ) -> Result<Option<Todo>, TodoError> {
+        let open = match updates.completed {
+            Some(true) => self.open_ids(&[id]).await,
+            _ => HashSet::new(),
+        };
+        let updated = self
+            .inner
+            .update_todo(id, updates, expected_version)
+            .await?;
+        if let Some(todo) = &updated {
+            self.publish_update(todo, &open).await;
+        }
+        Ok(updated)
+    }
+
+    async fn upsert_todo(
+        &self,
+        todo: &Todo,
+        expected_version: Option<i32>,
+    ) -> Result<TodoUpsert, TodoError> {
+        let open = match todo.completed {
+            true => self.open_ids(&[todo.id]).await,
+            false => HashSet::new(),
+        };
+        let upserted = self.inner.upsert_todo(todo, expected_version).await?;
+        match &upserted {
+            TodoUpsert::Created(todo) => self.publish(TodoEvent::Created(todo.clone())).await,
+            TodoUpsert::Replaced(todo) => self.publish_update(todo, &open).await,
+        }
+        Ok(upserted)
+    }
+
+    async fn update_todos_bulk(
+        &self,
+        ids: &[TodoId],
+        updates: &UpdateTodoRequest,
+    ) -> Result<Vec<Todo>, TodoError> {
+        let open = match updates.completed {
+            Some(true) => self.open_ids(ids).await,
+            _ => HashSet::new(),
+        };
+        let updated = self.inner.update_todos_bulk(ids, updates).await?;
+        for todo in &updated {
+            self.publish_update(todo, &open).await;
+        }
+        Ok(updated)
+    }
+
+    async fn update_todo_content(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        base_version: i32,
+    ) -> Result<ContentUpdate, TodoError> {
+        let outcome = self
+            .inner
+            .update_todo_content(id, content, base_version)
+            .await?;
+        if let ContentUpdate::Updated(todo) = &outcome {
+            self.publish(TodoEvent::Updated(todo.clone())).await;
+        }
+        Ok(outcome)
+    }
+
+    async fn split_todo(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        parts: &[Todo],
+    ) -> Result<Option<TodoSplit>, TodoError> {
+        let split = self
+            .inner
+            .split_todo(id, content, expected_version, parts)
+            .await?;
+        if let Some(split) = &split {
+            self.publish(TodoEvent::Updated(split.todo.clone())).await;
+            for part in &split.parts {
+                self.publish(TodoEvent::Created(part.clone())).await;
+            }
+        }
+        Ok(split)
+    }
+
+    async fn merge_subtasks(
+        &self,
+        parent: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        subtasks: &[TodoId],
+    ) -> Result<Option<SubtaskMerge>, TodoError> {
+        let merge = self
+            .inner
+            .merge_subtasks(parent, content, expected_version, subtasks)
+            .await?;
+        if let Some(merge) = &merge {
+            self.publish(TodoEvent::Updated(merge.parent.clone())).await;
+            for subtask in &merge.subtasks {
+                self.publish(TodoEvent::Updated(subtask.clone())).await;
+            }
+        }
+        Ok(merge)
+    }
+
+    async fn delete_todo(
+        &self,
+        id: TodoId,
+        expected_version: Option<i32>,
+    ) -> Result<bool, TodoError> {
+        let deleted = self.inner.delete_todo(id, expected_version).await?;
+        if deleted {
+            self.publish(TodoEvent::Deleted { id }).await;
+        }
+        Ok(deleted)
+    }
+
+    async fn delete_todos_bulk(&self, ids: &[TodoId]) -> Result<Vec<TodoId>, TodoError> {
+        let deleted = self.inner.delete_todos_bulk(ids).await?;
+        for id in &deleted {
+            self.publish(TodoEvent::Deleted { id: *id }).await;
+        }
+        Ok(deleted)
+    }
+
+    async fn complete_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        let completed = self.inner.complete_all_todos().await?;
+        // Only open todos are completed, so each one gets a `Completed`
+        for todo in &completed {
+            self.publish(TodoEvent::Updated(todo.clone())).await;
+            self.publish(TodoEvent::Completed(todo.clone())).await;
+        }
+        Ok(completed)
+    }
+
+    async fn delete_completed_todos(&self) -> Result<Vec<TodoId>, TodoError> {
+        let deleted = self.inner.delete_completed_todos().await?;
+        for id in &deleted {
+            self.publish(TodoEvent::Deleted { id: *id }).await;
+        }
+        Ok(deleted)
+    }
+
+    async fn get_short_id(&self, todo_id: TodoId) -> Result<Option<String>, TodoError> {
+        self.inner.get_short_id(todo_id).await
+    }
+
+    async fn resolve_short_id(&self, short_id: &str) -> Result<Option<TodoId>, TodoError> {
+        self.inner.resolve_short_id(short_id).await
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<ShareLink, TodoError> {
+        self.inner.create_share_link(link).await
+    }
+
+    async fn list_share_links(&self, todo_id: TodoId) -> Result<Vec<ShareLink>, TodoError> {
+        self.inner.list_share_links(todo_id).await
+    }
+
+    async fn get_share_link(&self, token: &str) -> Result<Option<ShareLink>, TodoError> {
+        self.inner.get_share_link(token).await
+    }
+
+    async fn delete_share_link(&self, token: &str) -> Result<bool, TodoError> {
+        self.inner.delete_share_link(token).await
+    }
+
+    async fn record_share_access(
+        &self,
+        token: &str,
+        access: &ShareAccess,
+    ) -> Result<ShareOutcome, TodoError> {
+        self.inner.record_share_access(token, access).await
+    }
+
+    async fn share_access_log(
+        &self,
+        token: &str,
+        limit: i64,
+    ) -> Result<Vec<ShareAccess>, TodoError> {
+        self.inner.share_access_log(token, limit).await
+    }
+
+    async fn list_metadata_fields(&self) -> Result<Vec<MetadataField>, TodoError> {
+        self.inner.list_metadata_fields().await
+    }
+
+    async fn upsert_metadata_field(
+        &self,
+        field: &MetadataField,
+    ) -> Result<MetadataField, TodoError> {
+        self.inner.upsert_metadata_field(field).await
+    }
+
+    async fn delete_metadata_field(&self, key: &str) -> Result<bool, TodoError> {
+        self.inner.delete_metadata_field(key).await
+    }
+
+    async fn validation_policy(&self) -> Result<ValidationPolicy, TodoError> {
+        self.inner.validation_policy().await
+    }
+
+    async fn set_validation_policy(
+        &self,
+        policy: &ValidationPolicy,
+    ) -> Result<ValidationPolicy, TodoError> {
+        self.inner.set_validation_policy(policy).await
+    }
+
+    async fn flag_todo(&self, id: TodoId, findings: &[Finding]) -> Result<(), TodoError> {
+        self.inner.flag_todo(id, findings).await
+    }
+
+    async fn moderation_flags(&self) -> Result<Vec<ModerationFlag>, TodoError> {
+        self.inner.moderation_flags().await
+    }
+
+    async fn dismiss_moderation_flag(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.inner.dismiss_moderation_flag(id).await
+    }
+
+    async fn create_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+    ) -> Result<EncryptedTodo, TodoError> {
+        self.inner.create_encrypted_todo(todo).await
+    }
+
+    async fn list_encrypted_todos(
+        &self,
+        key_fingerprint: Option<&str>,
+    ) -> Result<Vec<EncryptedTodo>, TodoError> {
+        self.inner.list_encrypted_todos(key_fingerprint).await
+    }
+
+    async fn get_encrypted_todo(&self, id: Uuid) -> Result<Option<EncryptedTodo>, TodoError> {
+        self.inner.get_encrypted_todo(id).await
+    }
+
+    async fn update_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+        base_version: i32,
+    ) -> Result<EncryptedUpdate, TodoError> {
+        self.inner.update_encrypted_todo(todo, base_version).await
+    }
+
+    async fn delete_encrypted_todo(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.inner.delete_encrypted_todo(id).await
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, TodoError> {
+        self.inner.create_webhook(webhook).await
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, TodoError> {
+        self.inner.list_webhooks().await
+    }
+
+    async fn get_webhook(&self, id: Uuid) -> Result<Option<Webhook>, TodoError> {
+        self.inner.get_webhook(id).await
+    }
+
+    async fn update_webhook(&self, webhook: &Webhook) -> Result<Option<Webhook>, TodoError> {
+        self.inner.update_webhook(webhook).await
+    }
+
+    async fn delete_webhook(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.inner.delete_webhook(id).await
+    }
+
+    async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), TodoError> {
+        self.inner.record_webhook_delivery(delivery).await
+    }
+
+    async fn webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, TodoError> {
+        self.inner.webhook_deliveries(webhook_id, limit).await
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, TodoError> {
+        self.inner.list_tags().await
+    }
+
+    async fn get_tag(&self, id: Uuid) -> Result<Option<Tag>, TodoError> {
+        self.inner.get_tag(id).await
+    }
+
+    async fn create_tag(&self, tag: &Tag) -> Result<Option<Tag>, TodoError> {
+        self.inner.create_tag(tag).await
+    }
+
+    async fn suggest_tags(&self, text: &str, limit: i64) -> Result<Vec<Tag>, TodoError> {
+        self.inner.suggest_tags(text, limit).await
+    }
+
+    async fn rename_tag(&self, id: Uuid, name: &str, merge: bool) -> Result<TagRename, TodoError> {
+        let outcome = self.inner.rename_tag(id, name, merge).await?;
+        if let TagRename::Renamed(_, todos) | TagRename::Merged(_, todos) = &outcome {
+            for todo in todos {
+                self.publish(TodoEvent::Updated(todo.clone())).await;
+            }
+        }
+        Ok(outcome)
+    }
+
+    async fn delete_tag(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        let detached = self.inner.delete_tag(id).await?;
+        for todo in detached.iter().flatten() {
+            self.publish(TodoEvent::Updated(todo.clone())).await;
+        }
+        Ok(detached)
+    }
+
+    async fn aging_report(&self) -> Result<AgingReport, TodoError> {
+        self.inner.aging_report().await
+    }
+
+    async fn workload_totals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<WorkloadTotals, TodoError> {
+        self.inner.workload_totals(from, to).await
+    }
+
+    async fn completion_days(&self, user: Uuid) -> Result<Vec<CompletionDay>, TodoError> {
+        self.inner.completion_days(user).await
+    }
+
+    async fn set_plan(
+        &self,
+        user: Uuid,
+        day: NaiveDate,
+        todo_ids: &[TodoId],
+    ) -> Result<(), TodoError> {
+        self.inner.set_plan(user, day, todo_ids).await
+    }
+
+    async fn plan_todos(&self, user: Uuid, day: NaiveDate) -> Result<Vec<Todo>, TodoError> {
+        self.inner.plan_todos(user, day).await
+    }
+
+    async fn relations_of(&self, todo_id: TodoId) -> Result<Vec<RelationSummary>, TodoError> {
+        self.inner.relations_of(todo_id).await
+    }
+
+    async fn create_relation(
+        &self,
+        relation: &TodoRelation,
+    ) -> Result<Option<TodoRelation>, TodoError> {
+        self.inner.create_relation(relation).await
+    }
+
+    async fn delete_relation(&self, todo_id: TodoId, relation_id: Uuid) -> Result<bool, TodoError> {
+        self.inner.delete_relation(todo_id, relation_id).await
+    }
+
+    async fn list_lists(&self) -> Result<Vec<TodoList>, TodoError> {
+        self.inner.list_lists().await
+    }
+
+    async fn get_list(&self, id: Uuid) -> Result<Option<TodoList>, TodoError> {
+        self.inner.get_list(id).await
+    }
+
+    async fn create_list(&self, list: &TodoList) -> Result<Option<TodoList>, TodoError> {
+        self.inner.create_list(list).await
+    }
+
+    async fn update_list(
+        &self,
+        id: Uuid,
+        update: &UpdateTodoListRequest,
+    ) -> Result<ListUpdate, TodoError> {
+        self.inner.update_list(id, update).await
+    }
+
+    async fn delete_list(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        let moved_out = self.inner.delete_list(id).await?;
+        for todo in moved_out.iter().flatten() {
+            self.publish(TodoEvent::Updated(todo.clone())).await;
+        }
+        Ok(moved_out)
+    }
+
+    async fn create_import_job(
+        &self,
+        job: &ImportJob,
+        items: &[ImportItem],
+    ) -> Result<ImportJob, TodoError> {
+        self.inner.create_import_job(job, items).await
+    }
+
+    async fn get_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        self.inner.get_import_job(id).await
+    }
+
+    async fn import_job_items(&self, id: Uuid) -> Result<Vec<ImportItem>, TodoError> {
+        self.inner.import_job_items(id).await
+    }
+
+    async fn update_import_job(&self, job: &ImportJob) -> Result<Option<ImportJob>, TodoError> {
+        self.inner.update_import_job(job).await
+    }
+
+    async fn cancel_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        self.inner.cancel_import_job(id).await
+    }
+
+    async fn create_user(&self, user: &User) -> Result<Option<User>, TodoError> {
+        self.inner.create_user(user).await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, TodoError> {
+        self.inner.find_user_by_email(email).await
+    }
+
+    async fn ping(&self) -> Result<(), TodoError> {
+        self.inner.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_kind_and_id() {
+        let todo = Todo::new_with_validation("Title", "Content").unwrap();
+        assert_eq!(TodoEvent::Created(todo.clone()).kind(), "created");
+        assert_eq!(TodoEvent::Updated(todo.clone()).todo_id(), todo.id);
+        assert_eq!(TodoEvent::Deleted { id: todo.id }.kind(), "deleted");
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let id = TodoId::new();
+        let value = serde_json::to_value(TodoEvent::Deleted { id }).unwrap();
+        assert_eq!(value, json!({ "type": "deleted", "data": { "id": id } }));
+    }
+}
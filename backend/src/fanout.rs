@@ -0,0 +1,185 @@
+//! Todo events shared between replicas over Redis pub/sub, with `REDIS_URL`.
+//!
+//! The same job as `notify`, for deployments scaled past what one Postgres should carry
+//! in `NOTIFY` traffic: `RedisFanout` is subscribed to the event bus in place of
+//! `PgNotifier` and publishes each event to `CHANNEL`, tagged with the sending process,
+//! and `spawn_subscriber` hands the events of other processes to the local subscribers
+//! that fan out to clients. Redis has no payload limit worth the name, so every message
+//! carries its whole event and nothing is read back. Messages published while the
+//! subscriber is reconnecting are lost, as with `notify`; it logs how long it was away.
+//!
+//! Needs the `redis` feature; without it the server warns and stays on Postgres.
+
+use crate::events::{PublishError, TodoEvent};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const CHANNEL: &str = "md_todo:events";
+
+/// Payload of a message
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Envelope {
+    /// The process that made the change
+    origin: Uuid,
+    event: TodoEvent,
+}
+
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+fn encode(origin: Uuid, event: &TodoEvent) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&Envelope {
+        origin,
+        event: event.clone(),
+    })
+}
+
+/// The event a message carries; `None` when it came from this process
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+fn decode(payload: &str, origin: Uuid) -> Result<Option<TodoEvent>, PublishError> {
+    let envelope: Envelope = serde_json::from_str(payload)?;
+    Ok((envelope.origin != origin).then_some(envelope.event))
+}
+
+#[cfg(feature = "redis")]
+pub use client::{spawn_subscriber, RedisFanout};
+
+#[cfg(feature = "redis")]
+mod client {
+    use super::{decode, encode, CHANNEL};
+    use crate::events::{EventPublisher, PublishError, TodoEvent};
+    use crate::health::DependencyCheck;
+    use async_trait::async_trait;
+    use futures::StreamExt;
+    use redis::aio::MultiplexedConnection;
+    use redis::AsyncCommands;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    /// Sends every event of this process to `CHANNEL`
+    pub struct RedisFanout {
+        client: redis::Client,
+        connection: MultiplexedConnection,
+        origin: Uuid,
+    }
+
+    impl RedisFanout {
+        /// `origin` identifies this process; the subscriber gets the same one
+        pub async fn connect(url: &str, origin: Uuid) -> Result<Self, PublishError> {
+            let client = redis::Client::open(url)?;
+            let connection = client.get_multiplexed_async_connection().await?;
+            Ok(Self {
+                client,
+                connection,
+                origin,
+            })
+        }
+
+        /// The client the subscriber opens its own connection with
+        pub fn client(&self) -> redis::Client {
+            self.client.clone()
+        }
+    }
+
+    #[async_trait]
+    impl EventPublisher for RedisFanout {
+        async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+            let payload = encode(self.origin, event)?;
+            let mut connection = self.connection.clone();
+            connection.publish::<_, _, ()>(CHANNEL, payload).await?;
+            Ok(())
+        }
+
+        /// A `PING` round trip
+        async fn check(&self) -> Result<(), PublishError> {
+            let mut connection = self.connection.clone();
+            redis::cmd("PING")
+                .query_async::<()>(&mut connection)
+                .await?;
+            Ok(())
+        }
+    }
+
+    /// Shown as `redis` in `/health/ready`; replicas keep serving their own writes
+    /// without it
+    #[async_trait]
+    impl DependencyCheck for RedisFanout {
+        fn name(&self) -> &str {
+            "redis"
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            EventPublisher::check(self).await.map_err(|e| e.to_string())
+        }
+    }
+
+    /// Subscribes to `CHANNEL` for as long as the process runs and publishes the events
+    /// of other processes to `subscribers`, reconnecting when the connection drops
+    pub fn spawn_subscriber(
+        client: redis::Client,
+        origin: Uuid,
+        subscribers: Arc<dyn EventPublisher>,
+    ) {
+        tokio::spawn(async move {
+            loop {
+                let disconnected = Instant::now();
+                if let Err(e) = subscribe(&client, origin, subscribers.as_ref()).await {
+                    tracing::error!("Subscribing to events of other replicas failed: {}", e);
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                tracing::warn!(
+                    "Resubscribing to {} after {:?}; events of other replicas in between are lost",
+                    CHANNEL,
+                    disconnected.elapsed()
+                );
+            }
+        });
+    }
+
+    async fn subscribe(
+        client: &redis::Client,
+        origin: Uuid,
+        subscribers: &dyn EventPublisher,
+    ) -> Result<(), PublishError> {
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.subscribe(CHANNEL).await?;
+        tracing::info!("Subscribed to {} for events of other replicas", CHANNEL);
+        // Ends once the connection is lost
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let received = message
+                .get_payload::<String>()
+                .map_err(PublishError::from)
+                .and_then(|payload| decode(&payload, origin));
+            match received {
+                Ok(Some(event)) => {
+                    if let Err(e) = subscribers.publish(&event).await {
+                        tracing::error!("Failed to publish an event of another replica: {}", e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Ignoring message on {}: {}", CHANNEL, e),
+            }
+        }
+        Err("connection lost".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TodoId;
+
+    #[test]
+    fn test_decode_skips_own_events() {
+        let (here, there) = (Uuid::new_v4(), Uuid::new_v4());
+        let event = TodoEvent::Deleted { id: TodoId::new() };
+
+        let payload = encode(there, &event).unwrap();
+        assert_eq!(decode(&payload, here).unwrap(), Some(event));
+        assert_eq!(decode(&payload, there).unwrap(), None);
+        assert!(decode("{}", here).is_err());
+    }
+}
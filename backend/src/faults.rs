@@ -0,0 +1,828 @@
+//! Fault injection for the repository, for integration tests and staging.
+//!
+//! `FaultInjectingTodoRepository` wraps a repository and, following the `FaultPlan` in its
+//! shared `Faults`, delays calls, fails a share of them at random and fails one given call.
+//! The plan can be swapped while the server runs, so a test can break the database halfway
+//! through a scenario and mend it again. An injected failure never reaches the wrapped
+//! repository.
+//!
+//! The server reads a plan from the environment and injects nothing unless one is set:
+//!
+//! - `FAULT_ERROR_RATE`: share of calls that fail, from 0 to 1
+//! - `FAULT_LATENCY_MS`: delay added to every call
+//! - `FAULT_FAIL_ON_CALL`: number of the one call that fails, counting from 1
+//! - `FAULT_METHODS`: comma-separated trait methods, such as `update_todo`, to limit the
+//!   faults and the call count to
+//! - `FAULT_KIND`: `database` (the default), `timeout` or `conflict`
+
+use crate::auth::User;
+use crate::autocomplete::{AutocompleteQuery, TitleSuggestion};
+use crate::domain::{TodoContent, TodoId};
+use crate::encryption::{EncryptedTodo, EncryptedUpdate};
+use crate::imports::{ImportItem, ImportJob};
+use crate::lists::{ListUpdate, TodoList, UpdateTodoListRequest};
+use crate::metadata::MetadataField;
+use crate::moderation::{Finding, ModerationFlag};
+use crate::policy::ValidationPolicy;
+use crate::relations::{RelationSummary, TodoRelation};
+use crate::search::{SearchHit, SearchQuery};
+use crate::share::{ShareAccess, ShareLink, ShareOutcome};
+use crate::split::TodoSplit;
+use crate::stats::{AgingReport, WorkloadTotals};
+use crate::streaks::CompletionDay;
+use crate::subtasks::SubtaskMerge;
+use crate::tags::{Tag, TagRename};
+use crate::webhooks::{Webhook, WebhookDelivery};
+use crate::{
+    ContentUpdate, SortOrder, Todo, TodoError, TodoFilter, TodoRepositoryTrait, TodoSort,
+    TodoUpsert, UpdateTodoRequest,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Error an injected failure returns
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FaultKind {
+    /// The database went away mid-call
+    #[default]
+    Database,
+    /// No connection could be had in time
+    Timeout,
+    /// A concurrent write got there first
+    Conflict,
+}
+
+impl FaultKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "database" => Some(Self::Database),
+            "timeout" => Some(Self::Timeout),
+            "conflict" => Some(Self::Conflict),
+            _ => None,
+        }
+    }
+
+    fn error(self, method: &str) -> TodoError {
+        match self {
+            Self::Database => TodoError::from(sqlx::Error::PoolClosed),
+            Self::Timeout => TodoError::from(sqlx::Error::PoolTimedOut),
+            Self::Conflict => TodoError::Conflict(format!("Injected conflict in {method}")),
+        }
+    }
+}
+
+/// What to inject; the default injects nothing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultPlan {
+    /// Share of calls that fail, from 0 to 1
+    pub error_rate: f64,
+    /// Added to every call, failing or not
+    pub latency: Duration,
+    /// The one call that fails, counting from 1
+    pub fail_on_call: Option<u64>,
+    /// Trait methods the faults and the call count are limited to; empty for all
+    pub methods: Vec<String>,
+    pub kind: FaultKind,
+}
+
+impl FaultPlan {
+    /// Every call fails
+    pub fn always() -> Self {
+        Self {
+            error_rate: 1.0,
+            ..Self::default()
+        }
+    }
+
+    /// Only call `n` fails
+    pub fn fail_on_call(n: u64) -> Self {
+        Self {
+            fail_on_call: Some(n),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Limits the plan to these trait methods
+    pub fn only(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|method| method.to_string()).collect();
+        self
+    }
+
+    pub fn with_kind(mut self, kind: FaultKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Reads the `FAULT_*` variables; the default plan when none is set
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        let mut plan = Self::default();
+        if let Some(value) = var("FAULT_ERROR_RATE") {
+            match value.trim().parse::<f64>() {
+                Ok(rate) if (0.0..=1.0).contains(&rate) => plan.error_rate = rate,
+                _ => tracing::warn!("FAULT_ERROR_RATE must be from 0 to 1, got {:?}", value),
+            }
+        }
+        if let Some(value) = var("FAULT_LATENCY_MS") {
+            match value.trim().parse() {
+                Ok(ms) => plan.latency = Duration::from_millis(ms),
+                Err(_) => {
+                    tracing::warn!("FAULT_LATENCY_MS must be a whole number, got {:?}", value)
+                }
+            }
+        }
+        if let Some(value) = var("FAULT_FAIL_ON_CALL") {
+            match value.trim().parse() {
+                Ok(n) if n > 0 => plan.fail_on_call = Some(n),
+                _ => tracing::warn!(
+                    "FAULT_FAIL_ON_CALL must be a positive whole number, got {:?}",
+                    value
+                ),
+            }
+        }
+        if let Some(value) = var("FAULT_METHODS") {
+            plan.methods = value
+                .split(',')
+                .map(str::trim)
+                .filter(|method| !method.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(value) = var("FAULT_KIND") {
+            match FaultKind::parse(value.trim()) {
+                Some(kind) => plan.kind = kind,
+                None => tracing::warn!(
+                    "FAULT_KIND must be database, timeout or conflict, got {:?}",
+                    value
+                ),
+            }
+        }
+        plan
+    }
+
+    fn covers(&self, method: &str) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|m| m == method)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    plan: FaultPlan,
+    /// Calls covered by the plan so far
+    calls: u64,
+}
+
+/// The current plan, shared between the repository and whoever changes it
+#[derive(Debug, Default)]
+pub struct Faults {
+    state: Mutex<State>,
+}
+
+impl Faults {
+    pub fn new(plan: FaultPlan) -> Self {
+        Self {
+            state: Mutex::new(State { plan, calls: 0 }),
+        }
+    }
+
+    /// Replaces the plan and restarts the call count
+    pub fn set(&self, plan: FaultPlan) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = State { plan, calls: 0 };
+    }
+
+    /// Stops injecting
+    pub fn clear(&self) {
+        self.set(FaultPlan::default());
+    }
+
+    /// Calls covered by the plan since it was set
+    pub fn calls(&self) -> u64 {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).calls
+    }
+
+    /// Delay and failure, if any, for the next call of `method`
+    fn next(&self, method: &str) -> (Duration, Option<FaultKind>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.plan.covers(method) {
+            return (Duration::ZERO, None);
+        }
+        state.calls += 1;
+        let plan = &state.plan;
+        let fails = plan.fail_on_call == Some(state.calls)
+            || (plan.error_rate > 0.0 && random_fraction() < plan.error_rate);
+        (plan.latency, fails.then_some(plan.kind))
+    }
+}
+
+/// Uniform in [0, 1)
+fn random_fraction() -> f64 {
+    (Uuid::new_v4().as_u64_pair().0 >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Repository decorator that delays and fails calls as its `Faults` say
+pub struct FaultInjectingTodoRepository<R> {
+    inner: R,
+    faults: Arc<Faults>,
+}
+
+impl<R: TodoRepositoryTrait> FaultInjectingTodoRepository<R> {
+    pub fn new(inner: R, faults: Arc<Faults>) -> Self {
+        Self { inner, faults }
+    }
+
+    pub fn faults(&self) -> &Arc<Faults> {
+        &self.faults
+    }
+
+    async fn inject<T>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = Result<T, TodoError>>,
+    ) -> Result<T, TodoError> {
+        let (latency, failure) = self.faults.next(method);
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        if let Some(kind) = failure {
+            tracing::debug!("Injecting a {:?} failure into {}", kind, method);
+            return Err(kind.error(method));
+        }
+        call.await
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepositoryTrait> TodoRepositoryTrait for FaultInjectingTodoRepository<R> {
+    async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError> {
+        self.inject("create_todo", self.inner.create_todo(todo))
+            .await
+    }
+
+    async fn create_todos_bulk(&self, todos: &[Todo]) -> Result<Vec<Todo>, TodoError> {
+        self.inject("create_todos_bulk", self.inner.create_todos_bulk(todos))
+            .await
+    }
+
+    async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        self.inject("get_all_todos", self.inner.get_all_todos())
+            .await
+    }
+
+    async fn find_todos(&self, filter: &TodoFilter) -> Result<Vec<Todo>, TodoError> {
+        self.inject("find_todos", self.inner.find_todos(filter))
+            .await
+    }
+
+    async fn search_todos(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, TodoError> {
+        self.inject("search_todos", self.inner.search_todos(query))
+            .await
+    }
+
+    async fn autocomplete_titles(
+        &self,
+        query: &AutocompleteQuery,
+    ) -> Result<Vec<TitleSuggestion>, TodoError> {
+        self.inject("autocomplete_titles", self.inner.autocomplete_titles(query))
+            .await
+    }
+
+    async fn get_todos_after(
+        &self,
+        cursor: Option<TodoId>,
+        limit: i64,
+        sort: TodoSort,
+        order: SortOrder,
+        include_archived: bool,
+    ) -> Result<Vec<Todo>, TodoError> {
+        self.inject(
+            "get_todos_after",
+            self.inner
+                .get_todos_after(cursor, limit, sort, order, include_archived),
+        )
+        .await
+    }
+
+    async fn get_todo_by_id(&self, id: TodoId) -> Result<Option<Todo>, TodoError> {
+        self.inject("get_todo_by_id", self.inner.get_todo_by_id(id))
+            .await
+    }
+
+    async fn update_todo(
+        &self,
+        id: TodoId,
+        updates: &UpdateTodoRequest,
+        expected_version: Option<i32>,
+    ) -> Result<Option<Todo>, TodoError> {
+        self.inject(
+            "update_todo",
+            self.inner.update_todo(id, updates, expected_version),
+        )
+        .await
+    }
+
+    async fn upsert_todo(
+        &self,
+        todo: &Todo,
+        expected_version: Option<i32>,
+    ) -> Result<TodoUpsert, TodoError> {
+        self.inject(
+            "upsert_todo",
+            self.inner.upsert_todo(todo, expected_version),
+        )
+        .await
+    }
+
+    async fn update_todos_bulk(
+        &self,
+        ids: &[TodoId],
+        updates: &UpdateTodoRequest,
+    ) -> Result<Vec<Todo>, TodoError> {
+        self.inject(
+            "update_todos_bulk",
+            self.inner.update_todos_bulk(ids, updates),
+        )
+        .await
+    }
+
+    async fn update_todo_content(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        base_version: i32,
+    ) -> Result<ContentUpdate, TodoError> {
+        self.inject(
+            "update_todo_content",
+            self.inner.update_todo_content(id, content, base_version),
+        )
+        .await
+    }
+
+    async fn split_todo(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        parts: &[Todo],
+    ) -> Result<Option<TodoSplit>, TodoError> {
+        self.inject(
+            "split_todo",
+            self.inner.split_todo(id, content, expected_version, parts),
+        )
+        .await
+    }
+
+    async fn merge_subtasks(
+        &self,
+        parent: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        subtasks: &[TodoId],
+    ) -> Result<Option<SubtaskMerge>, TodoError> {
+        self.inject(
+            "merge_subtasks",
+            self.inner
+                .merge_subtasks(parent, content, expected_version, subtasks),
+        )
+        .await
+    }
+
+    async fn delete_todo(
+        &self,
+        id: TodoId,
+        expected_version: Option<i32>,
+    ) -> Result<bool, TodoError> {
+        self.inject("delete_todo", self.inner.delete_todo(id, expected_version))
+            .await
+    }
+
+    async fn delete_todos_bulk(&self, ids: &[TodoId]) -> Result<Vec<TodoId>, TodoError> {
+        self.inject("delete_todos_bulk", self.inner.delete_todos_bulk(ids))
+            .await
+    }
+
+    async fn complete_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        self.inject("complete_all_todos", self.inner.complete_all_todos())
+            .await
+    }
+
+    async fn delete_completed_todos(&self) -> Result<Vec<TodoId>, TodoError> {
+        self.inject(
+            "delete_completed_todos",
+            self.inner.delete_completed_todos(),
+        )
+        .await
+    }
+
+    async fn get_short_id(&self, todo_id: TodoId) -> Result<Option<String>, TodoError> {
+        self.inject("get_short_id", self.inner.get_short_id(todo_id))
+            .await
+    }
+
+    async fn resolve_short_id(&self, short_id: &str) -> Result<Option<TodoId>, TodoError> {
+        self.inject("resolve_short_id", self.inner.resolve_short_id(short_id))
+            .await
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<ShareLink, TodoError> {
+        self.inject("create_share_link", self.inner.create_share_link(link))
+            .await
+    }
+
+    async fn list_share_links(&self, todo_id: TodoId) -> Result<Vec<ShareLink>, TodoError> {
+        self.inject("list_share_links", self.inner.list_share_links(todo_id))
+            .await
+    }
+
+    async fn get_share_link(&self, token: &str) -> Result<Option<ShareLink>, TodoError> {
+        self.inject("get_share_link", self.inner.get_share_link(token))
+            .await
+    }
+
+    async fn delete_share_link(&self, token: &str) -> Result<bool, TodoError> {
+        self.inject("delete_share_link", self.inner.delete_share_link(token))
+            .await
+    }
+
+    async fn record_share_access(
+        &self,
+        token: &str,
+        access: &ShareAccess,
+    ) -> Result<ShareOutcome, TodoError> {
+        self.inject(
+            "record_share_access",
+            self.inner.record_share_access(token, access),
+        )
+        .await
+    }
+
+    async fn share_access_log(
+        &self,
+        token: &str,
+        limit: i64,
+    ) -> Result<Vec<ShareAccess>, TodoError> {
+        self.inject(
+            "share_access_log",
+            self.inner.share_access_log(token, limit),
+        )
+        .await
+    }
+
+    async fn list_metadata_fields(&self) -> Result<Vec<MetadataField>, TodoError> {
+        self.inject("list_metadata_fields", self.inner.list_metadata_fields())
+            .await
+    }
+
+    async fn upsert_metadata_field(
+        &self,
+        field: &MetadataField,
+    ) -> Result<MetadataField, TodoError> {
+        self.inject(
+            "upsert_metadata_field",
+            self.inner.upsert_metadata_field(field),
+        )
+        .await
+    }
+
+    async fn delete_metadata_field(&self, key: &str) -> Result<bool, TodoError> {
+        self.inject(
+            "delete_metadata_field",
+            self.inner.delete_metadata_field(key),
+        )
+        .await
+    }
+
+    async fn validation_policy(&self) -> Result<ValidationPolicy, TodoError> {
+        self.inject("validation_policy", self.inner.validation_policy())
+            .await
+    }
+
+    async fn set_validation_policy(
+        &self,
+        policy: &ValidationPolicy,
+    ) -> Result<ValidationPolicy, TodoError> {
+        self.inject(
+            "set_validation_policy",
+            self.inner.set_validation_policy(policy),
+        )
+        .await
+    }
+
+    async fn flag_todo(&self, id: TodoId, findings: &[Finding]) -> Result<(), TodoError> {
+        self.inject("flag_todo", self.inner.flag_todo(id, findings))
+            .await
+    }
+
+    async fn moderation_flags(&self) -> Result<Vec<ModerationFlag>, TodoError> {
+        self.inject("moderation_flags", self.inner.moderation_flags())
+            .await
+    }
+
+    async fn dismiss_moderation_flag(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.inject(
+            "dismiss_moderation_flag",
+            self.inner.dismiss_moderation_flag(id),
+        )
+        .await
+    }
+
+    async fn create_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+    ) -> Result<EncryptedTodo, TodoError> {
+        self.inject(
+            "create_encrypted_todo",
+            self.inner.create_encrypted_todo(todo),
+        )
+        .await
+    }
+
+    async fn list_encrypted_todos(
+        &self,
+        key_fingerprint: Option<&str>,
+    ) -> Result<Vec<EncryptedTodo>, TodoError> {
+        self.inject(
+            "list_encrypted_todos",
+            self.inner.list_encrypted_todos(key_fingerprint),
+        )
+        .await
+    }
+
+    async fn get_encrypted_todo(&self, id: Uuid) -> Result<Option<EncryptedTodo>, TodoError> {
+        self.inject("get_encrypted_todo", self.inner.get_encrypted_todo(id))
+            .await
+    }
+
+    async fn update_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+        base_version: i32,
+    ) -> Result<EncryptedUpdate, TodoError> {
+        self.inject(
+            "update_encrypted_todo",
+            self.inner.update_encrypted_todo(todo, base_version),
+        )
+        .await
+    }
+
+    async fn delete_encrypted_todo(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.inject(
+            "delete_encrypted_todo",
+            self.inner.delete_encrypted_todo(id),
+        )
+        .await
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, TodoError> {
+        self.inject("create_webhook", self.inner.create_webhook(webhook))
+            .await
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, TodoError> {
+        self.inject("list_webhooks", self.inner.list_webhooks())
+            .await
+    }
+
+    async fn get_webhook(&self, id: Uuid) -> Result<Option<Webhook>, TodoError> {
+        self.inject("get_webhook", self.inner.get_webhook(id)).await
+    }
+
+    async fn update_webhook(&self, webhook: &Webhook) -> Result<Option<Webhook>, TodoError> {
+        self.inject("update_webhook", self.inner.update_webhook(webhook))
+            .await
+    }
+
+    async fn delete_webhook(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.inject("delete_webhook", self.inner.delete_webhook(id))
+            .await
+    }
+
+    async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), TodoError> {
+        self.inject(
+            "record_webhook_delivery",
+            self.inner.record_webhook_delivery(delivery),
+        )
+        .await
+    }
+
+    async fn webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, TodoError> {
+        self.inject(
+            "webhook_deliveries",
+            self.inner.webhook_deliveries(webhook_id, limit),
+        )
+        .await
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, TodoError> {
+        self.inject("list_tags", self.inner.list_tags()).await
+    }
+
+    async fn get_tag(&self, id: Uuid) -> Result<Option<Tag>, TodoError> {
+        self.inject("get_tag", self.inner.get_tag(id)).await
+    }
+
+    async fn create_tag(&self, tag: &Tag) -> Result<Option<Tag>, TodoError> {
+        self.inject("create_tag", self.inner.create_tag(tag)).await
+    }
+
+    async fn suggest_tags(&self, text: &str, limit: i64) -> Result<Vec<Tag>, TodoError> {
+        self.inject("suggest_tags", self.inner.suggest_tags(text, limit))
+            .await
+    }
+
+    async fn rename_tag(&self, id: Uuid, name: &str, merge: bool) -> Result<TagRename, TodoError> {
+        self.inject("rename_tag", self.inner.rename_tag(id, name, merge))
+            .await
+    }
+
+    async fn delete_tag(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        self.inject("delete_tag", self.inner.delete_tag(id)).await
+    }
+
+    async fn aging_report(&self) -> Result<AgingReport, TodoError> {
+        self.inject("aging_report", self.inner.aging_report()).await
+    }
+
+    async fn workload_totals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<WorkloadTotals, TodoError> {
+        self.inject("workload_totals", self.inner.workload_totals(from, to))
+            .await
+    }
+
+    async fn completion_days(&self, user: Uuid) -> Result<Vec<CompletionDay>, TodoError> {
+        self.inject("completion_days", self.inner.completion_days(user))
+            .await
+    }
+
+    async fn set_plan(
+        &self,
+        user: Uuid,
+        day: NaiveDate,
+        todo_ids: &[TodoId],
+    ) -> Result<(), TodoError> {
+        self.inject("set_plan", self.inner.set_plan(user, day, todo_ids))
+            .await
+    }
+
+    async fn plan_todos(&self, user: Uuid, day: NaiveDate) -> Result<Vec<Todo>, TodoError> {
+        self.inject("plan_todos", self.inner.plan_todos(user, day))
+            .await
+    }
+
+    async fn relations_of(&self, todo_id: TodoId) -> Result<Vec<RelationSummary>, TodoError> {
+        self.inject("relations_of", self.inner.relations_of(todo_id))
+            .await
+    }
+
+    async fn create_relation(
+        &self,
+        relation: &TodoRelation,
+    ) -> Result<Option<TodoRelation>, TodoError> {
+        self.inject("create_relation", self.inner.create_relation(relation))
+            .await
+    }
+
+    async fn delete_relation(&self, todo_id: TodoId, relation_id: Uuid) -> Result<bool, TodoError> {
+        self.inject(
+            "delete_relation",
+            self.inner.delete_relation(todo_id, relation_id),
+        )
+        .await
+    }
+
+    async fn list_lists(&self) -> Result<Vec<TodoList>, TodoError> {
+        self.inject("list_lists", self.inner.list_lists()).await
+    }
+
+    async fn get_list(&self, id: Uuid) -> Result<Option<TodoList>, TodoError> {
+        self.inject("get_list", self.inner.get_list(id)).await
+    }
+
+    async fn create_list(&self, list: &TodoList) -> Result<Option<TodoList>, TodoError> {
+        self.inject("create_list", self.inner.create_list(list))
+            .await
+    }
+
+    async fn update_list(
+        &self,
+        id: Uuid,
+        update: &UpdateTodoListRequest,
+    ) -> Result<ListUpdate, TodoError> {
+        self.inject("update_list", self.inner.update_list(id, update))
+            .await
+    }
+
+    async fn delete_list(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        self.inject("delete_list", self.inner.delete_list(id)).await
+    }
+
+    async fn create_import_job(
+        &self,
+        job: &ImportJob,
+        items: &[ImportItem],
+    ) -> Result<ImportJob, TodoError> {
+        self.inject(
+            "create_import_job",
+            self.inner.create_import_job(job, items),
+        )
+        .await
+    }
+
+    async fn get_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        self.inject("get_import_job", self.inner.get_import_job(id))
+            .await
+    }
+
+    async fn import_job_items(&self, id: Uuid) -> Result<Vec<ImportItem>, TodoError> {
+        self.inject("import_job_items", self.inner.import_job_items(id))
+            .await
+    }
+
+    async fn update_import_job(&self, job: &ImportJob) -> Result<Option<ImportJob>, TodoError> {
+        self.inject("update_import_job", self.inner.update_import_job(job))
+            .await
+    }
+
+    async fn cancel_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        self.inject("cancel_import_job", self.inner.cancel_import_job(id))
+            .await
+    }
+
+    async fn create_user(&self, user: &User) -> Result<Option<User>, TodoError> {
+        self.inject("create_user", self.inner.create_user(user))
+            .await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, TodoError> {
+        self.inject("find_user_by_email", self.inner.find_user_by_email(email))
+            .await
+    }
+
+    async fn ping(&self) -> Result<(), TodoError> {
+        self.inject("ping", self.inner.ping()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryTodoRepository;
+
+    fn repository(plan: FaultPlan) -> FaultInjectingTodoRepository<MemoryTodoRepository> {
+        FaultInjectingTodoRepository::new(MemoryTodoRepository::new(), Arc::new(Faults::new(plan)))
+    }
+
+    #[tokio::test]
+    async fn test_fail_on_call_counts_only_covered_methods() {
+        let repository = repository(FaultPlan::fail_on_call(2).only(&["get_all_todos"]));
+
+        assert!(repository.ping().await.is_ok());
+        assert!(repository.get_all_todos().await.is_ok());
+        assert!(matches!(
+            repository.get_all_todos().await,
+            Err(TodoError::Database(sqlx::Error::PoolClosed))
+        ));
+        assert!(repository.get_all_todos().await.is_ok());
+        assert_eq!(repository.faults().calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_plan_can_change_while_running() {
+        let repository = repository(FaultPlan::always().with_kind(FaultKind::Conflict));
+        assert!(matches!(
+            repository.ping().await,
+            Err(TodoError::Conflict(message)) if message == "Injected conflict in ping"
+        ));
+
+        repository.faults().clear();
+        assert!(repository.ping().await.is_ok());
+        assert_eq!(repository.faults().calls(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_latency_delays_every_call() {
+        let repository = repository(FaultPlan::default().with_latency(Duration::from_secs(3)));
+        let started = tokio::time::Instant::now();
+        repository.ping().await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,273 @@
+//! Change feed over Server-Sent Events, for clients that cannot hold a WebSocket open
+//! through their proxy.
+//!
+//! `ChangeFeed` is an `EventPublisher`: subscribed to the `EventBus` next to the broker, it
+//! receives every `TodoEvent` from `PublishingTodoRepository`, numbers it and keeps the
+//! latest `BUFFER_SIZE` in a ring buffer. `GET /api/todos/events` streams them as
+//! `created`, `updated`, `completed` and `deleted` events whose `id` is that number and whose data is the event as the broker gets it. A
+//! client that reconnects with `Last-Event-ID` first gets what it missed from the buffer;
+//! when that is no longer there (it was offline too long, or the server restarted) it gets
+//! a `reset` event and should reload its todos. A comment line every `HEARTBEAT` keeps
+//! proxies from closing an idle stream.
+//!
+//! A client only gets events about the todos it may see (`auth::may_see`); `deleted`
+//! events carry no owner and go to everyone.
+//!
+//! The feed only sees writes handled by this process.
+
+use crate::auth;
+use crate::events::{EventPublisher, PublishError, TodoEvent};
+use axum::{
+    http::{HeaderMap, HeaderValue},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension,
+};
+use futures::{stream, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Events kept for clients that reconnect
+pub const BUFFER_SIZE: usize = 1024;
+pub const HEARTBEAT: Duration = Duration::from_secs(15);
+/// Events a slow client may fall behind before it gets a `reset`
+const CHANNEL_CAPACITY: usize = 256;
+
+/// An event with its place in the feed
+type Numbered = (u64, TodoEvent);
+
+struct Ring {
+    /// Number of the next event; numbers start at 1
+    next_id: u64,
+    events: VecDeque<Numbered>,
+}
+
+/// Where a new subscriber starts
+enum Start {
+    /// The events after its `Last-Event-ID`, all still buffered
+    Replay(Vec<Numbered>),
+    /// Events were dropped since its `Last-Event-ID`
+    Reset,
+}
+
+pub struct ChangeFeed {
+    ring: Mutex<Ring>,
+    sender: broadcast::Sender<Numbered>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self {
+            ring: Mutex::new(Ring {
+                next_id: 1,
+                events: VecDeque::with_capacity(BUFFER_SIZE),
+            }),
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl std::fmt::Debug for ChangeFeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChangeFeed")
+            .field("subscribers", &self.sender.receiver_count())
+            .finish()
+    }
+}
+
+impl ChangeFeed {
+    fn push(&self, event: TodoEvent) {
+        let mut ring = self.ring.lock().unwrap();
+        let id = ring.next_id;
+        ring.next_id += 1;
+        if ring.events.len() == BUFFER_SIZE {
+            ring.events.pop_front();
+        }
+        ring.events.push_back((id, event.clone()));
+        // Sent under the lock so subscribers see events in number order; no subscribers
+        // is not an error
+        let _ = self.sender.send((id, event));
+    }
+
+    /// Subscribes, atomically with reading the buffer so no event is missed or repeated
+    fn subscribe(&self, last_event_id: Option<u64>) -> (Start, broadcast::Receiver<Numbered>) {
+        let ring = self.ring.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let Some(last) = last_event_id else {
+            return (Start::Replay(Vec::new()), receiver);
+        };
+        let oldest = ring.events.front().map_or(ring.next_id, |(id, _)| *id);
+        if last >= ring.next_id || last + 1 < oldest {
+            return (Start::Reset, receiver);
+        }
+        let missed = ring
+            .events
+            .iter()
+            .filter(|(id, _)| *id > last)
+            .cloned()
+            .collect();
+        (Start::Replay(missed), receiver)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventPublisher for ChangeFeed {
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        self.push(event.clone());
+        Ok(())
+    }
+}
+
+fn sse_event((id, event): &Numbered) -> Event {
+    Event::default()
+        .id(id.to_string())
+        .event(event.kind())
+        .json_data(event)
+        .unwrap_or_else(|e| Event::default().comment(format!("unserializable event: {e}")))
+}
+
+/// Whether `user` may be told about `event`
+fn shown_to(user: Uuid, event: &TodoEvent) -> bool {
+    match event {
+        TodoEvent::Created(todo) | TodoEvent::Updated(todo) | TodoEvent::Completed(todo) => {
+            auth::may_see(user, todo.owner_id)
+        }
+        TodoEvent::Deleted { .. } => true,
+    }
+}
+
+fn reset_event() -> Event {
+    Event::default().event("reset").data("{}")
+}
+
+/// Replayed events, then live ones, those `user` may see; a client that falls too far
+/// behind gets a `reset`
+fn event_stream(
+    user: Uuid,
+    start: Start,
+    receiver: broadcast::Receiver<Numbered>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let first: Vec<Event> = match start {
+        Start::Replay(missed) => missed
+            .iter()
+            .filter(|(_, event)| shown_to(user, event))
+            .map(sse_event)
+            .collect(),
+        Start::Reset => vec![reset_event()],
+    };
+    let live = stream::unfold(receiver, move |mut receiver| async move {
+        let event = loop {
+            match receiver.recv().await {
+                Ok(numbered) if shown_to(user, &numbered.1) => break sse_event(&numbered),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Change feed client fell {} events behind", skipped);
+                    break reset_event();
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        };
+        Some((event, receiver))
+    });
+    stream::iter(first).chain(live).map(Ok)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/events",
+    params(
+        ("Last-Event-ID" = Option<u64>, Header, description = "Id of the last event received, to resume after a disconnect"),
+        ("ticket" = Option<String>, Query, description = "Ticket from `POST /api/auth/ticket`, for browsers that cannot send a bearer token")
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events: `created`, `updated`, `completed` and `deleted` with the event as JSON data, `reset` when events were missed; a comment line every 15 seconds", content_type = "text/event-stream", body = String),
+        (status = 401, description = "Missing, invalid or expired access token or ticket", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn stream_events(
+    Extension(feed): Extension<Arc<ChangeFeed>>,
+    headers: HeaderMap,
+) -> Response {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let (start, receiver) = feed.subscribe(last_event_id);
+    if matches!(start, Start::Reset) {
+        tracing::info!(
+            "Change feed client resumed from {:?}, which is no longer buffered",
+            last_event_id
+        );
+    }
+    let mut response = Sse::new(event_stream(auth::acting_user(), start, receiver))
+        .keep_alive(KeepAlive::new().interval(HEARTBEAT))
+        .into_response();
+    // Tells nginx not to buffer the stream
+    response
+        .headers_mut()
+        .insert("x-accel-buffering", HeaderValue::from_static("no"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TodoId;
+    use crate::Todo;
+
+    fn deleted() -> TodoEvent {
+        TodoEvent::Deleted { id: TodoId::new() }
+    }
+
+    fn replayed(start: Start) -> Option<Vec<u64>> {
+        match start {
+            Start::Replay(events) => Some(events.iter().map(|(id, _)| *id).collect()),
+            Start::Reset => None,
+        }
+    }
+
+    #[test]
+    fn test_subscribe_replays_what_is_buffered() {
+        let feed = ChangeFeed::default();
+        assert_eq!(replayed(feed.subscribe(Some(0)).0), Some(vec![]));
+        for _ in 0..BUFFER_SIZE + 2 {
+            feed.push(deleted());
+        }
+        let last = BUFFER_SIZE as u64 + 2;
+        assert_eq!(replayed(feed.subscribe(None).0), Some(vec![]));
+        assert_eq!(replayed(feed.subscribe(Some(last)).0), Some(vec![]));
+        assert_eq!(
+            replayed(feed.subscribe(Some(last - 2)).0),
+            Some(vec![last - 1, last])
+        );
+        // Event 3 is the oldest still buffered, so resuming after 2 loses nothing
+        assert_eq!(
+            replayed(feed.subscribe(Some(2)).0).unwrap().len(),
+            BUFFER_SIZE
+        );
+        assert_eq!(replayed(feed.subscribe(Some(1)).0), None);
+        // From before a restart
+        assert_eq!(replayed(feed.subscribe(Some(last + 5)).0), None);
+    }
+
+    #[test]
+    fn test_events_go_to_those_who_may_see_the_todo() {
+        let (alice, bob) = (Uuid::now_v7(), Uuid::now_v7());
+        let mut todo = Todo::new_with_validation("Write report", "").unwrap();
+        todo.owner_id = alice;
+        let updated = TodoEvent::Updated(todo.clone());
+        assert!(shown_to(alice, &updated));
+        assert!(shown_to(auth::EVERYONE, &updated));
+        assert!(!shown_to(bob, &updated));
+        assert!(shown_to(bob, &deleted()));
+        todo.owner_id = auth::EVERYONE;
+        assert!(shown_to(bob, &TodoEvent::Completed(todo)));
+    }
+}
@@ -0,0 +1,230 @@
+//! Serving the built frontend from the backend, so a small deployment is one binary and
+//! one directory.
+//!
+//! With `FRONTEND_DIR` set, every path the API does not claim is looked up in that
+//! directory, and paths that match no file get `index.html` so client-side routes load.
+//! Unknown `/api/...` paths still answer 404. Files under `assets/` carry a content hash in
+//! their names and are cached as immutable for a year; everything else, `index.html`
+//! included, must be revalidated so a deploy shows up on the next load.
+//!
+//! Builds with the `embedded-frontend` feature carry `../frontend/build/client` inside the
+//! binary and serve it the same way when `FRONTEND_DIR` is unset, so the binary alone is a
+//! complete deployment. The frontend has to be built before the backend.
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    middleware::map_response,
+    response::Response,
+    routing::any,
+    Router,
+};
+use std::path::PathBuf;
+use tower::ServiceBuilder;
+use tower_http::services::{ServeDir, ServeFile};
+
+/// URL prefix (and subdirectory) of the hashed build output
+pub const ASSETS_PATH: &str = "/assets";
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+pub const REVALIDATE: &str = "no-cache";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontendConfig {
+    /// Directory holding `index.html` and `assets/`
+    pub dir: PathBuf,
+}
+
+impl FrontendConfig {
+    /// Reads `FRONTEND_DIR`; the frontend is not served without it
+    pub fn from_env() -> Option<Self> {
+        std::env::var("FRONTEND_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Self { dir: dir.into() })
+    }
+
+    pub fn index(&self) -> PathBuf {
+        self.dir.join("index.html")
+    }
+
+    /// Adds the frontend behind the routes of `app`
+    pub fn serve(&self, app: Router) -> Router {
+        if !self.index().is_file() {
+            tracing::warn!(
+                "FRONTEND_DIR {} has no index.html; client-side routes will not load",
+                self.dir.display()
+            );
+        }
+        let assets = Router::new()
+            .nest_service(
+                ASSETS_PATH,
+                ServeDir::new(self.dir.join(ASSETS_PATH.trim_start_matches('/'))),
+            )
+            .layer(map_response(cache_immutable));
+        let pages = ServiceBuilder::new()
+            .layer(map_response(cache_revalidate))
+            .service(ServeDir::new(&self.dir).fallback(ServeFile::new(self.index())));
+        app.merge(assets)
+            .route("/api/*path", any(|| async { StatusCode::NOT_FOUND }))
+            .fallback_service(pages)
+    }
+}
+
+/// Adds the frontend compiled into the binary behind the routes of `app`
+#[cfg(feature = "embedded-frontend")]
+pub fn serve_embedded(app: Router) -> Router {
+    if embedded::Build::get("index.html").is_none() {
+        tracing::warn!("The embedded frontend has no index.html; was the frontend built?");
+    }
+    app.route("/api/*path", any(|| async { StatusCode::NOT_FOUND }))
+        .fallback(embedded::serve)
+}
+
+#[cfg(feature = "embedded-frontend")]
+mod embedded {
+    use super::{ASSETS_PATH, IMMUTABLE, REVALIDATE};
+    use axum::{
+        body::Body,
+        http::{header, HeaderMap, StatusCode, Uri},
+        response::{IntoResponse, Response},
+    };
+
+    #[derive(rust_embed::RustEmbed)]
+    #[folder = "../frontend/build/client"]
+    pub struct Build;
+
+    pub async fn serve(uri: Uri, headers: HeaderMap) -> Response {
+        let path = uri.path().trim_start_matches('/');
+        let path = if path.is_empty() || path.ends_with('/') {
+            format!("{path}index.html")
+        } else {
+            path.to_string()
+        };
+        let is_asset = uri.path().starts_with(&format!("{ASSETS_PATH}/"));
+        let (file, cache_control) = match Build::get(&path) {
+            Some(file) => (file, if is_asset { IMMUTABLE } else { REVALIDATE }),
+            None if is_asset => return StatusCode::NOT_FOUND.into_response(),
+            None => match Build::get("index.html") {
+                Some(index) => (index, REVALIDATE),
+                None => return StatusCode::NOT_FOUND.into_response(),
+            },
+        };
+
+        let etag = format!("\"{}\"", hex::encode(file.metadata.sha256_hash()));
+        if headers
+            .get(header::IF_NONE_MATCH)
+            .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+        {
+            return (
+                StatusCode::NOT_MODIFIED,
+                [
+                    (header::ETAG, etag),
+                    (header::CACHE_CONTROL, cache_control.to_string()),
+                ],
+            )
+                .into_response();
+        }
+        (
+            [
+                (header::CONTENT_TYPE, file.metadata.mimetype().to_string()),
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, cache_control.to_string()),
+            ],
+            Body::from(file.data.into_owned()),
+        )
+            .into_response()
+    }
+}
+
+/// Only found files are marked; a missing asset must not be cached for a year
+async fn cache_immutable<B>(mut response: Response<B>) -> Response<B> {
+    if response.status().is_success() {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static(IMMUTABLE));
+    }
+    response
+}
+
+async fn cache_revalidate<B>(mut response: Response<B>) -> Response<B> {
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(REVALIDATE));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get};
+    use tower::ServiceExt;
+
+    async fn fetch(app: &Router, uri: &str) -> (StatusCode, Option<String>, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let cache_control = response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            cache_control,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_serves_assets_with_spa_fallback() {
+        let dir = std::env::temp_dir().join(format!("md-todo-frontend-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("index.html"), "<div id=\"app\"></div>").unwrap();
+        std::fs::write(dir.join("favicon.ico"), "icon").unwrap();
+        std::fs::write(dir.join("assets/app-3f2b8c1d.js"), "console.log(1)").unwrap();
+
+        let config = FrontendConfig { dir: dir.clone() };
+        let app = config.serve(Router::new().route("/api/todos", get(|| async { "[]" })));
+
+        let (status, cache, body) = fetch(&app, "/assets/app-3f2b8c1d.js").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cache.as_deref(), Some(IMMUTABLE));
+        assert_eq!(body, "console.log(1)");
+
+        let (status, cache, _) = fetch(&app, "/assets/app-00000000.js").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(cache, None);
+
+        for path in ["/", "/todos/42", "/favicon.ico"] {
+            let (status, cache, _) = fetch(&app, path).await;
+            assert_eq!(status, StatusCode::OK, "{path}");
+            assert_eq!(cache.as_deref(), Some(REVALIDATE), "{path}");
+        }
+        let (_, _, body) = fetch(&app, "/todos/42").await;
+        assert_eq!(body, "<div id=\"app\"></div>");
+
+        assert_eq!(fetch(&app, "/api/todos").await.2, "[]");
+        assert_eq!(fetch(&app, "/api/nope").await.0, StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "embedded-frontend")]
+    #[tokio::test]
+    async fn test_embedded_spa_fallback() {
+        let app = serve_embedded(Router::new());
+        let (status, cache, index) = fetch(&app, "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cache.as_deref(), Some(REVALIDATE));
+        assert_eq!(fetch(&app, "/todos/42").await.2, index);
+        assert_eq!(
+            fetch(&app, "/assets/missing-00000000.js").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(fetch(&app, "/api/nope").await.0, StatusCode::NOT_FOUND);
+    }
+}
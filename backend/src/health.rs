@@ -0,0 +1,231 @@
+//! Dependency checks behind `/health/ready`.
+//!
+//! Each configured dependency (the database always, the event broker with `NATS_URL`, the
+//! IMAP server with `IMAP_HOST`) is checked concurrently and reported with its latency. Only
+//! required dependencies make the server unready: events wait in the task queue while the
+//! broker is down and mail is fetched on the next poll, so those are reported but keep
+//! traffic flowing.
+//!
+//! Results are kept for `CACHE_TTL` so load balancers polling several times a second do not
+//! turn into as many database and broker round trips; concurrent requests wait for the
+//! check in flight instead of starting their own.
+
+use crate::events::EventPublisher;
+use crate::TodoRepositoryTrait;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+/// How long a check may take before it counts as failed
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long results are reused
+pub const CACHE_TTL: Duration = Duration::from_secs(2);
+
+#[async_trait]
+pub trait DependencyCheck: Send + Sync {
+    /// Name in the `dependencies` of `/health/ready`, as in "broker"
+    fn name(&self) -> &str;
+    /// Whether the server cannot serve requests without it
+    fn required(&self) -> bool {
+        false
+    }
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// The database behind the repository; always required
+pub struct DatabaseCheck<R: ?Sized>(pub Arc<R>);
+
+#[async_trait]
+impl<R: TodoRepositoryTrait + ?Sized> DependencyCheck for DatabaseCheck<R> {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.ping().await.map_err(|e| e.to_string())
+    }
+}
+
+/// The broker todo events are published to
+pub struct BrokerCheck(pub Arc<dyn EventPublisher>);
+
+#[async_trait]
+impl DependencyCheck for BrokerCheck {
+    fn name(&self) -> &str {
+        "broker"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.check().await.map_err(|e| e.to_string())
+    }
+}
+
+/// A server that only has to accept TCP connections, such as the IMAP server; the
+/// protocol is not spoken
+pub struct TcpCheck {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+}
+
+#[async_trait]
+impl DependencyCheck for TcpCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        tokio::net::TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map(drop)
+            .map_err(|e| format!("Cannot connect to {}:{}: {}", self.host, self.port, e))
+    }
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DependencyStatus {
+    #[schema(example = "database")]
+    pub name: String,
+    #[schema(example = true)]
+    pub healthy: bool,
+    /// Whether the server is unready while this dependency is unhealthy
+    #[schema(example = true)]
+    pub required: bool,
+    /// Time the check took, up to the timeout
+    #[schema(example = 3)]
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Cannot connect to imap.example.com:993: connection refused")]
+    pub error: Option<String>,
+}
+
+impl DependencyStatus {
+    async fn of(check: &dyn DependencyCheck) -> Self {
+        let started = Instant::now();
+        let error = match tokio::time::timeout(CHECK_TIMEOUT, check.check()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e),
+            Err(_) => Some(format!("No answer within {:?}", CHECK_TIMEOUT)),
+        };
+        if let Some(error) = &error {
+            tracing::warn!("Dependency {} is unhealthy: {}", check.name(), error);
+        }
+        Self {
+            name: check.name().to_string(),
+            healthy: error.is_none(),
+            required: check.required(),
+            latency_ms: started.elapsed().as_millis().try_into().unwrap_or(u64::MAX),
+            error,
+        }
+    }
+}
+
+type Cached = Option<(Instant, Vec<DependencyStatus>)>;
+
+/// The dependencies besides the database, with the latest results
+#[derive(Clone)]
+pub struct DependencyChecks {
+    checks: Vec<Arc<dyn DependencyCheck>>,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<Cached>>,
+}
+
+impl Default for DependencyChecks {
+    fn default() -> Self {
+        Self {
+            checks: Vec::new(),
+            cache_ttl: CACHE_TTL,
+            cache: Arc::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DependencyChecks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.checks.iter().map(|check| check.name()))
+            .finish()
+    }
+}
+
+impl DependencyChecks {
+    pub fn add(&mut self, check: Arc<dyn DependencyCheck>) {
+        self.checks.push(check);
+    }
+
+    /// Reuse results for `ttl`; `Duration::ZERO` checks on every request
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// Statuses of `database` and every added dependency, in that order
+    pub async fn statuses(&self, database: &dyn DependencyCheck) -> Vec<DependencyStatus> {
+        let mut cache = self.cache.lock().await;
+        if let Some((checked_at, statuses)) = cache.as_ref() {
+            if checked_at.elapsed() < self.cache_ttl {
+                return statuses.clone();
+            }
+        }
+        let checks = std::iter::once(database).chain(self.checks.iter().map(Arc::as_ref));
+        let statuses = futures::future::join_all(checks.map(DependencyStatus::of)).await;
+        *cache = Some((Instant::now(), statuses.clone()));
+        statuses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counted {
+        calls: AtomicUsize,
+        result: Result<(), String>,
+    }
+
+    #[async_trait]
+    impl DependencyCheck for Counted {
+        fn name(&self) -> &str {
+            "counted"
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.result.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_statuses_are_cached() {
+        let database = Counted {
+            calls: AtomicUsize::new(0),
+            result: Ok(()),
+        };
+        let broker = Arc::new(Counted {
+            calls: AtomicUsize::new(0),
+            result: Err("down".to_string()),
+        });
+        let mut checks = DependencyChecks::default();
+        checks.add(broker.clone());
+
+        let statuses = checks.statuses(&database).await;
+        assert_eq!(statuses.len(), 2);
+        assert!(statuses[0].healthy);
+        assert_eq!(statuses[1].error.as_deref(), Some("down"));
+        assert_eq!(checks.statuses(&database).await, statuses);
+        assert_eq!(broker.calls.load(Ordering::SeqCst), 1);
+
+        let checks = checks.with_cache_ttl(Duration::ZERO);
+        checks.statuses(&database).await;
+        assert_eq!(database.calls.load(Ordering::SeqCst), 2);
+    }
+}
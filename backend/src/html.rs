@@ -0,0 +1,314 @@
+//! Offline archive: `GET /api/export/html` compiles todos into one self-contained HTML file
+//! with an index, for reading without the app.
+//!
+//! A label plays the role of a project: `?label=work` bundles every todo filed under
+//! `work`. Links between todos, written as short links (`/t/1C`) or API paths
+//! (`/api/todos/<id>`), point to the todo's section when it is part of the bundle. Raw HTML
+//! in content is shown as text so an archive cannot run scripts. Emoji shortcodes such as
+//! `:tada:` are rendered as emoji.
+
+use crate::domain::TodoId;
+use crate::{emoji, labels, Todo, TodoError, TodoFilter, TodoRepositoryTrait};
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+const STYLE: &str =
+    "body{font-family:sans-serif;max-width:46rem;margin:2rem auto;padding:0 1rem;line-height:1.5}\
+article{border-top:1px solid #ccc;margin-top:2rem}.meta{color:#666;font-size:.9rem}\
+pre{background:#f5f5f5;padding:.5rem;overflow-x:auto}.done{text-decoration:line-through}";
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn anchor(id: TodoId) -> String {
+    format!("todo-{id}")
+}
+
+/// Links between the todos of one bundle, keyed by the paths that refer to them
+pub struct LinkTargets {
+    todos: HashSet<TodoId>,
+    short_ids: HashMap<String, TodoId>,
+}
+
+impl LinkTargets {
+    pub fn new(todos: &[Todo], short_ids: HashMap<String, TodoId>) -> Self {
+        Self {
+            todos: todos.iter().map(|todo| todo.id).collect(),
+            short_ids,
+        }
+    }
+
+    /// In-page anchor for a link to a bundled todo
+    fn resolve(&self, dest: &str) -> Option<String> {
+        let id = if let Some(short_id) = dest.strip_prefix("/t/") {
+            *self.short_ids.get(short_id)?
+        } else {
+            dest.strip_prefix("/api/todos/")?.parse().ok()?
+        };
+        self.todos.contains(&id).then(|| format!("#{}", anchor(id)))
+    }
+
+    /// Bundled todos become anchors; script-capable schemes are dropped
+    fn rewrite<'a>(&self, dest: CowStr<'a>) -> CowStr<'a> {
+        if let Some(local) = self.resolve(&dest) {
+            return local.into();
+        }
+        let scheme = dest
+            .split_once(':')
+            .filter(|(scheme, _)| !scheme.contains(['/', '?', '#']))
+            .map(|(scheme, _)| scheme.to_ascii_lowercase());
+        match scheme.as_deref() {
+            None | Some("http" | "https" | "mailto") => dest,
+            Some(_) => "#".into(),
+        }
+    }
+}
+
+pub fn render_markdown(markdown: &str, links: &LinkTargets) -> String {
+    let options =
+        Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut in_code_block = false;
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::CodeBlock(kind)) => {
+            in_code_block = true;
+            Event::Start(Tag::CodeBlock(kind))
+        }
+        Event::End(TagEnd::CodeBlock) => {
+            in_code_block = false;
+            Event::End(TagEnd::CodeBlock)
+        }
+        Event::Text(text) if !in_code_block => Event::Text(match emoji::expand(&text) {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(expanded) => expanded.into(),
+        }),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: links.rewrite(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: links.rewrite(dest_url),
+            title,
+            id,
+        }),
+        other => other,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+/// Whole HTML document: index first, then one section per todo
+pub fn render_bundle(title: &str, todos: &[Todo], links: &LinkTargets) -> String {
+    let title = escape_html(title);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>{STYLE}</style>\n</head>\n<body>\n<h1 id=\"index\">{title}</h1>\n<ol>\n"
+    );
+    for todo in todos {
+        html.push_str(&format!(
+            "<li><a href=\"#{}\"{}>{}</a></li>\n",
+            anchor(todo.id),
+            if todo.completed {
+                " class=\"done\""
+            } else {
+                ""
+            },
+            escape_html(&emoji::expand(&todo.title))
+        ));
+    }
+    html.push_str("</ol>\n");
+
+    for todo in todos {
+        let mut details = vec![
+            if todo.completed { "Completed" } else { "Open" }.to_string(),
+            format!("created {}", todo.created_at.format("%Y-%m-%d")),
+        ];
+        if !todo.labels.is_empty() {
+            details.push(format!("labels: {}", todo.labels.join(", ")));
+        }
+        html.push_str(&format!(
+            "<article id=\"{}\">\n<h2>{}</h2>\n<p class=\"meta\">{}</p>\n{}<p><a href=\"#index\">Back to index</a></p>\n</article>\n",
+            anchor(todo.id),
+            escape_html(&emoji::expand(&todo.title)),
+            escape_html(&details.join(" · ")),
+            render_markdown(&todo.content, links)
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/export/html",
+    params(
+        ("label" = Option<String>, Query, description = "Only bundle todos under this label path")
+    ),
+    responses(
+        (status = 200, description = "Self-contained HTML archive", content_type = "text/html", body = String),
+        (status = 400, description = "Invalid label", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn export_html<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, TodoError> {
+    let label = match params.get("label").map(|label| labels::normalize(label)) {
+        Some(Ok(label)) => Some(label),
+        Some(Err(e)) => {
+            tracing::warn!("Invalid label for HTML export: {}", e);
+            return Err(TodoError::Validation(e));
+        }
+        None => None,
+    };
+    let result = match &label {
+        Some(label) => {
+            let filter = TodoFilter {
+                label: Some(label.clone()),
+                ..Default::default()
+            };
+            repository.find_todos(&filter).await
+        }
+        None => repository.get_all_todos().await,
+    };
+    let todos = result.map_err(|e| {
+        tracing::error!("Failed to get todos for HTML export: {}", e);
+        e
+    })?;
+
+    let mut short_ids = HashMap::new();
+    for todo in &todos {
+        match repository.get_short_id(todo.id).await {
+            Ok(Some(short_id)) => {
+                short_ids.insert(short_id, todo.id);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to get short link for todo {}: {}", todo.id, e);
+                return Err(e);
+            }
+        }
+    }
+
+    tracing::info!("Exporting {} todos to HTML", todos.len());
+    let links = LinkTargets::new(&todos, short_ids);
+    let title = label.as_deref().unwrap_or("Todos");
+    let filename = match &label {
+        Some(label) => format!("{}.html", label.replace(labels::SEPARATOR, "-")),
+        None => "todos.html".to_string(),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        render_bundle(title, &todos, &links),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(todo: &Todo) -> LinkTargets {
+        LinkTargets::new(
+            std::slice::from_ref(todo),
+            HashMap::from([("1C".to_string(), todo.id)]),
+        )
+    }
+
+    #[test]
+    fn test_links_to_bundled_todos_become_anchors() {
+        let todo = Todo::new_with_validation("Target", "").unwrap();
+        let links = targets(&todo);
+        let html = render_markdown(
+            &format!(
+                "[short](/t/1C) [api](/api/todos/{}) [gone](/t/zz) [web](https://example.com)",
+                todo.id
+            ),
+            &links,
+        );
+        let local = format!("href=\"#todo-{}\"", todo.id);
+        assert_eq!(html.matches(&local).count(), 2);
+        assert!(html.contains("href=\"/t/zz\""));
+        assert!(html.contains("href=\"https://example.com\""));
+    }
+
+    #[test]
+    fn test_scripts_are_neutralized() {
+        let todo = Todo::new_with_validation("Target", "").unwrap();
+        let html = render_markdown(
+            "<script>alert(1)</script>\n\n[x](javascript:alert(1)) <b>hi</b>",
+            &targets(&todo),
+        );
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.contains("&lt;b&gt;hi"));
+        assert!(html.contains("href=\"#\""));
+    }
+
+    #[test]
+    fn test_shortcodes_render_outside_code() {
+        let todo = Todo::new_with_validation("Target", "").unwrap();
+        let html = render_markdown(
+            "Shipped :rocket: `:bug:`\n\n```\n:fire:\n```",
+            &targets(&todo),
+        );
+        assert!(html.contains("Shipped 🚀 <code>:bug:</code>"));
+        assert!(html.contains(":fire:"));
+    }
+
+    #[test]
+    fn test_render_bundle_has_index_and_sections() {
+        let mut done = Todo::new_with_validation("Done <1>", "body").unwrap();
+        done.completed = true;
+        let open = Todo::new_with_validation("Open", "- [ ] step").unwrap();
+        let todos = vec![done.clone(), open.clone()];
+        let html = render_bundle("work", &todos, &LinkTargets::new(&todos, HashMap::new()));
+        assert!(html.contains(&format!(
+            "<li><a href=\"#todo-{}\" class=\"done\">Done &lt;1&gt;</a></li>",
+            done.id
+        )));
+        assert!(html.contains(&format!("<article id=\"todo-{}\">", open.id)));
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\"/>"));
+    }
+}
@@ -0,0 +1,504 @@
+//! IMAP polling capture: a background worker that turns unread mail into todos.
+//!
+//! This is the provider-agnostic alternative to the Mailgun webhook in `inbound`. Each
+//! poll logs in, creates a todo for every unseen message in the mailbox and marks the
+//! message `\Seen`. Attachments are listed in the todo as they are for the webhook. A
+//! message whose todo could not be created stays unseen and is retried on the next poll;
+//! one a content policy rejects, or that is too big or cannot be parsed, is marked seen
+//! and skipped with a warning naming its UID.
+//!
+//! Only the handful of IMAP4rev1 commands the worker needs are implemented, and the
+//! server is not trusted with memory: response lines and literals have a size limit.
+//! TLS connections require the `imap` cargo feature.
+
+use crate::inbound::{Attachment, InboundEmail};
+use crate::moderation::{self, Moderator};
+use crate::TodoRepositoryTrait;
+use mail_parser::{MessageParser, MimeHeaders};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+
+pub type ImapError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Largest message the worker fetches; bigger ones are marked seen and skipped, and a
+/// server sending a bigger literal anyway is cut off rather than trusted with the size
+pub const MAX_MESSAGE_BYTES: usize = 25 * 1024 * 1024;
+
+/// Longest response line the worker reads, not counting literals; a server sending a
+/// longer one is cut off
+pub const MAX_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub mailbox: String,
+    pub poll_interval: Duration,
+}
+
+impl ImapConfig {
+    pub const DEFAULT_PORT: u16 = 993;
+    pub const DEFAULT_MAILBOX: &'static str = "INBOX";
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Reads `IMAP_HOST`, `IMAP_USERNAME` and `IMAP_PASSWORD` plus the optional
+    /// `IMAP_PORT`, `IMAP_MAILBOX` and `IMAP_POLL_INTERVAL_SECS`; `None` disables capture
+    pub fn from_env() -> Option<Self> {
+        let host = std::env::var("IMAP_HOST").ok().filter(|h| !h.is_empty())?;
+        let (Ok(username), Ok(password)) = (
+            std::env::var("IMAP_USERNAME"),
+            std::env::var("IMAP_PASSWORD"),
+        ) else {
+            tracing::warn!("IMAP_HOST is set but IMAP_USERNAME/IMAP_PASSWORD are missing");
+            return None;
+        };
+
+        Some(Self {
+            host,
+            port: std::env::var("IMAP_PORT")
+                .ok()
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(Self::DEFAULT_PORT),
+            username,
+            password,
+            mailbox: std::env::var("IMAP_MAILBOX")
+                .unwrap_or_else(|_| Self::DEFAULT_MAILBOX.to_string()),
+            poll_interval: std::env::var("IMAP_POLL_INTERVAL_SECS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(Self::DEFAULT_POLL_INTERVAL),
+        })
+    }
+}
+
+/// Untagged output of a single command
+#[derive(Debug, Default)]
+struct Reply {
+    lines: Vec<String>,
+    literals: Vec<Vec<u8>>,
+}
+
+pub struct ImapSession<S> {
+    stream: BufStream<S>,
+    next_tag: u32,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ImapSession<S> {
+    /// Wraps an established connection and consumes the server greeting
+    pub async fn new(stream: S) -> Result<Self, ImapError> {
+        let mut session = Self {
+            stream: BufStream::new(stream),
+            next_tag: 0,
+        };
+        let greeting = session.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(format!("Unexpected IMAP greeting: {}", greeting.trim_end()).into());
+        }
+        Ok(session)
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<(), ImapError> {
+        self.command(&format!("LOGIN {} {}", quote(username), quote(password)))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn select(&mut self, mailbox: &str) -> Result<(), ImapError> {
+        self.command(&format!("SELECT {}", quote(mailbox))).await?;
+        Ok(())
+    }
+
+    pub async fn search_unseen(&mut self) -> Result<Vec<u32>, ImapError> {
+        let reply = self.command("UID SEARCH UNSEEN").await?;
+        Ok(reply
+            .lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("* SEARCH"))
+            .flat_map(|uids| uids.split_whitespace())
+            .filter_map(|uid| uid.parse().ok())
+            .collect())
+    }
+
+    /// Size of a message in bytes as the server reports it
+    pub async fn size(&mut self, uid: u32) -> Result<Option<usize>, ImapError> {
+        let reply = self
+            .command(&format!("UID FETCH {uid} RFC822.SIZE"))
+            .await?;
+        Ok(reply.lines.iter().find_map(|line| {
+            let (_, rest) = line.split_once("RFC822.SIZE ")?;
+            let digits = rest.trim_start();
+            let end = digits
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(digits.len());
+            digits[..end].parse().ok()
+        }))
+    }
+
+    /// Raw RFC 822 message; `BODY.PEEK` leaves the `\Seen` flag untouched
+    pub async fn fetch(&mut self, uid: u32) -> Result<Option<Vec<u8>>, ImapError> {
+        let reply = self
+            .command(&format!("UID FETCH {uid} BODY.PEEK[]"))
+            .await?;
+        Ok(reply.literals.into_iter().next())
+    }
+
+    pub async fn mark_seen(&mut self, uid: u32) -> Result<(), ImapError> {
+        self.command(&format!("UID STORE {uid} +FLAGS (\\Seen)"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn logout(&mut self) -> Result<(), ImapError> {
+        self.command("LOGOUT").await?;
+        Ok(())
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Reply, ImapError> {
+        self.next_tag += 1;
+        let tag = format!("A{:04}", self.next_tag);
+        self.stream
+            .write_all(format!("{tag} {command}\r\n").as_bytes())
+            .await?;
+        self.stream.flush().await?;
+
+        let mut reply = Reply::default();
+        loop {
+            let mut line = self.read_line().await?;
+            if let Some(status) = line.strip_prefix(&format!("{tag} ")) {
+                if status.starts_with("OK") {
+                    return Ok(reply);
+                }
+                return Err(format!("IMAP command failed: {}", status.trim_end()).into());
+            }
+
+            // A line ending in `{n}` is followed by n bytes of literal data and then the
+            // rest of the response
+            while let Some(size) = literal_size(&line) {
+                if size > MAX_MESSAGE_BYTES {
+                    return Err(format!(
+                        "IMAP literal of {size} bytes exceeds the {MAX_MESSAGE_BYTES} byte limit"
+                    )
+                    .into());
+                }
+                let mut literal = vec![0; size];
+                self.stream.read_exact(&mut literal).await?;
+                reply.literals.push(literal);
+                reply.lines.push(line);
+                line = self.read_line().await?;
+            }
+            reply.lines.push(line);
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, ImapError> {
+        let mut line = Vec::new();
+        let mut limited = (&mut self.stream).take(MAX_LINE_BYTES as u64);
+        if limited.read_until(b'\n', &mut line).await? == 0 {
+            return Err("IMAP connection closed".into());
+        }
+        if line.len() == MAX_LINE_BYTES && !line.ends_with(b"\n") {
+            return Err(
+                format!("IMAP response line exceeds the {MAX_LINE_BYTES} byte limit").into(),
+            );
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn literal_size(line: &str) -> Option<usize> {
+    let line = line.trim_end().strip_suffix('}')?;
+    let start = line.rfind('{')?;
+    line[start + 1..].parse().ok()
+}
+
+/// Maps a raw message onto the same fields the webhook receives
+pub fn parse_message(raw: &[u8]) -> Option<InboundEmail> {
+    let message = MessageParser::default().parse(raw)?;
+    Some(InboundEmail {
+        subject: message.subject().unwrap_or_default().to_string(),
+        sender: message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address())
+            .unwrap_or_default()
+            .to_string(),
+        body: message
+            .body_text(0)
+            .map(|body| body.into_owned())
+            .unwrap_or_default(),
+        attachments: message
+            .attachments()
+            .map(|part| Attachment {
+                file_name: part.attachment_name().unwrap_or("(unnamed)").to_string(),
+                content_type: part
+                    .content_type()
+                    .map(|content_type| match content_type.subtype() {
+                        Some(subtype) => format!("{}/{}", content_type.ctype(), subtype),
+                        None => content_type.ctype().to_string(),
+                    })
+                    .unwrap_or_else(|| Attachment::DEFAULT_CONTENT_TYPE.to_string()),
+                size: part.contents().len(),
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// Creates a todo for every unseen message in `mailbox`, returning how many were created
+pub async fn capture_unseen<S, R>(
+    session: &mut ImapSession<S>,
+    repository: &R,
+    moderator: &Moderator,
+    mailbox: &str,
+) -> Result<usize, ImapError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: TodoRepositoryTrait + ?Sized,
+{
+    session.select(mailbox).await?;
+
+    let mut created = 0;
+    for uid in session.search_unseen().await? {
+        if let Some(size) = session.size(uid).await? {
+            if size > MAX_MESSAGE_BYTES {
+                tracing::warn!(
+                    "Skipping IMAP message {} of {} bytes, over the {} byte limit",
+                    uid,
+                    size,
+                    MAX_MESSAGE_BYTES
+                );
+                session.mark_seen(uid).await?;
+                continue;
+            }
+        }
+        let Some(raw) = session.fetch(uid).await? else {
+            tracing::warn!("IMAP message {} returned no body", uid);
+            continue;
+        };
+        let Some(email) = parse_message(&raw) else {
+            tracing::warn!("Skipping unparseable IMAP message {}", uid);
+            session.mark_seen(uid).await?;
+            continue;
+        };
+
+        let reviewed = email.to_todo().and_then(|todo| {
+            let findings =
+                moderator.review(Some(todo.title.as_str()), Some(todo.content.as_str()))?;
+            Ok((todo, findings))
+        });
+        let (todo, findings) = match reviewed {
+            Ok(reviewed) => reviewed,
+            Err(e) => {
+                tracing::warn!("Skipping IMAP message {}: {}", uid, e);
+                session.mark_seen(uid).await?;
+                continue;
+            }
+        };
+        match repository.create_todo(&todo).await {
+            Ok(created_todo) => {
+                tracing::info!("Created todo {} from IMAP message {}", created_todo.id, uid);
+                moderation::record(repository, created_todo.id, &findings).await;
+                session.mark_seen(uid).await?;
+                created += 1;
+            }
+            Err(e) => {
+                tracing::error!("Failed to create todo from IMAP message {}: {}", uid, e);
+            }
+        }
+    }
+    Ok(created)
+}
+
+#[cfg(feature = "imap")]
+pub async fn connect(
+    config: &ImapConfig,
+) -> Result<ImapSession<tokio_rustls::client::TlsStream<tokio::net::TcpStream>>, ImapError> {
+    use std::sync::Arc;
+    use tokio_rustls::rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore};
+
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let tls = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let tcp = tokio::net::TcpStream::connect((config.host.as_str(), config.port)).await?;
+    let server_name = ServerName::try_from(config.host.clone())?;
+    let stream = tokio_rustls::TlsConnector::from(Arc::new(tls))
+        .connect(server_name, tcp)
+        .await?;
+    ImapSession::new(stream).await
+}
+
+/// Polls the configured mailbox every `poll_interval` while this instance is the job
+/// leader, so replicas do not capture a mail more than once
+#[cfg(feature = "imap")]
+pub fn spawn_capture_worker<R: TodoRepositoryTrait + 'static>(
+    config: ImapConfig,
+    repository: std::sync::Arc<R>,
+    moderator: std::sync::Arc<Moderator>,
+    jobs: &std::sync::Arc<crate::jobs::JobRunner>,
+) -> tokio::task::JoinHandle<()> {
+    let interval = config.poll_interval;
+    jobs.spawn("imap-capture", interval, move || {
+        let config = config.clone();
+        let repository = repository.clone();
+        let moderator = moderator.clone();
+        async move {
+            poll_once(&config, repository.as_ref(), &moderator)
+                .await
+                .map_err(|e| format!("IMAP capture from {} failed: {}", config.host, e).into())
+        }
+    })
+}
+
+#[cfg(feature = "imap")]
+async fn poll_once<R: TodoRepositoryTrait>(
+    config: &ImapConfig,
+    repository: &R,
+    moderator: &Moderator,
+) -> Result<(), ImapError> {
+    let mut session = connect(config).await?;
+    session.login(&config.username, &config.password).await?;
+    let created = capture_unseen(&mut session, repository, moderator, &config.mailbox).await?;
+    session.logout().await?;
+    if created > 0 {
+        tracing::info!(
+            "Captured {} todos from IMAP mailbox {}",
+            created,
+            config.mailbox
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    async fn scripted_server(stream: DuplexStream, script: Vec<(&'static str, String)>) {
+        let mut stream = BufStream::new(stream);
+        stream.write_all(b"* OK IMAP4rev1 ready\r\n").await.unwrap();
+        stream.flush().await.unwrap();
+        for (expected, response) in script {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            let (tag, command) = line.trim_end().split_once(' ').unwrap();
+            assert_eq!(command, expected);
+            stream
+                .write_all(response.replace("{tag}", tag).as_bytes())
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_literal_size() {
+        assert_eq!(literal_size("* 1 FETCH (UID 7 BODY[] {42}\r\n"), Some(42));
+        assert_eq!(literal_size("* SEARCH 1 2\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_literal_is_refused() {
+        let (client, server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(scripted_server(
+            server,
+            vec![(
+                "UID FETCH 9 BODY.PEEK[]",
+                format!("* 2 FETCH (UID 9 BODY[] {{{}}}\r\n", MAX_MESSAGE_BYTES + 1),
+            )],
+        ));
+
+        let mut session = ImapSession::new(client).await.unwrap();
+        let error = session.fetch(9).await.unwrap_err();
+        assert!(error.to_string().contains("exceeds"));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_overlong_line_is_refused() {
+        let (client, server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            let mut stream = BufStream::new(server);
+            stream.write_all(b"* OK IMAP4rev1 ready\r\n").await.unwrap();
+            stream.flush().await.unwrap();
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            // Never ends the line; the client must give up before reading it all
+            let chunk = vec![b'x'; 4096];
+            for _ in 0..=MAX_LINE_BYTES / chunk.len() {
+                if stream.write_all(&chunk).await.is_err() || stream.flush().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut session = ImapSession::new(client).await.unwrap();
+        let error = session.search_unseen().await.unwrap_err();
+        assert!(error.to_string().contains("exceeds"));
+        drop(session);
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(r#"pa"ss\word"#), r#""pa\"ss\\word""#);
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let raw = b"From: Me <me@example.com>\r\nSubject: Pay rent\r\n\r\nBefore Friday.\r\n";
+        let email = parse_message(raw).unwrap();
+        assert_eq!(email.subject, "Pay rent");
+        assert_eq!(email.sender, "me@example.com");
+        assert_eq!(email.body.trim_end(), "Before Friday.");
+    }
+
+    #[tokio::test]
+    async fn test_session_commands() {
+        let (client, server) = tokio::io::duplex(4096);
+        let message = "Subject: Hi\r\n\r\nBody\r\n";
+        let server = tokio::spawn(scripted_server(
+            server,
+            vec![
+                ("LOGIN \"me\" \"secret\"", "{tag} OK LOGIN completed\r\n".into()),
+                ("SELECT \"INBOX\"", "* 2 EXISTS\r\n{tag} OK [READ-WRITE]\r\n".into()),
+                (
+                    "UID SEARCH UNSEEN",
+                    "* SEARCH 4 9\r\n{tag} OK SEARCH completed\r\n".into(),
+                ),
+                (
+                    "UID FETCH 9 BODY.PEEK[]",
+                    format!(
+                        "* 2 FETCH (UID 9 BODY[] {{{}}}\r\n{message})\r\n{{tag}} OK FETCH completed\r\n",
+                        message.len()
+                    ),
+                ),
+                (
+                    "UID FETCH 9 RFC822.SIZE",
+                    "* 2 FETCH (UID 9 RFC822.SIZE 18)\r\n{tag} OK FETCH completed\r\n".into(),
+                ),
+                ("UID STORE 9 +FLAGS (\\Seen)", "{tag} NO read-only\r\n".into()),
+            ],
+        ));
+
+        let mut session = ImapSession::new(client).await.unwrap();
+        session.login("me", "secret").await.unwrap();
+        session.select("INBOX").await.unwrap();
+        assert_eq!(session.search_unseen().await.unwrap(), vec![4, 9]);
+        assert_eq!(session.fetch(9).await.unwrap().unwrap(), message.as_bytes());
+        assert_eq!(session.size(9).await.unwrap(), Some(18));
+        assert!(session.mark_seen(9).await.is_err());
+        server.await.unwrap();
+    }
+}
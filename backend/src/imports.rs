@@ -0,0 +1,397 @@
+//! Imports too large to wait for: `POST /api/import` answers at once with a job, and the
+//! todos are created in the background.
+//!
+//! Items are validated like `POST /api/todos/bulk` before the job is stored, so rejected
+//! items show up in the job's `errors` right away, content policies included; what they
+//! flag is queued for review once the todo is created. The valid ones are stored with the
+//! job and created in chunks of `IMPORT_CHUNK`, each in one transaction, with
+//! `GET /api/jobs/:id` reporting progress after every chunk. `POST /api/jobs/:id/cancel`
+//! stops the import after the chunk being created; todos created before that stay.
+//!
+//! With a database the import runs as a task of the queue, so a replica that stops midway
+//! leaves it for another to resume: chunks already created are recognized by their first
+//! todo and skipped. Without one (`--local`) it runs in this process. Either way it acts
+//! for the user who started it, so the todos are theirs as if they had created them.
+
+use crate::auth;
+use crate::bulk::prepare_todos;
+use crate::emoji::EmojiConfig;
+use crate::errors::ErrorCode;
+use crate::moderation::{self, Moderator};
+use crate::queue::{QueueError, TaskHandler, TaskQueue};
+use crate::{ApiResponse, CreateTodoRequest, Todo, TodoError, TodoRepositoryTrait};
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Most items one import may carry
+pub const MAX_IMPORT_TODOS: usize = 10_000;
+/// Todos created per transaction, and between progress updates
+pub const IMPORT_CHUNK: usize = 100;
+/// Task queue kind of imports
+pub const IMPORT_TASK: &str = "import_todos";
+
+/// Where an import stands; the same as the `import_status` enum in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "import_status", rename_all = "snake_case")]
+pub enum ImportStatus {
+    Queued,
+    Running,
+    Completed,
+    Cancelled,
+}
+
+impl ImportStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, ImportStatus::Completed | ImportStatus::Cancelled)
+    }
+}
+
+/// An item that was not imported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ImportItemError {
+    /// Position of the item in the request
+    #[schema(example = 3)]
+    pub index: usize,
+    #[schema(example = "Title cannot be empty")]
+    pub error: String,
+}
+
+/// A valid item waiting to be created
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportItem {
+    pub index: usize,
+    pub todo: Todo,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct ImportJob {
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Uuid,
+    pub status: ImportStatus,
+    /// Items in the request
+    #[schema(example = 5000)]
+    pub total: i32,
+    /// Items created or rejected so far
+    #[schema(example = 1200)]
+    pub processed: i32,
+    /// Todos created so far
+    #[schema(example = 1199)]
+    pub created: i32,
+    /// Items that were not imported, by index
+    #[sqlx(json)]
+    pub errors: Vec<ImportItemError>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// When the import completed or was cancelled
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ImportJob {
+    /// A queued job over `total` items, of which those in `errors` were rejected
+    pub fn new(total: usize, errors: Vec<ImportItemError>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::now_v7(),
+            status: ImportStatus::Queued,
+            total: total as i32,
+            processed: errors.len() as i32,
+            created: 0,
+            errors,
+            created_at: now,
+            updated_at: now,
+            finished_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ImportJobResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<ImportJob>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<ImportJob>> for ImportJobResponse {
+    fn from(response: ApiResponse<ImportJob>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+/// Creates the pending todos of import `id`, resuming where an earlier run stopped
+pub async fn run_import<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    moderator: &Moderator,
+    id: Uuid,
+) -> Result<(), TodoError> {
+    let Some(mut job) = repository.get_import_job(id).await? else {
+        tracing::warn!("Import {} is gone", id);
+        return Ok(());
+    };
+    if job.status.is_finished() {
+        return Ok(());
+    }
+    let items = repository.import_job_items(id).await?;
+
+    // Counted again from the start, since an earlier run may have stopped midway
+    let pending: HashSet<usize> = items.iter().map(|item| item.index).collect();
+    job.errors.retain(|error| !pending.contains(&error.index));
+    job.processed = job.errors.len() as i32;
+    job.created = 0;
+    job.status = ImportStatus::Running;
+    tracing::info!("Importing {} todos for import {}", items.len(), id);
+
+    for chunk in items.chunks(IMPORT_CHUNK) {
+        match repository.update_import_job(&job).await? {
+            Some(stored) if stored.status == ImportStatus::Cancelled => {
+                tracing::info!("Import {} cancelled after {} todos", id, job.created);
+                return Ok(());
+            }
+            Some(_) => {}
+            None => return Ok(()),
+        }
+
+        let todos: Vec<Todo> = chunk.iter().map(|item| item.todo.clone()).collect();
+        if repository.get_todo_by_id(todos[0].id).await?.is_some() {
+            tracing::debug!(
+                "Import {} already created the chunk at {}",
+                id,
+                chunk[0].index
+            );
+            job.created += chunk.len() as i32;
+        } else {
+            match repository.create_todos_bulk(&todos).await {
+                Ok(created) => {
+                    job.created += created.len() as i32;
+                    for todo in &created {
+                        // Rejected items were turned away when the job was stored
+                        if let Ok(findings) =
+                            moderator.review(Some(todo.title.as_str()), Some(todo.content.as_str()))
+                        {
+                            moderation::record(repository, todo.id, &findings).await;
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Import {} failed to create the chunk at {}: {}",
+                        id,
+                        chunk[0].index,
+                        e
+                    );
+                    job.errors.extend(chunk.iter().map(|item| ImportItemError {
+                        index: item.index,
+                        error: format!("Failed to create the todo: {e}"),
+                    }));
+                }
+            }
+        }
+        job.processed += chunk.len() as i32;
+    }
+
+    job.errors.sort_by_key(|error| error.index);
+    job.status = ImportStatus::Completed;
+    repository.update_import_job(&job).await?;
+    tracing::info!(
+        "Import {} completed: {} created, {} not imported",
+        id,
+        job.created,
+        job.errors.len()
+    );
+    Ok(())
+}
+
+/// Runs imports from the task queue
+pub struct ImportTask<R: ?Sized> {
+    repository: Arc<R>,
+    moderator: Arc<Moderator>,
+}
+
+impl<R: TodoRepositoryTrait + ?Sized> ImportTask<R> {
+    pub fn new(repository: Arc<R>, moderator: Arc<Moderator>) -> Self {
+        Self {
+            repository,
+            moderator,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ImportPayload {
+    job_id: Uuid,
+    /// Who started the import; missing from tasks queued before imports had owners
+    #[serde(default)]
+    user: Uuid,
+}
+
+#[async_trait]
+impl<R: TodoRepositoryTrait + ?Sized> TaskHandler for ImportTask<R> {
+    async fn run(&self, payload: &serde_json::Value) -> Result<(), QueueError> {
+        let payload: ImportPayload = serde_json::from_value(payload.clone())?;
+        let import = run_import(self.repository.as_ref(), &self.moderator, payload.job_id);
+        Ok(auth::act_as(payload.user, import).await?)
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/import",
+    request_body = Vec<CreateTodoRequest>,
+    responses(
+        (status = 202, description = "Import queued; follow it with GET /api/jobs/{id}", body = ImportJobResponse),
+        (status = 400, description = "Empty request or more than 10000 items", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Imports"
+)]
+pub async fn start_import<R: TodoRepositoryTrait + 'static>(
+    State(repository): State<Arc<R>>,
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Extension(queue): Extension<Option<TaskQueue>>,
+    Json(requests): Json<Vec<CreateTodoRequest>>,
+) -> Result<(StatusCode, Json<ImportJobResponse>), TodoError> {
+    if requests.is_empty() || requests.len() > MAX_IMPORT_TODOS {
+        tracing::warn!(
+            "Imports need 1 to {} items, got {}",
+            MAX_IMPORT_TODOS,
+            requests.len()
+        );
+        return Err(TodoError::Validation(format!(
+            "An import needs 1 to {MAX_IMPORT_TODOS} items"
+        )));
+    }
+
+    let total = requests.len();
+    let results = prepare_todos(repository.as_ref(), &emoji, &moderator, requests).await?;
+    let mut items = Vec::new();
+    let mut errors = Vec::new();
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(todo) => items.push(ImportItem { index, todo }),
+            Err(error) => errors.push(ImportItemError { index, error }),
+        }
+    }
+    let job = repository
+        .create_import_job(&ImportJob::new(total, errors), &items)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create an import job: {}", e);
+            e
+        })?;
+
+    let user = auth::acting_user();
+    match queue {
+        Some(queue) => {
+            if let Err(e) = queue
+                .enqueue(
+                    IMPORT_TASK,
+                    &serde_json::json!({ "job_id": job.id, "user": user }),
+                )
+                .await
+            {
+                tracing::error!("Failed to queue import {}: {}", job.id, e);
+                let _ = repository.cancel_import_job(job.id).await;
+                return Err(TodoError::Internal(e.to_string()));
+            }
+        }
+        None => {
+            let repository = repository.clone();
+            let id = job.id;
+            tokio::spawn(async move {
+                let import = run_import(repository.as_ref(), &moderator, id);
+                if let Err(e) = auth::act_as(user, import).await {
+                    tracing::error!("Import {} failed: {}", id, e);
+                }
+            });
+        }
+    }
+    tracing::info!(
+        "Queued import {} of {} todos, {} rejected",
+        job.id,
+        items.len(),
+        job.errors.len()
+    );
+    Ok((StatusCode::ACCEPTED, Json(ApiResponse::success(job).into())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Import job ID")
+    ),
+    responses(
+        (status = 200, description = "Progress of the import", body = ImportJobResponse),
+        (status = 404, description = "Import job not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Imports"
+)]
+pub async fn get_job<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ImportJobResponse>, TodoError> {
+    match repository.get_import_job(id).await {
+        Ok(Some(job)) => Ok(Json(ApiResponse::success(job).into())),
+        Ok(None) => Err(TodoError::NotFound(format!("Import job {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to get import {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/jobs/{id}/cancel",
+    params(
+        ("id" = Uuid, Path, description = "Import job ID")
+    ),
+    responses(
+        (status = 200, description = "Import cancelled; todos created so far stay", body = ImportJobResponse),
+        (status = 404, description = "Import job not found", body = ErrorResponse),
+        (status = 409, description = "The import already completed", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Imports"
+)]
+pub async fn cancel_job<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ImportJobResponse>, TodoError> {
+    match repository.cancel_import_job(id).await {
+        Ok(Some(job)) if job.status == ImportStatus::Cancelled => {
+            tracing::info!("Cancelled import {}", id);
+            Ok(Json(ApiResponse::success(job).into()))
+        }
+        Ok(Some(_)) => Err(TodoError::Conflict(
+            "The import already completed".to_string(),
+        )),
+        Ok(None) => Err(TodoError::NotFound(format!("Import job {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to cancel import {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
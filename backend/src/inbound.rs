@@ -0,0 +1,549 @@
+//! Inbound email capture: turns emails forwarded by Mailgun or SendGrid into todos.
+//!
+//! Both POST each message as form data (multipart when it carries attachments).
+//! Mailgun requests at `/api/inbound/email` are authenticated with Mailgun's webhook
+//! signature, an HMAC-SHA256 of `timestamp + token` keyed with the account's signing key.
+//! Stale timestamps are rejected, and so is a token this process already accepted within
+//! the timestamp window, so captured requests cannot be replayed.
+//!
+//! SendGrid's Inbound Parse does not sign its requests. Its parse URL carries basic auth
+//! credentials instead, `https://sendgrid:<SENDGRID_INBOUND_PASSWORD>@<host>/api/inbound/email/sendgrid`,
+//! and only the password is checked. Such requests carry no token to replay-check, so
+//! the URL must only be served over TLS.
+//!
+//! There is nowhere to store attachment files, so the todo keeps a list of them instead:
+//! the name, type and size of each, under the body. Captured todos go through the content
+//! policies like todos created over the API.
+
+use crate::domain::{TodoContent, TodoTitle};
+use crate::moderation::{self, Moderator};
+use crate::{
+    keys_match, truncate_to_chars, unicode, ApiResponse, Todo, TodoError, TodoRepositoryTrait,
+    TodoResponse, MAX_CONTENT_CHARS,
+};
+use axum::{
+    extract::{FromRequest, Multipart, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap,
+    },
+    response::Json,
+    Extension, Form,
+};
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// How far a webhook timestamp may drift from the server clock
+pub const MAX_TIMESTAMP_SKEW_SECS: i64 = 15 * 60;
+
+const EMPTY_SUBJECT_TITLE: &str = "(no subject)";
+
+#[derive(Debug, Clone, Default)]
+pub struct InboundEmailConfig {
+    pub mailgun_signing_key: Option<String>,
+    /// Basic auth password of the SendGrid parse URL
+    pub sendgrid_password: Option<String>,
+}
+
+impl InboundEmailConfig {
+    /// Reads `MAILGUN_SIGNING_KEY` and `SENDGRID_INBOUND_PASSWORD`; each endpoint stays
+    /// disabled without its secret
+    pub fn from_env() -> Self {
+        Self {
+            mailgun_signing_key: std::env::var("MAILGUN_SIGNING_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            sendgrid_password: std::env::var("SENDGRID_INBOUND_PASSWORD")
+                .ok()
+                .filter(|password| !password.is_empty()),
+        }
+    }
+}
+
+/// Mailgun tokens accepted within the timestamp window, with their timestamps
+#[derive(Debug, Default)]
+pub struct SeenTokens {
+    tokens: Mutex<HashMap<String, i64>>,
+}
+
+impl SeenTokens {
+    /// Records `token`; false when it was already accepted. Tokens older than the window
+    /// are dropped, since their requests fail the timestamp check anyway.
+    pub fn insert(&self, token: &str, timestamp: i64) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        let oldest = Utc::now().timestamp() - MAX_TIMESTAMP_SKEW_SECS;
+        tokens.retain(|_, seen| *seen >= oldest);
+        if tokens.contains_key(token) {
+            return false;
+        }
+        tokens.insert(token.to_string(), timestamp);
+        true
+    }
+
+    /// Lets a request with `token` in again, e.g. after its todo could not be created
+    pub fn forget(&self, token: &str) {
+        self.tokens.lock().unwrap().remove(token);
+    }
+}
+
+/// A file attached to an inbound message, as listed in its todo
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub file_name: String,
+    /// MIME type; `application/octet-stream` when the sender gave none
+    pub content_type: String,
+    /// Size in bytes
+    pub size: usize,
+}
+
+impl Attachment {
+    pub const DEFAULT_CONTENT_TYPE: &'static str = "application/octet-stream";
+
+    /// Markdown list item, e.g. `` - `form.pdf` (application/pdf, 12.3 KiB) ``
+    fn list_item(&self) -> String {
+        let name = Todo::sanitize_title(&self.file_name).replace('`', "'");
+        format!(
+            "- `{}` ({}, {})",
+            name,
+            self.content_type,
+            human_size(self.size)
+        )
+    }
+}
+
+fn human_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} bytes"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// The parts of an inbound message we turn into a todo
+#[derive(Debug, Default)]
+pub struct InboundEmail {
+    pub subject: String,
+    pub sender: String,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+    pub timestamp: String,
+    pub token: String,
+    pub signature: String,
+}
+
+impl InboundEmail {
+    /// A Mailgun inbound route payload
+    pub fn from_mailgun(mut fields: HashMap<String, String>, attachments: Vec<Attachment>) -> Self {
+        let mut take = |name: &str| fields.remove(name).unwrap_or_default();
+        Self {
+            subject: take("subject"),
+            sender: take("sender"),
+            body: take("body-plain"),
+            timestamp: take("timestamp"),
+            token: take("token"),
+            signature: take("signature"),
+            attachments,
+        }
+    }
+
+    /// A SendGrid Inbound Parse payload, posted without the raw MIME message
+    pub fn from_sendgrid(
+        mut fields: HashMap<String, String>,
+        attachments: Vec<Attachment>,
+    ) -> Self {
+        let mut take = |name: &str| fields.remove(name).unwrap_or_default();
+        Self {
+            subject: take("subject"),
+            sender: take("from"),
+            body: take("text"),
+            attachments,
+            ..Default::default()
+        }
+    }
+
+    pub fn verify(&self, signing_key: &str) -> bool {
+        let Ok(timestamp) = self.timestamp.parse::<i64>() else {
+            return false;
+        };
+        if (Utc::now().timestamp() - timestamp).abs() > MAX_TIMESTAMP_SKEW_SECS {
+            return false;
+        }
+        verify_mailgun_signature(signing_key, &self.timestamp, &self.token, &self.signature)
+    }
+
+    /// Subject becomes the title, the plain-text body the markdown content, followed by
+    /// the list of attachments and the sender. A body too long for the content is cut
+    /// short rather than the list.
+    pub fn to_todo(&self) -> Result<Todo, TodoError> {
+        let mut trailer = String::new();
+        if !self.attachments.is_empty() {
+            trailer.push_str("**Attachments:**\n\n");
+            let items: Vec<String> = self.attachments.iter().map(Attachment::list_item).collect();
+            trailer.push_str(&items.join("\n"));
+        }
+        if !self.sender.is_empty() {
+            if !trailer.is_empty() {
+                trailer.push_str("\n\n");
+            }
+            trailer.push_str(&format!("**From:** {}", self.sender));
+        }
+        let trailer = unicode::nfc(&trailer);
+
+        let body = unicode::nfc(self.body.trim_end());
+        let room = MAX_CONTENT_CHARS.saturating_sub(trailer.chars().count() + "\n\n---\n\n".len());
+        let mut content = truncate_to_chars(&body, room).to_string();
+        if !trailer.is_empty() {
+            if !content.is_empty() {
+                content.push_str("\n\n---\n\n");
+            }
+            content.push_str(&trailer);
+        }
+
+        Ok(Todo::new(
+            subject_to_title(&self.subject),
+            TodoContent::new(truncate_to_chars(&content, MAX_CONTENT_CHARS))
+                .expect("content is truncated to the limit"),
+        ))
+    }
+}
+
+pub fn verify_mailgun_signature(
+    signing_key: &str,
+    timestamp: &str,
+    token: &str,
+    signature: &str,
+) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(signing_key.as_bytes()) else {
+        return false;
+    };
+    mac.update(timestamp.as_bytes());
+    mac.update(token.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+pub fn subject_to_title(subject: &str) -> TodoTitle {
+    let mut title = Todo::sanitize_title(subject);
+    if title.is_empty() {
+        title = EMPTY_SUBJECT_TITLE.to_string();
+    }
+    TodoTitle::new(title).expect("sanitized titles are valid")
+}
+
+async fn read_fields(
+    request: Request,
+) -> Result<(HashMap<String, String>, Vec<Attachment>), TodoError> {
+    let is_multipart = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+    if !is_multipart {
+        let Form(fields) = Form::<HashMap<String, String>>::from_request(request, &())
+            .await
+            .map_err(|e| {
+                tracing::warn!("Invalid inbound email form: {}", e);
+                TodoError::Validation(e.to_string())
+            })?;
+        return Ok((fields, Vec::new()));
+    }
+
+    let mut multipart = Multipart::from_request(request, &()).await.map_err(|e| {
+        tracing::warn!("Invalid inbound email multipart body: {}", e);
+        TodoError::Validation(e.to_string())
+    })?;
+    let mut fields = HashMap::new();
+    let mut attachments = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        tracing::warn!("Invalid inbound email multipart field: {}", e);
+        TodoError::Validation(e.to_string())
+    })? {
+        if let Some(file_name) = field.file_name().map(str::to_string) {
+            let content_type = field
+                .content_type()
+                .unwrap_or(Attachment::DEFAULT_CONTENT_TYPE)
+                .to_string();
+            let contents = field.bytes().await.map_err(|e| {
+                tracing::warn!("Invalid inbound email attachment {}: {}", file_name, e);
+                TodoError::Validation(e.to_string())
+            })?;
+            attachments.push(Attachment {
+                file_name,
+                content_type,
+                size: contents.len(),
+            });
+            continue;
+        }
+        let Some(name) = field.name().map(str::to_string) else {
+            continue;
+        };
+        let value = field.text().await.map_err(|e| {
+            tracing::warn!("Invalid inbound email field {}: {}", name, e);
+            TodoError::Validation(e.to_string())
+        })?;
+        fields.insert(name, value);
+    }
+    Ok((fields, attachments))
+}
+
+/// The password of a basic `Authorization` header, whatever its user name
+fn basic_auth_password(headers: &HeaderMap) -> Option<String> {
+    let encoded = headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (_, password) = credentials.split_once(':')?;
+    Some(password.to_string())
+}
+
+async fn create_from_email<R: TodoRepositoryTrait>(
+    repository: &R,
+    moderator: &Moderator,
+    email: &InboundEmail,
+) -> Result<Json<TodoResponse>, TodoError> {
+    let todo = email.to_todo().map_err(|e| {
+        tracing::warn!("Rejected inbound email: {}", e);
+        e
+    })?;
+    let findings = moderator.review(Some(todo.title.as_str()), Some(todo.content.as_str()))?;
+    tracing::info!("Creating todo from inbound email: '{}'", todo.title);
+    match repository.create_todo(&todo).await {
+        Ok(created_todo) => {
+            tracing::info!(
+                "Successfully created todo with id: {} from inbound email",
+                created_todo.id
+            );
+            moderation::record(repository, created_todo.id, &findings).await;
+            let response = TodoResponse::from(ApiResponse::success(created_todo));
+            Ok(Json(response.with_warnings(&findings)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create todo from inbound email: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/inbound/email",
+    request_body(
+        content = String,
+        content_type = "multipart/form-data",
+        description = "Mailgun inbound route payload (subject, sender, body-plain, timestamp, token, signature)"
+    ),
+    responses(
+        (status = 200, description = "Todo created from the email", body = TodoResponse),
+        (status = 400, description = "Malformed payload, or a content policy rejected it", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid, expired or already used signature", body = ErrorResponse),
+        (status = 404, description = "Inbound email is not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Inbound"
+)]
+pub async fn receive_email<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<InboundEmailConfig>>,
+    Extension(seen): Extension<Arc<SeenTokens>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    request: Request,
+) -> Result<Json<TodoResponse>, TodoError> {
+    let Some(signing_key) = config.mailgun_signing_key.as_deref() else {
+        tracing::warn!("Inbound email received but MAILGUN_SIGNING_KEY is not configured");
+        return Err(TodoError::NotFound("Inbound email".to_string()));
+    };
+
+    let (fields, attachments) = read_fields(request).await?;
+    let email = InboundEmail::from_mailgun(fields, attachments);
+    if !email.verify(signing_key) {
+        tracing::warn!("Rejected inbound email with invalid signature");
+        return Err(TodoError::Unauthorized("Invalid signature".to_string()));
+    }
+    let timestamp = email.timestamp.parse().unwrap_or_default();
+    if !seen.insert(&email.token, timestamp) {
+        tracing::warn!("Rejected replayed inbound email");
+        return Err(TodoError::Unauthorized(
+            "Signature was already used".to_string(),
+        ));
+    }
+
+    let created = create_from_email(repository.as_ref(), &moderator, &email).await;
+    if created.is_err() {
+        seen.forget(&email.token);
+    }
+    created
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/inbound/email/sendgrid",
+    request_body(
+        content = String,
+        content_type = "multipart/form-data",
+        description = "SendGrid Inbound Parse payload (subject, from, text)"
+    ),
+    responses(
+        (status = 200, description = "Todo created from the email", body = TodoResponse),
+        (status = 400, description = "Malformed payload, or a content policy rejected it", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong basic auth password", body = ErrorResponse),
+        (status = 404, description = "SendGrid inbound email is not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Inbound"
+)]
+pub async fn receive_sendgrid_email<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<InboundEmailConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    request: Request,
+) -> Result<Json<TodoResponse>, TodoError> {
+    let Some(password) = config.sendgrid_password.as_deref() else {
+        tracing::warn!("Inbound email received but SENDGRID_INBOUND_PASSWORD is not configured");
+        return Err(TodoError::NotFound("SendGrid inbound email".to_string()));
+    };
+    let provided = basic_auth_password(request.headers()).unwrap_or_default();
+    if !keys_match(password, &provided) {
+        tracing::warn!("Rejected SendGrid inbound email with invalid credentials");
+        return Err(TodoError::Unauthorized("Invalid credentials".to_string()));
+    }
+
+    let (fields, attachments) = read_fields(request).await?;
+    let email = InboundEmail::from_sendgrid(fields, attachments);
+    create_from_email(repository.as_ref(), &moderator, &email).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(key: &str, timestamp: &str, token: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(timestamp.as_bytes());
+        mac.update(token.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_verify_mailgun_signature() {
+        let signature = sign("key", "1700000000", "token");
+        assert!(verify_mailgun_signature(
+            "key",
+            "1700000000",
+            "token",
+            &signature
+        ));
+        assert!(!verify_mailgun_signature(
+            "other-key",
+            "1700000000",
+            "token",
+            &signature
+        ));
+        assert!(!verify_mailgun_signature(
+            "key",
+            "1700000000",
+            "token",
+            "not-hex"
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_stale_timestamp() {
+        let timestamp = (Utc::now().timestamp() - MAX_TIMESTAMP_SKEW_SECS - 60).to_string();
+        let email = InboundEmail {
+            signature: sign("key", &timestamp, "token"),
+            timestamp,
+            token: "token".to_string(),
+            ..Default::default()
+        };
+        assert!(!email.verify("key"));
+    }
+
+    #[test]
+    fn test_subject_to_title() {
+        assert_eq!(subject_to_title("  Fwd:\tBuy\r\nmilk  "), "Fwd: Buy milk");
+        assert_eq!(subject_to_title(" \n "), EMPTY_SUBJECT_TITLE);
+        assert_eq!(subject_to_title(&"あ".repeat(300)).chars().count(), 255);
+        assert!(Todo::validate_title(&subject_to_title(&"a".repeat(300))).is_ok());
+    }
+
+    #[test]
+    fn test_seen_tokens_reject_a_replay() {
+        let seen = SeenTokens::default();
+        let now = Utc::now().timestamp();
+        assert!(seen.insert("token", now));
+        assert!(!seen.insert("token", now));
+        seen.forget("token");
+        assert!(seen.insert("token", now));
+        assert!(seen.insert("other", now));
+    }
+
+    #[test]
+    fn test_basic_auth_password() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            "Basic c2VuZGdyaWQ6czNjcjN0Onh5eg==".parse().unwrap(),
+        );
+        assert_eq!(basic_auth_password(&headers).as_deref(), Some("s3cr3t:xyz"));
+        headers.insert(AUTHORIZATION, "Bearer s3cr3t".parse().unwrap());
+        assert_eq!(basic_auth_password(&headers), None);
+    }
+
+    #[test]
+    fn test_to_todo_formats_content() {
+        let email = InboundEmail {
+            subject: "Call the bank".to_string(),
+            sender: "me@example.com".to_string(),
+            body: "Ask about the fee.\n".to_string(),
+            ..Default::default()
+        };
+        let todo = email.to_todo().unwrap();
+        assert_eq!(todo.title, "Call the bank");
+        assert_eq!(
+            todo.content,
+            "Ask about the fee.\n\n---\n\n**From:** me@example.com"
+        );
+    }
+
+    #[test]
+    fn test_to_todo_lists_attachments() {
+        let email = InboundEmail {
+            subject: "Call the bank".to_string(),
+            sender: "me@example.com".to_string(),
+            body: "x".repeat(MAX_CONTENT_CHARS),
+            attachments: vec![
+                Attachment {
+                    file_name: "statement.pdf".to_string(),
+                    content_type: "application/pdf".to_string(),
+                    size: 12_595,
+                },
+                Attachment {
+                    file_name: "note`.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: 12,
+                },
+            ],
+            ..Default::default()
+        };
+        let todo = email.to_todo().unwrap();
+        assert_eq!(todo.content.chars().count(), MAX_CONTENT_CHARS);
+        assert!(todo.content.ends_with(
+            "\n\n---\n\n**Attachments:**\n\n\
+             - `statement.pdf` (application/pdf, 12.3 KiB)\n\
+             - `note'.txt` (text/plain, 12 bytes)\n\n\
+             **From:** me@example.com"
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+//! Background jobs, run by one replica at a time.
+//!
+//! Partition upkeep and IMAP capture are meant to run once per deployment: two replicas
+//! polling the same mailbox would turn every mail into two todos. Replicas sharing a
+//! database elect a leader by holding a Postgres advisory lock on a connection of their
+//! own, and only the leader runs jobs. When the leader stops or loses that connection,
+//! Postgres releases the lock and another replica takes it within `ELECTION_INTERVAL`.
+//! Without a database (`--local`) the single process always leads.
+//!
+//! `GET /api/admin/jobs` shows whether this instance leads and how its jobs went.
+
+use crate::DatabasePool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Shared by every replica of a deployment; distinct from the migration lock
+const LEADER_LOCK_KEY: i64 = 0x6d64_746f_646f_6a62;
+const ELECTION_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Election {
+    /// A single process without a database; it always leads
+    Single,
+    /// Replicas compete for a Postgres advisory lock
+    AdvisoryLock,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct JobInfo {
+    #[schema(example = "imap-capture")]
+    pub name: String,
+    #[schema(example = 60)]
+    pub interval_seconds: u64,
+    /// Runs on this instance since it started
+    #[schema(example = 42)]
+    pub runs: u64,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Error of the last run, `null` when it succeeded
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct JobsStatus {
+    /// `HOSTNAME`, which is the pod or container name in most deployments
+    #[schema(example = "md-todo-backend-7d9f8c-x2k4p")]
+    pub instance: String,
+    pub election: Election,
+    /// Whether this instance runs the jobs
+    #[schema(example = true)]
+    pub leader: bool,
+    pub leader_since: Option<DateTime<Utc>>,
+    pub jobs: Vec<JobInfo>,
+}
+
+#[derive(Debug)]
+pub struct JobRunner {
+    instance: String,
+    election: Election,
+    leader: watch::Sender<bool>,
+    leader_since: Mutex<Option<DateTime<Utc>>>,
+    jobs: Mutex<Vec<JobInfo>>,
+}
+
+impl JobRunner {
+    fn new(election: Election, leader: bool) -> Self {
+        let instance = std::env::var("HOSTNAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("pid-{}", std::process::id()));
+        Self {
+            instance,
+            election,
+            leader: watch::Sender::new(leader),
+            leader_since: Mutex::new(leader.then(Utc::now)),
+            jobs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Leads from the start
+    pub fn single() -> Arc<Self> {
+        Arc::new(Self::new(Election::Single, true))
+    }
+
+    /// Follows until it holds the leader lock in the database of `pool`
+    pub fn elect(pool: DatabasePool) -> Arc<Self> {
+        let runner = Arc::new(Self::new(Election::AdvisoryLock, false));
+        tokio::spawn(campaign(runner.clone(), pool));
+        runner
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.leader.borrow()
+    }
+
+    fn set_leader(&self, leader: bool) {
+        if self.leader.send_replace(leader) == leader {
+            return;
+        }
+        *self.leader_since.lock().unwrap() = leader.then(Utc::now);
+        if leader {
+            tracing::info!("{} is now the job leader", self.instance);
+        } else {
+            tracing::warn!("{} is no longer the job leader", self.instance);
+        }
+    }
+
+    pub fn status(&self) -> JobsStatus {
+        JobsStatus {
+            instance: self.instance.clone(),
+            election: self.election,
+            leader: self.is_leader(),
+            leader_since: *self.leader_since.lock().unwrap(),
+            jobs: self.jobs.lock().unwrap().clone(),
+        }
+    }
+
+    /// Runs `job` every `interval` while this instance leads. A run missed while following
+    /// happens as soon as the instance leads.
+    pub fn spawn<F, Fut>(
+        self: &Arc<Self>,
+        name: &str,
+        interval: Duration,
+        mut job: F,
+    ) -> tokio::task::JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), JobError>> + Send,
+    {
+        let index = {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.push(JobInfo {
+                name: name.to_string(),
+                interval_seconds: interval.as_secs(),
+                runs: 0,
+                last_run_at: None,
+                last_error: None,
+            });
+            jobs.len() - 1
+        };
+        let runner = self.clone();
+        let mut leader = self.leader.subscribe();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                if leader.wait_for(|leader| *leader).await.is_err() {
+                    return;
+                }
+                interval.tick().await;
+                if !runner.is_leader() {
+                    continue;
+                }
+                let result = job().await;
+                let mut jobs = runner.jobs.lock().unwrap();
+                let info = &mut jobs[index];
+                if let Err(e) = &result {
+                    tracing::error!("Job {} failed: {}", info.name, e);
+                }
+                info.runs += 1;
+                info.last_run_at = Some(Utc::now());
+                info.last_error = result.err().map(|e| e.to_string());
+            }
+        })
+    }
+}
+
+/// Holds a connection of its own, since the session that takes an advisory lock owns it
+async fn campaign(runner: Arc<JobRunner>, pool: DatabasePool) {
+    let mut connection = None;
+    let mut interval = tokio::time::interval(ELECTION_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if connection.is_none() {
+            match pool.acquire().await {
+                Ok(pooled) => connection = Some(pooled.detach()),
+                Err(e) => {
+                    tracing::warn!("Cannot connect for the job leader election: {}", e);
+                    runner.set_leader(false);
+                    continue;
+                }
+            }
+        }
+        let Some(conn) = connection.as_mut() else {
+            continue;
+        };
+        // The leader only checks its session is alive; locking again would stack the lock
+        let held: Result<bool, sqlx::Error> = if runner.is_leader() {
+            sqlx::query_scalar("SELECT TRUE")
+                .fetch_one(&mut *conn)
+                .await
+        } else {
+            sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+                .bind(LEADER_LOCK_KEY)
+                .fetch_one(&mut *conn)
+                .await
+        };
+        match held {
+            Ok(held) => runner.set_leader(held),
+            Err(e) => {
+                // The lock goes with the session, if it has not already
+                tracing::warn!("Lost the job leader election connection: {}", e);
+                connection = None;
+                runner.set_leader(false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_jobs_run_only_while_leading() {
+        let runner = Arc::new(JobRunner::new(Election::AdvisoryLock, false));
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        runner.spawn("count", Duration::from_secs(10), move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) == 1 {
+                    return Err("second run fails".into());
+                }
+                Ok(())
+            }
+        });
+
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        runner.set_leader(true);
+        tokio::time::sleep(Duration::from_secs(15)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let status = runner.status();
+        assert!(status.leader && status.leader_since.is_some());
+        assert_eq!(status.jobs[0].runs, 2);
+        assert_eq!(
+            status.jobs[0].last_error.as_deref(),
+            Some("second run fails")
+        );
+
+        runner.set_leader(false);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(runner.status().leader_since, None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Hierarchical labels such as `work/clientA/urgent`.
+//!
+//! Labels are stored as materialized paths in the `todos.labels` array. Filtering by a
+//! label also matches its descendants, so `?label=work` finds `work/clientA/urgent`.
+
+use crate::unicode;
+
+pub const SEPARATOR: char = '/';
+pub const MAX_LABELS: usize = 20;
+pub const MAX_LABEL_LENGTH: usize = 100;
+
+/// Canonical form of a label path: NFC, segments trimmed, no empty segments
+pub fn normalize(label: &str) -> Result<String, String> {
+    let composed = unicode::nfc(label);
+    let segments: Vec<&str> = composed.split(SEPARATOR).map(str::trim).collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!("Invalid label: {:?}", label));
+    }
+    if segments
+        .iter()
+        .any(|segment| segment.chars().any(char::is_control))
+    {
+        return Err("Labels cannot contain control characters".to_string());
+    }
+    let normalized = segments.join("/");
+    if normalized.len() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "Labels cannot exceed {} characters",
+            MAX_LABEL_LENGTH
+        ));
+    }
+    Ok(normalized)
+}
+
+/// Normalizes and de-duplicates a todo's labels, keeping their order. Labels that only
+/// differ by compatibility variants (`ﬁ` vs `fi`) count as duplicates.
+pub fn normalize_all(labels: &[String]) -> Result<Vec<String>, String> {
+    if labels.len() > MAX_LABELS {
+        return Err(format!(
+            "A todo cannot have more than {} labels",
+            MAX_LABELS
+        ));
+    }
+    let mut normalized: Vec<String> = Vec::with_capacity(labels.len());
+    for label in labels {
+        let label = normalize(label)?;
+        if !normalized
+            .iter()
+            .any(|seen| unicode::same_folded(seen, &label))
+        {
+            normalized.push(label);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Whether `label` is `prefix` itself or one of its descendants
+pub fn is_within(label: &str, prefix: &str) -> bool {
+    unicode::fold(label)
+        .strip_prefix(&unicode::fold(prefix))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(SEPARATOR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(" work / clientA/urgent ").unwrap(),
+            "work/clientA/urgent"
+        );
+        assert!(normalize("work//urgent").is_err());
+        assert!(normalize("/work").is_err());
+        assert!(normalize("").is_err());
+        assert!(normalize(&"a".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn test_normalize_all_dedups_unicode_variants() {
+        let labels = vec![
+            "Caf\u{e9}".to_string(),
+            "Cafe\u{301}".to_string(),
+            "\u{fb01}le".to_string(),
+            "file".to_string(),
+        ];
+        assert_eq!(
+            normalize_all(&labels).unwrap(),
+            vec!["Caf\u{e9}", "\u{fb01}le"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_all_dedups() {
+        let labels = vec!["home".to_string(), " home ".to_string(), "work".to_string()];
+        assert_eq!(normalize_all(&labels).unwrap(), vec!["home", "work"]);
+        assert!(normalize_all(&vec!["x".to_string(); 21]).is_err());
+    }
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("work/clientA/urgent", "work"));
+        assert!(is_within("work/clientA", "work/clientA"));
+        assert!(!is_within("workshop", "work"));
+        assert!(!is_within("work", "work/clientA"));
+    }
+}
@@ -0,0 +1,91 @@
+//! Language of a todo, which picks the text search configuration it is indexed with.
+//!
+//! Postgres detects it whenever a todo's title or content is written, with
+//! `todo_detect_language` from migration 022, and stores it in `todos.language`.
+//! `detect` applies the same rules for repositories without Postgres: any kana makes a
+//! todo Japanese, and letters that are at least 90% ASCII make it English. Content stored
+//! compressed is not looked at, like in search.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Share of ASCII letters, in tenths, from which text counts as English
+const MIN_ASCII_TENTHS: usize = 9;
+
+/// Detected language; the same as the `todo_language` enum in the database
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "todo_language", rename_all = "snake_case")]
+pub enum Language {
+    /// Indexed with the `english` configuration, so searches match other word forms
+    English,
+    /// Indexed with `simple` unless `database/optional/japanese_search.sql` set up a
+    /// configuration that splits Japanese into words
+    Japanese,
+    /// Anything else, and text without letters; indexed with `simple`
+    #[default]
+    Other,
+}
+
+fn is_kana(c: char) -> bool {
+    ('\u{3040}'..='\u{30ff}').contains(&c)
+}
+
+/// Kana, CJK ideographs or Hangul, scripts written without spaces between words
+fn is_cjk(c: char) -> bool {
+    is_kana(c)
+        || ('\u{3400}'..='\u{4dbf}').contains(&c)
+        || ('\u{4e00}'..='\u{9fff}').contains(&c)
+        || ('\u{f900}'..='\u{faff}').contains(&c)
+        || ('\u{ac00}'..='\u{d7af}').contains(&c)
+        || ('\u{ff66}'..='\u{ff9f}').contains(&c)
+}
+
+/// Whether `text` has any Chinese, Japanese or Korean characters
+pub fn has_cjk(text: &str) -> bool {
+    text.chars().any(is_cjk)
+}
+
+/// Language of `text`, usually a todo's title and content joined by a space
+pub fn detect(text: &str) -> Language {
+    if text.chars().any(is_kana) {
+        return Language::Japanese;
+    }
+    let (letters, ascii) = text
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .fold((0, 0), |(letters, ascii), c| {
+            (letters + 1, ascii + usize::from(c.is_ascii()))
+        });
+    if letters > 0 && ascii * 10 >= letters * MIN_ASCII_TENTHS {
+        Language::English
+    } else {
+        Language::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("Buy milk and bread"), Language::English);
+        assert_eq!(detect("Review the café menu draft"), Language::English);
+        assert_eq!(detect("牛乳を買う"), Language::Japanese);
+        assert_eq!(detect("Fix ログイン on Safari"), Language::Japanese);
+        assert_eq!(detect("Überprüfung der Öffnungszeiten"), Language::Other);
+        assert_eq!(detect("買牛奶"), Language::Other);
+        assert_eq!(detect("2024-01-01 🎉"), Language::Other);
+    }
+
+    #[test]
+    fn test_has_cjk() {
+        assert!(has_cjk("牛乳を買う"));
+        assert!(has_cjk("買牛奶"));
+        assert!(has_cjk("회의 준비"));
+        assert!(!has_cjk("Review the café menu"));
+    }
+}
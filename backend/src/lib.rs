@@ -1,54 +1,277 @@
 use async_trait::async_trait;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
-    routing::{delete, get, patch, post},
-    Router,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, patch, post, put},
+    Extension, Router,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 #[allow(unused_imports)] // json! macro is used in schema examples throughout the file
 use serde_json::json;
-use sqlx::{Pool, Postgres};
+use sqlx::{Pool, Postgres, QueryBuilder};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use utoipa::{OpenApi, ToSchema};
 use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub mod admin;
+pub mod auth;
+pub mod autocomplete;
+pub mod bulk;
+pub mod client;
+pub mod clip;
+pub mod collab;
+pub mod compat;
+pub mod compression;
+pub mod deprecation;
+pub mod domain;
+pub mod embed;
+pub mod emoji;
+pub mod encryption;
+pub mod errors;
+pub mod events;
+pub mod fanout;
+pub mod faults;
+pub mod feed;
+pub mod frontend;
+pub mod health;
+pub mod html;
+pub mod imap;
+pub mod imports;
+pub mod inbound;
+pub mod jobs;
+pub mod labels;
+pub mod language;
+pub mod lists;
+pub mod logging;
+pub mod memory;
+pub mod metadata;
+pub mod metrics;
+pub mod migrations;
+pub mod moderation;
+pub mod notify;
+pub mod partitions;
+pub mod pdf;
+pub mod plan;
+pub mod policy;
+pub mod preconditions;
+pub mod queue;
+pub mod relations;
+pub mod resources;
+pub mod search;
+pub mod secrets;
+pub mod selftest;
+pub mod settings;
+pub mod share;
+pub mod shortlink;
+pub mod snapshot;
+pub mod split;
+pub mod sqltrace;
+pub mod stale;
+pub mod stats;
+pub mod streaks;
+pub mod subtasks;
+pub mod tags;
+pub mod tui;
+pub mod unicode;
+pub mod webhooks;
+
+use admin::AdminConfig;
+use auth::{AuthConfig, BearerTokens, User};
+use autocomplete::{AutocompleteQuery, TitleCache, TitleSuggestion};
+use clip::ClipConfig;
+use collab::CollabHub;
+use compression::ContentCompression;
+use deprecation::DeprecatedRoutes;
+use domain::{TodoContent, TodoId, TodoTitle};
+use embed::EmbedConfig;
+use emoji::EmojiConfig;
+use encryption::{EncryptedTodo, EncryptedUpdate, EncryptionConfig};
+use errors::{
+    ErrorCode, ErrorFormat, FieldError, FieldErrorCode, LengthLimit, ProblemResponses,
+    ValidationErrors,
+};
+use feed::ChangeFeed;
+use health::{DatabaseCheck, DependencyChecks, DependencyStatus};
+use imports::{ImportItem, ImportJob};
+use inbound::{InboundEmailConfig, SeenTokens};
+use jobs::JobRunner;
+use language::Language;
+use lists::{ListUpdate, TodoList, UpdateTodoListRequest};
+use metadata::MetadataField;
+use metrics::RepositoryMetrics;
+use migrations::{MigrationStatus, Migrator};
+use moderation::{Finding, ModerationFlag, Moderator};
+use policy::ValidationPolicy;
+use preconditions::{etag_headers, PreconditionConfig};
+use queue::TaskQueue;
+use relations::{RelationSummary, TodoRelation};
+use resources::MaxBodyBytes;
+use search::{CjkSearch, SearchHit, SearchQuery};
+use share::{ShareAccess, ShareLink, ShareOutcome};
+use split::TodoSplit;
+use sqltrace::Redacted;
+use stale::{StaleConfig, StaleFilter};
+use stats::{AgingReport, WorkloadTotals};
+use streaks::CompletionDay;
+use subtasks::{SubtaskConfig, SubtaskMerge};
+use tags::{Tag, TagRename};
+use webhooks::{Webhook, WebhookDelivery};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 #[schema(example = json!({
     "id": "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d",
     "title": "Sample Todo",
     "content": "This is a **markdown** todo item",
     "completed": false,
+    "version": 1,
+    "metadata": {"sprint": 42},
+    "labels": ["work/clientA/urgent"],
+    "tags": ["work"],
+    "estimate_minutes": 90,
+    "priority": "high",
+    "list_id": null,
+    "archived": false,
+    "stale": false,
     "created_at": "2024-01-01T00:00:00Z",
     "updated_at": "2024-01-01T00:00:00Z"
 }))]
 pub struct Todo {
-    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
-    pub id: Uuid,
-    #[schema(example = "Complete project documentation")]
-    pub title: String,
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: TodoId,
+    #[schema(value_type = String, example = "Complete project documentation")]
+    pub title: TodoTitle,
     #[schema(
+        value_type = String,
         example = "Write comprehensive documentation including **API specs** and usage examples"
     )]
-    pub content: String,
+    pub content: TodoContent,
     #[schema(example = false)]
     pub completed: bool,
+    /// Incremented on every write; used to detect concurrent edits
+    #[schema(example = 1)]
+    pub version: i32,
+    /// Custom fields, validated against the metadata field definitions
+    #[schema(value_type = Object, example = json!({"sprint": 42}))]
+    pub metadata: serde_json::Value,
+    /// Hierarchical label paths; filtering by a parent label includes its children
+    #[schema(example = json!(["work/clientA/urgent"]))]
+    pub labels: Vec<String>,
+    /// Names of the attached tags, sorted
+    #[sqlx(default)]
+    #[serde(default)]
+    #[schema(example = json!(["work"]))]
+    pub tags: Vec<String>,
+    /// Expected effort in minutes, if estimated
+    #[schema(example = 90)]
+    pub estimate_minutes: Option<i32>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// List (project) the todo belongs to, if any
+    #[serde(default)]
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8e")]
+    pub list_id: Option<Uuid>,
+    /// Hidden from lists unless `include_archived=true`; independent of `completed`
+    #[serde(default)]
+    #[schema(example = false)]
+    pub archived: bool,
+    /// Account the todo belongs to; left out for todos shared by every account
+    #[serde(default, skip_serializing_if = "Uuid::is_nil")]
+    #[schema(value_type = Option<Uuid>, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8f")]
+    pub owner_id: Uuid,
+    /// Open and untouched for `STALE_AFTER_DAYS`; computed when the todo is read
+    #[sqlx(skip)]
+    #[serde(default)]
+    #[schema(example = false)]
+    pub stale: bool,
+    /// Links to other todos; only `GET /api/todos/:id` fills them in
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relations: Option<Vec<RelationSummary>>,
     #[schema(example = "2024-01-01T00:00:00Z")]
     pub created_at: DateTime<Utc>,
     #[schema(example = "2024-01-01T00:00:00Z")]
     pub updated_at: DateTime<Utc>,
 }
 
+/// How urgent a todo is; ordered from `low` to `urgent`, the same as the `todo_priority`
+/// enum in the database
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ToSchema,
+    sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "todo_priority", rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "low" => Ok(Priority::Low),
+            "medium" => Ok(Priority::Medium),
+            "high" => Ok(Priority::High),
+            "urgent" => Ok(Priority::Urgent),
+            other => Err(format!(
+                "priority must be low, medium, high or urgent, got {:?}",
+                other
+            )),
+        }
+    }
+
+    /// Reads a comma-separated list such as `high,urgent`
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        let mut priorities = value
+            .split(',')
+            .map(|part| Self::parse(part.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        priorities.sort();
+        priorities.dedup();
+        Ok(priorities)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 #[schema(example = json!({
     "title": "New Todo Item",
     "content": "Description with **markdown** support"
 }))]
 pub struct CreateTodoRequest {
+    /// Id chosen by the client, a UUIDv7, so an offline client can refer to the todo
+    /// before it is stored; generated when omitted
+    #[serde(default)]
+    #[schema(value_type = Option<Uuid>, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Option<TodoId>,
     #[schema(
         example = "Complete project documentation",
         min_length = 1,
@@ -60,9 +283,46 @@ pub struct CreateTodoRequest {
         max_length = 10000
     )]
     pub content: String,
+    #[serde(default)]
+    #[schema(value_type = Option<Object>, example = json!({"sprint": 42}))]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    #[schema(example = json!(["work/clientA/urgent"]))]
+    pub labels: Option<Vec<String>>,
+    /// Tag names; tags that do not exist yet are created
+    #[serde(default)]
+    #[schema(example = json!(["work"]))]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    #[schema(example = 90, minimum = 0)]
+    pub estimate_minutes: Option<i32>,
+    /// Defaults to `medium`
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// List to put the todo in
+    #[serde(default)]
+    pub list_id: Option<Uuid>,
+}
+
+/// The whole of a todo, for `PUT /api/todos/:id`; what is left out takes its default
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[schema(example = json!({
+    "title": "Replaced Todo Item",
+    "content": "Written by id from a **sync** client",
+    "completed": true
+}))]
+pub struct ReplaceTodoRequest {
+    #[serde(flatten)]
+    pub todo: CreateTodoRequest,
+    #[serde(default)]
+    #[schema(example = false)]
+    pub completed: bool,
+    #[serde(default)]
+    #[schema(example = false)]
+    pub archived: bool,
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
 #[schema(example = json!({
     "title": "Updated Todo Title",
     "content": "Updated content with **markdown**",
@@ -75,6 +335,232 @@ pub struct UpdateTodoRequest {
     pub content: Option<String>,
     #[schema(example = true)]
     pub completed: Option<bool>,
+    /// Replaces the whole metadata object
+    #[serde(default)]
+    #[schema(value_type = Option<Object>, example = json!({"sprint": 43}))]
+    pub metadata: Option<serde_json::Value>,
+    /// Replaces all labels
+    #[serde(default)]
+    #[schema(example = json!(["work/clientA"]))]
+    pub labels: Option<Vec<String>>,
+    /// Replaces all tags; tags that do not exist yet are created
+    #[serde(default)]
+    #[schema(example = json!(["work"]))]
+    pub tags: Option<Vec<String>>,
+    /// Tags to attach, after `tags`
+    #[serde(default)]
+    #[schema(example = json!(["urgent"]))]
+    pub add_tags: Option<Vec<String>>,
+    /// Tags to detach, after `tags`
+    #[serde(default)]
+    #[schema(example = json!(["someday"]))]
+    pub remove_tags: Option<Vec<String>>,
+    #[serde(default)]
+    #[schema(example = 120, minimum = 0)]
+    pub estimate_minutes: Option<i32>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Moves the todo to another list; `null` takes it out of its list
+    #[serde(
+        default,
+        deserialize_with = "lists::deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<Uuid>, nullable)]
+    pub list_id: Option<Option<Uuid>>,
+    /// Same as `POST /api/todos/:id/archive` or `/unarchive`
+    #[serde(default)]
+    #[schema(example = false)]
+    pub archived: Option<bool>,
+}
+
+/// Criteria for `GET /api/todos`; an empty filter lists every todo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TodoFilter {
+    /// `(key, value)` pairs compared against the text form of metadata values
+    pub metadata: Vec<(String, String)>,
+    /// Label path; matches the label itself and every label below it
+    pub label: Option<String>,
+    /// Normalized tag name
+    pub tag: Option<String>,
+    /// Matches todos of any of these priorities; empty matches all
+    pub priority: Vec<Priority>,
+    pub list_id: Option<Uuid>,
+    /// Archived todos are left out unless set
+    pub include_archived: bool,
+    pub stale: Option<StaleFilter>,
+    pub sort: TodoSort,
+    pub order: SortOrder,
+}
+
+impl TodoFilter {
+    /// True when no criterion narrows the list; the order and `include_archived` do not
+    /// count
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+            && self.label.is_none()
+            && self.tag.is_none()
+            && self.priority.is_empty()
+            && self.list_id.is_none()
+            && self.stale.is_none()
+    }
+}
+
+/// What `GET /api/todos` is ordered by; ties are broken by id in the same direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSort {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Priority,
+}
+
+impl TodoSort {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "created_at" => Ok(TodoSort::CreatedAt),
+            "updated_at" => Ok(TodoSort::UpdatedAt),
+            "title" => Ok(TodoSort::Title),
+            "priority" => Ok(TodoSort::Priority),
+            other => Err(format!(
+                "sort must be created_at, updated_at, title or priority, got {:?}",
+                other
+            )),
+        }
+    }
+
+    /// Newest first for timestamps, A to Z for titles, most urgent first for priorities
+    pub fn default_order(self) -> SortOrder {
+        match self {
+            TodoSort::CreatedAt | TodoSort::UpdatedAt | TodoSort::Priority => SortOrder::Desc,
+            TodoSort::Title => SortOrder::Asc,
+        }
+    }
+
+    /// Reads `sort` and `order`; without `order` the sort's default direction applies
+    pub fn from_query(params: &HashMap<String, String>) -> Result<(Self, SortOrder), String> {
+        let sort = params
+            .get("sort")
+            .map(|sort| Self::parse(sort))
+            .transpose()?
+            .unwrap_or_default();
+        let order = params
+            .get("order")
+            .map(|order| SortOrder::parse(order))
+            .transpose()?
+            .unwrap_or(sort.default_order());
+        Ok((sort, order))
+    }
+
+    /// Orders todos the way the database lists them
+    pub fn compare(self, order: SortOrder, a: &Todo, b: &Todo) -> std::cmp::Ordering {
+        let ordering = match self {
+            TodoSort::CreatedAt => a.created_at.cmp(&b.created_at),
+            TodoSort::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            TodoSort::Title => a.title.cmp(&b.title),
+            TodoSort::Priority => a.priority.cmp(&b.priority),
+        }
+        .then(a.id.cmp(&b.id));
+        match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            other => Err(format!("order must be asc or desc, got {:?}", other)),
+        }
+    }
+}
+
+/// One keyset page of `GET /api/todos`: up to `limit` todos listed after `cursor`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+    /// Id of the last todo of the previous page; `None` for the first page
+    pub cursor: Option<TodoId>,
+    pub limit: i64,
+}
+
+impl PageRequest {
+    pub const DEFAULT_LIMIT: i64 = 50;
+    pub const MAX_LIMIT: i64 = 200;
+
+    /// Reads `cursor` and `limit`; `None` when neither is given
+    pub fn from_query(params: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        let cursor = params
+            .get("cursor")
+            .map(|cursor| {
+                cursor
+                    .parse::<TodoId>()
+                    .map_err(|_| format!("Invalid cursor: {:?}", cursor))
+            })
+            .transpose()?;
+        let limit = params
+            .get("limit")
+            .map(|limit| match limit.parse::<i64>() {
+                Ok(limit) if (1..=Self::MAX_LIMIT).contains(&limit) => Ok(limit),
+                _ => Err(format!(
+                    "limit must be between 1 and {}, got {:?}",
+                    Self::MAX_LIMIT,
+                    limit
+                )),
+            })
+            .transpose()?;
+        if cursor.is_none() && limit.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            cursor,
+            limit: limit.unwrap_or(Self::DEFAULT_LIMIT),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[schema(example = json!({
+    "content": "Draft saved by the editor",
+    "base_version": 3,
+    "base_content": "Draft"
+}))]
+pub struct UpdateTodoContentRequest {
+    #[schema(example = "Draft saved by the editor", max_length = 10000)]
+    pub content: String,
+    /// Version of the todo the client's edit is based on
+    #[schema(example = 3)]
+    pub base_version: i32,
+    /// Content at `base_version`; when present, stale edits are three-way merged
+    #[schema(example = "Draft")]
+    pub base_content: Option<String>,
+}
+
+/// Outcome of a version-checked content write
+#[derive(Debug)]
+pub enum ContentUpdate {
+    Updated(Todo),
+    Conflict(Todo),
+    NotFound,
+}
+
+/// Outcome of writing a todo by id
+#[derive(Debug)]
+pub enum TodoUpsert {
+    Created(Todo),
+    Replaced(Todo),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,6 +568,11 @@ pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// Invalid fields of a rejected create or update
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -92,6 +583,9 @@ pub struct ApiResponse<T> {
         "title": "Sample Todo",
         "content": "This is a **markdown** todo item",
         "completed": false,
+        "version": 1,
+        "metadata": {},
+        "labels": [],
         "created_at": "2024-01-01T00:00:00Z",
         "updated_at": "2024-01-01T00:00:00Z"
     },
@@ -103,6 +597,13 @@ pub struct TodoResponse {
     pub data: Option<Todo>,
     #[schema(example = "Error message if any")]
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// What content policies flagged in the title or content written; the write went
+    /// through
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(example = json!(["Content contains what looks like an AWS access key (AKIA…MPLE)"]))]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -114,6 +615,9 @@ pub struct TodoResponse {
             "title": "Sample Todo",
             "content": "This is a **markdown** todo item",
             "completed": false,
+            "version": 1,
+            "metadata": {},
+            "labels": [],
             "created_at": "2024-01-01T00:00:00Z",
             "updated_at": "2024-01-01T00:00:00Z"
         }
@@ -126,6 +630,12 @@ pub struct TodoListResponse {
     pub data: Option<Vec<Todo>>,
     #[schema(example = "Error message if any")]
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// With `limit`, the `cursor` for the next page; absent on the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Uuid>)]
+    pub next_cursor: Option<TodoId>,
 }
 
 impl<T> ApiResponse<T> {
@@ -134,14 +644,18 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            code: None,
+            fields: Vec::new(),
         }
     }
 
-    pub fn error(message: String) -> Self {
+    pub fn error(code: ErrorCode, message: String) -> Self {
         Self {
             success: false,
             data: None,
             error: Some(message),
+            code: Some(code),
+            fields: Vec::new(),
         }
     }
 }
@@ -152,16 +666,27 @@ impl From<ApiResponse<Todo>> for TodoResponse {
             success: response.success,
             data: response.data,
             error: response.error,
+            code: response.code,
+            warnings: Vec::new(),
         }
     }
 }
 
+impl TodoResponse {
+    pub fn with_warnings(mut self, findings: &[Finding]) -> Self {
+        self.warnings = moderation::warnings(findings);
+        self
+    }
+}
+
 impl From<ApiResponse<Vec<Todo>>> for TodoListResponse {
     fn from(response: ApiResponse<Vec<Todo>>) -> Self {
         Self {
             success: response.success,
             data: response.data,
             error: response.error,
+            code: response.code,
+            next_cursor: None,
         }
     }
 }
@@ -170,18 +695,243 @@ impl From<ApiResponse<Vec<Todo>>> for TodoListResponse {
 #[openapi(
     paths(
         health_check,
+        readiness_check,
+        metrics::get_metrics,
         get_todos,
+        search::search_todos,
+        autocomplete::autocomplete_titles,
+        feed::stream_events,
         create_todo,
+        bulk::create_todos_bulk,
+        bulk::update_todos_bulk,
+        bulk::delete_todos_bulk,
+        bulk::complete_all_todos,
+        bulk::delete_completed_todos,
+        imports::start_import,
+        imports::get_job,
+        imports::cancel_job,
         get_todo,
         update_todo,
-        delete_todo
+        put_todo,
+        update_todo_content,
+        split::split_todo,
+        split::extract_section,
+        archive_todo,
+        unarchive_todo,
+        delete_todo,
+        collab::collab_socket,
+        collab::get_presence,
+        inbound::receive_email,
+        inbound::receive_sendgrid_email,
+        clip::clip_page,
+        shortlink::get_short_link,
+        shortlink::follow_short_link,
+        relations::list_relations,
+        relations::create_relation,
+        relations::delete_relation,
+        share::create_share_link,
+        share::list_share_links,
+        share::delete_share_link,
+        share::get_access_log,
+        share::open_share_link,
+        embed::get_embed,
+        embed::get_oembed,
+        metadata::list_fields,
+        metadata::put_field,
+        metadata::delete_field,
+        policy::get_policy,
+        policy::put_policy,
+        moderation::list_flags,
+        moderation::dismiss_flag,
+        webhooks::list_webhooks,
+        webhooks::create_webhook,
+        webhooks::get_webhook,
+        webhooks::update_webhook,
+        webhooks::delete_webhook,
+        webhooks::list_deliveries,
+        tags::list_tags,
+        tags::autocomplete_tags,
+        tags::create_tag,
+        tags::get_tag,
+        tags::rename_tag,
+        tags::delete_tag,
+        lists::list_lists,
+        lists::create_list,
+        lists::get_list,
+        lists::update_list,
+        lists::delete_list,
+        lists::get_list_todos,
+        encryption::list_encrypted_todos,
+        encryption::create_encrypted_todo,
+        encryption::get_encrypted_todo,
+        encryption::replace_encrypted_todo,
+        encryption::delete_encrypted_todo,
+        pdf::get_todo_pdf,
+        pdf::export_pdf,
+        html::export_html,
+        stats::get_aging,
+        stats::get_workload,
+        streaks::get_streaks,
+        plan::get_plan,
+        plan::set_plan,
+        admin::get_config,
+        admin::put_config,
+        admin::reload_config,
+        admin::get_log_level,
+        admin::put_log_level,
+        admin::get_jobs,
+        admin::get_queue,
+        admin::retry_task,
+        admin::discard_task,
+        auth::register,
+        auth::login,
+        auth::issue_ticket
     ),
     components(
-        schemas(Todo, CreateTodoRequest, UpdateTodoRequest, TodoResponse, TodoListResponse)
+        schemas(
+            Todo,
+            CreateTodoRequest,
+            UpdateTodoRequest,
+            ReplaceTodoRequest,
+            UpdateTodoContentRequest,
+            TodoResponse,
+            TodoListResponse,
+            TodoSort,
+            SortOrder,
+            Priority,
+            Readiness,
+            health::DependencyStatus,
+            migrations::MigrationKind,
+            migrations::MigrationStatus,
+            migrations::PendingMigration,
+            bulk::BulkItemResult,
+            bulk::BulkCreateResponse,
+            bulk::BulkUpdateRequest,
+            bulk::BulkDeleteRequest,
+            bulk::BulkFailure,
+            bulk::BulkChangeResult,
+            bulk::BulkChangeResponse,
+            bulk::AffectedTodos,
+            bulk::AffectedTodosResponse,
+            imports::ImportStatus,
+            imports::ImportItemError,
+            imports::ImportJob,
+            imports::ImportJobResponse,
+            search::SearchHit,
+            language::Language,
+            search::SearchResponse,
+            autocomplete::TitleSuggestion,
+            autocomplete::AutocompleteResponse,
+            collab::Cursor,
+            collab::Peer,
+            collab::Presence,
+            collab::PresenceResponse,
+            clip::ClipRequest,
+            shortlink::ShortLink,
+            shortlink::ShortLinkResponse,
+            share::CreateShareLinkRequest,
+            share::ShareLinkInfo,
+            share::ShareLinkResponse,
+            share::ShareLinkListResponse,
+            share::ShareOutcome,
+            share::ShareAccess,
+            share::ShareAccessLogResponse,
+            embed::OEmbed,
+            metadata::FieldType,
+            metadata::MetadataField,
+            metadata::MetadataFieldRequest,
+            metadata::MetadataFieldResponse,
+            metadata::MetadataFieldListResponse,
+            policy::ValidationPolicy,
+            policy::ValidationPolicyResponse,
+            moderation::ModerationFlag,
+            moderation::ModerationFlagListResponse,
+            webhooks::CreateWebhookRequest,
+            webhooks::UpdateWebhookRequest,
+            webhooks::WebhookInfo,
+            webhooks::WebhookDelivery,
+            webhooks::WebhookResponse,
+            webhooks::WebhookListResponse,
+            webhooks::WebhookDeliveryLogResponse,
+            relations::RelationKind,
+            relations::RelationDirection,
+            relations::TodoRelation,
+            relations::RelationSummary,
+            relations::CreateRelationRequest,
+            relations::RelationResponse,
+            relations::RelationListResponse,
+            split::SplitTodoRequest,
+            split::ExtractSectionRequest,
+            split::TodoSplit,
+            split::TodoSplitResponse,
+            tags::Tag,
+            tags::TagRequest,
+            tags::RenameTagRequest,
+            tags::TagResponse,
+            tags::TagListResponse,
+            lists::TodoList,
+            lists::CreateTodoListRequest,
+            lists::UpdateTodoListRequest,
+            lists::ListResponse,
+            lists::ListsResponse,
+            encryption::EncryptedTodo,
+            encryption::EncryptedTodoRequest,
+            encryption::EncryptedTodoResponse,
+            encryption::EncryptedTodoListResponse,
+            stats::AgingBucket,
+            stats::AgingReport,
+            stats::AgingReportResponse,
+            stats::Workload,
+            stats::WorkloadResponse,
+            streaks::Badge,
+            streaks::Streaks,
+            streaks::StreaksResponse,
+            plan::PlanRequest,
+            plan::Plan,
+            plan::PlanResponse,
+            errors::ErrorCode,
+            errors::ErrorResponse,
+            errors::FieldError,
+            errors::FieldErrorCode,
+            errors::LengthLimit,
+            errors::LengthUnit,
+            errors::ProblemDetails,
+            admin::RuntimeConfig,
+            admin::RuntimeConfigUpdate,
+            admin::RuntimeConfigResponse,
+            admin::LogLevel,
+            admin::LogLevelResponse,
+            admin::JobsResponse,
+            jobs::Election,
+            jobs::JobInfo,
+            jobs::JobsStatus,
+            admin::QueueResponse,
+            admin::TaskResponse,
+            queue::QueueStatus,
+            queue::Task,
+            auth::User,
+            auth::RegisterRequest,
+            auth::LoginRequest,
+            auth::AccessToken,
+            auth::UserResponse,
+            auth::AccessTokenResponse,
+            auth::StreamTicket,
+            auth::StreamTicketResponse
+        )
     ),
     tags(
         (name = "Health", description = "Health check endpoints"),
-        (name = "Todos", description = "Todo management API")
+        (name = "Todos", description = "Todo management API, behind bearer tokens when JWT_SECRET is set"),
+        (name = "Auth", description = "Accounts and access tokens, enabled by JWT_SECRET"),
+        (name = "Imports", description = "Imports run in the background"),
+        (name = "Inbound", description = "Create todos from external sources"),
+        (name = "Metadata", description = "Custom field definitions"),
+        (name = "Tags", description = "Tags shared between todos"),
+        (name = "Lists", description = "Projects grouping todos"),
+        (name = "Encrypted todos", description = "Todos encrypted by clients, enabled by E2EE_MODE"),
+        (name = "Stats", description = "Reports over todos"),
+        (name = "Webhooks", description = "Signed HTTP callbacks for todo events, managed with ADMIN_TOKEN"),
+        (name = "Admin", description = "Operator endpoints, enabled by ADMIN_TOKEN")
     ),
     info(
         title = "MD-Todo API",
@@ -196,6 +946,7 @@ impl From<ApiResponse<Vec<Todo>>> for TodoListResponse {
             url = "https://opensource.org/licenses/MIT"
         )
     ),
+    modifiers(&DeprecatedRoutes, &ProblemResponses, &BearerTokens),
     servers(
         (url = "http://localhost:8000", description = "Local development server"),
         (url = "https://api.md-todo.com", description = "Production server")
@@ -209,197 +960,3751 @@ pub async fn create_database_pool(database_url: &str) -> Result<DatabasePool, sq
     sqlx::PgPool::connect(database_url).await
 }
 
-pub type TodoError = Box<dyn std::error::Error + Send + Sync>;
+/// A pool holding at most `max_connections` open connections
+pub async fn create_database_pool_with_max_connections(
+    database_url: &str,
+    max_connections: u32,
+) -> Result<DatabasePool, sqlx::Error> {
+    sqlx::postgres::PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(database_url)
+        .await
+}
+
+/// Why a repository call or a handler failed; the variant decides the HTTP status
+#[derive(Debug, thiserror::Error)]
+pub enum TodoError {
+    /// The thing asked for does not exist
+    #[error("{0} not found")]
+    NotFound(String),
+    /// The request breaks a rule, such as an empty title
+    #[error("{0}")]
+    Validation(String),
+    /// The request breaks rules on one or more of its fields
+    #[error("{0}")]
+    InvalidFields(ValidationErrors),
+    /// The request clashes with stored state, such as an id that is already taken
+    #[error("{0}")]
+    Conflict(String),
+    /// The todo is no longer at the version the request expected
+    #[error("{0}")]
+    PreconditionFailed(String),
+    /// The request has to say which version it expects
+    #[error("{0}")]
+    PreconditionRequired(String),
+    /// Missing or wrong credentials
+    #[error("{0}")]
+    Unauthorized(String),
+    /// A valid request for something the server does not offer
+    #[error("{0}")]
+    NotImplemented(String),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    /// A broken invariant, such as stored content that fails to decompress
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl TodoError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            TodoError::NotFound(_) => StatusCode::NOT_FOUND,
+            TodoError::Validation(_) | TodoError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            TodoError::Conflict(_) => StatusCode::CONFLICT,
+            TodoError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            TodoError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            TodoError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            TodoError::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+            TodoError::Database(_) | TodoError::Serialization(_) | TodoError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// What the client is told; server-side failures are not described, since they
+    /// can carry SQL or internal details
+    pub fn client_message(&self) -> String {
+        match self {
+            TodoError::Database(_) | TodoError::Serialization(_) | TodoError::Internal(_) => {
+                let status = self.status();
+                status.canonical_reason().unwrap_or_default().to_string()
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TodoError::NotFound(_) => ErrorCode::NotFound,
+            TodoError::Validation(_) | TodoError::InvalidFields(_) => ErrorCode::ValidationFailed,
+            TodoError::Conflict(_) => ErrorCode::Conflict,
+            TodoError::PreconditionFailed(_) => ErrorCode::PreconditionFailed,
+            TodoError::PreconditionRequired(_) => ErrorCode::PreconditionRequired,
+            TodoError::Unauthorized(_) => ErrorCode::Unauthorized,
+            TodoError::NotImplemented(_) => ErrorCode::NotImplemented,
+            TodoError::Database(_) | TodoError::Serialization(_) | TodoError::Internal(_) => {
+                ErrorCode::InternalError
+            }
+        }
+    }
+
+    /// The error envelope for a response of any data type
+    pub fn body<T>(&self) -> ApiResponse<T> {
+        let mut body = ApiResponse::error(self.code(), self.client_message());
+        if let TodoError::InvalidFields(errors) = self {
+            body.fields = errors.fields().to_vec();
+        }
+        body
+    }
+}
+
+impl From<ValidationErrors> for TodoError {
+    fn from(errors: ValidationErrors) -> Self {
+        TodoError::InvalidFields(errors)
+    }
+}
+
+/// The `ApiResponse` envelope with the client message and code
+impl IntoResponse for TodoError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self.body::<()>())).into_response()
+    }
+}
 
 #[async_trait]
 pub trait TodoRepositoryTrait: Send + Sync {
     async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError>;
+    /// Creates all of `todos` or none of them; returns them in the same order
+    async fn create_todos_bulk(&self, todos: &[Todo]) -> Result<Vec<Todo>, TodoError>;
     async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError>;
-    async fn get_todo_by_id(&self, id: Uuid) -> Result<Option<Todo>, TodoError>;
+    async fn find_todos(&self, filter: &TodoFilter) -> Result<Vec<Todo>, TodoError>;
+    /// Todos matching `query`, best first, at most `query.limit`
+    async fn search_todos(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, TodoError>;
+    /// Titles of todos that are not archived for `query`: those starting with it, newest
+    /// first, then those resembling it, at most `query.limit`
+    async fn autocomplete_titles(
+        &self,
+        query: &AutocompleteQuery,
+    ) -> Result<Vec<TitleSuggestion>, TodoError>;
+    /// Up to `limit` todos that come after `cursor` when listed by `sort` in `order`;
+    /// archived todos are skipped unless `include_archived`
+    async fn get_todos_after(
+        &self,
+        cursor: Option<TodoId>,
+        limit: i64,
+        sort: TodoSort,
+        order: SortOrder,
+        include_archived: bool,
+    ) -> Result<Vec<Todo>, TodoError>;
+    async fn get_todo_by_id(&self, id: TodoId) -> Result<Option<Todo>, TodoError>;
+    /// Fails with `PreconditionFailed` when the todo is not at `expected_version`
     async fn update_todo(
         &self,
-        id: Uuid,
+        id: TodoId,
         updates: &UpdateTodoRequest,
+        expected_version: Option<i32>,
     ) -> Result<Option<Todo>, TodoError>;
-    async fn delete_todo(&self, id: Uuid) -> Result<bool, TodoError>;
+    /// Creates `todo`, or replaces every field of the stored todo with its id but the
+    /// creation time, bumping the version. Fails with `PreconditionFailed` when the todo
+    /// exists at another version than `expected_version`.
+    async fn upsert_todo(
+        &self,
+        todo: &Todo,
+        expected_version: Option<i32>,
+    ) -> Result<TodoUpsert, TodoError>;
+    /// Applies `updates` to every todo among `ids` that exists, all or none; returns the
+    /// updated todos
+    async fn update_todos_bulk(
+        &self,
+        ids: &[TodoId],
+        updates: &UpdateTodoRequest,
+    ) -> Result<Vec<Todo>, TodoError>;
+    async fn update_todo_content(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        base_version: i32,
+    ) -> Result<ContentUpdate, TodoError>;
+    /// Replaces the content of a todo and creates `parts`, each related to it as
+    /// `split_from`, all or nothing. Fails with `PreconditionFailed` when the todo is not
+    /// at `expected_version`; `None` when it is gone.
+    async fn split_todo(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        parts: &[Todo],
+    ) -> Result<Option<TodoSplit>, TodoError>;
+    /// Replaces the content of `parent` and archives `subtasks`, all or nothing. Fails
+    /// with `PreconditionFailed` when the parent is not at `expected_version`; `None` when
+    /// it is gone or a subtask is no longer completed or already archived.
+    async fn merge_subtasks(
+        &self,
+        parent: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        subtasks: &[TodoId],
+    ) -> Result<Option<SubtaskMerge>, TodoError>;
+    /// Fails with `PreconditionFailed` when the todo is not at `expected_version`
+    async fn delete_todo(
+        &self,
+        id: TodoId,
+        expected_version: Option<i32>,
+    ) -> Result<bool, TodoError>;
+    /// Deletes every todo among `ids` that exists, all or none; returns their ids
+    async fn delete_todos_bulk(&self, ids: &[TodoId]) -> Result<Vec<TodoId>, TodoError>;
+    /// Completes every open todo that is not archived, in one statement; returns them
+    async fn complete_all_todos(&self) -> Result<Vec<Todo>, TodoError>;
+    /// Deletes every completed todo that is not archived, in one statement; returns their
+    /// ids
+    async fn delete_completed_todos(&self) -> Result<Vec<TodoId>, TodoError>;
+    async fn get_short_id(&self, todo_id: TodoId) -> Result<Option<String>, TodoError>;
+    async fn resolve_short_id(&self, short_id: &str) -> Result<Option<TodoId>, TodoError>;
+    async fn create_share_link(&self, link: &ShareLink) -> Result<ShareLink, TodoError>;
+    /// Share links of a todo, newest first
+    async fn list_share_links(&self, todo_id: TodoId) -> Result<Vec<ShareLink>, TodoError>;
+    async fn get_share_link(&self, token: &str) -> Result<Option<ShareLink>, TodoError>;
+    async fn delete_share_link(&self, token: &str) -> Result<bool, TodoError>;
+    /// Logs an attempt to open a link and updates its counters. A `Viewed` attempt only
+    /// counts while the link is within its limits; otherwise it is recorded, and returned,
+    /// as `ViewLimitReached`.
+    async fn record_share_access(
+        &self,
+        token: &str,
+        access: &ShareAccess,
+    ) -> Result<ShareOutcome, TodoError>;
+    /// The latest `limit` attempts to open a link, newest first
+    async fn share_access_log(
+        &self,
+        token: &str,
+        limit: i64,
+    ) -> Result<Vec<ShareAccess>, TodoError>;
+    async fn list_metadata_fields(&self) -> Result<Vec<MetadataField>, TodoError>;
+    async fn upsert_metadata_field(
+        &self,
+        field: &MetadataField,
+    ) -> Result<MetadataField, TodoError>;
+    async fn delete_metadata_field(&self, key: &str) -> Result<bool, TodoError>;
+    /// The stored policy, or one without rules when none was set
+    async fn validation_policy(&self) -> Result<ValidationPolicy, TodoError>;
+    async fn set_validation_policy(
+        &self,
+        policy: &ValidationPolicy,
+    ) -> Result<ValidationPolicy, TodoError>;
+    /// Opens a flag for each finding, or refreshes the open one of the same policy and field
+    async fn flag_todo(&self, id: TodoId, findings: &[Finding]) -> Result<(), TodoError>;
+    /// Open flags, oldest first
+    async fn moderation_flags(&self) -> Result<Vec<ModerationFlag>, TodoError>;
+    /// `false` when no flag has this id
+    async fn dismiss_moderation_flag(&self, id: Uuid) -> Result<bool, TodoError>;
+    /// Fails with `Conflict` when an encrypted todo has this id
+    async fn create_encrypted_todo(&self, todo: &EncryptedTodo)
+        -> Result<EncryptedTodo, TodoError>;
+    /// Encrypted todos, all or those of one key, oldest first
+    async fn list_encrypted_todos(
+        &self,
+        key_fingerprint: Option<&str>,
+    ) -> Result<Vec<EncryptedTodo>, TodoError>;
+    async fn get_encrypted_todo(&self, id: Uuid) -> Result<Option<EncryptedTodo>, TodoError>;
+    /// Replaces everything but the creation time, if the todo is still at `base_version`
+    async fn update_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+        base_version: i32,
+    ) -> Result<EncryptedUpdate, TodoError>;
+    async fn delete_encrypted_todo(&self, id: Uuid) -> Result<bool, TodoError>;
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, TodoError>;
+    /// Every webhook, oldest first
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, TodoError>;
+    async fn get_webhook(&self, id: Uuid) -> Result<Option<Webhook>, TodoError>;
+    /// Stores the URL, secret, events and active flag and bumps `updated_at`; `None` when
+    /// the webhook is gone
+    async fn update_webhook(&self, webhook: &Webhook) -> Result<Option<Webhook>, TodoError>;
+    /// Deletes the webhook with its delivery log
+    async fn delete_webhook(&self, id: Uuid) -> Result<bool, TodoError>;
+    async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), TodoError>;
+    /// The latest `limit` delivery attempts of a webhook, newest first
+    async fn webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, TodoError>;
+    /// Every tag with its todo count, by name
+    async fn list_tags(&self) -> Result<Vec<Tag>, TodoError>;
+    async fn get_tag(&self, id: Uuid) -> Result<Option<Tag>, TodoError>;
+    /// `None` when a tag of the same name exists
+    async fn create_tag(&self, tag: &Tag) -> Result<Option<Tag>, TodoError>;
+    /// Tags whose name contains `text`, ordered as by `tags::suggest_in`
+    async fn suggest_tags(&self, text: &str, limit: i64) -> Result<Vec<Tag>, TodoError>;
+    /// Renames a tag everywhere, bumping the version of every todo carrying it. With
+    /// `merge`, a tag that already has `name` takes over its todos instead and the tag is
+    /// deleted, all or nothing.
+    async fn rename_tag(&self, id: Uuid, name: &str, merge: bool) -> Result<TagRename, TodoError>;
+    /// Deletes a tag and detaches it, bumping the version of every todo carrying it;
+    /// returns those todos, or `None` when there is no tag `id`
+    async fn delete_tag(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError>;
+    async fn aging_report(&self) -> Result<AgingReport, TodoError>;
+    /// Estimate sums over todos created in `[from, to)`
+    async fn workload_totals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<WorkloadTotals, TodoError>;
+    /// Todos `user` marked completed per UTC day, oldest first; days without any are left
+    /// out. Completions are counted for `auth::acting_user`.
+    async fn completion_days(&self, user: Uuid) -> Result<Vec<CompletionDay>, TodoError>;
+    /// Replaces the plan `user` made for `day` with `todo_ids`, in that order
+    async fn set_plan(
+        &self,
+        user: Uuid,
+        day: NaiveDate,
+        todo_ids: &[TodoId],
+    ) -> Result<(), TodoError>;
+    /// Todos `user` planned for `day` that still exist, in plan order
+    async fn plan_todos(&self, user: Uuid, day: NaiveDate) -> Result<Vec<Todo>, TodoError>;
+    /// Relations of a todo from its side, oldest first
+    async fn relations_of(&self, todo_id: TodoId) -> Result<Vec<RelationSummary>, TodoError>;
+    /// `None` when the two todos already have a relation of that kind, either way round
+    async fn create_relation(
+        &self,
+        relation: &TodoRelation,
+    ) -> Result<Option<TodoRelation>, TodoError>;
+    /// Deletes a relation `todo_id` is on either side of
+    async fn delete_relation(&self, todo_id: TodoId, relation_id: Uuid) -> Result<bool, TodoError>;
+    /// Every list with its todo count, by name
+    async fn list_lists(&self) -> Result<Vec<TodoList>, TodoError>;
+    async fn get_list(&self, id: Uuid) -> Result<Option<TodoList>, TodoError>;
+    /// `None` when a list of the same name exists
+    async fn create_list(&self, list: &TodoList) -> Result<Option<TodoList>, TodoError>;
+    async fn update_list(
+        &self,
+        id: Uuid,
+        update: &UpdateTodoListRequest,
+    ) -> Result<ListUpdate, TodoError>;
+    /// Deletes a list, moving its todos out of it with a new version; returns those todos,
+    /// or `None` when there is no list `id`
+    async fn delete_list(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError>;
+    /// Stores an import job with the items it has yet to create
+    async fn create_import_job(
+        &self,
+        job: &ImportJob,
+        items: &[ImportItem],
+    ) -> Result<ImportJob, TodoError>;
+    async fn get_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError>;
+    /// Items of an import yet to be created, in request order; none once it finished
+    async fn import_job_items(&self, id: Uuid) -> Result<Vec<ImportItem>, TodoError>;
+    /// Saves the progress of an import and returns the stored job. A cancelled job stays
+    /// cancelled; a finished one gets its `finished_at` and drops its items.
+    async fn update_import_job(&self, job: &ImportJob) -> Result<Option<ImportJob>, TodoError>;
+    /// Cancels an import unless it already finished; returns the stored job either way
+    async fn cancel_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError>;
+    /// `None` when a user with the same email exists
+    async fn create_user(&self, user: &User) -> Result<Option<User>, TodoError>;
+    /// `email` must already be normalized
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, TodoError>;
+    /// Fails when the storage behind the repository cannot answer queries
+    async fn ping(&self) -> Result<(), TodoError>;
 }
 
+/// Rows per INSERT of `create_todos_bulk`; 14 binds each
+const BULK_INSERT_ROWS: usize = 1000;
+
+const IMPORT_JOB_COLUMNS: &str =
+    "id, status, total, processed, created, errors, created_at, updated_at, finished_at";
+
 pub struct DatabaseTodoRepository {
     pool: DatabasePool,
+    compression: ContentCompression,
+    cjk_search: CjkSearch,
 }
 
 impl DatabaseTodoRepository {
     pub fn new(pool: DatabasePool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            compression: ContentCompression::default(),
+            cjk_search: CjkSearch::default(),
+        }
     }
-}
 
-#[async_trait]
-impl TodoRepositoryTrait for DatabaseTodoRepository {
-    async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError> {
-        tracing::debug!("DatabaseTodoRepository: Creating todo with id: {}", todo.id);
-        let row = sqlx::query_as::<_, Todo>(
+    pub fn with_content_compression(mut self, compression: ContentCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_cjk_search(mut self, cjk_search: CjkSearch) -> Self {
+        self.cjk_search = cjk_search;
+        self
+    }
+
+    /// Sort key column; only these fixed names are ever spliced into SQL
+    fn sort_column(sort: TodoSort) -> &'static str {
+        match sort {
+            TodoSort::CreatedAt => "created_at",
+            TodoSort::UpdatedAt => "updated_at",
+            TodoSort::Title => "title",
+            TodoSort::Priority => "priority",
+        }
+    }
+
+    /// `ORDER BY` terms, with id breaking ties in the same direction
+    fn order_by(sort: TodoSort, order: SortOrder) -> String {
+        let direction = match order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        format!("{} {direction}, id {direction}", Self::sort_column(sort))
+    }
+
+    /// Word search through the extension `SEARCH_CJK` names. The expressions match the
+    /// indexes its script in `database/optional` creates.
+    async fn search_cjk(
+        &self,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        query: &SearchQuery,
+    ) -> Result<Vec<SearchRow>, sqlx::Error> {
+        if self.cjk_search == CjkSearch::Pgroonga {
+            return sqlx::query_as::<_, SearchRow>(sqltrace::traced(
+                r#"
+                SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at,
+                       language, pgroonga_score(tableoid, ctid)::REAL AS score
+                FROM todos
+                WHERE ARRAY[title, content] &@~ $1
+                ORDER BY score DESC, id DESC
+                LIMIT $2
+                "#,
+                &[&query.text, &query.limit],
+            ))
+            .bind(&query.text)
+            .bind(query.limit)
+            .fetch_all(&mut **tx)
+            .await;
+        }
+
+        // One LIKE per term; the placeholders after $2 are the terms in order
+        let terms = search::terms(&query.text);
+        let conditions: Vec<String> = terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| {
+                let operator = if term.exclude { "NOT LIKE" } else { "LIKE" };
+                format!("(title || ' ' || content) {operator} likequery(${})", i + 3)
+            })
+            .collect();
+        let sql = format!(
             r#"
-            INSERT INTO todos (id, title, content, completed, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            RETURNING id, title, content, completed, created_at, updated_at
+            SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at,
+                   language, bigm_similarity($1, title || ' ' || content) AS score
+            FROM todos
+            WHERE {}
+            ORDER BY score DESC, id DESC
+            LIMIT $2
             "#,
-        )
-        .bind(todo.id)
-        .bind(&todo.title)
-        .bind(&todo.content)
-        .bind(todo.completed)
-        .bind(todo.created_at)
-        .bind(todo.updated_at)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("DatabaseTodoRepository: Failed to create todo: {}", e);
-            Box::new(e) as TodoError
-        })?;
-
-        tracing::debug!(
-            "DatabaseTodoRepository: Successfully created todo with id: {}",
-            row.id
+            conditions.join(" AND ")
         );
-        Ok(row)
+        let mut binds: Vec<sqltrace::Bind> = vec![&query.text, &query.limit];
+        binds.extend(terms.iter().map(|term| &term.text as sqltrace::Bind));
+        let mut rows = sqlx::query_as::<_, SearchRow>(sqltrace::traced(&sql, &binds))
+            .bind(&query.text)
+            .bind(query.limit);
+        for term in &terms {
+            rows = rows.bind(&term.text);
+        }
+        rows.fetch_all(&mut **tx).await
     }
 
-    async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
-        tracing::debug!("DatabaseTodoRepository: Fetching all todos");
-        let rows = sqlx::query_as::<_, Todo>(
+    /// Fills in the tag names of `todos`
+    async fn load_tags<'a>(
+        executor: impl sqlx::PgExecutor<'_>,
+        todos: impl IntoIterator<Item = &'a mut Todo>,
+    ) -> Result<(), sqlx::Error> {
+        let mut todos: Vec<&mut Todo> = todos.into_iter().collect();
+        if todos.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<TodoId> = todos.iter().map(|todo| todo.id).collect();
+        let rows: Vec<(TodoId, String)> = sqlx::query_as(sqltrace::traced(
             r#"
-            SELECT id, title, content, completed, created_at, updated_at
-            FROM todos
-            ORDER BY created_at DESC
+            SELECT todo_tags.todo_id, tags.name
+            FROM todo_tags
+            JOIN tags ON tags.id = todo_tags.tag_id
+            WHERE todo_tags.todo_id = ANY($1)
+            ORDER BY tags.name
             "#,
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("DatabaseTodoRepository: Failed to fetch all todos: {}", e);
-            Box::new(e) as TodoError
-        })?;
+            &[&ids],
+        ))
+        .bind(&ids)
+        .fetch_all(executor)
+        .await?;
+        let mut names: HashMap<TodoId, Vec<String>> = HashMap::new();
+        for (id, name) in rows {
+            names.entry(id).or_default().push(name);
+        }
+        for todo in todos.iter_mut() {
+            todo.tags = names.remove(&todo.id).unwrap_or_default();
+        }
+        Ok(())
+    }
 
-        tracing::debug!(
-            "DatabaseTodoRepository: Successfully fetched {} todos",
-            rows.len()
-        );
-        Ok(rows)
+    async fn with_tags<'a>(
+        &self,
+        todos: impl IntoIterator<Item = &'a mut Todo>,
+    ) -> Result<(), TodoError> {
+        Self::load_tags(&self.pool, todos).await.map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to load tags: {}", e);
+            TodoError::from(e)
+        })
     }
 
-    async fn get_todo_by_id(&self, id: Uuid) -> Result<Option<Todo>, TodoError> {
-        tracing::debug!("DatabaseTodoRepository: Fetching todo with id: {}", id);
-        let row = sqlx::query_as::<_, Todo>(
+    /// `Conflict` when inserting todos failed on an id that is taken. The unique
+    /// `short_links.todo_id` inserted along with every todo catches it even when `todos`
+    /// is partitioned and its primary key no longer does.
+    fn insert_error(e: sqlx::Error, map_err: impl FnOnce(sqlx::Error) -> TodoError) -> TodoError {
+        match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                tracing::warn!("DatabaseTodoRepository: Todo id already taken: {}", e);
+                TodoError::Conflict("A todo with this id already exists".to_string())
+            }
+            e => map_err(e),
+        }
+    }
+
+    /// Creates the tags among `names` that do not exist yet
+    async fn insert_tag_names(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        names: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let new_ids: Vec<Uuid> = names.iter().map(|_| Uuid::now_v7()).collect();
+        sqlx::query(sqltrace::traced(
             r#"
-            SELECT id, title, content, completed, created_at, updated_at
-            FROM todos
-            WHERE id = $1
+            INSERT INTO tags (id, name)
+            SELECT * FROM unnest($1::UUID[], $2::TEXT[])
+            ON CONFLICT (name) DO NOTHING
             "#,
-        )
+            &[&new_ids, &names],
+        ))
+        .bind(&new_ids)
+        .bind(names)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Makes `names` the tags of todo `id`, creating the tags that do not exist yet
+    async fn replace_tags(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        id: TodoId,
+        names: &[String],
+    ) -> Result<(), sqlx::Error> {
+        Self::insert_tag_names(tx, names).await?;
+        sqlx::query(sqltrace::traced(
+            r#"
+            DELETE FROM todo_tags
+            WHERE todo_id = $1
+              AND tag_id NOT IN (SELECT id FROM tags WHERE name = ANY($2))
+            "#,
+            &[&id, &names],
+        ))
         .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(names)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO todo_tags (todo_id, tag_id)
+            SELECT $1, id FROM tags WHERE name = ANY($2)
+            ON CONFLICT DO NOTHING
+            "#,
+            &[&id, &names],
+        ))
+        .bind(id)
+        .bind(names)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Gives every todo carrying tag `id` a new version, as its tag names change, and
+    /// returns them
+    async fn bump_tagged_todos(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<Vec<TodoRow>, sqlx::Error> {
+        sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            UPDATE todos
+            SET version = version + 1
+            WHERE id IN (SELECT todo_id FROM todo_tags WHERE tag_id = $1)
+            RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_all(&mut **tx)
         .await
-        .map_err(|e| {
-            tracing::error!(
-                "DatabaseTodoRepository: Failed to fetch todo with id {}: {}",
-                id,
-                e
-            );
-            Box::new(e) as TodoError
-        })?;
+    }
 
-        if row.is_some() {
-            tracing::debug!(
-                "DatabaseTodoRepository: Successfully fetched todo with id: {}",
-                id
-            );
-        } else {
-            tracing::debug!("DatabaseTodoRepository: Todo not found with id: {}", id);
+    /// Turns rows changed within `tx` into todos with the tags they have in it
+    async fn todos_in(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        rows: Vec<TodoRow>,
+    ) -> Result<Vec<Todo>, TodoError> {
+        let mut todos = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::load_tags(&mut **tx, todos.iter_mut()).await?;
+        Ok(todos)
+    }
+
+    /// Moves the todos of tag `id` to `target` and deletes `id`, returning the moved
+    /// todos; `None` when there is no tag `id`
+    async fn merge_tag(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        id: Uuid,
+        target: Uuid,
+    ) -> Result<Option<Vec<TodoRow>>, sqlx::Error> {
+        let moved = Self::bump_tagged_todos(tx, id).await?;
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO todo_tags (todo_id, tag_id)
+            SELECT todo_id, $2 FROM todo_tags WHERE tag_id = $1
+            ON CONFLICT DO NOTHING
+            "#,
+            &[&id, &target],
+        ))
+        .bind(id)
+        .bind(target)
+        .execute(&mut **tx)
+        .await?;
+        let deleted = sqlx::query(sqltrace::traced("DELETE FROM tags WHERE id = $1", &[&id]))
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        Ok((deleted.rows_affected() > 0).then_some(moved))
+    }
+
+    /// Locks the todo and fails when it is at another version than `expected`; a
+    /// missing todo is left to the write to report
+    async fn check_version(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        id: TodoId,
+        expected: Option<i32>,
+    ) -> Result<(), TodoError> {
+        if expected.is_none() {
+            return Ok(());
+        }
+        let version: Option<i32> = sqlx::query_scalar(sqltrace::traced(
+            "SELECT version FROM todos WHERE id = $1 FOR UPDATE",
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        match version {
+            Some(version) => preconditions::check_version(id, version, expected),
+            None => Ok(()),
         }
-        Ok(row)
     }
 
-    async fn update_todo(
+    /// Scopes `tx` to `user`, whose todos, completion days and plans are then the only
+    /// ones the row-level security policies of migrations 031 and 032 let it see or write,
+    /// along with the shared todos
+    async fn act_in(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        user: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT set_config('md_todo.user_id', $1, true)")
+            .bind(user.to_string())
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Starts a transaction scoped to the acting user; every query that reads or writes
+    /// todos runs in one, since a transaction acting for no one sees no todos
+    async fn begin(&self) -> Result<sqlx::Transaction<'static, Postgres>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        Self::act_in(&mut tx, auth::acting_user()).await?;
+        Ok(tx)
+    }
+
+    /// Counts the open todos among `ids` towards the acting user's completions of the day
+    /// of `now`; locking them first keeps a todo completed concurrently from counting twice.
+    /// `tx` is one from `begin`.
+    async fn record_completions(
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        ids: &[TodoId],
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let day = now.date_naive();
+        let user = auth::acting_user();
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO completion_days (user_id, day, completions)
+            SELECT $3, $2, COUNT(*)
+            FROM (SELECT id FROM todos WHERE id = ANY($1) AND NOT completed ORDER BY id FOR UPDATE) AS opened
+            HAVING COUNT(*) > 0
+            ON CONFLICT (user_id, day) DO UPDATE SET completions = completion_days.completions + EXCLUDED.completions
+            "#,
+            &[&ids, &day, &user],
+        ))
+        .bind(ids)
+        .bind(day)
+        .bind(user)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Rewrites stored content that is not in the form the current compression setting
+    /// would write, without touching versions. Returns the number of rows rewritten.
+    /// Applies `updates` to the todos among `ids`. Several todos are locked in id order
+    /// first, so concurrent bulk updates cannot deadlock.
+    async fn update_in(
         &self,
-        id: Uuid,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        ids: &[TodoId],
         updates: &UpdateTodoRequest,
-    ) -> Result<Option<Todo>, TodoError> {
-        tracing::debug!("DatabaseTodoRepository: Updating todo with id: {}", id);
-        let row = sqlx::query_as::<_, Todo>(
+    ) -> Result<Vec<Todo>, TodoError> {
+        let (title, content) = updates.text()?;
+        let (content, content_zstd) = match &content {
+            Some(content) => {
+                let (inline, compressed) = self.compression.encode(content);
+                (Some(inline), compressed)
+            }
+            None => (None, None),
+        };
+        let now = Utc::now();
+        if ids.len() > 1 {
+            sqlx::query(sqltrace::traced(
+                "SELECT id FROM todos WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+                &[&ids],
+            ))
+            .bind(ids)
+            .execute(&mut **tx)
+            .await?;
+        }
+        if updates.completed == Some(true) {
+            Self::record_completions(tx, ids, now).await?;
+        }
+        // The row lock taken by the update keeps concurrent tag changes of a todo apart
+        let rows = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
             r#"
             UPDATE todos
             SET title = COALESCE($2, title),
                 content = COALESCE($3, content),
+                content_zstd = CASE WHEN $3::TEXT IS NULL THEN content_zstd ELSE $9 END,
                 completed = COALESCE($4, completed),
+                metadata = COALESCE($6, metadata),
+                labels = COALESCE($7, labels),
+                estimate_minutes = COALESCE($8, estimate_minutes),
+                priority = COALESCE($10, priority),
+                list_id = CASE WHEN $11 THEN $12 ELSE list_id END,
+                archived = COALESCE($13, archived),
+                version = version + 1,
                 updated_at = $5
-            WHERE id = $1
-            RETURNING id, title, content, completed, created_at, updated_at
+            WHERE id = ANY($1)
+            RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
             "#,
-        )
-        .bind(id)
-        .bind(updates.title.as_ref())
-        .bind(updates.content.as_ref())
+            &[
+                &ids,
+                &title.as_deref(),
+                &Redacted::text(content),
+                &updates.completed,
+                &now,
+                &updates.metadata,
+                &updates.labels,
+                &updates.estimate_minutes,
+                &Redacted::bytes(content_zstd.as_deref()),
+                &updates.priority,
+                &updates.list_id.is_some(),
+                &updates.list_id.flatten(),
+                &updates.archived,
+            ],
+        ))
+        .bind(ids)
+        .bind(title.as_deref())
+        .bind(content)
         .bind(updates.completed)
-        .bind(Utc::now())
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| {
-            tracing::error!(
+        .bind(now)
+        .bind(updates.metadata.as_ref())
+        .bind(updates.labels.as_ref())
+        .bind(updates.estimate_minutes)
+        .bind(content_zstd)
+        .bind(updates.priority)
+        .bind(updates.list_id.is_some())
+        .bind(updates.list_id.flatten())
+        .bind(updates.archived)
+        .fetch_all(&mut **tx)
+        .await?;
+        let mut todos = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::load_tags(&mut **tx, todos.iter_mut()).await?;
+        for todo in todos.iter_mut() {
+            if let Some(tags) = tags::apply_update(&todo.tags, updates) {
+                Self::replace_tags(tx, todo.id, &tags).await?;
+                todo.tags = tags;
+            }
+        }
+        Ok(todos)
+    }
+
+    /// Inserts `todo` with its short id and tags within `tx`
+    async fn insert_in(
+        &self,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        todo: &Todo,
+    ) -> Result<Todo, TodoError> {
+        let (content, content_zstd) = self.compression.encode(&todo.content);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to create todo: {}", e);
+            TodoError::from(e)
+        };
+        let row = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            WITH inserted AS (
+                INSERT INTO todos (id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, created_at, updated_at)
+                VALUES ($1, $2, $3, $11, $4, $5, $6, $7, $8, $12, $13, $14, $9, $10)
+                RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            ), short_link AS (
+                INSERT INTO short_links (todo_id)
+                SELECT id FROM inserted
+            )
+            SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            FROM inserted
+            "#,
+            &[
+                &todo.id,
+                &todo.title,
+                &Redacted::text(Some(content)),
+                &todo.completed,
+                &todo.version,
+                &todo.metadata,
+                &todo.labels,
+                &todo.estimate_minutes,
+                &todo.created_at,
+                &todo.updated_at,
+                &Redacted::bytes(content_zstd.as_deref()),
+                &todo.priority,
+                &todo.list_id,
+                &todo.archived,
+            ],
+        ))
+        .bind(todo.id)
+        .bind(&todo.title)
+        .bind(content)
+        .bind(todo.completed)
+        .bind(todo.version)
+        .bind(&todo.metadata)
+        .bind(&todo.labels)
+        .bind(todo.estimate_minutes)
+        .bind(todo.created_at)
+        .bind(todo.updated_at)
+        .bind(content_zstd)
+        .bind(todo.priority)
+        .bind(todo.list_id)
+        .bind(todo.archived)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(|e| Self::insert_error(e, map_err))?;
+        let mut row = row.into_todo()?;
+        if !todo.tags.is_empty() {
+            Self::replace_tags(tx, row.id, &todo.tags)
+                .await
+                .map_err(map_err)?;
+            row.tags = todo.tags.clone();
+        }
+        Ok(row)
+    }
+
+    pub async fn recompress_contents(&self) -> Result<u64, TodoError> {
+        const BATCH_SIZE: i64 = 500;
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to recompress content: {}",
+                e
+            );
+            TodoError::from(e)
+        };
+
+        let mut rewritten = 0;
+        let mut after: Option<Uuid> = None;
+        loop {
+            let mut tx = self.begin().await.map_err(map_err)?;
+            let rows: Vec<(Uuid, i32, String, Option<Vec<u8>>)> = sqlx::query_as(
+                r#"
+                SELECT id, version, content, content_zstd
+                FROM todos
+                WHERE $1::UUID IS NULL OR id > $1
+                ORDER BY id
+                LIMIT $2
+                "#,
+            )
+            .bind(after)
+            .bind(BATCH_SIZE)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(map_err)?;
+            let Some(last) = rows.last() else {
+                return Ok(rewritten);
+            };
+            after = Some(last.0);
+
+            for (id, version, content, compressed) in rows {
+                let was_compressed = compressed.is_some();
+                let content = ContentCompression::decode(content, compressed.as_deref())?;
+                let (inline, compressed) = self.compression.encode(&content);
+                if compressed.is_some() == was_compressed {
+                    continue;
+                }
+                // A todo edited since it was read already has the right form
+                let result = sqlx::query(
+                    "UPDATE todos SET content = $3, content_zstd = $4 WHERE id = $1 AND version = $2",
+                )
+                .bind(id)
+                .bind(version)
+                .bind(inline)
+                .bind(compressed)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_err)?;
+                rewritten += result.rows_affected();
+            }
+            tx.commit().await.map_err(map_err)?;
+        }
+    }
+}
+
+/// A `todos` row before its content is decompressed
+#[derive(sqlx::FromRow)]
+struct TodoRow {
+    #[sqlx(flatten)]
+    todo: Todo,
+    content_zstd: Option<Vec<u8>>,
+}
+
+/// A `todos` row with its search score
+#[derive(sqlx::FromRow)]
+struct SearchRow {
+    #[sqlx(flatten)]
+    row: TodoRow,
+    language: Language,
+    score: f32,
+}
+
+impl SearchRow {
+    fn into_hit(self) -> Result<SearchHit, TodoError> {
+        Ok(SearchHit {
+            todo: self.row.into_todo()?,
+            language: self.language,
+            score: self.score,
+        })
+    }
+}
+
+impl TodoRow {
+    fn into_todo(self) -> Result<Todo, TodoError> {
+        let mut todo = self.todo;
+        todo.content = TodoContent::stored(ContentCompression::decode(
+            todo.content.into_inner(),
+            self.content_zstd.as_deref(),
+        )?);
+        Ok(todo)
+    }
+}
+
+#[async_trait]
+impl TodoRepositoryTrait for DatabaseTodoRepository {
+    async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Creating todo with id: {}", todo.id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to create todo: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let row = self.insert_in(&mut tx, todo).await?;
+        tx.commit().await.map_err(map_err)?;
+
+        tracing::debug!(
+            "DatabaseTodoRepository: Successfully created todo with id: {}",
+            row.id
+        );
+        Ok(row)
+    }
+
+    async fn create_todos_bulk(&self, todos: &[Todo]) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Creating {} todos in one transaction",
+            todos.len()
+        );
+        let encoded: Vec<_> = todos
+            .iter()
+            .map(|todo| self.compression.encode(&todo.content))
+            .collect();
+        let redacted: Vec<_> = encoded
+            .iter()
+            .map(|(content, compressed)| {
+                (
+                    Redacted::text(Some(content)),
+                    Redacted::bytes(compressed.as_deref()),
+                )
+            })
+            .collect();
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to create todos: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let mut created = HashMap::with_capacity(todos.len());
+        // A multi-row INSERT per chunk keeps each statement under Postgres' 65535 binds
+        for (chunk, first) in todos
+            .chunks(BULK_INSERT_ROWS)
+            .zip((0..).step_by(BULK_INSERT_ROWS))
+        {
+            let mut binds: Vec<sqltrace::Bind> = Vec::with_capacity(chunk.len() * 14);
+            let mut query = QueryBuilder::<Postgres>::new(
+                "WITH inserted AS (INSERT INTO todos (id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, created_at, updated_at) ",
+            );
+            query.push_values(chunk.iter().enumerate(), |mut row, (offset, todo)| {
+                let (content, compressed) = &encoded[first + offset];
+                let (content_redacted, compressed_redacted) = &redacted[first + offset];
+                row.push_bind(todo.id)
+                    .push_bind(&todo.title)
+                    .push_bind(*content)
+                    .push_bind(compressed.as_deref())
+                    .push_bind(todo.completed)
+                    .push_bind(todo.version)
+                    .push_bind(&todo.metadata)
+                    .push_bind(&todo.labels)
+                    .push_bind(todo.estimate_minutes)
+                    .push_bind(todo.priority)
+                    .push_bind(todo.list_id)
+                    .push_bind(todo.archived)
+                    .push_bind(todo.created_at)
+                    .push_bind(todo.updated_at);
+                binds.extend([
+                    &todo.id as sqltrace::Bind,
+                    &todo.title,
+                    content_redacted,
+                    compressed_redacted,
+                    &todo.completed,
+                    &todo.version,
+                    &todo.metadata,
+                    &todo.labels,
+                    &todo.estimate_minutes,
+                    &todo.priority,
+                    &todo.list_id,
+                    &todo.archived,
+                    &todo.created_at,
+                    &todo.updated_at,
+                ]);
+            });
+            query.push(
+                r#"
+                RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            ), short_link AS (
+                INSERT INTO short_links (todo_id)
+                SELECT id FROM inserted
+            )
+            SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            FROM inserted
+            "#,
+            );
+            sqltrace::traced(query.sql(), &binds);
+            let rows = query
+                .build_query_as::<TodoRow>()
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(|e| Self::insert_error(e, map_err))?;
+            for row in rows {
+                let todo = row.into_todo()?;
+                created.insert(todo.id, todo);
+            }
+        }
+
+        let (tagged_ids, tag_names): (Vec<TodoId>, Vec<String>) = todos
+            .iter()
+            .flat_map(|todo| todo.tags.iter().map(|name| (todo.id, name.clone())))
+            .unzip();
+        if !tag_names.is_empty() {
+            let mut names = tag_names.clone();
+            names.sort();
+            names.dedup();
+            Self::insert_tag_names(&mut tx, &names)
+                .await
+                .map_err(map_err)?;
+            sqlx::query(sqltrace::traced(
+                r#"
+                INSERT INTO todo_tags (todo_id, tag_id)
+                SELECT pairs.todo_id, tags.id
+                FROM unnest($1::UUID[], $2::TEXT[]) AS pairs(todo_id, name)
+                JOIN tags ON tags.name = pairs.name
+                ON CONFLICT DO NOTHING
+                "#,
+                &[&tagged_ids, &tag_names],
+            ))
+            .bind(&tagged_ids)
+            .bind(&tag_names)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        }
+        tx.commit().await.map_err(map_err)?;
+
+        // RETURNING does not promise the order of VALUES
+        let created = todos
+            .iter()
+            .map(|todo| {
+                let mut row = created.remove(&todo.id).ok_or_else(|| {
+                    TodoError::Internal(format!("todo {} was not returned by the insert", todo.id))
+                })?;
+                row.tags = todo.tags.clone();
+                Ok(row)
+            })
+            .collect::<Result<Vec<_>, TodoError>>()?;
+        tracing::debug!(
+            "DatabaseTodoRepository: Successfully created {} todos",
+            created.len()
+        );
+        Ok(created)
+    }
+
+    async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching all todos");
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to fetch all todos: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let rows = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            FROM todos
+            ORDER BY created_at DESC, id DESC
+            "#,
+            &[],
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        let mut rows = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_tags(&mut rows).await?;
+
+        tracing::debug!(
+            "DatabaseTodoRepository: Successfully fetched {} todos",
+            rows.len()
+        );
+        Ok(rows)
+    }
+
+    async fn find_todos(&self, filter: &TodoFilter) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Finding todos matching {:?}",
+            filter
+        );
+        let descendants = filter
+            .label
+            .as_ref()
+            .map(|label| format!("{}{}", label, labels::SEPARATOR));
+        let mut binds: Vec<sqltrace::Bind> = Vec::new();
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at FROM todos WHERE TRUE",
+        );
+        for (key, value) in &filter.metadata {
+            query
+                .push(" AND metadata ->> ")
+                .push_bind(key)
+                .push(" = ")
+                .push_bind(value);
+            binds.extend([key as sqltrace::Bind, value]);
+        }
+        if let (Some(label), Some(descendants)) = (&filter.label, &descendants) {
+            query
+                .push(" AND EXISTS (SELECT 1 FROM unnest(labels) AS label WHERE label = ")
+                .push_bind(label)
+                .push(" OR starts_with(label, ")
+                .push_bind(descendants)
+                .push("))");
+            binds.extend([label as sqltrace::Bind, descendants]);
+        }
+        if let Some(tag) = &filter.tag {
+            query
+                .push(" AND EXISTS (SELECT 1 FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id WHERE todo_tags.todo_id = todos.id AND tags.name = ")
+                .push_bind(tag)
+                .push(")");
+            binds.push(tag);
+        }
+        if !filter.priority.is_empty() {
+            query
+                .push(" AND priority = ANY(")
+                .push_bind(&filter.priority)
+                .push(")");
+            binds.push(&filter.priority);
+        }
+        if let Some(list_id) = &filter.list_id {
+            query.push(" AND list_id = ").push_bind(list_id);
+            binds.push(list_id);
+        }
+        if !filter.include_archived {
+            query.push(" AND NOT archived");
+        }
+        if let Some(stale) = &filter.stale {
+            query
+                .push(if stale.stale { " AND " } else { " AND NOT " })
+                .push("(NOT completed AND NOT archived AND updated_at < ")
+                .push_bind(stale.cutoff)
+                .push(")");
+            binds.push(&stale.cutoff);
+        }
+        query
+            .push(" ORDER BY ")
+            .push(Self::order_by(filter.sort, filter.order));
+        sqltrace::traced(query.sql(), &binds);
+
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to find todos: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let rows = query
+            .build_query_as::<TodoRow>()
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        let mut rows = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_tags(&mut rows).await?;
+
+        tracing::debug!(
+            "DatabaseTodoRepository: Found {} matching todos",
+            rows.len()
+        );
+        Ok(rows)
+    }
+
+    async fn search_todos(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Searching todos for {:?}", query);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to search todos: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let rows = if query.fuzzy {
+            // <% compares against a session threshold; setting it for this transaction
+            // only keeps the trigram index usable
+            let threshold = search::MIN_SIMILARITY.to_string();
+            sqlx::query(sqltrace::traced(
+                "SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)",
+                &[&threshold],
+            ))
+            .bind(&threshold)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?;
+            sqlx::query_as::<_, SearchRow>(sqltrace::traced(
+                r#"
+                SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at,
+                       language, word_similarity($1, title) AS score
+                FROM todos
+                WHERE $1 <% title
+                ORDER BY score DESC, id DESC
+                LIMIT $2
+                "#,
+                &[&query.text, &query.limit],
+            ))
+            .bind(&query.text)
+            .bind(query.limit)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(map_err)?
+        } else if self.cjk_search.applies_to(query) {
+            self.search_cjk(&mut tx, query).await.map_err(map_err)?
+        } else {
+            // The document expression matches idx_todos_search, which indexes each todo
+            // with the text search configuration of its language
+            sqlx::query_as::<_, SearchRow>(sqltrace::traced(
+                r#"
+                SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at,
+                       language, ts_rank(to_tsvector(todo_search_config(language), title || ' ' || content), words) AS score
+                FROM todos, todo_search_query($1) AS words
+                WHERE to_tsvector(todo_search_config(language), title || ' ' || content) @@ words
+                ORDER BY score DESC, id DESC
+                LIMIT $2
+                "#,
+                &[&query.text, &query.limit],
+            ))
+            .bind(&query.text)
+            .bind(query.limit)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(map_err)?
+        };
+        tx.commit().await.map_err(map_err)?;
+        let mut hits = rows
+            .into_iter()
+            .map(SearchRow::into_hit)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_tags(hits.iter_mut().map(|hit| &mut hit.todo))
+            .await?;
+
+        tracing::debug!("DatabaseTodoRepository: Found {} todos", hits.len());
+        Ok(hits)
+    }
+
+    async fn autocomplete_titles(
+        &self,
+        query: &AutocompleteQuery,
+    ) -> Result<Vec<TitleSuggestion>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Autocompleting {:?}", query);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to autocomplete titles: {}",
+                e
+            );
+            TodoError::from(e)
+        };
+        // Both ILIKE and <% are served by idx_todos_title_trgm; the threshold is set for
+        // this transaction only, as in a fuzzy search
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let threshold = search::MIN_SIMILARITY.to_string();
+        sqlx::query(sqltrace::traced(
+            "SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)",
+            &[&threshold],
+        ))
+        .bind(&threshold)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        let prefix = query.like_prefix();
+        let suggestions = sqlx::query_as::<_, TitleSuggestion>(sqltrace::traced(
+            r#"
+            SELECT id, title, completed
+            FROM todos
+            WHERE NOT archived AND (title ILIKE $2 OR $1 <% title)
+            ORDER BY title ILIKE $2 DESC, word_similarity($1, title) DESC, id DESC
+            LIMIT $3
+            "#,
+            &[&query.text, &prefix, &query.limit],
+        ))
+        .bind(&query.text)
+        .bind(&prefix)
+        .bind(query.limit)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(suggestions)
+    }
+
+    async fn get_todos_after(
+        &self,
+        cursor: Option<TodoId>,
+        limit: i64,
+        sort: TodoSort,
+        order: SortOrder,
+        include_archived: bool,
+    ) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching {} todos after {:?} by {:?} {:?}{}",
+            limit,
+            cursor,
+            sort,
+            order,
+            if include_archived {
+                " with archived"
+            } else {
+                ""
+            }
+        );
+        // Row comparison against the cursor's key walks the (key, id) index of the sort,
+        // so a page costs O(limit) however deep it is
+        let column = Self::sort_column(sort);
+        let after = match order {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        };
+        let order_by = Self::order_by(sort, order);
+        let archived = if include_archived {
+            "TRUE"
+        } else {
+            "NOT archived"
+        };
+        let sql = match cursor {
+            Some(_) => format!(
+                r#"
+                SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+                FROM todos
+                WHERE ({column}, id) {after} (SELECT {column}, id FROM todos WHERE id = $1)
+                  AND {archived}
+                ORDER BY {order_by}
+                LIMIT $2
+                "#
+            ),
+            None => format!(
+                r#"
+                SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+                FROM todos
+                WHERE {archived}
+                ORDER BY {order_by}
+                LIMIT $1
+                "#
+            ),
+        };
+        let query = match cursor {
+            Some(cursor) => {
+                sqlx::query_as::<_, TodoRow>(sqltrace::traced(&sql, &[&cursor, &limit]))
+                    .bind(cursor)
+                    .bind(limit)
+            }
+            None => sqlx::query_as::<_, TodoRow>(sqltrace::traced(&sql, &[&limit])).bind(limit),
+        };
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to fetch todo page: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let rows = query.fetch_all(&mut *tx).await.map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        let mut rows = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_tags(&mut rows).await?;
+
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetched page of {} todos",
+            rows.len()
+        );
+        Ok(rows)
+    }
+
+    async fn get_todo_by_id(&self, id: TodoId) -> Result<Option<Todo>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching todo with id: {}", id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch todo with id {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let row = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            FROM todos
+            WHERE id = $1
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        let mut row = row.map(TodoRow::into_todo).transpose()?;
+        self.with_tags(row.iter_mut()).await?;
+
+        if row.is_some() {
+            tracing::debug!(
+                "DatabaseTodoRepository: Successfully fetched todo with id: {}",
+                id
+            );
+        } else {
+            tracing::debug!("DatabaseTodoRepository: Todo not found with id: {}", id);
+        }
+        Ok(row)
+    }
+
+    async fn update_todo(
+        &self,
+        id: TodoId,
+        updates: &UpdateTodoRequest,
+        expected_version: Option<i32>,
+    ) -> Result<Option<Todo>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Updating todo with id: {}", id);
+        let log_err = |e: TodoError| {
+            tracing::error!(
                 "DatabaseTodoRepository: Failed to update todo with id {}: {}",
                 id,
                 e
             );
-            Box::new(e) as TodoError
+            e
+        };
+        let mut tx = self.begin().await.map_err(|e| log_err(e.into()))?;
+        Self::check_version(&mut tx, id, expected_version).await?;
+        let row = self
+            .update_in(&mut tx, &[id], updates)
+            .await
+            .map_err(log_err)?
+            .pop();
+        tx.commit().await.map_err(|e| log_err(e.into()))?;
+
+        if row.is_some() {
+            tracing::debug!(
+                "DatabaseTodoRepository: Successfully updated todo with id: {}",
+                id
+            );
+        } else {
+            tracing::debug!(
+                "DatabaseTodoRepository: Todo not found for update with id: {}",
+                id
+            );
+        }
+        Ok(row)
+    }
+
+    async fn upsert_todo(
+        &self,
+        todo: &Todo,
+        expected_version: Option<i32>,
+    ) -> Result<TodoUpsert, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Writing todo with id: {}", todo.id);
+        let (content, content_zstd) = self.compression.encode(&todo.content);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to write todo with id {}: {}",
+                todo.id,
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        Self::check_version(&mut tx, todo.id, expected_version).await?;
+        // The creation time of a stored todo completes its primary key when todos is
+        // partitioned, so the insert below runs into the row it replaces
+        let created_at: Option<DateTime<Utc>> = sqlx::query_scalar(sqltrace::traced(
+            "SELECT created_at FROM todos WHERE id = $1 FOR UPDATE",
+            &[&todo.id],
+        ))
+        .bind(todo.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        // Another user's todo is out of sight, but its id is still taken
+        if created_at.is_none() {
+            let taken: bool =
+                sqlx::query_scalar(sqltrace::traced("SELECT todo_exists($1)", &[&todo.id]))
+                    .bind(todo.id)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(map_err)?;
+            if taken {
+                tracing::warn!(
+                    "DatabaseTodoRepository: Todo id {} belongs to another user",
+                    todo.id
+                );
+                return Err(TodoError::Conflict(
+                    "A todo with this id already exists".to_string(),
+                ));
+            }
+        }
+        if todo.completed && created_at.is_some() {
+            Self::record_completions(&mut tx, &[todo.id], todo.updated_at)
+                .await
+                .map_err(map_err)?;
+        }
+        let stored_created_at = created_at.unwrap_or(todo.created_at);
+        let creating = created_at.is_none();
+        let row = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            WITH upserted AS (
+                INSERT INTO todos (id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                ON CONFLICT ON CONSTRAINT todos_pkey DO UPDATE
+                SET title = EXCLUDED.title,
+                    content = EXCLUDED.content,
+                    content_zstd = EXCLUDED.content_zstd,
+                    completed = EXCLUDED.completed,
+                    version = todos.version + 1,
+                    metadata = EXCLUDED.metadata,
+                    labels = EXCLUDED.labels,
+                    estimate_minutes = EXCLUDED.estimate_minutes,
+                    priority = EXCLUDED.priority,
+                    list_id = EXCLUDED.list_id,
+                    archived = EXCLUDED.archived,
+                    updated_at = EXCLUDED.updated_at
+                RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            ), short_link AS (
+                INSERT INTO short_links (todo_id)
+                SELECT id FROM upserted WHERE $15
+            )
+            SELECT id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            FROM upserted
+            "#,
+            &[
+                &todo.id,
+                &todo.title,
+                &Redacted::text(Some(content)),
+                &Redacted::bytes(content_zstd.as_deref()),
+                &todo.completed,
+                &todo.version,
+                &todo.metadata,
+                &todo.labels,
+                &todo.estimate_minutes,
+                &todo.priority,
+                &todo.list_id,
+                &todo.archived,
+                &stored_created_at,
+                &todo.updated_at,
+                &creating,
+            ],
+        ))
+        .bind(todo.id)
+        .bind(&todo.title)
+        .bind(content)
+        .bind(content_zstd)
+        .bind(todo.completed)
+        .bind(todo.version)
+        .bind(&todo.metadata)
+        .bind(&todo.labels)
+        .bind(todo.estimate_minutes)
+        .bind(todo.priority)
+        .bind(todo.list_id)
+        .bind(todo.archived)
+        .bind(stored_created_at)
+        .bind(todo.updated_at)
+        .bind(creating)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| Self::insert_error(e, map_err))?;
+        let mut row = row.into_todo()?;
+        Self::replace_tags(&mut tx, row.id, &todo.tags)
+            .await
+            .map_err(map_err)?;
+        row.tags = todo.tags.clone();
+        tx.commit().await.map_err(map_err)?;
+
+        tracing::debug!(
+            "DatabaseTodoRepository: Successfully {} todo with id: {}",
+            if creating { "created" } else { "replaced" },
+            row.id
+        );
+        Ok(if creating {
+            TodoUpsert::Created(row)
+        } else {
+            TodoUpsert::Replaced(row)
+        })
+    }
+
+    async fn update_todos_bulk(
+        &self,
+        ids: &[TodoId],
+        updates: &UpdateTodoRequest,
+    ) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Updating {} todos", ids.len());
+        let log_err = |e: TodoError| {
+            tracing::error!("DatabaseTodoRepository: Failed to update todos: {}", e);
+            e
+        };
+        let mut tx = self.begin().await.map_err(|e| log_err(e.into()))?;
+        let updated = self
+            .update_in(&mut tx, ids, updates)
+            .await
+            .map_err(log_err)?;
+        tx.commit().await.map_err(|e| log_err(e.into()))?;
+        Ok(updated)
+    }
+
+    async fn update_todo_content(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        base_version: i32,
+    ) -> Result<ContentUpdate, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Updating content of todo with id: {} at version {}",
+            id,
+            base_version
+        );
+        let (content, content_zstd) = self.compression.encode(content);
+        let now = Utc::now();
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to update content of todo with id {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let row = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            UPDATE todos
+            SET content = $2,
+                content_zstd = $5,
+                version = version + 1,
+                updated_at = $4
+            WHERE id = $1 AND version = $3
+            RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            "#,
+            &[
+                &id,
+                &Redacted::text(Some(content)),
+                &base_version,
+                &now,
+                &Redacted::bytes(content_zstd.as_deref()),
+            ],
+        ))
+        .bind(id)
+        .bind(content)
+        .bind(base_version)
+        .bind(now)
+        .bind(content_zstd)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        let row = row.map(TodoRow::into_todo).transpose()?;
+
+        if let Some(mut todo) = row {
+            self.with_tags([&mut todo]).await?;
+            tracing::debug!(
+                "DatabaseTodoRepository: Successfully updated content of todo with id: {}",
+                id
+            );
+            return Ok(ContentUpdate::Updated(todo));
+        }
+
+        // Either the todo is gone or someone else wrote a newer version
+        match self.get_todo_by_id(id).await? {
+            Some(current) => {
+                tracing::debug!(
+                    "DatabaseTodoRepository: Version conflict for todo with id: {} (current version {})",
+                    id,
+                    current.version
+                );
+                Ok(ContentUpdate::Conflict(current))
+            }
+            None => Ok(ContentUpdate::NotFound),
+        }
+    }
+
+    async fn split_todo(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        parts: &[Todo],
+    ) -> Result<Option<TodoSplit>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Splitting {} todos off todo with id: {}",
+            parts.len(),
+            id
+        );
+        let log_err = |e: TodoError| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to split todo with id {}: {}",
+                id,
+                e
+            );
+            e
+        };
+        let mut tx = self.begin().await.map_err(|e| log_err(e.into()))?;
+        Self::check_version(&mut tx, id, Some(expected_version)).await?;
+        let updates = UpdateTodoRequest {
+            content: Some(content.as_str().to_string()),
+            ..Default::default()
+        };
+        let Some(todo) = self
+            .update_in(&mut tx, &[id], &updates)
+            .await
+            .map_err(log_err)?
+            .pop()
+        else {
+            return Ok(None);
+        };
+        let mut created = Vec::with_capacity(parts.len());
+        for part in parts {
+            created.push(self.insert_in(&mut tx, part).await?);
+        }
+        let relation_ids: Vec<Uuid> = created.iter().map(|_| Uuid::now_v7()).collect();
+        let part_ids: Vec<TodoId> = created.iter().map(|part| part.id).collect();
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO todo_relations (id, todo_id, related_id, kind)
+            SELECT relation_id, part_id, $3::UUID, 'split_from'
+            FROM unnest($1::UUID[], $2::UUID[]) AS parts(relation_id, part_id)
+            "#,
+            &[&relation_ids, &part_ids, &id],
+        ))
+        .bind(&relation_ids)
+        .bind(&part_ids)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| log_err(e.into()))?;
+        tx.commit().await.map_err(|e| log_err(e.into()))?;
+        Ok(Some(TodoSplit {
+            todo,
+            parts: created,
+        }))
+    }
+
+    async fn merge_subtasks(
+        &self,
+        parent: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        subtasks: &[TodoId],
+    ) -> Result<Option<SubtaskMerge>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Merging {} subtasks into todo with id: {}",
+            subtasks.len(),
+            parent
+        );
+        let log_err = |e: TodoError| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to merge subtasks into todo with id {}: {}",
+                parent,
+                e
+            );
+            e
+        };
+        let mut tx = self.begin().await.map_err(|e| log_err(e.into()))?;
+        Self::check_version(&mut tx, parent, Some(expected_version)).await?;
+        // Locked, so none of them can be reopened before the commit
+        let mergeable: Vec<TodoId> = sqlx::query_scalar(sqltrace::traced(
+            r#"
+            SELECT id FROM todos
+            WHERE id = ANY($1) AND completed AND NOT archived
+            FOR UPDATE
+            "#,
+            &[&subtasks],
+        ))
+        .bind(subtasks)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| log_err(e.into()))?;
+        if mergeable.len() != subtasks.len() {
+            return Ok(None);
+        }
+        let updates = UpdateTodoRequest {
+            content: Some(content.as_str().to_string()),
+            ..Default::default()
+        };
+        let Some(todo) = self
+            .update_in(&mut tx, &[parent], &updates)
+            .await
+            .map_err(log_err)?
+            .pop()
+        else {
+            return Ok(None);
+        };
+        let archive = UpdateTodoRequest {
+            archived: Some(true),
+            ..Default::default()
+        };
+        let archived = self
+            .update_in(&mut tx, subtasks, &archive)
+            .await
+            .map_err(log_err)?;
+        tx.commit().await.map_err(|e| log_err(e.into()))?;
+        Ok(Some(SubtaskMerge {
+            parent: todo,
+            subtasks: archived,
+        }))
+    }
+
+    async fn delete_todo(
+        &self,
+        id: TodoId,
+        expected_version: Option<i32>,
+    ) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting todo with id: {}", id);
+        let log_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete todo with id {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(log_err)?;
+        Self::check_version(&mut tx, id, expected_version).await?;
+        let result = sqlx::query(sqltrace::traced(
+            r#"
+            DELETE FROM todos
+            WHERE id = $1
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(log_err)?;
+        tx.commit().await.map_err(log_err)?;
+
+        let deleted = result.rows_affected() > 0;
+        if deleted {
+            tracing::debug!(
+                "DatabaseTodoRepository: Successfully deleted todo with id: {}",
+                id
+            );
+        } else {
+            tracing::debug!(
+                "DatabaseTodoRepository: Todo not found for deletion with id: {}",
+                id
+            );
+        }
+        Ok(deleted)
+    }
+
+    async fn delete_todos_bulk(&self, ids: &[TodoId]) -> Result<Vec<TodoId>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting {} todos", ids.len());
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to delete todos: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let deleted = sqlx::query_scalar(sqltrace::traced(
+            "DELETE FROM todos WHERE id = ANY($1) RETURNING id",
+            &[&ids],
+        ))
+        .bind(ids)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(deleted)
+    }
+
+    async fn complete_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Completing all todos");
+        let now = Utc::now();
+        let day = now.date_naive();
+        let user = auth::acting_user();
+        let mut tx = self.begin().await?;
+        let rows = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            WITH completed AS (
+                UPDATE todos
+                SET completed = TRUE, version = version + 1, updated_at = $1
+                WHERE NOT completed AND NOT archived
+                RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            ), counted AS (
+                INSERT INTO completion_days (user_id, day, completions)
+                SELECT $3, $2, COUNT(*) FROM completed
+                HAVING COUNT(*) > 0
+                ON CONFLICT (user_id, day) DO UPDATE SET completions = completion_days.completions + EXCLUDED.completions
+            )
+            SELECT * FROM completed
+            "#,
+            &[&now, &day, &user],
+        ))
+        .bind(now)
+        .bind(day)
+        .bind(user)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to complete all todos: {}", e);
+            TodoError::from(e)
+        })?;
+        tx.commit().await?;
+        let mut todos = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_tags(&mut todos).await?;
+        Ok(todos)
+    }
+
+    async fn delete_completed_todos(&self) -> Result<Vec<TodoId>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting completed todos");
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete completed todos: {}",
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let deleted = sqlx::query_scalar(sqltrace::traced(
+            "DELETE FROM todos WHERE completed AND NOT archived RETURNING id",
+            &[],
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(deleted)
+    }
+
+    async fn get_short_id(&self, todo_id: TodoId) -> Result<Option<String>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching short link for todo: {}",
+            todo_id
+        );
+        let seq: Option<i64> = sqlx::query_scalar(sqltrace::traced(
+            "SELECT seq FROM short_links WHERE todo_id = $1",
+            &[&todo_id],
+        ))
+        .bind(todo_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch short link for todo {}: {}",
+                todo_id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(seq.map(|seq| shortlink::encode(seq as u64)))
+    }
+
+    async fn resolve_short_id(&self, short_id: &str) -> Result<Option<TodoId>, TodoError> {
+        let Some(seq) = shortlink::decode(short_id).and_then(|seq| i64::try_from(seq).ok()) else {
+            return Ok(None);
+        };
+        tracing::debug!("DatabaseTodoRepository: Resolving short link: {}", short_id);
+        sqlx::query_scalar(sqltrace::traced(
+            "SELECT todo_id FROM short_links WHERE seq = $1",
+            &[&seq],
+        ))
+        .bind(seq)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to resolve short link {}: {}",
+                short_id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<ShareLink, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Creating share link for todo: {}",
+            link.todo_id
+        );
+        sqlx::query_as::<_, ShareLink>(sqltrace::traced(
+            r#"
+            INSERT INTO share_links (token, todo_id, passcode_hash, max_views, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING token, todo_id, passcode_hash, max_views, view_count, failed_attempts, expires_at, created_at
+            "#,
+            &[
+                &Redacted::text(Some(&link.token)),
+                &link.todo_id,
+                &Redacted::text(link.passcode_hash.as_deref()),
+                &link.max_views,
+                &link.expires_at,
+                &link.created_at,
+            ],
+        ))
+        .bind(&link.token)
+        .bind(link.todo_id)
+        .bind(&link.passcode_hash)
+        .bind(link.max_views)
+        .bind(link.expires_at)
+        .bind(link.created_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to create share link for todo {}: {}",
+                link.todo_id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn list_share_links(&self, todo_id: TodoId) -> Result<Vec<ShareLink>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching share links for todo: {}",
+            todo_id
+        );
+        sqlx::query_as::<_, ShareLink>(sqltrace::traced(
+            r#"
+            SELECT token, todo_id, passcode_hash, max_views, view_count, failed_attempts, expires_at, created_at
+            FROM share_links
+            WHERE todo_id = $1
+            ORDER BY created_at DESC, token
+            "#,
+            &[&todo_id],
+        ))
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch share links for todo {}: {}",
+                todo_id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn get_share_link(&self, token: &str) -> Result<Option<ShareLink>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching share link");
+        sqlx::query_as::<_, ShareLink>(sqltrace::traced(
+            r#"
+            SELECT token, todo_id, passcode_hash, max_views, view_count, failed_attempts, expires_at, created_at
+            FROM share_links
+            WHERE token = $1
+            "#,
+            &[&Redacted::text(Some(token))],
+        ))
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to fetch share link: {}", e);
+            TodoError::from(e)
+        })
+    }
+
+    async fn delete_share_link(&self, token: &str) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting share link");
+        let result = sqlx::query(sqltrace::traced(
+            "DELETE FROM share_links WHERE token = $1",
+            &[&Redacted::text(Some(token))],
+        ))
+        .bind(token)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to delete share link: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_share_access(
+        &self,
+        token: &str,
+        access: &ShareAccess,
+    ) -> Result<ShareOutcome, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Recording share link access: {}",
+            access.outcome.as_str()
+        );
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to record share link access: {}",
+                e
+            );
+            TodoError::from(e)
+        };
+        let redacted_token = Redacted::text(Some(token));
+
+        let mut tx = self.pool.begin().await.map_err(map_err)?;
+        let mut outcome = access.outcome;
+        match outcome {
+            ShareOutcome::Viewed => {
+                let counted = sqlx::query(sqltrace::traced(
+                    r#"
+                    UPDATE share_links
+                    SET view_count = view_count + 1
+                    WHERE token = $1
+                      AND (max_views IS NULL OR view_count < max_views)
+                      AND (expires_at IS NULL OR expires_at > $2)
+                    "#,
+                    &[&redacted_token, &access.accessed_at],
+                ))
+                .bind(token)
+                .bind(access.accessed_at)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_err)?
+                .rows_affected()
+                    > 0;
+                if !counted {
+                    outcome = ShareOutcome::ViewLimitReached;
+                }
+            }
+            ShareOutcome::WrongPasscode => {
+                sqlx::query(sqltrace::traced(
+                    "UPDATE share_links SET failed_attempts = failed_attempts + 1 WHERE token = $1",
+                    &[&redacted_token],
+                ))
+                .bind(token)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_err)?;
+            }
+            _ => {}
+        }
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO share_link_access (token, accessed_at, outcome, client, user_agent)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            &[
+                &redacted_token,
+                &access.accessed_at,
+                &outcome.as_str(),
+                &access.client,
+                &access.user_agent,
+            ],
+        ))
+        .bind(token)
+        .bind(access.accessed_at)
+        .bind(outcome.as_str())
+        .bind(&access.client)
+        .bind(&access.user_agent)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(outcome)
+    }
+
+    async fn share_access_log(
+        &self,
+        token: &str,
+        limit: i64,
+    ) -> Result<Vec<ShareAccess>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching share link access log");
+        sqlx::query_as::<_, ShareAccess>(sqltrace::traced(
+            r#"
+            SELECT accessed_at, outcome, client, user_agent
+            FROM share_link_access
+            WHERE token = $1
+            ORDER BY accessed_at DESC, id DESC
+            LIMIT $2
+            "#,
+            &[&Redacted::text(Some(token)), &limit],
+        ))
+        .bind(token)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch share link access log: {}",
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn list_metadata_fields(&self) -> Result<Vec<MetadataField>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching metadata fields");
+        sqlx::query_as::<_, MetadataField>(sqltrace::traced(
+            r#"
+            SELECT key, field_type, required, enum_values
+            FROM metadata_fields
+            ORDER BY key
+            "#,
+            &[],
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch metadata fields: {}",
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn upsert_metadata_field(
+        &self,
+        field: &MetadataField,
+    ) -> Result<MetadataField, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Saving metadata field: {}",
+            field.key
+        );
+        sqlx::query_as::<_, MetadataField>(sqltrace::traced(
+            r#"
+            INSERT INTO metadata_fields (key, field_type, required, enum_values)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (key) DO UPDATE
+            SET field_type = EXCLUDED.field_type,
+                required = EXCLUDED.required,
+                enum_values = EXCLUDED.enum_values
+            RETURNING key, field_type, required, enum_values
+            "#,
+            &[
+                &field.key,
+                &field.field_type.as_str(),
+                &field.required,
+                &field.enum_values,
+            ],
+        ))
+        .bind(&field.key)
+        .bind(field.field_type.as_str())
+        .bind(field.required)
+        .bind(&field.enum_values)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to save metadata field {}: {}",
+                field.key,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn delete_metadata_field(&self, key: &str) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting metadata field: {}", key);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete metadata field {}: {}",
+                key,
+                e
+            );
+            TodoError::from(e)
+        };
+
+        let mut tx = self.pool.begin().await.map_err(map_err)?;
+        // Field definitions are shared, so the key goes from every user's todos
+        Self::act_in(&mut tx, auth::EVERYONE)
+            .await
+            .map_err(map_err)?;
+        let deleted = sqlx::query(sqltrace::traced(
+            "DELETE FROM metadata_fields WHERE key = $1",
+            &[&key],
+        ))
+        .bind(key)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?
+        .rows_affected()
+            > 0;
+        if deleted {
+            sqlx::query(sqltrace::traced(
+                r#"
+                UPDATE todos
+                SET metadata = metadata - $1,
+                    version = version + 1
+                WHERE metadata ? $1
+                "#,
+                &[&key],
+            ))
+            .bind(key)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        }
+        tx.commit().await.map_err(map_err)?;
+        Ok(deleted)
+    }
+
+    async fn validation_policy(&self) -> Result<ValidationPolicy, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching the validation policy");
+        let policy = sqlx::query_as::<_, ValidationPolicy>(sqltrace::traced(
+            r#"
+            SELECT max_title_length, min_tags, required_fields, required_fields_from
+            FROM validation_policy
+            "#,
+            &[],
+        ))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch the validation policy: {}",
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(policy.unwrap_or_default())
+    }
+
+    async fn set_validation_policy(
+        &self,
+        policy: &ValidationPolicy,
+    ) -> Result<ValidationPolicy, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Saving the validation policy");
+        sqlx::query_as::<_, ValidationPolicy>(sqltrace::traced(
+            r#"
+            INSERT INTO validation_policy (max_title_length, min_tags, required_fields, required_fields_from)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (id) DO UPDATE
+            SET max_title_length = EXCLUDED.max_title_length,
+                min_tags = EXCLUDED.min_tags,
+                required_fields = EXCLUDED.required_fields,
+                required_fields_from = EXCLUDED.required_fields_from,
+                updated_at = CURRENT_TIMESTAMP
+            RETURNING max_title_length, min_tags, required_fields, required_fields_from
+            "#,
+            &[
+                &policy.max_title_length,
+                &policy.min_tags,
+                &policy.required_fields,
+                &policy.required_fields_from,
+            ],
+        ))
+        .bind(policy.max_title_length)
+        .bind(policy.min_tags)
+        .bind(&policy.required_fields)
+        .bind(policy.required_fields_from)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to save the validation policy: {}",
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn flag_todo(&self, id: TodoId, findings: &[Finding]) -> Result<(), TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Flagging todo {} for review", id);
+        let ids: Vec<Uuid> = findings.iter().map(|_| Uuid::now_v7()).collect();
+        let policies: Vec<&str> = findings.iter().map(|finding| finding.policy).collect();
+        let fields: Vec<&str> = findings.iter().map(|finding| finding.field).collect();
+        let reasons: Vec<&str> = findings
+            .iter()
+            .map(|finding| finding.reason.as_str())
+            .collect();
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO moderation_flags (id, todo_id, policy, field, reason)
+            SELECT flag.id, $2, flag.policy, flag.field, flag.reason
+            FROM UNNEST($1::uuid[], $3::text[], $4::text[], $5::text[])
+                AS flag(id, policy, field, reason)
+            ON CONFLICT (todo_id, policy, field) DO UPDATE
+            SET reason = EXCLUDED.reason,
+                created_at = CURRENT_TIMESTAMP
+            "#,
+            &[&ids, &id, &policies, &fields, &reasons],
+        ))
+        .bind(&ids)
+        .bind(id)
+        .bind(&policies)
+        .bind(&fields)
+        .bind(&reasons)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to flag todo {}: {}", id, e);
+            TodoError::from(e)
+        })?;
+        Ok(())
+    }
+
+    async fn moderation_flags(&self) -> Result<Vec<ModerationFlag>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Listing moderation flags");
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to list moderation flags: {}",
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let flags = sqlx::query_as::<_, ModerationFlag>(sqltrace::traced(
+            r#"
+            SELECT f.id, f.todo_id, t.title, f.policy, f.field, f.reason, f.created_at
+            FROM moderation_flags f
+            JOIN todos t ON t.id = f.todo_id
+            ORDER BY f.created_at, f.id
+            "#,
+            &[],
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(flags)
+    }
+
+    async fn dismiss_moderation_flag(&self, id: Uuid) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Dismissing moderation flag {}", id);
+        let result = sqlx::query(sqltrace::traced(
+            "DELETE FROM moderation_flags WHERE id = $1",
+            &[&id],
+        ))
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to dismiss moderation flag {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+    ) -> Result<EncryptedTodo, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Storing encrypted todo {}", todo.id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to store encrypted todo {}: {}",
+                todo.id,
+                e
+            );
+            TodoError::from(e)
+        };
+        sqlx::query_as::<_, EncryptedTodo>(sqltrace::traced(
+            r#"
+            INSERT INTO encrypted_todos (id, key_fingerprint, algorithm, nonce, ciphertext, envelope, version, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING id, key_fingerprint, algorithm, nonce, ciphertext, envelope, version, created_at, updated_at
+            "#,
+            &[
+                &todo.id,
+                &todo.key_fingerprint,
+                &todo.algorithm,
+                &Redacted::bytes(Some(&todo.nonce)),
+                &Redacted::bytes(Some(&todo.ciphertext)),
+                &Redacted::bytes(Some(&todo.envelope)),
+                &todo.version,
+                &todo.created_at,
+                &todo.updated_at,
+            ],
+        ))
+        .bind(todo.id)
+        .bind(&todo.key_fingerprint)
+        .bind(&todo.algorithm)
+        .bind(&todo.nonce)
+        .bind(&todo.ciphertext)
+        .bind(&todo.envelope)
+        .bind(todo.version)
+        .bind(todo.created_at)
+        .bind(todo.updated_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Self::insert_error(e, map_err))
+    }
+
+    async fn list_encrypted_todos(
+        &self,
+        key_fingerprint: Option<&str>,
+    ) -> Result<Vec<EncryptedTodo>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Listing encrypted todos of key {:?}",
+            key_fingerprint
+        );
+        let todos = sqlx::query_as::<_, EncryptedTodo>(sqltrace::traced(
+            r#"
+            SELECT id, key_fingerprint, algorithm, nonce, ciphertext, envelope, version, created_at, updated_at
+            FROM encrypted_todos
+            WHERE $1::TEXT IS NULL OR key_fingerprint = $1
+            ORDER BY id
+            "#,
+            &[&key_fingerprint],
+        ))
+        .bind(key_fingerprint)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to list encrypted todos: {}",
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(todos)
+    }
+
+    async fn get_encrypted_todo(&self, id: Uuid) -> Result<Option<EncryptedTodo>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Getting encrypted todo {}", id);
+        sqlx::query_as::<_, EncryptedTodo>(sqltrace::traced(
+            r#"
+            SELECT id, key_fingerprint, algorithm, nonce, ciphertext, envelope, version, created_at, updated_at
+            FROM encrypted_todos
+            WHERE id = $1
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to get encrypted todo {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn update_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+        base_version: i32,
+    ) -> Result<EncryptedUpdate, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Replacing encrypted todo {} at version {}",
+            todo.id,
+            base_version
+        );
+        let row = sqlx::query_as::<_, EncryptedTodo>(sqltrace::traced(
+            r#"
+            UPDATE encrypted_todos
+            SET key_fingerprint = $2,
+                algorithm = $3,
+                nonce = $4,
+                ciphertext = $5,
+                envelope = $6,
+                version = version + 1,
+                updated_at = $8
+            WHERE id = $1 AND version = $7
+            RETURNING id, key_fingerprint, algorithm, nonce, ciphertext, envelope, version, created_at, updated_at
+            "#,
+            &[
+                &todo.id,
+                &todo.key_fingerprint,
+                &todo.algorithm,
+                &Redacted::bytes(Some(&todo.nonce)),
+                &Redacted::bytes(Some(&todo.ciphertext)),
+                &Redacted::bytes(Some(&todo.envelope)),
+                &base_version,
+                &todo.updated_at,
+            ],
+        ))
+        .bind(todo.id)
+        .bind(&todo.key_fingerprint)
+        .bind(&todo.algorithm)
+        .bind(&todo.nonce)
+        .bind(&todo.ciphertext)
+        .bind(&todo.envelope)
+        .bind(base_version)
+        .bind(todo.updated_at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to replace encrypted todo {}: {}",
+                todo.id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        if let Some(updated) = row {
+            return Ok(EncryptedUpdate::Updated(updated));
+        }
+        // Either the todo is gone or another client replaced it first
+        Ok(match self.get_encrypted_todo(todo.id).await? {
+            Some(current) => EncryptedUpdate::Conflict(current),
+            None => EncryptedUpdate::NotFound,
+        })
+    }
+
+    async fn delete_encrypted_todo(&self, id: Uuid) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting encrypted todo {}", id);
+        let result = sqlx::query(sqltrace::traced(
+            "DELETE FROM encrypted_todos WHERE id = $1",
+            &[&id],
+        ))
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete encrypted todo {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Creating webhook {}", webhook.id);
+        sqlx::query_as::<_, Webhook>(sqltrace::traced(
+            r#"
+            INSERT INTO webhooks (id, url, secret, events, active, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, url, secret, events, active, created_at, updated_at
+            "#,
+            &[
+                &webhook.id,
+                &Redacted::text(Some(&webhook.url)),
+                &Redacted::text(Some(&webhook.secret)),
+                &webhook.events,
+                &webhook.active,
+                &webhook.created_at,
+                &webhook.updated_at,
+            ],
+        ))
+        .bind(webhook.id)
+        .bind(&webhook.url)
+        .bind(&webhook.secret)
+        .bind(&webhook.events)
+        .bind(webhook.active)
+        .bind(webhook.created_at)
+        .bind(webhook.updated_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to create webhook {}: {}",
+                webhook.id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Listing webhooks");
+        sqlx::query_as::<_, Webhook>(sqltrace::traced(
+            r#"
+            SELECT id, url, secret, events, active, created_at, updated_at
+            FROM webhooks
+            ORDER BY created_at, id
+            "#,
+            &[],
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to list webhooks: {}", e);
+            TodoError::from(e)
+        })
+    }
+
+    async fn get_webhook(&self, id: Uuid) -> Result<Option<Webhook>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Getting webhook {}", id);
+        sqlx::query_as::<_, Webhook>(sqltrace::traced(
+            r#"
+            SELECT id, url, secret, events, active, created_at, updated_at
+            FROM webhooks
+            WHERE id = $1
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to get webhook {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn update_webhook(&self, webhook: &Webhook) -> Result<Option<Webhook>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Updating webhook {}", webhook.id);
+        sqlx::query_as::<_, Webhook>(sqltrace::traced(
+            r#"
+            UPDATE webhooks
+            SET url = $2, secret = $3, events = $4, active = $5, updated_at = now()
+            WHERE id = $1
+            RETURNING id, url, secret, events, active, created_at, updated_at
+            "#,
+            &[
+                &webhook.id,
+                &Redacted::text(Some(&webhook.url)),
+                &Redacted::text(Some(&webhook.secret)),
+                &webhook.events,
+                &webhook.active,
+            ],
+        ))
+        .bind(webhook.id)
+        .bind(&webhook.url)
+        .bind(&webhook.secret)
+        .bind(&webhook.events)
+        .bind(webhook.active)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to update webhook {}: {}",
+                webhook.id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn delete_webhook(&self, id: Uuid) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting webhook {}", id);
+        let result = sqlx::query(sqltrace::traced(
+            "DELETE FROM webhooks WHERE id = $1",
+            &[&id],
+        ))
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete webhook {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Logging delivery {} of webhook {}",
+            delivery.delivery_id,
+            delivery.webhook_id
+        );
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO webhook_deliveries
+                (delivery_id, webhook_id, event, todo_id, attempted_at, succeeded, status_code, duration_ms, error)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            &[
+                &delivery.delivery_id,
+                &delivery.webhook_id,
+                &delivery.event,
+                &delivery.todo_id,
+                &delivery.attempted_at,
+                &delivery.succeeded,
+                &delivery.status_code,
+                &delivery.duration_ms,
+                &delivery.error,
+            ],
+        ))
+        .bind(delivery.delivery_id)
+        .bind(delivery.webhook_id)
+        .bind(&delivery.event)
+        .bind(delivery.todo_id)
+        .bind(delivery.attempted_at)
+        .bind(delivery.succeeded)
+        .bind(delivery.status_code)
+        .bind(delivery.duration_ms)
+        .bind(&delivery.error)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to log delivery {}: {}",
+                delivery.delivery_id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(())
+    }
+
+    async fn webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching deliveries of webhook {}",
+            webhook_id
+        );
+        sqlx::query_as::<_, WebhookDelivery>(sqltrace::traced(
+            r#"
+            SELECT delivery_id, webhook_id, event, todo_id, attempted_at, succeeded, status_code, duration_ms, error
+            FROM webhook_deliveries
+            WHERE webhook_id = $1
+            ORDER BY attempted_at DESC
+            LIMIT $2
+            "#,
+            &[&webhook_id, &limit],
+        ))
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch deliveries of webhook {}: {}",
+                webhook_id,
+                e
+            );
+            TodoError::from(e)
+        })
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Listing tags");
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to list tags: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let tags = sqlx::query_as::<_, Tag>(sqltrace::traced(
+            r#"
+            SELECT tags.id, tags.name, COUNT(todos.id) AS todo_count, tags.created_at
+            FROM tags
+            LEFT JOIN todo_tags ON todo_tags.tag_id = tags.id
+            LEFT JOIN todos ON todos.id = todo_tags.todo_id
+            GROUP BY tags.id
+            ORDER BY tags.name
+            "#,
+            &[],
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(tags)
+    }
+
+    async fn get_tag(&self, id: Uuid) -> Result<Option<Tag>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching tag {}", id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to fetch tag {}: {}", id, e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let tag = sqlx::query_as::<_, Tag>(sqltrace::traced(
+            r#"
+            SELECT tags.id, tags.name, COUNT(todos.id) AS todo_count, tags.created_at
+            FROM tags
+            LEFT JOIN todo_tags ON todo_tags.tag_id = tags.id
+            LEFT JOIN todos ON todos.id = todo_tags.todo_id
+            WHERE tags.id = $1
+            GROUP BY tags.id
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(tag)
+    }
+
+    async fn create_tag(&self, tag: &Tag) -> Result<Option<Tag>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Creating tag {}", tag.name);
+        let created = sqlx::query_as::<_, Tag>(sqltrace::traced(
+            r#"
+            INSERT INTO tags (id, name, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id, name, 0::BIGINT AS todo_count, created_at
+            "#,
+            &[&tag.id, &tag.name, &tag.created_at],
+        ))
+        .bind(tag.id)
+        .bind(&tag.name)
+        .bind(tag.created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to create tag: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(created)
+    }
+
+    async fn suggest_tags(&self, text: &str, limit: i64) -> Result<Vec<Tag>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Suggesting tags for {:?}", text);
+        let pattern = format!("%{}%", autocomplete::escape_like(text));
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to suggest tags: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let tags = sqlx::query_as::<_, Tag>(sqltrace::traced(
+            r#"
+            SELECT tags.id, tags.name, COUNT(todos.id) AS todo_count, tags.created_at
+            FROM tags
+            LEFT JOIN todo_tags ON todo_tags.tag_id = tags.id
+            LEFT JOIN todos ON todos.id = todo_tags.todo_id
+            WHERE tags.name LIKE $1
+            GROUP BY tags.id
+            ORDER BY starts_with(tags.name, $2) DESC, todo_count DESC, tags.name
+            LIMIT $3
+            "#,
+            &[&pattern, &text, &limit],
+        ))
+        .bind(&pattern)
+        .bind(text)
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(tags)
+    }
+
+    async fn rename_tag(&self, id: Uuid, name: &str, merge: bool) -> Result<TagRename, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Renaming tag {} to {}", id, name);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to rename tag {}: {}", id, e);
+            TodoError::from(e)
+        };
+
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let target: Option<Uuid> = if merge {
+            sqlx::query_scalar(sqltrace::traced(
+                "SELECT id FROM tags WHERE name = $1 AND id <> $2 FOR UPDATE",
+                &[&name, &id],
+            ))
+            .bind(name)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(map_err)?
+        } else {
+            None
+        };
+        if let Some(target) = target {
+            let Some(moved) = Self::merge_tag(&mut tx, id, target)
+                .await
+                .map_err(map_err)?
+            else {
+                return Ok(TagRename::NotFound);
+            };
+            let todos = Self::todos_in(&mut tx, moved).await?;
+            tx.commit().await.map_err(map_err)?;
+            return match self.get_tag(target).await? {
+                Some(tag) => Ok(TagRename::Merged(tag, todos)),
+                None => Ok(TagRename::NotFound),
+            };
+        }
+        let renamed = sqlx::query(sqltrace::traced(
+            "UPDATE tags SET name = $2 WHERE id = $1",
+            &[&id, &name],
+        ))
+        .bind(id)
+        .bind(name)
+        .execute(&mut *tx)
+        .await;
+        match renamed {
+            Ok(result) if result.rows_affected() == 0 => return Ok(TagRename::NotFound),
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Ok(TagRename::NameTaken)
+            }
+            Err(e) => return Err(map_err(e)),
+        }
+        let renamed = Self::bump_tagged_todos(&mut tx, id)
+            .await
+            .map_err(map_err)?;
+        let todos = Self::todos_in(&mut tx, renamed).await?;
+        tx.commit().await.map_err(map_err)?;
+
+        match self.get_tag(id).await? {
+            Some(tag) => Ok(TagRename::Renamed(tag, todos)),
+            None => Ok(TagRename::NotFound),
+        }
+    }
+
+    async fn delete_tag(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting tag {}", id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to delete tag {}: {}", id, e);
+            TodoError::from(e)
+        };
+
+        let mut tx = self.begin().await.map_err(map_err)?;
+        // Before the delete cascades to todo_tags and forgets which todos carried the tag
+        let detached = Self::bump_tagged_todos(&mut tx, id)
+            .await
+            .map_err(map_err)?;
+        let deleted = sqlx::query(sqltrace::traced("DELETE FROM tags WHERE id = $1", &[&id]))
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?
+            .rows_affected()
+            > 0;
+        if !deleted {
+            return Ok(None);
+        }
+        let todos = Self::todos_in(&mut tx, detached).await?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(Some(todos))
+    }
+
+    async fn list_lists(&self) -> Result<Vec<TodoList>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Listing lists");
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to list lists: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let lists = sqlx::query_as::<_, TodoList>(sqltrace::traced(
+            r#"
+            SELECT todo_lists.id, todo_lists.name, todo_lists.description,
+                   COUNT(todos.id) AS todo_count, todo_lists.created_at, todo_lists.updated_at
+            FROM todo_lists
+            LEFT JOIN todos ON todos.list_id = todo_lists.id
+            GROUP BY todo_lists.id
+            ORDER BY todo_lists.name
+            "#,
+            &[],
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(lists)
+    }
+
+    async fn get_list(&self, id: Uuid) -> Result<Option<TodoList>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching list {}", id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to fetch list {}: {}", id, e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let list = sqlx::query_as::<_, TodoList>(sqltrace::traced(
+            r#"
+            SELECT todo_lists.id, todo_lists.name, todo_lists.description,
+                   COUNT(todos.id) AS todo_count, todo_lists.created_at, todo_lists.updated_at
+            FROM todo_lists
+            LEFT JOIN todos ON todos.list_id = todo_lists.id
+            WHERE todo_lists.id = $1
+            GROUP BY todo_lists.id
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(list)
+    }
+
+    async fn create_list(&self, list: &TodoList) -> Result<Option<TodoList>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Creating list {}", list.name);
+        let created = sqlx::query_as::<_, TodoList>(sqltrace::traced(
+            r#"
+            INSERT INTO todo_lists (id, name, description, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id, name, description, 0::BIGINT AS todo_count, created_at, updated_at
+            "#,
+            &[
+                &list.id,
+                &list.name,
+                &list.description,
+                &list.created_at,
+                &list.updated_at,
+            ],
+        ))
+        .bind(list.id)
+        .bind(&list.name)
+        .bind(&list.description)
+        .bind(list.created_at)
+        .bind(list.updated_at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to create list: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(created)
+    }
+
+    async fn update_list(
+        &self,
+        id: Uuid,
+        update: &UpdateTodoListRequest,
+    ) -> Result<ListUpdate, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Updating list {}", id);
+        let now = Utc::now();
+        let updated = sqlx::query(sqltrace::traced(
+            r#"
+            UPDATE todo_lists
+            SET name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                updated_at = $4
+            WHERE id = $1
+            "#,
+            &[&id, &update.name, &update.description, &now],
+        ))
+        .bind(id)
+        .bind(update.name.as_ref())
+        .bind(update.description.as_ref())
+        .bind(now)
+        .execute(&self.pool)
+        .await;
+        match updated {
+            Ok(result) if result.rows_affected() == 0 => return Ok(ListUpdate::NotFound),
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Ok(ListUpdate::NameTaken)
+            }
+            Err(e) => {
+                tracing::error!(
+                    "DatabaseTodoRepository: Failed to update list {}: {}",
+                    id,
+                    e
+                );
+                return Err(e.into());
+            }
+        }
+        match self.get_list(id).await? {
+            Some(list) => Ok(ListUpdate::Updated(list)),
+            None => Ok(ListUpdate::NotFound),
+        }
+    }
+
+    async fn delete_list(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting list {}", id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete list {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        };
+
+        let mut tx = self.begin().await.map_err(map_err)?;
+        // Done here rather than by ON DELETE SET NULL so the todos get a new version; those
+        // of other users are left to it
+        let moved_out = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            UPDATE todos SET list_id = NULL, version = version + 1 WHERE list_id = $1
+            RETURNING id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            "#,
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        let deleted = sqlx::query(sqltrace::traced(
+            "DELETE FROM todo_lists WHERE id = $1",
+            &[&id],
+        ))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?
+        .rows_affected()
+            > 0;
+        if !deleted {
+            return Ok(None);
+        }
+        let todos = Self::todos_in(&mut tx, moved_out).await?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(Some(todos))
+    }
+
+    async fn create_import_job(
+        &self,
+        job: &ImportJob,
+        items: &[ImportItem],
+    ) -> Result<ImportJob, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Creating import {}", job.id);
+        let items = serde_json::to_string(items)?;
+        let errors = sqlx::types::Json(&job.errors);
+        let created = sqlx::query_as::<_, ImportJob>(sqltrace::traced(
+            &format!(
+                r#"
+                INSERT INTO import_jobs
+                    (id, status, total, processed, created, errors, items, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7::JSONB, $8, $9)
+                RETURNING {IMPORT_JOB_COLUMNS}
+                "#
+            ),
+            &[
+                &job.id,
+                &job.status,
+                &job.total,
+                &job.processed,
+                &job.created,
+                &errors,
+                &Redacted::text(Some(&items)),
+                &job.created_at,
+                &job.updated_at,
+            ],
+        ))
+        .bind(job.id)
+        .bind(job.status)
+        .bind(job.total)
+        .bind(job.processed)
+        .bind(job.created)
+        .bind(errors)
+        .bind(&items)
+        .bind(job.created_at)
+        .bind(job.updated_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to create import: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(created)
+    }
+
+    async fn get_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching import {}", id);
+        let job = sqlx::query_as::<_, ImportJob>(sqltrace::traced(
+            &format!("SELECT {IMPORT_JOB_COLUMNS} FROM import_jobs WHERE id = $1"),
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch import {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(job)
+    }
+
+    async fn import_job_items(&self, id: Uuid) -> Result<Vec<ImportItem>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching the items of import {}",
+            id
+        );
+        let items: Option<sqlx::types::Json<Vec<ImportItem>>> = sqlx::query_scalar(
+            sqltrace::traced("SELECT items FROM import_jobs WHERE id = $1", &[&id]),
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch the items of import {}: {}",
+                id,
+                e
+            );
+            TodoError::from(e)
         })?;
+        Ok(items.map(|items| items.0).unwrap_or_default())
+    }
 
-        if row.is_some() {
-            tracing::debug!(
-                "DatabaseTodoRepository: Successfully updated todo with id: {}",
-                id
+    async fn update_import_job(&self, job: &ImportJob) -> Result<Option<ImportJob>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Updating import {}", job.id);
+        let errors = sqlx::types::Json(&job.errors);
+        let updated = sqlx::query_as::<_, ImportJob>(sqltrace::traced(
+            &format!(
+                r#"
+                UPDATE import_jobs
+                SET status = CASE WHEN status = 'cancelled' THEN status ELSE $2 END,
+                    processed = $3,
+                    created = $4,
+                    errors = $5,
+                    updated_at = now(),
+                    finished_at = CASE
+                        WHEN status = 'cancelled' OR $2 IN ('completed', 'cancelled')
+                        THEN COALESCE(finished_at, now())
+                    END,
+                    items = CASE
+                        WHEN status = 'cancelled' OR $2 IN ('completed', 'cancelled')
+                        THEN '[]'::JSONB
+                        ELSE items
+                    END
+                WHERE id = $1
+                RETURNING {IMPORT_JOB_COLUMNS}
+                "#
+            ),
+            &[&job.id, &job.status, &job.processed, &job.created, &errors],
+        ))
+        .bind(job.id)
+        .bind(job.status)
+        .bind(job.processed)
+        .bind(job.created)
+        .bind(errors)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to update import {}: {}",
+                job.id,
+                e
             );
-        } else {
-            tracing::debug!(
-                "DatabaseTodoRepository: Todo not found for update with id: {}",
-                id
+            TodoError::from(e)
+        })?;
+        Ok(updated)
+    }
+
+    async fn cancel_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Cancelling import {}", id);
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to cancel import {}: {}",
+                id,
+                e
             );
+            TodoError::from(e)
+        };
+        let cancelled = sqlx::query_as::<_, ImportJob>(sqltrace::traced(
+            &format!(
+                r#"
+                UPDATE import_jobs
+                SET status = 'cancelled', items = '[]'::JSONB, updated_at = now(),
+                    finished_at = now()
+                WHERE id = $1 AND status IN ('queued', 'running')
+                RETURNING {IMPORT_JOB_COLUMNS}
+                "#
+            ),
+            &[&id],
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_err)?;
+        match cancelled {
+            Some(job) => Ok(Some(job)),
+            None => self.get_import_job(id).await,
         }
-        Ok(row)
     }
 
-    async fn delete_todo(&self, id: Uuid) -> Result<bool, TodoError> {
-        tracing::debug!("DatabaseTodoRepository: Deleting todo with id: {}", id);
-        let result = sqlx::query(
+    async fn aging_report(&self) -> Result<AgingReport, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Building aging report");
+        let [day, week, month] = stats::AGE_BUCKET_BOUNDS;
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to build aging report: {}",
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let counts: (i64, i64, i64, i64) = sqlx::query_as(sqltrace::traced(
             r#"
-            DELETE FROM todos
-            WHERE id = $1
+            SELECT
+                COUNT(*) FILTER (WHERE age < make_interval(days => $1)),
+                COUNT(*) FILTER (WHERE age >= make_interval(days => $1)
+                                   AND age < make_interval(days => $2)),
+                COUNT(*) FILTER (WHERE age >= make_interval(days => $2)
+                                   AND age < make_interval(days => $3)),
+                COUNT(*) FILTER (WHERE age >= make_interval(days => $3))
+            FROM (
+                SELECT NOW() - created_at AS age
+                FROM todos
+                WHERE completed IS NOT TRUE
+            ) open_todos
             "#,
-        )
-        .bind(id)
-        .execute(&self.pool)
+            &[&day, &week, &month],
+        ))
+        .bind(day)
+        .bind(week)
+        .bind(month)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+
+        Ok(AgingReport::from_counts([
+            counts.0, counts.1, counts.2, counts.3,
+        ]))
+    }
+
+    async fn workload_totals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<WorkloadTotals, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Summing estimates from {} to {}",
+            from,
+            to
+        );
+        let map_err = |e: sqlx::Error| {
+            tracing::error!("DatabaseTodoRepository: Failed to sum estimates: {}", e);
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let (todos, unestimated, estimate_minutes, remaining_minutes): (i64, i64, i64, i64) =
+            sqlx::query_as(sqltrace::traced(
+                r#"
+                SELECT
+                    COUNT(*),
+                    COUNT(*) FILTER (WHERE estimate_minutes IS NULL),
+                    COALESCE(SUM(estimate_minutes), 0)::BIGINT,
+                    COALESCE(SUM(estimate_minutes) FILTER (WHERE completed IS NOT TRUE), 0)::BIGINT
+                FROM todos
+                WHERE created_at >= $1 AND created_at < $2
+                "#,
+                &[&from, &to],
+            ))
+            .bind(from)
+            .bind(to)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+
+        Ok(WorkloadTotals {
+            todos,
+            unestimated,
+            estimate_minutes,
+            remaining_minutes,
+        })
+    }
+
+    async fn completion_days(&self, user: Uuid) -> Result<Vec<CompletionDay>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching completion days of user {}",
+            user
+        );
+        let mut tx = self.pool.begin().await?;
+        Self::act_in(&mut tx, user).await?;
+        let days = sqlx::query_as::<_, CompletionDay>(sqltrace::traced(
+            "SELECT day, completions FROM completion_days WHERE user_id = $1 ORDER BY day",
+            &[&user],
+        ))
+        .bind(user)
+        .fetch_all(&mut *tx)
         .await
         .map_err(|e| {
             tracing::error!(
-                "DatabaseTodoRepository: Failed to delete todo with id {}: {}",
-                id,
+                "DatabaseTodoRepository: Failed to fetch completion days: {}",
                 e
             );
-            Box::new(e) as TodoError
+            TodoError::from(e)
         })?;
+        tx.commit().await?;
+        Ok(days)
+    }
 
-        let deleted = result.rows_affected() > 0;
-        if deleted {
-            tracing::debug!(
-                "DatabaseTodoRepository: Successfully deleted todo with id: {}",
-                id
+    async fn set_plan(
+        &self,
+        user: Uuid,
+        day: NaiveDate,
+        todo_ids: &[TodoId],
+    ) -> Result<(), TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Planning {} todos for {} of user {}",
+            todo_ids.len(),
+            day,
+            user
+        );
+        let mut tx = self.pool.begin().await?;
+        Self::act_in(&mut tx, user).await?;
+        sqlx::query(sqltrace::traced(
+            "DELETE FROM daily_plans WHERE user_id = $1 AND day = $2",
+            &[&user, &day],
+        ))
+        .bind(user)
+        .bind(day)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(sqltrace::traced(
+            r#"
+            INSERT INTO daily_plans (user_id, day, position, todo_id)
+            SELECT $1, $2, planned.position, planned.todo_id
+            FROM UNNEST($3::uuid[]) WITH ORDINALITY AS planned(todo_id, position)
+            "#,
+            &[&user, &day, &todo_ids],
+        ))
+        .bind(user)
+        .bind(day)
+        .bind(todo_ids)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to save the plan for {}: {}",
+                day,
+                e
             );
-        } else {
-            tracing::debug!(
-                "DatabaseTodoRepository: Todo not found for deletion with id: {}",
-                id
+            TodoError::from(e)
+        })?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn plan_todos(&self, user: Uuid, day: NaiveDate) -> Result<Vec<Todo>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching the plan for {} of user {}",
+            day,
+            user
+        );
+        let mut tx = self.pool.begin().await?;
+        Self::act_in(&mut tx, user).await?;
+        let rows = sqlx::query_as::<_, TodoRow>(sqltrace::traced(
+            r#"
+            SELECT todos.id, title, content, content_zstd, completed, version, metadata, labels, estimate_minutes, priority, list_id, archived, owner_id, created_at, updated_at
+            FROM daily_plans
+            JOIN todos ON todos.id = daily_plans.todo_id
+            WHERE daily_plans.user_id = $1 AND daily_plans.day = $2
+            ORDER BY daily_plans.position
+            "#,
+            &[&user, &day],
+        ))
+        .bind(user)
+        .bind(day)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch the plan for {}: {}",
+                day,
+                e
             );
-        }
-        Ok(deleted)
+            TodoError::from(e)
+        })?;
+        tx.commit().await?;
+        let mut todos = rows
+            .into_iter()
+            .map(TodoRow::into_todo)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.with_tags(&mut todos).await?;
+        Ok(todos)
+    }
+
+    async fn relations_of(&self, todo_id: TodoId) -> Result<Vec<RelationSummary>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Fetching relations of todo {}",
+            todo_id
+        );
+        let map_err = |e: sqlx::Error| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to fetch relations of todo {}: {}",
+                todo_id,
+                e
+            );
+            TodoError::from(e)
+        };
+        let mut tx = self.begin().await.map_err(map_err)?;
+        let rows = sqlx::query_as::<_, relations::RelationRow>(sqltrace::traced(
+            r#"
+            SELECT todo_relations.id, todo_relations.todo_id, todo_relations.related_id,
+                   todo_relations.kind, todo_relations.created_at, todos.title, todos.completed
+            FROM todo_relations
+            JOIN todos ON todos.id = CASE
+                WHEN todo_relations.todo_id = $1 THEN todo_relations.related_id
+                ELSE todo_relations.todo_id
+            END
+            WHERE todo_relations.todo_id = $1 OR todo_relations.related_id = $1
+            ORDER BY todo_relations.created_at, todo_relations.id
+            "#,
+            &[&todo_id],
+        ))
+        .bind(todo_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        tx.commit().await.map_err(map_err)?;
+        Ok(rows.into_iter().map(|row| row.summary(todo_id)).collect())
+    }
+
+    async fn create_relation(
+        &self,
+        relation: &TodoRelation,
+    ) -> Result<Option<TodoRelation>, TodoError> {
+        tracing::debug!(
+            "DatabaseTodoRepository: Relating todo {} to {}",
+            relation.todo_id,
+            relation.related_id
+        );
+        let created = sqlx::query_as::<_, TodoRelation>(sqltrace::traced(
+            r#"
+            INSERT INTO todo_relations (id, todo_id, related_id, kind, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            RETURNING id, todo_id, related_id, kind, created_at
+            "#,
+            &[
+                &relation.id,
+                &relation.todo_id,
+                &relation.related_id,
+                &relation.kind,
+                &relation.created_at,
+            ],
+        ))
+        .bind(relation.id)
+        .bind(relation.todo_id)
+        .bind(relation.related_id)
+        .bind(relation.kind)
+        .bind(relation.created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to create relation: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(created)
+    }
+
+    async fn delete_relation(&self, todo_id: TodoId, relation_id: Uuid) -> Result<bool, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Deleting relation {}", relation_id);
+        let result = sqlx::query(sqltrace::traced(
+            "DELETE FROM todo_relations WHERE id = $2 AND (todo_id = $1 OR related_id = $1)",
+            &[&todo_id, &relation_id],
+        ))
+        .bind(todo_id)
+        .bind(relation_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "DatabaseTodoRepository: Failed to delete relation {}: {}",
+                relation_id,
+                e
+            );
+            TodoError::from(e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_user(&self, user: &User) -> Result<Option<User>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Creating user {}", user.id);
+        let created = sqlx::query_as::<_, User>(sqltrace::traced(
+            r#"
+            INSERT INTO users (id, email, password_hash, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING id, email, password_hash, created_at
+            "#,
+            &[&user.id, &user.email, &user.password_hash, &user.created_at],
+        ))
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(&user.password_hash)
+        .bind(user.created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to create user: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(created)
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, TodoError> {
+        tracing::debug!("DatabaseTodoRepository: Fetching user by email");
+        let user = sqlx::query_as::<_, User>(sqltrace::traced(
+            "SELECT id, email, password_hash, created_at FROM users WHERE email = $1",
+            &[&email],
+        ))
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("DatabaseTodoRepository: Failed to fetch user: {}", e);
+            TodoError::from(e)
+        })?;
+        Ok(user)
+    }
+
+    async fn ping(&self) -> Result<(), TodoError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("DatabaseTodoRepository: Database is not reachable: {}", e);
+                TodoError::from(e)
+            })?;
+        Ok(())
     }
 }
 
@@ -415,214 +4720,977 @@ pub async fn health_check() -> &'static str {
     "OK"
 }
 
+/// Body of `/health/ready`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Readiness {
+    #[schema(example = true)]
+    pub ready: bool,
+    #[schema(example = true)]
+    pub database: bool,
+    /// Applied and pending schema migrations; `null` unless MIGRATIONS_DIR is set
+    pub migrations: Option<MigrationStatus>,
+    /// Every configured dependency, the database first
+    pub dependencies: Vec<DependencyStatus>,
+}
+
+/// Unlike `/health`, which only shows the process is up, this also checks the database and
+/// the other configured dependencies and, with MIGRATIONS_DIR set, that no migration is
+/// pending, so a new version takes no traffic before the schema it expects is in place
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Server can serve requests; optional dependencies may be unhealthy", body = Readiness),
+        (status = 503, description = "Database or another required dependency unavailable, or migrations pending", body = Readiness)
+    ),
+    tag = "Health"
+)]
+pub async fn readiness_check<R: TodoRepositoryTrait + 'static>(
+    State(repository): State<Arc<R>>,
+    Extension(migrator): Extension<Option<Migrator>>,
+    Extension(checks): Extension<Arc<DependencyChecks>>,
+) -> (StatusCode, Json<Readiness>) {
+    let dependencies = checks.statuses(&DatabaseCheck(repository)).await;
+    let database = dependencies[0].healthy;
+    let mut ready = dependencies
+        .iter()
+        .all(|dependency| dependency.healthy || !dependency.required);
+    if !ready {
+        tracing::warn!("Not ready: a required dependency is unhealthy");
+    }
+    let mut migrations = None;
+    if let (Some(migrator), true) = (migrator, database) {
+        match migrator.status().await {
+            Ok(status) => {
+                if !status.is_current() {
+                    tracing::warn!("Not ready: {} migrations pending", status.pending.len());
+                    ready = false;
+                }
+                migrations = Some(status);
+            }
+            Err(e) => {
+                tracing::warn!("Not ready: cannot read migration history: {}", e);
+                ready = false;
+            }
+        }
+    }
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            database,
+            migrations,
+            dependencies,
+        }),
+    )
+}
+
+/// Filter and order of `GET /api/todos` and `GET /api/lists/:id/todos`
+pub(crate) fn filter_from_query(
+    params: &HashMap<String, String>,
+    stale: &StaleConfig,
+) -> Result<TodoFilter, TodoError> {
+    let label = match params.get("label").map(|label| labels::normalize(label)) {
+        Some(Ok(label)) => Some(label),
+        Some(Err(e)) => {
+            tracing::warn!("Invalid label filter: {}", e);
+            return Err(TodoError::Validation(e));
+        }
+        None => None,
+    };
+    let tag = params
+        .get("tag")
+        .map(|tag| tags::normalize(tag))
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Invalid tag filter: {}", e);
+            TodoError::Validation(e)
+        })?;
+    let priority = params
+        .get("priority")
+        .map(|priority| Priority::parse_list(priority))
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Invalid priority filter: {}", e);
+            TodoError::Validation(e)
+        })?
+        .unwrap_or_default();
+    let include_archived = match params.get("include_archived").map(String::as_str) {
+        None | Some("false") | Some("0") => false,
+        Some("true") | Some("1") => true,
+        Some(other) => {
+            let message = format!("include_archived must be true or false, got {:?}", other);
+            tracing::warn!("{}", message);
+            return Err(TodoError::Validation(message));
+        }
+    };
+    let stale = match params.get("stale").map(String::as_str) {
+        None => None,
+        Some("true") | Some("1") => Some(true),
+        Some("false") | Some("0") => Some(false),
+        Some(other) => {
+            let message = format!("stale must be true or false, got {:?}", other);
+            tracing::warn!("{}", message);
+            return Err(TodoError::Validation(message));
+        }
+    }
+    .map(|wanted| StaleFilter {
+        stale: wanted,
+        cutoff: stale.cutoff(Utc::now()),
+    });
+    let (sort, order) = TodoSort::from_query(params).map_err(|e| {
+        tracing::warn!("Invalid sort: {}", e);
+        TodoError::Validation(e)
+    })?;
+    Ok(TodoFilter {
+        metadata: metadata::filters_from_query(params),
+        label,
+        tag,
+        priority,
+        list_id: None,
+        include_archived,
+        stale,
+        sort,
+        order,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos",
+    params(
+        ("label" = Option<String>, Query, description = "Label path; also matches child labels"),
+        ("tag" = Option<String>, Query, description = "Tag name; keeps todos carrying the tag"),
+        ("priority" = Option<String>, Query, description = "Comma-separated priorities, e.g. high,urgent"),
+        ("include_archived" = Option<bool>, Query, description = "Also list archived todos (default false)"),
+        ("stale" = Option<bool>, Query, description = "Only open todos untouched for `STALE_AFTER_DAYS` (true) or only the others (false)"),
+        ("meta.sprint" = Option<String>, Query, description = "Example metadata filter; any `meta.<key>=<value>` parameter keeps todos whose metadata value matches"),
+        ("cursor" = Option<Uuid>, Query, description = "Return the page after this todo; pass the previous response's `next_cursor`"),
+        ("limit" = Option<i64>, Query, description = "Page size (1-200, default 50); enables cursor pagination. Cannot be combined with filters"),
+        ("sort" = Option<TodoSort>, Query, description = "Sort key (default created_at)"),
+        ("order" = Option<SortOrder>, Query, description = "Direction; defaults to asc for title and desc otherwise"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a previous response; 304 while the list is unchanged"),
+        ("If-Modified-Since" = Option<String>, Header, description = "Last-Modified of a previous response; only used without If-None-Match")
+    ),
+    responses(
+        (status = 200, description = "List of todos", body = TodoListResponse,
+            headers(
+                ("ETag" = String, description = "Hash of the response body"),
+                ("Last-Modified" = String, description = "Latest updated_at among the listed todos")
+            )),
+        (status = 304, description = "The list has not changed"),
+        (status = 400, description = "Invalid filter, sort, cursor or limit", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_todos<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(stale): Extension<Arc<StaleConfig>>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, TodoError> {
+    let Json(response) = list_todos(repository.as_ref(), &stale, &params).await?;
+    let todos = response.data.as_deref().unwrap_or_default();
+    Ok(preconditions::conditional_list(&headers, todos, &response))
+}
+
+async fn list_todos<R: TodoRepositoryTrait>(
+    repository: &R,
+    stale: &StaleConfig,
+    params: &HashMap<String, String>,
+) -> Result<Json<TodoListResponse>, TodoError> {
+    let filter = filter_from_query(params, stale)?;
+    let (sort, order) = (filter.sort, filter.order);
+    let page = PageRequest::from_query(params).map_err(|e| {
+        tracing::warn!("Invalid page request: {}", e);
+        TodoError::Validation(e)
+    })?;
+    if let Some(page) = page {
+        if !filter.is_empty() {
+            tracing::warn!("Cursor pagination cannot be combined with filters");
+            return Err(TodoError::Validation(
+                "Cursor pagination cannot be combined with filters".to_string(),
+            ));
+        }
+        return get_todo_page(
+            repository,
+            page,
+            sort,
+            order,
+            filter.include_archived,
+            stale,
+        )
+        .await;
+    }
+    let everything = TodoFilter {
+        include_archived: true,
+        ..TodoFilter::default()
+    };
+    let result = if filter == everything {
+        tracing::info!("Getting all todos");
+        repository.get_all_todos().await
+    } else {
+        tracing::info!("Getting todos matching {:?}", filter);
+        repository.find_todos(&filter).await
+    };
+    match result {
+        Ok(mut todos) => {
+            tracing::info!("Successfully retrieved {} todos", todos.len());
+            stale.mark(&mut todos);
+            Ok(Json(ApiResponse::success(todos).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get todos: {}", e);
+            Err(e)
+        }
+    }
+}
+
+async fn get_todo_page<R: TodoRepositoryTrait>(
+    repository: &R,
+    page: PageRequest,
+    sort: TodoSort,
+    order: SortOrder,
+    include_archived: bool,
+    stale: &StaleConfig,
+) -> Result<Json<TodoListResponse>, TodoError> {
+    tracing::info!("Getting {} todos after {:?}", page.limit, page.cursor);
+    let internal_error = |e: TodoError| {
+        tracing::error!("Failed to get todo page: {}", e);
+        e
+    };
+    let mut todos = repository
+        .get_todos_after(page.cursor, page.limit, sort, order, include_archived)
+        .await
+        .map_err(internal_error)?;
+    // An empty page after a cursor that no longer exists would look like the end
+    if let (true, Some(cursor)) = (todos.is_empty(), page.cursor) {
+        if repository
+            .get_todo_by_id(cursor)
+            .await
+            .map_err(internal_error)?
+            .is_none()
+        {
+            tracing::warn!("Unknown page cursor: {}", cursor);
+            return Err(TodoError::Validation(format!(
+                "Unknown page cursor: {}",
+                cursor
+            )));
+        }
+    }
+
+    let next_cursor = (todos.len() as i64 == page.limit)
+        .then(|| todos.last().map(|todo| todo.id))
+        .flatten();
+    tracing::info!("Successfully retrieved page of {} todos", todos.len());
+    stale.mark(&mut todos);
+    let mut response: TodoListResponse = ApiResponse::success(todos).into();
+    response.next_cursor = next_cursor;
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos",
+    request_body = CreateTodoRequest,
+    responses(
+        (status = 200, description = "Todo created successfully", body = TodoResponse),
+        (status = 400, description = "Bad request - validation failed, or the todo breaks the validation policy or a content policy", body = ErrorResponse),
+        (status = 409, description = "A todo with the client-chosen id already exists", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn create_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Json(request): Json<CreateTodoRequest>,
+) -> Result<Json<TodoResponse>, TodoError> {
+    tracing::info!("Creating new todo with title: {:?}", request.title);
+
+    let todo = prepare_todo(repository.as_ref(), &emoji, request).await?;
+    let findings = moderator.review(Some(todo.title.as_str()), Some(todo.content.as_str()))?;
+
+    match repository.create_todo(&todo).await {
+        Ok(created_todo) => {
+            tracing::info!("Successfully created todo with id: {}", created_todo.id);
+            moderation::record(repository.as_ref(), created_todo.id, &findings).await;
+            let response = TodoResponse::from(ApiResponse::success(created_todo));
+            Ok(Json(response.with_warnings(&findings)))
+        }
+        Err(e @ TodoError::Conflict(_)) => {
+            tracing::warn!("Refused to create todo {}: {}", todo.id, e);
+            Err(e)
+        }
+        Err(e) => {
+            tracing::error!("Failed to create todo: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a previous response; 304 while the todo is at that version"),
+        ("If-Modified-Since" = Option<String>, Header, description = "Last-Modified of a previous response; only used without If-None-Match")
+    ),
+    responses(
+        (status = 200, description = "Todo found", body = TodoResponse,
+            headers(
+                ("ETag" = String, description = "Version of the todo, for If-Match and If-None-Match"),
+                ("Last-Modified" = String, description = "updated_at of the todo")
+            )),
+        (status = 304, description = "The todo has not changed"),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(stale): Extension<Arc<StaleConfig>>,
+    Path(id): Path<TodoId>,
+    headers: HeaderMap,
+) -> Result<Response, TodoError> {
+    tracing::info!("Getting todo with id: {}", id);
+    match repository.get_todo_by_id(id).await {
+        Ok(Some(mut todo)) => {
+            tracing::info!("Successfully retrieved todo with id: {}", id);
+            stale.mark(std::slice::from_mut(&mut todo));
+            todo.relations = Some(repository.relations_of(id).await?);
+            let body = Json(TodoResponse::from(ApiResponse::success(todo.clone())));
+            Ok(preconditions::conditional_todo(&headers, &todo, body))
+        }
+        Ok(None) => {
+            tracing::warn!("Todo not found with id: {}", id);
+            Err(TodoError::NotFound(format!("Todo {}", id)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get todo with id {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+/// Records the validation failure of a repository check against `field`; any other
+/// failure is returned
+fn collect_field_error(
+    errors: &mut ValidationErrors,
+    field: &str,
+    result: Result<(), TodoError>,
+) -> Result<(), TodoError> {
+    match result {
+        Err(TodoError::Validation(e)) => {
+            errors.add(field, FieldErrorCode::Invalid, e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// The todo a full body describes, validated against the repository and the validation
+/// policy
+async fn prepare_todo<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    emoji: &EmojiConfig,
+    request: CreateTodoRequest,
+) -> Result<Todo, TodoError> {
+    let mut errors = request.field_errors();
+    let metadata = request.metadata.clone().unwrap_or_else(|| json!({}));
+    collect_field_error(
+        &mut errors,
+        "metadata",
+        metadata::check_metadata(repository, &metadata).await,
+    )?;
+    collect_field_error(
+        &mut errors,
+        "list_id",
+        lists::check_list(repository, request.list_id).await,
+    )?;
+    if !errors.is_empty() {
+        tracing::warn!("Validation failed for todo: {}", errors);
+        return Err(errors.into());
+    }
+    let todo = request.into_todo(emoji).map_err(TodoError::Validation)?;
+    let policy = policy::load(repository).await?;
+    policy.check(&todo).into_result().map_err(|errors| {
+        tracing::warn!("Todo breaks the validation policy: {}", errors);
+        TodoError::from(errors)
+    })?;
+    Ok(todo)
+}
+
+/// Validates an update and normalizes it the way todos are stored
+pub(crate) async fn prepare_update<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    emoji: &EmojiConfig,
+    request: &mut UpdateTodoRequest,
+) -> Result<(), TodoError> {
+    let mut errors = request.field_errors();
+    if let Some(metadata) = &request.metadata {
+        collect_field_error(
+            &mut errors,
+            "metadata",
+            metadata::check_metadata(repository, metadata).await,
+        )?;
+    }
+    collect_field_error(
+        &mut errors,
+        "list_id",
+        lists::check_list(repository, request.list_id.flatten()).await,
+    )?;
+    if !errors.is_empty() {
+        tracing::warn!("Validation failed for update todo request: {}", errors);
+        return Err(errors.into());
+    }
+    request.title = request.title.as_deref().map(Todo::normalize_title);
+    request.content = request
+        .content
+        .as_deref()
+        .map(|content| emoji.prepare_content(&unicode::nfc(content)));
+    if let Some(labels) = &request.labels {
+        request.labels = Some(labels::normalize_all(labels).map_err(TodoError::Validation)?);
+    }
+    for names in [
+        &mut request.tags,
+        &mut request.add_tags,
+        &mut request.remove_tags,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *names = tags::normalize_all(names).map_err(TodoError::Validation)?;
+    }
+    Ok(())
+}
+
 #[utoipa::path(
-    get,
-    path = "/api/todos",
+    patch,
+    path = "/api/todos/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the todo must still have; required with REQUIRE_IF_MATCH")
+    ),
+    request_body = UpdateTodoRequest,
     responses(
-        (status = 200, description = "List of todos", body = TodoListResponse),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Todo updated successfully; with MERGE_COMPLETED_SUBTASKS, completing the last open subtask of a todo also archives it", body = TodoResponse,
+            headers(("ETag" = String, description = "Version of the updated todo"))),
+        (status = 400, description = "Bad request - validation failed, or the update breaks the validation policy or a content policy", body = ErrorResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 412, description = "The todo has changed since the If-Match ETag", body = ErrorResponse),
+        (status = 428, description = "If-Match is missing and required", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Todos"
 )]
-pub async fn get_todos<R: TodoRepositoryTrait>(
+#[allow(clippy::too_many_arguments)]
+pub async fn update_todo<R: TodoRepositoryTrait>(
     State(repository): State<Arc<R>>,
-) -> Result<Json<TodoListResponse>, StatusCode> {
-    tracing::info!("Getting all todos");
-    match repository.get_all_todos().await {
-        Ok(todos) => {
-            tracing::info!("Successfully retrieved {} todos", todos.len());
-            Ok(Json(ApiResponse::success(todos).into()))
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(preconditions): Extension<Arc<PreconditionConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Extension(subtasks): Extension<Arc<SubtaskConfig>>,
+    Path(id): Path<TodoId>,
+    headers: HeaderMap,
+    Json(mut request): Json<UpdateTodoRequest>,
+) -> Result<(HeaderMap, Json<TodoResponse>), TodoError> {
+    tracing::info!("Updating todo with id: {}", id);
+
+    let expected_version = preconditions.expected_version(&headers, id)?;
+    prepare_update(repository.as_ref(), &emoji, &mut request).await?;
+    let policy = policy::load(repository.as_ref()).await?;
+    policy::check_update(repository.as_ref(), &policy, id, &request).await?;
+    let findings = moderator.review(request.title.as_deref(), request.content.as_deref())?;
+
+    match repository.update_todo(id, &request, expected_version).await {
+        Ok(Some(mut todo)) => {
+            tracing::info!("Successfully updated todo with id: {}", id);
+            moderation::record(repository.as_ref(), id, &findings).await;
+            if request.completed == Some(true) {
+                let todo = std::slice::from_mut(&mut todo);
+                subtasks::merge_completed(repository.as_ref(), &moderator, &subtasks, todo).await;
+            }
+            let headers = etag_headers(&todo);
+            let response = TodoResponse::from(ApiResponse::success(todo));
+            Ok((headers, Json(response.with_warnings(&findings))))
+        }
+        Ok(None) => {
+            tracing::warn!("Todo not found for update with id: {}", id);
+            Err(TodoError::NotFound(format!("Todo {}", id)))
+        }
+        Err(e @ TodoError::PreconditionFailed(_)) => {
+            tracing::warn!("Refused stale update of todo {}: {}", id, e);
+            Err(e)
         }
         Err(e) => {
-            tracing::error!("Failed to get todos: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            tracing::error!("Failed to update todo with id {}: {}", id, e);
+            Err(e)
         }
     }
 }
 
 #[utoipa::path(
-    post,
-    path = "/api/todos",
-    request_body = CreateTodoRequest,
+    put,
+    path = "/api/todos/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID; a UUIDv7 when the todo is new"),
+        ("If-Match" = Option<String>, Header, description = "ETag the todo must still have; required with REQUIRE_IF_MATCH")
+    ),
+    request_body = ReplaceTodoRequest,
     responses(
-        (status = 200, description = "Todo created successfully", body = TodoResponse),
-        (status = 400, description = "Bad request - validation failed"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Todo replaced; with MERGE_COMPLETED_SUBTASKS, completing the last open subtask of a todo also archives it", body = TodoResponse,
+            headers(("ETag" = String, description = "Version of the todo"))),
+        (status = 201, description = "Todo created", body = TodoResponse,
+            headers(("ETag" = String, description = "Version of the todo"))),
+        (status = 400, description = "Bad request - validation failed, or the todo breaks the validation policy or a content policy", body = ErrorResponse),
+        (status = 409, description = "The todo was created meanwhile by another request", body = ErrorResponse),
+        (status = 412, description = "The todo does not exist or has changed since the If-Match ETag", body = ErrorResponse),
+        (status = 428, description = "If-Match is missing and required", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Todos"
 )]
-pub async fn create_todo<R: TodoRepositoryTrait>(
+#[allow(clippy::too_many_arguments)]
+pub async fn put_todo<R: TodoRepositoryTrait>(
     State(repository): State<Arc<R>>,
-    Json(request): Json<CreateTodoRequest>,
-) -> Result<Json<TodoResponse>, StatusCode> {
-    tracing::info!("Creating new todo with title: '{}'", request.title);
-
-    if let Err(e) = request.validate() {
-        tracing::warn!("Validation failed for create todo request: {}", e);
-        return Err(StatusCode::BAD_REQUEST);
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(preconditions): Extension<Arc<PreconditionConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Extension(subtasks): Extension<Arc<SubtaskConfig>>,
+    Path(id): Path<TodoId>,
+    headers: HeaderMap,
+    Json(mut request): Json<ReplaceTodoRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<TodoResponse>), TodoError> {
+    tracing::info!("Writing todo with id: {}", id);
+
+    let expected_version = preconditions.expected_version(&headers, id)?;
+    if request.todo.id.is_some_and(|body_id| body_id != id) {
+        let mut errors = ValidationErrors::default();
+        errors.add(
+            "id",
+            FieldErrorCode::Invalid,
+            format!("Todo id must be the one in the path, {}", id),
+        );
+        tracing::warn!("Validation failed for todo {}: {}", id, errors);
+        return Err(errors.into());
     }
-
-    let todo = Todo::new(&request.title, &request.content);
-
-    match repository.create_todo(&todo).await {
-        Ok(created_todo) => {
-            tracing::info!("Successfully created todo with id: {}", created_todo.id);
-            Ok(Json(ApiResponse::success(created_todo).into()))
+    let exists = repository.get_todo_by_id(id).await?.is_some();
+    if expected_version.is_some() && !exists {
+        tracing::warn!("Refused to replace todo {}: it does not exist", id);
+        return Err(TodoError::PreconditionFailed(format!(
+            "Todo {} does not exist",
+            id
+        )));
+    }
+    // New todos take a client id, held to the rules of POST; stored ones keep theirs
+    request.todo.id = (!exists).then_some(id);
+    let mut todo = prepare_todo(repository.as_ref(), &emoji, request.todo).await?;
+    todo.id = id;
+    todo.completed = request.completed;
+    todo.archived = request.archived;
+    let findings = moderator.review(Some(todo.title.as_str()), Some(todo.content.as_str()))?;
+
+    let (status, mut todo) = match repository.upsert_todo(&todo, expected_version).await {
+        Ok(TodoUpsert::Created(todo)) => {
+            tracing::info!("Successfully created todo with id: {}", id);
+            (StatusCode::CREATED, todo)
+        }
+        Ok(TodoUpsert::Replaced(todo)) => {
+            tracing::info!("Successfully replaced todo with id: {}", id);
+            (StatusCode::OK, todo)
+        }
+        Err(e @ (TodoError::Conflict(_) | TodoError::PreconditionFailed(_))) => {
+            tracing::warn!("Refused to write todo {}: {}", id, e);
+            return Err(e);
         }
         Err(e) => {
-            tracing::error!("Failed to create todo: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            tracing::error!("Failed to write todo with id {}: {}", id, e);
+            return Err(e);
         }
+    };
+    moderation::record(repository.as_ref(), id, &findings).await;
+    if todo.completed {
+        let todo = std::slice::from_mut(&mut todo);
+        subtasks::merge_completed(repository.as_ref(), &moderator, &subtasks, todo).await;
     }
+    let headers = etag_headers(&todo);
+    let response = TodoResponse::from(ApiResponse::success(todo));
+    Ok((status, headers, Json(response.with_warnings(&findings))))
 }
 
 #[utoipa::path(
-    get,
-    path = "/api/todos/{id}",
+    patch,
+    path = "/api/todos/{id}/content",
     params(
         ("id" = Uuid, Path, description = "Todo ID")
     ),
+    request_body = UpdateTodoContentRequest,
     responses(
-        (status = 200, description = "Todo found", body = TodoResponse),
-        (status = 404, description = "Todo not found"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Content saved (merged if the base version was stale)", body = TodoResponse),
+        (status = 400, description = "Bad request - validation failed, or a content policy rejects the content", body = ErrorResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 409, description = "Edit conflicts with a newer version; server todo returned in data", body = TodoResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Todos"
 )]
-pub async fn get_todo<R: TodoRepositoryTrait>(
+pub async fn update_todo_content<R: TodoRepositoryTrait>(
     State(repository): State<Arc<R>>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<TodoResponse>, StatusCode> {
-    tracing::info!("Getting todo with id: {}", id);
-    match repository.get_todo_by_id(id).await {
-        Ok(Some(todo)) => {
-            tracing::info!("Successfully retrieved todo with id: {}", id);
-            Ok(Json(ApiResponse::success(todo).into()))
+    Extension(emoji): Extension<Arc<EmojiConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Path(id): Path<TodoId>,
+    Json(mut request): Json<UpdateTodoContentRequest>,
+) -> Result<Json<TodoResponse>, (StatusCode, Json<TodoResponse>)> {
+    tracing::info!(
+        "Updating content of todo with id: {} based on version {}",
+        id,
+        request.base_version
+    );
+
+    let error = |e: TodoError| (e.status(), Json(e.body::<Todo>().into()));
+
+    // The merge base goes through the same steps so a stale edit merges against
+    // what was actually stored
+    let content = Todo::check_content(&request.content)
+        .and_then(|()| TodoContent::new(emoji.prepare_content(&unicode::nfc(&request.content))))
+        .map_err(|e| {
+            tracing::warn!("Validation failed for update todo content request: {}", e);
+            error(TodoError::Validation(e.message))
+        })?;
+    let findings = moderator
+        .review(None, Some(content.as_str()))
+        .map_err(error)?;
+    request.base_content = request
+        .base_content
+        .as_deref()
+        .map(|base| emoji.prepare_content(&unicode::nfc(base)));
+
+    let internal_error = |e: TodoError| {
+        tracing::error!("Failed to update content of todo with id {}: {}", id, e);
+        error(e)
+    };
+
+    let outcome = repository
+        .update_todo_content(id, &content, request.base_version)
+        .await
+        .map_err(internal_error)?;
+
+    // A stale edit gets one merge attempt against the latest server content
+    let outcome = match (outcome, request.base_content.as_deref()) {
+        (ContentUpdate::Conflict(current), Some(base)) => {
+            match merge_content(base, &current.content, &content)
+                .and_then(|merged| TodoContent::new(merged).ok())
+            {
+                Some(merged) => {
+                    tracing::debug!(
+                        "Merged stale edit of todo {} onto version {}",
+                        id,
+                        current.version
+                    );
+                    repository
+                        .update_todo_content(id, &merged, current.version)
+                        .await
+                        .map_err(internal_error)?
+                }
+                None => ContentUpdate::Conflict(current),
+            }
         }
-        Ok(None) => {
-            tracing::warn!("Todo not found with id: {}", id);
-            Err(StatusCode::NOT_FOUND)
+        (outcome, _) => outcome,
+    };
+
+    match outcome {
+        ContentUpdate::Updated(todo) => {
+            tracing::info!(
+                "Successfully updated content of todo with id: {} to version {}",
+                id,
+                todo.version
+            );
+            moderation::record(repository.as_ref(), id, &findings).await;
+            let response = TodoResponse::from(ApiResponse::success(todo));
+            Ok(Json(response.with_warnings(&findings)))
         }
-        Err(e) => {
-            tracing::error!("Failed to get todo with id {}: {}", id, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        ContentUpdate::Conflict(current) => {
+            tracing::warn!(
+                "Content conflict for todo {}: base version {}, current version {}",
+                id,
+                request.base_version,
+                current.version
+            );
+            Err((
+                StatusCode::CONFLICT,
+                Json(TodoResponse {
+                    success: false,
+                    data: Some(current),
+                    error: Some("Content was modified by another client".to_string()),
+                    code: Some(ErrorCode::Conflict),
+                    warnings: Vec::new(),
+                }),
+            ))
+        }
+        ContentUpdate::NotFound => {
+            tracing::warn!("Todo not found for content update with id: {}", id);
+            Err(error(TodoError::NotFound(format!("Todo {}", id))))
         }
     }
 }
 
 #[utoipa::path(
-    patch,
-    path = "/api/todos/{id}",
+    post,
+    path = "/api/todos/{id}/archive",
     params(
         ("id" = Uuid, Path, description = "Todo ID")
     ),
-    request_body = UpdateTodoRequest,
     responses(
-        (status = 200, description = "Todo updated successfully", body = TodoResponse),
-        (status = 400, description = "Bad request - validation failed"),
-        (status = 404, description = "Todo not found"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Todo archived; it no longer appears in lists unless include_archived", body = TodoResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Todos"
 )]
-pub async fn update_todo<R: TodoRepositoryTrait>(
+pub async fn archive_todo<R: TodoRepositoryTrait>(
     State(repository): State<Arc<R>>,
-    Path(id): Path<Uuid>,
-    Json(request): Json<UpdateTodoRequest>,
-) -> Result<Json<TodoResponse>, StatusCode> {
-    tracing::info!("Updating todo with id: {}", id);
+    Path(id): Path<TodoId>,
+) -> Result<Json<TodoResponse>, TodoError> {
+    set_archived(repository.as_ref(), id, true).await
+}
 
-    if let Err(e) = request.validate() {
-        tracing::warn!("Validation failed for update todo request: {}", e);
-        return Err(StatusCode::BAD_REQUEST);
-    }
+#[utoipa::path(
+    post,
+    path = "/api/todos/{id}/unarchive",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    responses(
+        (status = 200, description = "Todo restored to lists", body = TodoResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn unarchive_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<TodoId>,
+) -> Result<Json<TodoResponse>, TodoError> {
+    set_archived(repository.as_ref(), id, false).await
+}
 
-    match repository.update_todo(id, &request).await {
+/// Archiving leaves `completed` alone, so a todo can be archived unfinished
+async fn set_archived<R: TodoRepositoryTrait>(
+    repository: &R,
+    id: TodoId,
+    archived: bool,
+) -> Result<Json<TodoResponse>, TodoError> {
+    let action = if archived { "archive" } else { "unarchive" };
+    tracing::info!("Setting archived={} on todo with id: {}", archived, id);
+    let updates = UpdateTodoRequest {
+        archived: Some(archived),
+        ..Default::default()
+    };
+    match repository.update_todo(id, &updates, None).await {
         Ok(Some(todo)) => {
-            tracing::info!("Successfully updated todo with id: {}", id);
+            tracing::info!("Successfully {}d todo with id: {}", action, id);
             Ok(Json(ApiResponse::success(todo).into()))
         }
         Ok(None) => {
-            tracing::warn!("Todo not found for update with id: {}", id);
-            Err(StatusCode::NOT_FOUND)
+            tracing::warn!("Todo not found to {} with id: {}", action, id);
+            Err(TodoError::NotFound(format!("Todo {}", id)))
         }
         Err(e) => {
-            tracing::error!("Failed to update todo with id {}: {}", id, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            tracing::error!("Failed to {} todo with id {}: {}", action, id, e);
+            Err(e)
         }
     }
 }
 
+/// Line-based three-way merge; `None` when both sides changed the same lines
+pub fn merge_content(base: &str, server: &str, client: &str) -> Option<String> {
+    diffy::merge(base, server, client).ok()
+}
+
 #[utoipa::path(
     delete,
     path = "/api/todos/{id}",
     params(
-        ("id" = Uuid, Path, description = "Todo ID")
+        ("id" = Uuid, Path, description = "Todo ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the todo must still have; required with REQUIRE_IF_MATCH")
     ),
     responses(
         (status = 204, description = "Todo deleted successfully"),
-        (status = 404, description = "Todo not found"),
-        (status = 500, description = "Internal server error")
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 412, description = "The todo has changed since the If-Match ETag", body = ErrorResponse),
+        (status = 428, description = "If-Match is missing and required", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "Todos"
 )]
 pub async fn delete_todo<R: TodoRepositoryTrait>(
     State(repository): State<Arc<R>>,
-    Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
+    Extension(preconditions): Extension<Arc<PreconditionConfig>>,
+    Path(id): Path<TodoId>,
+    headers: HeaderMap,
+) -> Result<StatusCode, TodoError> {
     tracing::info!("Deleting todo with id: {}", id);
-    match repository.delete_todo(id).await {
+    let expected_version = preconditions.expected_version(&headers, id)?;
+    match repository.delete_todo(id, expected_version).await {
         Ok(true) => {
             tracing::info!("Successfully deleted todo with id: {}", id);
             Ok(StatusCode::NO_CONTENT)
         }
         Ok(false) => {
             tracing::warn!("Todo not found for deletion with id: {}", id);
-            Err(StatusCode::NOT_FOUND)
+            Err(TodoError::NotFound(format!("Todo {}", id)))
+        }
+        Err(e @ TodoError::PreconditionFailed(_)) => {
+            tracing::warn!("Refused stale deletion of todo {}: {}", id, e);
+            Err(e)
         }
         Err(e) => {
             tracing::error!("Failed to delete todo with id {}: {}", id, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(e)
         }
     }
 }
 
 impl Todo {
-    pub fn new(title: &str, content: &str) -> Self {
+    pub fn new(title: TodoTitle, content: TodoContent) -> Self {
         let now = Utc::now();
         Self {
-            id: Uuid::now_v7(),
-            title: title.to_string(),
-            content: content.to_string(),
+            id: TodoId::new(),
+            title,
+            content,
             completed: false,
+            version: 1,
+            metadata: serde_json::Value::Object(Default::default()),
+            labels: Vec::new(),
+            tags: Vec::new(),
+            estimate_minutes: None,
+            priority: Priority::default(),
+            list_id: None,
+            archived: false,
+            owner_id: auth::acting_user(),
+            stale: false,
+            relations: None,
             created_at: now,
             updated_at: now,
         }
     }
 
     pub fn new_with_validation(title: &str, content: &str) -> Result<Self, String> {
-        Self::validate_title(title)?;
-        Self::validate_content(content)?;
+        let title = TodoTitle::new(title).map_err(|e| e.message)?;
+        let content = TodoContent::new(content).map_err(|e| e.message)?;
         Ok(Self::new(title, content))
     }
 
     pub fn validate_title(title: &str) -> Result<(), String> {
+        Self::check_title(title).map_err(|e| e.message)
+    }
+
+    /// `validate_title` with the rule that failed
+    pub fn check_title(title: &str) -> Result<(), FieldError> {
         let trimmed = title.trim();
         if trimmed.is_empty() {
-            return Err("Title cannot be empty".to_string());
+            return Err(FieldError::new(
+                FieldErrorCode::Required,
+                "Title cannot be empty".to_string(),
+            ));
         }
-        if title.len() > 255 {
-            return Err("Title cannot exceed 255 characters".to_string());
+        if let Some(limit) = LengthLimit::characters(title, MAX_TITLE_CHARS) {
+            return Err(FieldError::too_long(
+                limit,
+                format!("Title cannot exceed {} characters", MAX_TITLE_CHARS),
+            ));
         }
         if title.contains('\n') {
-            return Err("Title cannot contain newlines".to_string());
+            return Err(FieldError::new(
+                FieldErrorCode::Invalid,
+                "Title cannot contain newlines".to_string(),
+            ));
         }
         Ok(())
     }
 
+    /// Title as stored: NFC, trimmed, whitespace runs collapsed to one space and control
+    /// characters dropped. Case is left alone.
+    pub fn normalize_title(title: &str) -> String {
+        unicode::nfc(title)
+            .split_whitespace()
+            .map(|word| word.chars().filter(|c| !c.is_control()).collect::<String>())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Validates a client-supplied title after normalizing it; errors quote the raw input
+    pub fn validate_raw_title(raw: &str) -> Result<(), String> {
+        Self::check_raw_title(raw).map_err(|e| e.message)
+    }
+
+    /// `validate_raw_title` with the rule that failed
+    pub fn check_raw_title(raw: &str) -> Result<(), FieldError> {
+        Self::check_title(&Self::normalize_title(raw)).map_err(|e| {
+            let raw = truncate_to_bytes(raw, MAX_ECHOED_TITLE_BYTES);
+            FieldError {
+                message: format!("{} (got {:?})", e.message, raw),
+                ..e
+            }
+        })
+    }
+
+    /// Collapse whitespace and cut free-form text (email subjects, page titles) down to a
+    /// title that passes `validate_title`, or an empty string if nothing is left
+    pub fn sanitize_title(text: &str) -> String {
+        truncate_to_chars(&Self::normalize_title(text), MAX_TITLE_CHARS).to_string()
+    }
+
     pub fn validate_content(content: &str) -> Result<(), String> {
-        if content.len() > 10000 {
-            return Err("Content cannot exceed 10000 characters".to_string());
+        Self::check_content(content).map_err(|e| e.message)
+    }
+
+    /// `validate_content` with the rule that failed
+    pub fn check_content(content: &str) -> Result<(), FieldError> {
+        match LengthLimit::characters(content, MAX_CONTENT_CHARS) {
+            Some(limit) => Err(FieldError::too_long(
+                limit,
+                format!("Content cannot exceed {} characters", MAX_CONTENT_CHARS),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn validate_estimate(estimate_minutes: i32) -> Result<(), String> {
+        if estimate_minutes < 0 {
+            return Err("Estimate cannot be negative".to_string());
+        }
+        Ok(())
+    }
+
+    /// `validate_estimate` with the rule that failed
+    pub fn check_estimate(estimate_minutes: i32) -> Result<(), FieldError> {
+        Self::validate_estimate(estimate_minutes)
+            .map_err(|e| FieldError::new(FieldErrorCode::OutOfRange, e))
+    }
+
+    /// Ids from clients must be UUIDv7, like the ones the server generates, so that they
+    /// sort by creation time
+    pub fn check_client_id(id: TodoId) -> Result<(), FieldError> {
+        if id.get_version_num() != 7 {
+            return Err(FieldError::new(
+                FieldErrorCode::Invalid,
+                format!(
+                    "Todo id must be a UUIDv7, got version {}",
+                    id.get_version_num()
+                ),
+            ));
         }
         Ok(())
     }
@@ -630,16 +5698,19 @@ impl Todo {
     pub fn is_valid(&self) -> Result<(), String> {
         Self::validate_title(&self.title)?;
         Self::validate_content(&self.content)?;
+        if let Some(estimate) = self.estimate_minutes {
+            Self::validate_estimate(estimate)?;
+        }
         Ok(())
     }
 
-    pub fn update_title(&mut self, title: &str) {
-        self.title = title.to_string();
+    pub fn update_title(&mut self, title: TodoTitle) {
+        self.title = title;
         self.updated_at = Utc::now();
     }
 
-    pub fn update_content(&mut self, content: &str) {
-        self.content = content.to_string();
+    pub fn update_content(&mut self, content: TodoContent) {
+        self.content = content;
         self.updated_at = Utc::now();
     }
 
@@ -654,18 +5725,20 @@ impl Todo {
         content: Option<&str>,
         completed: Option<bool>,
     ) -> Result<(), String> {
-        if let Some(title) = title {
-            Self::validate_title(title)?;
-        }
-        if let Some(content) = content {
-            Self::validate_content(content)?;
-        }
+        let title = title
+            .map(TodoTitle::new)
+            .transpose()
+            .map_err(|e| e.message)?;
+        let content = content
+            .map(TodoContent::new)
+            .transpose()
+            .map_err(|e| e.message)?;
 
         if let Some(title) = title {
-            self.title = title.to_string();
+            self.title = title;
         }
         if let Some(content) = content {
-            self.content = content.to_string();
+            self.content = content;
         }
         if let Some(completed) = completed {
             self.completed = completed;
@@ -676,35 +5749,483 @@ impl Todo {
     }
 }
 
+/// Longest title, in characters rather than bytes so that CJK titles get as many as
+/// Latin ones
+pub const MAX_TITLE_CHARS: usize = 255;
+/// Longest content, in characters
+pub const MAX_CONTENT_CHARS: usize = 10000;
+/// How much of a rejected title a validation error quotes back
+const MAX_ECHOED_TITLE_BYTES: usize = 300;
+
+/// Longest prefix of `text` within `max_bytes` that ends on a char boundary
+pub(crate) fn truncate_to_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// The first `max_chars` characters of `text`
+pub(crate) fn truncate_to_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Compares secrets in time independent of where they differ
+pub(crate) fn keys_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Rule a list of labels or tags breaks, if `normalize` rejects it
+fn check_names(
+    names: &[String],
+    max: usize,
+    normalize: fn(&[String]) -> Result<Vec<String>, String>,
+) -> Result<(), FieldError> {
+    normalize(names).map(drop).map_err(|e| {
+        let code = if names.len() > max {
+            FieldErrorCode::TooMany
+        } else {
+            FieldErrorCode::Invalid
+        };
+        FieldError::new(code, e)
+    })
+}
+
 impl CreateTodoRequest {
+    /// Every field breaking a rule; metadata and the list are checked against the
+    /// repository separately
+    pub fn field_errors(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
+        if let Some(id) = self.id {
+            errors.check("id", Todo::check_client_id(id));
+        }
+        errors.check("title", Todo::check_raw_title(&self.title));
+        errors.check("content", Todo::check_content(&self.content));
+        if let Some(labels) = &self.labels {
+            errors.check(
+                "labels",
+                check_names(labels, labels::MAX_LABELS, labels::normalize_all),
+            );
+        }
+        if let Some(tags) = &self.tags {
+            errors.check(
+                "tags",
+                check_names(tags, tags::MAX_TAGS, tags::normalize_all),
+            );
+        }
+        if let Some(estimate) = self.estimate_minutes {
+            errors.check("estimate_minutes", Todo::check_estimate(estimate));
+        }
+        errors
+    }
+
     pub fn validate(&self) -> Result<(), String> {
-        Todo::validate_title(&self.title)?;
-        Todo::validate_content(&self.content)?;
-        Ok(())
+        self.field_errors()
+            .into_result()
+            .map_err(|errors| errors.to_string())
+    }
+
+    /// The todo to store, validated and normalized; metadata and the list are checked
+    /// against the repository separately
+    pub fn into_todo(self, emoji: &EmojiConfig) -> Result<Todo, String> {
+        self.validate()?;
+        let title = TodoTitle::parse(&self.title).map_err(|e| e.message)?;
+        let content = TodoContent::new(emoji.prepare_content(&unicode::nfc(&self.content)))
+            .map_err(|e| e.message)?;
+        let mut todo = Todo::new(title, content);
+        if let Some(id) = self.id {
+            todo.id = id;
+        }
+        if let Some(metadata) = self.metadata {
+            todo.metadata = metadata;
+        }
+        if let Some(labels) = self.labels {
+            todo.labels = labels::normalize_all(&labels)?;
+        }
+        if let Some(tags) = self.tags {
+            todo.tags = tags::normalize_all(&tags)?;
+        }
+        todo.estimate_minutes = self.estimate_minutes;
+        todo.priority = self.priority.unwrap_or_default();
+        todo.list_id = self.list_id;
+        Ok(todo)
     }
 }
 
 impl UpdateTodoRequest {
-    pub fn validate(&self) -> Result<(), String> {
+    /// Every field breaking a rule, as for `CreateTodoRequest::field_errors`
+    pub fn field_errors(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
         if let Some(title) = &self.title {
-            Todo::validate_title(title)?;
+            errors.check("title", Todo::check_raw_title(title));
         }
         if let Some(content) = &self.content {
-            Todo::validate_content(content)?;
+            errors.check("content", Todo::check_content(content));
+        }
+        if let Some(labels) = &self.labels {
+            errors.check(
+                "labels",
+                check_names(labels, labels::MAX_LABELS, labels::normalize_all),
+            );
+        }
+        for (field, tags) in [
+            ("tags", &self.tags),
+            ("add_tags", &self.add_tags),
+            ("remove_tags", &self.remove_tags),
+        ] {
+            if let Some(tags) = tags {
+                errors.check(
+                    field,
+                    check_names(tags, tags::MAX_TAGS, tags::normalize_all),
+                );
+            }
+        }
+        if let Some(estimate) = self.estimate_minutes {
+            errors.check("estimate_minutes", Todo::check_estimate(estimate));
+        }
+        errors
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.field_errors()
+            .into_result()
+            .map_err(|errors| errors.to_string())
+    }
+
+    /// The title and content to store, once `prepare_update` has normalized them
+    pub fn text(&self) -> Result<(Option<TodoTitle>, Option<TodoContent>), TodoError> {
+        let mut errors = ValidationErrors::default();
+        let title = self
+            .title
+            .as_deref()
+            .and_then(|title| errors.take("title", TodoTitle::new(title)));
+        let content = self
+            .content
+            .as_deref()
+            .and_then(|content| errors.take("content", TodoContent::new(content)));
+        errors.into_result()?;
+        Ok((title, content))
+    }
+}
+
+/// Settings of the router that differ between deployments
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub admin: AdminConfig,
+    pub auth: AuthConfig,
+    pub clip: ClipConfig,
+    pub inbound: InboundEmailConfig,
+    pub emoji: EmojiConfig,
+    pub embed: EmbedConfig,
+    pub stale: StaleConfig,
+    pub subtasks: SubtaskConfig,
+    pub preconditions: PreconditionConfig,
+    pub moderation: Moderator,
+    pub encryption: EncryptionConfig,
+    /// Largest request body accepted
+    pub max_body_bytes: usize,
+    /// Error bodies for requests that do not ask for problem details
+    pub error_format: ErrorFormat,
+    /// Schema migrations `/health/ready` waits for, when the server manages them
+    pub migrations: Option<Migrator>,
+    /// Background jobs and whether this instance runs them
+    pub jobs: Arc<JobRunner>,
+    /// Task queue behind `/api/admin/queue`; there is none without a database
+    pub queue: Option<TaskQueue>,
+    /// Dependencies `/health/ready` checks besides the database
+    pub dependencies: DependencyChecks,
+    /// Events of `GET /api/todos/events`; only filled when the repository publishes to it
+    pub feed: Arc<ChangeFeed>,
+    /// Rooms of `/api/todos/:id/collab`; REST writes only reach them when the repository
+    /// publishes to it
+    pub collab: Arc<CollabHub>,
+}
+
+impl AppConfig {
+    pub fn from_env() -> Self {
+        Self {
+            admin: AdminConfig::from_env(),
+            auth: AuthConfig::from_env(),
+            clip: ClipConfig::from_env(),
+            inbound: InboundEmailConfig::from_env(),
+            emoji: EmojiConfig::from_env(),
+            embed: EmbedConfig::from_env(),
+            stale: StaleConfig::from_env(),
+            subtasks: SubtaskConfig::from_env(),
+            preconditions: PreconditionConfig::from_env(),
+            moderation: Moderator::from_env(),
+            encryption: EncryptionConfig::from_env(),
+            max_body_bytes: resources::DEFAULT_MAX_BODY_BYTES,
+            error_format: ErrorFormat::from_env(),
+            migrations: None,
+            jobs: JobRunner::single(),
+            queue: None,
+            dependencies: DependencyChecks::default(),
+            feed: Arc::default(),
+            collab: Arc::default(),
         }
-        Ok(())
+    }
+
+    /// A personal server on loopback: admin, clip and todo endpoints work without a token
+    pub fn local() -> Self {
+        let mut config = Self::from_env();
+        config.admin.skip_auth = true;
+        config.auth.skip_auth = true;
+        config.clip.skip_auth = true;
+        config
     }
 }
 
 pub fn create_app_with_repository<R: TodoRepositoryTrait + 'static>(repository: Arc<R>) -> Router {
-    Router::new()
-        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .route("/health", get(health_check))
+    create_app_with_config(repository, AppConfig::from_env())
+}
+
+pub fn create_app_with_config<R: TodoRepositoryTrait + 'static>(
+    repository: Arc<R>,
+    config: AppConfig,
+) -> Router {
+    // Behind bearer tokens once JWT_SECRET is set; see `auth::PUBLIC_PREFIXES`
+    let data = Router::new()
         .route("/api/todos", get(get_todos::<R>))
-        .route("/api/todos", post(create_todo::<R>))
+        .route(
+            "/api/todos",
+            post(create_todo::<R>).layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/todos/bulk",
+            post(bulk::create_todos_bulk::<R>)
+                .patch(bulk::update_todos_bulk::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext))
+                .delete(bulk::delete_todos_bulk::<R>),
+        )
+        .route(
+            "/api/todos/complete-all",
+            post(bulk::complete_all_todos::<R>),
+        )
+        .route(
+            "/api/todos/completed",
+            delete(bulk::delete_completed_todos::<R>),
+        )
+        .route("/api/todos/events", get(feed::stream_events))
+        .route(
+            "/api/todos/autocomplete",
+            get(autocomplete::autocomplete_titles::<R>),
+        )
         .route("/api/todos/:id", get(get_todo::<R>))
-        .route("/api/todos/:id", patch(update_todo::<R>))
+        .route(
+            "/api/todos/:id",
+            patch(update_todo::<R>)
+                .layer(middleware::from_fn(compat::translate_update_todo))
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/todos/:id",
+            put(put_todo::<R>).layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/todos/:id/content",
+            patch(update_todo_content::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/todos/:id/split",
+            post(split::split_todo::<R>).layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/todos/:id/extract-section",
+            post(split::extract_section::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route("/api/todos/:id/archive", post(archive_todo::<R>))
+        .route("/api/todos/:id/unarchive", post(unarchive_todo::<R>))
         .route("/api/todos/:id", delete(delete_todo::<R>))
+        .route(
+            "/api/todos/:id/collab",
+            get(collab::collab_socket::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route("/api/todos/:id/presence", get(collab::get_presence::<R>))
+        .route(
+            "/api/todos/:id/short-link",
+            get(shortlink::get_short_link::<R>),
+        )
+        .route(
+            "/api/todos/:id/relations",
+            get(relations::list_relations::<R>).post(relations::create_relation::<R>),
+        )
+        .route(
+            "/api/todos/:id/relations/:relation_id",
+            delete(relations::delete_relation::<R>),
+        )
+        .route(
+            "/api/todos/:id/share-links",
+            get(share::list_share_links::<R>).post(share::create_share_link::<R>),
+        )
+        .route("/api/todos/:id/pdf", get(pdf::get_todo_pdf::<R>))
+        .route(
+            "/api/import",
+            post(imports::start_import::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route("/api/jobs/:id", get(imports::get_job::<R>))
+        .route("/api/jobs/:id/cancel", post(imports::cancel_job::<R>))
+        .route("/api/search", get(search::search_todos::<R>))
+        .route(
+            "/api/share-links/:token",
+            delete(share::delete_share_link::<R>),
+        )
+        .route(
+            "/api/share-links/:token/access-log",
+            get(share::get_access_log::<R>),
+        )
+        .route("/api/metadata-fields", get(metadata::list_fields::<R>))
+        .route("/api/export/pdf", get(pdf::export_pdf::<R>))
+        .route("/api/export/html", get(html::export_html::<R>))
+        .route("/api/stats/aging", get(stats::get_aging::<R>))
+        .route("/api/stats/workload", get(stats::get_workload::<R>))
+        .route("/api/me/streaks", get(streaks::get_streaks::<R>))
+        .route(
+            "/api/me/plan",
+            get(plan::get_plan::<R>).post(plan::set_plan::<R>),
+        )
+        .route(
+            "/api/metadata-fields/:key",
+            put(metadata::put_field::<R>).delete(metadata::delete_field::<R>),
+        )
+        .route("/api/policy", get(policy::get_policy::<R>))
+        .route(
+            "/api/tags",
+            get(tags::list_tags::<R>).post(tags::create_tag::<R>),
+        )
+        .route("/api/tags/autocomplete", get(tags::autocomplete_tags::<R>))
+        .route(
+            "/api/tags/:id",
+            get(tags::get_tag::<R>)
+                .patch(tags::rename_tag::<R>)
+                .delete(tags::delete_tag::<R>),
+        )
+        .route(
+            "/api/lists",
+            get(lists::list_lists::<R>).post(lists::create_list::<R>),
+        )
+        .route(
+            "/api/lists/:id",
+            get(lists::get_list::<R>)
+                .patch(lists::update_list::<R>)
+                .delete(lists::delete_list::<R>),
+        )
+        .route("/api/lists/:id/todos", get(lists::get_list_todos::<R>))
+        .route(
+            "/api/encrypted-todos",
+            get(encryption::list_encrypted_todos::<R>).post(encryption::create_encrypted_todo::<R>),
+        )
+        .route(
+            "/api/encrypted-todos/:id",
+            get(encryption::get_encrypted_todo::<R>)
+                .put(encryption::replace_encrypted_todo::<R>)
+                .delete(encryption::delete_encrypted_todo::<R>),
+        )
+        .route_layer(middleware::from_fn(auth::require_user));
+
+    Router::new()
+        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
+        .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check::<R>))
+        .route("/metrics", get(metrics::get_metrics))
+        .route("/api/auth/register", post(auth::register::<R>))
+        .route("/api/auth/login", post(auth::login::<R>))
+        .route("/api/auth/ticket", post(auth::issue_ticket))
+        .merge(data)
+        .route("/t/:short_id", get(shortlink::follow_short_link::<R>))
+        .route("/s/:token", get(share::open_share_link::<R>))
+        .route("/embed/:token", get(embed::get_embed::<R>))
+        .route("/oembed", get(embed::get_oembed::<R>))
+        .route(
+            "/api/inbound/email",
+            post(inbound::receive_email::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/inbound/email/sendgrid",
+            post(inbound::receive_sendgrid_email::<R>)
+                .layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/clip",
+            post(clip::clip_page::<R>).layer(middleware::from_fn(encryption::refuse_plaintext)),
+        )
+        .route(
+            "/api/admin/config",
+            get(admin::get_config).put(admin::put_config),
+        )
+        .route("/api/admin/config/reload", post(admin::reload_config))
+        .route(
+            "/api/admin/log-level",
+            get(admin::get_log_level).put(admin::put_log_level),
+        )
+        .route("/api/admin/jobs", get(admin::get_jobs))
+        .route("/api/admin/queue", get(admin::get_queue))
+        .route("/api/admin/queue/:id", delete(admin::discard_task))
+        .route("/api/admin/queue/:id/retry", post(admin::retry_task))
+        .route("/api/admin/policy", put(policy::put_policy::<R>))
+        .route("/api/admin/moderation", get(moderation::list_flags::<R>))
+        .route(
+            "/api/admin/moderation/:id",
+            delete(moderation::dismiss_flag::<R>),
+        )
+        .route(
+            "/api/webhooks",
+            get(webhooks::list_webhooks::<R>).post(webhooks::create_webhook::<R>),
+        )
+        .route(
+            "/api/webhooks/:id",
+            get(webhooks::get_webhook::<R>)
+                .patch(webhooks::update_webhook::<R>)
+                .delete(webhooks::delete_webhook::<R>),
+        )
+        .route(
+            "/api/webhooks/:id/deliveries",
+            get(webhooks::list_deliveries::<R>),
+        )
+        .layer(Extension(config.collab))
+        .layer(Extension(Arc::new(TitleCache::default())))
+        .layer(Extension(Arc::new(config.inbound)))
+        .layer(Extension(Arc::new(SeenTokens::default())))
+        .layer(Extension(Arc::new(config.clip)))
+        .layer(Extension(Arc::new(config.admin)))
+        .layer(Extension(Arc::new(config.auth)))
+        .layer(Extension(Arc::new(config.emoji)))
+        .layer(Extension(Arc::new(config.embed)))
+        .layer(Extension(Arc::new(config.stale)))
+        .layer(Extension(Arc::new(config.subtasks)))
+        .layer(Extension(Arc::new(config.preconditions)))
+        .layer(Extension(Arc::new(config.moderation)))
+        .layer(Extension(Arc::new(config.encryption)))
+        .layer(Extension(pdf::renderer_from_env()))
+        .layer(Extension(RepositoryMetrics::global()))
+        .layer(Extension(MaxBodyBytes(config.max_body_bytes)))
+        .layer(Extension(config.migrations))
+        .layer(Extension(config.jobs))
+        .layer(Extension(config.queue))
+        .layer(Extension(Arc::new(config.dependencies)))
+        .layer(Extension(config.feed))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(middleware::from_fn_with_state(
+            config.error_format,
+            errors::json_errors,
+        ))
         .layer(CorsLayer::permissive())
         .with_state(repository)
 }
@@ -715,6 +6236,7 @@ pub fn create_app_with_database(pool: DatabasePool) -> Router {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use chrono::Utc;
@@ -723,10 +6245,21 @@ mod tests {
     fn test_todo_creation_with_valid_data() {
         let now = Utc::now();
         let todo = Todo {
-            id: Uuid::now_v7(),
-            title: "Test Todo".to_string(),
-            content: "Test content with **markdown**".to_string(),
+            id: TodoId::new(),
+            title: TodoTitle::new("Test Todo").unwrap(),
+            content: TodoContent::new("Test content with **markdown**").unwrap(),
             completed: false,
+            version: 1,
+            metadata: serde_json::json!({}),
+            labels: vec![],
+            tags: vec![],
+            estimate_minutes: None,
+            priority: Priority::default(),
+            list_id: None,
+            archived: false,
+            owner_id: Uuid::nil(),
+            stale: false,
+            relations: None,
             created_at: now,
             updated_at: now,
         };
@@ -785,8 +6318,15 @@ mod tests {
     #[test]
     fn test_create_todo_request_validation() {
         let valid_request = CreateTodoRequest {
+            id: None,
             title: "Valid Title".to_string(),
             content: "Valid content".to_string(),
+            metadata: None,
+            labels: None,
+            tags: None,
+            priority: None,
+            list_id: None,
+            estimate_minutes: None,
         };
 
         let result = valid_request.validate();
@@ -796,8 +6336,15 @@ mod tests {
     #[test]
     fn test_create_todo_request_validation_empty_title() {
         let invalid_request = CreateTodoRequest {
+            id: None,
             title: "".to_string(),
             content: "Valid content".to_string(),
+            metadata: None,
+            labels: None,
+            tags: None,
+            priority: None,
+            list_id: None,
+            estimate_minutes: None,
         };
 
         let result = invalid_request.validate();
@@ -810,6 +6357,15 @@ mod tests {
             title: Some("Updated Title".to_string()),
             content: Some("Updated content".to_string()),
             completed: Some(true),
+            metadata: None,
+            labels: None,
+            tags: None,
+            add_tags: None,
+            remove_tags: None,
+            estimate_minutes: None,
+            priority: None,
+            list_id: None,
+            archived: None,
         };
 
         let result = valid_request.validate();
@@ -822,6 +6378,15 @@ mod tests {
             title: Some("".to_string()),
             content: Some("Valid content".to_string()),
             completed: None,
+            metadata: None,
+            labels: None,
+            tags: None,
+            add_tags: None,
+            remove_tags: None,
+            estimate_minutes: None,
+            priority: None,
+            list_id: None,
+            archived: None,
         };
 
         let result = invalid_request.validate();
@@ -830,7 +6395,7 @@ mod tests {
 
     #[test]
     fn test_todo_new_constructor() {
-        let todo = Todo::new("Test Title", "Test Content");
+        let todo = Todo::new_with_validation("Test Title", "Test Content").unwrap();
 
         assert_eq!(todo.title, "Test Title");
         assert_eq!(todo.content, "Test Content");
@@ -842,12 +6407,12 @@ mod tests {
 
     #[test]
     fn test_todo_update_content() {
-        let mut todo = Todo::new("Original Title", "Original Content");
+        let mut todo = Todo::new_with_validation("Original Title", "Original Content").unwrap();
         let original_created_at = todo.created_at;
 
         std::thread::sleep(std::time::Duration::from_millis(1));
 
-        todo.update_content("Updated Content");
+        todo.update_content(TodoContent::new("Updated Content").unwrap());
 
         assert_eq!(todo.content, "Updated Content");
         assert_eq!(todo.created_at, original_created_at);
@@ -856,12 +6421,12 @@ mod tests {
 
     #[test]
     fn test_todo_update_title() {
-        let mut todo = Todo::new("Original Title", "Original Content");
+        let mut todo = Todo::new_with_validation("Original Title", "Original Content").unwrap();
         let original_created_at = todo.created_at;
 
         std::thread::sleep(std::time::Duration::from_millis(1));
 
-        todo.update_title("Updated Title");
+        todo.update_title(TodoTitle::new("Updated Title").unwrap());
 
         assert_eq!(todo.title, "Updated Title");
         assert_eq!(todo.created_at, original_created_at);
@@ -870,7 +6435,7 @@ mod tests {
 
     #[test]
     fn test_todo_toggle_completed() {
-        let mut todo = Todo::new("Test Title", "Test Content");
+        let mut todo = Todo::new_with_validation("Test Title", "Test Content").unwrap();
         let original_created_at = todo.created_at;
 
         assert_eq!(todo.completed, false);
@@ -889,7 +6454,7 @@ mod tests {
 
     #[test]
     fn test_todo_serialization() {
-        let todo = Todo::new("Test Title", "Test Content");
+        let todo = Todo::new_with_validation("Test Title", "Test Content").unwrap();
         let json_result = serde_json::to_string(&todo);
 
         assert!(json_result.is_ok());
@@ -906,6 +6471,9 @@ mod tests {
             "title": "Test Title",
             "content": "Test Content",
             "completed": false,
+            "version": 1,
+            "metadata": {},
+            "labels": [],
             "created_at": "2024-01-01T00:00:00Z",
             "updated_at": "2024-01-01T00:00:00Z"
         }
@@ -922,7 +6490,7 @@ mod tests {
 
     #[test]
     fn test_api_response_success() {
-        let todo = Todo::new("Test", "Content");
+        let todo = Todo::new_with_validation("Test", "Content").unwrap();
         let response = ApiResponse::success(todo.clone());
 
         assert!(response.success);
@@ -933,10 +6501,12 @@ mod tests {
 
     #[test]
     fn test_api_response_error() {
-        let response: ApiResponse<Todo> = ApiResponse::error("Test error".to_string());
+        let response: ApiResponse<Todo> =
+            ApiResponse::error(ErrorCode::ValidationFailed, "Test error".to_string());
 
         assert!(!response.success);
         assert!(response.data.is_none());
+        assert_eq!(response.code, Some(ErrorCode::ValidationFailed));
         assert!(response.error.is_some());
         assert_eq!(response.error.unwrap(), "Test error");
     }
@@ -955,6 +6525,95 @@ mod tests {
         assert_eq!(result.unwrap_err(), "Title cannot contain newlines");
     }
 
+    #[test]
+    fn test_page_request_from_query() {
+        let query = |pairs: &[(&str, &str)]| {
+            let params = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>();
+            PageRequest::from_query(&params)
+        };
+        let cursor = TodoId::new();
+        assert_eq!(query(&[("label", "work")]), Ok(None));
+        assert_eq!(
+            query(&[("limit", "10")]),
+            Ok(Some(PageRequest {
+                cursor: None,
+                limit: 10
+            }))
+        );
+        assert_eq!(
+            query(&[("cursor", &cursor.to_string())]),
+            Ok(Some(PageRequest {
+                cursor: Some(cursor),
+                limit: PageRequest::DEFAULT_LIMIT
+            }))
+        );
+        assert!(query(&[("limit", "0")]).is_err());
+        assert!(query(&[("limit", "201")]).is_err());
+        assert!(query(&[("cursor", "nope")]).is_err());
+    }
+
+    #[test]
+    fn test_sort_from_query() {
+        let query = |pairs: &[(&str, &str)]| {
+            let params = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>();
+            TodoSort::from_query(&params)
+        };
+        assert_eq!(query(&[]), Ok((TodoSort::CreatedAt, SortOrder::Desc)));
+        assert_eq!(
+            query(&[("sort", "title")]),
+            Ok((TodoSort::Title, SortOrder::Asc))
+        );
+        assert_eq!(
+            query(&[("sort", "updated_at"), ("order", "asc")]),
+            Ok((TodoSort::UpdatedAt, SortOrder::Asc))
+        );
+        assert!(query(&[("sort", "title DESC")]).is_err());
+        assert!(query(&[("order", "DESC")]).is_err());
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(Todo::normalize_title("  Fix   the\tBUG \n"), "Fix the BUG");
+        assert_eq!(Todo::normalize_title("Ship\u{7} it\u{1b}"), "Ship it");
+        assert_eq!(Todo::normalize_title(" \u{0} "), "");
+    }
+
+    #[test]
+    fn test_validate_raw_title_quotes_input() {
+        assert!(Todo::validate_raw_title("Title\nwith\nnewlines").is_ok());
+        assert_eq!(
+            Todo::validate_raw_title(" \t ").unwrap_err(),
+            "Title cannot be empty (got \" \\t \")"
+        );
+        let long = format!("  {}  ", "a".repeat(256));
+        let error = Todo::validate_raw_title(&long).unwrap_err();
+        assert!(error.starts_with("Title cannot exceed 255 characters (got \"  aaa"));
+    }
+
+    #[test]
+    fn test_title_length_counts_characters() {
+        assert!(Todo::validate_title(&"あ".repeat(200)).is_ok());
+        assert!(Todo::validate_title(&"🦀".repeat(255)).is_ok());
+        let error = Todo::check_title(&"あ".repeat(256)).unwrap_err();
+        assert_eq!(error.code, FieldErrorCode::TooLong);
+        assert_eq!(
+            error.limit,
+            Some(LengthLimit {
+                max: 255,
+                actual: 256,
+                unit: errors::LengthUnit::Characters
+            })
+        );
+        assert!(Todo::validate_content(&"日本語".repeat(3000)).is_ok());
+        assert_eq!(Todo::sanitize_title(&"あ".repeat(300)).chars().count(), 255);
+    }
+
     #[test]
     fn test_todo_validation_title_edge_case_255_chars() {
         let title_255 = "a".repeat(255);
@@ -986,20 +6645,40 @@ mod tests {
     #[test]
     fn test_create_todo_request_validation_title_too_long() {
         let invalid_request = CreateTodoRequest {
+            id: None,
             title: "a".repeat(256),
             content: "Valid content".to_string(),
+            metadata: None,
+            labels: None,
+            tags: None,
+            priority: None,
+            list_id: None,
+            estimate_minutes: None,
         };
 
         let result = invalid_request.validate();
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Title cannot exceed 255 characters");
+        assert_eq!(
+            result.unwrap_err(),
+            format!(
+                "Title cannot exceed 255 characters (got {:?})",
+                "a".repeat(256)
+            )
+        );
     }
 
     #[test]
     fn test_create_todo_request_validation_content_too_long() {
         let invalid_request = CreateTodoRequest {
+            id: None,
             title: "Valid title".to_string(),
             content: "a".repeat(10001),
+            metadata: None,
+            labels: None,
+            tags: None,
+            priority: None,
+            list_id: None,
+            estimate_minutes: None,
         };
 
         let result = invalid_request.validate();
@@ -1016,6 +6695,15 @@ mod tests {
             title: None,
             content: None,
             completed: None,
+            metadata: None,
+            labels: None,
+            tags: None,
+            add_tags: None,
+            remove_tags: None,
+            estimate_minutes: None,
+            priority: None,
+            list_id: None,
+            archived: None,
         };
 
         let result = request.validate();
@@ -1028,11 +6716,26 @@ mod tests {
             title: Some("a".repeat(256)),
             content: None,
             completed: None,
+            metadata: None,
+            labels: None,
+            tags: None,
+            add_tags: None,
+            remove_tags: None,
+            estimate_minutes: None,
+            priority: None,
+            list_id: None,
+            archived: None,
         };
 
         let result = invalid_request.validate();
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Title cannot exceed 255 characters");
+        assert_eq!(
+            result.unwrap_err(),
+            format!(
+                "Title cannot exceed 255 characters (got {:?})",
+                "a".repeat(256)
+            )
+        );
     }
 
     #[test]
@@ -1041,6 +6744,15 @@ mod tests {
             title: None,
             content: Some("a".repeat(10001)),
             completed: None,
+            metadata: None,
+            labels: None,
+            tags: None,
+            add_tags: None,
+            remove_tags: None,
+            estimate_minutes: None,
+            priority: None,
+            list_id: None,
+            archived: None,
         };
 
         let result = invalid_request.validate();
@@ -1078,7 +6790,7 @@ mod tests {
 
     #[test]
     fn test_todo_update_with_validation() {
-        let mut todo = Todo::new("Original Title", "Original Content");
+        let mut todo = Todo::new_with_validation("Original Title", "Original Content").unwrap();
 
         let result =
             todo.update_with_validation(Some("Updated Title"), Some("Updated Content"), Some(true));
@@ -1091,7 +6803,7 @@ mod tests {
 
     #[test]
     fn test_todo_update_with_validation_empty_title() {
-        let mut todo = Todo::new("Original Title", "Original Content");
+        let mut todo = Todo::new_with_validation("Original Title", "Original Content").unwrap();
 
         let result = todo.update_with_validation(Some(""), None, None);
         assert!(result.is_err());
@@ -1104,16 +6816,17 @@ mod tests {
 
     #[test]
     fn test_todo_is_valid() {
-        let todo = Todo::new("Valid Title", "Valid Content");
+        let todo = Todo::new_with_validation("Valid Title", "Valid Content").unwrap();
         assert!(todo.is_valid().is_ok());
     }
 
     #[test]
     fn test_todo_markdown_content_processing() {
-        let todo = Todo::new(
+        let todo = Todo::new_with_validation(
             "Test Title",
             "# Header\n\n**Bold** text with [link](https://example.com)",
-        );
+        )
+        .unwrap();
         assert!(todo.content.contains("# Header"));
         assert!(todo.content.contains("**Bold**"));
         assert!(todo.content.contains("[link](https://example.com)"));
@@ -1121,8 +6834,8 @@ mod tests {
 
     #[test]
     fn test_todo_uuid_generation() {
-        let todo1 = Todo::new("Title 1", "Content 1");
-        let todo2 = Todo::new("Title 2", "Content 2");
+        let todo1 = Todo::new_with_validation("Title 1", "Content 1").unwrap();
+        let todo2 = Todo::new_with_validation("Title 2", "Content 2").unwrap();
 
         assert_ne!(todo1.id, todo2.id);
         assert!(todo1.id.get_version() == Some(uuid::Version::SortRand));
@@ -1131,16 +6844,16 @@ mod tests {
 
     #[test]
     fn test_todo_timestamp_ordering() {
-        let todo1 = Todo::new("Title 1", "Content 1");
+        let todo1 = Todo::new_with_validation("Title 1", "Content 1").unwrap();
         std::thread::sleep(std::time::Duration::from_millis(1));
-        let todo2 = Todo::new("Title 2", "Content 2");
+        let todo2 = Todo::new_with_validation("Title 2", "Content 2").unwrap();
 
         assert!(todo1.created_at < todo2.created_at);
     }
 
     #[test]
     fn test_todo_clone() {
-        let original = Todo::new("Original Title", "Original Content");
+        let original = Todo::new_with_validation("Original Title", "Original Content").unwrap();
         let cloned = original.clone();
 
         assert_eq!(original.id, cloned.id);
@@ -1150,4 +6863,11 @@ mod tests {
         assert_eq!(original.created_at, cloned.created_at);
         assert_eq!(original.updated_at, cloned.updated_at);
     }
+
+    #[test]
+    fn test_keys_match() {
+        assert!(keys_match("secret", "secret"));
+        assert!(!keys_match("secret", "secreT"));
+        assert!(!keys_match("secret", ""));
+    }
 }
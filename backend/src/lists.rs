@@ -0,0 +1,432 @@
+//! Lists: projects that group todos, such as "Home" or "Client A".
+//!
+//! A list lives under `/api/lists` with a unique name and a description. A todo belongs to
+//! at most one list through its `list_id`, set on create and changed or cleared (with
+//! `"list_id": null`) on update. `GET /api/lists/:id/todos` lists a list's todos and takes
+//! the same filters and sort as `GET /api/todos`. Deleting a list keeps its todos; they
+//! just no longer belong to a list.
+
+use crate::errors::ErrorCode;
+use crate::stale::StaleConfig;
+use crate::{filter_from_query, ApiResponse, TodoError, TodoListResponse, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub const MAX_NAME_LENGTH: usize = 100;
+pub const MAX_DESCRIPTION_LENGTH: usize = 2000;
+
+/// NFC and trimmed; names are unique as written after that
+pub fn normalize_name(name: &str) -> Result<String, String> {
+    let name = crate::unicode::nfc(name.trim());
+    if name.is_empty() {
+        return Err("List names cannot be empty".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("List names cannot contain control characters".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "List names cannot exceed {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    Ok(name)
+}
+
+fn check_description(description: &str) -> Result<(), String> {
+    if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        return Err(format!(
+            "List descriptions cannot exceed {} characters",
+            MAX_DESCRIPTION_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Tells a field that is absent (`None`) from one set to null (`Some(None)`)
+pub fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Fails with 400 unless `list_id` is `None` or an existing list
+pub async fn check_list<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    list_id: Option<Uuid>,
+) -> Result<(), TodoError> {
+    let Some(list_id) = list_id else {
+        return Ok(());
+    };
+    match repository.get_list(list_id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(TodoError::Validation(format!("No list with id {list_id}"))),
+        Err(e) => {
+            tracing::error!("Failed to look up list {}: {}", list_id, e);
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct TodoList {
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Uuid,
+    #[schema(example = "Client A")]
+    pub name: String,
+    #[schema(example = "Website relaunch, due in March")]
+    pub description: String,
+    /// Todos in the list
+    #[schema(example = 12)]
+    pub todo_count: i64,
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub created_at: DateTime<Utc>,
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TodoList {
+    /// `name` must already be normalized
+    pub fn new(name: &str, description: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::now_v7(),
+            name: name.to_string(),
+            description: description.to_string(),
+            todo_count: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Outcome of updating a list
+#[derive(Debug)]
+pub enum ListUpdate {
+    Updated(TodoList),
+    NameTaken,
+    NotFound,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CreateTodoListRequest {
+    #[schema(example = "Client A", min_length = 1, max_length = 100)]
+    pub name: String,
+    #[serde(default)]
+    #[schema(example = "Website relaunch, due in March", max_length = 2000)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct UpdateTodoListRequest {
+    #[schema(example = "Client A", min_length = 1, max_length = 100)]
+    pub name: Option<String>,
+    #[schema(example = "Website relaunch, due in April", max_length = 2000)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<TodoList>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<TodoList>> for ListResponse {
+    fn from(response: ApiResponse<TodoList>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ListsResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<TodoList>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<TodoList>>> for ListsResponse {
+    fn from(response: ApiResponse<Vec<TodoList>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/lists",
+    responses(
+        (status = 200, description = "All lists by name, with how many todos each holds", body = ListsResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Lists"
+)]
+pub async fn list_lists<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<ListsResponse>, TodoError> {
+    match repository.list_lists().await {
+        Ok(lists) => Ok(Json(ApiResponse::success(lists).into())),
+        Err(e) => {
+            tracing::error!("Failed to list lists: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/lists",
+    request_body = CreateTodoListRequest,
+    responses(
+        (status = 200, description = "List created", body = ListResponse),
+        (status = 400, description = "Invalid name or description", body = ErrorResponse),
+        (status = 409, description = "A list of that name exists", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Lists"
+)]
+pub async fn create_list<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Json(request): Json<CreateTodoListRequest>,
+) -> Result<Json<ListResponse>, TodoError> {
+    let name = normalize_name(&request.name).map_err(|e| {
+        tracing::warn!("Validation failed for list {:?}: {}", request.name, e);
+        TodoError::Validation(e)
+    })?;
+    let description = request.description.unwrap_or_default();
+    check_description(&description).map_err(TodoError::Validation)?;
+    match repository
+        .create_list(&TodoList::new(&name, &description))
+        .await
+    {
+        Ok(Some(list)) => {
+            tracing::info!("Created list {}", list.name);
+            Ok(Json(ApiResponse::success(list).into()))
+        }
+        Ok(None) => {
+            tracing::warn!("List already exists: {}", name);
+            Err(TodoError::Conflict(format!(
+                "A list named {:?} exists",
+                name
+            )))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create list {}: {}", name, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/lists/{id}",
+    params(
+        ("id" = Uuid, Path, description = "List ID")
+    ),
+    responses(
+        (status = 200, description = "List found", body = ListResponse),
+        (status = 404, description = "List not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Lists"
+)]
+pub async fn get_list<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ListResponse>, TodoError> {
+    match repository.get_list(id).await {
+        Ok(Some(list)) => Ok(Json(ApiResponse::success(list).into())),
+        Ok(None) => Err(TodoError::NotFound(format!("List {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to get list {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/lists/{id}",
+    params(
+        ("id" = Uuid, Path, description = "List ID")
+    ),
+    request_body = UpdateTodoListRequest,
+    responses(
+        (status = 200, description = "List updated", body = ListResponse),
+        (status = 400, description = "Invalid name or description", body = ErrorResponse),
+        (status = 404, description = "List not found", body = ErrorResponse),
+        (status = 409, description = "Another list has that name", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Lists"
+)]
+pub async fn update_list<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+    Json(mut request): Json<UpdateTodoListRequest>,
+) -> Result<Json<ListResponse>, TodoError> {
+    if let Some(name) = &request.name {
+        request.name = Some(normalize_name(name).map_err(|e| {
+            tracing::warn!("Validation failed for list {:?}: {}", name, e);
+            TodoError::Validation(e)
+        })?);
+    }
+    if let Some(description) = &request.description {
+        check_description(description).map_err(TodoError::Validation)?;
+    }
+    match repository.update_list(id, &request).await {
+        Ok(ListUpdate::Updated(list)) => {
+            tracing::info!("Updated list {}", id);
+            Ok(Json(ApiResponse::success(list).into()))
+        }
+        Ok(ListUpdate::NameTaken) => {
+            tracing::warn!("Cannot rename list {}: name taken", id);
+            Err(TodoError::Conflict(
+                "Another list has that name".to_string(),
+            ))
+        }
+        Ok(ListUpdate::NotFound) => Err(TodoError::NotFound(format!("List {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to update list {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+/// The list's todos stay, outside any list and with a new version
+#[utoipa::path(
+    delete,
+    path = "/api/lists/{id}",
+    params(
+        ("id" = Uuid, Path, description = "List ID")
+    ),
+    responses(
+        (status = 204, description = "List deleted"),
+        (status = 404, description = "List not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Lists"
+)]
+pub async fn delete_list<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, TodoError> {
+    match repository.delete_list(id).await {
+        Ok(Some(moved_out)) => {
+            tracing::info!("Deleted list {} holding {} todos", id, moved_out.len());
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(None) => Err(TodoError::NotFound(format!("List {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to delete list {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/lists/{id}/todos",
+    params(
+        ("id" = Uuid, Path, description = "List ID"),
+        ("label" = Option<String>, Query, description = "Label path; also matches child labels"),
+        ("tag" = Option<String>, Query, description = "Tag name; keeps todos carrying the tag"),
+        ("priority" = Option<String>, Query, description = "Comma-separated priorities, e.g. high,urgent"),
+        ("stale" = Option<bool>, Query, description = "Only open todos untouched for `STALE_AFTER_DAYS` (true) or only the others (false)"),
+        ("sort" = Option<crate::TodoSort>, Query, description = "Sort key (default created_at)"),
+        ("order" = Option<crate::SortOrder>, Query, description = "Direction; defaults to asc for title and desc otherwise")
+    ),
+    responses(
+        (status = 200, description = "Todos in the list", body = TodoListResponse),
+        (status = 400, description = "Invalid filter or sort", body = ErrorResponse),
+        (status = 404, description = "List not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Lists"
+)]
+pub async fn get_list_todos<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(stale): Extension<Arc<StaleConfig>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<TodoListResponse>, TodoError> {
+    let mut filter = filter_from_query(&params, &stale)?;
+    filter.list_id = Some(id);
+    match repository.get_list(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(TodoError::NotFound(format!("List {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to get list {}: {}", id, e);
+            return Err(e);
+        }
+    }
+    match repository.find_todos(&filter).await {
+        Ok(mut todos) => {
+            stale.mark(&mut todos);
+            Ok(Json(ApiResponse::success(todos).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get todos of list {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UpdateTodoRequest;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("  Client A "), Ok("Client A".to_string()));
+        assert_eq!(normalize_name("Cafe\u{301}"), Ok("Café".to_string()));
+        assert!(normalize_name(" ").is_err());
+        assert!(normalize_name("a\nb").is_err());
+        assert!(normalize_name(&"x".repeat(MAX_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_update_tells_null_list_from_absent() {
+        let id = Uuid::now_v7();
+        let update: UpdateTodoRequest = serde_json::from_str(r#"{"title": "T"}"#).unwrap();
+        assert_eq!(update.list_id, None);
+        let update: UpdateTodoRequest = serde_json::from_str(r#"{"list_id": null}"#).unwrap();
+        assert_eq!(update.list_id, Some(None));
+        let update: UpdateTodoRequest =
+            serde_json::from_str(&format!(r#"{{"list_id": "{id}"}}"#)).unwrap();
+        assert_eq!(update.list_id, Some(Some(id)));
+        // Serializing back must not turn "leave alone" into "clear"
+        let json = serde_json::to_value(UpdateTodoRequest::default()).unwrap();
+        assert!(json.get("list_id").is_none());
+    }
+}
@@ -0,0 +1,72 @@
+//! Log output setup, with a filter that can be changed while the server runs.
+//!
+//! `init` installs the global subscriber. Its `EnvFilter` starts from `RUST_LOG` and sits
+//! behind a reload handle, so `PUT /api/admin/log-level` can switch a running deployment
+//! to debug logging and back without a restart.
+
+use std::sync::OnceLock;
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+const DEFAULT_FILTER: &str = "md_todo_backend=debug,tower_http=debug,axum::rejection=trace";
+
+struct ReloadableFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+static FILTER: OnceLock<ReloadableFilter> = OnceLock::new();
+
+/// Installs the global subscriber; later calls do nothing
+pub fn init() {
+    FILTER.get_or_init(|| {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into());
+        let (filter, handle) = reload::Layer::new(filter);
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer())
+            .init();
+        ReloadableFilter { handle }
+    });
+}
+
+/// Filter directives in effect, `None` before `init`
+pub fn current_filter() -> Option<String> {
+    FILTER
+        .get()?
+        .handle
+        .with_current(|filter| filter.to_string())
+        .ok()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SetFilterError {
+    /// The directives do not parse
+    Invalid(String),
+    /// `init` was not called in this process
+    NotReloadable,
+}
+
+/// Replaces the filter with `directives`, e.g. `debug` or `md_todo_backend=trace,warn`
+pub fn set_filter(directives: &str) -> Result<(), SetFilterError> {
+    let filter =
+        EnvFilter::try_new(directives).map_err(|e| SetFilterError::Invalid(e.to_string()))?;
+    let reloadable = FILTER.get().ok_or(SetFilterError::NotReloadable)?;
+    reloadable
+        .handle
+        .reload(filter)
+        .map_err(|_| SetFilterError::NotReloadable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_directives_are_rejected() {
+        assert!(matches!(
+            set_filter("md_todo_backend=loud"),
+            Err(SetFilterError::Invalid(_))
+        ));
+    }
+}
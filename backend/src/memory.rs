@@ -0,0 +1,1448 @@
+//! Repository that keeps everything in process memory, for `--local` mode.
+//!
+//! It behaves like `DatabaseTodoRepository` as far as the API can tell (list order,
+//! versions, short ids, share link limits, metadata field cleanup) but nothing survives a
+//! restart. Content is stored as written; compression only matters on disk. Like the
+//! row-level security of `todos`, it only shows the acting user their own todos and the
+//! shared ones.
+
+use crate::auth::{self, User};
+use crate::autocomplete::{self, AutocompleteQuery, TitleSuggestion};
+use crate::domain::{TodoContent, TodoId};
+use crate::encryption::{EncryptedTodo, EncryptedUpdate};
+use crate::imports::{ImportItem, ImportJob, ImportStatus};
+use crate::lists::{ListUpdate, TodoList, UpdateTodoListRequest};
+use crate::metadata::{self, MetadataField};
+use crate::moderation::{Finding, ModerationFlag};
+use crate::policy::ValidationPolicy;
+use crate::preconditions;
+use crate::relations::{RelationKind, RelationSummary, TodoRelation};
+use crate::search::{self, SearchHit, SearchQuery};
+use crate::share::{ShareAccess, ShareLink, ShareOutcome};
+use crate::split::TodoSplit;
+use crate::stats::{self, AgingReport, WorkloadTotals};
+use crate::streaks::CompletionDay;
+use crate::subtasks::SubtaskMerge;
+use crate::tags::{self, Tag, TagRename};
+use crate::webhooks::{Webhook, WebhookDelivery};
+use crate::{
+    labels, shortlink, ContentUpdate, SortOrder, Todo, TodoError, TodoFilter, TodoRepositoryTrait,
+    TodoSort, TodoUpsert, UpdateTodoRequest,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use tokio::sync::{RwLock, RwLockWriteGuard};
+use uuid::Uuid;
+
+#[derive(Default)]
+struct Store {
+    todos: Vec<Todo>,
+    /// Todo of each short id, in issue order; short id `n` is entry `n - 1`
+    short_ids: Vec<TodoId>,
+    share_links: Vec<ShareLink>,
+    share_access: Vec<(String, ShareAccess)>,
+    metadata_fields: Vec<MetadataField>,
+    validation_policy: ValidationPolicy,
+    /// Flags carry the title of their todo as it was when flagged; it is refreshed on
+    /// the way out
+    moderation_flags: Vec<ModerationFlag>,
+    /// In creation order, which is id order
+    encrypted_todos: Vec<EncryptedTodo>,
+    /// In creation order, which is id order
+    webhooks: Vec<Webhook>,
+    /// In attempt order
+    webhook_deliveries: Vec<WebhookDelivery>,
+    /// Todos hold tag names, so `todo_count` is left at 0 here and counted on the way out
+    tags: Vec<Tag>,
+    /// `todo_count` is counted on the way out, as for tags
+    lists: Vec<TodoList>,
+    /// Import jobs with the items they have yet to create
+    imports: Vec<(ImportJob, Vec<ImportItem>)>,
+    /// Todos marked completed per user and UTC day
+    completions: BTreeMap<(Uuid, NaiveDate), i32>,
+    /// Todo ids planned by each user for each UTC day, in plan order
+    plans: BTreeMap<(Uuid, NaiveDate), Vec<TodoId>>,
+    /// In creation order
+    relations: Vec<TodoRelation>,
+    users: Vec<User>,
+}
+
+impl Store {
+    fn todo_mut(&mut self, id: TodoId) -> Option<&mut Todo> {
+        self.todos.iter_mut().find(|todo| todo.id == id)
+    }
+
+    /// Fails when the todo exists at another version than `expected`
+    fn check_version(&self, id: TodoId, expected: Option<i32>) -> Result<(), TodoError> {
+        match self.todos.iter().find(|todo| todo.id == id) {
+            Some(todo) => preconditions::check_version(id, todo.version, expected),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates the tags among `names` that do not exist yet
+    fn ensure_tags(&mut self, names: &[String]) {
+        for name in names {
+            if !self.tags.iter().any(|tag| &tag.name == name) {
+                self.tags.push(Tag::new(name));
+            }
+        }
+    }
+
+    /// Applies `updates` to a todo; `None` when there is no such todo
+    fn update(
+        &mut self,
+        id: TodoId,
+        updates: &UpdateTodoRequest,
+    ) -> Result<Option<Todo>, TodoError> {
+        let (title, content) = updates.text()?;
+        let Some(current) = self.todo_mut(id) else {
+            return Ok(None);
+        };
+        let tags = tags::apply_update(&current.tags, updates);
+        if let Some(tags) = &tags {
+            self.ensure_tags(tags);
+        }
+        let Some(todo) = self.todo_mut(id) else {
+            return Ok(None);
+        };
+        let newly_completed = updates.completed == Some(true) && !todo.completed;
+        if let Some(tags) = tags {
+            todo.tags = tags;
+        }
+        if let Some(title) = title {
+            todo.title = title;
+        }
+        if let Some(content) = content {
+            todo.content = content;
+        }
+        if let Some(completed) = updates.completed {
+            todo.completed = completed;
+        }
+        if let Some(archived) = updates.archived {
+            todo.archived = archived;
+        }
+        if let Some(metadata) = &updates.metadata {
+            todo.metadata = metadata.clone();
+        }
+        if let Some(labels) = &updates.labels {
+            todo.labels = labels.clone();
+        }
+        if let Some(estimate) = updates.estimate_minutes {
+            todo.estimate_minutes = Some(estimate);
+        }
+        if let Some(priority) = updates.priority {
+            todo.priority = priority;
+        }
+        if let Some(list_id) = updates.list_id {
+            todo.list_id = list_id;
+        }
+        todo.version += 1;
+        todo.updated_at = Utc::now();
+        let updated = todo.clone();
+        if newly_completed {
+            self.record_completions(1, updated.updated_at);
+        }
+        Ok(Some(updated))
+    }
+
+    fn record_completions(&mut self, count: usize, at: DateTime<Utc>) {
+        if count > 0 {
+            let key = (auth::acting_user(), at.date_naive());
+            *self.completions.entry(key).or_default() += count as i32;
+        }
+    }
+
+    /// Deletes a todo along with its share links
+    fn delete(&mut self, id: TodoId) -> bool {
+        let before = self.todos.len();
+        self.todos.retain(|todo| todo.id != id);
+        if self.todos.len() == before {
+            return false;
+        }
+        // Short ids are never reissued, so their slot stays taken
+        self.relations
+            .retain(|relation| relation.todo_id != id && relation.related_id != id);
+        let Store {
+            share_links,
+            share_access,
+            ..
+        } = self;
+        share_links.retain(|link| {
+            let keep = link.todo_id != id;
+            if !keep {
+                share_access.retain(|(token, _)| *token != link.token);
+            }
+            keep
+        });
+        true
+    }
+
+    fn counted(&self, tag: &Tag) -> Tag {
+        Tag {
+            todo_count: self
+                .todos
+                .iter()
+                .filter(|todo| todo.tags.contains(&tag.name))
+                .count() as i64,
+            ..tag.clone()
+        }
+    }
+
+    fn counted_list(&self, list: &TodoList) -> TodoList {
+        TodoList {
+            todo_count: self
+                .todos
+                .iter()
+                .filter(|todo| todo.list_id == Some(list.id))
+                .count() as i64,
+            ..list.clone()
+        }
+    }
+
+    fn sorted(&self, sort: TodoSort, order: SortOrder) -> Vec<Todo> {
+        let mut todos = self.todos.clone();
+        todos.sort_by(|a, b| sort.compare(order, a, b));
+        todos
+    }
+}
+
+/// `todo` as created by the acting user, whose it then is as in the database
+fn owned(todo: &Todo) -> Todo {
+    Todo {
+        owner_id: auth::acting_user(),
+        ..todo.clone()
+    }
+}
+
+/// The store with the todos the acting user may not see set aside, as the row-level
+/// security of `todos` does in the database; they go back in place when it is dropped
+struct Visible<'a> {
+    store: RwLockWriteGuard<'a, Store>,
+    /// Set-aside todos with their places in `todos`, in order
+    hidden: Vec<(usize, Todo)>,
+}
+
+impl<'a> Visible<'a> {
+    fn new(mut store: RwLockWriteGuard<'a, Store>) -> Self {
+        let user = auth::acting_user();
+        let mut hidden = Vec::new();
+        if user != auth::EVERYONE {
+            for (index, todo) in std::mem::take(&mut store.todos).into_iter().enumerate() {
+                if auth::may_see(user, todo.owner_id) {
+                    store.todos.push(todo);
+                } else {
+                    hidden.push((index, todo));
+                }
+            }
+        }
+        Self { store, hidden }
+    }
+
+    /// Whether there is a todo `id`, in sight or not
+    fn taken(&self, id: TodoId) -> bool {
+        self.store
+            .todos
+            .iter()
+            .chain(self.hidden.iter().map(|(_, todo)| todo))
+            .any(|todo| todo.id == id)
+    }
+
+    /// The todos set aside, for changes that reach every user's todos, as a tag name or a
+    /// list reference does in the database
+    fn hidden_mut(&mut self) -> impl Iterator<Item = &mut Todo> {
+        self.hidden.iter_mut().map(|(_, todo)| todo)
+    }
+}
+
+impl Deref for Visible<'_> {
+    type Target = Store;
+
+    fn deref(&self) -> &Store {
+        &self.store
+    }
+}
+
+impl DerefMut for Visible<'_> {
+    fn deref_mut(&mut self) -> &mut Store {
+        &mut self.store
+    }
+}
+
+impl Drop for Visible<'_> {
+    fn drop(&mut self) {
+        for (index, todo) in self.hidden.drain(..) {
+            let index = index.min(self.store.todos.len());
+            self.store.todos.insert(index, todo);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryTodoRepository {
+    store: RwLock<Store>,
+}
+
+impl MemoryTodoRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The store as the acting user sees it
+    async fn visible(&self) -> Visible<'_> {
+        Visible::new(self.store.write().await)
+    }
+}
+
+#[async_trait]
+impl TodoRepositoryTrait for MemoryTodoRepository {
+    async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError> {
+        let mut store = self.visible().await;
+        if store.taken(todo.id) {
+            return Err(TodoError::Conflict(format!(
+                "Todo {} already exists",
+                todo.id
+            )));
+        }
+        let todo = owned(todo);
+        store.ensure_tags(&todo.tags);
+        store.todos.push(todo.clone());
+        store.short_ids.push(todo.id);
+        Ok(todo)
+    }
+
+    async fn create_todos_bulk(&self, todos: &[Todo]) -> Result<Vec<Todo>, TodoError> {
+        let mut store = self.visible().await;
+        let mut ids = HashSet::new();
+        for todo in todos {
+            if !ids.insert(todo.id) || store.taken(todo.id) {
+                return Err(TodoError::Conflict(format!(
+                    "Todo {} already exists",
+                    todo.id
+                )));
+            }
+        }
+        let todos: Vec<Todo> = todos.iter().map(owned).collect();
+        for todo in &todos {
+            store.ensure_tags(&todo.tags);
+        }
+        store.todos.extend_from_slice(&todos);
+        store.short_ids.extend(todos.iter().map(|todo| todo.id));
+        Ok(todos)
+    }
+
+    async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        let store = self.visible().await;
+        Ok(store.sorted(TodoSort::default(), SortOrder::default()))
+    }
+
+    async fn find_todos(&self, filter: &TodoFilter) -> Result<Vec<Todo>, TodoError> {
+        let store = self.visible().await;
+        Ok(store
+            .sorted(filter.sort, filter.order)
+            .into_iter()
+            .filter(|todo| metadata::matches_filters(&todo.metadata, &filter.metadata))
+            .filter(|todo| {
+                filter.label.as_ref().is_none_or(|prefix| {
+                    todo.labels
+                        .iter()
+                        .any(|label| labels::is_within(label, prefix))
+                })
+            })
+            .filter(|todo| {
+                filter
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| todo.tags.contains(tag))
+            })
+            .filter(|todo| filter.priority.is_empty() || filter.priority.contains(&todo.priority))
+            .filter(|todo| {
+                filter
+                    .list_id
+                    .is_none_or(|list_id| todo.list_id == Some(list_id))
+            })
+            .filter(|todo| filter.include_archived || !todo.archived)
+            .filter(|todo| filter.stale.is_none_or(|stale| stale.matches(todo)))
+            .collect())
+    }
+
+    async fn search_todos(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, TodoError> {
+        Ok(search::search_in(query, &self.visible().await.todos))
+    }
+
+    async fn autocomplete_titles(
+        &self,
+        query: &AutocompleteQuery,
+    ) -> Result<Vec<TitleSuggestion>, TodoError> {
+        Ok(autocomplete::suggest_in(query, &self.visible().await.todos))
+    }
+
+    async fn get_todos_after(
+        &self,
+        cursor: Option<TodoId>,
+        limit: i64,
+        sort: TodoSort,
+        order: SortOrder,
+        include_archived: bool,
+    ) -> Result<Vec<Todo>, TodoError> {
+        let todos = self.visible().await.sorted(sort, order);
+        let start = match cursor {
+            Some(cursor) => match todos.iter().position(|todo| todo.id == cursor) {
+                Some(index) => index + 1,
+                None => return Ok(Vec::new()),
+            },
+            None => 0,
+        };
+        // The cursor may have been archived since; it still marks where the page starts
+        Ok(todos
+            .into_iter()
+            .skip(start)
+            .filter(|todo| include_archived || !todo.archived)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn get_todo_by_id(&self, id: TodoId) -> Result<Option<Todo>, TodoError> {
+        let store = self.visible().await;
+        Ok(store.todos.iter().find(|todo| todo.id == id).cloned())
+    }
+
+    async fn update_todo(
+        &self,
+        id: TodoId,
+        updates: &UpdateTodoRequest,
+        expected_version: Option<i32>,
+    ) -> Result<Option<Todo>, TodoError> {
+        let mut store = self.visible().await;
+        store.check_version(id, expected_version)?;
+        store.update(id, updates)
+    }
+
+    async fn upsert_todo(
+        &self,
+        todo: &Todo,
+        expected_version: Option<i32>,
+    ) -> Result<TodoUpsert, TodoError> {
+        let mut store = self.visible().await;
+        store.check_version(todo.id, expected_version)?;
+        if store.todo_mut(todo.id).is_none() && store.taken(todo.id) {
+            return Err(TodoError::Conflict(
+                "A todo with this id already exists".to_string(),
+            ));
+        }
+        store.ensure_tags(&todo.tags);
+        let Some(current) = store.todo_mut(todo.id) else {
+            let todo = owned(todo);
+            store.todos.push(todo.clone());
+            store.short_ids.push(todo.id);
+            return Ok(TodoUpsert::Created(todo));
+        };
+        let newly_completed = todo.completed && !current.completed;
+        *current = Todo {
+            version: current.version + 1,
+            owner_id: current.owner_id,
+            created_at: current.created_at,
+            ..todo.clone()
+        };
+        let replaced = current.clone();
+        if newly_completed {
+            store.record_completions(1, replaced.updated_at);
+        }
+        Ok(TodoUpsert::Replaced(replaced))
+    }
+
+    async fn update_todos_bulk(
+        &self,
+        ids: &[TodoId],
+        updates: &UpdateTodoRequest,
+    ) -> Result<Vec<Todo>, TodoError> {
+        let mut store = self.visible().await;
+        let mut updated = Vec::with_capacity(ids.len());
+        for &id in ids {
+            updated.extend(store.update(id, updates)?);
+        }
+        Ok(updated)
+    }
+
+    async fn update_todo_content(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        base_version: i32,
+    ) -> Result<ContentUpdate, TodoError> {
+        let mut store = self.visible().await;
+        Ok(match store.todo_mut(id) {
+            Some(todo) if todo.version == base_version => {
+                todo.content = content.clone();
+                todo.version += 1;
+                todo.updated_at = Utc::now();
+                ContentUpdate::Updated(todo.clone())
+            }
+            Some(todo) => ContentUpdate::Conflict(todo.clone()),
+            None => ContentUpdate::NotFound,
+        })
+    }
+
+    async fn split_todo(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        parts: &[Todo],
+    ) -> Result<Option<TodoSplit>, TodoError> {
+        let mut store = self.visible().await;
+        store.check_version(id, Some(expected_version))?;
+        if let Some(part) = parts.iter().find(|part| store.taken(part.id)) {
+            return Err(TodoError::Conflict(format!(
+                "Todo {} already exists",
+                part.id
+            )));
+        }
+        let updates = UpdateTodoRequest {
+            content: Some(content.as_str().to_string()),
+            ..Default::default()
+        };
+        let Some(todo) = store.update(id, &updates)? else {
+            return Ok(None);
+        };
+        let parts: Vec<Todo> = parts.iter().map(owned).collect();
+        for part in &parts {
+            store.ensure_tags(&part.tags);
+            store.todos.push(part.clone());
+            store.short_ids.push(part.id);
+            store
+                .relations
+                .push(TodoRelation::new(part.id, id, RelationKind::SplitFrom));
+        }
+        Ok(Some(TodoSplit { todo, parts }))
+    }
+
+    async fn merge_subtasks(
+        &self,
+        parent: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        subtasks: &[TodoId],
+    ) -> Result<Option<SubtaskMerge>, TodoError> {
+        let mut store = self.visible().await;
+        store.check_version(parent, Some(expected_version))?;
+        let mergeable = subtasks.iter().all(|id| {
+            store
+                .todos
+                .iter()
+                .any(|todo| todo.id == *id && todo.completed && !todo.archived)
+        });
+        if !mergeable {
+            return Ok(None);
+        }
+        let updates = UpdateTodoRequest {
+            content: Some(content.as_str().to_string()),
+            ..Default::default()
+        };
+        let Some(todo) = store.update(parent, &updates)? else {
+            return Ok(None);
+        };
+        let archive = UpdateTodoRequest {
+            archived: Some(true),
+            ..Default::default()
+        };
+        let mut archived = Vec::with_capacity(subtasks.len());
+        for id in subtasks {
+            archived.extend(store.update(*id, &archive)?);
+        }
+        Ok(Some(SubtaskMerge {
+            parent: todo,
+            subtasks: archived,
+        }))
+    }
+
+    async fn delete_todo(
+        &self,
+        id: TodoId,
+        expected_version: Option<i32>,
+    ) -> Result<bool, TodoError> {
+        let mut store = self.visible().await;
+        store.check_version(id, expected_version)?;
+        Ok(store.delete(id))
+    }
+
+    async fn delete_todos_bulk(&self, ids: &[TodoId]) -> Result<Vec<TodoId>, TodoError> {
+        let mut store = self.visible().await;
+        Ok(ids.iter().copied().filter(|id| store.delete(*id)).collect())
+    }
+
+    async fn complete_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        let mut store = self.visible().await;
+        let now = Utc::now();
+        let completed: Vec<Todo> = store
+            .todos
+            .iter_mut()
+            .filter(|todo| !todo.completed && !todo.archived)
+            .map(|todo| {
+                todo.completed = true;
+                todo.version += 1;
+                todo.updated_at = now;
+                todo.clone()
+            })
+            .collect();
+        store.record_completions(completed.len(), now);
+        Ok(completed)
+    }
+
+    async fn delete_completed_todos(&self) -> Result<Vec<TodoId>, TodoError> {
+        let mut store = self.visible().await;
+        let ids: Vec<TodoId> = store
+            .todos
+            .iter()
+            .filter(|todo| todo.completed && !todo.archived)
+            .map(|todo| todo.id)
+            .collect();
+        for id in &ids {
+            store.delete(*id);
+        }
+        Ok(ids)
+    }
+
+    async fn get_short_id(&self, todo_id: TodoId) -> Result<Option<String>, TodoError> {
+        let store = self.visible().await;
+        Ok(store
+            .short_ids
+            .iter()
+            .position(|id| *id == todo_id)
+            .filter(|_| store.todos.iter().any(|todo| todo.id == todo_id))
+            .map(|index| shortlink::encode(index as u64 + 1)))
+    }
+
+    async fn resolve_short_id(&self, short_id: &str) -> Result<Option<TodoId>, TodoError> {
+        let Some(seq) = shortlink::decode(short_id).filter(|seq| *seq > 0) else {
+            return Ok(None);
+        };
+        let store = self.store.read().await;
+        Ok(usize::try_from(seq - 1)
+            .ok()
+            .and_then(|index| store.short_ids.get(index))
+            .copied()
+            .filter(|id| store.todos.iter().any(|todo| todo.id == *id)))
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<ShareLink, TodoError> {
+        let mut store = self.store.write().await;
+        if !store.todos.iter().any(|todo| todo.id == link.todo_id) {
+            return Err(TodoError::NotFound(format!("Todo {}", link.todo_id)));
+        }
+        store.share_links.push(link.clone());
+        Ok(link.clone())
+    }
+
+    async fn list_share_links(&self, todo_id: TodoId) -> Result<Vec<ShareLink>, TodoError> {
+        let store = self.store.read().await;
+        let mut links: Vec<ShareLink> = store
+            .share_links
+            .iter()
+            .filter(|link| link.todo_id == todo_id)
+            .cloned()
+            .collect();
+        links.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| a.token.cmp(&b.token))
+        });
+        Ok(links)
+    }
+
+    async fn get_share_link(&self, token: &str) -> Result<Option<ShareLink>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .share_links
+            .iter()
+            .find(|link| link.token == token)
+            .cloned())
+    }
+
+    async fn delete_share_link(&self, token: &str) -> Result<bool, TodoError> {
+        let mut store = self.store.write().await;
+        let before = store.share_links.len();
+        store.share_links.retain(|link| link.token != token);
+        store.share_access.retain(|(logged, _)| logged != token);
+        Ok(store.share_links.len() < before)
+    }
+
+    async fn record_share_access(
+        &self,
+        token: &str,
+        access: &ShareAccess,
+    ) -> Result<ShareOutcome, TodoError> {
+        let mut store = self.store.write().await;
+        let link = store
+            .share_links
+            .iter_mut()
+            .find(|link| link.token == token)
+            .ok_or_else(|| TodoError::NotFound("Share link".to_string()))?;
+        let mut access = access.clone();
+        match access.outcome {
+            ShareOutcome::Viewed if link.max_views.is_some_and(|max| link.view_count >= max) => {
+                access.outcome = ShareOutcome::ViewLimitReached;
+            }
+            ShareOutcome::Viewed => link.view_count += 1,
+            ShareOutcome::WrongPasscode => link.failed_attempts += 1,
+            _ => {}
+        }
+        let outcome = access.outcome;
+        store.share_access.push((token.to_string(), access));
+        Ok(outcome)
+    }
+
+    async fn share_access_log(
+        &self,
+        token: &str,
+        limit: i64,
+    ) -> Result<Vec<ShareAccess>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .share_access
+            .iter()
+            .rev()
+            .filter(|(logged, _)| logged == token)
+            .take(limit.max(0) as usize)
+            .map(|(_, access)| access.clone())
+            .collect())
+    }
+
+    async fn list_metadata_fields(&self) -> Result<Vec<MetadataField>, TodoError> {
+        Ok(self.store.read().await.metadata_fields.clone())
+    }
+
+    async fn upsert_metadata_field(
+        &self,
+        field: &MetadataField,
+    ) -> Result<MetadataField, TodoError> {
+        let mut store = self.store.write().await;
+        store.metadata_fields.retain(|f| f.key != field.key);
+        store.metadata_fields.push(field.clone());
+        store.metadata_fields.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(field.clone())
+    }
+
+    async fn delete_metadata_field(&self, key: &str) -> Result<bool, TodoError> {
+        let mut store = self.store.write().await;
+        let before = store.metadata_fields.len();
+        store.metadata_fields.retain(|f| f.key != key);
+        if store.metadata_fields.len() == before {
+            return Ok(false);
+        }
+        for todo in store.todos.iter_mut() {
+            if let Some(entries) = todo.metadata.as_object_mut() {
+                if entries.remove(key).is_some() {
+                    todo.version += 1;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    async fn validation_policy(&self) -> Result<ValidationPolicy, TodoError> {
+        Ok(self.store.read().await.validation_policy.clone())
+    }
+
+    async fn set_validation_policy(
+        &self,
+        policy: &ValidationPolicy,
+    ) -> Result<ValidationPolicy, TodoError> {
+        self.store.write().await.validation_policy = policy.clone();
+        Ok(policy.clone())
+    }
+
+    async fn flag_todo(&self, id: TodoId, findings: &[Finding]) -> Result<(), TodoError> {
+        let mut store = self.store.write().await;
+        let Some(title) = store
+            .todos
+            .iter()
+            .find(|t| t.id == id)
+            .map(|t| t.title.clone())
+        else {
+            return Err(TodoError::NotFound(format!("Todo {}", id)));
+        };
+        for finding in findings {
+            store.moderation_flags.retain(|flag| {
+                !(flag.todo_id == id
+                    && flag.policy == finding.policy
+                    && flag.field == finding.field)
+            });
+            store.moderation_flags.push(ModerationFlag {
+                id: Uuid::now_v7(),
+                todo_id: id,
+                title: title.clone(),
+                policy: finding.policy.to_string(),
+                field: finding.field.to_string(),
+                reason: finding.reason.clone(),
+                created_at: Utc::now(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn moderation_flags(&self) -> Result<Vec<ModerationFlag>, TodoError> {
+        let store = self.visible().await;
+        Ok(store
+            .moderation_flags
+            .iter()
+            .filter_map(|flag| {
+                let todo = store.todos.iter().find(|t| t.id == flag.todo_id)?;
+                Some(ModerationFlag {
+                    title: todo.title.clone(),
+                    ..flag.clone()
+                })
+            })
+            .collect())
+    }
+
+    async fn dismiss_moderation_flag(&self, id: Uuid) -> Result<bool, TodoError> {
+        let mut store = self.store.write().await;
+        let before = store.moderation_flags.len();
+        store.moderation_flags.retain(|flag| flag.id != id);
+        Ok(store.moderation_flags.len() < before)
+    }
+
+    async fn create_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+    ) -> Result<EncryptedTodo, TodoError> {
+        let mut store = self.store.write().await;
+        if store.encrypted_todos.iter().any(|t| t.id == todo.id) {
+            return Err(TodoError::Conflict(
+                "A todo with this id already exists".to_string(),
+            ));
+        }
+        store.encrypted_todos.push(todo.clone());
+        Ok(todo.clone())
+    }
+
+    async fn list_encrypted_todos(
+        &self,
+        key_fingerprint: Option<&str>,
+    ) -> Result<Vec<EncryptedTodo>, TodoError> {
+        let store = self.store.read().await;
+        let mut todos: Vec<EncryptedTodo> = store
+            .encrypted_todos
+            .iter()
+            .filter(|todo| key_fingerprint.is_none_or(|key| todo.key_fingerprint == key))
+            .cloned()
+            .collect();
+        todos.sort_by_key(|todo| todo.id);
+        Ok(todos)
+    }
+
+    async fn get_encrypted_todo(&self, id: Uuid) -> Result<Option<EncryptedTodo>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store.encrypted_todos.iter().find(|t| t.id == id).cloned())
+    }
+
+    async fn update_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+        base_version: i32,
+    ) -> Result<EncryptedUpdate, TodoError> {
+        let mut store = self.store.write().await;
+        let Some(current) = store.encrypted_todos.iter_mut().find(|t| t.id == todo.id) else {
+            return Ok(EncryptedUpdate::NotFound);
+        };
+        if current.version != base_version {
+            return Ok(EncryptedUpdate::Conflict(current.clone()));
+        }
+        *current = EncryptedTodo {
+            version: current.version + 1,
+            created_at: current.created_at,
+            ..todo.clone()
+        };
+        Ok(EncryptedUpdate::Updated(current.clone()))
+    }
+
+    async fn delete_encrypted_todo(&self, id: Uuid) -> Result<bool, TodoError> {
+        let mut store = self.store.write().await;
+        let before = store.encrypted_todos.len();
+        store.encrypted_todos.retain(|todo| todo.id != id);
+        Ok(store.encrypted_todos.len() < before)
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, TodoError> {
+        self.store.write().await.webhooks.push(webhook.clone());
+        Ok(webhook.clone())
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, TodoError> {
+        Ok(self.store.read().await.webhooks.clone())
+    }
+
+    async fn get_webhook(&self, id: Uuid) -> Result<Option<Webhook>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store.webhooks.iter().find(|w| w.id == id).cloned())
+    }
+
+    async fn update_webhook(&self, webhook: &Webhook) -> Result<Option<Webhook>, TodoError> {
+        let mut store = self.store.write().await;
+        let Some(current) = store.webhooks.iter_mut().find(|w| w.id == webhook.id) else {
+            return Ok(None);
+        };
+        *current = Webhook {
+            created_at: current.created_at,
+            updated_at: Utc::now(),
+            ..webhook.clone()
+        };
+        Ok(Some(current.clone()))
+    }
+
+    async fn delete_webhook(&self, id: Uuid) -> Result<bool, TodoError> {
+        let mut store = self.store.write().await;
+        let before = store.webhooks.len();
+        store.webhooks.retain(|webhook| webhook.id != id);
+        store
+            .webhook_deliveries
+            .retain(|delivery| delivery.webhook_id != id);
+        Ok(store.webhooks.len() < before)
+    }
+
+    async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), TodoError> {
+        let mut store = self.store.write().await;
+        if store.webhooks.iter().any(|w| w.id == delivery.webhook_id) {
+            store.webhook_deliveries.push(delivery.clone());
+        }
+        Ok(())
+    }
+
+    async fn webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .webhook_deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, TodoError> {
+        let store = self.visible().await;
+        let mut tags: Vec<Tag> = store.tags.iter().map(|tag| store.counted(tag)).collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    async fn get_tag(&self, id: Uuid) -> Result<Option<Tag>, TodoError> {
+        let store = self.visible().await;
+        Ok(store
+            .tags
+            .iter()
+            .find(|tag| tag.id == id)
+            .map(|tag| store.counted(tag)))
+    }
+
+    async fn create_tag(&self, tag: &Tag) -> Result<Option<Tag>, TodoError> {
+        let mut store = self.store.write().await;
+        if store.tags.iter().any(|existing| existing.name == tag.name) {
+            return Ok(None);
+        }
+        let tag = Tag {
+            todo_count: 0,
+            ..tag.clone()
+        };
+        store.tags.push(tag.clone());
+        Ok(Some(tag))
+    }
+
+    async fn suggest_tags(&self, text: &str, limit: i64) -> Result<Vec<Tag>, TodoError> {
+        Ok(tags::suggest_in(text, limit, self.list_tags().await?))
+    }
+
+    async fn rename_tag(&self, id: Uuid, name: &str, merge: bool) -> Result<TagRename, TodoError> {
+        let mut store = self.visible().await;
+        let Some(index) = store.tags.iter().position(|tag| tag.id == id) else {
+            return Ok(TagRename::NotFound);
+        };
+        if let Some(target) = store
+            .tags
+            .iter()
+            .position(|tag| tag.id != id && tag.name == name)
+        {
+            if !merge {
+                return Ok(TagRename::NameTaken);
+            }
+            let old = store.tags.remove(index).name;
+            let merge_into = |todo: &mut Todo| {
+                let carried = todo.tags.contains(&old);
+                if carried {
+                    todo.tags.retain(|tag| *tag != old);
+                    todo.tags.push(name.to_string());
+                    todo.tags.sort();
+                    todo.tags.dedup();
+                }
+                carried
+            };
+            let mut moved = Vec::new();
+            for todo in store.todos.iter_mut() {
+                if merge_into(todo) {
+                    todo.version += 1;
+                    moved.push(todo.clone());
+                }
+            }
+            // Other users' todos carry the new name too, without a new version
+            store.hidden_mut().for_each(|todo| {
+                merge_into(todo);
+            });
+            let target = if target > index { target - 1 } else { target };
+            return Ok(TagRename::Merged(store.counted(&store.tags[target]), moved));
+        }
+        let old = std::mem::replace(&mut store.tags[index].name, name.to_string());
+        let rename = |todo: &mut Todo| {
+            let Some(index) = todo.tags.iter().position(|tag| *tag == old) else {
+                return false;
+            };
+            todo.tags[index] = name.to_string();
+            todo.tags.sort();
+            true
+        };
+        let mut renamed = Vec::new();
+        for todo in store.todos.iter_mut() {
+            if rename(todo) {
+                todo.version += 1;
+                renamed.push(todo.clone());
+            }
+        }
+        store.hidden_mut().for_each(|todo| {
+            rename(todo);
+        });
+        Ok(TagRename::Renamed(
+            store.counted(&store.tags[index]),
+            renamed,
+        ))
+    }
+
+    async fn delete_tag(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        let mut store = self.visible().await;
+        let Some(index) = store.tags.iter().position(|tag| tag.id == id) else {
+            return Ok(None);
+        };
+        let tag = store.tags.remove(index);
+        let mut detached = Vec::new();
+        for todo in store.todos.iter_mut() {
+            if todo.tags.contains(&tag.name) {
+                todo.tags.retain(|name| *name != tag.name);
+                todo.version += 1;
+                detached.push(todo.clone());
+            }
+        }
+        store
+            .hidden_mut()
+            .for_each(|todo| todo.tags.retain(|name| *name != tag.name));
+        Ok(Some(detached))
+    }
+
+    async fn list_lists(&self) -> Result<Vec<TodoList>, TodoError> {
+        let store = self.visible().await;
+        let mut lists: Vec<TodoList> = store
+            .lists
+            .iter()
+            .map(|list| store.counted_list(list))
+            .collect();
+        lists.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(lists)
+    }
+
+    async fn get_list(&self, id: Uuid) -> Result<Option<TodoList>, TodoError> {
+        let store = self.visible().await;
+        Ok(store
+            .lists
+            .iter()
+            .find(|list| list.id == id)
+            .map(|list| store.counted_list(list)))
+    }
+
+    async fn create_list(&self, list: &TodoList) -> Result<Option<TodoList>, TodoError> {
+        let mut store = self.store.write().await;
+        if store
+            .lists
+            .iter()
+            .any(|existing| existing.name == list.name)
+        {
+            return Ok(None);
+        }
+        store.lists.push(list.clone());
+        Ok(Some(store.counted_list(list)))
+    }
+
+    async fn update_list(
+        &self,
+        id: Uuid,
+        update: &UpdateTodoListRequest,
+    ) -> Result<ListUpdate, TodoError> {
+        let mut store = self.visible().await;
+        if let Some(name) = &update.name {
+            if store
+                .lists
+                .iter()
+                .any(|list| list.id != id && &list.name == name)
+            {
+                return Ok(ListUpdate::NameTaken);
+            }
+        }
+        let Some(list) = store.lists.iter_mut().find(|list| list.id == id) else {
+            return Ok(ListUpdate::NotFound);
+        };
+        if let Some(name) = &update.name {
+            list.name = name.clone();
+        }
+        if let Some(description) = &update.description {
+            list.description = description.clone();
+        }
+        list.updated_at = Utc::now();
+        let list = list.clone();
+        Ok(ListUpdate::Updated(store.counted_list(&list)))
+    }
+
+    async fn delete_list(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        let mut store = self.visible().await;
+        let Some(index) = store.lists.iter().position(|list| list.id == id) else {
+            return Ok(None);
+        };
+        store.lists.remove(index);
+        let mut moved_out = Vec::new();
+        for todo in store.todos.iter_mut() {
+            if todo.list_id == Some(id) {
+                todo.list_id = None;
+                todo.version += 1;
+                moved_out.push(todo.clone());
+            }
+        }
+        // As ON DELETE SET NULL does for other users' todos
+        store
+            .hidden_mut()
+            .filter(|todo| todo.list_id == Some(id))
+            .for_each(|todo| todo.list_id = None);
+        Ok(Some(moved_out))
+    }
+
+    async fn create_import_job(
+        &self,
+        job: &ImportJob,
+        items: &[ImportItem],
+    ) -> Result<ImportJob, TodoError> {
+        let mut store = self.store.write().await;
+        store.imports.push((job.clone(), items.to_vec()));
+        Ok(job.clone())
+    }
+
+    async fn get_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .imports
+            .iter()
+            .find(|(job, _)| job.id == id)
+            .map(|(job, _)| job.clone()))
+    }
+
+    async fn import_job_items(&self, id: Uuid) -> Result<Vec<ImportItem>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .imports
+            .iter()
+            .find(|(job, _)| job.id == id)
+            .map(|(_, items)| items.clone())
+            .unwrap_or_default())
+    }
+
+    async fn update_import_job(&self, job: &ImportJob) -> Result<Option<ImportJob>, TodoError> {
+        let mut store = self.store.write().await;
+        let Some((stored, items)) = store
+            .imports
+            .iter_mut()
+            .find(|(stored, _)| stored.id == job.id)
+        else {
+            return Ok(None);
+        };
+        if stored.status != ImportStatus::Cancelled {
+            stored.status = job.status;
+        }
+        stored.processed = job.processed;
+        stored.created = job.created;
+        stored.errors = job.errors.clone();
+        stored.updated_at = Utc::now();
+        if stored.status.is_finished() {
+            stored.finished_at.get_or_insert(stored.updated_at);
+            items.clear();
+        }
+        Ok(Some(stored.clone()))
+    }
+
+    async fn cancel_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        let mut store = self.store.write().await;
+        let Some((job, items)) = store.imports.iter_mut().find(|(job, _)| job.id == id) else {
+            return Ok(None);
+        };
+        if !job.status.is_finished() {
+            job.status = ImportStatus::Cancelled;
+            job.updated_at = Utc::now();
+            job.finished_at = Some(job.updated_at);
+            items.clear();
+        }
+        Ok(Some(job.clone()))
+    }
+
+    async fn create_user(&self, user: &User) -> Result<Option<User>, TodoError> {
+        let mut store = self.store.write().await;
+        if store
+            .users
+            .iter()
+            .any(|existing| existing.email == user.email)
+        {
+            return Ok(None);
+        }
+        store.users.push(user.clone());
+        Ok(Some(user.clone()))
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store.users.iter().find(|user| user.email == email).cloned())
+    }
+
+    async fn aging_report(&self) -> Result<AgingReport, TodoError> {
+        let mut counts = [0; 4];
+        let now = Utc::now();
+        let store = self.visible().await;
+        for todo in store.todos.iter().filter(|todo| !todo.completed) {
+            counts[stats::age_bucket(now - todo.created_at)] += 1;
+        }
+        Ok(AgingReport::from_counts(counts))
+    }
+
+    async fn workload_totals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<WorkloadTotals, TodoError> {
+        let mut totals = WorkloadTotals::default();
+        let store = self.visible().await;
+        for todo in store
+            .todos
+            .iter()
+            .filter(|todo| todo.created_at >= from && todo.created_at < to)
+        {
+            totals.todos += 1;
+            match todo.estimate_minutes {
+                Some(estimate) => {
+                    totals.estimate_minutes += i64::from(estimate);
+                    if !todo.completed {
+                        totals.remaining_minutes += i64::from(estimate);
+                    }
+                }
+                None => totals.unestimated += 1,
+            }
+        }
+        Ok(totals)
+    }
+
+    async fn completion_days(&self, user: Uuid) -> Result<Vec<CompletionDay>, TodoError> {
+        let store = self.store.read().await;
+        Ok(store
+            .completions
+            .range((user, NaiveDate::MIN)..=(user, NaiveDate::MAX))
+            .map(|(&(_, day), &completions)| CompletionDay { day, completions })
+            .collect())
+    }
+
+    async fn set_plan(
+        &self,
+        user: Uuid,
+        day: NaiveDate,
+        todo_ids: &[TodoId],
+    ) -> Result<(), TodoError> {
+        self.store
+            .write()
+            .await
+            .plans
+            .insert((user, day), todo_ids.to_vec());
+        Ok(())
+    }
+
+    async fn plan_todos(&self, user: Uuid, day: NaiveDate) -> Result<Vec<Todo>, TodoError> {
+        let store = self.visible().await;
+        let planned = store
+            .plans
+            .get(&(user, day))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        Ok(planned
+            .iter()
+            .filter_map(|id| store.todos.iter().find(|todo| todo.id == *id).cloned())
+            .collect())
+    }
+
+    async fn relations_of(&self, todo_id: TodoId) -> Result<Vec<RelationSummary>, TodoError> {
+        let store = self.visible().await;
+        Ok(store
+            .relations
+            .iter()
+            .filter(|relation| relation.todo_id == todo_id || relation.related_id == todo_id)
+            .filter_map(|relation| {
+                let other = if relation.todo_id == todo_id {
+                    relation.related_id
+                } else {
+                    relation.todo_id
+                };
+                let other = store.todos.iter().find(|todo| todo.id == other)?;
+                Some(RelationSummary::new(
+                    relation,
+                    todo_id,
+                    other.title.clone(),
+                    other.completed,
+                ))
+            })
+            .collect())
+    }
+
+    async fn create_relation(
+        &self,
+        relation: &TodoRelation,
+    ) -> Result<Option<TodoRelation>, TodoError> {
+        let mut store = self.store.write().await;
+        let pair = |r: &TodoRelation| {
+            (
+                r.todo_id.min(r.related_id),
+                r.todo_id.max(r.related_id),
+                r.kind,
+            )
+        };
+        if store
+            .relations
+            .iter()
+            .any(|existing| pair(existing) == pair(relation))
+        {
+            return Ok(None);
+        }
+        store.relations.push(relation.clone());
+        Ok(Some(relation.clone()))
+    }
+
+    async fn delete_relation(&self, todo_id: TodoId, relation_id: Uuid) -> Result<bool, TodoError> {
+        let mut store = self.store.write().await;
+        let before = store.relations.len();
+        store.relations.retain(|relation| {
+            relation.id != relation_id
+                || (relation.todo_id != todo_id && relation.related_id != todo_id)
+        });
+        Ok(store.relations.len() < before)
+    }
+
+    async fn ping(&self) -> Result<(), TodoError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TodoTitle;
+    use crate::Priority;
+    use chrono::Duration;
+
+    fn todo(title: &str, created_at: DateTime<Utc>) -> Todo {
+        Todo {
+            id: TodoId::new(),
+            title: TodoTitle::new(title).unwrap(),
+            content: TodoContent::default(),
+            completed: false,
+            version: 1,
+            metadata: serde_json::json!({}),
+            labels: vec![],
+            tags: vec![],
+            estimate_minutes: None,
+            priority: Priority::default(),
+            list_id: None,
+            archived: false,
+            owner_id: Uuid::nil(),
+            stale: false,
+            relations: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lists_newest_first_and_pages() {
+        let repository = MemoryTodoRepository::new();
+        let now = Utc::now();
+        let older = repository
+            .create_todo(&todo("older", now - Duration::hours(1)))
+            .await
+            .unwrap();
+        let newer = repository.create_todo(&todo("newer", now)).await.unwrap();
+
+        let all = repository.get_all_todos().await.unwrap();
+        assert_eq!(all, vec![newer.clone(), older.clone()]);
+        let page = repository
+            .get_todos_after(
+                Some(newer.id),
+                10,
+                TodoSort::CreatedAt,
+                SortOrder::Desc,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(page, vec![older]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_keeps_short_ids_and_drops_share_links() {
+        let repository = MemoryTodoRepository::new();
+        let first = repository
+            .create_todo(&todo("first", Utc::now()))
+            .await
+            .unwrap();
+        let second = repository
+            .create_todo(&todo("second", Utc::now()))
+            .await
+            .unwrap();
+        let link = repository
+            .create_share_link(&ShareLink::new(first.id, None, None, None))
+            .await
+            .unwrap();
+
+        assert!(repository.delete_todo(first.id, None).await.unwrap());
+        assert_eq!(repository.get_share_link(&link.token).await.unwrap(), None);
+        assert_eq!(repository.resolve_short_id("1").await.unwrap(), None);
+        assert_eq!(
+            repository.get_short_id(second.id).await.unwrap().as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            repository.resolve_short_id("2").await.unwrap(),
+            Some(second.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_content_update_detects_conflicts() {
+        let repository = MemoryTodoRepository::new();
+        let created = repository
+            .create_todo(&todo("draft", Utc::now()))
+            .await
+            .unwrap();
+        let updated = repository
+            .update_todo_content(created.id, &TodoContent::new("v2").unwrap(), 1)
+            .await
+            .unwrap();
+        assert!(matches!(updated, ContentUpdate::Updated(ref todo) if todo.version == 2));
+        let stale = repository
+            .update_todo_content(created.id, &TodoContent::new("v2'").unwrap(), 1)
+            .await
+            .unwrap();
+        assert!(matches!(stale, ContentUpdate::Conflict(ref todo) if todo.content == "v2"));
+    }
+}
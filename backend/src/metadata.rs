@@ -0,0 +1,409 @@
+//! Custom fields: a JSON `metadata` object on every todo, validated against field definitions.
+//!
+//! Definitions live in `metadata_fields` and apply to all todos. Keys without a definition
+//! are rejected so a typo cannot silently create a new field. `GET /api/todos?meta.<key>=<value>`
+//! filters on the text form of a value, the same text Postgres' `->>` operator yields.
+
+use crate::errors::ErrorCode;
+use crate::{policy, unicode, ApiResponse, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Query parameters starting with this prefix filter on metadata
+pub const FILTER_PREFIX: &str = "meta.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    String,
+    Number,
+    Boolean,
+    Enum,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Enum => "enum",
+        }
+    }
+}
+
+impl TryFrom<String> for FieldType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "string" => Ok(FieldType::String),
+            "number" => Ok(FieldType::Number),
+            "boolean" => Ok(FieldType::Boolean),
+            "enum" => Ok(FieldType::Enum),
+            other => Err(format!("Unknown field type: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct MetadataField {
+    #[schema(example = "sprint")]
+    pub key: String,
+    #[sqlx(try_from = "String")]
+    pub field_type: FieldType,
+    #[schema(example = false)]
+    pub required: bool,
+    /// Allowed values when `field_type` is `enum`
+    #[schema(example = json!([]))]
+    pub enum_values: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "field_type": "enum",
+    "required": false,
+    "enum_values": ["low", "medium", "high"]
+}))]
+pub struct MetadataFieldRequest {
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub enum_values: Vec<String>,
+}
+
+impl MetadataField {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.key.is_empty() || self.key.len() > 64 {
+            return Err("Field key must be 1-64 characters".to_string());
+        }
+        if !self
+            .key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err("Field key may only contain letters, digits, '_' and '-'".to_string());
+        }
+        match self.field_type {
+            FieldType::Enum if self.enum_values.is_empty() => {
+                Err("Enum fields need at least one value".to_string())
+            }
+            FieldType::Enum => Ok(()),
+            _ if !self.enum_values.is_empty() => {
+                Err("Only enum fields can have enum values".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        match (self.field_type, value) {
+            (FieldType::String, Value::String(_)) => true,
+            (FieldType::Number, Value::Number(_)) => true,
+            (FieldType::Boolean, Value::Bool(_)) => true,
+            (FieldType::Enum, Value::String(s)) => self.enum_values.contains(s),
+            _ => false,
+        }
+    }
+}
+
+/// Checks a whole metadata object: known keys, matching types, required keys present
+pub fn validate_metadata(metadata: &Value, fields: &[MetadataField]) -> Result<(), String> {
+    let Value::Object(entries) = metadata else {
+        return Err("Metadata must be a JSON object".to_string());
+    };
+    for (key, value) in entries {
+        let Some(field) = fields.iter().find(|field| &field.key == key) else {
+            return Err(format!("Unknown metadata field: {}", key));
+        };
+        if !field.accepts(value) {
+            return Err(format!(
+                "Metadata field {} must be a valid {}",
+                key,
+                field.field_type.as_str()
+            ));
+        }
+    }
+    if let Some(missing) = fields
+        .iter()
+        .find(|field| field.required && !entries.contains_key(&field.key))
+    {
+        return Err(format!("Metadata field {} is required", missing.key));
+    }
+    Ok(())
+}
+
+/// Validates metadata against the current field definitions, as a handler would respond
+pub async fn check_metadata<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    metadata: &Value,
+) -> Result<(), TodoError> {
+    let fields = repository.list_metadata_fields().await.map_err(|e| {
+        tracing::error!("Failed to load metadata fields: {}", e);
+        e
+    })?;
+    validate_metadata(metadata, &fields).map_err(|e| {
+        tracing::warn!("Metadata validation failed: {}", e);
+        TodoError::Validation(e.to_string())
+    })
+}
+
+/// Text form of a metadata value, matching Postgres' `->>`
+pub fn value_as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub fn matches_filters(metadata: &Value, filters: &[(String, String)]) -> bool {
+    filters.iter().all(|(key, expected)| {
+        metadata
+            .get(key)
+            .is_some_and(|value| unicode::same_folded(&value_as_text(value), expected))
+    })
+}
+
+/// Collects `meta.<key>=<value>` pairs from query parameters, sorted by key
+pub fn filters_from_query(params: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut filters: Vec<_> = params
+        .iter()
+        .filter_map(|(name, value)| {
+            name.strip_prefix(FILTER_PREFIX)
+                .filter(|key| !key.is_empty())
+                .map(|key| (key.to_string(), value.clone()))
+        })
+        .collect();
+    filters.sort();
+    filters
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MetadataFieldResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<MetadataField>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<MetadataField>> for MetadataFieldResponse {
+    fn from(response: ApiResponse<MetadataField>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MetadataFieldListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<MetadataField>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<MetadataField>>> for MetadataFieldListResponse {
+    fn from(response: ApiResponse<Vec<MetadataField>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/metadata-fields",
+    responses(
+        (status = 200, description = "All custom field definitions", body = MetadataFieldListResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Metadata"
+)]
+pub async fn list_fields<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<MetadataFieldListResponse>, TodoError> {
+    match repository.list_metadata_fields().await {
+        Ok(fields) => Ok(Json(ApiResponse::success(fields).into())),
+        Err(e) => {
+            tracing::error!("Failed to list metadata fields: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/metadata-fields/{key}",
+    params(
+        ("key" = String, Path, description = "Metadata key")
+    ),
+    request_body = MetadataFieldRequest,
+    responses(
+        (status = 200, description = "Field definition created or replaced", body = MetadataFieldResponse),
+        (status = 400, description = "Invalid definition", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Metadata"
+)]
+pub async fn put_field<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(key): Path<String>,
+    Json(request): Json<MetadataFieldRequest>,
+) -> Result<Json<MetadataFieldResponse>, TodoError> {
+    let field = MetadataField {
+        key,
+        field_type: request.field_type,
+        required: request.required,
+        enum_values: request.enum_values,
+    };
+    if let Err(e) = field.validate() {
+        tracing::warn!("Validation failed for metadata field {}: {}", field.key, e);
+        return Err(TodoError::Validation(e));
+    }
+
+    match repository.upsert_metadata_field(&field).await {
+        Ok(saved) => {
+            tracing::info!("Saved metadata field: {}", saved.key);
+            Ok(Json(ApiResponse::success(saved).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save metadata field {}: {}", field.key, e);
+            Err(e)
+        }
+    }
+}
+
+/// Removing a definition also strips the key from every todo
+#[utoipa::path(
+    delete,
+    path = "/api/metadata-fields/{key}",
+    params(
+        ("key" = String, Path, description = "Metadata key")
+    ),
+    responses(
+        (status = 204, description = "Field definition deleted"),
+        (status = 404, description = "Field not found", body = ErrorResponse),
+        (status = 409, description = "The validation policy requires the field", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Metadata"
+)]
+pub async fn delete_field<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(key): Path<String>,
+) -> Result<StatusCode, TodoError> {
+    if policy::load(repository.as_ref())
+        .await?
+        .required_fields
+        .contains(&key)
+    {
+        tracing::warn!(
+            "Metadata field {} is required by the validation policy",
+            key
+        );
+        return Err(TodoError::Conflict(format!(
+            "Metadata field {} is required by the validation policy",
+            key
+        )));
+    }
+    match repository.delete_metadata_field(&key).await {
+        Ok(true) => {
+            tracing::info!("Deleted metadata field: {}", key);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(TodoError::NotFound(format!("Metadata field {}", key))),
+        Err(e) => {
+            tracing::error!("Failed to delete metadata field {}: {}", key, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields() -> Vec<MetadataField> {
+        vec![
+            MetadataField {
+                key: "sprint".to_string(),
+                field_type: FieldType::Number,
+                required: true,
+                enum_values: vec![],
+            },
+            MetadataField {
+                key: "size".to_string(),
+                field_type: FieldType::Enum,
+                required: false,
+                enum_values: vec!["s".to_string(), "m".to_string()],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_validate_metadata() {
+        let fields = fields();
+        assert!(validate_metadata(&json!({"sprint": 42, "size": "m"}), &fields).is_ok());
+        assert!(validate_metadata(&json!({"sprint": "42"}), &fields).is_err());
+        assert!(validate_metadata(&json!({"sprint": 1, "size": "xl"}), &fields).is_err());
+        assert!(validate_metadata(&json!({"sprint": 1, "owner": "me"}), &fields).is_err());
+        assert!(validate_metadata(&json!({"size": "s"}), &fields).is_err());
+        assert!(validate_metadata(&json!([1]), &fields).is_err());
+    }
+
+    #[test]
+    fn test_field_definition_validation() {
+        let mut field = fields().remove(1);
+        assert!(field.validate().is_ok());
+        field.enum_values.clear();
+        assert!(field.validate().is_err());
+        field.field_type = FieldType::String;
+        assert!(field.validate().is_ok());
+        field.key = "has space".to_string();
+        assert!(field.validate().is_err());
+    }
+
+    #[test]
+    fn test_filters() {
+        let params = HashMap::from([
+            ("meta.sprint".to_string(), "42".to_string()),
+            ("meta.done".to_string(), "true".to_string()),
+            ("q".to_string(), "ignored".to_string()),
+        ]);
+        let filters = filters_from_query(&params);
+        assert_eq!(
+            filters,
+            vec![
+                ("done".to_string(), "true".to_string()),
+                ("sprint".to_string(), "42".to_string())
+            ]
+        );
+        assert!(matches_filters(
+            &json!({"sprint": 42, "done": true}),
+            &filters
+        ));
+        assert!(!matches_filters(&json!({"sprint": 42}), &filters));
+    }
+}
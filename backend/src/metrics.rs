@@ -0,0 +1,774 @@
+//! Per-method repository metrics in the Prometheus text format.
+//!
+//! `InstrumentedTodoRepository` wraps a repository and records, for every trait method,
+//! how often it was called, how often it failed and how long it took. `GET /metrics`
+//! exposes the totals for Prometheus to scrape:
+//!
+//! - `md_todo_repository_calls_total{backend, method}`
+//! - `md_todo_repository_errors_total{backend, method}`
+//! - `md_todo_repository_call_duration_seconds{backend, method}` (histogram)
+//!
+//! The same registry counts uses of deprecated API features in
+//! `md_todo_deprecated_requests_total{deprecation}` (see `deprecation`).
+//!
+//! Error rates are `errors_total / calls_total`; only `Err` results count as errors, so
+//! a lookup that finds nothing does not.
+
+use crate::auth::User;
+use crate::autocomplete::{AutocompleteQuery, TitleSuggestion};
+use crate::domain::{TodoContent, TodoId};
+use crate::encryption::{EncryptedTodo, EncryptedUpdate};
+use crate::imports::{ImportItem, ImportJob};
+use crate::lists::{ListUpdate, TodoList, UpdateTodoListRequest};
+use crate::metadata::MetadataField;
+use crate::moderation::{Finding, ModerationFlag};
+use crate::policy::ValidationPolicy;
+use crate::relations::{RelationSummary, TodoRelation};
+use crate::search::{SearchHit, SearchQuery};
+use crate::share::{ShareAccess, ShareLink, ShareOutcome};
+use crate::split::TodoSplit;
+use crate::stats::{AgingReport, WorkloadTotals};
+use crate::streaks::CompletionDay;
+use crate::subtasks::SubtaskMerge;
+use crate::tags::{Tag, TagRename};
+use crate::webhooks::{Webhook, WebhookDelivery};
+use crate::{
+    ContentUpdate, SortOrder, Todo, TodoError, TodoFilter, TodoRepositoryTrait, TodoSort,
+    TodoUpsert, UpdateTodoRequest,
+};
+use async_trait::async_trait;
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone, Default)]
+struct MethodStats {
+    calls: u64,
+    errors: u64,
+    /// Non-cumulative counts per bucket; the extra last slot is `+Inf`
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    seconds: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct RepositoryMetrics {
+    methods: Mutex<BTreeMap<(&'static str, &'static str), MethodStats>>,
+    deprecated_uses: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl RepositoryMetrics {
+    /// Registry shared by the server's repositories and served at `/metrics`
+    pub fn global() -> Arc<Self> {
+        static GLOBAL: OnceLock<Arc<RepositoryMetrics>> = OnceLock::new();
+        GLOBAL.get_or_init(Default::default).clone()
+    }
+
+    pub fn record(&self, backend: &'static str, method: &'static str, elapsed: Duration, ok: bool) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        let mut methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = methods.entry((backend, method)).or_default();
+        stats.calls += 1;
+        if !ok {
+            stats.errors += 1;
+        }
+        stats.buckets[bucket] += 1;
+        stats.seconds += seconds;
+    }
+
+    pub fn record_deprecated_use(&self, deprecation: &'static str) {
+        let mut uses = self
+            .deprecated_uses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *uses.entry(deprecation).or_default() += 1;
+    }
+
+    /// Prometheus text exposition of everything recorded so far
+    pub fn render(&self) -> String {
+        let methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        let mut calls = String::from(
+            "# HELP md_todo_repository_calls_total Repository method calls.\n\
+             # TYPE md_todo_repository_calls_total counter\n",
+        );
+        let mut errors = String::from(
+            "# HELP md_todo_repository_errors_total Repository method calls that returned an error.\n\
+             # TYPE md_todo_repository_errors_total counter\n",
+        );
+        let mut durations = String::from(
+            "# HELP md_todo_repository_call_duration_seconds Repository method latency.\n\
+             # TYPE md_todo_repository_call_duration_seconds histogram\n",
+        );
+        for ((backend, method), stats) in methods.iter() {
+            let labels = format!("backend=\"{backend}\",method=\"{method}\"");
+            let _ = writeln!(
+                calls,
+                "md_todo_repository_calls_total{{{labels}}} {}",
+                stats.calls
+            );
+            let _ = writeln!(
+                errors,
+                "md_todo_repository_errors_total{{{labels}}} {}",
+                stats.errors
+            );
+            let mut cumulative = 0;
+            for (i, count) in stats.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = LATENCY_BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".to_string(), f64::to_string);
+                let _ = writeln!(
+                    durations,
+                    "md_todo_repository_call_duration_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                durations,
+                "md_todo_repository_call_duration_seconds_sum{{{labels}}} {}",
+                stats.seconds
+            );
+            let _ = writeln!(
+                durations,
+                "md_todo_repository_call_duration_seconds_count{{{labels}}} {}",
+                stats.calls
+            );
+        }
+        let mut deprecated = String::from(
+            "# HELP md_todo_deprecated_requests_total Requests that used a deprecated API feature.\n\
+             # TYPE md_todo_deprecated_requests_total counter\n",
+        );
+        let uses = self
+            .deprecated_uses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        for (deprecation, count) in uses.iter() {
+            let _ = writeln!(
+                deprecated,
+                "md_todo_deprecated_requests_total{{deprecation=\"{deprecation}\"}} {count}"
+            );
+        }
+        calls + &errors + &durations + &deprecated
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Repository metrics in the Prometheus text format", content_type = "text/plain", body = String)
+    ),
+    tag = "Health"
+)]
+pub async fn get_metrics(Extension(metrics): Extension<Arc<RepositoryMetrics>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+        .into_response()
+}
+
+/// Repository decorator that records calls, errors and latency per method
+pub struct InstrumentedTodoRepository<R> {
+    inner: R,
+    backend: &'static str,
+    metrics: Arc<RepositoryMetrics>,
+}
+
+impl<R: TodoRepositoryTrait> InstrumentedTodoRepository<R> {
+    /// `backend` labels the metrics, e.g. `postgres`
+    pub fn new(inner: R, backend: &'static str, metrics: Arc<RepositoryMetrics>) -> Self {
+        Self {
+            inner,
+            backend,
+            metrics,
+        }
+    }
+
+    async fn observe<T>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = Result<T, TodoError>>,
+    ) -> Result<T, TodoError> {
+        let started = Instant::now();
+        let result = call.await;
+        self.metrics
+            .record(self.backend, method, started.elapsed(), result.is_ok());
+        result
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepositoryTrait> TodoRepositoryTrait for InstrumentedTodoRepository<R> {
+    async fn create_todo(&self, todo: &Todo) -> Result<Todo, TodoError> {
+        self.observe("create_todo", self.inner.create_todo(todo))
+            .await
+    }
+
+    async fn create_todos_bulk(&self, todos: &[Todo]) -> Result<Vec<Todo>, TodoError> {
+        self.observe("create_todos_bulk", self.inner.create_todos_bulk(todos))
+            .await
+    }
+
+    async fn get_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        self.observe("get_all_todos", self.inner.get_all_todos())
+            .await
+    }
+
+    async fn find_todos(&self, filter: &TodoFilter) -> Result<Vec<Todo>, TodoError> {
+        self.observe("find_todos", self.inner.find_todos(filter))
+            .await
+    }
+
+    async fn search_todos(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, TodoError> {
+        self.observe("search_todos", self.inner.search_todos(query))
+            .await
+    }
+
+    async fn autocomplete_titles(
+        &self,
+        query: &AutocompleteQuery,
+    ) -> Result<Vec<TitleSuggestion>, TodoError> {
+        self.observe("autocomplete_titles", self.inner.autocomplete_titles(query))
+            .await
+    }
+
+    async fn get_todos_after(
+        &self,
+        cursor: Option<TodoId>,
+        limit: i64,
+        sort: TodoSort,
+        order: SortOrder,
+        include_archived: bool,
+    ) -> Result<Vec<Todo>, TodoError> {
+        self.observe(
+            "get_todos_after",
+            self.inner
+                .get_todos_after(cursor, limit, sort, order, include_archived),
+        )
+        .await
+    }
+
+    async fn get_todo_by_id(&self, id: TodoId) -> Result<Option<Todo>, TodoError> {
+        self.observe("get_todo_by_id", self.inner.get_todo_by_id(id))
+            .await
+    }
+
+    async fn update_todo(
+        &self,
+        id: TodoId,
+        updates: &UpdateTodoRequest,
+        expected_version: Option<i32>,
+    ) -> Result<Option<Todo>, TodoError> {
+        self.observe(
+            "update_todo",
+            self.inner.update_todo(id, updates, expected_version),
+        )
+        .await
+    }
+
+    async fn upsert_todo(
+        &self,
+        todo: &Todo,
+        expected_version: Option<i32>,
+    ) -> Result<TodoUpsert, TodoError> {
+        self.observe(
+            "upsert_todo",
+            self.inner.upsert_todo(todo, expected_version),
+        )
+        .await
+    }
+
+    async fn update_todos_bulk(
+        &self,
+        ids: &[TodoId],
+        updates: &UpdateTodoRequest,
+    ) -> Result<Vec<Todo>, TodoError> {
+        self.observe(
+            "update_todos_bulk",
+            self.inner.update_todos_bulk(ids, updates),
+        )
+        .await
+    }
+
+    async fn update_todo_content(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        base_version: i32,
+    ) -> Result<ContentUpdate, TodoError> {
+        self.observe(
+            "update_todo_content",
+            self.inner.update_todo_content(id, content, base_version),
+        )
+        .await
+    }
+
+    async fn split_todo(
+        &self,
+        id: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        parts: &[Todo],
+    ) -> Result<Option<TodoSplit>, TodoError> {
+        self.observe(
+            "split_todo",
+            self.inner.split_todo(id, content, expected_version, parts),
+        )
+        .await
+    }
+
+    async fn merge_subtasks(
+        &self,
+        parent: TodoId,
+        content: &TodoContent,
+        expected_version: i32,
+        subtasks: &[TodoId],
+    ) -> Result<Option<SubtaskMerge>, TodoError> {
+        self.observe(
+            "merge_subtasks",
+            self.inner
+                .merge_subtasks(parent, content, expected_version, subtasks),
+        )
+        .await
+    }
+
+    async fn delete_todo(
+        &self,
+        id: TodoId,
+        expected_version: Option<i32>,
+    ) -> Result<bool, TodoError> {
+        self.observe("delete_todo", self.inner.delete_todo(id, expected_version))
+            .await
+    }
+
+    async fn delete_todos_bulk(&self, ids: &[TodoId]) -> Result<Vec<TodoId>, TodoError> {
+        self.observe("delete_todos_bulk", self.inner.delete_todos_bulk(ids))
+            .await
+    }
+
+    async fn complete_all_todos(&self) -> Result<Vec<Todo>, TodoError> {
+        self.observe("complete_all_todos", self.inner.complete_all_todos())
+            .await
+    }
+
+    async fn delete_completed_todos(&self) -> Result<Vec<TodoId>, TodoError> {
+        self.observe(
+            "delete_completed_todos",
+            self.inner.delete_completed_todos(),
+        )
+        .await
+    }
+
+    async fn get_short_id(&self, todo_id: TodoId) -> Result<Option<String>, TodoError> {
+        self.observe("get_short_id", self.inner.get_short_id(todo_id))
+            .await
+    }
+
+    async fn resolve_short_id(&self, short_id: &str) -> Result<Option<TodoId>, TodoError> {
+        self.observe("resolve_short_id", self.inner.resolve_short_id(short_id))
+            .await
+    }
+
+    async fn create_share_link(&self, link: &ShareLink) -> Result<ShareLink, TodoError> {
+        self.observe("create_share_link", self.inner.create_share_link(link))
+            .await
+    }
+
+    async fn list_share_links(&self, todo_id: TodoId) -> Result<Vec<ShareLink>, TodoError> {
+        self.observe("list_share_links", self.inner.list_share_links(todo_id))
+            .await
+    }
+
+    async fn get_share_link(&self, token: &str) -> Result<Option<ShareLink>, TodoError> {
+        self.observe("get_share_link", self.inner.get_share_link(token))
+            .await
+    }
+
+    async fn delete_share_link(&self, token: &str) -> Result<bool, TodoError> {
+        self.observe("delete_share_link", self.inner.delete_share_link(token))
+            .await
+    }
+
+    async fn record_share_access(
+        &self,
+        token: &str,
+        access: &ShareAccess,
+    ) -> Result<ShareOutcome, TodoError> {
+        self.observe(
+            "record_share_access",
+            self.inner.record_share_access(token, access),
+        )
+        .await
+    }
+
+    async fn share_access_log(
+        &self,
+        token: &str,
+        limit: i64,
+    ) -> Result<Vec<ShareAccess>, TodoError> {
+        self.observe(
+            "share_access_log",
+            self.inner.share_access_log(token, limit),
+        )
+        .await
+    }
+
+    async fn list_metadata_fields(&self) -> Result<Vec<MetadataField>, TodoError> {
+        self.observe("list_metadata_fields", self.inner.list_metadata_fields())
+            .await
+    }
+
+    async fn upsert_metadata_field(
+        &self,
+        field: &MetadataField,
+    ) -> Result<MetadataField, TodoError> {
+        self.observe(
+            "upsert_metadata_field",
+            self.inner.upsert_metadata_field(field),
+        )
+        .await
+    }
+
+    async fn delete_metadata_field(&self, key: &str) -> Result<bool, TodoError> {
+        self.observe(
+            "delete_metadata_field",
+            self.inner.delete_metadata_field(key),
+        )
+        .await
+    }
+
+    async fn validation_policy(&self) -> Result<ValidationPolicy, TodoError> {
+        self.observe("validation_policy", self.inner.validation_policy())
+            .await
+    }
+
+    async fn set_validation_policy(
+        &self,
+        policy: &ValidationPolicy,
+    ) -> Result<ValidationPolicy, TodoError> {
+        self.observe(
+            "set_validation_policy",
+            self.inner.set_validation_policy(policy),
+        )
+        .await
+    }
+
+    async fn flag_todo(&self, id: TodoId, findings: &[Finding]) -> Result<(), TodoError> {
+        self.observe("flag_todo", self.inner.flag_todo(id, findings))
+            .await
+    }
+
+    async fn moderation_flags(&self) -> Result<Vec<ModerationFlag>, TodoError> {
+        self.observe("moderation_flags", self.inner.moderation_flags())
+            .await
+    }
+
+    async fn dismiss_moderation_flag(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.observe(
+            "dismiss_moderation_flag",
+            self.inner.dismiss_moderation_flag(id),
+        )
+        .await
+    }
+
+    async fn create_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+    ) -> Result<EncryptedTodo, TodoError> {
+        self.observe(
+            "create_encrypted_todo",
+            self.inner.create_encrypted_todo(todo),
+        )
+        .await
+    }
+
+    async fn list_encrypted_todos(
+        &self,
+        key_fingerprint: Option<&str>,
+    ) -> Result<Vec<EncryptedTodo>, TodoError> {
+        self.observe(
+            "list_encrypted_todos",
+            self.inner.list_encrypted_todos(key_fingerprint),
+        )
+        .await
+    }
+
+    async fn get_encrypted_todo(&self, id: Uuid) -> Result<Option<EncryptedTodo>, TodoError> {
+        self.observe("get_encrypted_todo", self.inner.get_encrypted_todo(id))
+            .await
+    }
+
+    async fn update_encrypted_todo(
+        &self,
+        todo: &EncryptedTodo,
+        base_version: i32,
+    ) -> Result<EncryptedUpdate, TodoError> {
+        self.observe(
+            "update_encrypted_todo",
+            self.inner.update_encrypted_todo(todo, base_version),
+        )
+        .await
+    }
+
+    async fn delete_encrypted_todo(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.observe(
+            "delete_encrypted_todo",
+            self.inner.delete_encrypted_todo(id),
+        )
+        .await
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, TodoError> {
+        self.observe("create_webhook", self.inner.create_webhook(webhook))
+            .await
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, TodoError> {
+        self.observe("list_webhooks", self.inner.list_webhooks())
+            .await
+    }
+
+    async fn get_webhook(&self, id: Uuid) -> Result<Option<Webhook>, TodoError> {
+        self.observe("get_webhook", self.inner.get_webhook(id))
+            .await
+    }
+
+    async fn update_webhook(&self, webhook: &Webhook) -> Result<Option<Webhook>, TodoError> {
+        self.observe("update_webhook", self.inner.update_webhook(webhook))
+            .await
+    }
+
+    async fn delete_webhook(&self, id: Uuid) -> Result<bool, TodoError> {
+        self.observe("delete_webhook", self.inner.delete_webhook(id))
+            .await
+    }
+
+    async fn record_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), TodoError> {
+        self.observe(
+            "record_webhook_delivery",
+            self.inner.record_webhook_delivery(delivery),
+        )
+        .await
+    }
+
+    async fn webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, TodoError> {
+        self.observe(
+            "webhook_deliveries",
+            self.inner.webhook_deliveries(webhook_id, limit),
+        )
+        .await
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, TodoError> {
+        self.observe("list_tags", self.inner.list_tags()).await
+    }
+
+    async fn get_tag(&self, id: Uuid) -> Result<Option<Tag>, TodoError> {
+        self.observe("get_tag", self.inner.get_tag(id)).await
+    }
+
+    async fn create_tag(&self, tag: &Tag) -> Result<Option<Tag>, TodoError> {
+        self.observe("create_tag", self.inner.create_tag(tag)).await
+    }
+
+    async fn suggest_tags(&self, text: &str, limit: i64) -> Result<Vec<Tag>, TodoError> {
+        self.observe("suggest_tags", self.inner.suggest_tags(text, limit))
+            .await
+    }
+
+    async fn rename_tag(&self, id: Uuid, name: &str, merge: bool) -> Result<TagRename, TodoError> {
+        self.observe("rename_tag", self.inner.rename_tag(id, name, merge))
+            .await
+    }
+
+    async fn delete_tag(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        self.observe("delete_tag", self.inner.delete_tag(id)).await
+    }
+
+    async fn aging_report(&self) -> Result<AgingReport, TodoError> {
+        self.observe("aging_report", self.inner.aging_report())
+            .await
+    }
+
+    async fn workload_totals(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<WorkloadTotals, TodoError> {
+        self.observe("workload_totals", self.inner.workload_totals(from, to))
+            .await
+    }
+
+    async fn completion_days(&self, user: Uuid) -> Result<Vec<CompletionDay>, TodoError> {
+        self.observe("completion_days", self.inner.completion_days(user))
+            .await
+    }
+
+    async fn set_plan(
+        &self,
+        user: Uuid,
+        day: NaiveDate,
+        todo_ids: &[TodoId],
+    ) -> Result<(), TodoError> {
+        self.observe("set_plan", self.inner.set_plan(user, day, todo_ids))
+            .await
+    }
+
+    async fn plan_todos(&self, user: Uuid, day: NaiveDate) -> Result<Vec<Todo>, TodoError> {
+        self.observe("plan_todos", self.inner.plan_todos(user, day))
+            .await
+    }
+
+    async fn relations_of(&self, todo_id: TodoId) -> Result<Vec<RelationSummary>, TodoError> {
+        self.observe("relations_of", self.inner.relations_of(todo_id))
+            .await
+    }
+
+    async fn create_relation(
+        &self,
+        relation: &TodoRelation,
+    ) -> Result<Option<TodoRelation>, TodoError> {
+        self.observe("create_relation", self.inner.create_relation(relation))
+            .await
+    }
+
+    async fn delete_relation(&self, todo_id: TodoId, relation_id: Uuid) -> Result<bool, TodoError> {
+        self.observe(
+            "delete_relation",
+            self.inner.delete_relation(todo_id, relation_id),
+        )
+        .await
+    }
+
+    async fn list_lists(&self) -> Result<Vec<TodoList>, TodoError> {
+        self.observe("list_lists", self.inner.list_lists()).await
+    }
+
+    async fn get_list(&self, id: Uuid) -> Result<Option<TodoList>, TodoError> {
+        self.observe("get_list", self.inner.get_list(id)).await
+    }
+
+    async fn create_list(&self, list: &TodoList) -> Result<Option<TodoList>, TodoError> {
+        self.observe("create_list", self.inner.create_list(list))
+            .await
+    }
+
+    async fn update_list(
+        &self,
+        id: Uuid,
+        update: &UpdateTodoListRequest,
+    ) -> Result<ListUpdate, TodoError> {
+        self.observe("update_list", self.inner.update_list(id, update))
+            .await
+    }
+
+    async fn delete_list(&self, id: Uuid) -> Result<Option<Vec<Todo>>, TodoError> {
+        self.observe("delete_list", self.inner.delete_list(id))
+            .await
+    }
+
+    async fn create_import_job(
+        &self,
+        job: &ImportJob,
+        items: &[ImportItem],
+    ) -> Result<ImportJob, TodoError> {
+        self.observe(
+            "create_import_job",
+            self.inner.create_import_job(job, items),
+        )
+        .await
+    }
+
+    async fn get_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        self.observe("get_import_job", self.inner.get_import_job(id))
+            .await
+    }
+
+    async fn import_job_items(&self, id: Uuid) -> Result<Vec<ImportItem>, TodoError> {
+        self.observe("import_job_items", self.inner.import_job_items(id))
+            .await
+    }
+
+    async fn update_import_job(&self, job: &ImportJob) -> Result<Option<ImportJob>, TodoError> {
+        self.observe("update_import_job", self.inner.update_import_job(job))
+            .await
+    }
+
+    async fn cancel_import_job(&self, id: Uuid) -> Result<Option<ImportJob>, TodoError> {
+        self.observe("cancel_import_job", self.inner.cancel_import_job(id))
+            .await
+    }
+
+    async fn create_user(&self, user: &User) -> Result<Option<User>, TodoError> {
+        self.observe("create_user", self.inner.create_user(user))
+            .await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, TodoError> {
+        self.observe("find_user_by_email", self.inner.find_user_by_email(email))
+            .await
+    }
+
+    async fn ping(&self) -> Result<(), TodoError> {
+        self.observe("ping", self.inner.ping()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counts_errors_and_buckets() {
+        let metrics = RepositoryMetrics::default();
+        metrics.record("postgres", "get_todo_by_id", Duration::from_millis(3), true);
+        metrics.record("postgres", "get_todo_by_id", Duration::from_secs(20), false);
+        let text = metrics.render();
+        let labels = "backend=\"postgres\",method=\"get_todo_by_id\"";
+        assert!(text.contains(&format!("md_todo_repository_calls_total{{{labels}}} 2\n")));
+        assert!(text.contains(&format!("md_todo_repository_errors_total{{{labels}}} 1\n")));
+        assert!(text.contains(&format!(
+            "md_todo_repository_call_duration_seconds_bucket{{{labels},le=\"0.001\"}} 0\n"
+        )));
+        assert!(text.contains(&format!(
+            "md_todo_repository_call_duration_seconds_bucket{{{labels},le=\"0.005\"}} 1\n"
+        )));
+        assert!(text.contains(&format!(
+            "md_todo_repository_call_duration_seconds_bucket{{{labels},le=\"10\"}} 1\n"
+        )));
+        assert!(text.contains(&format!(
+            "md_todo_repository_call_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 2\n"
+        )));
+        assert!(text.contains(&format!(
+            "md_todo_repository_call_duration_seconds_count{{{labels}}} 2\n"
+        )));
+    }
+
+    #[test]
+    fn test_render_without_calls_has_only_headers() {
+        let text = RepositoryMetrics::default().render();
+        assert_eq!(text.lines().count(), 8);
+        assert!(text.contains("# TYPE md_todo_repository_call_duration_seconds histogram"));
+    }
+}
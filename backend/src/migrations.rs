@@ -0,0 +1,444 @@
+//! Schema migrations from `database/migrations`, applied by the backend with guardrails for
+//! deploys that keep old and new servers running side by side.
+//!
+//! Every file says in a `-- migrate:` comment at its top whether it is `online`, safe to
+//! apply under traffic (new tables, nullable columns, constant defaults, `CREATE INDEX
+//! CONCURRENTLY`), or `locking`, holding a lock on a busy table for as long as it rewrites,
+//! scans or indexes it. Files without the comment count as locking. `no-transaction` runs a
+//! file outside a transaction, as `CREATE INDEX CONCURRENTLY` needs; such a file must hold a
+//! single statement.
+//!
+//! Pending migrations are applied in order, stopping before the first locking one unless
+//! locking ones are allowed. Applied versions are recorded in `schema_migrations`, which
+//! `database/init.sql` fills for the migrations it runs.
+
+use crate::DatabasePool;
+use serde::{Deserialize, Serialize};
+use sqlx::Connection;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+pub type MigrationError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Held while applying so servers starting together do not run the same migration twice
+const ADVISORY_LOCK_KEY: i64 = 0x6d64_746f_646f;
+/// How long a migration waits for a table lock before failing, rather than queueing every
+/// query behind it
+const LOCK_TIMEOUT: &str = "5s";
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationKind {
+    Online,
+    Locking,
+}
+
+impl fmt::Display for MigrationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Online => "online",
+            Self::Locking => "locking",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    pub kind: MigrationKind,
+    pub transactional: bool,
+    pub sql: String,
+}
+
+impl Migration {
+    /// `file_name` is `<version>_<name>.sql`, e.g. `015_todo_lists.sql`
+    pub fn parse(file_name: &str, sql: String) -> Result<Self, String> {
+        let stem = file_name
+            .strip_suffix(".sql")
+            .ok_or_else(|| format!("{file_name}: not an .sql file"))?;
+        let (version, name) = stem
+            .split_once('_')
+            .ok_or_else(|| format!("{file_name}: expected <version>_<name>.sql"))?;
+        let version = version
+            .parse()
+            .map_err(|_| format!("{file_name}: {version:?} is not a version number"))?;
+
+        let mut kind = None;
+        let mut transactional = true;
+        // Only the comment block before the first statement is read
+        let header = sql
+            .lines()
+            .map(str::trim)
+            .take_while(|line| line.is_empty() || line.starts_with("--"));
+        for line in header {
+            let Some(annotation) = line.trim_start_matches('-').trim().strip_prefix("migrate:")
+            else {
+                continue;
+            };
+            for word in annotation.split([',', ' ']).filter(|word| !word.is_empty()) {
+                let word_kind = match word {
+                    "online" => MigrationKind::Online,
+                    "locking" => MigrationKind::Locking,
+                    "no-transaction" => {
+                        transactional = false;
+                        continue;
+                    }
+                    other => return Err(format!("{file_name}: unknown annotation {other:?}")),
+                };
+                if kind.is_some_and(|kind| kind != word_kind) {
+                    return Err(format!("{file_name}: both online and locking"));
+                }
+                kind = Some(word_kind);
+            }
+        }
+        Ok(Self {
+            version,
+            name: name.to_string(),
+            kind: kind.unwrap_or(MigrationKind::Locking),
+            transactional,
+            sql,
+        })
+    }
+}
+
+/// The `.sql` files in `dir`, by version
+pub fn load_dir(dir: &Path) -> Result<Vec<Migration>, MigrationError> {
+    let mut migrations = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .map_err(|e| format!("Cannot read migrations from {}: {}", dir.display(), e))?
+    {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !file_name.ends_with(".sql") {
+            continue;
+        }
+        migrations.push(Migration::parse(
+            file_name,
+            std::fs::read_to_string(&path)?,
+        )?);
+    }
+    migrations.sort_by_key(|migration| migration.version);
+    if let Some(pair) = migrations
+        .windows(2)
+        .find(|pair| pair[0].version == pair[1].version)
+    {
+        return Err(format!(
+            "Migrations {}_{} and {}_{} share a version",
+            pair[0].version, pair[0].name, pair[1].version, pair[1].name
+        )
+        .into());
+    }
+    Ok(migrations)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PendingMigration {
+    #[schema(example = 16)]
+    pub version: i64,
+    #[schema(example = "todo_due_dates")]
+    pub name: String,
+    pub kind: MigrationKind,
+}
+
+impl From<&Migration> for PendingMigration {
+    fn from(migration: &Migration) -> Self {
+        Self {
+            version: migration.version,
+            name: migration.name.clone(),
+            kind: migration.kind,
+        }
+    }
+}
+
+impl fmt::Display for PendingMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:03}_{} ({})", self.version, self.name, self.kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MigrationStatus {
+    /// Highest applied version, `None` on a database without migration history
+    #[schema(example = 15)]
+    pub applied: Option<i64>,
+    /// Migrations in the directory not applied yet, in the order they will run
+    pub pending: Vec<PendingMigration>,
+}
+
+impl MigrationStatus {
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Outcome of `Migrator::run`
+#[derive(Debug, Default)]
+pub struct MigrationRun {
+    pub applied: Vec<PendingMigration>,
+    /// The locking migration the run stopped before
+    pub blocked_by: Option<PendingMigration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Migrator {
+    dir: PathBuf,
+    migrations: Arc<Vec<Migration>>,
+    pool: DatabasePool,
+}
+
+impl Migrator {
+    pub fn new(dir: PathBuf, migrations: Vec<Migration>, pool: DatabasePool) -> Self {
+        Self {
+            dir,
+            migrations: Arc::new(migrations),
+            pool,
+        }
+    }
+
+    /// `None` unless `MIGRATIONS_DIR` is set
+    pub fn from_env(pool: DatabasePool) -> Option<Result<Self, MigrationError>> {
+        let dir = std::env::var("MIGRATIONS_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())?;
+        let dir = PathBuf::from(dir);
+        Some(load_dir(&dir).map(|migrations| Self::new(dir, migrations, pool)))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Read-only, so readiness probes can call it
+    pub async fn status(&self) -> Result<MigrationStatus, sqlx::Error> {
+        let exists: bool =
+            sqlx::query_scalar("SELECT to_regclass('schema_migrations') IS NOT NULL")
+                .fetch_one(&self.pool)
+                .await?;
+        let applied: Vec<i64> = if exists {
+            sqlx::query_scalar("SELECT version FROM schema_migrations")
+                .fetch_all(&self.pool)
+                .await?
+        } else {
+            Vec::new()
+        };
+        let applied_set: HashSet<i64> = applied.iter().copied().collect();
+        Ok(MigrationStatus {
+            applied: applied.into_iter().max(),
+            pending: self
+                .migrations
+                .iter()
+                .filter(|migration| !applied_set.contains(&migration.version))
+                .map(PendingMigration::from)
+                .collect(),
+        })
+    }
+
+    /// Applies pending migrations in order, stopping before the first locking one unless
+    /// `allow_locking`
+    pub async fn run(&self, allow_locking: bool) -> Result<MigrationRun, MigrationError> {
+        let mut connection = self.pool.acquire().await?;
+        sqlx::query("SELECT pg_advisory_lock($1)")
+            .bind(ADVISORY_LOCK_KEY)
+            .execute(&mut *connection)
+            .await?;
+        let result = self.run_locked(&mut connection, allow_locking).await;
+        // Session settings and locks would otherwise stay with the pooled connection
+        sqlx::query("SELECT pg_advisory_unlock($1)")
+            .bind(ADVISORY_LOCK_KEY)
+            .execute(&mut *connection)
+            .await?;
+        sqlx::query("RESET lock_timeout")
+            .execute(&mut *connection)
+            .await?;
+        result
+    }
+
+    async fn run_locked(
+        &self,
+        connection: &mut sqlx::PgConnection,
+        allow_locking: bool,
+    ) -> Result<MigrationRun, MigrationError> {
+        self.check_history(connection).await?;
+        sqlx::query(CREATE_TABLE).execute(&mut *connection).await?;
+        sqlx::query(&format!("SET lock_timeout = '{LOCK_TIMEOUT}'"))
+            .execute(&mut *connection)
+            .await?;
+        let applied: HashSet<i64> = sqlx::query_scalar("SELECT version FROM schema_migrations")
+            .fetch_all(&mut *connection)
+            .await?
+            .into_iter()
+            .collect();
+
+        let mut run = MigrationRun::default();
+        for migration in self
+            .migrations
+            .iter()
+            .filter(|migration| !applied.contains(&migration.version))
+        {
+            if migration.kind == MigrationKind::Locking && !allow_locking {
+                run.blocked_by = Some(migration.into());
+                break;
+            }
+            tracing::info!("Applying migration {}", PendingMigration::from(migration));
+            apply(connection, migration).await.map_err(|e| {
+                format!(
+                    "Migration {:03}_{} failed: {}",
+                    migration.version, migration.name, e
+                )
+            })?;
+            run.applied.push(migration.into());
+        }
+        Ok(run)
+    }
+
+    /// A database set up by hand or by an older `init.sql` has tables but no history, and
+    /// running every migration on it would, among others, insert the sample data again
+    async fn check_history(
+        &self,
+        connection: &mut sqlx::PgConnection,
+    ) -> Result<(), MigrationError> {
+        let (history, todos): (bool, bool) = sqlx::query_as(
+            "SELECT to_regclass('schema_migrations') IS NOT NULL, to_regclass('todos') IS NOT NULL",
+        )
+        .fetch_one(&mut *connection)
+        .await?;
+        if todos && !history {
+            return Err(
+                "The database has tables but no migration history; record the migrations it \
+                 already has with `md-todo-backend migrate --baseline <version>`"
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Records the migrations up to `version` as applied without running them; returns how
+    /// many were not recorded yet
+    pub async fn baseline(&self, version: i64) -> Result<u64, MigrationError> {
+        if !self.migrations.iter().any(|m| m.version == version) {
+            return Err(format!("No migration has version {version}").into());
+        }
+        let mut connection = self.pool.acquire().await?;
+        let mut transaction = connection.begin().await?;
+        sqlx::query(CREATE_TABLE).execute(&mut *transaction).await?;
+        let mut recorded = 0;
+        for migration in self.migrations.iter().filter(|m| m.version <= version) {
+            recorded += record(&mut transaction, migration).await?;
+        }
+        transaction.commit().await?;
+        Ok(recorded)
+    }
+}
+
+async fn record(
+    connection: &mut sqlx::PgConnection,
+    migration: &Migration,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)
+         ON CONFLICT (version) DO NOTHING",
+    )
+    .bind(migration.version)
+    .bind(&migration.name)
+    .execute(connection)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+async fn apply(
+    connection: &mut sqlx::PgConnection,
+    migration: &Migration,
+) -> Result<(), sqlx::Error> {
+    if !migration.transactional {
+        sqlx::raw_sql(&migration.sql)
+            .execute(&mut *connection)
+            .await?;
+        record(connection, migration).await?;
+        return Ok(());
+    }
+    let mut transaction = connection.begin().await?;
+    sqlx::raw_sql(&migration.sql)
+        .execute(&mut *transaction)
+        .await?;
+    record(&mut transaction, migration).await?;
+    transaction.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_annotations() {
+        let migration = Migration::parse(
+            "016_due_dates.sql",
+            "-- Migration 016: Due dates\n-- migrate: online\n\nALTER TABLE todos ADD COLUMN due DATE;\n"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(migration.version, 16);
+        assert_eq!(migration.name, "due_dates");
+        assert_eq!(migration.kind, MigrationKind::Online);
+        assert!(migration.transactional);
+
+        let migration = Migration::parse(
+            "017_due_index.sql",
+            "-- migrate: online, no-transaction\nCREATE INDEX CONCURRENTLY idx ON todos(due);\n"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(migration.kind, MigrationKind::Online);
+        assert!(!migration.transactional);
+
+        // Unannotated files, and annotations after the first statement, count as locking
+        let migration = Migration::parse(
+            "018_backfill.sql",
+            "UPDATE todos SET due = NULL;\n-- migrate: online\n".to_string(),
+        )
+        .unwrap();
+        assert_eq!(migration.kind, MigrationKind::Locking);
+
+        for (file_name, sql) in [
+            ("019_x.txt", "-- migrate: online\n"),
+            ("x_y.sql", "-- migrate: online\n"),
+            ("020.sql", "-- migrate: online\n"),
+            ("021_x.sql", "-- migrate: online, locking\n"),
+            ("022_x.sql", "-- migrate: quick\n"),
+        ] {
+            assert!(
+                Migration::parse(file_name, sql.to_string()).is_err(),
+                "{file_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_repository_migrations_are_annotated() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../database/migrations");
+        let migrations = load_dir(&dir).unwrap();
+        assert!(!migrations.is_empty());
+        for migration in &migrations {
+            assert!(
+                migration
+                    .sql
+                    .lines()
+                    .any(|line| line.starts_with("-- migrate:")),
+                "{:03}_{} has no -- migrate: annotation",
+                migration.version,
+                migration.name
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Todo events shared between replicas over Postgres `LISTEN`/`NOTIFY`.
+//!
+//! The event bus only reaches subscribers in the process that made the change, so behind a
+//! load balancer a client of `/api/todos/events` would miss every write handled by another
+//! replica. `PgNotifier` is subscribed to the bus next to the feed and sends each event to
+//! `CHANNEL` with `pg_notify`, tagged with the sending process; `spawn_listener` listens on
+//! that channel and hands the events of other processes to the local subscribers. Those
+//! are only the ones that fan out to clients: the broker and webhooks already got the event
+//! from the replica that made the change.
+//!
+//! A notification carries at most 8000 bytes, so one whose todo is too large for it carries
+//! only its kind and id, and the listener reads the todo back. Notifications sent while the
+//! listener is reconnecting are lost; it logs how long it was away.
+
+use crate::domain::TodoId;
+use crate::events::{EventPublisher, PublishError, TodoEvent};
+use crate::{DatabasePool, TodoRepositoryTrait};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub const CHANNEL: &str = "md_todo_events";
+/// Postgres refuses longer payloads
+const MAX_PAYLOAD_BYTES: usize = 7999;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Payload of a notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Notice {
+    /// The process that made the change
+    origin: Uuid,
+    kind: String,
+    id: TodoId,
+    /// Left out when it does not fit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event: Option<TodoEvent>,
+}
+
+impl Notice {
+    fn encode(origin: Uuid, event: &TodoEvent) -> Result<String, serde_json::Error> {
+        let mut notice = Notice {
+            origin,
+            kind: event.kind().to_string(),
+            id: event.todo_id(),
+            event: Some(event.clone()),
+        };
+        let payload = serde_json::to_string(&notice)?;
+        if payload.len() <= MAX_PAYLOAD_BYTES {
+            return Ok(payload);
+        }
+        notice.event = None;
+        serde_json::to_string(&notice)
+    }
+}
+
+/// Sends every event of this process to `CHANNEL`
+pub struct PgNotifier {
+    pool: DatabasePool,
+    origin: Uuid,
+}
+
+impl PgNotifier {
+    /// `origin` identifies this process; the listener gets the same one
+    pub fn new(pool: DatabasePool, origin: Uuid) -> Self {
+        Self { pool, origin }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for PgNotifier {
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        let payload = Notice::encode(self.origin, event)?;
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(CHANNEL)
+            .bind(payload)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// The event a notification stands for; `None` when it came from this process, or when
+/// the todo it had no room for is gone already
+async fn received<R: TodoRepositoryTrait>(
+    payload: &str,
+    origin: Uuid,
+    repository: &R,
+) -> Result<Option<TodoEvent>, PublishError> {
+    let notice: Notice = serde_json::from_str(payload)?;
+    if notice.origin == origin {
+        return Ok(None);
+    }
+    if let Some(event) = notice.event {
+        return Ok(Some(event));
+    }
+    if notice.kind == "deleted" {
+        return Ok(Some(TodoEvent::Deleted { id: notice.id }));
+    }
+    let Some(todo) = repository.get_todo_by_id(notice.id).await? else {
+        return Ok(None);
+    };
+    Ok(match notice.kind.as_str() {
+        "created" => Some(TodoEvent::Created(todo)),
+        "updated" => Some(TodoEvent::Updated(todo)),
+        "completed" => Some(TodoEvent::Completed(todo)),
+        kind => return Err(format!("unknown event kind {kind}").into()),
+    })
+}
+
+/// Listens on `CHANNEL` for as long as the process runs and publishes the events of other
+/// processes to `subscribers`, reconnecting when the connection drops
+pub fn spawn_listener<R: TodoRepositoryTrait + 'static>(
+    pool: DatabasePool,
+    origin: Uuid,
+    repository: Arc<R>,
+    subscribers: Arc<dyn EventPublisher>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let disconnected = Instant::now();
+            if let Err(e) = listen(&pool, origin, repository.as_ref(), subscribers.as_ref()).await {
+                tracing::error!("Listening for events of other replicas failed: {}", e);
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            tracing::warn!(
+                "Reconnecting to {} after {:?}; events of other replicas in between are lost",
+                CHANNEL,
+                disconnected.elapsed()
+            );
+        }
+    });
+}
+
+async fn listen<R: TodoRepositoryTrait>(
+    pool: &DatabasePool,
+    origin: Uuid,
+    repository: &R,
+    subscribers: &dyn EventPublisher,
+) -> Result<(), PublishError> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    tracing::info!("Listening on {} for events of other replicas", CHANNEL);
+    // `None` once the connection is lost; the next call would reconnect silently, so
+    // return to log the gap instead
+    while let Some(notification) = listener.try_recv().await? {
+        match received(notification.payload(), origin, repository).await {
+            Ok(Some(event)) => {
+                if let Err(e) = subscribers.publish(&event).await {
+                    tracing::error!("Failed to publish an event of another replica: {}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Ignoring notification on {}: {}", CHANNEL, e),
+        }
+    }
+    Err("connection lost".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryTodoRepository;
+    use crate::Todo;
+
+    async fn todo(repository: &MemoryTodoRepository, content: &str) -> Todo {
+        let todo = Todo::new_with_validation("Shared", content).unwrap();
+        repository.create_todo(&todo).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_received() {
+        let repository = MemoryTodoRepository::new();
+        let (here, there) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let small = TodoEvent::Completed(todo(&repository, "").await);
+        let payload = Notice::encode(there, &small).unwrap();
+        assert_eq!(
+            received(&payload, here, &repository).await.unwrap(),
+            Some(small.clone())
+        );
+        // Sent by this process
+        assert_eq!(received(&payload, there, &repository).await.unwrap(), None);
+
+        // Too large to carry, so read back
+        let large = todo(&repository, &"x".repeat(MAX_PAYLOAD_BYTES)).await;
+        let payload = Notice::encode(there, &TodoEvent::Updated(large.clone())).unwrap();
+        assert!(payload.len() < 200);
+        assert_eq!(
+            received(&payload, here, &repository).await.unwrap(),
+            Some(TodoEvent::Updated(large.clone()))
+        );
+        repository.delete_todo(large.id, None).await.unwrap();
+        assert_eq!(received(&payload, here, &repository).await.unwrap(), None);
+        let deleted = TodoEvent::Deleted { id: large.id };
+        let payload = Notice::encode(there, &deleted).unwrap();
+        assert_eq!(
+            received(&payload, here, &repository).await.unwrap(),
+            Some(deleted)
+        );
+
+        assert!(received("{}", here, &repository).await.is_err());
+    }
+}
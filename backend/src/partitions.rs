@@ -0,0 +1,65 @@
+//! Upkeep for the optional monthly partitioning of `todos`
+//! (`database/optional/partition_todos_by_month.sql`).
+//!
+//! A partitioned table needs a partition for every month todos are created in. With
+//! `TODO_PARTITION_MONTHS_AHEAD` set, the backend creates the coming months' partitions at
+//! startup and once a day after that, so inserts never fall back to the default partition.
+//! Among replicas, only the job leader does (see `jobs`). New partitions get the row-level
+//! security of `todos` (`secure_todos()`, migration 032) in the same transaction, so they
+//! are never readable without it.
+
+use crate::jobs::JobRunner;
+use crate::DatabasePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionConfig {
+    pub months_ahead: i32,
+}
+
+impl PartitionConfig {
+    /// `None` unless `TODO_PARTITION_MONTHS_AHEAD` is a non-negative number
+    pub fn from_env() -> Option<Self> {
+        std::env::var("TODO_PARTITION_MONTHS_AHEAD")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|months: &i32| *months >= 0)
+            .map(|months_ahead| Self { months_ahead })
+    }
+}
+
+/// Creates any missing partitions from this month up to `months_ahead`; returns how many
+pub async fn ensure_partitions(pool: &DatabasePool, months_ahead: i32) -> Result<i32, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let created: i32 = sqlx::query_scalar("SELECT ensure_todo_partitions(CURRENT_TIMESTAMP, $1)")
+        .bind(months_ahead)
+        .fetch_one(&mut *tx)
+        .await?;
+    if created > 0 {
+        sqlx::query("SELECT secure_todos()")
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(created)
+}
+
+pub fn spawn_partition_maintenance(
+    config: PartitionConfig,
+    pool: DatabasePool,
+    jobs: &Arc<JobRunner>,
+) -> tokio::task::JoinHandle<()> {
+    jobs.spawn("todo-partitions", CHECK_INTERVAL, move || {
+        let pool = pool.clone();
+        async move {
+            match ensure_partitions(&pool, config.months_ahead).await? {
+                0 => tracing::debug!("Todo partitions are up to date"),
+                created => tracing::info!("Created {} todo partitions", created),
+            }
+            Ok(())
+        }
+    })
+}
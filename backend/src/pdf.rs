@@ -0,0 +1,915 @@
+//! Printable PDFs: `GET /api/todos/:id/pdf` and `GET /api/export/pdf`.
+//!
+//! Todos are turned into one markdown document and handed to a [`PdfRenderer`]. The
+//! built-in renderer lays the markdown out with the standard PDF fonts, which only cover
+//! Latin-1, so other characters print as `?`. Setting `PDF_RENDER_COMMAND` pipes the
+//! markdown through an external tool instead (pandoc, typst, weasyprint, ...), which must
+//! read markdown on stdin and write the PDF to stdout.
+
+use crate::domain::TodoId;
+use crate::{Todo, TodoError, TodoRepositoryTrait};
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::fmt::Write as _;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+pub type RenderError = Box<dyn std::error::Error + Send + Sync>;
+
+#[async_trait]
+pub trait PdfRenderer: Send + Sync {
+    async fn render(&self, title: &str, markdown: &str) -> Result<Vec<u8>, RenderError>;
+}
+
+/// `PDF_RENDER_COMMAND` if set, the built-in renderer otherwise
+pub fn renderer_from_env() -> Arc<dyn PdfRenderer> {
+    match std::env::var("PDF_RENDER_COMMAND")
+        .ok()
+        .and_then(|command| CommandPdfRenderer::parse(&command))
+    {
+        Some(renderer) => {
+            tracing::info!("Rendering PDFs with external command: {}", renderer.program);
+            Arc::new(renderer)
+        }
+        None => Arc::new(BuiltinPdfRenderer),
+    }
+}
+
+/// Runs an external program with the markdown on stdin and takes the PDF from stdout
+pub struct CommandPdfRenderer {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandPdfRenderer {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Arguments are split on whitespace; there is no shell quoting
+    pub fn parse(command: &str) -> Option<Self> {
+        let mut parts = command.split_whitespace().map(str::to_string);
+        Some(Self {
+            program: parts.next()?,
+            args: parts.collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl PdfRenderer for CommandPdfRenderer {
+    async fn render(&self, _title: &str, markdown: &str) -> Result<Vec<u8>, RenderError> {
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // Written from a separate task so a renderer that streams output early cannot
+        // deadlock against a full stdin pipe
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = markdown.as_bytes().to_vec();
+        let writer = tokio::spawn(async move { stdin.write_all(&input).await });
+
+        let output = tokio::time::timeout(Self::TIMEOUT, child.wait_with_output())
+            .await
+            .map_err(|_| format!("{} timed out", self.program))??;
+        if !output.status.success() {
+            return Err(format!(
+                "{} exited with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        writer.await??;
+        if output.stdout.is_empty() {
+            return Err(format!("{} produced no output", self.program).into());
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// Dependency-free renderer covering headings, paragraphs, lists, quotes, code and rules
+pub struct BuiltinPdfRenderer;
+
+#[async_trait]
+impl PdfRenderer for BuiltinPdfRenderer {
+    async fn render(&self, title: &str, markdown: &str) -> Result<Vec<u8>, RenderError> {
+        let pages = layout(&blocks_from_markdown(markdown));
+        Ok(write_pdf(&encode_win_ansi(title), &pages))
+    }
+}
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.5;
+const LEADING: f32 = 1.35;
+const INDENT: f32 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 4] = [Font::Regular, Font::Bold, Font::Italic, Font::Mono];
+
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::Mono => "F4",
+        }
+    }
+
+    fn base_font(self) -> &'static str {
+        match self {
+            Font::Regular => "Helvetica",
+            Font::Bold => "Helvetica-Bold",
+            Font::Italic => "Helvetica-Oblique",
+            Font::Mono => "Courier",
+        }
+    }
+
+    /// Advance width of a WinAnsi byte in thousandths of the font size
+    fn char_width(self, byte: u8) -> u16 {
+        let table = match self {
+            Font::Mono => return 600,
+            Font::Bold => &HELVETICA_BOLD_WIDTHS,
+            Font::Regular | Font::Italic => &HELVETICA_WIDTHS,
+        };
+        match byte {
+            0x20..=0x7e => table[usize::from(byte - 0x20)],
+            _ => 556,
+        }
+    }
+
+    fn text_width(self, text: &[u8], size: f32) -> f32 {
+        let units: u32 = text.iter().map(|&b| u32::from(self.char_width(b))).sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+/// Helvetica widths for ASCII 0x20..=0x7e, from the standard AFM metrics
+#[rustfmt::skip]
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[rustfmt::skip]
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// Encodes text for the standard fonts; characters outside WinAnsi become `?`
+fn encode_win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' => c as u8,
+            '\n' => b'\n',
+            '\t' => b' ',
+            '\u{a0}'..='\u{ff}' => c as u8,
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '™' => 0x99,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// PDF literal string; non-printable bytes are octal-escaped so streams stay ASCII
+fn pdf_string(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len() + 2);
+    escaped.push('(');
+    for &byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\{:03o}", byte);
+            }
+        }
+    }
+    escaped.push(')');
+    escaped
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockKind {
+    Text { size: f32 },
+    Code,
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    kind: BlockKind,
+    /// Offset of the text from the left margin
+    indent: f32,
+    /// Bullet, number or checkbox drawn left of the first line
+    marker: Option<Vec<u8>>,
+    /// Runs of WinAnsi text; `\n` forces a line break
+    spans: Vec<(Font, Vec<u8>)>,
+}
+
+#[derive(Default)]
+struct BlockBuilder {
+    blocks: Vec<Block>,
+    current: Option<Block>,
+    /// Next number for ordered lists, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    quote_depth: usize,
+    bold: usize,
+    italic: usize,
+    in_code_block: bool,
+    /// Link target and the number of spans in the block when the link started
+    links: Vec<(String, usize)>,
+}
+
+impl BlockBuilder {
+    fn indent(&self) -> f32 {
+        (self.lists.len() + self.quote_depth) as f32 * INDENT
+    }
+
+    fn font(&self) -> Font {
+        if self.in_code_block {
+            Font::Mono
+        } else if self.bold > 0 {
+            Font::Bold
+        } else if self.italic > 0 {
+            Font::Italic
+        } else {
+            Font::Regular
+        }
+    }
+
+    fn start(&mut self, kind: BlockKind) {
+        self.flush();
+        self.current = Some(Block {
+            kind,
+            indent: self.indent(),
+            marker: None,
+            spans: Vec::new(),
+        });
+    }
+
+    fn flush(&mut self) {
+        let Some(mut block) = self.current.take() else {
+            return;
+        };
+        if let Some((_, text)) = block.spans.last_mut() {
+            while text.last() == Some(&b'\n') {
+                text.pop();
+            }
+        }
+        if block.marker.is_some() || block.spans.iter().any(|(_, text)| !text.is_empty()) {
+            self.blocks.push(block);
+        }
+    }
+
+    fn push(&mut self, font: Font, text: &str) {
+        if self.current.is_none() {
+            self.start(BlockKind::Text { size: BODY_SIZE });
+        }
+        let block = self.current.as_mut().expect("block was just started");
+        block.spans.push((font, encode_win_ansi(text)));
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                // A loose list item wraps its text in a paragraph; keep the item's marker
+                let item_pending = self
+                    .current
+                    .as_ref()
+                    .is_some_and(|block| block.marker.is_some() && block.spans.is_empty());
+                if !item_pending {
+                    self.start(BlockKind::Text { size: BODY_SIZE });
+                }
+            }
+            Event::End(TagEnd::Paragraph) => self.flush(),
+            Event::Start(Tag::Heading { level, .. }) => {
+                let size = match level {
+                    HeadingLevel::H1 => 20.0,
+                    HeadingLevel::H2 => 16.0,
+                    HeadingLevel::H3 => 13.5,
+                    _ => 12.0,
+                };
+                self.start(BlockKind::Text { size });
+                self.bold += 1;
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                self.bold -= 1;
+                self.flush();
+            }
+            Event::Start(Tag::List(start)) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                self.flush();
+                self.lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                self.start(BlockKind::Text { size: BODY_SIZE });
+                if let Some(block) = self.current.as_mut() {
+                    block.marker = Some(encode_win_ansi(&marker));
+                }
+            }
+            Event::End(TagEnd::Item) => self.flush(),
+            Event::TaskListMarker(checked) => {
+                if let Some(block) = self.current.as_mut() {
+                    block.marker = Some(if checked { b"[x]" } else { b"[ ]" }.to_vec());
+                }
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                self.flush();
+                self.quote_depth += 1;
+                self.italic += 1;
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                self.flush();
+                self.quote_depth -= 1;
+                self.italic -= 1;
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                self.start(BlockKind::Code);
+                self.in_code_block = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                self.in_code_block = false;
+                self.flush();
+            }
+            Event::Start(Tag::HtmlBlock) => self.start(BlockKind::Code),
+            Event::End(TagEnd::HtmlBlock) => self.flush(),
+            Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
+                self.start(BlockKind::Text { size: BODY_SIZE });
+            }
+            Event::Start(Tag::TableCell)
+                if self
+                    .current
+                    .as_ref()
+                    .is_some_and(|block| !block.spans.is_empty()) =>
+            {
+                self.push(Font::Regular, " | ");
+            }
+            Event::End(TagEnd::TableHead) | Event::End(TagEnd::TableRow) => self.flush(),
+            Event::Start(Tag::Emphasis) => self.italic += 1,
+            Event::End(TagEnd::Emphasis) => self.italic -= 1,
+            Event::Start(Tag::Strong) => self.bold += 1,
+            Event::End(TagEnd::Strong) => self.bold -= 1,
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                let spans = self.current.as_ref().map_or(0, |block| block.spans.len());
+                self.links.push((dest_url.to_string(), spans));
+            }
+            Event::End(TagEnd::Link) => {
+                let Some((url, first_span)) = self.links.pop() else {
+                    return;
+                };
+                let text: Vec<u8> = self.current.as_ref().map_or_else(Vec::new, |block| {
+                    block.spans[first_span.min(block.spans.len())..]
+                        .iter()
+                        .flat_map(|(_, text)| text.clone())
+                        .collect()
+                });
+                // Printed pages cannot be clicked, so spell out the target
+                if text != encode_win_ansi(&url) {
+                    self.push(Font::Regular, &format!(" <{url}>"));
+                }
+            }
+            Event::Start(Tag::Image { .. }) => self.push(self.font(), "[Image: "),
+            Event::End(TagEnd::Image) => self.push(self.font(), "]"),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                self.push(self.font(), &text)
+            }
+            Event::Code(code) | Event::InlineMath(code) | Event::DisplayMath(code) => {
+                self.push(Font::Mono, &code)
+            }
+            Event::FootnoteReference(name) => self.push(self.font(), &format!("[{name}]")),
+            Event::SoftBreak => self.push(self.font(), " "),
+            Event::HardBreak => self.push(self.font(), "\n"),
+            Event::Rule => {
+                self.flush();
+                self.blocks.push(Block {
+                    kind: BlockKind::Rule,
+                    indent: self.indent(),
+                    marker: None,
+                    spans: Vec::new(),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn blocks_from_markdown(markdown: &str) -> Vec<Block> {
+    let options =
+        Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut builder = BlockBuilder::default();
+    for event in Parser::new_ext(markdown, options) {
+        builder.handle(event);
+    }
+    builder.flush();
+    builder.blocks
+}
+
+/// A run of text placed at an x offset within its line
+type Fragment = (Font, f32, Vec<u8>);
+
+/// Breaks a block into lines no wider than `width`. Text blocks wrap at spaces; code
+/// blocks keep their spacing and only break inside over-long lines.
+fn wrap(block: &Block, size: f32, width: f32) -> Vec<Vec<Fragment>> {
+    let split_words = matches!(block.kind, BlockKind::Text { .. });
+    let mut lines: Vec<Vec<Fragment>> = vec![Vec::new()];
+    let mut x = 0.0;
+
+    for (font, text) in &block.spans {
+        for (line_index, segment) in text.split(|&b| b == b'\n').enumerate() {
+            if line_index > 0 {
+                lines.push(Vec::new());
+                x = 0.0;
+            }
+            let words: Vec<&[u8]> = if split_words {
+                segment.split(|&b| b == b' ').collect()
+            } else {
+                vec![segment]
+            };
+            for (word_index, word) in words.into_iter().enumerate() {
+                let mut space = if word_index > 0 {
+                    font.text_width(b" ", size)
+                } else {
+                    0.0
+                };
+                if word.is_empty() {
+                    x += if x > 0.0 { space } else { 0.0 };
+                    continue;
+                }
+                let mut word = word;
+                loop {
+                    let word_width = font.text_width(word, size);
+                    if x > 0.0 && x + space + word_width > width {
+                        lines.push(Vec::new());
+                        x = 0.0;
+                        space = 0.0;
+                    }
+                    if x == 0.0 && word_width > width {
+                        // Longer than a whole line, e.g. a URL: break it anywhere
+                        let mut fits = 1;
+                        while fits < word.len() && font.text_width(&word[..=fits], size) <= width {
+                            fits += 1;
+                        }
+                        let line = lines.last_mut().expect("lines is never empty");
+                        line.push((*font, 0.0, word[..fits].to_vec()));
+                        lines.push(Vec::new());
+                        word = &word[fits..];
+                        if word.is_empty() {
+                            break;
+                        }
+                        continue;
+                    }
+                    let line = lines.last_mut().expect("lines is never empty");
+                    line.push((*font, x + space, word.to_vec()));
+                    x += space + word_width;
+                    break;
+                }
+            }
+        }
+    }
+    if lines.len() > 1 && lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Lays blocks out top to bottom and returns one content stream per page
+fn layout(blocks: &[Block]) -> Vec<String> {
+    let mut pages = vec![String::new()];
+    let mut y = PAGE_HEIGHT - MARGIN;
+
+    for block in blocks {
+        let size = match block.kind {
+            BlockKind::Text { size } => size,
+            BlockKind::Code => CODE_SIZE,
+            BlockKind::Rule => BODY_SIZE,
+        };
+        let x = MARGIN + block.indent;
+        y -= size * 0.6;
+
+        if block.kind == BlockKind::Rule {
+            if y - size < MARGIN {
+                pages.push(String::new());
+                y = PAGE_HEIGHT - MARGIN;
+            }
+            y -= size / 2.0;
+            let page = pages.last_mut().expect("pages is never empty");
+            let _ = writeln!(
+                page,
+                "0.5 w {:.2} {:.2} m {:.2} {:.2} l S",
+                x,
+                y,
+                PAGE_WIDTH - MARGIN,
+                y
+            );
+            y -= size / 2.0;
+            continue;
+        }
+
+        let width = PAGE_WIDTH - MARGIN - x;
+        for (index, line) in wrap(block, size, width).into_iter().enumerate() {
+            let line_height = size * LEADING;
+            if y - line_height < MARGIN {
+                pages.push(String::new());
+                y = PAGE_HEIGHT - MARGIN;
+            }
+            y -= line_height;
+            let page = pages.last_mut().expect("pages is never empty");
+            if index == 0 {
+                if let Some(marker) = &block.marker {
+                    let marker_x = x - Font::Regular.text_width(marker, size) - 4.0;
+                    write_text(page, Font::Regular, size, marker_x, y, marker);
+                }
+            }
+            for (font, offset, text) in line {
+                write_text(page, font, size, x + offset, y, &text);
+            }
+        }
+    }
+    pages
+}
+
+fn write_text(page: &mut String, font: Font, size: f32, x: f32, y: f32, text: &[u8]) {
+    let _ = writeln!(
+        page,
+        "BT /{} {} Tf {:.2} {:.2} Td {} Tj ET",
+        font.resource(),
+        size,
+        x,
+        y,
+        pdf_string(text)
+    );
+}
+
+/// Serializes pages into a PDF 1.4 file with the four standard fonts
+fn write_pdf(title: &[u8], pages: &[String]) -> Vec<u8> {
+    const FIRST_FONT: usize = 3;
+    const INFO: usize = FIRST_FONT + Font::ALL.len();
+    const FIRST_PAGE: usize = INFO + 1;
+
+    let font_resources: String = Font::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, font)| format!("/{} {} 0 R ", font.resource(), FIRST_FONT + i))
+        .collect();
+    let kids: String = (0..pages.len())
+        .map(|i| format!("{} 0 R ", FIRST_PAGE + 2 * i))
+        .collect();
+
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.trim_end(),
+            pages.len()
+        ),
+    ];
+    for font in Font::ALL {
+        objects.push(format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+            font.base_font()
+        ));
+    }
+    objects.push(format!(
+        "<< /Title {} /Producer (md-todo) >>",
+        pdf_string(title)
+    ));
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << {}>> >> /Contents {} 0 R >>",
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+            font_resources,
+            FIRST_PAGE + 2 * i + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+    let xref_offset = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        INFO,
+        xref_offset
+    );
+    pdf.extend_from_slice(trailer.as_bytes());
+    pdf
+}
+
+/// Backslash-escapes characters that would turn plain text into markdown syntax
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~' | '|'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Title, a status line and the markdown content of a todo
+pub fn todo_markdown(todo: &Todo) -> String {
+    let mut details = vec![
+        if todo.completed { "Completed" } else { "Open" }.to_string(),
+        format!("created {}", todo.created_at.format("%Y-%m-%d")),
+    ];
+    if !todo.labels.is_empty() {
+        details.push(format!(
+            "labels: {}",
+            escape_markdown(&todo.labels.join(", "))
+        ));
+    }
+    format!(
+        "# {}\n\n*{}*\n\n{}\n",
+        escape_markdown(&todo.title),
+        details.join(" · "),
+        todo.content
+    )
+}
+
+/// All todos in one document, separated by rules
+pub fn export_markdown(todos: &[Todo]) -> String {
+    todos
+        .iter()
+        .map(todo_markdown)
+        .collect::<Vec<_>>()
+        .join("\n---\n\n")
+}
+
+fn pdf_response(pdf: Vec<u8>, filename: &str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        pdf,
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}/pdf",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    responses(
+        (status = 200, description = "Printable PDF of the todo", content_type = "application/pdf", body = Vec<u8>),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_todo_pdf<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(renderer): Extension<Arc<dyn PdfRenderer>>,
+    Path(id): Path<TodoId>,
+) -> Result<Response, TodoError> {
+    let todo = match repository.get_todo_by_id(id).await {
+        Ok(Some(todo)) => todo,
+        Ok(None) => {
+            tracing::warn!("Todo not found for PDF with id: {}", id);
+            return Err(TodoError::NotFound(format!("Todo {}", id)));
+        }
+        Err(e) => {
+            tracing::error!("Failed to get todo {} for PDF: {}", id, e);
+            return Err(e);
+        }
+    };
+
+    match renderer.render(&todo.title, &todo_markdown(&todo)).await {
+        Ok(pdf) => Ok(pdf_response(pdf, &format!("todo-{id}.pdf"))),
+        Err(e) => {
+            tracing::error!("Failed to render PDF for todo {}: {}", id, e);
+            Err(TodoError::Internal(e.to_string()))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/export/pdf",
+    responses(
+        (status = 200, description = "Printable PDF of every todo", content_type = "application/pdf", body = Vec<u8>),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn export_pdf<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(renderer): Extension<Arc<dyn PdfRenderer>>,
+) -> Result<Response, TodoError> {
+    let todos = repository.get_all_todos().await.map_err(|e| {
+        tracing::error!("Failed to get todos for PDF export: {}", e);
+        e
+    })?;
+
+    tracing::info!("Exporting {} todos to PDF", todos.len());
+    match renderer.render("Todos", &export_markdown(&todos)).await {
+        Ok(pdf) => Ok(pdf_response(pdf, "todos.pdf")),
+        Err(e) => {
+            tracing::error!("Failed to render PDF export: {}", e);
+            Err(TodoError::Internal(e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_text(block: &Block) -> Vec<u8> {
+        block
+            .spans
+            .iter()
+            .flat_map(|(_, text)| text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_win_ansi() {
+        assert_eq!(encode_win_ansi("Café • “ok”"), b"Caf\xe9 \x95 \x93ok\x94");
+        assert_eq!(encode_win_ansi("日本"), b"??");
+    }
+
+    #[test]
+    fn test_pdf_string_escapes() {
+        assert_eq!(pdf_string(b"a(b)\\c\xe9"), "(a\\(b\\)\\\\c\\351)");
+    }
+
+    #[test]
+    fn test_blocks_from_markdown() {
+        let blocks = blocks_from_markdown(
+            "# Title\n\n- [x] done\n- [ ] todo\n\n3. three\n4. four\n\n```\nlet x = 1;\n```\n\n---\n",
+        );
+        let markers: Vec<_> = blocks.iter().map(|b| b.marker.clone()).collect();
+        assert_eq!(
+            markers,
+            vec![
+                None,
+                Some(b"[x]".to_vec()),
+                Some(b"[ ]".to_vec()),
+                Some(b"3.".to_vec()),
+                Some(b"4.".to_vec()),
+                None,
+                None,
+            ]
+        );
+        assert_eq!(blocks[0].kind, BlockKind::Text { size: 20.0 });
+        assert_eq!(blocks[0].spans[0].0, Font::Bold);
+        assert_eq!(blocks[1].indent, INDENT);
+        assert_eq!(blocks[5].kind, BlockKind::Code);
+        assert_eq!(block_text(&blocks[5]), b"let x = 1;");
+        assert_eq!(blocks[6].kind, BlockKind::Rule);
+    }
+
+    #[test]
+    fn test_links_spell_out_target() {
+        let blocks = blocks_from_markdown("[docs](https://example.com) and <https://a.b>");
+        assert_eq!(
+            block_text(&blocks[0]),
+            b"docs <https://example.com> and https://a.b"
+        );
+    }
+
+    #[test]
+    fn test_wrap_respects_width() {
+        let block = Block {
+            kind: BlockKind::Text { size: BODY_SIZE },
+            indent: 0.0,
+            marker: None,
+            spans: vec![(Font::Regular, b"word ".repeat(100))],
+        };
+        let lines = wrap(&block, BODY_SIZE, 200.0);
+        assert!(lines.len() > 5);
+        for line in &lines {
+            let (font, x, text) = line.last().unwrap();
+            assert!(x + font.text_width(text, BODY_SIZE) <= 200.0);
+        }
+
+        let long = Block {
+            spans: vec![(Font::Mono, b"x".repeat(100))],
+            ..block
+        };
+        let lines = wrap(&long, 10.0, 60.0);
+        assert_eq!(lines.len(), 10);
+    }
+
+    #[test]
+    fn test_write_pdf_structure() {
+        let markdown = "paragraph\n\n".repeat(200);
+        let pages = layout(&blocks_from_markdown(&markdown));
+        assert!(pages.len() > 1);
+
+        let pdf = write_pdf(b"Title", &pages);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        // Everything after the binary comment in the header is ASCII
+        let text = std::str::from_utf8(&pdf[15..]).unwrap();
+        assert!(text.contains(&format!("/Count {}", pages.len())));
+
+        let startxref: usize = text
+            .rsplit("startxref\n")
+            .next()
+            .and_then(|rest| rest.lines().next())
+            .and_then(|offset| offset.parse().ok())
+            .unwrap();
+        assert!(pdf[startxref..].starts_with(b"xref"));
+        let third_object: usize = text[startxref - 15..].lines().nth(5).unwrap()[..10]
+            .parse()
+            .unwrap();
+        assert!(pdf[third_object..].starts_with(b"3 0 obj"));
+    }
+
+    #[test]
+    fn test_todo_markdown() {
+        let mut todo = Todo::new_with_validation("Fix #12 *now*", "- [ ] step").unwrap();
+        todo.labels = vec!["work/urgent".to_string()];
+        let markdown = todo_markdown(&todo);
+        assert!(markdown.starts_with("# Fix \\#12 \\*now\\*\n\n*Open · created "));
+        assert!(markdown.contains(" · labels: work/urgent*\n\n- [ ] step\n"));
+    }
+
+    #[tokio::test]
+    async fn test_command_renderer() {
+        let renderer = CommandPdfRenderer::parse("cat").unwrap();
+        assert_eq!(renderer.render("t", "# hi").await.unwrap(), b"# hi");
+
+        let failing = CommandPdfRenderer::parse("false").unwrap();
+        assert!(failing.render("t", "# hi").await.is_err());
+        assert!(CommandPdfRenderer::parse("  ").is_none());
+    }
+}
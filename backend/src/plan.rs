@@ -0,0 +1,196 @@
+//! Focus mode: today's plan at `/api/me/plan`.
+//!
+//! A plan pins up to `MAX_PLAN_TODOS` todos, in the order they are meant to be worked
+//! on, to one UTC day. Plans are stored per user and day, so reloading keeps today's plan
+//! and `GET /api/me/plan?day=` shows an earlier one. "Me" is the user of the access token,
+//! or everyone using the server while accounts are off. Deleted todos drop out of every
+//! plan.
+
+use crate::auth;
+use crate::domain::TodoId;
+use crate::errors::ErrorCode;
+use crate::{ApiResponse, Todo, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Todos one day's plan can hold
+pub const MAX_PLAN_TODOS: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PlanRequest {
+    /// Todos to work on today, first one first; an empty list clears the plan
+    #[schema(value_type = Vec<Uuid>, example = json!(["018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d"]))]
+    pub todo_ids: Vec<TodoId>,
+}
+
+impl PlanRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.todo_ids.len() > MAX_PLAN_TODOS {
+            return Err(format!(
+                "A plan holds at most {} todos, got {}",
+                MAX_PLAN_TODOS,
+                self.todo_ids.len()
+            ));
+        }
+        let mut seen = HashSet::new();
+        if let Some(id) = self.todo_ids.iter().find(|id| !seen.insert(*id)) {
+            return Err(format!("Todo {} is planned twice", id));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlanQuery {
+    pub day: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Plan {
+    /// UTC day the plan is for
+    #[schema(example = "2026-10-17")]
+    pub day: NaiveDate,
+    /// Planned todos in working order
+    pub todos: Vec<Todo>,
+    #[schema(example = 2)]
+    pub completed: usize,
+    #[schema(example = 3)]
+    pub total: usize,
+    /// Share of planned todos completed, rounded down; 0 for an empty plan
+    #[schema(example = 66)]
+    pub progress_percent: u8,
+}
+
+impl Plan {
+    pub fn new(day: NaiveDate, todos: Vec<Todo>) -> Self {
+        let completed = todos.iter().filter(|todo| todo.completed).count();
+        let total = todos.len();
+        let progress_percent = (completed * 100).checked_div(total).unwrap_or(0) as u8;
+        Self {
+            day,
+            todos,
+            completed,
+            total,
+            progress_percent,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlanResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Plan>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Plan>> for PlanResponse {
+    fn from(response: ApiResponse<Plan>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me/plan",
+    params(
+        ("day" = Option<NaiveDate>, Query, description = "UTC day of the plan (default today)")
+    ),
+    responses(
+        (status = 200, description = "The user's plan for the day with its progress; empty when nothing was planned", body = PlanResponse),
+        (status = 400, description = "Invalid day", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_plan<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Query(query): Query<PlanQuery>,
+) -> Result<Json<PlanResponse>, TodoError> {
+    let day = query.day.unwrap_or_else(|| Utc::now().date_naive());
+    match repository.plan_todos(auth::acting_user(), day).await {
+        Ok(todos) => Ok(Json(ApiResponse::success(Plan::new(day, todos)).into())),
+        Err(e) => {
+            tracing::error!("Failed to get the plan for {}: {}", day, e);
+            Err(e)
+        }
+    }
+}
+
+/// Replaces today's plan
+#[utoipa::path(
+    post,
+    path = "/api/me/plan",
+    request_body = PlanRequest,
+    responses(
+        (status = 200, description = "Today's plan as stored", body = PlanResponse),
+        (status = 400, description = "More than 5 todos, or a todo listed twice", body = ErrorResponse),
+        (status = 404, description = "A planned todo does not exist", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn set_plan<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Json(request): Json<PlanRequest>,
+) -> Result<Json<PlanResponse>, TodoError> {
+    if let Err(e) = request.validate() {
+        tracing::warn!("Validation failed for plan: {}", e);
+        return Err(TodoError::Validation(e));
+    }
+    for &id in &request.todo_ids {
+        if repository.get_todo_by_id(id).await?.is_none() {
+            tracing::warn!("Todo not found for plan with id: {}", id);
+            return Err(TodoError::NotFound(format!("Todo {}", id)));
+        }
+    }
+
+    let user = auth::acting_user();
+    let day = Utc::now().date_naive();
+    repository
+        .set_plan(user, day, &request.todo_ids)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to save the plan for {}: {}", day, e);
+            e
+        })?;
+    tracing::info!("Planned {} todos for {}", request.todo_ids.len(), day);
+    let todos = repository.plan_todos(user, day).await?;
+    Ok(Json(ApiResponse::success(Plan::new(day, todos)).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_requests_are_small_and_distinct() {
+        let ids: Vec<TodoId> = (0..=MAX_PLAN_TODOS).map(|_| TodoId::new()).collect();
+        let request = |todo_ids: &[TodoId]| PlanRequest {
+            todo_ids: todo_ids.to_vec(),
+        };
+
+        assert!(request(&[]).validate().is_ok());
+        assert!(request(&ids[..MAX_PLAN_TODOS]).validate().is_ok());
+        assert!(request(&ids).validate().is_err());
+        assert_eq!(
+            request(&[ids[0], ids[1], ids[0]]).validate(),
+            Err(format!("Todo {} is planned twice", ids[0]))
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! The validation policy: rules an administrator adds on top of the built-in validation
+//! of todos, read at `GET /api/policy` and replaced at `PUT /api/admin/policy`.
+//!
+//! The instance is one workspace, so there is one policy, stored in `validation_policy`.
+//! It can shorten the longest title, ask for a number of tags, and make metadata fields
+//! mandatory from a priority up. Todos have no due date of their own; "High todos need a
+//! due date" is a `due` metadata field required from `high`.
+//!
+//! `POST /api/todos`, the bulk endpoints, imports and the split and extract-section
+//! endpoints check the todos they write. An update is only held to the rules for what it
+//! changes, so todos stored before the policy can still be completed or edited; raising
+//! the priority of one brings in the required fields. Todos captured by mail or clipped
+//! from a page are not checked.
+
+use crate::admin::{self, AdminConfig};
+use crate::domain::TodoId;
+use crate::errors::{ErrorCode, FieldError, FieldErrorCode, LengthLimit, ValidationErrors};
+use crate::metadata::MetadataField;
+use crate::{
+    tags, ApiResponse, Priority, Todo, TodoError, TodoRepositoryTrait, UpdateTodoRequest,
+    MAX_TITLE_CHARS,
+};
+use axum::{extract::State, http::HeaderMap, response::Json, Extension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+#[serde(default)]
+pub struct ValidationPolicy {
+    /// Longest title in characters; the built-in limit of 255 when unset
+    #[schema(example = 80, minimum = 1, maximum = 255)]
+    pub max_title_length: Option<i32>,
+    /// Fewest tags a todo must carry
+    #[schema(example = 1, minimum = 0, maximum = 20)]
+    pub min_tags: i32,
+    /// Metadata fields todos must set from `required_fields_from` up
+    #[schema(example = json!(["due"]))]
+    pub required_fields: Vec<String>,
+    /// Lowest priority `required_fields` apply to; `low` for every todo
+    #[schema(example = "high")]
+    pub required_fields_from: Priority,
+}
+
+/// No rules beyond the built-in ones
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            max_title_length: None,
+            min_tags: 0,
+            required_fields: Vec::new(),
+            required_fields_from: Priority::Low,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// Errors of a policy to store, given the metadata fields defined
+    pub fn validate(&self, fields: &[MetadataField]) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
+        if let Some(max) = self.max_title_length {
+            if !(1..=MAX_TITLE_CHARS as i32).contains(&max) {
+                errors.add(
+                    "max_title_length",
+                    FieldErrorCode::OutOfRange,
+                    format!("max_title_length must be between 1 and {}", MAX_TITLE_CHARS),
+                );
+            }
+        }
+        if !(0..=tags::MAX_TAGS as i32).contains(&self.min_tags) {
+            errors.add(
+                "min_tags",
+                FieldErrorCode::OutOfRange,
+                format!("min_tags must be between 0 and {}", tags::MAX_TAGS),
+            );
+        }
+        for key in &self.required_fields {
+            if !fields.iter().any(|field| &field.key == key) {
+                errors.add(
+                    "required_fields",
+                    FieldErrorCode::Invalid,
+                    format!("Unknown metadata field: {}", key),
+                );
+            }
+        }
+        errors
+    }
+
+    /// Rules `todo` breaks
+    pub fn check(&self, todo: &Todo) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
+        self.check_title(&mut errors, &todo.title);
+        self.check_tags(&mut errors, todo.tags.len());
+        self.check_fields(&mut errors, &todo.metadata, todo.priority);
+        errors
+    }
+
+    /// Whether `update` changes anything a rule looks at
+    pub fn covers(&self, update: &UpdateTodoRequest) -> bool {
+        let tags_changed =
+            update.tags.is_some() || update.add_tags.is_some() || update.remove_tags.is_some();
+        (self.max_title_length.is_some() && update.title.is_some())
+            || (self.min_tags > 0 && tags_changed)
+            || (!self.required_fields.is_empty()
+                && (update.metadata.is_some() || update.priority.is_some()))
+    }
+
+    /// Rules `current` would break after `update`, among those for what it changes
+    pub fn check_update(&self, current: &Todo, update: &UpdateTodoRequest) -> ValidationErrors {
+        let mut errors = ValidationErrors::default();
+        if let Some(title) = &update.title {
+            self.check_title(&mut errors, title);
+        }
+        if update.tags.is_some() || update.add_tags.is_some() || update.remove_tags.is_some() {
+            let mut names = update.tags.clone().unwrap_or_else(|| current.tags.clone());
+            names.extend(update.add_tags.iter().flatten().cloned());
+            names.retain(|name| !update.remove_tags.iter().flatten().any(|r| r == name));
+            names.sort();
+            names.dedup();
+            self.check_tags(&mut errors, names.len());
+        }
+        if update.metadata.is_some() || update.priority.is_some() {
+            self.check_fields(
+                &mut errors,
+                update.metadata.as_ref().unwrap_or(&current.metadata),
+                update.priority.unwrap_or(current.priority),
+            );
+        }
+        errors
+    }
+
+    fn check_title(&self, errors: &mut ValidationErrors, title: &str) {
+        let Some(max) = self.max_title_length else {
+            return;
+        };
+        if let Some(limit) = LengthLimit::characters(title, max.max(0) as usize) {
+            errors.check(
+                "title",
+                Err(FieldError::too_long(
+                    limit,
+                    format!("Title cannot exceed {} characters", max),
+                )),
+            );
+        }
+    }
+
+    fn check_tags(&self, errors: &mut ValidationErrors, count: usize) {
+        if (count as i32) < self.min_tags {
+            errors.add(
+                "tags",
+                FieldErrorCode::Required,
+                format!("A todo needs at least {} tags", self.min_tags),
+            );
+        }
+    }
+
+    fn check_fields(&self, errors: &mut ValidationErrors, metadata: &Value, priority: Priority) {
+        if priority < self.required_fields_from {
+            return;
+        }
+        for key in &self.required_fields {
+            if metadata.get(key).is_none() {
+                errors.add(
+                    "metadata",
+                    FieldErrorCode::Required,
+                    format!(
+                        "Metadata field {} is required for todos of {} priority",
+                        key,
+                        priority.as_str()
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// The stored policy, as a handler would respond
+pub async fn load<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+) -> Result<ValidationPolicy, TodoError> {
+    repository.validation_policy().await.map_err(|e| {
+        tracing::error!("Failed to load the validation policy: {}", e);
+        e
+    })
+}
+
+/// Fails with every rule `update` would make the todo `id` break, if the policy covers it
+pub async fn check_update<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    policy: &ValidationPolicy,
+    id: TodoId,
+    update: &UpdateTodoRequest,
+) -> Result<(), TodoError> {
+    if !policy.covers(update) {
+        return Ok(());
+    }
+    let Some(current) = repository.get_todo_by_id(id).await? else {
+        return Ok(());
+    };
+    policy
+        .check_update(&current, update)
+        .into_result()
+        .map_err(|errors| {
+            tracing::warn!(
+                "Update of todo {} breaks the validation policy: {}",
+                id,
+                errors
+            );
+            errors.into()
+        })
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ValidationPolicyResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<ValidationPolicy>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<ValidationPolicy>> for ValidationPolicyResponse {
+    fn from(response: ApiResponse<ValidationPolicy>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/policy",
+    responses(
+        (status = 200, description = "The validation policy todos are held to", body = ValidationPolicyResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_policy<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<ValidationPolicyResponse>, TodoError> {
+    let policy = load(repository.as_ref()).await?;
+    Ok(Json(ApiResponse::success(policy).into()))
+}
+
+/// Applies to todos written from now on; stored todos are left as they are
+#[utoipa::path(
+    put,
+    path = "/api/admin/policy",
+    request_body = ValidationPolicy,
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "The policy as stored", body = ValidationPolicyResponse),
+        (status = 400, description = "A limit is out of range or a field is not defined", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Admin"
+)]
+pub async fn put_policy<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Json(mut policy): Json<ValidationPolicy>,
+) -> Result<Json<ValidationPolicyResponse>, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    policy.required_fields.sort();
+    policy.required_fields.dedup();
+    let fields = repository.list_metadata_fields().await.map_err(|e| {
+        tracing::error!("Failed to load metadata fields: {}", e);
+        e
+    })?;
+    policy.validate(&fields).into_result().map_err(|errors| {
+        tracing::warn!("Validation failed for the validation policy: {}", errors);
+        TodoError::from(errors)
+    })?;
+
+    match repository.set_validation_policy(&policy).await {
+        Ok(saved) => {
+            tracing::info!("Saved the validation policy: {:?}", saved);
+            Ok(Json(ApiResponse::success(saved).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save the validation policy: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy() -> ValidationPolicy {
+        ValidationPolicy {
+            max_title_length: Some(10),
+            min_tags: 1,
+            required_fields: vec!["due".to_string()],
+            required_fields_from: Priority::High,
+        }
+    }
+
+    fn fields(errors: ValidationErrors) -> Vec<(String, FieldErrorCode)> {
+        errors
+            .into_fields()
+            .into_iter()
+            .map(|error| (error.field, error.code))
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let mut todo = Todo::new_with_validation("A long title", "").unwrap();
+        todo.priority = Priority::Urgent;
+        assert_eq!(
+            fields(policy().check(&todo)),
+            [
+                ("title".to_string(), FieldErrorCode::TooLong),
+                ("tags".to_string(), FieldErrorCode::Required),
+                ("metadata".to_string(), FieldErrorCode::Required),
+            ]
+        );
+
+        let mut todo = Todo::new_with_validation("Short", "").unwrap();
+        todo.tags = vec!["work".to_string()];
+        assert!(policy().check(&todo).is_empty());
+        todo.metadata = json!({"due": "2026-11-01"});
+        todo.priority = Priority::High;
+        assert!(policy().check(&todo).is_empty());
+        assert!(ValidationPolicy::default().check(&todo).is_empty());
+    }
+
+    #[test]
+    fn test_check_update_only_looks_at_what_changes() {
+        let todo = Todo::new_with_validation("A long title", "").unwrap();
+        let update = |value: Value| serde_json::from_value::<UpdateTodoRequest>(value).unwrap();
+
+        let complete = update(json!({"completed": true}));
+        assert!(!policy().covers(&complete));
+        assert!(policy().check_update(&todo, &complete).is_empty());
+
+        assert_eq!(
+            fields(policy().check_update(&todo, &update(json!({"priority": "high"})))),
+            [("metadata".to_string(), FieldErrorCode::Required)]
+        );
+        assert!(policy()
+            .check_update(&todo, &update(json!({"add_tags": ["work"]})))
+            .is_empty());
+        assert_eq!(
+            fields(policy().check_update(
+                &todo,
+                &update(json!({"tags": ["work"], "remove_tags": ["work"]}))
+            )),
+            [("tags".to_string(), FieldErrorCode::Required)]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! HTTP preconditions on todos: optimistic concurrency with `ETag` and `If-Match`, and
+//! conditional `GET` with `If-None-Match` and `If-Modified-Since`.
+//!
+//! `GET` and `PATCH /api/todos/:id` return the todo's entity tag, which changes with
+//! every write to it. A `PATCH` or `DELETE` carrying that tag in `If-Match` only goes
+//! through while the todo is still at that version and fails with 412 otherwise, so two
+//! clients editing the same note cannot silently overwrite each other. The repository
+//! checks the version under the row lock of the write itself.
+//!
+//! `If-Match: *` writes whatever the version, as does a request without the header.
+//! With `REQUIRE_IF_MATCH` set the header is mandatory and its absence answered with 428.
+//!
+//! `GET /api/todos/:id` and `GET /api/todos` also send `Last-Modified`, from `updated_at`,
+//! and answer 304 without a body when `If-None-Match` names the current tag or, without
+//! `If-None-Match`, nothing changed since `If-Modified-Since`. The tag of a single todo
+//! is its version, so a relation added from the other todo does not change it. A list is
+//! tagged by a hash of its body; its `Last-Modified` is the latest `updated_at` in it,
+//! which a deleted todo does not move, so pollers should prefer `If-None-Match`.
+
+use crate::domain::TodoId;
+use crate::{Todo, TodoError};
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreconditionConfig {
+    /// Refuse `PATCH` and `DELETE` of a todo without `If-Match`
+    pub require_if_match: bool,
+}
+
+impl PreconditionConfig {
+    /// Reads `REQUIRE_IF_MATCH`; the header is optional by default
+    pub fn from_env() -> Self {
+        Self {
+            require_if_match: std::env::var("REQUIRE_IF_MATCH")
+                .map(|value| matches!(value.as_str(), "1" | "true"))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Version `headers` require the todo `id` to be at before writing it; `None` when
+    /// any version will do
+    pub fn expected_version(
+        &self,
+        headers: &HeaderMap,
+        id: TodoId,
+    ) -> Result<Option<i32>, TodoError> {
+        let Some(value) = headers.get(header::IF_MATCH) else {
+            if self.require_if_match {
+                return Err(TodoError::PreconditionRequired(
+                    "If-Match with the todo's ETag is required".to_string(),
+                ));
+            }
+            return Ok(None);
+        };
+        let value = value
+            .to_str()
+            .map_err(|_| TodoError::Validation("If-Match is not valid ASCII".to_string()))?;
+        if value.trim() == "*" {
+            return Ok(None);
+        }
+        let mut versions: Vec<i32> = value
+            .split(',')
+            .filter_map(|tag| version_of(tag.trim(), id))
+            .collect();
+        versions.sort_unstable();
+        versions.dedup();
+        match versions[..] {
+            [version] => Ok(Some(version)),
+            [] => Err(TodoError::PreconditionFailed(format!(
+                "If-Match does not name a version of todo {}",
+                id
+            ))),
+            _ => Err(TodoError::Validation(
+                "If-Match names more than one version of the todo".to_string(),
+            )),
+        }
+    }
+}
+
+/// Fails unless the todo `id`, found at `version`, is at the `expected` one
+pub fn check_version(id: TodoId, version: i32, expected: Option<i32>) -> Result<(), TodoError> {
+    match expected {
+        Some(expected) if expected != version => Err(TodoError::PreconditionFailed(format!(
+            "Todo {} is at version {}, not {}",
+            id, version, expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Entity tag of a todo, `"<id>-<version>"`
+pub fn etag(todo: &Todo) -> String {
+    format!("\"{}-{}\"", todo.id, todo.version)
+}
+
+/// Headers of a response carrying `todo`
+pub fn etag_headers(todo: &Todo) -> HeaderMap {
+    validator_headers(&etag(todo), Some(todo.updated_at))
+}
+
+/// `ETag` and, when there is one, `Last-Modified`
+fn validator_headers(etag: &str, last_modified: Option<DateTime<Utc>>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(etag) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&http_date(last_modified)) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+    headers
+}
+
+/// `at` as an HTTP date, which has whole seconds
+pub fn http_date(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether a `GET` with `headers` can be answered with 304 for a representation tagged
+/// `etag` and last changed at `last_modified`. `If-Modified-Since` is only consulted
+/// without `If-None-Match`, and compares whole seconds.
+pub fn not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+        // Weak comparison: W/"x" and "x" are the same tag
+        let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return value.to_str().is_ok_and(|value| {
+            value.trim() == "*" || value.split(',').any(|tag| opaque(tag) == opaque(etag))
+        });
+    }
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+    match (since, last_modified) {
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// `GET /api/todos/:id` answering `request_headers`: `body` with the todo's validators,
+/// or 304 with only the validators
+pub fn conditional_todo(
+    request_headers: &HeaderMap,
+    todo: &Todo,
+    body: impl IntoResponse,
+) -> Response {
+    let headers = etag_headers(todo);
+    if not_modified(request_headers, &etag(todo), Some(todo.updated_at)) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (headers, body).into_response()
+}
+
+/// A list of `todos` answering `request_headers`, as `body` tagged by its hash, or 304
+pub fn conditional_list<'a>(
+    request_headers: &HeaderMap,
+    todos: impl IntoIterator<Item = &'a Todo>,
+    body: &impl Serialize,
+) -> Response {
+    let body = match serde_json::to_vec(body) {
+        Ok(body) => body,
+        Err(e) => return TodoError::from(e).into_response(),
+    };
+    let hash = Sha256::digest(&body);
+    let etag = format!("W/\"{}\"", hex::encode(&hash[..16]));
+    let last_modified = todos.into_iter().map(|todo| todo.updated_at).max();
+    let headers = validator_headers(&etag, last_modified);
+    if not_modified(request_headers, &etag, last_modified) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (headers, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Version in a strong entity tag of the todo `id`; weak tags never match `If-Match`
+fn version_of(tag: &str, id: TodoId) -> Option<i32> {
+    let (tag_id, version) = tag.strip_prefix('"')?.strip_suffix('"')?.rsplit_once('-')?;
+    (tag_id.parse::<TodoId>().ok()? == id)
+        .then(|| version.parse().ok())
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn if_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let modified = DateTime::parse_from_rfc3339("2026-05-01T10:00:00.700Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let request = |name: header::HeaderName, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        let check = |headers: HeaderMap| not_modified(&headers, "W/\"abc\"", Some(modified));
+
+        assert!(check(request(header::IF_NONE_MATCH, "\"abc\"")));
+        assert!(check(request(header::IF_NONE_MATCH, "\"x\", W/\"abc\"")));
+        assert!(!check(request(header::IF_NONE_MATCH, "\"abd\"")));
+        assert!(check(request(
+            header::IF_MODIFIED_SINCE,
+            &http_date(modified)
+        )));
+        assert!(!check(request(
+            header::IF_MODIFIED_SINCE,
+            "Fri, 01 May 2026 09:59:59 GMT"
+        )));
+        // If-None-Match wins over If-Modified-Since
+        let mut both = request(header::IF_NONE_MATCH, "\"old\"");
+        both.extend(request(header::IF_MODIFIED_SINCE, &http_date(modified)));
+        assert!(!check(both));
+        assert!(!check(HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_expected_version() {
+        let config = PreconditionConfig::default();
+        let id = TodoId::new();
+        let other = TodoId::new();
+        let expect = |value: &str| config.expected_version(&if_match(value), id);
+
+        assert_eq!(expect(&format!("\"{id}-3\"")).unwrap(), Some(3));
+        assert_eq!(
+            expect(&format!("\"{other}-1\", \"{id}-3\"")).unwrap(),
+            Some(3)
+        );
+        assert_eq!(expect(" * ").unwrap(), None);
+        assert!(matches!(
+            expect(&format!("W/\"{id}-3\"")),
+            Err(TodoError::PreconditionFailed(_))
+        ));
+        assert!(matches!(
+            expect(&format!("\"{other}-3\"")),
+            Err(TodoError::PreconditionFailed(_))
+        ));
+        assert!(matches!(
+            expect(&format!("\"{id}-3\", \"{id}-4\"")),
+            Err(TodoError::Validation(_))
+        ));
+
+        assert_eq!(
+            config.expected_version(&HeaderMap::new(), id).unwrap(),
+            None
+        );
+        let required = PreconditionConfig {
+            require_if_match: true,
+        };
+        assert!(matches!(
+            required.expected_version(&HeaderMap::new(), id),
+            Err(TodoError::PreconditionRequired(_))
+        ));
+    }
+}
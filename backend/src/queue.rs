@@ -0,0 +1,326 @@
+//! Durable task queue in Postgres.
+//!
+//! Work that should outlive the request that caused it goes into `task_queue`, and
+//! workers on every replica run it. A worker claims the oldest due task with
+//! `FOR UPDATE SKIP LOCKED` and keeps the row locked while the task runs, so no two
+//! workers run a task at once, and a task whose worker dies returns to the queue when
+//! its transaction aborts. Workers only claim kinds they have a handler for, so a
+//! replica of an older release leaves newer kinds alone during a rollout.
+//!
+//! A failed task is retried with exponential backoff. Once out of attempts it stays in the
+//! table as dead until `POST /api/admin/queue/:id/retry` queues it again or
+//! `DELETE /api/admin/queue/:id` drops it. `enqueue_at` schedules a task for later.
+
+use crate::DatabasePool;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+
+pub type QueueError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_WORKERS: usize = 2;
+/// Workers also wake up when this process enqueues; polling picks up other replicas' tasks
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A task running longer counts as failed
+const TASK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+/// Dead tasks listed by `GET /api/admin/queue`
+const DEAD_TASKS_SHOWN: i64 = 50;
+
+const TASK_COLUMNS: &str =
+    "id, kind, payload, attempts, max_attempts, run_at, last_error, dead_at, created_at";
+
+/// Runs the tasks of one kind
+#[async_trait]
+pub trait TaskHandler: Send + Sync {
+    async fn run(&self, payload: &serde_json::Value) -> Result<(), QueueError>;
+}
+
+/// Handlers by task kind
+pub type TaskHandlers = HashMap<String, Arc<dyn TaskHandler>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct Task {
+    #[schema(example = 42)]
+    pub id: i64,
+    #[schema(example = "publish_event")]
+    pub kind: String,
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    /// Failed runs so far
+    #[schema(example = 10)]
+    pub attempts: i32,
+    #[schema(example = 10)]
+    pub max_attempts: i32,
+    /// When the task is due next
+    pub run_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    /// Set once the task ran out of attempts
+    pub dead_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct QueueStatus {
+    /// Tasks due now, including those running
+    #[schema(example = 0)]
+    pub due: i64,
+    /// Tasks waiting for their `run_at`, such as retries
+    #[schema(example = 3)]
+    pub scheduled: i64,
+    #[schema(example = 1)]
+    pub dead: i64,
+    /// The most recently dead tasks, newest first
+    pub dead_tasks: Vec<Task>,
+}
+
+/// 5 seconds after the first failure, doubling up to an hour
+pub fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    FIRST_RETRY_DELAY
+        .saturating_mul(1 << doublings)
+        .min(MAX_RETRY_DELAY)
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskQueue {
+    pool: DatabasePool,
+    wake: Arc<Notify>,
+}
+
+impl TaskQueue {
+    pub fn new(pool: DatabasePool) -> Self {
+        Self {
+            pool,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Queues a task to run as soon as a worker is free; returns its id
+    pub async fn enqueue(
+        &self,
+        kind: &str,
+        payload: &serde_json::Value,
+    ) -> Result<i64, QueueError> {
+        self.enqueue_at(kind, payload, None).await
+    }
+
+    /// Queues a task that is not due before `run_at`
+    pub async fn enqueue_at(
+        &self,
+        kind: &str,
+        payload: &serde_json::Value,
+        run_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, QueueError> {
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO task_queue (kind, payload, run_at) VALUES ($1, $2, COALESCE($3, now())) RETURNING id",
+        )
+        .bind(kind)
+        .bind(payload)
+        .bind(run_at)
+        .fetch_one(&self.pool)
+        .await?;
+        if run_at.is_none() {
+            self.wake.notify_one();
+        }
+        Ok(id)
+    }
+
+    pub async fn status(&self) -> Result<QueueStatus, QueueError> {
+        let (due, scheduled, dead): (i64, i64, i64) = sqlx::query_as(
+            r#"
+            SELECT count(*) FILTER (WHERE dead_at IS NULL AND run_at <= now()),
+                   count(*) FILTER (WHERE dead_at IS NULL AND run_at > now()),
+                   count(*) FILTER (WHERE dead_at IS NOT NULL)
+            FROM task_queue
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let dead_tasks = sqlx::query_as::<_, Task>(&format!(
+            "SELECT {TASK_COLUMNS} FROM task_queue WHERE dead_at IS NOT NULL ORDER BY dead_at DESC, id DESC LIMIT $1"
+        ))
+        .bind(DEAD_TASKS_SHOWN)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(QueueStatus {
+            due,
+            scheduled,
+            dead,
+            dead_tasks,
+        })
+    }
+
+    /// Gives a dead task a fresh set of attempts, due now; `None` unless `id` is dead
+    pub async fn retry(&self, id: i64) -> Result<Option<Task>, QueueError> {
+        let task = sqlx::query_as::<_, Task>(&format!(
+            r#"
+            UPDATE task_queue SET dead_at = NULL, attempts = 0, run_at = now()
+            WHERE id = $1 AND dead_at IS NOT NULL
+            RETURNING {TASK_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        if task.is_some() {
+            self.wake.notify_one();
+        }
+        Ok(task)
+    }
+
+    /// Drops a dead task; false unless `id` is dead
+    pub async fn discard(&self, id: i64) -> Result<bool, QueueError> {
+        let result = sqlx::query("DELETE FROM task_queue WHERE id = $1 AND dead_at IS NOT NULL")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Starts `TASK_QUEUE_WORKERS` workers (default 2) for the kinds in `handlers`; none
+    /// without handlers
+    pub fn start(&self, handlers: TaskHandlers) {
+        if handlers.is_empty() {
+            return;
+        }
+        let workers = match std::env::var("TASK_QUEUE_WORKERS").ok() {
+            None => DEFAULT_WORKERS,
+            Some(value) => match value.parse::<usize>() {
+                Ok(workers) if workers > 0 => workers,
+                _ => {
+                    tracing::warn!(
+                        "Ignoring TASK_QUEUE_WORKERS={:?}; expected a positive number",
+                        value
+                    );
+                    DEFAULT_WORKERS
+                }
+            },
+        };
+        let mut kinds: Vec<String> = handlers.keys().cloned().collect();
+        kinds.sort();
+        tracing::info!(
+            "Starting {} task queue workers for {}",
+            workers,
+            kinds.join(", ")
+        );
+        let handlers = Arc::new(handlers);
+        for _ in 0..workers {
+            tokio::spawn(work(self.clone(), handlers.clone(), kinds.clone()));
+        }
+    }
+
+    /// Claims and runs one due task; false when there was none
+    async fn run_next(
+        &self,
+        handlers: &TaskHandlers,
+        kinds: &[String],
+    ) -> Result<bool, QueueError> {
+        let mut tx = self.pool.begin().await?;
+        let task = sqlx::query_as::<_, Task>(&format!(
+            r#"
+            SELECT {TASK_COLUMNS} FROM task_queue
+            WHERE dead_at IS NULL AND run_at <= now() AND kind = ANY($1)
+            ORDER BY run_at, id
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#
+        ))
+        .bind(kinds)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(task) = task else {
+            return Ok(false);
+        };
+
+        let result = match handlers.get(&task.kind) {
+            Some(handler) => tokio::time::timeout(TASK_TIMEOUT, handler.run(&task.payload))
+                .await
+                .unwrap_or_else(|_| Err("timed out".into())),
+            None => Err(format!("no handler for {}", task.kind).into()),
+        };
+        match result {
+            Ok(()) => {
+                sqlx::query("DELETE FROM task_queue WHERE id = $1")
+                    .bind(task.id)
+                    .execute(&mut *tx)
+                    .await?;
+                tracing::debug!("Task {} ({}) done", task.id, task.kind);
+            }
+            Err(e) => {
+                let attempts = task.attempts + 1;
+                let dead = attempts >= task.max_attempts;
+                let delay = retry_delay(attempts);
+                sqlx::query(
+                    r#"
+                    UPDATE task_queue
+                    SET attempts = $2, last_error = $3,
+                        run_at = clock_timestamp() + make_interval(secs => $4),
+                        dead_at = CASE WHEN $5 THEN clock_timestamp() END
+                    WHERE id = $1
+                    "#,
+                )
+                .bind(task.id)
+                .bind(attempts)
+                .bind(e.to_string())
+                .bind(delay.as_secs_f64())
+                .bind(dead)
+                .execute(&mut *tx)
+                .await?;
+                if dead {
+                    tracing::error!(
+                        "Task {} ({}) failed {} times and is dead: {}",
+                        task.id,
+                        task.kind,
+                        attempts,
+                        e
+                    );
+                } else {
+                    tracing::warn!(
+                        "Task {} ({}) failed, retrying in {:?}: {}",
+                        task.id,
+                        task.kind,
+                        delay,
+                        e
+                    );
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+async fn work(queue: TaskQueue, handlers: Arc<TaskHandlers>, kinds: Vec<String>) {
+    loop {
+        match queue.run_next(&handlers, &kinds).await {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => tracing::error!("Task queue worker failed: {}", e),
+        }
+        tokio::select! {
+            _ = queue.wake.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::from_secs(5));
+        assert_eq!(retry_delay(2), Duration::from_secs(10));
+        assert_eq!(retry_delay(5), Duration::from_secs(80));
+        assert_eq!(retry_delay(11), Duration::from_secs(60 * 60));
+        assert_eq!(retry_delay(i32::MAX), Duration::from_secs(60 * 60));
+    }
+}
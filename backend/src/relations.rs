@@ -0,0 +1,332 @@
+//! Typed links between todos at `/api/todos/:id/relations`.
+//!
+//! A relation reads from the todo it was created on: A `relates_to` B, A `duplicates` B,
+//! A was `caused_by` B. Relations only describe; unlike a blocking dependency they never
+//! keep a todo from being completed. Two todos have at most one relation of each kind,
+//! whichever side it was created from, and deleting either todo removes it.
+//! `GET /api/todos/:id` lists the relations of the todo from its side in `relations`.
+
+use crate::domain::{TodoId, TodoTitle};
+use crate::errors::ErrorCode;
+use crate::{ApiResponse, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Kind of a relation; the same as the `todo_relation_kind` enum in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "todo_relation_kind", rename_all = "snake_case")]
+pub enum RelationKind {
+    RelatesTo,
+    /// The todo repeats the related one
+    Duplicates,
+    /// The todo exists because of the related one, e.g. a bug caused by a change
+    CausedBy,
+    /// The todo was moved out of the related one by `POST /api/todos/:id/split` or
+    /// `/extract-section`
+    SplitFrom,
+}
+
+/// A stored relation: `todo_id` `kind` `related_id`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct TodoRelation {
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Uuid,
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8e")]
+    pub todo_id: TodoId,
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8f")]
+    pub related_id: TodoId,
+    pub kind: RelationKind,
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl TodoRelation {
+    pub fn new(todo_id: TodoId, related_id: TodoId, kind: RelationKind) -> Self {
+        Self {
+            id: Uuid::now_v7(),
+            todo_id,
+            related_id,
+            kind,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Which side of a relation a todo is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationDirection {
+    /// Created on this todo: this todo duplicates the other one
+    Outgoing,
+    /// Created on the other todo: the other todo duplicates this one
+    Incoming,
+}
+
+/// A relation as seen from one of its todos
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RelationSummary {
+    /// Relation ID, for deleting it
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Uuid,
+    pub kind: RelationKind,
+    pub direction: RelationDirection,
+    /// The todo on the other side
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8f")]
+    pub todo_id: TodoId,
+    #[schema(value_type = String, example = "Login fails on Safari")]
+    pub title: TodoTitle,
+    #[schema(example = false)]
+    pub completed: bool,
+}
+
+impl RelationSummary {
+    /// `relation` from the side of `todo_id`; `title` and `completed` are the other todo's
+    pub fn new(
+        relation: &TodoRelation,
+        todo_id: TodoId,
+        title: TodoTitle,
+        completed: bool,
+    ) -> Self {
+        let (direction, other) = if relation.todo_id == todo_id {
+            (RelationDirection::Outgoing, relation.related_id)
+        } else {
+            (RelationDirection::Incoming, relation.todo_id)
+        };
+        Self {
+            id: relation.id,
+            kind: relation.kind,
+            direction,
+            todo_id: other,
+            title,
+            completed,
+        }
+    }
+}
+
+/// A relation with the title and state of the todo on the other side
+#[derive(Debug, sqlx::FromRow)]
+pub struct RelationRow {
+    #[sqlx(flatten)]
+    pub relation: TodoRelation,
+    pub title: TodoTitle,
+    pub completed: bool,
+}
+
+impl RelationRow {
+    pub fn summary(self, todo_id: TodoId) -> RelationSummary {
+        RelationSummary::new(&self.relation, todo_id, self.title, self.completed)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CreateRelationRequest {
+    /// The todo on the other side
+    #[schema(value_type = Uuid, example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8f")]
+    pub related_id: TodoId,
+    pub kind: RelationKind,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RelationResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<RelationSummary>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<RelationSummary>> for RelationResponse {
+    fn from(response: ApiResponse<RelationSummary>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RelationListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<RelationSummary>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<RelationSummary>>> for RelationListResponse {
+    fn from(response: ApiResponse<Vec<RelationSummary>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}/relations",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    responses(
+        (status = 200, description = "Relations of the todo, oldest first", body = RelationListResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn list_relations<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<TodoId>,
+) -> Result<Json<RelationListResponse>, TodoError> {
+    if repository.get_todo_by_id(id).await?.is_none() {
+        return Err(TodoError::NotFound(format!("Todo {}", id)));
+    }
+    match repository.relations_of(id).await {
+        Ok(relations) => Ok(Json(ApiResponse::success(relations).into())),
+        Err(e) => {
+            tracing::error!("Failed to list relations of todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos/{id}/relations",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    request_body = CreateRelationRequest,
+    responses(
+        (status = 200, description = "Relation created", body = RelationResponse),
+        (status = 400, description = "A todo cannot relate to itself", body = ErrorResponse),
+        (status = 404, description = "Either todo not found", body = ErrorResponse),
+        (status = 409, description = "The todos already have a relation of that kind", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn create_relation<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<TodoId>,
+    Json(request): Json<CreateRelationRequest>,
+) -> Result<Json<RelationResponse>, TodoError> {
+    if request.related_id == id {
+        tracing::warn!("Todo {} cannot relate to itself", id);
+        return Err(TodoError::Validation(
+            "A todo cannot relate to itself".to_string(),
+        ));
+    }
+    if repository.get_todo_by_id(id).await?.is_none() {
+        return Err(TodoError::NotFound(format!("Todo {}", id)));
+    }
+    let Some(related) = repository.get_todo_by_id(request.related_id).await? else {
+        tracing::warn!("Related todo not found with id: {}", request.related_id);
+        return Err(TodoError::NotFound(format!("Todo {}", request.related_id)));
+    };
+
+    let relation = TodoRelation::new(id, request.related_id, request.kind);
+    match repository.create_relation(&relation).await {
+        Ok(Some(relation)) => {
+            tracing::info!(
+                "Related todo {} to {} ({:?})",
+                id,
+                relation.related_id,
+                relation.kind
+            );
+            let summary = RelationSummary::new(&relation, id, related.title, related.completed);
+            Ok(Json(ApiResponse::success(summary).into()))
+        }
+        Ok(None) => {
+            tracing::warn!(
+                "Todos {} and {} already have a {:?} relation",
+                id,
+                request.related_id,
+                request.kind
+            );
+            Err(TodoError::Conflict(format!(
+                "Todos {} and {} are already related that way",
+                id, request.related_id
+            )))
+        }
+        Err(e) => {
+            tracing::error!("Failed to relate todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+/// Works from either todo of the relation
+#[utoipa::path(
+    delete,
+    path = "/api/todos/{id}/relations/{relation_id}",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID"),
+        ("relation_id" = Uuid, Path, description = "Relation ID")
+    ),
+    responses(
+        (status = 204, description = "Relation deleted"),
+        (status = 404, description = "The todo has no such relation", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn delete_relation<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path((id, relation_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, TodoError> {
+    let id = TodoId::from(id);
+    match repository.delete_relation(id, relation_id).await {
+        Ok(true) => {
+            tracing::info!("Deleted relation {} of todo {}", relation_id, id);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(TodoError::NotFound(format!(
+            "Relation {} of todo {}",
+            relation_id, id
+        ))),
+        Err(e) => {
+            tracing::error!("Failed to delete relation {}: {}", relation_id, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_sides() {
+        let (a, b) = (TodoId::new(), TodoId::new());
+        let relation = TodoRelation::new(a, b, RelationKind::Duplicates);
+
+        let title = |title: &str| TodoTitle::new(title).unwrap();
+        let from_a = RelationSummary::new(&relation, a, title("B"), true);
+        assert_eq!(from_a.direction, RelationDirection::Outgoing);
+        assert_eq!((from_a.todo_id, from_a.completed), (b, true));
+
+        let from_b = RelationSummary::new(&relation, b, title("A"), false);
+        assert_eq!(from_b.direction, RelationDirection::Incoming);
+        assert_eq!(from_b.todo_id, a);
+        assert_eq!(from_b.id, relation.id);
+    }
+}
@@ -0,0 +1,290 @@
+//! Defaults sized to the CPU and memory the server may actually use.
+//!
+//! In a container the host's core count and memory say little about what the process
+//! gets, so at startup the backend reads the cgroup (v2, else v1) CPU quota and memory
+//! limit and derives the database pool size, the number of Tokio worker threads and the
+//! largest request body it accepts. `DATABASE_MAX_CONNECTIONS`, `TOKIO_WORKER_THREADS`
+//! and `MAX_BODY_BYTES` override the derived values. Without a limit the CPUs Rust
+//! reports are used and the body limit stays at axum's 2 MiB.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// cgroup v1 reports "no limit" as the largest page-aligned `i64` or close to it
+const UNLIMITED_V1_BYTES: u64 = 1 << 62;
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+const MIN_BODY_BYTES: usize = 1024 * 1024;
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+/// A request body may take at most this share of the memory limit
+const MEMORY_PER_BODY: u64 = 128;
+const CONNECTIONS_PER_CPU: u32 = 4;
+const MIN_CONNECTIONS: u32 = 4;
+const MAX_CONNECTIONS: u32 = 32;
+
+/// Request extension carrying `AppConfig::max_body_bytes` to middleware that reads bodies
+#[derive(Debug, Clone, Copy)]
+pub struct MaxBodyBytes(pub usize);
+
+/// CPU and memory available to the process
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceLimits {
+    /// CPU quota in cores, or the CPUs Rust reports without one
+    pub cpus: f64,
+    /// Memory limit in bytes, `None` when unlimited
+    pub memory_bytes: Option<u64>,
+    /// Whether either value came from a cgroup limit
+    pub from_cgroup: bool,
+}
+
+impl ResourceLimits {
+    pub fn detect() -> Self {
+        let available = std::thread::available_parallelism()
+            .map(|cpus| cpus.get())
+            .unwrap_or(1) as f64;
+        let cgroups = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        let quota = cpu_quota(&cgroups);
+        let memory_bytes = memory_limit(&cgroups);
+        Self {
+            // A quota above the CPUs the process may run on cannot be used
+            cpus: quota.map_or(available, |quota| quota.min(available)),
+            memory_bytes,
+            from_cgroup: quota.is_some() || memory_bytes.is_some(),
+        }
+    }
+}
+
+impl fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CPUs {}", self.cpus)?;
+        match self.memory_bytes {
+            Some(bytes) => write!(f, ", memory {}", mebibytes(bytes as usize))?,
+            None => write!(f, ", memory unlimited")?,
+        }
+        if self.from_cgroup {
+            write!(f, " (cgroup)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Values the server runs with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuning {
+    pub database_max_connections: u32,
+    pub worker_threads: usize,
+    pub max_body_bytes: usize,
+}
+
+impl Tuning {
+    /// Four connections per CPU between 4 and 32, a worker thread per whole CPU, and
+    /// 1/128 of the memory limit per body between 1 and 16 MiB
+    pub fn derive(limits: &ResourceLimits) -> Self {
+        let cpus = limits.cpus.ceil().max(1.0) as u32;
+        let max_body_bytes = match limits.memory_bytes {
+            Some(bytes) => {
+                ((bytes / MEMORY_PER_BODY) as usize).clamp(MIN_BODY_BYTES, MAX_BODY_BYTES)
+            }
+            None => DEFAULT_MAX_BODY_BYTES,
+        };
+        Self {
+            database_max_connections: (cpus * CONNECTIONS_PER_CPU)
+                .clamp(MIN_CONNECTIONS, MAX_CONNECTIONS),
+            worker_threads: cpus as usize,
+            max_body_bytes,
+        }
+    }
+
+    /// Derived from the detected limits, with overrides from the environment; logs the
+    /// values chosen and where they came from
+    pub fn from_env() -> Self {
+        let limits = ResourceLimits::detect();
+        let derived = Self::derive(&limits);
+        let connections = env_override("DATABASE_MAX_CONNECTIONS");
+        let threads = env_override("TOKIO_WORKER_THREADS");
+        let body_bytes = env_override("MAX_BODY_BYTES");
+        let tuning = Self {
+            database_max_connections: connections.unwrap_or(derived.database_max_connections),
+            worker_threads: threads.unwrap_or(derived.worker_threads),
+            max_body_bytes: body_bytes.unwrap_or(derived.max_body_bytes),
+        };
+        let source = |overridden: bool| if overridden { "set" } else { "derived" };
+        tracing::info!(
+            "Resources: {}; database pool {} connections ({}), {} worker threads ({}), request bodies up to {} ({})",
+            limits,
+            tuning.database_max_connections,
+            source(connections.is_some()),
+            tuning.worker_threads,
+            source(threads.is_some()),
+            mebibytes(tuning.max_body_bytes),
+            source(body_bytes.is_some()),
+        );
+        tuning
+    }
+}
+
+/// A positive number from `name`; anything else is ignored with a warning
+fn env_override<T: std::str::FromStr + Default + PartialOrd>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok().filter(|value| !value.is_empty())?;
+    match value.parse::<T>() {
+        Ok(parsed) if parsed > T::default() => Some(parsed),
+        _ => {
+            tracing::warn!("Ignoring {}={:?}; expected a positive number", name, value);
+            None
+        }
+    }
+}
+
+fn mebibytes(bytes: usize) -> String {
+    if bytes < 1024 * 1024 {
+        return format!("{bytes} bytes");
+    }
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Directory of `controller` for this process, given the contents of `/proc/self/cgroup`.
+/// v2 lists a single `0::/path` line; v1 one line per hierarchy, e.g. `4:memory:/path`.
+fn cgroup_dir(cgroups: &str, controller: Option<&str>) -> Option<PathBuf> {
+    cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let path = path.trim_start_matches('/');
+        match controller {
+            None if controllers.is_empty() => Some(Path::new(CGROUP_ROOT).join(path)),
+            Some(controller) if controllers.split(',').any(|name| name == controller) => {
+                Some(Path::new(CGROUP_ROOT).join(controller).join(path))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// First readable `file` in `dir` or, inside a cgroup namespace, the hierarchy root
+fn read_cgroup_file(dir: Option<PathBuf>, root: PathBuf, file: &str) -> Option<String> {
+    dir.into_iter()
+        .chain([root])
+        .find_map(|dir| std::fs::read_to_string(dir.join(file)).ok())
+}
+
+fn cpu_quota(cgroups: &str) -> Option<f64> {
+    if let Some(cpu_max) = read_cgroup_file(
+        cgroup_dir(cgroups, None),
+        PathBuf::from(CGROUP_ROOT),
+        "cpu.max",
+    ) {
+        return parse_cpu_max(&cpu_max);
+    }
+    let dir = cgroup_dir(cgroups, Some("cpu"));
+    let root = Path::new(CGROUP_ROOT).join("cpu");
+    let quota = read_cgroup_file(dir.clone(), root.clone(), "cpu.cfs_quota_us")?;
+    let period = read_cgroup_file(dir, root, "cpu.cfs_period_us")?;
+    parse_cfs_quota(&quota, &period)
+}
+
+fn memory_limit(cgroups: &str) -> Option<u64> {
+    if let Some(memory_max) = read_cgroup_file(
+        cgroup_dir(cgroups, None),
+        PathBuf::from(CGROUP_ROOT),
+        "memory.max",
+    ) {
+        return parse_memory_max(&memory_max);
+    }
+    let limit = read_cgroup_file(
+        cgroup_dir(cgroups, Some("memory")),
+        Path::new(CGROUP_ROOT).join("memory"),
+        "memory.limit_in_bytes",
+    )?;
+    limit
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|bytes| *bytes < UNLIMITED_V1_BYTES)
+}
+
+/// cgroup v2 `cpu.max`: `$QUOTA $PERIOD`, with `max` for no quota
+pub fn parse_cpu_max(contents: &str) -> Option<f64> {
+    let mut fields = contents.split_whitespace();
+    let quota = fields.next()?.parse::<f64>().ok()?;
+    let period = fields.next().unwrap_or("100000").parse::<f64>().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// cgroup v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us`, with a quota of -1 for none
+pub fn parse_cfs_quota(quota: &str, period: &str) -> Option<f64> {
+    let quota = quota.trim().parse::<f64>().ok()?;
+    let period = period.trim().parse::<f64>().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// cgroup v2 `memory.max`: bytes, or `max` for no limit
+pub fn parse_memory_max(contents: &str) -> Option<u64> {
+    contents.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_parse_cgroup_limits() {
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+        assert_eq!(parse_cfs_quota("50000\n", "100000\n"), Some(0.5));
+        assert_eq!(parse_memory_max("max\n"), None);
+        assert_eq!(parse_memory_max("536870912\n"), Some(512 * MIB));
+        assert_eq!(
+            cgroup_dir(
+                "4:memory:/docker/abc\n1:cpu,cpuacct:/docker/abc\n",
+                Some("cpu")
+            ),
+            Some(PathBuf::from("/sys/fs/cgroup/cpu/docker/abc"))
+        );
+        assert_eq!(
+            cgroup_dir("0::/system.slice/md-todo.service\n", None),
+            Some(PathBuf::from("/sys/fs/cgroup/system.slice/md-todo.service"))
+        );
+        assert_eq!(cgroup_dir("0::/\n", Some("memory")), None);
+    }
+
+    #[test]
+    fn test_derive_tuning() {
+        let small = ResourceLimits {
+            cpus: 0.5,
+            memory_bytes: Some(256 * MIB),
+            from_cgroup: true,
+        };
+        assert_eq!(
+            Tuning::derive(&small),
+            Tuning {
+                database_max_connections: 4,
+                worker_threads: 1,
+                max_body_bytes: 2 * MIB as usize,
+            }
+        );
+        let large = ResourceLimits {
+            cpus: 16.0,
+            memory_bytes: Some(64 * 1024 * MIB),
+            from_cgroup: true,
+        };
+        assert_eq!(
+            Tuning::derive(&large),
+            Tuning {
+                database_max_connections: 32,
+                worker_threads: 16,
+                max_body_bytes: 16 * MIB as usize,
+            }
+        );
+        let unlimited = ResourceLimits {
+            cpus: 3.0,
+            memory_bytes: None,
+            from_cgroup: false,
+        };
+        let tuning = Tuning::derive(&unlimited);
+        assert_eq!(tuning.database_max_connections, 12);
+        assert_eq!(tuning.max_body_bytes, DEFAULT_MAX_BODY_BYTES);
+    }
+}
@@ -0,0 +1,418 @@
+//! Searching todos with `GET /api/search`.
+//!
+//! By default `q` is matched word by word against the title and content with Postgres
+//! full-text search (`websearch_to_tsquery`, so quoted phrases and `-word` work) and hits
+//! are ranked by `ts_rank`. Each todo is indexed with the text search configuration of
+//! its detected language (see `language`), so `tests` also finds "test" in English todos;
+//! `q` is parsed with every configuration, as the language of the hits is not known. With `fuzzy=true` only
+//! titles are compared, by `pg_trgm` word similarity, so `grocries` still finds
+//! "Buy groceries"; titles scoring below `MIN_SIMILARITY` are left out. Either way every
+//! hit carries its score and the best come first. Content stored compressed is not
+//! searched, only its title.
+//!
+//! Chinese, Japanese and Korean leave no spaces between words, so full-text search only
+//! finds whole runs of such text. `SEARCH_CJK` hands queries containing any of it to an
+//! extension that matches substrings instead: `pg_bigm` (`LIKE` on title and content,
+//! scored by `bigm_similarity`) or `pgroonga` (its `&@~` operator, scored by
+//! `pgroonga_score`). The extension and its index come from the matching script in
+//! `database/optional`.
+//!
+//! Repositories without Postgres use `search_in`, which applies the same rules in memory
+//! with a simpler word match that does not stem and a trigram similarity close to `pg_trgm`'s.
+//! Words with CJK characters match anywhere in the text there, as with `SEARCH_CJK`.
+
+use crate::errors::ErrorCode;
+use crate::language::{self, Language};
+use crate::{ApiResponse, Todo, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+/// Lowest word similarity of a fuzzy hit; typos in short words land around 0.5
+pub const MIN_SIMILARITY: f32 = 0.4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Compare titles by trigram similarity instead of matching words
+    pub fuzzy: bool,
+    pub limit: i64,
+}
+
+impl SearchQuery {
+    /// Reads `q`, `fuzzy` and `limit`
+    pub fn from_query(params: &HashMap<String, String>) -> Result<Self, String> {
+        let text = params
+            .get("q")
+            .map(|q| q.trim())
+            .filter(|q| !q.is_empty())
+            .ok_or("q must not be empty")?
+            .to_string();
+        let fuzzy = match params.get("fuzzy").map(String::as_str) {
+            None | Some("false") | Some("0") => false,
+            Some("true") | Some("1") => true,
+            Some(other) => return Err(format!("fuzzy must be true or false, got {:?}", other)),
+        };
+        let limit = match params.get("limit") {
+            None => DEFAULT_LIMIT,
+            Some(limit) => match limit.parse::<i64>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+                _ => {
+                    return Err(format!(
+                        "limit must be between 1 and {}, got {:?}",
+                        MAX_LIMIT, limit
+                    ))
+                }
+            },
+        };
+        Ok(Self { text, fuzzy, limit })
+    }
+}
+
+/// How `DatabaseTodoRepository` searches for text in Chinese, Japanese or Korean
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CjkSearch {
+    /// Full-text search, like any other query
+    #[default]
+    Off,
+    /// `LIKE` per word or phrase, served by a `gin_bigm_ops` index
+    Bigm,
+    /// PGroonga's `&@~`, which reads `q` much like `websearch_to_tsquery` does
+    Pgroonga,
+}
+
+impl CjkSearch {
+    /// Reads `SEARCH_CJK`: `off` (default), `pg_bigm` or `pgroonga`
+    pub fn from_env() -> Self {
+        match std::env::var("SEARCH_CJK") {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                tracing::warn!(
+                    "Ignoring SEARCH_CJK={:?}, expected off, pg_bigm or pgroonga",
+                    value
+                );
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Whether `query` is searched with the extension rather than full-text search
+    pub fn applies_to(self, query: &SearchQuery) -> bool {
+        self != Self::Off && !query.fuzzy && language::has_cjk(&query.text)
+    }
+}
+
+impl FromStr for CjkSearch {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "pg_bigm" => Ok(Self::Bigm),
+            "pgroonga" => Ok(Self::Pgroonga),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A word or quoted phrase of `q`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub text: String,
+    /// Written with a leading `-`: hits must not contain it
+    pub exclude: bool,
+}
+
+/// Words and `"quoted phrases"` of `text`, each optionally preceded by `-`
+pub fn terms(text: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (exclude, term) = match rest.strip_prefix('-') {
+            Some(term) => (true, term),
+            None => (false, rest),
+        };
+        let (term, after) = match term.strip_prefix('"') {
+            Some(phrase) => match phrase.find('"') {
+                Some(end) => (&phrase[..end], &phrase[end + 1..]),
+                None => (phrase, ""),
+            },
+            None => {
+                let end = term.find(char::is_whitespace).unwrap_or(term.len());
+                term.split_at(end)
+            }
+        };
+        let term = term.trim();
+        if !term.is_empty() {
+            terms.push(Term {
+                text: term.to_string(),
+                exclude,
+            });
+        }
+        rest = after.trim_start();
+    }
+    terms
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub todo: Todo,
+    /// Language detected for the todo, which decides how its words were indexed
+    pub language: Language,
+    /// `ts_rank` of a word search or title similarity (0 to 1) of a fuzzy one; only
+    /// comparable within one response
+    #[schema(example = 0.58)]
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SearchResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    /// Best match first
+    pub data: Option<Vec<SearchHit>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<SearchHit>>> for SearchResponse {
+    fn from(response: ApiResponse<Vec<SearchHit>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(
+        ("q" = String, Query, description = "Words to look for; quoted phrases and -word are supported unless fuzzy"),
+        ("fuzzy" = Option<bool>, Query, description = "Match titles by trigram similarity, tolerating typos"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of hits (1-100, default 20)")
+    ),
+    responses(
+        (status = 200, description = "Matching todos, best first", body = SearchResponse),
+        (status = 400, description = "Missing q or invalid fuzzy or limit", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn search_todos<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<SearchResponse>, TodoError> {
+    let query = SearchQuery::from_query(&params).map_err(|e| {
+        tracing::warn!("Invalid search: {}", e);
+        TodoError::Validation(e.to_string())
+    })?;
+    tracing::info!("Searching todos for {:?}", query);
+    match repository.search_todos(&query).await {
+        Ok(hits) => {
+            tracing::info!("Found {} todos", hits.len());
+            Ok(Json(ApiResponse::success(hits).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to search todos: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Lowercased alphanumeric runs, the words of both `pg_trgm` and the `simple` configuration
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Trigrams of every word in order, each word padded with two spaces in front and one
+/// behind as `pg_trgm` does
+fn trigram_list(text: &str) -> Vec<[char; 3]> {
+    words(text)
+        .flat_map(|word| {
+            let padded: Vec<char> = "  ".chars().chain(word.chars()).chain([' ']).collect();
+            padded
+                .windows(3)
+                .map(|window| [window[0], window[1], window[2]])
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn jaccard(a: &HashSet<[char; 3]>, b: &HashSet<[char; 3]>) -> f32 {
+    let shared = a.intersection(b).count();
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        0.0
+    } else {
+        shared as f32 / union as f32
+    }
+}
+
+/// Shared trigrams over all trigrams of the two texts, like `similarity()`
+pub fn similarity(a: &str, b: &str) -> f32 {
+    jaccard(
+        &trigram_list(a).into_iter().collect(),
+        &trigram_list(b).into_iter().collect(),
+    )
+}
+
+/// Best similarity between `query` and any continuous run of the trigrams of `text`,
+/// like `word_similarity()`
+pub fn word_similarity(query: &str, text: &str) -> f32 {
+    let query: HashSet<_> = trigram_list(query).into_iter().collect();
+    let text = trigram_list(text);
+    let mut best = 0.0f32;
+    for start in 0..text.len() {
+        // Runs have to start and end on a shared trigram to be the best one
+        if !query.contains(&text[start]) {
+            continue;
+        }
+        let mut run = HashSet::new();
+        for trigram in &text[start..] {
+            run.insert(*trigram);
+            if query.contains(trigram) {
+                best = best.max(jaccard(&query, &run));
+            }
+        }
+    }
+    best
+}
+
+/// Score of `todo` for `query`, or `None` when it does not match. Every word match scores 1.
+pub fn score(query: &SearchQuery, todo: &Todo) -> Option<f32> {
+    if query.fuzzy {
+        let similarity = word_similarity(&query.text, &todo.title);
+        return (similarity >= MIN_SIMILARITY).then_some(similarity);
+    }
+    let document: HashSet<String> = words(&todo.title).chain(words(&todo.content)).collect();
+    let text = format!("{} {}", todo.title, todo.content).to_lowercase();
+    let mut wanted = words(&query.text).peekable();
+    wanted.peek()?;
+    wanted
+        .all(|word| document.contains(&word) || (language::has_cjk(&word) && text.contains(&word)))
+        .then_some(1.0)
+}
+
+/// Hits among `todos`, best first and newest first among equals, at most `query.limit`
+pub fn search_in<'a>(
+    query: &SearchQuery,
+    todos: impl IntoIterator<Item = &'a Todo>,
+) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = todos
+        .into_iter()
+        .filter_map(|todo| {
+            score(query, todo).map(|score| SearchHit {
+                todo: todo.clone(),
+                language: language::detect(&format!("{} {}", todo.title, todo.content)),
+                score,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.todo.id.cmp(&a.todo.id)));
+    hits.truncate(query.limit as usize);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_query_from_query() {
+        let query = SearchQuery::from_query(&params(&[("q", " milk "), ("fuzzy", "true")]));
+        assert_eq!(
+            query,
+            Ok(SearchQuery {
+                text: "milk".to_string(),
+                fuzzy: true,
+                limit: DEFAULT_LIMIT,
+            })
+        );
+        assert!(SearchQuery::from_query(&params(&[("q", "  ")])).is_err());
+        assert!(SearchQuery::from_query(&params(&[("q", "milk"), ("fuzzy", "yes")])).is_err());
+        assert!(SearchQuery::from_query(&params(&[("q", "milk"), ("limit", "101")])).is_err());
+    }
+
+    #[test]
+    fn test_cjk_search() {
+        assert_eq!("pg_bigm".parse(), Ok(CjkSearch::Bigm));
+        assert_eq!(" PGroonga ".parse(), Ok(CjkSearch::Pgroonga));
+        assert_eq!("".parse(), Ok(CjkSearch::Off));
+        assert!("mecab".parse::<CjkSearch>().is_err());
+
+        let query = |text: &str, fuzzy: bool| SearchQuery {
+            text: text.to_string(),
+            fuzzy,
+            limit: DEFAULT_LIMIT,
+        };
+        assert!(CjkSearch::Bigm.applies_to(&query("牛乳", false)));
+        assert!(!CjkSearch::Bigm.applies_to(&query("牛乳", true)));
+        assert!(!CjkSearch::Bigm.applies_to(&query("milk", false)));
+        assert!(!CjkSearch::Off.applies_to(&query("牛乳", false)));
+    }
+
+    #[test]
+    fn test_terms() {
+        let term = |text: &str, exclude: bool| Term {
+            text: text.to_string(),
+            exclude,
+        };
+        assert_eq!(
+            terms(r#" 牛乳  "買い 物" -豆乳 "unclosed"#),
+            vec![
+                term("牛乳", false),
+                term("買い 物", false),
+                term("豆乳", true),
+                term("unclosed", false),
+            ]
+        );
+        assert_eq!(terms(r#"- "" "#), vec![]);
+    }
+
+    #[test]
+    fn test_search_in_matches_cjk_inside_words() {
+        let todo = Todo::new_with_validation("牛乳を買う", "").unwrap();
+        let query = SearchQuery {
+            text: "牛乳".to_string(),
+            fuzzy: false,
+            limit: DEFAULT_LIMIT,
+        };
+        assert_eq!(search_in(&query, [&todo]).len(), 1);
+    }
+
+    #[test]
+    fn test_trigram_similarity_matches_pg_trgm() {
+        // Values returned by PostgreSQL 15 for the same arguments
+        assert!((word_similarity("grocries", "Buy groceries for the week") - 0.583).abs() < 0.001);
+        assert!(
+            (word_similarity("documntation", "Complete project documentation") - 0.6875).abs()
+                < 0.001
+        );
+        assert!((similarity("grocries", "Buy groceries") - 0.4375).abs() < 0.001);
+        assert_eq!(word_similarity("Milk", "milk"), 1.0);
+        assert_eq!(word_similarity("zebra", "Buy groceries"), 0.0);
+    }
+}
@@ -0,0 +1,357 @@
+//! Smoke test of a freshly booted server, behind `md-todo-backend selftest`.
+//!
+//! Deployment pipelines run it with the production configuration before switching traffic:
+//! the app is wired against the configured database, broker and mail server exactly as
+//! `serve` would, then `run` sends it requests in-process, without binding a port. It waits
+//! for `/health/ready` and walks a temporary todo through create, read, update and delete,
+//! checking it is gone afterwards. When a step fails the todo is still deleted if it was
+//! created. With `E2EE_MODE=required` plaintext todos are refused, so the same steps go
+//! through `/api/encrypted-todos` instead.
+//!
+//! The temporary todo is written like any other, so event consumers see it come and go;
+//! its title starts with `SELFTEST_TITLE`.
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::{Duration, Instant};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+pub const SELFTEST_TITLE: &str = "md-todo selftest";
+
+/// Endpoints the temporary record goes through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Todos,
+    /// For `E2EE_MODE=required`
+    EncryptedTodos,
+}
+
+impl Target {
+    fn path(self) -> &'static str {
+        match self {
+            Self::Todos => "/api/todos",
+            Self::EncryptedTodos => "/api/encrypted-todos",
+        }
+    }
+
+    fn create_body(self) -> Value {
+        match self {
+            Self::Todos => json!({
+                "title": format!("{} {}", SELFTEST_TITLE, Uuid::new_v4()),
+                "content": "Created by `md-todo-backend selftest`; deleted right after.",
+            }),
+            Self::EncryptedTodos => encrypted_body("Y3JlYXRl", None),
+        }
+    }
+
+    /// Request changing the record read as `current`
+    fn update(self, current: &Value) -> (&'static str, Value) {
+        match self {
+            Self::Todos => ("PATCH", json!({ "completed": true })),
+            Self::EncryptedTodos => (
+                "PUT",
+                encrypted_body("dXBkYXRl", current["version"].as_i64()),
+            ),
+        }
+    }
+
+    fn updated(self, before: &Value, after: &Value) -> bool {
+        match self {
+            Self::Todos => after["completed"] == json!(true),
+            Self::EncryptedTodos => after["version"].as_i64() > before["version"].as_i64(),
+        }
+    }
+}
+
+/// The server only stores ciphertext, so any bytes do
+fn encrypted_body(ciphertext: &str, version: Option<i64>) -> Value {
+    json!({
+        "key_fingerprint": "0".repeat(64),
+        "algorithm": "selftest",
+        "nonce": "AAAAAAAAAAAAAAAA",
+        "ciphertext": ciphertext,
+        "version": version,
+    })
+}
+
+/// Outcome of one step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub name: &'static str,
+    pub passed: bool,
+    pub elapsed: Duration,
+    /// What went wrong, or for `ready` the optional dependencies that are down
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelfTestReport {
+    pub steps: Vec<Step>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.passed)
+    }
+
+    fn record<T>(
+        &mut self,
+        name: &'static str,
+        started: Instant,
+        result: Result<T, String>,
+    ) -> Option<T> {
+        let elapsed = started.elapsed();
+        let (passed, detail, value) = match result {
+            Ok(value) => (true, None, Some(value)),
+            Err(detail) => (false, Some(detail), None),
+        };
+        self.steps.push(Step {
+            name,
+            passed,
+            elapsed,
+            detail,
+        });
+        value
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            write!(
+                f,
+                "{:<4}  {:<8}  {:>5} ms",
+                if step.passed { "ok" } else { "FAIL" },
+                step.name,
+                step.elapsed.as_millis()
+            )?;
+            if let Some(detail) = &step.detail {
+                write!(f, "  {detail}")?;
+            }
+            writeln!(f)?;
+        }
+        let failed = self.steps.iter().filter(|step| !step.passed).count();
+        if failed == 0 {
+            writeln!(f, "Self-test passed")
+        } else {
+            writeln!(
+                f,
+                "Self-test failed: {failed} of {} steps",
+                self.steps.len()
+            )
+        }
+    }
+}
+
+/// Sends a request and returns the status and the body as JSON
+async fn request(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> Result<(StatusCode, Value), String> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if method == "PATCH" || method == "DELETE" {
+        // Satisfies REQUIRE_IF_MATCH; nothing else writes the temporary todo
+        request = request.header("if-match", "*");
+    }
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .map_err(|e| e.to_string())?;
+    let response = app
+        .clone()
+        .oneshot(request)
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| format!("{method} {uri}: cannot read the response: {e}"))?;
+    let body = serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+    Ok((status, body))
+}
+
+/// The body as JSON when the status is `expected`
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+    expected: StatusCode,
+) -> Result<Value, String> {
+    let (status, body) = request(app, method, uri, body).await?;
+    if status != expected {
+        let reason = body["error"]
+            .as_str()
+            .map_or_else(|| body.to_string(), str::to_string);
+        return Err(format!(
+            "{method} {uri}: expected {expected}, got {status}: {reason}"
+        ));
+    }
+    Ok(body)
+}
+
+/// Unhealthy dependencies listed by `/health/ready`
+fn unhealthy(body: &Value) -> Option<String> {
+    let unhealthy: Vec<String> = body["dependencies"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|dependency| dependency["healthy"] == json!(false))
+        .map(|dependency| {
+            format!(
+                "{}: {}",
+                dependency["name"].as_str().unwrap_or("?"),
+                dependency["error"].as_str().unwrap_or("unhealthy")
+            )
+        })
+        .collect();
+    (!unhealthy.is_empty()).then(|| unhealthy.join("; "))
+}
+
+/// Runs the script against `app`; stops at the first failed step, deleting the temporary
+/// record if it exists
+pub async fn run(app: &Router, target: Target) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+
+    let started = Instant::now();
+    let ready = match request(app, "GET", "/health/ready", None).await {
+        Ok((StatusCode::OK, body)) => Ok(unhealthy(&body)),
+        Ok((status, body)) => Err(format!(
+            "/health/ready answered {status}: {}",
+            unhealthy(&body)
+                .or_else(|| body["error"].as_str().map(str::to_string))
+                .unwrap_or_else(|| body.to_string())
+        )),
+        Err(e) => Err(e),
+    };
+    // Optional dependencies may be down without failing readiness; they are noted
+    let Some(degraded) = report.record("ready", started, ready) else {
+        return report;
+    };
+    if let Some(step) = report.steps.last_mut() {
+        step.detail = degraded;
+    }
+
+    let path = target.path();
+    let started = Instant::now();
+    let created = send(
+        app,
+        "POST",
+        path,
+        Some(target.create_body()),
+        StatusCode::OK,
+    )
+    .await
+    .and_then(|body| {
+        body["data"]["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("POST {path}: no id in {body}"))
+    });
+    let Some(id) = report.record("create", started, created) else {
+        return report;
+    };
+    let uri = format!("{path}/{id}");
+
+    let passed = script(app, target, &uri, &mut report).await;
+    if !passed {
+        let started = Instant::now();
+        let deleted = send(app, "DELETE", &uri, None, StatusCode::NO_CONTENT)
+            .await
+            .map(drop);
+        report.record("cleanup", started, deleted);
+    }
+    report
+}
+
+/// Read, update, delete and read again; whether every step passed
+async fn script(app: &Router, target: Target, uri: &str, report: &mut SelfTestReport) -> bool {
+    let started = Instant::now();
+    let read = send(app, "GET", uri, None, StatusCode::OK).await;
+    let Some(current) = report.record("read", started, read) else {
+        return false;
+    };
+
+    let started = Instant::now();
+    let (method, body) = target.update(&current["data"]);
+    let updated = send(app, method, uri, Some(body), StatusCode::OK)
+        .await
+        .and_then(|body| {
+            if target.updated(&current["data"], &body["data"]) {
+                Ok(())
+            } else {
+                Err(format!("{method} {uri}: the change is missing from {body}"))
+            }
+        });
+    if report.record("update", started, updated).is_none() {
+        return false;
+    }
+
+    let started = Instant::now();
+    let deleted = send(app, "DELETE", uri, None, StatusCode::NO_CONTENT).await;
+    if report.record("delete", started, deleted).is_none() {
+        return false;
+    }
+
+    let started = Instant::now();
+    let gone = send(app, "GET", uri, None, StatusCode::NOT_FOUND).await;
+    report.record("gone", started, gone).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::EncryptionMode;
+    use crate::memory::MemoryTodoRepository;
+    use crate::{create_app_with_config, AppConfig, TodoRepositoryTrait};
+    use std::sync::Arc;
+
+    fn names(report: &SelfTestReport) -> Vec<&str> {
+        report.steps.iter().map(|step| step.name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_run_leaves_nothing_behind() {
+        let repository = Arc::new(MemoryTodoRepository::new());
+        let app = create_app_with_config(repository.clone(), AppConfig::local());
+
+        let report = run(&app, Target::Todos).await;
+        assert!(report.passed(), "{report}");
+        assert_eq!(
+            names(&report),
+            ["ready", "create", "read", "update", "delete", "gone"]
+        );
+        assert!(repository.get_all_todos().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_through_encrypted_todos() {
+        let mut config = AppConfig::local();
+        config.encryption.mode = EncryptionMode::Required;
+        let app = create_app_with_config(Arc::new(MemoryTodoRepository::new()), config.clone());
+
+        let report = run(&app, Target::EncryptedTodos).await;
+        assert!(report.passed(), "{report}");
+
+        // Plaintext is refused in this mode, so nothing is created
+        let app = create_app_with_config(Arc::new(MemoryTodoRepository::new()), config);
+        let report = run(&app, Target::Todos).await;
+        assert!(!report.passed());
+        assert_eq!(names(&report), ["ready", "create"]);
+        assert!(report
+            .to_string()
+            .contains("Self-test failed: 1 of 2 steps"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Settings that can be reloaded from a file without restarting the server.
+//!
+//! With `RUNTIME_CONFIG_FILE` pointing at a JSON file such as
+//!
+//! ```json
+//! { "log_filter": "md_todo_backend=debug,warn", "sql_trace": true }
+//! ```
+//!
+//! the server applies it at startup, on SIGHUP and on `POST /api/admin/config/reload`.
+//! Omitted settings keep their current value. A file that does not parse, or carries an
+//! invalid log filter, is rejected as a whole so a typo never half-applies.
+
+use crate::logging::{self, SetFilterError};
+use crate::sqltrace;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeSettings {
+    pub log_filter: Option<String>,
+    pub sql_trace: Option<bool>,
+}
+
+impl RuntimeSettings {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid runtime config: {e}"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        Self::parse(&json)
+    }
+
+    /// Applies every setting, or none if the log filter is invalid
+    pub fn apply(&self) -> Result<(), String> {
+        if let Some(filter) = &self.log_filter {
+            match logging::set_filter(filter) {
+                Ok(()) => {}
+                Err(SetFilterError::Invalid(e)) => {
+                    return Err(format!("Invalid log filter {filter:?}: {e}"))
+                }
+                Err(SetFilterError::NotReloadable) => {
+                    tracing::warn!("Log filter is not reloadable in this process; ignoring it");
+                }
+            }
+        }
+        if let Some(enabled) = self.sql_trace {
+            sqltrace::set_enabled(enabled);
+        }
+        Ok(())
+    }
+}
+
+/// Path from `RUNTIME_CONFIG_FILE`, if reloading is configured
+pub fn config_file() -> Option<PathBuf> {
+    std::env::var_os("RUNTIME_CONFIG_FILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Loads and applies the runtime config file
+pub fn reload(path: &Path) -> Result<RuntimeSettings, String> {
+    let settings = RuntimeSettings::load(path)?;
+    settings.apply()?;
+    tracing::info!("Applied runtime config from {}", path.display());
+    Ok(settings)
+}
+
+/// Reloads the file every time the process receives SIGHUP
+#[cfg(unix)]
+pub fn spawn_sighup_reloader(path: PathBuf) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading {}", path.display());
+            if let Err(e) = reload(&path) {
+                tracing::error!("Failed to reload runtime config: {}", e);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RuntimeSettings::parse(r#"{"sql_trace": true}"#).unwrap(),
+            RuntimeSettings {
+                log_filter: None,
+                sql_trace: Some(true),
+            }
+        );
+        assert!(RuntimeSettings::parse(r#"{"sql_tracing": true}"#).is_err());
+        assert!(RuntimeSettings::parse("sql_trace = true").is_err());
+    }
+
+    #[test]
+    fn test_invalid_filter_applies_nothing() {
+        let settings = RuntimeSettings {
+            log_filter: Some("md_todo_backend=loud".to_string()),
+            sql_trace: Some(!sqltrace::is_enabled()),
+        };
+        let before = sqltrace::is_enabled();
+        assert!(settings.apply().is_err());
+        assert_eq!(sqltrace::is_enabled(), before);
+    }
+}
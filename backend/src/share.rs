@@ -0,0 +1,627 @@
+//! Share links for showing a todo to people outside the app: `GET /s/:token`.
+//!
+//! Unlike short links, a share link has an unguessable token and can carry limits that the
+//! server enforces on every open: a passcode (sent as `X-Share-Passcode`), a maximum number
+//! of views and an expiry time. After `MAX_FAILED_ATTEMPTS` wrong passcodes the link locks.
+//! Every attempt, allowed or not, lands in the link's access log. Deleting a link revokes it.
+
+use crate::domain::TodoId;
+use crate::errors::ErrorCode;
+use crate::{
+    keys_match, truncate_to_bytes, ApiResponse, Todo, TodoError, TodoRepositoryTrait, TodoResponse,
+};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub const PASSCODE_HEADER: &str = "x-share-passcode";
+/// Wrong passcodes a link tolerates before it locks for good
+pub const MAX_FAILED_ATTEMPTS: i32 = 5;
+pub const MIN_PASSCODE_LENGTH: usize = 4;
+pub const MAX_PASSCODE_LENGTH: usize = 64;
+/// Access log entries returned per request, newest first
+pub const ACCESS_LOG_LIMIT: i64 = 500;
+/// Longest user agent kept in the access log
+const MAX_USER_AGENT_BYTES: usize = 256;
+
+pub fn share_path(token: &str) -> String {
+    format!("/s/{token}")
+}
+
+/// `<salt>$<hex HMAC-SHA256(salt, passcode)>`
+pub fn hash_passcode(passcode: &str) -> String {
+    let salt = Uuid::new_v4().simple().to_string();
+    format!("{salt}${}", passcode_mac(&salt, passcode))
+}
+
+pub fn verify_passcode(stored: &str, passcode: &str) -> bool {
+    let Some((salt, expected)) = stored.split_once('$') else {
+        return false;
+    };
+    keys_match(expected, &passcode_mac(salt, passcode))
+}
+
+fn passcode_mac(salt: &str, passcode: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(passcode.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ShareLink {
+    pub token: String,
+    pub todo_id: TodoId,
+    /// See `hash_passcode`; `None` when the link is open to anyone holding it
+    pub passcode_hash: Option<String>,
+    pub max_views: Option<i32>,
+    pub view_count: i32,
+    pub failed_attempts: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ShareLink {
+    pub fn new(
+        todo_id: TodoId,
+        passcode: Option<&str>,
+        max_views: Option<i32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            token: Uuid::new_v4().simple().to_string(),
+            todo_id,
+            passcode_hash: passcode.map(hash_passcode),
+            max_views,
+            view_count: 0,
+            failed_attempts: 0,
+            expires_at,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// What opening the link with `passcode` at `now` amounts to
+    pub fn check(&self, passcode: Option<&str>, now: DateTime<Utc>) -> ShareOutcome {
+        if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return ShareOutcome::Expired;
+        }
+        if self.failed_attempts >= MAX_FAILED_ATTEMPTS {
+            return ShareOutcome::Locked;
+        }
+        if self.max_views.is_some_and(|max| self.view_count >= max) {
+            return ShareOutcome::ViewLimitReached;
+        }
+        match (&self.passcode_hash, passcode) {
+            (None, _) => ShareOutcome::Viewed,
+            (Some(_), None) => ShareOutcome::PasscodeRequired,
+            (Some(stored), Some(passcode)) if verify_passcode(stored, passcode) => {
+                ShareOutcome::Viewed
+            }
+            (Some(_), Some(_)) => ShareOutcome::WrongPasscode,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareOutcome {
+    Viewed,
+    PasscodeRequired,
+    WrongPasscode,
+    Expired,
+    ViewLimitReached,
+    Locked,
+}
+
+impl ShareOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareOutcome::Viewed => "viewed",
+            ShareOutcome::PasscodeRequired => "passcode_required",
+            ShareOutcome::WrongPasscode => "wrong_passcode",
+            ShareOutcome::Expired => "expired",
+            ShareOutcome::ViewLimitReached => "view_limit_reached",
+            ShareOutcome::Locked => "locked",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            ShareOutcome::Viewed => StatusCode::OK,
+            ShareOutcome::PasscodeRequired | ShareOutcome::WrongPasscode => {
+                StatusCode::UNAUTHORIZED
+            }
+            ShareOutcome::Expired | ShareOutcome::ViewLimitReached | ShareOutcome::Locked => {
+                StatusCode::GONE
+            }
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            ShareOutcome::Viewed => "",
+            ShareOutcome::PasscodeRequired => "This link requires a passcode",
+            ShareOutcome::WrongPasscode => "Wrong passcode",
+            ShareOutcome::Expired => "This link has expired",
+            ShareOutcome::ViewLimitReached => "This link has reached its view limit",
+            ShareOutcome::Locked => "This link is locked after too many wrong passcodes",
+        }
+    }
+}
+
+impl TryFrom<String> for ShareOutcome {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "viewed" => Ok(ShareOutcome::Viewed),
+            "passcode_required" => Ok(ShareOutcome::PasscodeRequired),
+            "wrong_passcode" => Ok(ShareOutcome::WrongPasscode),
+            "expired" => Ok(ShareOutcome::Expired),
+            "view_limit_reached" => Ok(ShareOutcome::ViewLimitReached),
+            "locked" => Ok(ShareOutcome::Locked),
+            other => Err(format!("Unknown share outcome: {}", other)),
+        }
+    }
+}
+
+/// One attempt to open a share link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ShareAccess {
+    pub accessed_at: DateTime<Utc>,
+    #[sqlx(try_from = "String")]
+    pub outcome: ShareOutcome,
+    /// First address in `X-Forwarded-For`, when a proxy sets it
+    #[schema(example = "203.0.113.7")]
+    pub client: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ShareAccess {
+    pub fn from_headers(headers: &HeaderMap, outcome: ShareOutcome) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        Self {
+            accessed_at: Utc::now(),
+            outcome,
+            client: header("x-forwarded-for")
+                .and_then(|value| value.split(',').next())
+                .map(|client| client.trim().to_string())
+                .filter(|client| !client.is_empty()),
+            user_agent: header(header::USER_AGENT.as_str())
+                .map(|agent| truncate_to_bytes(agent, MAX_USER_AGENT_BYTES).to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+#[schema(example = json!({
+    "passcode": "4821",
+    "max_views": 10,
+    "expires_at": "2030-01-01T00:00:00Z"
+}))]
+pub struct CreateShareLinkRequest {
+    #[serde(default)]
+    pub passcode: Option<String>,
+    #[serde(default)]
+    #[schema(minimum = 1)]
+    pub max_views: Option<i32>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl CreateShareLinkRequest {
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if let Some(passcode) = &self.passcode {
+            let length = passcode.chars().count();
+            if !(MIN_PASSCODE_LENGTH..=MAX_PASSCODE_LENGTH).contains(&length) {
+                return Err(format!(
+                    "Passcode must be {} to {} characters",
+                    MIN_PASSCODE_LENGTH, MAX_PASSCODE_LENGTH
+                ));
+            }
+            if passcode.chars().any(char::is_control) {
+                return Err("Passcode cannot contain control characters".to_string());
+            }
+        }
+        if self.max_views.is_some_and(|max| max < 1) {
+            return Err("max_views must be at least 1".to_string());
+        }
+        if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err("expires_at must be in the future".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A share link as shown to its owner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ShareLinkInfo {
+    #[schema(example = "3f2b8c1d9e4a4b7f8a6c5d4e3f2a1b0c")]
+    pub token: String,
+    /// Path relative to the API host
+    #[schema(example = "/s/3f2b8c1d9e4a4b7f8a6c5d4e3f2a1b0c")]
+    pub path: String,
+    #[schema(value_type = Uuid)]
+    pub todo_id: TodoId,
+    #[schema(example = true)]
+    pub has_passcode: bool,
+    #[schema(example = 10)]
+    pub max_views: Option<i32>,
+    #[schema(example = 0)]
+    pub view_count: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[schema(example = false)]
+    pub locked: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ShareLink> for ShareLinkInfo {
+    fn from(link: ShareLink) -> Self {
+        Self {
+            path: share_path(&link.token),
+            token: link.token,
+            todo_id: link.todo_id,
+            has_passcode: link.passcode_hash.is_some(),
+            max_views: link.max_views,
+            view_count: link.view_count,
+            expires_at: link.expires_at,
+            locked: link.failed_attempts >= MAX_FAILED_ATTEMPTS,
+            created_at: link.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ShareLinkResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<ShareLinkInfo>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<ShareLinkInfo>> for ShareLinkResponse {
+    fn from(response: ApiResponse<ShareLinkInfo>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ShareLinkListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<ShareLinkInfo>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<ShareLinkInfo>>> for ShareLinkListResponse {
+    fn from(response: ApiResponse<Vec<ShareLinkInfo>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ShareAccessLogResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<ShareAccess>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<ShareAccess>>> for ShareAccessLogResponse {
+    fn from(response: ApiResponse<Vec<ShareAccess>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos/{id}/share-links",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    request_body = CreateShareLinkRequest,
+    responses(
+        (status = 200, description = "Share link created", body = ShareLinkResponse),
+        (status = 400, description = "Invalid passcode or limits", body = ErrorResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn create_share_link<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<TodoId>,
+    Json(request): Json<CreateShareLinkRequest>,
+) -> Result<Json<ShareLinkResponse>, TodoError> {
+    if let Err(e) = request.validate(Utc::now()) {
+        tracing::warn!("Validation failed for share link of todo {}: {}", id, e);
+        return Err(TodoError::Validation(e));
+    }
+    let internal_error = |e| {
+        tracing::error!("Failed to create share link for todo {}: {}", id, e);
+        e
+    };
+    if repository
+        .get_todo_by_id(id)
+        .await
+        .map_err(internal_error)?
+        .is_none()
+    {
+        tracing::warn!("Todo not found for share link with id: {}", id);
+        return Err(TodoError::NotFound(format!("Todo {}", id)));
+    }
+
+    let link = ShareLink::new(
+        id,
+        request.passcode.as_deref(),
+        request.max_views,
+        request.expires_at,
+    );
+    let link = repository
+        .create_share_link(&link)
+        .await
+        .map_err(internal_error)?;
+    tracing::info!("Created share link for todo {}", id);
+    Ok(Json(ApiResponse::success(ShareLinkInfo::from(link)).into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}/share-links",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    responses(
+        (status = 200, description = "Share links of the todo, newest first", body = ShareLinkListResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn list_share_links<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<TodoId>,
+) -> Result<Json<ShareLinkListResponse>, TodoError> {
+    match repository.list_share_links(id).await {
+        Ok(links) => {
+            let links = links.into_iter().map(ShareLinkInfo::from).collect();
+            Ok(Json(ApiResponse::success(links).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list share links of todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/share-links/{token}",
+    params(
+        ("token" = String, Path, description = "Share link token")
+    ),
+    responses(
+        (status = 204, description = "Share link revoked"),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn delete_share_link<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(token): Path<String>,
+) -> StatusCode {
+    match repository.delete_share_link(&token).await {
+        Ok(true) => {
+            tracing::info!("Revoked share link");
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Failed to revoke share link: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/share-links/{token}/access-log",
+    params(
+        ("token" = String, Path, description = "Share link token")
+    ),
+    responses(
+        (status = 200, description = "Latest attempts to open the link, newest first", body = ShareAccessLogResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_access_log<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(token): Path<String>,
+) -> Result<Json<ShareAccessLogResponse>, TodoError> {
+    let internal_error = |e| {
+        tracing::error!("Failed to read share link access log: {}", e);
+        e
+    };
+    if repository
+        .get_share_link(&token)
+        .await
+        .map_err(internal_error)?
+        .is_none()
+    {
+        return Err(TodoError::NotFound("Share link".to_string()));
+    }
+    let log = repository
+        .share_access_log(&token, ACCESS_LOG_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(ApiResponse::success(log).into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/s/{token}",
+    params(
+        ("token" = String, Path, description = "Share link token"),
+        ("X-Share-Passcode" = Option<String>, Header, description = "Passcode, for links that have one")
+    ),
+    responses(
+        (status = 200, description = "The shared todo", body = TodoResponse),
+        (status = 401, description = "Passcode missing or wrong", body = ErrorResponse),
+        (status = 404, description = "Unknown or revoked link", body = ErrorResponse),
+        (status = 410, description = "Expired, out of views or locked", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn open_share_link<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<TodoResponse>, (StatusCode, Json<TodoResponse>)> {
+    let error = |status: StatusCode, message: &str| {
+        (
+            status,
+            Json(
+                ApiResponse::<Todo>::error(ErrorCode::for_status(status), message.to_string())
+                    .into(),
+            ),
+        )
+    };
+    let internal_error = |e| {
+        tracing::error!("Failed to open share link: {}", e);
+        error(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    };
+    let not_found = || error(StatusCode::NOT_FOUND, "Share link not found");
+
+    let Some(link) = repository
+        .get_share_link(&token)
+        .await
+        .map_err(internal_error)?
+    else {
+        tracing::warn!("Unknown share link opened");
+        return Err(not_found());
+    };
+    let passcode = headers
+        .get(PASSCODE_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let checked = link.check(passcode, Utc::now());
+    // The view limit is enforced again atomically while recording, for concurrent opens
+    let outcome = repository
+        .record_share_access(&token, &ShareAccess::from_headers(&headers, checked))
+        .await
+        .map_err(internal_error)?;
+    if outcome != ShareOutcome::Viewed {
+        tracing::warn!(
+            "Denied share link for todo {}: {}",
+            link.todo_id,
+            outcome.as_str()
+        );
+        return Err(error(outcome.status(), outcome.message()));
+    }
+
+    match repository
+        .get_todo_by_id(link.todo_id)
+        .await
+        .map_err(internal_error)?
+    {
+        Some(todo) => {
+            tracing::info!("Opened share link for todo {}", todo.id);
+            Ok(Json(ApiResponse::success(todo).into()))
+        }
+        None => Err(not_found()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_passcode_hash_roundtrip() {
+        let stored = hash_passcode("4821");
+        assert!(!stored.contains("4821"));
+        assert!(verify_passcode(&stored, "4821"));
+        assert!(!verify_passcode(&stored, "4822"));
+        assert_ne!(stored, hash_passcode("4821"));
+        assert!(!verify_passcode("garbage", "4821"));
+    }
+
+    #[test]
+    fn test_check_enforces_limits() {
+        let now = Utc::now();
+        let mut link = ShareLink::new(TodoId::new(), Some("4821"), Some(2), None);
+        assert_eq!(link.check(None, now), ShareOutcome::PasscodeRequired);
+        assert_eq!(link.check(Some("0000"), now), ShareOutcome::WrongPasscode);
+        assert_eq!(link.check(Some("4821"), now), ShareOutcome::Viewed);
+
+        link.view_count = 2;
+        assert_eq!(
+            link.check(Some("4821"), now),
+            ShareOutcome::ViewLimitReached
+        );
+        link.view_count = 0;
+        link.failed_attempts = MAX_FAILED_ATTEMPTS;
+        assert_eq!(link.check(Some("4821"), now), ShareOutcome::Locked);
+        link.expires_at = Some(now - Duration::seconds(1));
+        assert_eq!(link.check(Some("4821"), now), ShareOutcome::Expired);
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let now = Utc::now();
+        assert!(CreateShareLinkRequest::default().validate(now).is_ok());
+        let request = |passcode: &str| CreateShareLinkRequest {
+            passcode: Some(passcode.to_string()),
+            ..Default::default()
+        };
+        assert!(request("123").validate(now).is_err());
+        assert!(request("12\n34").validate(now).is_err());
+        assert!(request("1234").validate(now).is_ok());
+        let request = CreateShareLinkRequest {
+            max_views: Some(0),
+            ..Default::default()
+        };
+        assert!(request.validate(now).is_err());
+        let request = CreateShareLinkRequest {
+            expires_at: Some(now),
+            ..Default::default()
+        };
+        assert!(request.validate(now).is_err());
+    }
+}
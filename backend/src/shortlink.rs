@@ -0,0 +1,166 @@
+//! Human-friendly short links: `/t/:short_id` redirects to the canonical todo URL.
+//!
+//! Every todo gets a row in `short_links` when it is created. The short id is the base62
+//! encoding of that row's sequence number, so ids stay short and never need a
+//! collision check.
+
+use crate::domain::TodoId;
+use crate::errors::ErrorCode;
+use crate::{ApiResponse, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, State},
+    response::{Json, Redirect},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+pub fn encode(mut seq: u64) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(ALPHABET[(seq % 62) as usize]);
+        seq /= 62;
+        if seq == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("alphabet is ASCII")
+}
+
+/// `None` for anything `encode` could not have produced
+pub fn decode(short_id: &str) -> Option<u64> {
+    if short_id.is_empty() || (short_id.len() > 1 && short_id.starts_with('0')) {
+        return None;
+    }
+    short_id.bytes().try_fold(0u64, |seq, byte| {
+        let digit = ALPHABET.iter().position(|&c| c == byte)? as u64;
+        seq.checked_mul(62)?.checked_add(digit)
+    })
+}
+
+pub fn short_path(short_id: &str) -> String {
+    format!("/t/{short_id}")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ShortLink {
+    #[schema(example = "1C")]
+    pub short_id: String,
+    /// Path relative to the API host
+    #[schema(example = "/t/1C")]
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ShortLinkResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<ShortLink>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<ShortLink>> for ShortLinkResponse {
+    fn from(response: ApiResponse<ShortLink>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/todos/{id}/short-link",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID")
+    ),
+    responses(
+        (status = 200, description = "Short link for the todo", body = ShortLinkResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn get_short_link<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<TodoId>,
+) -> Result<Json<ShortLinkResponse>, TodoError> {
+    match repository.get_short_id(id).await {
+        Ok(Some(short_id)) => Ok(Json(
+            ApiResponse::success(ShortLink {
+                path: short_path(&short_id),
+                short_id,
+            })
+            .into(),
+        )),
+        Ok(None) => {
+            tracing::warn!("Todo not found for short link with id: {}", id);
+            Err(TodoError::NotFound(format!("Todo {}", id)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get short link for todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/t/{short_id}",
+    params(
+        ("short_id" = String, Path, description = "Base62 short id")
+    ),
+    responses(
+        (status = 307, description = "Redirect to /api/todos/{id}"),
+        (status = 404, description = "Unknown short id", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn follow_short_link<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(short_id): Path<String>,
+) -> Result<Redirect, TodoError> {
+    match repository.resolve_short_id(&short_id).await {
+        Ok(Some(id)) => Ok(Redirect::temporary(&format!("/api/todos/{id}"))),
+        Ok(None) => {
+            tracing::warn!("Unknown short link: {}", short_id);
+            Err(TodoError::NotFound(format!("Short link {}", short_id)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to resolve short link {}: {}", short_id, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        assert_eq!(encode(0), "0");
+        assert_eq!(encode(61), "z");
+        assert_eq!(encode(62), "10");
+        for seq in [1, 62, 3843, 1_000_000, u64::MAX] {
+            assert_eq!(decode(&encode(seq)), Some(seq));
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid() {
+        assert_eq!(decode(""), None);
+        assert_eq!(decode("01"), None);
+        assert_eq!(decode("a-b"), None);
+        assert_eq!(decode("zzzzzzzzzzzzz"), None);
+    }
+}
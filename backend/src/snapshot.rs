@@ -0,0 +1,250 @@
+//! Snapshot tests of API responses.
+//!
+//! `SnapshotApp` serves the router from an in-memory repository, sends it requests
+//! in-process and compares each response with a JSON file checked in next to the tests,
+//! so a renamed field, a changed status or a new error shape fails a test instead of a
+//! client. A snapshot holds the request line, the status, the content type and the body,
+//! with object keys sorted. What changes from run to run is scrubbed first: UUIDs, also
+//! inside strings, become `[uuid-N]`, numbered in the order they appear so a response can
+//! still be seen to point at the todo of its request, and RFC 3339 timestamps become
+//! `[timestamp]`. `redact` covers anything else, such as share tokens.
+//!
+//! A missing or different snapshot fails the test, showing the first line that differs;
+//! run with `UPDATE_SNAPSHOTS=1` to write the current responses instead, then review the
+//! files in the diff.
+
+use crate::memory::MemoryTodoRepository;
+use crate::{create_app_with_config, AppConfig};
+use axum::{body::Body, http::Request, Router};
+use chrono::DateTime;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+pub const UPDATE_ENV: &str = "UPDATE_SNAPSHOTS";
+const UUID_LENGTH: usize = 36;
+
+/// Replaces what differs between runs, consistently within one snapshot
+#[derive(Default)]
+struct Scrubber {
+    uuids: HashMap<Uuid, usize>,
+}
+
+impl Scrubber {
+    fn uuid(&mut self, uuid: Uuid) -> String {
+        let next = self.uuids.len() + 1;
+        format!("[uuid-{}]", self.uuids.entry(uuid).or_insert(next))
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        if DateTime::parse_from_rfc3339(text).is_ok() {
+            return "[timestamp]".to_string();
+        }
+        let mut scrubbed = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_hexdigit()) {
+            scrubbed.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match rest
+                .get(..UUID_LENGTH)
+                .and_then(|s| Uuid::try_parse(s).ok())
+            {
+                // Hyphenated only; 32 hex digits in a row may be anything
+                Some(uuid) if rest.as_bytes()[8] == b'-' => {
+                    scrubbed.push_str(&self.uuid(uuid));
+                    rest = &rest[UUID_LENGTH..];
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_hexdigit())
+                        .unwrap_or(rest.len());
+                    scrubbed.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                }
+            }
+        }
+        scrubbed.push_str(rest);
+        scrubbed
+    }
+
+    /// Scrubs strings and sorts object keys
+    fn value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.text(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.value(item)).collect())
+            }
+            Value::Object(object) => {
+                let mut keys: Vec<&String> = object.keys().collect();
+                keys.sort();
+                let mut sorted = Map::new();
+                for key in keys {
+                    sorted.insert(key.clone(), self.value(&object[key]));
+                }
+                Value::Object(sorted)
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+/// A response as sent, and its canonical form
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub status: u16,
+    /// Unscrubbed, for following requests to use
+    pub body: Value,
+    canonical: Value,
+}
+
+impl Snapshot {
+    /// Replaces the value at the JSON pointer `pointer` of the body, which must exist, with
+    /// `[redacted]`
+    pub fn redact(mut self, pointer: &str) -> Self {
+        let target = self.canonical["body"]
+            .pointer_mut(pointer)
+            .unwrap_or_else(|| panic!("nothing to redact at {pointer}"));
+        *target = json!("[redacted]");
+        self
+    }
+
+    /// Pretty-printed, with a final newline
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(&self.canonical).unwrap();
+        json.push('\n');
+        json
+    }
+}
+
+pub struct SnapshotApp {
+    app: Router,
+    repository: Arc<MemoryTodoRepository>,
+    dir: PathBuf,
+}
+
+impl SnapshotApp {
+    /// The app of `--local` with an empty repository, comparing with the files in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_config(dir, AppConfig::local())
+    }
+
+    pub fn with_config(dir: impl Into<PathBuf>, config: AppConfig) -> Self {
+        let repository = Arc::new(MemoryTodoRepository::new());
+        Self {
+            app: create_app_with_config(repository.clone(), config),
+            repository,
+            dir: dir.into(),
+        }
+    }
+
+    /// For arranging data the API cannot create
+    pub fn repository(&self) -> &Arc<MemoryTodoRepository> {
+        &self.repository
+    }
+
+    pub async fn request(&self, method: &str, uri: &str, body: Option<Value>) -> Snapshot {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match &body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+        let mut scrubber = Scrubber::default();
+        let canonical = json!({
+            "request": scrubber.text(&format!("{method} {uri}")),
+            "status": status,
+            "content_type": content_type,
+            "body": scrubber.value(&body),
+        });
+        Snapshot {
+            status,
+            body,
+            canonical,
+        }
+    }
+
+    /// Compares `snapshot` with `<dir>/<name>.json`, or writes it with `UPDATE_SNAPSHOTS=1`
+    pub fn assert(&self, name: &str, snapshot: &Snapshot) {
+        let path = self.dir.join(format!("{name}.json"));
+        let actual = snapshot.to_json();
+        if std::env::var(UPDATE_ENV).is_ok_and(|value| value == "1") {
+            std::fs::create_dir_all(&self.dir).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let Ok(expected) = std::fs::read_to_string(&path) else {
+            panic!(
+                "No snapshot at {}; run with {UPDATE_ENV}=1 to write it:\n{actual}",
+                path.display()
+            );
+        };
+        if expected == actual {
+            return;
+        }
+        let lines = expected.lines().count().max(actual.lines().count());
+        // Past the last line when only the line endings differ
+        let (line, (old, new)) = expected
+            .lines()
+            .chain(std::iter::repeat(""))
+            .zip(actual.lines().chain(std::iter::repeat("")))
+            .take(lines)
+            .enumerate()
+            .find(|(_, (old, new))| old != new)
+            .unwrap_or((lines, ("", "")));
+        panic!(
+            "Response differs from {} at line {}:\n- {old}\n+ {new}\nRun with {UPDATE_ENV}=1 to accept it:\n{actual}",
+            path.display(),
+            line + 1
+        );
+    }
+
+    /// Sends a request and compares its snapshot
+    pub async fn check(&self, name: &str, method: &str, uri: &str, body: Option<Value>) -> Value {
+        let snapshot = self.request(method, uri, body).await;
+        self.assert(name, &snapshot);
+        snapshot.body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub() {
+        let mut scrubber = Scrubber::default();
+        let id = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d";
+        let value = json!({
+            "b": [format!("Todo {id} not found"), "2026-10-17T02:47:38.655790Z"],
+            "a": { "id": id, "other": Uuid::nil(), "date": "2026-10-17" },
+            "hex": "deadbeef",
+            "simple": "018c8f3e7c4b7f2a9b1d3e4f5a6b7c8d",
+        });
+        let scrubbed = scrubber.value(&value);
+        assert_eq!(
+            serde_json::to_string(&scrubbed).unwrap(),
+            r#"{"a":{"date":"2026-10-17","id":"[uuid-1]","other":"[uuid-2]"},"b":["Todo [uuid-1] not found","[timestamp]"],"hex":"deadbeef","simple":"018c8f3e7c4b7f2a9b1d3e4f5a6b7c8d"}"#
+        );
+    }
+}
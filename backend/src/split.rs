@@ -0,0 +1,399 @@
+//! Moving markdown sections out of a todo at `POST /api/todos/:id/split` and
+//! `POST /api/todos/:id/extract-section`.
+//!
+//! A section is a heading with everything below it, up to the next heading of the same
+//! or a higher level. `extract-section` moves the first section whose heading reads
+//! `heading`; `split` moves every section at `level`, by default the highest level the
+//! content has. Each section becomes a new todo titled by its heading and holding what
+//! was under it, in the list of the original and related to it as `split_from`; a
+//! `[[title]]` link takes the place of the section in the original. The rewrite, the new
+//! todos and their relations are stored in one transaction against the version the
+//! sections were read at, so an edit made in between fails the request instead of being
+//! lost. The new todos are held to the validation policy and go through the content
+//! policies like todos created over the API.
+
+use crate::domain::{TodoContent, TodoId, TodoTitle};
+use crate::errors::{ErrorCode, FieldError, ValidationErrors};
+use crate::moderation::{self, Moderator};
+use crate::policy::{self, ValidationPolicy};
+use crate::preconditions::{self, PreconditionConfig};
+use crate::{unicode, ApiResponse, Todo, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Json,
+    Extension,
+};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// A top-level section of markdown content
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// 1 for `#`, up to 6
+    pub level: u8,
+    /// Text of the heading without its markup
+    pub heading: String,
+    /// The heading and everything under it
+    pub range: Range<usize>,
+    /// What is under the heading
+    pub body: Range<usize>,
+}
+
+/// Top-level sections of `content` in order. Headings inside lists, quotes or code
+/// blocks do not start a section.
+pub fn sections(content: &str) -> Vec<Section> {
+    let mut headings = Vec::new();
+    let mut depth = 0usize;
+    let mut current: Option<(u8, String, Range<usize>)> = None;
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) if depth == 0 => {
+                current = Some((level as u8, String::new(), range));
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(TagEnd::Heading(_)) if depth == 1 => {
+                depth -= 1;
+                if let Some(heading) = current.take() {
+                    headings.push(heading);
+                }
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading, _)) = current.as_mut() {
+                    heading.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, heading, _)) = current.as_mut() {
+                    heading.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+        .iter()
+        .enumerate()
+        .map(|(index, (level, heading, range))| {
+            let end = headings[index + 1..]
+                .iter()
+                .find(|(next, _, _)| next <= level)
+                .map_or(content.len(), |(_, _, next)| next.start);
+            Section {
+                level: *level,
+                heading: heading.trim().to_string(),
+                range: range.start..end,
+                body: range.end.min(end)..end,
+            }
+        })
+        .collect()
+}
+
+/// `content` with each of `moved` replaced by a `[[title]]` link to the todo it became
+fn rewrite(content: &str, moved: &[(&Section, &Todo)]) -> String {
+    let mut rewritten = String::with_capacity(content.len());
+    let mut at = 0;
+    for (section, todo) in moved {
+        rewritten.push_str(&content[at..section.range.start]);
+        rewritten.push_str(&format!("[[{}]]\n", todo.title.as_str()));
+        if content[..section.range.end].ends_with("\n\n") {
+            rewritten.push('\n');
+        }
+        at = section.range.end;
+    }
+    rewritten.push_str(&content[at..]);
+    rewritten
+}
+
+/// The todo a section becomes, in the list of `source`
+fn section_todo(source: &Todo, content: &str, section: &Section) -> Result<Todo, TodoError> {
+    let title = TodoTitle::parse(&section.heading).map_err(|e| {
+        TodoError::Validation(format!(
+            "Section {:?} cannot become a todo: {}",
+            section.heading, e.message
+        ))
+    })?;
+    let body = unicode::nfc(content[section.body.clone()].trim_matches('\n'));
+    let mut todo = Todo::new(
+        title,
+        TodoContent::new(body).map_err(|e| TodoError::Validation(e.message))?,
+    );
+    todo.list_id = source.list_id;
+    Ok(todo)
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ExtractSectionRequest {
+    /// Text of the heading, without the `#`s; the first section that has it is moved
+    #[schema(example = "Research notes")]
+    pub heading: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct SplitTodoRequest {
+    /// Heading level to split at, 1 to 6; the highest level the content has by default
+    #[serde(default)]
+    #[schema(example = 2, minimum = 1, maximum = 6)]
+    pub level: Option<u8>,
+}
+
+/// The original todo after a split, with the todos its sections became
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TodoSplit {
+    /// The original, with links in place of the moved sections
+    pub todo: Todo,
+    /// One todo per moved section, in content order
+    pub parts: Vec<Todo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TodoSplitResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<TodoSplit>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<TodoSplit>> for TodoSplitResponse {
+    fn from(response: ApiResponse<TodoSplit>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos/{id}/extract-section",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the todo must still have; required with REQUIRE_IF_MATCH")
+    ),
+    request_body = ExtractSectionRequest,
+    responses(
+        (status = 200, description = "Section moved into a new todo", body = TodoSplitResponse),
+        (status = 400, description = "No section has that heading, the heading is not a valid title, the new todo breaks the validation policy, or a content policy rejected it", body = ErrorResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 412, description = "The todo has changed since the If-Match ETag or while it was split", body = ErrorResponse),
+        (status = 428, description = "If-Match is missing and required", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn extract_section<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(preconditions): Extension<Arc<PreconditionConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Path(id): Path<TodoId>,
+    headers: HeaderMap,
+    Json(request): Json<ExtractSectionRequest>,
+) -> Result<Json<TodoSplitResponse>, TodoError> {
+    tracing::info!("Extracting section {:?} of todo {}", request.heading, id);
+    let expected_version = preconditions.expected_version(&headers, id)?;
+    let heading = Todo::normalize_title(&request.heading);
+    let split = move_sections(
+        repository.as_ref(),
+        &moderator,
+        id,
+        expected_version,
+        |sections| {
+            let section = sections
+                .iter()
+                .find(|section| Todo::normalize_title(&section.heading) == heading)
+                .ok_or_else(|| {
+                    TodoError::Validation(format!("Todo {} has no section {:?}", id, heading))
+                })?;
+            Ok(vec![section])
+        },
+    )
+    .await?;
+    Ok(Json(ApiResponse::success(split).into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/todos/{id}/split",
+    params(
+        ("id" = Uuid, Path, description = "Todo ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the todo must still have; required with REQUIRE_IF_MATCH")
+    ),
+    request_body = SplitTodoRequest,
+    responses(
+        (status = 200, description = "Every section at the level moved into a todo of its own", body = TodoSplitResponse),
+        (status = 400, description = "The content has no heading at the level, one is not a valid title, a new todo breaks the validation policy, or a content policy rejected one", body = ErrorResponse),
+        (status = 404, description = "Todo not found", body = ErrorResponse),
+        (status = 412, description = "The todo has changed since the If-Match ETag or while it was split", body = ErrorResponse),
+        (status = 428, description = "If-Match is missing and required", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Todos"
+)]
+pub async fn split_todo<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(preconditions): Extension<Arc<PreconditionConfig>>,
+    Extension(moderator): Extension<Arc<Moderator>>,
+    Path(id): Path<TodoId>,
+    headers: HeaderMap,
+    Json(request): Json<SplitTodoRequest>,
+) -> Result<Json<TodoSplitResponse>, TodoError> {
+    tracing::info!("Splitting todo {} at level {:?}", id, request.level);
+    if let Some(level) = request.level {
+        if !(1..=6).contains(&level) {
+            return Err(TodoError::Validation(
+                "level must be between 1 and 6".to_string(),
+            ));
+        }
+    }
+    let expected_version = preconditions.expected_version(&headers, id)?;
+    let split = move_sections(
+        repository.as_ref(),
+        &moderator,
+        id,
+        expected_version,
+        |sections| {
+            let level = request
+                .level
+                .or_else(|| sections.iter().map(|section| section.level).min());
+            let moved: Vec<_> = sections
+                .iter()
+                .filter(|section| Some(section.level) == level)
+                .collect();
+            if moved.is_empty() {
+                return Err(TodoError::Validation(match level {
+                    Some(level) => format!("Todo {} has no heading of level {}", id, level),
+                    None => format!("Todo {} has no headings", id),
+                }));
+            }
+            Ok(moved)
+        },
+    )
+    .await?;
+    Ok(Json(ApiResponse::success(split).into()))
+}
+
+/// Fails with every rule of the validation policy a new todo breaks, each message naming
+/// the section the todo would come from
+fn check_policy(policy: &ValidationPolicy, parts: &[Todo]) -> Result<(), TodoError> {
+    let mut errors = ValidationErrors::default();
+    for part in parts {
+        for error in policy.check(part).into_fields() {
+            let message = format!("Section {:?}: {}", part.title.as_str(), error.message);
+            let field = error.field.clone();
+            errors.check(&field, Err(FieldError { message, ..error }));
+        }
+    }
+    errors.into_result().map_err(|errors| {
+        tracing::warn!("Split todos break the validation policy: {}", errors);
+        TodoError::from(errors)
+    })
+}
+
+/// Moves the sections `choose` picks out of the todo, at the version it was read at
+async fn move_sections<R, F>(
+    repository: &R,
+    moderator: &Moderator,
+    id: TodoId,
+    expected_version: Option<i32>,
+    choose: F,
+) -> Result<TodoSplit, TodoError>
+where
+    R: TodoRepositoryTrait + ?Sized,
+    F: for<'a> FnOnce(&'a [Section]) -> Result<Vec<&'a Section>, TodoError>,
+{
+    let Some(source) = repository.get_todo_by_id(id).await? else {
+        tracing::warn!("Todo not found to split with id: {}", id);
+        return Err(TodoError::NotFound(format!("Todo {}", id)));
+    };
+    preconditions::check_version(id, source.version, expected_version)?;
+
+    let content = source.content.as_str();
+    let sections = sections(content);
+    let moved = choose(&sections)?;
+    let parts = moved
+        .iter()
+        .map(|section| section_todo(&source, content, section))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_policy(&policy::load(repository).await?, &parts)?;
+    let findings = parts
+        .iter()
+        .map(|part| moderator.review(Some(part.title.as_str()), Some(part.content.as_str())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let pairs: Vec<_> = moved.iter().copied().zip(&parts).collect();
+    let rewritten =
+        TodoContent::new(rewrite(content, &pairs)).map_err(|e| TodoError::Validation(e.message))?;
+
+    match repository
+        .split_todo(id, &rewritten, source.version, &parts)
+        .await
+    {
+        Ok(Some(split)) => {
+            tracing::info!(
+                "Moved {} sections of todo {} into new todos",
+                split.parts.len(),
+                id
+            );
+            for (part, findings) in parts.iter().zip(&findings) {
+                moderation::record(repository, part.id, findings).await;
+            }
+            Ok(split)
+        }
+        Ok(None) => Err(TodoError::NotFound(format!("Todo {}", id))),
+        Err(e @ TodoError::PreconditionFailed(_)) => {
+            tracing::warn!("Todo {} changed while it was split: {}", id, e);
+            Err(e)
+        }
+        Err(e) => {
+            tracing::error!("Failed to split todo {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str =
+        "Intro\n\n# Plan\n\nSteps\n\n## Details\n\nMore\n\n# Notes\n\n```\n# not a heading\n```\n";
+
+    #[test]
+    fn test_sections_nest_under_higher_levels() {
+        let found = sections(NOTE);
+        let summary: Vec<_> = found
+            .iter()
+            .map(|section| (section.level, section.heading.as_str()))
+            .collect();
+        assert_eq!(summary, vec![(1, "Plan"), (2, "Details"), (1, "Notes")]);
+
+        assert_eq!(
+            &NOTE[found[0].range.clone()],
+            "# Plan\n\nSteps\n\n## Details\n\nMore\n\n"
+        );
+        assert_eq!(&NOTE[found[1].body.clone()], "\nMore\n\n");
+        assert!(NOTE[found[2].range.clone()].ends_with("# not a heading\n```\n"));
+    }
+
+    #[test]
+    fn test_rewrite_links_moved_sections() {
+        let source = Todo::new(TodoTitle::new("Big note").unwrap(), TodoContent::default());
+        let found = sections(NOTE);
+        let part = section_todo(&source, NOTE, &found[0]).unwrap();
+        assert_eq!(part.title.as_str(), "Plan");
+        assert_eq!(part.content.as_str(), "Steps\n\n## Details\n\nMore");
+
+        let rewritten = rewrite(NOTE, &[(&found[0], &part)]);
+        assert!(rewritten.starts_with("Intro\n\n[[Plan]]\n\n# Notes\n"));
+    }
+}
@@ -0,0 +1,115 @@
+//! Optional logging of the SQL statements `DatabaseTodoRepository` runs.
+//!
+//! When enabled (`SQL_TRACE=true` at startup, or at runtime through
+//! `PUT /api/admin/config`), every statement is logged at debug level under the
+//! `md_todo_backend::sql` target together with its bind values. Todo content is never
+//! logged, only its size, and other values are cut to `MAX_VALUE_CHARS`, so traces stay
+//! readable and free of the bodies users write.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Longest rendering of a single bind value before it is truncated
+pub const MAX_VALUE_CHARS: usize = 64;
+
+/// A value bound to a traced statement
+pub type Bind<'a> = &'a (dyn fmt::Debug + Sync);
+
+static ENABLED: OnceLock<AtomicBool> = OnceLock::new();
+
+fn flag() -> &'static AtomicBool {
+    ENABLED.get_or_init(|| {
+        AtomicBool::new(
+            std::env::var("SQL_TRACE")
+                .map(|value| matches!(value.as_str(), "1" | "true"))
+                .unwrap_or(false),
+        )
+    })
+}
+
+pub fn is_enabled() -> bool {
+    flag().load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    flag().store(enabled, Ordering::Relaxed);
+}
+
+/// Bind value that is logged by size only
+pub struct Redacted(Option<usize>);
+
+impl Redacted {
+    pub fn text(text: Option<&str>) -> Self {
+        Self(text.map(str::len))
+    }
+
+    pub fn bytes(bytes: Option<&[u8]>) -> Self {
+        Self(bytes.map(<[u8]>::len))
+    }
+}
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(len) => write!(f, "<redacted {len} bytes>"),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Debug rendering of a bind value, cut to `MAX_VALUE_CHARS`
+pub fn format_value(value: &dyn fmt::Debug) -> String {
+    let text = format!("{value:?}");
+    match text.char_indices().nth(MAX_VALUE_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// One log line: the statement on a single line followed by `$n = value` pairs
+pub fn format_statement(sql: &str, binds: &[Bind]) -> String {
+    let mut line = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    for (i, value) in binds.iter().enumerate() {
+        line.push_str(if i == 0 { " -- " } else { ", " });
+        line.push_str(&format!("${} = {}", i + 1, format_value(*value)));
+    }
+    line
+}
+
+/// Logs the statement when tracing is enabled and returns it for the query
+pub fn traced<'q>(sql: &'q str, binds: &[Bind]) -> &'q str {
+    if is_enabled() {
+        tracing::debug!(target: "md_todo_backend::sql", "{}", format_statement(sql, binds));
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_statement_redacts_and_truncates() {
+        let long_title = "t".repeat(100);
+        let line = format_statement(
+            "UPDATE todos\n    SET title = $1, content = $2\n    WHERE id = $3",
+            &[&long_title, &Redacted::text(Some("secret body")), &42],
+        );
+        assert!(
+            line.starts_with("UPDATE todos SET title = $1, content = $2 WHERE id = $3 -- $1 = \"")
+        );
+        assert!(line.contains(&format!("\"{}…, ", "t".repeat(MAX_VALUE_CHARS - 1))));
+        assert!(line.contains("$2 = <redacted 11 bytes>, $3 = 42"));
+        assert!(!line.contains("secret"));
+    }
+
+    #[test]
+    fn test_redacted_none() {
+        assert_eq!(format_value(&Redacted::bytes(None)), "None");
+        assert_eq!(
+            format_value(&Redacted::bytes(Some(&[1, 2]))),
+            "<redacted 2 bytes>"
+        );
+    }
+}
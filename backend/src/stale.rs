@@ -0,0 +1,202 @@
+//! Stale todo detection.
+//!
+//! A todo is stale when it is open (neither completed nor archived) and nobody has
+//! touched it for `STALE_AFTER_DAYS` days. The flag is computed whenever todos are read
+//! through the API, so it never lags behind an edit; `GET /api/todos?stale=true` lists
+//! only stale todos and `stale=false` only the others.
+//!
+//! With `STALE_NUDGE_HOURS` set, the job leader also looks for stale todos at that
+//! interval and logs a nudge naming them, oldest first, so the backlog gets groomed.
+
+use crate::jobs::JobRunner;
+use crate::{SortOrder, Todo, TodoFilter, TodoRepositoryTrait, TodoSort};
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+
+pub const DEFAULT_STALE_AFTER_DAYS: i64 = 14;
+/// Todos named in one nudge; the rest are only counted
+const NUDGE_TITLES: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct StaleConfig {
+    /// How long an open todo may go untouched
+    pub after: Duration,
+    /// How often the job leader nudges about stale todos; never when `None`
+    pub nudge_interval: Option<std::time::Duration>,
+}
+
+impl Default for StaleConfig {
+    fn default() -> Self {
+        Self {
+            after: Duration::days(DEFAULT_STALE_AFTER_DAYS),
+            nudge_interval: None,
+        }
+    }
+}
+
+impl StaleConfig {
+    /// Reads `STALE_AFTER_DAYS` (default 14) and `STALE_NUDGE_HOURS` (off by default)
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(days) = positive_env("STALE_AFTER_DAYS") {
+            config.after = Duration::days(days as i64);
+        }
+        config.nudge_interval = positive_env("STALE_NUDGE_HOURS")
+            .map(|hours| std::time::Duration::from_secs(hours * 3600));
+        config
+    }
+
+    /// Todos last updated before this instant are stale at `now`
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - self.after
+    }
+
+    /// Sets the `stale` flag of every todo as of now
+    pub fn mark(&self, todos: &mut [Todo]) {
+        let cutoff = self.cutoff(Utc::now());
+        for todo in todos {
+            todo.stale = is_stale(todo, cutoff);
+        }
+    }
+}
+
+fn positive_env(name: &str) -> Option<u64> {
+    let value = std::env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(number) if number > 0 => Some(number),
+        _ => {
+            tracing::warn!("{} must be a positive whole number, got {:?}", name, value);
+            None
+        }
+    }
+}
+
+pub fn is_stale(todo: &Todo, cutoff: DateTime<Utc>) -> bool {
+    !todo.completed && !todo.archived && todo.updated_at < cutoff
+}
+
+/// `stale=` criterion of `GET /api/todos`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleFilter {
+    /// Keeps stale todos when true and the others when false
+    pub stale: bool,
+    pub cutoff: DateTime<Utc>,
+}
+
+impl StaleFilter {
+    pub fn matches(&self, todo: &Todo) -> bool {
+        is_stale(todo, self.cutoff) == self.stale
+    }
+}
+
+/// Logs the stale todos every `nudge_interval` while this instance is the job leader
+pub fn spawn_nudges<R: TodoRepositoryTrait + 'static>(
+    config: StaleConfig,
+    repository: Arc<R>,
+    jobs: &Arc<JobRunner>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let interval = config.nudge_interval?;
+    Some(jobs.spawn("stale-nudges", interval, move || {
+        let config = config.clone();
+        let repository = repository.clone();
+        async move {
+            nudge(&config, repository.as_ref())
+                .await
+                .map_err(|e| format!("Looking for stale todos failed: {}", e).into())
+        }
+    }))
+}
+
+async fn nudge<R: TodoRepositoryTrait>(
+    config: &StaleConfig,
+    repository: &R,
+) -> Result<(), crate::TodoError> {
+    let filter = TodoFilter {
+        stale: Some(StaleFilter {
+            stale: true,
+            cutoff: config.cutoff(Utc::now()),
+        }),
+        sort: TodoSort::UpdatedAt,
+        order: SortOrder::Asc,
+        ..TodoFilter::default()
+    };
+    let stale = repository.find_todos(&filter).await?;
+    if stale.is_empty() {
+        return Ok(());
+    }
+    let titles = stale
+        .iter()
+        .take(NUDGE_TITLES)
+        .map(|todo| format!("{:?}", todo.title))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let more = stale.len().saturating_sub(NUDGE_TITLES);
+    tracing::info!(
+        "{} todos untouched for {} days: {}{}",
+        stale.len(),
+        config.after.num_days(),
+        titles,
+        if more > 0 {
+            format!(" and {more} more")
+        } else {
+            String::new()
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{TodoContent, TodoId, TodoTitle};
+    use crate::Priority;
+
+    fn todo(completed: bool, archived: bool, updated_at: DateTime<Utc>) -> Todo {
+        Todo {
+            id: TodoId::new(),
+            title: TodoTitle::new("Old").unwrap(),
+            content: TodoContent::default(),
+            completed,
+            version: 1,
+            metadata: serde_json::json!({}),
+            labels: vec![],
+            tags: vec![],
+            estimate_minutes: None,
+            priority: Priority::default(),
+            list_id: None,
+            archived,
+            owner_id: uuid::Uuid::nil(),
+            stale: false,
+            relations: None,
+            created_at: updated_at,
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn test_only_open_todos_untouched_past_the_cutoff_are_stale() {
+        let config = StaleConfig::default();
+        let now = Utc::now();
+        let cutoff = config.cutoff(now);
+        assert_eq!(cutoff, now - Duration::days(14));
+
+        let old = now - Duration::days(15);
+        assert!(is_stale(&todo(false, false, old), cutoff));
+        assert!(!is_stale(&todo(true, false, old), cutoff));
+        assert!(!is_stale(&todo(false, true, old), cutoff));
+        assert!(!is_stale(
+            &todo(false, false, now - Duration::days(13)),
+            cutoff
+        ));
+
+        let mut todos = vec![todo(false, false, old), todo(false, false, now)];
+        config.mark(&mut todos);
+        assert!(todos[0].stale && !todos[1].stale);
+
+        let filter = StaleFilter {
+            stale: false,
+            cutoff,
+        };
+        assert!(!filter.matches(&todos[0]) && filter.matches(&todos[1]));
+    }
+}
@@ -0,0 +1,295 @@
+//! Reporting endpoints under `/api/stats`.
+
+use crate::errors::ErrorCode;
+use crate::{ApiResponse, TodoError, TodoRepositoryTrait};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Upper bounds, in days, of every age bucket but the last (open-ended) one
+pub const AGE_BUCKET_BOUNDS: [i32; 3] = [1, 7, 30];
+const AGE_BUCKET_LABELS: [&str; 4] = ["0-1d", "1-7d", "7-30d", ">30d"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AgingBucket {
+    #[schema(example = "1-7d")]
+    pub label: String,
+    #[schema(example = 1)]
+    pub min_days: i32,
+    /// Exclusive; `null` for the last bucket
+    #[schema(example = 7)]
+    pub max_days: Option<i32>,
+    #[schema(example = 3)]
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AgingReport {
+    /// Todos that are not completed
+    #[schema(example = 12)]
+    pub open: i64,
+    pub buckets: Vec<AgingBucket>,
+}
+
+impl AgingReport {
+    pub fn from_counts(counts: [i64; 4]) -> Self {
+        let buckets = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| AgingBucket {
+                label: AGE_BUCKET_LABELS[i].to_string(),
+                min_days: if i == 0 { 0 } else { AGE_BUCKET_BOUNDS[i - 1] },
+                max_days: AGE_BUCKET_BOUNDS.get(i).copied(),
+                count,
+            })
+            .collect();
+        Self {
+            open: counts.iter().sum(),
+            buckets,
+        }
+    }
+}
+
+/// Index into the aging buckets for a todo of the given age
+pub fn age_bucket(age: Duration) -> usize {
+    AGE_BUCKET_BOUNDS
+        .iter()
+        .position(|&days| age < Duration::days(days.into()))
+        .unwrap_or(AGE_BUCKET_BOUNDS.len())
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AgingReportResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<AgingReport>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<AgingReport>> for AgingReportResponse {
+    fn from(response: ApiResponse<AgingReport>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats/aging",
+    responses(
+        (status = 200, description = "Open todos bucketed by age since creation", body = AgingReportResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Stats"
+)]
+pub async fn get_aging<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<AgingReportResponse>, TodoError> {
+    match repository.aging_report().await {
+        Ok(report) => Ok(Json(ApiResponse::success(report).into())),
+        Err(e) => {
+            tracing::error!("Failed to build aging report: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// An ISO 8601 week such as `2026-W42`, Monday to Monday in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Week {
+    monday: NaiveDate,
+}
+
+impl Week {
+    pub fn containing(at: DateTime<Utc>) -> Self {
+        let iso = at.date_naive().iso_week();
+        Self::new(iso.year(), iso.week()).expect("ISO week of a valid date")
+    }
+
+    pub fn new(year: i32, week: u32) -> Option<Self> {
+        NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).map(|monday| Self { monday })
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let (year, week) = text.split_once("-W")?;
+        Self::new(year.parse().ok()?, week.parse().ok()?)
+    }
+
+    pub fn starts_at(&self) -> DateTime<Utc> {
+        self.monday.and_time(Default::default()).and_utc()
+    }
+
+    /// Exclusive
+    pub fn ends_at(&self) -> DateTime<Utc> {
+        self.starts_at() + Duration::weeks(1)
+    }
+}
+
+impl std::fmt::Display for Week {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let iso = self.monday.iso_week();
+        write!(f, "{}-W{:02}", iso.year(), iso.week())
+    }
+}
+
+/// Estimate sums over the todos created in a time range
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkloadTotals {
+    pub todos: i64,
+    pub unestimated: i64,
+    pub estimate_minutes: i64,
+    pub remaining_minutes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Workload {
+    #[schema(example = "2026-W42")]
+    pub week: String,
+    #[schema(example = "2026-10-12T00:00:00Z")]
+    pub starts_at: DateTime<Utc>,
+    /// Exclusive
+    #[schema(example = "2026-10-19T00:00:00Z")]
+    pub ends_at: DateTime<Utc>,
+    /// Todos created during the week
+    #[schema(example = 8)]
+    pub todos: i64,
+    /// Todos without an estimate; they do not count towards the sums
+    #[schema(example = 2)]
+    pub unestimated: i64,
+    #[schema(example = 540)]
+    pub estimate_minutes: i64,
+    /// Estimates of the todos that are not completed yet
+    #[schema(example = 300)]
+    pub remaining_minutes: i64,
+}
+
+impl Workload {
+    pub fn new(week: Week, totals: WorkloadTotals) -> Self {
+        Self {
+            week: week.to_string(),
+            starts_at: week.starts_at(),
+            ends_at: week.ends_at(),
+            todos: totals.todos,
+            unestimated: totals.unestimated,
+            estimate_minutes: totals.estimate_minutes,
+            remaining_minutes: totals.remaining_minutes,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WorkloadResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Workload>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Workload>> for WorkloadResponse {
+    fn from(response: ApiResponse<Workload>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkloadQuery {
+    pub week: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats/workload",
+    params(
+        ("week" = Option<String>, Query, description = "ISO week such as 2026-W42; defaults to the current week")
+    ),
+    responses(
+        (status = 200, description = "Estimates of the todos created during the week", body = WorkloadResponse),
+        (status = 400, description = "Invalid week", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Stats"
+)]
+pub async fn get_workload<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Query(query): Query<WorkloadQuery>,
+) -> Result<Json<WorkloadResponse>, TodoError> {
+    let week = match query.week.as_deref() {
+        Some(text) => Week::parse(text).ok_or_else(|| {
+            tracing::warn!("Invalid week for workload: {}", text);
+            TodoError::Validation(format!("Invalid week {text:?}, expected e.g. 2026-W42"))
+        })?,
+        None => Week::containing(Utc::now()),
+    };
+
+    match repository
+        .workload_totals(week.starts_at(), week.ends_at())
+        .await
+    {
+        Ok(totals) => Ok(Json(
+            ApiResponse::success(Workload::new(week, totals)).into(),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to build workload for {}: {}", week, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_age_bucket() {
+        assert_eq!(age_bucket(Duration::hours(23)), 0);
+        assert_eq!(age_bucket(Duration::days(1)), 1);
+        assert_eq!(age_bucket(Duration::days(29)), 2);
+        assert_eq!(age_bucket(Duration::days(400)), 3);
+    }
+
+    #[test]
+    fn test_report_from_counts() {
+        let report = AgingReport::from_counts([1, 2, 0, 4]);
+        assert_eq!(report.open, 7);
+        assert_eq!(report.buckets[1].label, "1-7d");
+        assert_eq!(report.buckets[1].min_days, 1);
+        assert_eq!(report.buckets[1].max_days, Some(7));
+        assert_eq!(report.buckets[3].max_days, None);
+    }
+
+    #[test]
+    fn test_week_parse_and_display() {
+        let week = Week::parse("2026-W42").unwrap();
+        assert_eq!(week.to_string(), "2026-W42");
+        assert_eq!(week.starts_at().to_rfc3339(), "2026-10-12T00:00:00+00:00");
+        assert_eq!(week.ends_at().to_rfc3339(), "2026-10-19T00:00:00+00:00");
+        assert_eq!(Week::parse("2026-W54"), None);
+        assert_eq!(Week::parse("2026-42"), None);
+    }
+
+    #[test]
+    fn test_week_containing_uses_iso_year() {
+        let at = "2027-01-01T12:00:00Z".parse().unwrap();
+        assert_eq!(Week::containing(at).to_string(), "2026-W53");
+    }
+}
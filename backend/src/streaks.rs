@@ -0,0 +1,194 @@
+//! Completion streaks and badges at `GET /api/me/streaks`.
+//!
+//! Every time a todo is marked completed, the repository counts it towards the current
+//! UTC day of the user who completed it, in the same transaction. A streak is a run of
+//! consecutive days with at least one completion; the current one is still alive until a
+//! whole day passes without one. "Me" is the user of the access token, or everyone using
+//! the server while accounts are off.
+
+use crate::auth;
+use crate::errors::ErrorCode;
+use crate::{ApiResponse, TodoError, TodoRepositoryTrait};
+use axum::{extract::State, response::Json};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Todos marked completed on one UTC day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::FromRow)]
+pub struct CompletionDay {
+    pub day: NaiveDate,
+    pub completions: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Badge {
+    /// A first todo completed
+    FirstCompletion,
+    /// A streak of 7 days
+    WeekStreak,
+    /// A streak of 30 days
+    MonthStreak,
+    /// 10 todos completed in one day
+    BusyDay,
+    /// 100 todos completed
+    Century,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Streaks {
+    /// Days in a row with a completion, up to today or, while today has none yet,
+    /// yesterday
+    #[schema(example = 4)]
+    pub current_days: u32,
+    #[schema(example = 12)]
+    pub longest_days: u32,
+    #[schema(example = true)]
+    pub completed_today: bool,
+    #[schema(example = "2026-10-16")]
+    pub last_completed_on: Option<NaiveDate>,
+    #[schema(example = 57)]
+    pub total_completions: i64,
+    /// Badges earned so far
+    #[schema(example = json!(["first_completion", "week_streak"]))]
+    pub badges: Vec<Badge>,
+}
+
+impl Streaks {
+    /// Reads `days`, oldest first, as of the UTC day `today`
+    pub fn from_days(days: &[CompletionDay], today: NaiveDate) -> Self {
+        let mut longest = 0;
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for day in days {
+            run = match previous {
+                Some(previous) if previous.succ_opt() == Some(day.day) => run + 1,
+                _ => 1,
+            };
+            longest = longest.max(run);
+            previous = Some(day.day);
+        }
+        let last_completed_on = previous;
+        let completed_today = last_completed_on == Some(today);
+        let alive = completed_today || last_completed_on == today.pred_opt();
+        let total_completions = days.iter().map(|day| i64::from(day.completions)).sum();
+        let busiest = days.iter().map(|day| day.completions).max().unwrap_or(0);
+
+        let badges = [
+            (Badge::FirstCompletion, total_completions >= 1),
+            (Badge::WeekStreak, longest >= 7),
+            (Badge::MonthStreak, longest >= 30),
+            (Badge::BusyDay, busiest >= 10),
+            (Badge::Century, total_completions >= 100),
+        ]
+        .into_iter()
+        .filter_map(|(badge, earned)| earned.then_some(badge))
+        .collect();
+
+        Self {
+            current_days: if alive { run } else { 0 },
+            longest_days: longest,
+            completed_today,
+            last_completed_on,
+            total_completions,
+            badges,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StreaksResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Streaks>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Streaks>> for StreaksResponse {
+    fn from(response: ApiResponse<Streaks>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me/streaks",
+    responses(
+        (status = 200, description = "Completion streaks and badges of the user, by UTC day", body = StreaksResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Stats"
+)]
+pub async fn get_streaks<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<StreaksResponse>, TodoError> {
+    match repository.completion_days(auth::acting_user()).await {
+        Ok(days) => {
+            let streaks = Streaks::from_days(&days, Utc::now().date_naive());
+            Ok(Json(ApiResponse::success(streaks).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to read completion days: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str, completions: i32) -> CompletionDay {
+        CompletionDay {
+            day: date.parse().unwrap(),
+            completions,
+        }
+    }
+
+    #[test]
+    fn test_streaks_count_consecutive_days() {
+        let today: NaiveDate = "2026-10-16".parse().unwrap();
+        assert_eq!(
+            Streaks::from_days(&[], today),
+            Streaks {
+                current_days: 0,
+                longest_days: 0,
+                completed_today: false,
+                last_completed_on: None,
+                total_completions: 0,
+                badges: vec![],
+            }
+        );
+
+        let mut days: Vec<_> = (1..=8)
+            .map(|n| day(&format!("2026-09-{n:02}"), 1))
+            .collect();
+        days.extend([day("2026-10-14", 12), day("2026-10-15", 2)]);
+        let streaks = Streaks::from_days(&days, today);
+        assert_eq!(streaks.current_days, 2);
+        assert_eq!(streaks.longest_days, 8);
+        assert!(!streaks.completed_today);
+        assert_eq!(streaks.total_completions, 22);
+        assert_eq!(
+            streaks.badges,
+            [Badge::FirstCompletion, Badge::WeekStreak, Badge::BusyDay]
+        );
+
+        days.push(day("2026-10-16", 1));
+        let streaks = Streaks::from_days(&days, today);
+        assert_eq!((streaks.current_days, streaks.completed_today), (3, true));
+
+        let later = Streaks::from_days(&days, "2026-10-18".parse().unwrap());
+        assert_eq!((later.current_days, later.longest_days), (0, 8));
+    }
+}
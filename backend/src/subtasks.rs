@@ -0,0 +1,295 @@
+//! Folding finished subtasks back into their parent.
+//!
+//! The subtasks of a todo are the todos split off it by `POST /api/todos/:id/split` or
+//! `/extract-section`, related to it as `split_from`. With `MERGE_COMPLETED_SUBTASKS=true`,
+//! completing the last open one appends a checklist to the parent, whichever route
+//! completed it: `PATCH` or `PUT /api/todos/:id`, `PATCH /api/todos/bulk` or
+//! `POST /api/todos/complete-all` all go through `merge_completed`. The checklist has one
+//! checked item per subtask, with the task list items of its content nested below, so the
+//! parent keeps a record of what was done. The subtasks are then archived.
+//! Both happen in one transaction against the version the parent was read at; a parent
+//! edited in between, or a subtask reopened, leaves everything as it was, and completing
+//! a subtask again retries the merge. The merged content goes through the content
+//! policies: one that rejects it leaves everything as it was too, and what they flag is
+//! queued for review on the parent.
+
+use crate::domain::{TodoContent, TodoId};
+use crate::moderation::{self, Moderator};
+use crate::relations::{RelationDirection, RelationKind};
+use crate::{Todo, TodoError, TodoRepositoryTrait};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+/// Heading of the checklist appended to a parent
+pub const SUMMARY_HEADING: &str = "## Completed subtasks";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubtaskConfig {
+    /// Merge the subtasks of a parent once all of them are completed
+    pub merge_on_complete: bool,
+}
+
+impl SubtaskConfig {
+    /// Reads `MERGE_COMPLETED_SUBTASKS`; subtasks are left alone by default
+    pub fn from_env() -> Self {
+        Self {
+            merge_on_complete: std::env::var("MERGE_COMPLETED_SUBTASKS")
+                .map(|value| matches!(value.as_str(), "1" | "true"))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// A parent with the subtasks folded into it
+#[derive(Debug, Clone)]
+pub struct SubtaskMerge {
+    /// The parent with the checklist in its content
+    pub parent: Todo,
+    /// The subtasks, now archived
+    pub subtasks: Vec<Todo>,
+}
+
+/// A task list item: whether it is checked and its text without markup
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecklistItem {
+    pub checked: bool,
+    pub text: String,
+}
+
+/// Task list items of `content` in order, nested ones included. Items inside code
+/// blocks are not items.
+pub fn checklist_items(content: &str) -> Vec<ChecklistItem> {
+    let mut items = Vec::new();
+    // Text of the items being read, innermost last
+    let mut open: Vec<Option<ChecklistItem>> = Vec::new();
+    for event in Parser::new_ext(content, Options::ENABLE_TASKLISTS) {
+        match event {
+            Event::Start(Tag::Item) => open.push(None),
+            Event::TaskListMarker(checked) => {
+                if let Some(item) = open.last_mut() {
+                    *item = Some(ChecklistItem {
+                        checked,
+                        text: String::new(),
+                    });
+                }
+            }
+            // A nested list ends the text of its item; the item goes before its children
+            Event::Start(Tag::List(_)) => {
+                if let Some(item) = open.last_mut().and_then(Option::take) {
+                    items.push(item);
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                if let Some(item) = open.pop().flatten() {
+                    items.push(item);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(Some(item)) = open.last_mut() {
+                    item.text.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(Some(item)) = open.last_mut() {
+                    item.text.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+    items
+        .into_iter()
+        .filter(|item| !item.text.trim().is_empty())
+        .map(|item| ChecklistItem {
+            text: item.text.trim().to_string(),
+            ..item
+        })
+        .collect()
+}
+
+/// The checklist summarizing `subtasks`, under `SUMMARY_HEADING`
+pub fn summary(subtasks: &[Todo]) -> String {
+    let mut summary = format!("{SUMMARY_HEADING}\n\n");
+    for subtask in subtasks {
+        summary.push_str(&format!("- [x] {}\n", subtask.title.as_str()));
+        for item in checklist_items(subtask.content.as_str()) {
+            let mark = if item.checked { 'x' } else { ' ' };
+            summary.push_str(&format!("  - [{mark}] {}\n", item.text));
+        }
+    }
+    summary
+}
+
+/// `content` of a parent with the summary of `subtasks` appended
+pub fn merged_content(content: &str, subtasks: &[Todo]) -> String {
+    let content = content.trim_end();
+    if content.is_empty() {
+        summary(subtasks)
+    } else {
+        format!("{content}\n\n{}", summary(subtasks))
+    }
+}
+
+/// The parent `todo` was split off, if any
+async fn parent_of<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    todo: TodoId,
+) -> Result<Option<TodoId>, TodoError> {
+    Ok(repository
+        .relations_of(todo)
+        .await?
+        .into_iter()
+        .find(|relation| {
+            relation.kind == RelationKind::SplitFrom
+                && relation.direction == RelationDirection::Outgoing
+        })
+        .map(|relation| relation.todo_id))
+}
+
+/// Subtasks of `parent` that are not archived, oldest first
+async fn open_subtasks<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    parent: TodoId,
+) -> Result<Vec<Todo>, TodoError> {
+    let mut subtasks = Vec::new();
+    for relation in repository.relations_of(parent).await? {
+        if relation.kind != RelationKind::SplitFrom
+            || relation.direction != RelationDirection::Incoming
+        {
+            continue;
+        }
+        if let Some(subtask) = repository.get_todo_by_id(relation.todo_id).await? {
+            if !subtask.archived {
+                subtasks.push(subtask);
+            }
+        }
+    }
+    Ok(subtasks)
+}
+
+/// Merges the subtasks of the parent of `completed` into it if `completed` finished the
+/// last of them; `None` when there is nothing to merge yet
+pub async fn merge_if_done<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    moderator: &Moderator,
+    completed: &Todo,
+) -> Result<Option<SubtaskMerge>, TodoError> {
+    if !completed.completed || completed.archived {
+        return Ok(None);
+    }
+    let Some(parent_id) = parent_of(repository, completed.id).await? else {
+        return Ok(None);
+    };
+    let Some(parent) = repository.get_todo_by_id(parent_id).await? else {
+        return Ok(None);
+    };
+    let subtasks = open_subtasks(repository, parent_id).await?;
+    // Empty when another completion merged them first
+    if parent.archived || subtasks.is_empty() || subtasks.iter().any(|subtask| !subtask.completed) {
+        return Ok(None);
+    }
+    let content = merged_content(parent.content.as_str(), &subtasks);
+    let content = TodoContent::new(content).map_err(|e| {
+        TodoError::Validation(format!(
+            "Subtasks of {} cannot be merged into it: {}",
+            parent_id, e.message
+        ))
+    })?;
+    let findings = moderator.review(None, Some(content.as_str()))?;
+    let ids: Vec<TodoId> = subtasks.iter().map(|subtask| subtask.id).collect();
+    match repository
+        .merge_subtasks(parent_id, &content, parent.version, &ids)
+        .await
+    {
+        Ok(Some(merge)) => {
+            moderation::record(repository, parent_id, &findings).await;
+            Ok(Some(merge))
+        }
+        Ok(None) => Ok(None),
+        // The parent changed since it was read
+        Err(TodoError::PreconditionFailed(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Merges the subtasks of the parents of `completed` when they finish the last of them,
+/// if `config` asks for it, and leaves each todo as it is afterwards. Every route that
+/// completes todos ends here; the completions are stored either way, so a failed merge is
+/// only logged.
+pub async fn merge_completed<R: TodoRepositoryTrait + ?Sized>(
+    repository: &R,
+    moderator: &Moderator,
+    config: &SubtaskConfig,
+    completed: &mut [Todo],
+) {
+    if !config.merge_on_complete {
+        return;
+    }
+    for index in 0..completed.len() {
+        let todo = &completed[index];
+        match merge_if_done(repository, moderator, todo).await {
+            Ok(Some(merge)) => {
+                tracing::info!(
+                    "Merged {} completed subtasks into todo {}",
+                    merge.subtasks.len(),
+                    merge.parent.id
+                );
+                // Subtasks completed together are all archived by the first merge
+                for merged in merge.subtasks.into_iter().chain([merge.parent]) {
+                    if let Some(todo) = completed.iter_mut().find(|todo| todo.id == merged.id) {
+                        *todo = merged;
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!(
+                "Failed to merge subtasks after completing todo {}: {}",
+                todo.id,
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TodoTitle;
+
+    fn todo(title: &str, content: &str) -> Todo {
+        Todo::new(
+            TodoTitle::parse(title).unwrap(),
+            TodoContent::new(content).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_checklist_items_are_read_in_order_with_nested_ones() {
+        let content = "Intro\n\n- [x] Read the **paper**\n  - [ ] Take `notes`\n- plain item\n\n```\n- [ ] not an item\n```\n";
+        assert_eq!(
+            checklist_items(content),
+            vec![
+                ChecklistItem {
+                    checked: true,
+                    text: "Read the paper".to_string()
+                },
+                ChecklistItem {
+                    checked: false,
+                    text: "Take notes".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_content_appends_a_checklist_per_subtask() {
+        let subtasks = [
+            todo("Research", "- [x] Read\n- [ ] Summarize\n"),
+            todo("Write", "No checklist here"),
+        ];
+        assert_eq!(
+            merged_content("Plan\n\n[[Research]]\n[[Write]]\n\n", &subtasks),
+            "Plan\n\n[[Research]]\n[[Write]]\n\n## Completed subtasks\n\n- [x] Research\n  - [x] Read\n  - [ ] Summarize\n- [x] Write\n"
+        );
+        assert!(merged_content("", &subtasks).starts_with(SUMMARY_HEADING));
+    }
+}
@@ -0,0 +1,461 @@
+//! Tags: named entities shared between todos through the `todo_tags` join table.
+//!
+//! Unlike labels, which are free-form paths stored on each todo, a tag exists on its own
+//! under `/api/tags` and can be renamed or deleted once for every todo carrying it. Todos
+//! list the names of their tags; `tags` on create or update sets them, `add_tags` and
+//! `remove_tags` attach and detach single ones, and unknown names are created on the way.
+//! `GET /api/todos?tag=work` lists the todos tagged `work`. Names are compared in their
+//! normalized form, so `Work` and `work ` are the same tag.
+//!
+//! Renaming a tag to the name of another one fails unless `merge` is set; then the other
+//! tag absorbs it, and todos that carried either carry only the other one, in a single
+//! transaction. `GET /api/tags/autocomplete` suggests existing tags while one is typed.
+
+use crate::errors::ErrorCode;
+use crate::{unicode, ApiResponse, Todo, TodoError, TodoRepositoryTrait, UpdateTodoRequest};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub const MAX_TAGS: usize = 20;
+pub const MAX_TAG_LENGTH: usize = 50;
+pub const DEFAULT_SUGGESTIONS: i64 = 10;
+pub const MAX_SUGGESTIONS: i64 = 50;
+
+/// Canonical form of a tag name: NFC, trimmed, lowercase
+pub fn normalize(name: &str) -> Result<String, String> {
+    let name = unicode::nfc(name.trim()).to_lowercase();
+    if name.is_empty() {
+        return Err("Tag names cannot be empty".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("Tag names cannot contain control characters".to_string());
+    }
+    if name.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "Tag names cannot exceed {} characters",
+            MAX_TAG_LENGTH
+        ));
+    }
+    Ok(name)
+}
+
+/// Normalized, de-duplicated and sorted, the order todos list their tags in
+pub fn normalize_all(names: &[String]) -> Result<Vec<String>, String> {
+    if names.len() > MAX_TAGS {
+        return Err(format!("A todo cannot have more than {} tags", MAX_TAGS));
+    }
+    let mut normalized = names
+        .iter()
+        .map(|name| normalize(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    normalized.sort();
+    normalized.dedup();
+    Ok(normalized)
+}
+
+/// Tags of a todo after `update`, or `None` when the update leaves them alone. `tags`
+/// replaces the current ones before `add_tags` and `remove_tags` apply.
+pub fn apply_update(current: &[String], update: &UpdateTodoRequest) -> Option<Vec<String>> {
+    if update.tags.is_none() && update.add_tags.is_none() && update.remove_tags.is_none() {
+        return None;
+    }
+    let mut tags = update.tags.clone().unwrap_or_else(|| current.to_vec());
+    tags.extend(update.add_tags.iter().flatten().cloned());
+    if let Some(remove) = &update.remove_tags {
+        tags.retain(|tag| !remove.contains(tag));
+    }
+    tags.sort();
+    tags.dedup();
+    Some(tags)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct Tag {
+    #[schema(example = "018c8f3e-7c4b-7f2a-9b1d-3e4f5a6b7c8d")]
+    pub id: Uuid,
+    #[schema(example = "work")]
+    pub name: String,
+    /// Todos carrying the tag
+    #[schema(example = 12)]
+    pub todo_count: i64,
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl Tag {
+    /// `name` must already be normalized
+    pub fn new(name: &str) -> Self {
+        Self {
+            id: Uuid::now_v7(),
+            name: name.to_string(),
+            todo_count: 0,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Tags whose name contains `text`, already normalized: those starting with it first,
+/// each group by how many todos carry the tag and then by name, at most `limit`
+pub fn suggest_in(text: &str, limit: i64, tags: impl IntoIterator<Item = Tag>) -> Vec<Tag> {
+    let mut tags: Vec<Tag> = tags
+        .into_iter()
+        .filter(|tag| tag.name.contains(text))
+        .collect();
+    tags.sort_by(|a, b| {
+        b.name
+            .starts_with(text)
+            .cmp(&a.name.starts_with(text))
+            .then(b.todo_count.cmp(&a.todo_count))
+            .then(a.name.cmp(&b.name))
+    });
+    tags.truncate(limit as usize);
+    tags
+}
+
+/// Outcome of renaming a tag
+#[derive(Debug)]
+pub enum TagRename {
+    /// The renamed tag and the todos carrying it
+    Renamed(Tag, Vec<Todo>),
+    /// Merged into the tag that had the name, which is returned with the todos it took
+    /// over
+    Merged(Tag, Vec<Todo>),
+    NameTaken,
+    NotFound,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct TagRequest {
+    #[schema(example = "work", min_length = 1, max_length = 50)]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RenameTagRequest {
+    #[schema(example = "work", min_length = 1, max_length = 50)]
+    pub name: String,
+    /// When another tag has the name, merge this tag into it instead of failing
+    #[serde(default)]
+    #[schema(example = false)]
+    pub merge: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TagResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Tag>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Tag>> for TagResponse {
+    fn from(response: ApiResponse<Tag>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TagListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<Tag>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<Tag>>> for TagListResponse {
+    fn from(response: ApiResponse<Vec<Tag>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tags",
+    responses(
+        (status = 200, description = "All tags by name, with how many todos carry each", body = TagListResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Tags"
+)]
+pub async fn list_tags<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+) -> Result<Json<TagListResponse>, TodoError> {
+    match repository.list_tags().await {
+        Ok(tags) => Ok(Json(ApiResponse::success(tags).into())),
+        Err(e) => {
+            tracing::error!("Failed to list tags: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/tags",
+    request_body = TagRequest,
+    responses(
+        (status = 200, description = "Tag created", body = TagResponse),
+        (status = 400, description = "Invalid name", body = ErrorResponse),
+        (status = 409, description = "A tag of that name exists", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Tags"
+)]
+pub async fn create_tag<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Json(request): Json<TagRequest>,
+) -> Result<Json<TagResponse>, TodoError> {
+    let name = normalize(&request.name).map_err(|e| {
+        tracing::warn!("Validation failed for tag {:?}: {}", request.name, e);
+        TodoError::Validation(e.to_string())
+    })?;
+    match repository.create_tag(&Tag::new(&name)).await {
+        Ok(Some(tag)) => {
+            tracing::info!("Created tag {}", tag.name);
+            Ok(Json(ApiResponse::success(tag).into()))
+        }
+        Ok(None) => {
+            tracing::warn!("Tag already exists: {}", name);
+            Err(TodoError::Conflict(format!("Tag {} already exists", name)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create tag {}: {}", name, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tags/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Tag ID")
+    ),
+    responses(
+        (status = 200, description = "Tag found", body = TagResponse),
+        (status = 404, description = "Tag not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Tags"
+)]
+pub async fn get_tag<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TagResponse>, TodoError> {
+    match repository.get_tag(id).await {
+        Ok(Some(tag)) => Ok(Json(ApiResponse::success(tag).into())),
+        Ok(None) => Err(TodoError::NotFound(format!("Tag {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to get tag {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tags/autocomplete",
+    params(
+        ("q" = String, Query, description = "Part of the tag name; names starting with it come first"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of tags (1-50, default 10)")
+    ),
+    responses(
+        (status = 200, description = "Matching tags, most used first among those starting with q and among the others", body = TagListResponse),
+        (status = 400, description = "Missing or invalid q, or invalid limit", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Tags"
+)]
+pub async fn autocomplete_tags<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<TagListResponse>, TodoError> {
+    let invalid = |e: String| {
+        tracing::warn!("Invalid tag autocomplete: {}", e);
+        TodoError::Validation(e)
+    };
+    let text = normalize(params.get("q").map_or("", String::as_str)).map_err(invalid)?;
+    let limit = match params.get("limit") {
+        None => DEFAULT_SUGGESTIONS,
+        Some(limit) => match limit.parse::<i64>() {
+            Ok(limit) if (1..=MAX_SUGGESTIONS).contains(&limit) => limit,
+            _ => {
+                return Err(invalid(format!(
+                    "limit must be between 1 and {}, got {:?}",
+                    MAX_SUGGESTIONS, limit
+                )))
+            }
+        },
+    };
+    match repository.suggest_tags(&text, limit).await {
+        Ok(tags) => Ok(Json(ApiResponse::success(tags).into())),
+        Err(e) => {
+            tracing::error!("Failed to suggest tags for {:?}: {}", text, e);
+            Err(e)
+        }
+    }
+}
+
+/// Every todo carrying the tag shows the new name and gets a new version. With `merge`,
+/// a tag that already has the name takes over the todos of this one, which is deleted.
+#[utoipa::path(
+    patch,
+    path = "/api/tags/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Tag ID")
+    ),
+    request_body = RenameTagRequest,
+    responses(
+        (status = 200, description = "Tag renamed, or the tag it was merged into", body = TagResponse),
+        (status = 400, description = "Invalid name", body = ErrorResponse),
+        (status = 404, description = "Tag not found", body = ErrorResponse),
+        (status = 409, description = "Another tag has that name and merge is not set", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Tags"
+)]
+pub async fn rename_tag<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<RenameTagRequest>,
+) -> Result<Json<TagResponse>, TodoError> {
+    let name = normalize(&request.name).map_err(|e| {
+        tracing::warn!("Validation failed for tag {:?}: {}", request.name, e);
+        TodoError::Validation(e.to_string())
+    })?;
+    match repository.rename_tag(id, &name, request.merge).await {
+        Ok(TagRename::Renamed(tag, _)) => {
+            tracing::info!("Renamed tag {} to {}", id, tag.name);
+            Ok(Json(ApiResponse::success(tag).into()))
+        }
+        Ok(TagRename::Merged(tag, _)) => {
+            tracing::info!("Merged tag {} into {} ({})", id, tag.id, tag.name);
+            Ok(Json(ApiResponse::success(tag).into()))
+        }
+        Ok(TagRename::NameTaken) => {
+            tracing::warn!("Cannot rename tag {}: {} exists", id, name);
+            Err(TodoError::Conflict(format!("Tag {} already exists", name)))
+        }
+        Ok(TagRename::NotFound) => Err(TodoError::NotFound(format!("Tag {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to rename tag {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+/// Detaches the tag from every todo carrying it, giving those todos a new version
+#[utoipa::path(
+    delete,
+    path = "/api/tags/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Tag ID")
+    ),
+    responses(
+        (status = 204, description = "Tag deleted"),
+        (status = 404, description = "Tag not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Tags"
+)]
+pub async fn delete_tag<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, TodoError> {
+    match repository.delete_tag(id).await {
+        Ok(Some(detached)) => {
+            tracing::info!("Deleted tag {} from {} todos", id, detached.len());
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(None) => Err(TodoError::NotFound(format!("Tag {}", id))),
+        Err(e) => {
+            tracing::error!("Failed to delete tag {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  Work "), Ok("work".to_string()));
+        assert_eq!(normalize("Cafe\u{301}"), Ok("café".to_string()));
+        assert!(normalize(" ").is_err());
+        assert!(normalize("a\tb").is_err());
+        assert!(normalize(&"x".repeat(MAX_TAG_LENGTH + 1)).is_err());
+        assert_eq!(
+            normalize_all(&names(&["urgent", "Work", "work"])),
+            Ok(names(&["urgent", "work"]))
+        );
+    }
+
+    #[test]
+    fn test_suggest_in() {
+        let tag = |name: &str, todo_count: i64| Tag {
+            todo_count,
+            ..Tag::new(name)
+        };
+        let tags = vec![
+            tag("homework", 9),
+            tag("work", 2),
+            tag("workshop", 5),
+            tag("home", 1),
+            tag("worker", 5),
+        ];
+        let suggested: Vec<String> = suggest_in("work", 3, tags)
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(suggested, names(&["worker", "workshop", "work"]));
+    }
+
+    #[test]
+    fn test_apply_update() {
+        let current = names(&["home", "work"]);
+        let mut update = UpdateTodoRequest::default();
+        assert_eq!(apply_update(&current, &update), None);
+
+        update.add_tags = Some(names(&["urgent", "work"]));
+        update.remove_tags = Some(names(&["home"]));
+        assert_eq!(
+            apply_update(&current, &update),
+            Some(names(&["urgent", "work"]))
+        );
+
+        update.tags = Some(vec![]);
+        update.add_tags = None;
+        assert_eq!(apply_update(&current, &update), Some(vec![]));
+    }
+}
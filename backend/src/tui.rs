@@ -0,0 +1,481 @@
+//! Terminal dashboard for `md-todo tui`: the todos of a remote server in three tabs (open,
+//! done, all), keyboard navigation, server-side search and toggling completion.
+//!
+//! `Dashboard` holds the state and turns keys into `Action`s that `run` carries out with
+//! `TodoClient`, so the behaviour is tested without a terminal. Drawing and the event loop
+//! use ratatui and need the `tui` cargo feature.
+//!
+//! Keys: `j`/`k` or arrows move, `g`/`G` jump to the ends, `Tab` switches tabs, `/` starts a
+//! search (Enter runs it, Esc cancels), `Space` or `x` toggles the selected todo, `r`
+//! reloads, `Esc` clears the search and `q` quits.
+
+use crate::domain::TodoId;
+use crate::{Priority, Todo};
+
+/// Key presses the dashboard reacts to, independent of the terminal library
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Home,
+    End,
+    Tab,
+    BackTab,
+    Enter,
+    Esc,
+    Backspace,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tab {
+    #[default]
+    Open,
+    Done,
+    All,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 3] = [Tab::Open, Tab::Done, Tab::All];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Tab::Open => "Open",
+            Tab::Done => "Done",
+            Tab::All => "All",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|tab| *tab == self).unwrap_or(0)
+    }
+
+    fn shows(self, todo: &Todo) -> bool {
+        match self {
+            Tab::Open => !todo.completed,
+            Tab::Done => todo.completed,
+            Tab::All => true,
+        }
+    }
+}
+
+/// Work for the event loop; everything else is handled inside `Dashboard`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Reload,
+    Search(String),
+    SetCompleted(TodoId, bool),
+    Quit,
+}
+
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    /// Every todo, or the hits of the active search, in server order
+    todos: Vec<Todo>,
+    tab: Tab,
+    selected: usize,
+    /// Text being typed after `/`
+    input: Option<String>,
+    /// Search whose hits `todos` holds
+    search: Option<String>,
+    status: Option<String>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the listed todos, keeping the selection on the same todo when it is still shown
+    pub fn set_todos(&mut self, todos: Vec<Todo>) {
+        let selected = self.selected_todo().map(|todo| todo.id);
+        self.todos = todos;
+        self.selected = selected
+            .and_then(|id| self.visible().iter().position(|todo| todo.id == id))
+            .unwrap_or(self.selected);
+        self.clamp_selection();
+    }
+
+    /// Puts a todo returned by an update in place of its old version
+    pub fn replace_todo(&mut self, updated: Todo) {
+        if let Some(todo) = self.todos.iter_mut().find(|todo| todo.id == updated.id) {
+            *todo = updated;
+        }
+        self.clamp_selection();
+    }
+
+    pub fn set_search(&mut self, search: Option<String>) {
+        self.search = search;
+        self.selected = 0;
+    }
+
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    /// Todos of the current tab
+    pub fn visible(&self) -> Vec<&Todo> {
+        self.todos
+            .iter()
+            .filter(|todo| self.tab.shows(todo))
+            .collect()
+    }
+
+    pub fn selected_todo(&self) -> Option<&Todo> {
+        self.visible().get(self.selected).copied()
+    }
+
+    pub fn tab(&self) -> Tab {
+        self.tab
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    pub fn search(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    /// Number of todos each tab would show
+    pub fn counts(&self) -> [usize; 3] {
+        Tab::ALL.map(|tab| self.todos.iter().filter(|todo| tab.shows(todo)).count())
+    }
+
+    fn clamp_selection(&mut self) {
+        self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+    }
+
+    fn switch_tab(&mut self, step: usize) {
+        let index = (self.tab.index() + step) % Tab::ALL.len();
+        self.tab = Tab::ALL[index];
+        self.selected = 0;
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> Option<Action> {
+        if let Some(input) = &mut self.input {
+            match key {
+                Key::Char(c) => input.push(c),
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Esc => self.input = None,
+                Key::Enter => {
+                    let text = self.input.take().unwrap_or_default();
+                    let text = text.trim();
+                    return Some(if text.is_empty() {
+                        self.search = None;
+                        Action::Reload
+                    } else {
+                        Action::Search(text.to_string())
+                    });
+                }
+                _ => {}
+            }
+            return None;
+        }
+
+        self.status = None;
+        let last = self.visible().len().saturating_sub(1);
+        match key {
+            Key::Char('q') => return Some(Action::Quit),
+            Key::Esc if self.search.is_some() => {
+                self.search = None;
+                return Some(Action::Reload);
+            }
+            Key::Esc => return Some(Action::Quit),
+            Key::Char('j') | Key::Down => self.selected = (self.selected + 1).min(last),
+            Key::Char('k') | Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Char('g') | Key::Home => self.selected = 0,
+            Key::Char('G') | Key::End => self.selected = last,
+            Key::Tab => self.switch_tab(1),
+            Key::BackTab => self.switch_tab(Tab::ALL.len() - 1),
+            Key::Char('/') => self.input = Some(String::new()),
+            Key::Char('r') => {
+                return Some(match &self.search {
+                    Some(search) => Action::Search(search.clone()),
+                    None => Action::Reload,
+                })
+            }
+            Key::Char(' ') | Key::Char('x') => {
+                return self
+                    .selected_todo()
+                    .map(|todo| Action::SetCompleted(todo.id, !todo.completed))
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Short label of a priority for the list; medium, the default, is left blank
+pub fn priority_label(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Low => "low",
+        Priority::Medium => "",
+        Priority::High => "high",
+        Priority::Urgent => "URGENT",
+    }
+}
+
+#[cfg(feature = "tui")]
+pub use terminal::run;
+
+#[cfg(feature = "tui")]
+mod terminal {
+    use super::{priority_label, Action, Dashboard, Key, Tab};
+    use crate::client::{ClientError, TodoClient};
+    use crate::{Priority, UpdateTodoRequest};
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState, Tabs};
+    use ratatui::{DefaultTerminal, Frame};
+
+    const HELP: &str =
+        "j/k move  Tab switch  / search  Space toggle  r reload  Esc clear search  q quit";
+
+    fn key(event: Event) -> Option<Key> {
+        let Event::Key(key) = event else {
+            return None;
+        };
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        Some(match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Key::Char('q'),
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::BackTab => Key::BackTab,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Esc,
+            KeyCode::Backspace => Key::Backspace,
+            _ => return None,
+        })
+    }
+
+    fn priority_style(priority: Priority) -> Style {
+        match priority {
+            Priority::Low => Style::new().fg(Color::DarkGray),
+            Priority::Medium => Style::new(),
+            Priority::High => Style::new().fg(Color::Yellow),
+            Priority::Urgent => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    fn draw(frame: &mut Frame, dashboard: &Dashboard, server: &str) {
+        let [header, list, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let counts = dashboard.counts();
+        let titles = Tab::ALL
+            .iter()
+            .zip(counts)
+            .map(|(tab, count)| format!("{} ({count})", tab.title()));
+        let mut title = format!(" md-todo · {server} ");
+        if let Some(search) = dashboard.search() {
+            title.push_str(&format!("· search: {search} "));
+        }
+        let tabs = Tabs::new(titles)
+            .select(Tab::ALL.iter().position(|tab| *tab == dashboard.tab()))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(tabs, header);
+
+        let rows = dashboard.visible().into_iter().map(|todo| {
+            let id = todo.id.to_string();
+            Row::new([
+                Line::from(id[id.len() - 8..].to_string()),
+                Line::from(if todo.completed { "[x]" } else { "[ ]" }),
+                Line::styled(priority_label(todo.priority), priority_style(todo.priority)),
+                Line::from(todo.title.as_str().to_owned()),
+                Line::styled(
+                    todo.tags
+                        .iter()
+                        .map(|tag| format!("#{tag}"))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    Style::new().fg(Color::Cyan),
+                ),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(3),
+                Constraint::Length(6),
+                Constraint::Fill(3),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(["ID", "", "PRIO", "TITLE", "TAGS"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::default().borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM));
+        let mut state = TableState::default().with_selected(Some(dashboard.selected()));
+        frame.render_stateful_widget(table, list, &mut state);
+
+        let footer_text = match (dashboard.input(), dashboard.status()) {
+            (Some(input), _) => format!("/{input}"),
+            (None, Some(status)) => status.to_string(),
+            (None, None) => HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+
+    async fn perform(
+        client: &TodoClient,
+        dashboard: &mut Dashboard,
+        action: Action,
+    ) -> Result<(), ClientError> {
+        match action {
+            Action::Reload => dashboard.set_todos(client.list_todos().await?),
+            Action::Search(text) => {
+                let hits = client.search_todos(&text, true).await?;
+                let todos = hits.into_iter().map(|hit| hit.todo).collect();
+                if dashboard.search() != Some(text.as_str()) {
+                    dashboard.set_search(Some(text));
+                }
+                dashboard.set_todos(todos);
+            }
+            Action::SetCompleted(id, completed) => {
+                let update = UpdateTodoRequest {
+                    completed: Some(completed),
+                    ..Default::default()
+                };
+                let todo = client.update_todo(id, &update).await?;
+                dashboard.set_status(format!(
+                    "{} {}",
+                    if completed { "Completed" } else { "Reopened" },
+                    todo.title
+                ));
+                dashboard.replace_todo(todo);
+            }
+            Action::Quit => {}
+        }
+        Ok(())
+    }
+
+    async fn event_loop(
+        terminal: &mut DefaultTerminal,
+        client: &TodoClient,
+    ) -> Result<(), ClientError> {
+        let server = client.base_url().to_string();
+        let mut dashboard = Dashboard::new();
+        // The first load has to work; later failures only show in the status line
+        perform(client, &mut dashboard, Action::Reload).await?;
+        loop {
+            terminal.draw(|frame| draw(frame, &dashboard, &server))?;
+            let Some(key) = tokio::task::block_in_place(event::read).map(key)? else {
+                continue;
+            };
+            match dashboard.handle_key(key) {
+                Some(Action::Quit) => return Ok(()),
+                Some(action) => {
+                    if let Err(e) = perform(client, &mut dashboard, action).await {
+                        dashboard.set_status(format!("Error: {e}"));
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Runs the dashboard until the user quits, restoring the terminal either way
+    pub async fn run(client: &TodoClient) -> Result<(), ClientError> {
+        let mut terminal = ratatui::init();
+        let result = event_loop(&mut terminal, client).await;
+        ratatui::restore();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todos() -> Vec<Todo> {
+        let mut done = Todo::new_with_validation("Pay rent", "").unwrap();
+        done.completed = true;
+        vec![
+            Todo::new_with_validation("Write report", "").unwrap(),
+            done,
+            Todo::new_with_validation("Call Bob", "").unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_navigation_and_toggle() {
+        let mut dashboard = Dashboard::new();
+        dashboard.set_todos(todos());
+        assert_eq!(dashboard.counts(), [2, 1, 3]);
+        assert_eq!(dashboard.selected_todo().unwrap().title, "Write report");
+
+        dashboard.handle_key(Key::Char('j'));
+        dashboard.handle_key(Key::Down);
+        let call = dashboard.selected_todo().unwrap().clone();
+        assert_eq!(call.title, "Call Bob");
+        assert_eq!(
+            dashboard.handle_key(Key::Char(' ')),
+            Some(Action::SetCompleted(call.id, true))
+        );
+
+        // Completing it moves it off the open tab; the selection stays in range
+        let mut completed = call.clone();
+        completed.completed = true;
+        dashboard.replace_todo(completed);
+        assert_eq!(dashboard.selected_todo().unwrap().title, "Write report");
+
+        dashboard.handle_key(Key::Tab);
+        assert_eq!(dashboard.tab(), Tab::Done);
+        assert_eq!(dashboard.visible().len(), 2);
+        dashboard.handle_key(Key::BackTab);
+        assert_eq!(dashboard.tab(), Tab::Open);
+        assert_eq!(dashboard.handle_key(Key::Char('q')), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_search_input() {
+        let mut dashboard = Dashboard::new();
+        dashboard.set_todos(todos());
+        assert_eq!(dashboard.handle_key(Key::Char('/')), None);
+        for c in "rentq".chars() {
+            dashboard.handle_key(Key::Char(c));
+        }
+        dashboard.handle_key(Key::Backspace);
+        // Keys go to the input while typing, so q does not quit
+        assert_eq!(dashboard.input(), Some("rent"));
+        assert_eq!(
+            dashboard.handle_key(Key::Enter),
+            Some(Action::Search("rent".to_string()))
+        );
+        dashboard.set_search(Some("rent".to_string()));
+        assert_eq!(
+            dashboard.handle_key(Key::Char('r')),
+            Some(Action::Search("rent".to_string()))
+        );
+        assert_eq!(dashboard.handle_key(Key::Esc), Some(Action::Reload));
+        assert_eq!(dashboard.search(), None);
+        assert_eq!(dashboard.handle_key(Key::Esc), Some(Action::Quit));
+    }
+}
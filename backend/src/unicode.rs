@@ -0,0 +1,47 @@
+//! Unicode normalization of user text.
+//!
+//! The same visible string can arrive as different code points: `é` precomposed or as `e`
+//! plus a combining accent, depending on the keyboard, OS or paste source. Titles, content
+//! and labels are stored in NFC so equal-looking text is byte-equal. Comparisons that
+//! should also ignore compatibility variants, such as full-width letters or the `ﬁ`
+//! ligature, go through `fold`.
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// Canonical composition (NFC) of `text`
+pub fn nfc(text: &str) -> String {
+    match is_nfc_quick(text.chars()) {
+        IsNormalized::Yes => text.to_string(),
+        _ => text.nfc().collect(),
+    }
+}
+
+/// Compatibility decomposition (NFKD) of `text`, for search and de-duplication only
+pub fn fold(text: &str) -> String {
+    text.nfkd().collect()
+}
+
+/// Whether two strings are the same once folded
+pub fn same_folded(a: &str, b: &str) -> bool {
+    a.nfkd().eq(b.nfkd())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfc_composes() {
+        assert_eq!(nfc("Cafe\u{301}"), "Caf\u{e9}");
+        assert_eq!(nfc("plain"), "plain");
+    }
+
+    #[test]
+    fn test_fold_matches_variants() {
+        assert!(same_folded("Caf\u{e9}", "Cafe\u{301}"));
+        assert!(same_folded("\u{fb01}le", "file"));
+        assert!(same_folded("\u{ff37}ork", "Work"));
+        assert!(!same_folded("Work", "work"));
+        assert_eq!(fold("\u{e9}"), "e\u{301}");
+    }
+}
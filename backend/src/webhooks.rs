@@ -0,0 +1,841 @@
+//! Outgoing webhooks: todo events POSTed to external automations such as n8n or Zapier.
+//!
+//! Webhooks are managed under `/api/webhooks` with the admin token. Each has a target URL,
+//! a secret and the event kinds it wants (all of them when empty). `WebhookDispatcher`
+//! subscribes to the event bus and, for every event, schedules one delivery per matching
+//! active webhook. With a database a delivery is a task in the task queue, which retries
+//! it with exponential backoff while the target fails; without one it is retried in
+//! process, `IN_PROCESS_ATTEMPTS` times.
+//!
+//! A delivery POSTs the event as the broker gets it (`{"type": "created", "data": {...}}`)
+//! with these headers:
+//!
+//! - `X-MD-Todo-Event`: the event kind
+//! - `X-MD-Todo-Delivery`: an id that stays the same across retries of the delivery
+//! - `X-MD-Todo-Timestamp`: Unix time of the attempt, in seconds
+//! - `X-MD-Todo-Signature`: `sha256=` and the hex HMAC-SHA256, keyed with the secret, of
+//!   the timestamp, a `.` and the body
+//!
+//! Receivers should recompute the signature and reject old timestamps. Any 2xx answer
+//! counts as delivered. Every attempt is logged and `GET /api/webhooks/:id/deliveries`
+//! returns the latest `DELIVERY_LOG_LIMIT`.
+
+use crate::admin::{self, AdminConfig};
+use crate::errors::{ErrorCode, FieldErrorCode, ValidationErrors};
+use crate::events::{EventPublisher, PublishError, TodoEvent};
+use crate::queue::{self, QueueError, TaskHandler, TaskQueue};
+use crate::{ApiResponse, TodoError, TodoRepositoryTrait};
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Task kind of a delivery waiting in the task queue
+pub const DELIVER_WEBHOOK_TASK: &str = "deliver_webhook";
+pub const EVENT_HEADER: &str = "x-md-todo-event";
+pub const DELIVERY_HEADER: &str = "x-md-todo-delivery";
+pub const TIMESTAMP_HEADER: &str = "x-md-todo-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-md-todo-signature";
+pub const MIN_SECRET_LENGTH: usize = 16;
+pub const MAX_SECRET_LENGTH: usize = 256;
+pub const MAX_URL_LENGTH: usize = 2048;
+/// Delivery log entries returned per request, newest first
+pub const DELIVERY_LOG_LIMIT: i64 = 100;
+/// Attempts of a delivery when there is no task queue
+pub const IN_PROCESS_ATTEMPTS: i32 = 5;
+/// How long a target may take to answer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest error kept in the delivery log
+const MAX_ERROR_BYTES: usize = 512;
+
+/// `sha256=<hex HMAC-SHA256(secret, "<timestamp>.<body>")>`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// 64 random hex digits
+pub fn generate_secret() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub secret: String,
+    /// Event kinds delivered; empty for all
+    pub events: Vec<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: &TodoEvent) -> bool {
+        self.active && (self.events.is_empty() || self.events.iter().any(|e| e == event.kind()))
+    }
+}
+
+/// One attempt to deliver an event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    /// Shared by the retries of a delivery, as sent in `X-MD-Todo-Delivery`
+    pub delivery_id: Uuid,
+    pub webhook_id: Uuid,
+    #[schema(example = "created")]
+    pub event: String,
+    #[schema(value_type = Uuid)]
+    pub todo_id: Uuid,
+    pub attempted_at: DateTime<Utc>,
+    #[schema(example = true)]
+    pub succeeded: bool,
+    /// Status the target answered with; absent when it could not be reached
+    #[schema(example = 200)]
+    pub status_code: Option<i32>,
+    #[schema(example = 84)]
+    pub duration_ms: i32,
+    #[schema(example = "HTTP 503 Service Unavailable")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+#[schema(example = json!({
+    "url": "https://n8n.example.com/webhook/todos",
+    "events": ["created", "completed"]
+}))]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Event kinds to deliver: `created`, `updated`, `completed`, `deleted`; all when
+    /// absent or empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Key of the signatures; one is generated when absent
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "active_by_default")]
+    #[schema(default = true)]
+    pub active: bool,
+}
+
+fn active_by_default() -> bool {
+    true
+}
+
+/// Absent fields are left as they are
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+#[schema(example = json!({ "active": false }))]
+pub struct UpdateWebhookRequest {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub active: Option<bool>,
+}
+
+fn validate(
+    url: Option<&str>,
+    events: Option<&[String]>,
+    secret: Option<&str>,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    if let Some(url) = url {
+        if url.len() > MAX_URL_LENGTH {
+            errors.add(
+                "url",
+                FieldErrorCode::TooLong,
+                format!("url must be at most {} bytes", MAX_URL_LENGTH),
+            );
+        } else {
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => {}
+                _ => errors.add(
+                    "url",
+                    FieldErrorCode::Invalid,
+                    "url must be an absolute http or https URL".to_string(),
+                ),
+            }
+        }
+    }
+    for event in events.unwrap_or_default() {
+        if !TodoEvent::KINDS.contains(&event.as_str()) {
+            errors.add(
+                "events",
+                FieldErrorCode::Invalid,
+                format!(
+                    "Unknown event {:?}, expected one of {}",
+                    event,
+                    TodoEvent::KINDS.join(", ")
+                ),
+            );
+        }
+    }
+    if let Some(secret) = secret {
+        let length = secret.chars().count();
+        if !(MIN_SECRET_LENGTH..=MAX_SECRET_LENGTH).contains(&length) {
+            errors.add(
+                "secret",
+                FieldErrorCode::OutOfRange,
+                format!(
+                    "secret must be {} to {} characters",
+                    MIN_SECRET_LENGTH, MAX_SECRET_LENGTH
+                ),
+            );
+        }
+    }
+    errors.into_result()
+}
+
+fn normalized_events(events: &[String]) -> Vec<String> {
+    let mut events = events.to_vec();
+    events.sort();
+    events.dedup();
+    events
+}
+
+impl CreateWebhookRequest {
+    pub fn into_webhook(self) -> Result<Webhook, ValidationErrors> {
+        validate(Some(&self.url), Some(&self.events), self.secret.as_deref())?;
+        let now = Utc::now();
+        Ok(Webhook {
+            id: Uuid::now_v7(),
+            url: self.url,
+            secret: self.secret.unwrap_or_else(generate_secret),
+            events: normalized_events(&self.events),
+            active: self.active,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+}
+
+impl UpdateWebhookRequest {
+    pub fn apply(self, webhook: &mut Webhook) -> Result<(), ValidationErrors> {
+        validate(
+            self.url.as_deref(),
+            self.events.as_deref(),
+            self.secret.as_deref(),
+        )?;
+        if let Some(url) = self.url {
+            webhook.url = url;
+        }
+        if let Some(events) = self.events {
+            webhook.events = normalized_events(&events);
+        }
+        if let Some(secret) = self.secret {
+            webhook.secret = secret;
+        }
+        if let Some(active) = self.active {
+            webhook.active = active;
+        }
+        Ok(())
+    }
+}
+
+/// A webhook as shown to its owner; the secret only right after it was set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WebhookInfo {
+    pub id: Uuid,
+    #[schema(example = "https://n8n.example.com/webhook/todos")]
+    pub url: String,
+    #[schema(example = json!(["created", "completed"]))]
+    pub events: Vec<String>,
+    #[schema(example = true)]
+    pub active: bool,
+    /// Only in the answer to the request that set it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub secret: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WebhookInfo {
+    fn new(webhook: Webhook, show_secret: bool) -> Self {
+        Self {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events,
+            active: webhook.active,
+            secret: show_secret.then_some(webhook.secret),
+            created_at: webhook.created_at,
+            updated_at: webhook.updated_at,
+        }
+    }
+}
+
+impl From<Webhook> for WebhookInfo {
+    fn from(webhook: Webhook) -> Self {
+        Self::new(webhook, false)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<WebhookInfo>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<WebhookInfo>> for WebhookResponse {
+    fn from(response: ApiResponse<WebhookInfo>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookListResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<WebhookInfo>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<WebhookInfo>>> for WebhookListResponse {
+    fn from(response: ApiResponse<Vec<WebhookInfo>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDeliveryLogResponse {
+    #[schema(example = true)]
+    pub success: bool,
+    pub data: Option<Vec<WebhookDelivery>>,
+    #[schema(example = "Error message if any")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl From<ApiResponse<Vec<WebhookDelivery>>> for WebhookDeliveryLogResponse {
+    fn from(response: ApiResponse<Vec<WebhookDelivery>>) -> Self {
+        Self {
+            success: response.success,
+            data: response.data,
+            error: response.error,
+            code: response.code,
+        }
+    }
+}
+
+/// A scheduled delivery; the payload of `DELIVER_WEBHOOK_TASK`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Delivery {
+    webhook_id: Uuid,
+    delivery_id: Uuid,
+    event: TodoEvent,
+}
+
+/// Sends deliveries and logs every attempt
+pub struct WebhookSender<R> {
+    repository: Arc<R>,
+    client: reqwest::Client,
+}
+
+impl<R: TodoRepositoryTrait> WebhookSender<R> {
+    pub fn new(repository: Arc<R>) -> Self {
+        Self {
+            repository,
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .user_agent(concat!("md-todo-webhooks/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .expect("Failed to build the webhook HTTP client"),
+        }
+    }
+
+    /// One attempt; fails when the target should be tried again
+    async fn attempt(&self, delivery: &Delivery) -> Result<(), QueueError> {
+        // Deleted or paused since the event: the delivery is dropped
+        let Some(webhook) = self.repository.get_webhook(delivery.webhook_id).await? else {
+            return Ok(());
+        };
+        if !webhook.active {
+            return Ok(());
+        }
+        let body = serde_json::to_vec(&delivery.event)?;
+        let timestamp = Utc::now().timestamp();
+        let started = Instant::now();
+        let result = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, delivery.event.kind())
+            .header(DELIVERY_HEADER, delivery.delivery_id.to_string())
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, timestamp, &body))
+            .body(body)
+            .send()
+            .await;
+        let duration_ms = started.elapsed().as_millis().try_into().unwrap_or(i32::MAX);
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("HTTP {}", response.status())),
+            ),
+            Err(e) => (
+                e.status().map(|status| status.as_u16()),
+                Some(crate::truncate_to_bytes(&e.to_string(), MAX_ERROR_BYTES).to_string()),
+            ),
+        };
+        let log = WebhookDelivery {
+            delivery_id: delivery.delivery_id,
+            webhook_id: webhook.id,
+            event: delivery.event.kind().to_string(),
+            todo_id: delivery.event.todo_id().into(),
+            attempted_at: Utc::now(),
+            succeeded: error.is_none(),
+            status_code: status_code.map(i32::from),
+            duration_ms,
+            error: error.clone(),
+        };
+        if let Err(e) = self.repository.record_webhook_delivery(&log).await {
+            tracing::warn!(
+                "Failed to log delivery {} of webhook {}: {}",
+                delivery.delivery_id,
+                webhook.id,
+                e
+            );
+        }
+        match error {
+            None => {
+                tracing::debug!(
+                    "Delivered {} event to webhook {}",
+                    delivery.event.kind(),
+                    webhook.id
+                );
+                Ok(())
+            }
+            Some(error) => {
+                tracing::warn!(
+                    "Delivery {} to webhook {} failed: {}",
+                    delivery.delivery_id,
+                    webhook.id,
+                    error
+                );
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Attempts with the task queue's backoff until one succeeds or attempts run out
+    async fn deliver_in_process(&self, delivery: Delivery) {
+        for attempt in 1..=IN_PROCESS_ATTEMPTS {
+            if self.attempt(&delivery).await.is_ok() {
+                return;
+            }
+            if attempt < IN_PROCESS_ATTEMPTS {
+                tokio::time::sleep(queue::retry_delay(attempt)).await;
+            }
+        }
+        tracing::error!(
+            "Gave up delivery {} to webhook {} after {} attempts",
+            delivery.delivery_id,
+            delivery.webhook_id,
+            IN_PROCESS_ATTEMPTS
+        );
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepositoryTrait + 'static> TaskHandler for WebhookSender<R> {
+    async fn run(&self, payload: &serde_json::Value) -> Result<(), QueueError> {
+        let delivery: Delivery = serde_json::from_value(payload.clone())?;
+        self.attempt(&delivery).await
+    }
+}
+
+/// Event bus subscriber that schedules a delivery per matching webhook
+pub struct WebhookDispatcher<R> {
+    repository: Arc<R>,
+    sender: Arc<WebhookSender<R>>,
+    queue: Option<TaskQueue>,
+}
+
+impl<R: TodoRepositoryTrait + 'static> WebhookDispatcher<R> {
+    /// Without `queue`, deliveries are retried in process and lost on restart
+    pub fn new(repository: Arc<R>, queue: Option<TaskQueue>) -> Self {
+        Self {
+            sender: Arc::new(WebhookSender::new(repository.clone())),
+            repository,
+            queue,
+        }
+    }
+
+    /// The handler of `DELIVER_WEBHOOK_TASK`
+    pub fn sender(&self) -> Arc<WebhookSender<R>> {
+        self.sender.clone()
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepositoryTrait + 'static> EventPublisher for WebhookDispatcher<R> {
+    async fn publish(&self, event: &TodoEvent) -> Result<(), PublishError> {
+        let webhooks = self.repository.list_webhooks().await?;
+        for webhook in webhooks.into_iter().filter(|webhook| webhook.wants(event)) {
+            let delivery = Delivery {
+                webhook_id: webhook.id,
+                delivery_id: Uuid::now_v7(),
+                event: event.clone(),
+            };
+            match &self.queue {
+                Some(queue) => {
+                    queue
+                        .enqueue(DELIVER_WEBHOOK_TASK, &serde_json::to_value(&delivery)?)
+                        .await?;
+                }
+                None => {
+                    let sender = self.sender.clone();
+                    tokio::spawn(async move { sender.deliver_in_process(delivery).await });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn not_found(id: Uuid) -> TodoError {
+    TodoError::NotFound(format!("Webhook {}", id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Webhooks, oldest first, without their secrets", body = WebhookListResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Webhooks"
+)]
+pub async fn list_webhooks<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<WebhookListResponse>, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    match repository.list_webhooks().await {
+        Ok(webhooks) => {
+            let webhooks = webhooks.into_iter().map(WebhookInfo::from).collect();
+            Ok(Json(ApiResponse::success(webhooks).into()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list webhooks: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/webhooks",
+    params(
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 200, description = "Webhook created; the only answer that carries its secret", body = WebhookResponse),
+        (status = 400, description = "Invalid URL, event or secret", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Webhooks"
+)]
+pub async fn create_webhook<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<Json<WebhookResponse>, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    let webhook = request.into_webhook().map_err(|errors| {
+        tracing::warn!("Validation failed for webhook: {}", errors);
+        TodoError::from(errors)
+    })?;
+    match repository.create_webhook(&webhook).await {
+        Ok(webhook) => {
+            tracing::info!("Created webhook {} for {}", webhook.id, webhook.url);
+            Ok(Json(
+                ApiResponse::success(WebhookInfo::new(webhook, true)).into(),
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create webhook: {}", e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Webhook ID"),
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "The webhook, without its secret", body = WebhookResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No such webhook, or admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Webhooks"
+)]
+pub async fn get_webhook<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookResponse>, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    match repository.get_webhook(id).await {
+        Ok(Some(webhook)) => Ok(Json(
+            ApiResponse::success(WebhookInfo::from(webhook)).into(),
+        )),
+        Ok(None) => Err(not_found(id)),
+        Err(e) => {
+            tracing::error!("Failed to fetch webhook {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/webhooks/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Webhook ID"),
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    request_body = UpdateWebhookRequest,
+    responses(
+        (status = 200, description = "Webhook updated; carries the secret when the request set one", body = WebhookResponse),
+        (status = 400, description = "Invalid URL, event or secret", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No such webhook, or admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Webhooks"
+)]
+pub async fn update_webhook<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateWebhookRequest>,
+) -> Result<Json<WebhookResponse>, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    let internal_error = |e| {
+        tracing::error!("Failed to update webhook {}: {}", id, e);
+        e
+    };
+    let mut webhook = repository
+        .get_webhook(id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| not_found(id))?;
+    let show_secret = request.secret.is_some();
+    request.apply(&mut webhook).map_err(|errors| {
+        tracing::warn!("Validation failed for webhook {}: {}", id, errors);
+        TodoError::from(errors)
+    })?;
+    let webhook = repository
+        .update_webhook(&webhook)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| not_found(id))?;
+    tracing::info!("Updated webhook {}", id);
+    Ok(Json(
+        ApiResponse::success(WebhookInfo::new(webhook, show_secret)).into(),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/webhooks/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Webhook ID"),
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 204, description = "Webhook deleted with its delivery log; pending deliveries are dropped"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No such webhook, or admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Webhooks"
+)]
+pub async fn delete_webhook<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    match repository.delete_webhook(id).await {
+        Ok(true) => {
+            tracing::info!("Deleted webhook {}", id);
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(not_found(id)),
+        Err(e) => {
+            tracing::error!("Failed to delete webhook {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}/deliveries",
+    params(
+        ("id" = Uuid, Path, description = "Webhook ID"),
+        ("Authorization" = String, Header, description = "Bearer ADMIN_TOKEN")
+    ),
+    responses(
+        (status = 200, description = "Latest delivery attempts, newest first", body = WebhookDeliveryLogResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "No such webhook, or admin endpoints are not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "Webhooks"
+)]
+pub async fn list_deliveries<R: TodoRepositoryTrait>(
+    State(repository): State<Arc<R>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookDeliveryLogResponse>, TodoError> {
+    config.authorize(&headers).map_err(admin::refusal)?;
+    let internal_error = |e| {
+        tracing::error!("Failed to read the delivery log of webhook {}: {}", id, e);
+        e
+    };
+    if repository
+        .get_webhook(id)
+        .await
+        .map_err(internal_error)?
+        .is_none()
+    {
+        return Err(not_found(id));
+    }
+    let log = repository
+        .webhook_deliveries(id, DELIVERY_LOG_LIMIT)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(ApiResponse::success(log).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(url: &str) -> CreateWebhookRequest {
+        CreateWebhookRequest {
+            url: url.to_string(),
+            active: true,
+            ..Default::default()
+        }
+    }
+
+    fn fields(errors: ValidationErrors) -> Vec<(String, FieldErrorCode)> {
+        errors
+            .into_fields()
+            .into_iter()
+            .map(|error| (error.field, error.code))
+            .collect()
+    }
+
+    #[test]
+    fn test_sign() {
+        // printf '1700000000.{}' | openssl dgst -sha256 -hmac 'a secret of 16+ chars'
+        assert_eq!(
+            sign("a secret of 16+ chars", 1_700_000_000, b"{}"),
+            "sha256=7b791eb4bf2b7a238272a3984fb84f45f9f5bee5fe370337f97b314baa39fda9"
+        );
+    }
+
+    #[test]
+    fn test_into_webhook() {
+        let mut create = request("https://hooks.example.com/todos");
+        create.events = vec![
+            "deleted".to_string(),
+            "created".to_string(),
+            "created".to_string(),
+        ];
+        let webhook = create.into_webhook().unwrap();
+        assert_eq!(webhook.events, ["created", "deleted"]);
+        assert_eq!(webhook.secret.len(), 64);
+
+        let mut create = request("ftp://hooks.example.com");
+        create.events = vec!["archived".to_string()];
+        create.secret = Some("short".to_string());
+        assert_eq!(
+            fields(create.into_webhook().unwrap_err()),
+            [
+                ("url".to_string(), FieldErrorCode::Invalid),
+                ("events".to_string(), FieldErrorCode::Invalid),
+                ("secret".to_string(), FieldErrorCode::OutOfRange),
+            ]
+        );
+        assert!(request("/relative").into_webhook().is_err());
+    }
+
+    #[test]
+    fn test_wants() {
+        let mut webhook = request("https://hooks.example.com").into_webhook().unwrap();
+        let deleted = TodoEvent::Deleted {
+            id: crate::domain::TodoId::new(),
+        };
+        assert!(webhook.wants(&deleted));
+        webhook.events = vec!["created".to_string()];
+        assert!(!webhook.wants(&deleted));
+        webhook.events = vec!["deleted".to_string()];
+        webhook.active = false;
+        assert!(!webhook.wants(&deleted));
+    }
+}
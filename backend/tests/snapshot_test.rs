@@ -0,0 +1,59 @@
+//! Response shapes of the main endpoints; see `md_todo_backend::snapshot`. After an
+//! intended change, run `UPDATE_SNAPSHOTS=1 cargo test --test snapshot_test` and review
+//! the changed files in `tests/snapshots`.
+
+use md_todo_backend::snapshot::SnapshotApp;
+use serde_json::json;
+
+fn snapshots() -> SnapshotApp {
+    SnapshotApp::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots"))
+}
+
+#[tokio::test]
+async fn test_todo_lifecycle_snapshots() {
+    let app = snapshots();
+    app.check("health", "GET", "/health", None).await;
+    app.check("list_todos_empty", "GET", "/api/todos", None)
+        .await;
+
+    let created = app
+        .check(
+            "create_todo",
+            "POST",
+            "/api/todos",
+            Some(json!({
+                "title": "Write the report",
+                "content": "- [ ] outline\n- [ ] draft",
+                "tags": ["work"],
+                "priority": "high",
+                "estimate_minutes": 90,
+            })),
+        )
+        .await;
+    let uri = format!("/api/todos/{}", created["data"]["id"].as_str().unwrap());
+    app.check("get_todo", "GET", &uri, None).await;
+    app.check(
+        "update_todo",
+        "PATCH",
+        &uri,
+        Some(json!({ "completed": true })),
+    )
+    .await;
+    app.check("list_todos", "GET", "/api/todos", None).await;
+    app.check("delete_todo", "DELETE", &uri, None).await;
+    app.check("get_todo_missing", "GET", &uri, None).await;
+}
+
+#[tokio::test]
+async fn test_error_snapshots() {
+    let app = snapshots();
+    app.check(
+        "create_todo_invalid",
+        "POST",
+        "/api/todos",
+        Some(json!({ "title": "", "content": "" })),
+    )
+    .await;
+    app.check("get_todo_malformed_id", "GET", "/api/todos/not-an-id", None)
+        .await;
+}
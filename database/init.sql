@@ -5,4 +5,129 @@
 \i /docker-entrypoint-initdb.d/migrations/001_initial_schema.sql
 
 -- Run migration 002: Sample data
-\i /docker-entrypoint-initdb.d/migrations/002_sample_data.sql
\ No newline at end of file
+\i /docker-entrypoint-initdb.d/migrations/002_sample_data.sql
+
+-- Run migration 003: Todo version column
+\i /docker-entrypoint-initdb.d/migrations/003_todo_version.sql
+
+-- Run migration 004: Short links
+\i /docker-entrypoint-initdb.d/migrations/004_short_links.sql
+
+-- Run migration 005: Todo metadata
+\i /docker-entrypoint-initdb.d/migrations/005_todo_metadata.sql
+
+-- Run migration 006: Todo labels
+\i /docker-entrypoint-initdb.d/migrations/006_todo_labels.sql
+
+-- Run migration 007: Todo estimates
+\i /docker-entrypoint-initdb.d/migrations/007_todo_estimates.sql
+
+-- Run migration 008: Content compression
+\i /docker-entrypoint-initdb.d/migrations/008_content_compression.sql
+
+-- Run migration 009: Stable list order
+\i /docker-entrypoint-initdb.d/migrations/009_stable_list_order.sql
+
+-- Run migration 010: Share links
+\i /docker-entrypoint-initdb.d/migrations/010_share_links.sql
+
+-- Run migration 011: List sort indexes
+\i /docker-entrypoint-initdb.d/migrations/011_list_sort_indexes.sql
+
+-- Run migration 012: Todo search
+\i /docker-entrypoint-initdb.d/migrations/012_todo_search.sql
+
+-- Run migration 013: Todo tags
+\i /docker-entrypoint-initdb.d/migrations/013_todo_tags.sql
+
+-- Run migration 014: Todo priority
+\i /docker-entrypoint-initdb.d/migrations/014_todo_priority.sql
+
+-- Run migration 015: Todo lists
+\i /docker-entrypoint-initdb.d/migrations/015_todo_lists.sql
+
+-- Run migration 016: Todo archive
+\i /docker-entrypoint-initdb.d/migrations/016_todo_archive.sql
+
+-- Run migration 017: Task queue
+\i /docker-entrypoint-initdb.d/migrations/017_task_queue.sql
+
+-- Run migration 018: Import jobs
+\i /docker-entrypoint-initdb.d/migrations/018_import_jobs.sql
+
+-- Run migration 019: Completion days
+\i /docker-entrypoint-initdb.d/migrations/019_completion_days.sql
+
+-- Run migration 020: Daily plans
+\i /docker-entrypoint-initdb.d/migrations/020_daily_plans.sql
+
+-- Run migration 021: Todo relations
+\i /docker-entrypoint-initdb.d/migrations/021_todo_relations.sql
+
+-- Run migration 022: Todo language
+\i /docker-entrypoint-initdb.d/migrations/022_todo_language.sql
+
+-- Run migration 023: Validation policy
+\i /docker-entrypoint-initdb.d/migrations/023_validation_policy.sql
+
+-- Run migration 024: Moderation flags
+\i /docker-entrypoint-initdb.d/migrations/024_moderation_flags.sql
+
+-- Run migration 025: Encrypted todos
+\i /docker-entrypoint-initdb.d/migrations/025_encrypted_todos.sql
+
+-- Run migration 026: Outgoing webhooks
+\i /docker-entrypoint-initdb.d/migrations/026_webhooks.sql
+
+-- Run migration 027: Split relation
+\i /docker-entrypoint-initdb.d/migrations/027_split_relation.sql
+
+-- Run migration 028: Users
+\i /docker-entrypoint-initdb.d/migrations/028_users.sql
+\i /docker-entrypoint-initdb.d/migrations/029_user_completion_days.sql
+\i /docker-entrypoint-initdb.d/migrations/030_user_daily_plans.sql
+\i /docker-entrypoint-initdb.d/migrations/031_user_row_security.sql
+\i /docker-entrypoint-initdb.d/migrations/032_todo_owner_security.sql
+
+-- History for the backend's migration runner (MIGRATIONS_DIR), so it only applies
+-- migrations added after this database was created; add a row with every migration above
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO schema_migrations (version, name) VALUES
+    (1, 'initial_schema'),
+    (2, 'sample_data'),
+    (3, 'todo_version'),
+    (4, 'short_links'),
+    (5, 'todo_metadata'),
+    (6, 'todo_labels'),
+    (7, 'todo_estimates'),
+    (8, 'content_compression'),
+    (9, 'stable_list_order'),
+    (10, 'share_links'),
+    (11, 'list_sort_indexes'),
+    (12, 'todo_search'),
+    (13, 'todo_tags'),
+    (14, 'todo_priority'),
+    (15, 'todo_lists'),
+    (16, 'todo_archive'),
+    (17, 'task_queue'),
+    (18, 'import_jobs'),
+    (19, 'completion_days'),
+    (20, 'daily_plans'),
+    (21, 'todo_relations'),
+    (22, 'todo_language'),
+    (23, 'validation_policy'),
+    (24, 'moderation_flags'),
+    (25, 'encrypted_todos'),
+    (26, 'webhooks'),
+    (27, 'split_relation'),
+    (28, 'users'),
+    (29, 'user_completion_days'),
+    (30, 'user_daily_plans'),
+    (31, 'user_row_security'),
+    (32, 'todo_owner_security')
+ON CONFLICT (version) DO NOTHING;
@@ -1,5 +1,6 @@
 -- Migration 001: Initial schema for md-todo application
 -- This migration creates the base todos table with UUID v7 primary key
+-- migrate: online
 
 -- Enable UUID extension
 CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
@@ -1,5 +1,6 @@
 -- Migration 002: Sample data for development
 -- This migration adds sample todos for testing and development
+-- migrate: online
 
 -- Insert sample data for development
 INSERT INTO todos (title, content, completed) VALUES
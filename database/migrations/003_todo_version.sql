@@ -0,0 +1,5 @@
+-- Migration 003: Version column for optimistic concurrency
+-- Every write increments the version so editors can detect concurrent changes
+-- migrate: online
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
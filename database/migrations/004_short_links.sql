@@ -0,0 +1,14 @@
+-- Migration 004: Short links
+-- Human-friendly /t/:short_id links; short_id is the base62 encoding of seq
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS short_links (
+    seq BIGSERIAL PRIMARY KEY,
+    todo_id UUID NOT NULL UNIQUE REFERENCES todos(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Give existing todos a link, oldest first
+INSERT INTO short_links (todo_id)
+SELECT id FROM todos ORDER BY created_at, id
+ON CONFLICT (todo_id) DO NOTHING;
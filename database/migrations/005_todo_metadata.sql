@@ -0,0 +1,13 @@
+-- Migration 005: Custom fields
+-- Free-form JSONB metadata per todo, validated against the definitions in metadata_fields
+-- migrate: online
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS metadata JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+CREATE TABLE IF NOT EXISTS metadata_fields (
+    key TEXT PRIMARY KEY,
+    field_type TEXT NOT NULL CHECK (field_type IN ('string', 'number', 'boolean', 'enum')),
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    enum_values TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
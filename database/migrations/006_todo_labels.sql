@@ -0,0 +1,8 @@
+-- Migration 006: Hierarchical labels
+-- Labels are materialized paths such as 'work/clientA/urgent'; filtering by a parent
+-- matches the label itself or anything starting with '<parent>/'
+-- migrate: locking
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS labels TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS idx_todos_labels ON todos USING GIN (labels);
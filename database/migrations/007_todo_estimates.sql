@@ -0,0 +1,5 @@
+-- Migration 007: Time estimates
+-- Optional estimate in minutes, summed per week by GET /api/stats/workload
+-- migrate: locking
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS estimate_minutes INTEGER CHECK (estimate_minutes >= 0);
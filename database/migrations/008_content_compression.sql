@@ -0,0 +1,6 @@
+-- Migration 008: Optional content compression
+-- When content_zstd is set it holds the zstd-compressed body and content is empty;
+-- see CONTENT_COMPRESSION_MIN_BYTES and the compress_content tool
+-- migrate: online
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS content_zstd BYTEA;
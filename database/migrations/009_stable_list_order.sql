@@ -0,0 +1,7 @@
+-- Migration 009: Stable list order
+-- Lists are ordered by (created_at, id) so todos created in the same instant keep a fixed
+-- order between requests; this index serves that order and created_at range queries alike
+-- migrate: locking
+
+CREATE INDEX IF NOT EXISTS idx_todos_created_at_id ON todos(created_at DESC, id DESC);
+DROP INDEX IF EXISTS idx_todos_created_at;
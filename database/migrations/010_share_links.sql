@@ -0,0 +1,57 @@
+-- Migration 010: Share links
+-- Unguessable /s/:token links for showing a todo outside the app, optionally protected by a
+-- passcode and limited in views and lifetime. Every attempt to open one is logged.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS share_links (
+    token TEXT PRIMARY KEY,
+    todo_id UUID NOT NULL,
+    -- '<salt>$<hex HMAC-SHA256>' of the passcode; NULL when anyone with the link may open it
+    passcode_hash TEXT,
+    max_views INTEGER CHECK (max_views > 0),
+    view_count INTEGER NOT NULL DEFAULT 0,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_links_todo_id ON share_links(todo_id);
+
+CREATE TABLE IF NOT EXISTS share_link_access (
+    id BIGSERIAL PRIMARY KEY,
+    token TEXT NOT NULL REFERENCES share_links(token) ON DELETE CASCADE,
+    accessed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    outcome TEXT NOT NULL,
+    -- First X-Forwarded-For address, when a proxy sets it
+    client TEXT,
+    user_agent TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_link_access_token ON share_link_access(token, accessed_at DESC);
+
+CREATE OR REPLACE FUNCTION delete_todo_share_links()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM share_links WHERE todo_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- A todos table partitioned by database/optional/partition_todos_by_month.sql cannot be
+-- referenced by id alone, so there a trigger removes a todo's share links instead
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'todos'::regclass) THEN
+        DROP TRIGGER IF EXISTS delete_todos_share_links ON todos;
+        CREATE TRIGGER delete_todos_share_links
+            AFTER DELETE ON todos
+            FOR EACH ROW
+            EXECUTE FUNCTION delete_todo_share_links();
+    ELSIF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'share_links_todo_id_fkey'
+    ) THEN
+        ALTER TABLE share_links
+            ADD CONSTRAINT share_links_todo_id_fkey
+            FOREIGN KEY (todo_id) REFERENCES todos(id) ON DELETE CASCADE;
+    END IF;
+END $$;
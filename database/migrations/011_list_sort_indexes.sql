@@ -0,0 +1,8 @@
+-- Migration 011: List sort indexes
+-- GET /api/todos can also be sorted by updated_at or title; like the default order, each
+-- sort key is paired with id so keyset pages walk an index in either direction
+-- migrate: locking
+
+CREATE INDEX IF NOT EXISTS idx_todos_updated_at_id ON todos(updated_at DESC, id DESC);
+DROP INDEX IF EXISTS idx_todos_updated_at;
+CREATE INDEX IF NOT EXISTS idx_todos_title_id ON todos(title, id);
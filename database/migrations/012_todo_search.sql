@@ -0,0 +1,11 @@
+-- Migration 012: Todo search
+-- GET /api/search matches words with full-text search over title and content, or, with
+-- fuzzy=true, titles by trigram similarity so typos still find them. The expression of
+-- idx_todos_search must stay identical to the one the backend queries with.
+-- migrate: locking
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_todos_search ON todos
+    USING GIN (to_tsvector('simple', title || ' ' || content));
+CREATE INDEX IF NOT EXISTS idx_todos_title_trgm ON todos USING GIN (title gin_trgm_ops);
@@ -0,0 +1,45 @@
+-- Migration 013: Tags
+-- Named tags shared between todos, renamed or deleted once for every todo carrying them.
+-- Names are stored normalized (NFC, trimmed, lowercase) by the backend.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS tags (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS todo_tags (
+    todo_id UUID NOT NULL,
+    tag_id UUID NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (todo_id, tag_id)
+);
+
+-- The primary key serves lookups by todo; this one serves counts and ?tag= filters
+CREATE INDEX IF NOT EXISTS idx_todo_tags_tag_id ON todo_tags(tag_id);
+
+CREATE OR REPLACE FUNCTION delete_todo_tags()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM todo_tags WHERE todo_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- As for share links, a partitioned todos table gets a trigger instead of a foreign key
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'todos'::regclass) THEN
+        DROP TRIGGER IF EXISTS delete_todos_tags ON todos;
+        CREATE TRIGGER delete_todos_tags
+            AFTER DELETE ON todos
+            FOR EACH ROW
+            EXECUTE FUNCTION delete_todo_tags();
+    ELSIF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'todo_tags_todo_id_fkey'
+    ) THEN
+        ALTER TABLE todo_tags
+            ADD CONSTRAINT todo_tags_todo_id_fkey
+            FOREIGN KEY (todo_id) REFERENCES todos(id) ON DELETE CASCADE;
+    END IF;
+END $$;
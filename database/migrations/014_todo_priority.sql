@@ -0,0 +1,16 @@
+-- Migration 014: Priority
+-- How urgent a todo is. Enum values sort in declaration order, so ORDER BY priority goes
+-- from low to urgent.
+-- migrate: locking
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'todo_priority') THEN
+        CREATE TYPE todo_priority AS ENUM ('low', 'medium', 'high', 'urgent');
+    END IF;
+END $$;
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS priority todo_priority NOT NULL DEFAULT 'medium';
+
+-- Paired with id like the other sort keys, for keyset pages sorted by priority
+CREATE INDEX IF NOT EXISTS idx_todos_priority_id ON todos(priority, id);
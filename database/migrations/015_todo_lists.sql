@@ -0,0 +1,20 @@
+-- Migration 015: Lists
+-- Projects that group todos, such as "Home" or "Client A". A todo belongs to at most one
+-- list; todos without one stay in the default, unlisted view.
+-- migrate: locking
+
+CREATE TABLE IF NOT EXISTS todo_lists (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The backend moves a deleted list's todos out first to bump their versions; the foreign
+-- key only backs that up
+ALTER TABLE todos
+    ADD COLUMN IF NOT EXISTS list_id UUID REFERENCES todo_lists(id) ON DELETE SET NULL;
+
+-- Serves GET /api/lists/:id/todos and the todo counts of GET /api/lists
+CREATE INDEX IF NOT EXISTS idx_todos_list_id ON todos(list_id);
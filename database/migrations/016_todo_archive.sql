@@ -0,0 +1,7 @@
+-- Migration 016: Archive
+-- Todos put out of sight without deleting them, whether completed or not. Lists leave
+-- archived todos out unless asked with include_archived.
+-- migrate: online
+
+-- A constant default only touches the catalog, so existing rows are not rewritten
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,19 @@
+-- Migration 017: Task queue
+-- Work run outside the request that caused it, by workers on every replica. Successful
+-- tasks are deleted; tasks out of attempts keep their row with dead_at set.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS task_queue (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 10,
+    run_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_error TEXT,
+    dead_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Workers poll for the oldest due task that is still alive
+CREATE INDEX IF NOT EXISTS idx_task_queue_due ON task_queue(run_at, id) WHERE dead_at IS NULL;
@@ -0,0 +1,24 @@
+-- Migration 018: Import jobs
+-- Progress of imports run in the background by POST /api/import. Items waiting to be
+-- created are kept with the job and dropped once it completes or is cancelled.
+-- migrate: online
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'import_status') THEN
+        CREATE TYPE import_status AS ENUM ('queued', 'running', 'completed', 'cancelled');
+    END IF;
+END $$;
+
+CREATE TABLE IF NOT EXISTS import_jobs (
+    id UUID PRIMARY KEY,
+    status import_status NOT NULL DEFAULT 'queued',
+    total INTEGER NOT NULL,
+    processed INTEGER NOT NULL DEFAULT 0,
+    created INTEGER NOT NULL DEFAULT 0,
+    errors JSONB NOT NULL DEFAULT '[]',
+    items JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP WITH TIME ZONE
+);
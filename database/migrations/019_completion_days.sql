@@ -0,0 +1,9 @@
+-- Migration 019: Completion days
+-- How many todos were marked completed on each UTC day, counted in the transaction that
+-- completes them. Streaks are read from it, so deleting a todo keeps its completion.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS completion_days (
+    day DATE PRIMARY KEY,
+    completions INTEGER NOT NULL CHECK (completions > 0)
+);
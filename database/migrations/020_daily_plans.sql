@@ -0,0 +1,40 @@
+-- Migration 020: Daily plans
+-- The todos pinned as one UTC day's plan, in working order. Deleting a todo takes it
+-- out of every plan.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS daily_plans (
+    day DATE NOT NULL,
+    position INTEGER NOT NULL,
+    todo_id UUID NOT NULL,
+    PRIMARY KEY (day, position),
+    UNIQUE (day, todo_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_daily_plans_todo_id ON daily_plans(todo_id);
+
+CREATE OR REPLACE FUNCTION delete_todo_daily_plans()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM daily_plans WHERE todo_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- As for share links, a partitioned todos table gets a trigger instead of a foreign key
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'todos'::regclass) THEN
+        DROP TRIGGER IF EXISTS delete_todos_daily_plans ON todos;
+        CREATE TRIGGER delete_todos_daily_plans
+            AFTER DELETE ON todos
+            FOR EACH ROW
+            EXECUTE FUNCTION delete_todo_daily_plans();
+    ELSIF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'daily_plans_todo_id_fkey'
+    ) THEN
+        ALTER TABLE daily_plans
+            ADD CONSTRAINT daily_plans_todo_id_fkey
+            FOREIGN KEY (todo_id) REFERENCES todos(id) ON DELETE CASCADE;
+    END IF;
+END $$;
@@ -0,0 +1,60 @@
+-- Migration 021: Todo relations
+-- Typed, informational links between two todos (relates_to, duplicates, caused_by).
+-- Two todos have at most one relation of each kind, whichever side it was created from.
+-- migrate: online
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'todo_relation_kind') THEN
+        CREATE TYPE todo_relation_kind AS ENUM ('relates_to', 'duplicates', 'caused_by');
+    END IF;
+END $$;
+
+CREATE TABLE IF NOT EXISTS todo_relations (
+    id UUID PRIMARY KEY,
+    todo_id UUID NOT NULL,
+    related_id UUID NOT NULL,
+    kind todo_relation_kind NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (todo_id <> related_id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_todo_relations_pair
+    ON todo_relations (LEAST(todo_id, related_id), GREATEST(todo_id, related_id), kind);
+CREATE INDEX IF NOT EXISTS idx_todo_relations_todo_id ON todo_relations(todo_id);
+CREATE INDEX IF NOT EXISTS idx_todo_relations_related_id ON todo_relations(related_id);
+
+CREATE OR REPLACE FUNCTION delete_todo_relations()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM todo_relations WHERE todo_id = OLD.id OR related_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- As for share links, a partitioned todos table gets a trigger instead of foreign keys
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'todos'::regclass) THEN
+        DROP TRIGGER IF EXISTS delete_todos_relations ON todos;
+        CREATE TRIGGER delete_todos_relations
+            AFTER DELETE ON todos
+            FOR EACH ROW
+            EXECUTE FUNCTION delete_todo_relations();
+    ELSE
+        IF NOT EXISTS (
+            SELECT 1 FROM pg_constraint WHERE conname = 'todo_relations_todo_id_fkey'
+        ) THEN
+            ALTER TABLE todo_relations
+                ADD CONSTRAINT todo_relations_todo_id_fkey
+                FOREIGN KEY (todo_id) REFERENCES todos(id) ON DELETE CASCADE;
+        END IF;
+        IF NOT EXISTS (
+            SELECT 1 FROM pg_constraint WHERE conname = 'todo_relations_related_id_fkey'
+        ) THEN
+            ALTER TABLE todo_relations
+                ADD CONSTRAINT todo_relations_related_id_fkey
+                FOREIGN KEY (related_id) REFERENCES todos(id) ON DELETE CASCADE;
+        END IF;
+    END IF;
+END $$;
@@ -0,0 +1,69 @@
+-- Migration 022: Todo language
+-- Each todo stores the language detected from its title and content whenever either is
+-- written, and idx_todos_search indexes it with that language's text search
+-- configuration: english for English, simple for everything else until
+-- database/optional/japanese_search.sql gives Japanese its own. todo_detect_language
+-- must follow the same rules as backend/src/language.rs.
+-- migrate: locking
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'todo_language') THEN
+        CREATE TYPE todo_language AS ENUM ('english', 'japanese', 'other');
+    END IF;
+END $$;
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS language todo_language NOT NULL DEFAULT 'other';
+
+-- Any kana makes text Japanese; letters that are at least 90% ASCII make it English
+CREATE OR REPLACE FUNCTION todo_detect_language(text TEXT)
+RETURNS todo_language AS $$
+DECLARE
+    letters TEXT := regexp_replace(text, '[^[:alpha:]]', '', 'g');
+BEGIN
+    IF text ~ '[ぁ-ヿ]' THEN
+        RETURN 'japanese';
+    END IF;
+    IF letters <> ''
+        AND length(regexp_replace(letters, '[^A-Za-z]', '', 'g')) * 10 >= length(letters) * 9 THEN
+        RETURN 'english';
+    END IF;
+    RETURN 'other';
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION todo_search_config(language todo_language)
+RETURNS regconfig AS $$
+    SELECT CASE language WHEN 'english' THEN 'english'::regconfig ELSE 'simple'::regconfig END;
+$$ LANGUAGE sql IMMUTABLE;
+
+-- A search does not know the language of the todos it looks for, so it asks in every one
+CREATE OR REPLACE FUNCTION todo_search_query(query TEXT)
+RETURNS tsquery AS $$
+    SELECT websearch_to_tsquery(todo_search_config('english'), query)
+        || websearch_to_tsquery(todo_search_config('japanese'), query)
+        || websearch_to_tsquery(todo_search_config('other'), query);
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION set_todo_language()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.language = todo_detect_language(NEW.title || ' ' || NEW.content);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS set_todos_language ON todos;
+CREATE TRIGGER set_todos_language
+    BEFORE INSERT OR UPDATE OF title, content ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION set_todo_language();
+
+-- Detecting the language of existing todos is not an edit of them
+ALTER TABLE todos DISABLE TRIGGER update_todos_updated_at;
+UPDATE todos SET language = todo_detect_language(title || ' ' || content);
+ALTER TABLE todos ENABLE TRIGGER update_todos_updated_at;
+
+DROP INDEX IF EXISTS idx_todos_search;
+CREATE INDEX idx_todos_search ON todos
+    USING GIN (to_tsvector(todo_search_config(language), title || ' ' || content));
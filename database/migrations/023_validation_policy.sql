@@ -0,0 +1,13 @@
+-- Migration 023: Validation policy
+-- The rules an administrator adds on top of the built-in validation of todos. The table
+-- holds at most one row; without it todos are only checked by the built-in rules.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS validation_policy (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    max_title_length INTEGER CHECK (max_title_length > 0),
+    min_tags INTEGER NOT NULL DEFAULT 0 CHECK (min_tags >= 0),
+    required_fields TEXT[] NOT NULL DEFAULT '{}',
+    required_fields_from todo_priority NOT NULL DEFAULT 'low',
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,42 @@
+-- Migration 024: Moderation flags
+-- Content a content policy flagged on write, waiting for an administrator to review it.
+-- A todo has at most one open flag per policy and field; flagging it again refreshes it.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS moderation_flags (
+    id UUID PRIMARY KEY,
+    todo_id UUID NOT NULL,
+    policy TEXT NOT NULL,
+    field TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (todo_id, policy, field)
+);
+
+CREATE INDEX IF NOT EXISTS idx_moderation_flags_created_at ON moderation_flags(created_at, id);
+
+CREATE OR REPLACE FUNCTION delete_todo_moderation_flags()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM moderation_flags WHERE todo_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- As for share links, a partitioned todos table gets a trigger instead of a foreign key
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = 'todos'::regclass) THEN
+        DROP TRIGGER IF EXISTS delete_todos_moderation_flags ON todos;
+        CREATE TRIGGER delete_todos_moderation_flags
+            AFTER DELETE ON todos
+            FOR EACH ROW
+            EXECUTE FUNCTION delete_todo_moderation_flags();
+    ELSIF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'moderation_flags_todo_id_fkey'
+    ) THEN
+        ALTER TABLE moderation_flags
+            ADD CONSTRAINT moderation_flags_todo_id_fkey
+            FOREIGN KEY (todo_id) REFERENCES todos(id) ON DELETE CASCADE;
+    END IF;
+END $$;
@@ -0,0 +1,19 @@
+-- Migration 025: Encrypted todos
+-- Todos encrypted by their clients with keys the server never sees. The server keeps the
+-- ciphertext, the encrypted envelope of its metadata and the fingerprint of the key, and
+-- cannot read, search or validate what is inside.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS encrypted_todos (
+    id UUID PRIMARY KEY,
+    key_fingerprint TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    nonce BYTEA NOT NULL,
+    ciphertext BYTEA NOT NULL,
+    envelope BYTEA NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_encrypted_todos_key_fingerprint ON encrypted_todos(key_fingerprint, id);
@@ -0,0 +1,6 @@
+-- Migration 027: Split relation
+-- A todo moved out of another one by the split and extract-section endpoints is related to
+-- it as split_from, so the original keeps a trail to what left it.
+-- migrate: online
+
+ALTER TYPE todo_relation_kind ADD VALUE IF NOT EXISTS 'split_from';
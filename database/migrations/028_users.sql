@@ -0,0 +1,11 @@
+-- Migration 028: Users
+-- Accounts for the todo API: an email address, unique as normalized by the backend
+-- (trimmed, lowercase), and an Argon2 hash of the password in PHC string format.
+-- migrate: online
+
+CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,11 @@
+-- Migration 029: Completion days per user
+-- Completions are counted for the user who completed the todos; the nil UUID stands for
+-- everyone while accounts are off, and keeps the days counted before this migration.
+-- Servers from before it upsert on the old key, so they must be stopped first.
+-- migrate: locking
+
+ALTER TABLE completion_days
+    ADD COLUMN IF NOT EXISTS user_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000';
+
+ALTER TABLE completion_days DROP CONSTRAINT IF EXISTS completion_days_pkey;
+ALTER TABLE completion_days ADD CONSTRAINT completion_days_pkey PRIMARY KEY (user_id, day);
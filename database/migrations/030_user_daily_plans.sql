@@ -0,0 +1,15 @@
+-- Migration 030: Daily plans per user
+-- Every user plans their own day; the nil UUID stands for everyone while accounts are
+-- off, and keeps the plans made before this migration. Servers from before it would
+-- replace every user's plan of the day, so they must be stopped first.
+-- migrate: locking
+
+ALTER TABLE daily_plans
+    ADD COLUMN IF NOT EXISTS user_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000';
+
+ALTER TABLE daily_plans DROP CONSTRAINT IF EXISTS daily_plans_pkey;
+ALTER TABLE daily_plans DROP CONSTRAINT IF EXISTS daily_plans_day_todo_id_key;
+ALTER TABLE daily_plans DROP CONSTRAINT IF EXISTS daily_plans_user_id_day_todo_id_key;
+ALTER TABLE daily_plans ADD CONSTRAINT daily_plans_pkey PRIMARY KEY (user_id, day, position);
+ALTER TABLE daily_plans
+    ADD CONSTRAINT daily_plans_user_id_day_todo_id_key UNIQUE (user_id, day, todo_id);
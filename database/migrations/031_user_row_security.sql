@@ -0,0 +1,25 @@
+-- Migration 031: Row-level security on per-user rows
+-- The backend sets md_todo.user_id in every transaction that reads or writes completion
+-- days or plans, and these policies only show it that user's rows, so a query that
+-- forgets its user_id filter finds nothing rather than everyone's. A transaction that
+-- sets no user sees no rows. The policies are forced on the table owner too; only
+-- superusers and BYPASSRLS roles skip them.
+-- Plans of a todo that is gone may be deleted by anyone, which the cleanup trigger of a
+-- partitioned todos table relies on. Todos get their own policy in migration 032.
+-- migrate: locking
+
+ALTER TABLE completion_days ENABLE ROW LEVEL SECURITY;
+ALTER TABLE completion_days FORCE ROW LEVEL SECURITY;
+DROP POLICY IF EXISTS completion_days_of_user ON completion_days;
+CREATE POLICY completion_days_of_user ON completion_days
+    USING (user_id = NULLIF(current_setting('md_todo.user_id', true), '')::uuid);
+
+ALTER TABLE daily_plans ENABLE ROW LEVEL SECURITY;
+ALTER TABLE daily_plans FORCE ROW LEVEL SECURITY;
+DROP POLICY IF EXISTS daily_plans_of_user ON daily_plans;
+CREATE POLICY daily_plans_of_user ON daily_plans
+    USING (user_id = NULLIF(current_setting('md_todo.user_id', true), '')::uuid);
+DROP POLICY IF EXISTS daily_plans_of_deleted_todos ON daily_plans;
+CREATE POLICY daily_plans_of_deleted_todos ON daily_plans
+    FOR DELETE
+    USING (NOT EXISTS (SELECT 1 FROM todos WHERE todos.id = daily_plans.todo_id));
@@ -0,0 +1,70 @@
+-- Migration 032: Todos belong to the user who created them
+-- owner_id defaults to the user the transaction acts for, from md_todo.user_id as for
+-- completion days and plans; the nil UUID stands for no account, as it does there. Todos
+-- from before this migration, or made while acting for no account (accounts off, mail,
+-- clipping, IMAP capture), belong to the nil UUID and are shared by every account.
+-- Row-level security lets a user read and write only their own todos and the shared
+-- ones, so a query that forgets to filter by owner cannot reach another user's todos. A
+-- transaction acting for no account (accounts off, share links, background jobs) sees
+-- every todo, and one that sets no user sees none. The policy is forced on the table
+-- owner too, and secure_todos() puts it on every partition of a partitioned todos table
+-- (database/optional/partition_todos_by_month.sql) so reading a partition directly is
+-- held to it as well; the partition upkeep runs it after adding partitions.
+-- migrate: locking
+
+ALTER TABLE todos
+    ADD COLUMN IF NOT EXISTS owner_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000';
+ALTER TABLE todos
+    ALTER COLUMN owner_id
+    SET DEFAULT COALESCE(
+        NULLIF(current_setting('md_todo.user_id', true), '')::uuid,
+        '00000000-0000-0000-0000-000000000000'
+    );
+
+CREATE INDEX IF NOT EXISTS idx_todos_owner_id ON todos(owner_id);
+
+-- Puts todos_of_user on todos and each of its partitions. The condition: the acting user
+-- owns the row, the row is shared, or the transaction acts for no account; never when
+-- no user is set
+CREATE OR REPLACE FUNCTION secure_todos()
+RETURNS VOID AS $$
+DECLARE
+    target REGCLASS;
+    visible CONSTANT TEXT := $cond$
+        NULLIF(current_setting('md_todo.user_id', true), '')::uuid
+            IN (owner_id, '00000000-0000-0000-0000-000000000000')
+        OR (owner_id = '00000000-0000-0000-0000-000000000000'
+            AND NULLIF(current_setting('md_todo.user_id', true), '') IS NOT NULL)
+    $cond$;
+BEGIN
+    -- pg_partition_tree() has no rows for a table that is not partitioned
+    FOR target IN
+        SELECT 'todos'::regclass
+        UNION
+        SELECT relid FROM pg_partition_tree('todos')
+    LOOP
+        EXECUTE format('ALTER TABLE %s ENABLE ROW LEVEL SECURITY', target);
+        EXECUTE format('ALTER TABLE %s FORCE ROW LEVEL SECURITY', target);
+        EXECUTE format('DROP POLICY IF EXISTS todos_of_user ON %s', target);
+        EXECUTE format(
+            'CREATE POLICY todos_of_user ON %s USING (%s) WITH CHECK (%s)',
+            target, visible, visible
+        );
+    END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT secure_todos();
+
+-- Whether a todo exists for anyone, looked up as no account; the acting user may not see it
+CREATE OR REPLACE FUNCTION todo_exists(todo UUID)
+RETURNS BOOLEAN AS $$
+    SELECT EXISTS (SELECT 1 FROM todos WHERE id = todo)
+$$ LANGUAGE sql STABLE
+SET md_todo.user_id = '00000000-0000-0000-0000-000000000000';
+
+-- Replaces the policy from migration 031, which would take another user's todo for gone
+DROP POLICY IF EXISTS daily_plans_of_deleted_todos ON daily_plans;
+CREATE POLICY daily_plans_of_deleted_todos ON daily_plans
+    FOR DELETE
+    USING (NOT todo_exists(todo_id));
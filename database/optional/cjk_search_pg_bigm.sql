@@ -0,0 +1,17 @@
+-- Optional: substring search for Chinese, Japanese and Korean with pg_bigm
+-- Full-text search finds nothing inside runs of CJK text, which have no spaces between
+-- words. With SEARCH_CJK=pg_bigm, queries containing CJK characters are matched with one
+-- LIKE per word or quoted phrase instead, served by this bigram index. Matching is
+-- case-sensitive. Install pg_bigm, then run once:
+--
+--   psql "$DATABASE_URL" -f database/optional/cjk_search_pg_bigm.sql
+--
+-- The index expression must stay identical to the one the backend queries with. Building
+-- it concurrently keeps todos writable, so the script runs outside a transaction.
+
+\set ON_ERROR_STOP on
+
+CREATE EXTENSION IF NOT EXISTS pg_bigm;
+
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_todos_bigm ON todos
+    USING GIN ((title || ' ' || content) gin_bigm_ops);
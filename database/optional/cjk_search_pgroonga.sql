@@ -0,0 +1,19 @@
+-- Optional: search for Chinese, Japanese and Korean with PGroonga
+-- Full-text search finds nothing inside runs of CJK text, which have no spaces between
+-- words. With SEARCH_CJK=pgroonga, queries containing CJK characters are matched with
+-- PGroonga's &@~ instead, which splits text into N-grams and reads q much like
+-- websearch_to_tsquery: words, "quoted phrases", -word and OR. Hits are ranked by
+-- pgroonga_score, which is only filled in when this index is used. Install PGroonga, then
+-- run once:
+--
+--   psql "$DATABASE_URL" -f database/optional/cjk_search_pgroonga.sql
+--
+-- The index expression must stay identical to the one the backend queries with. Building
+-- it concurrently keeps todos writable, so the script runs outside a transaction.
+
+\set ON_ERROR_STOP on
+
+CREATE EXTENSION IF NOT EXISTS pgroonga;
+
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_todos_pgroonga ON todos
+    USING pgroonga ((ARRAY[title, content]));
@@ -0,0 +1,37 @@
+-- Optional: word-based full-text search for Japanese
+-- Japanese leaves no spaces between words, so the simple configuration indexes whole runs
+-- of text and searching for one word of a sentence finds nothing. Given a text search
+-- configuration that splits Japanese into words, such as `japanese` from textsearch_ja
+-- (MeCab), todos detected as Japanese are indexed and searched with it instead. PGroonga
+-- brings its own index and operators rather than a configuration; see cjk_search_pgroonga.sql.
+-- Install the extension, then run once, with migrations up to date:
+--
+--   psql "$DATABASE_URL" -v config=japanese -f database/optional/japanese_search.sql
+--
+-- Running it with config=simple switches back. Reindexing locks todos against writes.
+
+\set ON_ERROR_STOP on
+
+BEGIN;
+
+-- Fails here if the configuration does not exist
+SELECT :'config'::regconfig AS japanese_config;
+
+SELECT format(
+    $sql$
+    CREATE OR REPLACE FUNCTION todo_search_config(language todo_language)
+    RETURNS regconfig AS $$
+        SELECT CASE language
+            WHEN 'english' THEN 'english'::regconfig
+            WHEN 'japanese' THEN %L::regconfig
+            ELSE 'simple'::regconfig
+        END;
+    $$ LANGUAGE sql IMMUTABLE
+    $sql$,
+    :'config'
+) \gexec
+
+-- The index holds words split by the previous configuration
+REINDEX INDEX idx_todos_search;
+
+COMMIT;
@@ -0,0 +1,153 @@
+-- Optional: partition todos by created_at month
+-- For deployments with millions of todos, where one table makes indexes and vacuums slow.
+-- Run once, with migrations up to date, in a maintenance window:
+--
+--   psql "$DATABASE_URL" -f database/optional/partition_todos_by_month.sql
+--
+-- then start the backend with TODO_PARTITION_MONTHS_AHEAD set so upcoming months always
+-- have a partition. Queries bounded by created_at (such as /api/stats/workload) only touch the
+-- partitions they need; lookups by id check each partition's primary key index.
+--
+-- Trade-offs of partitioning:
+-- * The primary key becomes (id, created_at). Ids stay unique because they are UUIDs,
+--   but the database no longer enforces it across partitions.
+-- * short_links, share_links, todo_tags, daily_plans, todo_relations and moderation_flags
+--   cannot reference a partitioned table by id alone, so their foreign keys are replaced
+--   by triggers that delete a todo's rows with the todo.
+-- * Row-level security (migration 032) is put on every partition as well as on todos, and
+--   the backend puts it on the partitions it adds.
+
+BEGIN;
+
+-- Creates monthly partitions (UTC) from the month of from_time up to months_ahead months
+-- from now. Returns how many were created; existing partitions are left alone.
+CREATE OR REPLACE FUNCTION ensure_todo_partitions(from_time TIMESTAMPTZ, months_ahead INTEGER)
+RETURNS INTEGER AS $$
+DECLARE
+    month_start TIMESTAMP := date_trunc('month', from_time AT TIME ZONE 'UTC');
+    last_month TIMESTAMP := date_trunc(
+        'month',
+        (CURRENT_TIMESTAMP AT TIME ZONE 'UTC') + make_interval(months => months_ahead)
+    );
+    partition_name TEXT;
+    created INTEGER := 0;
+BEGIN
+    WHILE month_start <= last_month LOOP
+        partition_name := 'todos_' || to_char(month_start, 'YYYY_MM');
+        IF to_regclass(partition_name) IS NULL THEN
+            EXECUTE format(
+                'CREATE TABLE %I PARTITION OF todos FOR VALUES FROM (%L) TO (%L)',
+                partition_name,
+                month_start AT TIME ZONE 'UTC',
+                (month_start + INTERVAL '1 month') AT TIME ZONE 'UTC'
+            );
+            created := created + 1;
+        END IF;
+        month_start := month_start + INTERVAL '1 month';
+    END LOOP;
+    RETURN created;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION delete_todo_short_link()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM short_links WHERE todo_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+LOCK TABLE todos IN ACCESS EXCLUSIVE MODE;
+
+ALTER TABLE short_links DROP CONSTRAINT IF EXISTS short_links_todo_id_fkey;
+ALTER TABLE share_links DROP CONSTRAINT IF EXISTS share_links_todo_id_fkey;
+ALTER TABLE todo_tags DROP CONSTRAINT IF EXISTS todo_tags_todo_id_fkey;
+ALTER TABLE daily_plans DROP CONSTRAINT IF EXISTS daily_plans_todo_id_fkey;
+ALTER TABLE todo_relations DROP CONSTRAINT IF EXISTS todo_relations_todo_id_fkey;
+ALTER TABLE todo_relations DROP CONSTRAINT IF EXISTS todo_relations_related_id_fkey;
+ALTER TABLE moderation_flags DROP CONSTRAINT IF EXISTS moderation_flags_todo_id_fkey;
+ALTER TABLE todos RENAME TO todos_unpartitioned;
+-- Copies every todo below, whoever owns it
+ALTER TABLE todos_unpartitioned DISABLE ROW LEVEL SECURITY;
+
+CREATE TABLE todos (LIKE todos_unpartitioned INCLUDING DEFAULTS INCLUDING CONSTRAINTS)
+    PARTITION BY RANGE (created_at);
+ALTER TABLE todos ADD PRIMARY KEY (id, created_at);
+-- Catches rows far outside the maintained range instead of rejecting them
+CREATE TABLE todos_default PARTITION OF todos DEFAULT;
+-- LIKE does not copy foreign keys; this one references a plain table, so it stays
+ALTER TABLE todos
+    ADD CONSTRAINT todos_list_id_fkey
+    FOREIGN KEY (list_id) REFERENCES todo_lists(id) ON DELETE SET NULL;
+
+SELECT ensure_todo_partitions(
+    COALESCE((SELECT MIN(created_at) FROM todos_unpartitioned), CURRENT_TIMESTAMP),
+    3
+);
+
+UPDATE todos_unpartitioned
+SET created_at = COALESCE(updated_at, CURRENT_TIMESTAMP)
+WHERE created_at IS NULL;
+INSERT INTO todos SELECT * FROM todos_unpartitioned;
+DROP TABLE todos_unpartitioned;
+
+CREATE INDEX idx_todos_completed ON todos(completed);
+CREATE INDEX idx_todos_created_at_id ON todos(created_at DESC, id DESC);
+CREATE INDEX idx_todos_updated_at_id ON todos(updated_at DESC, id DESC);
+CREATE INDEX idx_todos_title_id ON todos(title, id);
+CREATE INDEX idx_todos_priority_id ON todos(priority, id);
+CREATE INDEX idx_todos_list_id ON todos(list_id);
+CREATE INDEX idx_todos_owner_id ON todos(owner_id);
+CREATE INDEX idx_todos_labels ON todos USING GIN (labels);
+CREATE INDEX idx_todos_search ON todos
+    USING GIN (to_tsvector(todo_search_config(language), title || ' ' || content));
+CREATE INDEX idx_todos_title_trgm ON todos USING GIN (title gin_trgm_ops);
+
+CREATE TRIGGER update_todos_updated_at
+    BEFORE UPDATE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+CREATE TRIGGER set_todos_language
+    BEFORE INSERT OR UPDATE OF title, content ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION set_todo_language();
+
+CREATE TRIGGER delete_todos_short_link
+    AFTER DELETE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION delete_todo_short_link();
+
+-- delete_todo_share_links() comes from migration 010
+CREATE TRIGGER delete_todos_share_links
+    AFTER DELETE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION delete_todo_share_links();
+
+-- delete_todo_tags() comes from migration 013
+CREATE TRIGGER delete_todos_tags
+    AFTER DELETE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION delete_todo_tags();
+
+-- delete_todo_daily_plans() comes from migration 020
+CREATE TRIGGER delete_todos_daily_plans
+    AFTER DELETE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION delete_todo_daily_plans();
+
+-- delete_todo_relations() comes from migration 021; it covers both sides of a relation
+CREATE TRIGGER delete_todos_relations
+    AFTER DELETE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION delete_todo_relations();
+
+-- delete_todo_moderation_flags() comes from migration 024
+CREATE TRIGGER delete_todos_moderation_flags
+    AFTER DELETE ON todos
+    FOR EACH ROW
+    EXECUTE FUNCTION delete_todo_moderation_flags();
+
+-- secure_todos() comes from migration 032; LIKE does not copy row-level security
+SELECT secure_todos();
+
+COMMIT;